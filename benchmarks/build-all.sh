@@ -2,7 +2,14 @@
 
 # Either build all of the benchmarks or a random subset of them.
 #
-# Usage: ./build-all.sh <number of benchmarks>
+# Usage: ./build-all.sh [<build.sh flags>] <number of benchmarks>
+# - <build.sh flags>, optional flags passed to `build.sh` for each benchmark: `--docker`, `--podman`,
+#   or `--local` to build with Docker, with Podman, or with locally installed toolchains,
+#   `--rebuild` to ignore the cached benchmarks, which are otherwise reused for any benchmark that
+#   has not changed, `--verify` to check that each benchmark builds reproducibly (reporting all of
+#   those that do not at the end), `--variant <name>=<flags>` to build a variant of each benchmark
+#   (e.g., `--variant simd=-msimd128`), and `--wasm-opt <name>=<passes>` to also optimize each
+#   benchmark with `wasm-opt` (e.g., `--wasm-opt o3=-O3`)
 # - <number of benchmarks>, an optional number of benchmarks to build; if provided, this script will
 #   randomize the list of benchmarks and pick a subset of them to build
 
@@ -10,16 +17,39 @@ set -e
 PROJECT_DIR=$(dirname "$0" | xargs dirname | xargs realpath)
 BENCHMARKS_DIR=$PROJECT_DIR/benchmarks
 BUILD_SCRIPT=$PROJECT_DIR/benchmarks/build.sh
-DOCKERFILES=$(find $BENCHMARKS_DIR -name Dockerfile)
+BUILD_FLAGS=()
+while [[ $1 == --* ]]; do
+    if [[ $1 == --variant || $1 == --wasm-opt ]]; then
+        BUILD_FLAGS+=("$1" "$2")
+        shift
+    else
+        BUILD_FLAGS+=("$1")
+    fi
+    if [[ $1 == --verify ]]; then
+        VERIFY=1
+    fi
+    shift
+done
+# Benchmarks without a `Dockerfile` may still be built by their `build-local.sh`.
+BENCHMARK_DIRS=$(find $BENCHMARKS_DIR -mindepth 2 -maxdepth 2 \
+    \( -name Dockerfile -o -name build-local.sh \) -exec dirname {} \; | sort -u)
 
 # If a numeric parameter `N` is provided to the script (e.g., `./build-all.sh 5`), randomly select
 # `N` benchmarks to rebuild; otherwise, rebuild all benchmarks.
 re='^[0-9]+$'
 if [[ $1 =~ $re ]]; then
-    DOCKERFILES=$(echo "$DOCKERFILES" | shuf -n $1)
+    BENCHMARK_DIRS=$(echo "$BENCHMARK_DIRS" | shuf -n $1)
 fi
 
-for DOCKERFILE in $DOCKERFILES; do
-    BENCHMARK_DIR=$(dirname $DOCKERFILE)
-    $BUILD_SCRIPT $BENCHMARK_DIR
+NOT_REPRODUCIBLE=()
+for BENCHMARK_DIR in $BENCHMARK_DIRS; do
+    if [[ -n $VERIFY ]]; then
+        $BUILD_SCRIPT "${BUILD_FLAGS[@]}" $BENCHMARK_DIR || NOT_REPRODUCIBLE+=($BENCHMARK_DIR)
+    else
+        $BUILD_SCRIPT "${BUILD_FLAGS[@]}" $BENCHMARK_DIR
+    fi
 done
+if [[ ${#NOT_REPRODUCIBLE[@]} -gt 0 ]]; then
+    >&2 echo "Failed to build reproducibly: ${NOT_REPRODUCIBLE[@]}"
+    exit 1
+fi
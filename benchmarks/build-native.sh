@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+
+# Build a single benchmark natively, as the baseline for the `native` engine (see
+# `sightglass-cli benchmark --help`). Expects the benchmark directory to contain a `benchmark.c` or
+# `benchmark.cpp` file, which is compiled with the host's compiler (`$CC` or `$CXX`) to a shared
+# library next to `benchmark.wasm`. Unlike `build.sh`, this does not use Docker: the result is
+# specific to the host, which is the point of a native baseline.
+#
+# Usage: ./build-native.sh <path to benchmark directory>
+
+set -e
+
+BENCHMARK_DIR=$1
+if [[ ! -d $BENCHMARK_DIR ]]; then
+    echo "Unknown benchmark directory; usage: ./build-native.sh <path to benchmark directory>"
+    exit 1
+fi
+>&2 echo "Building $BENCHMARK_DIR natively"
+
+# The flags mirror those used to build the Wasm benchmarks, plus those needed for a shared library.
+FLAGS="-O3 -g -DNDEBUG -I. -shared -fPIC"
+cd $BENCHMARK_DIR
+if [[ -f benchmark.c ]]; then
+    (set -x; ${CC:-cc} $FLAGS benchmark.c -o benchmark.so)
+elif [[ -f benchmark.cpp ]]; then
+    (set -x; ${CXX:-c++} $FLAGS benchmark.cpp -o benchmark.so)
+else
+    >&2 echo "No benchmark.c or benchmark.cpp to build natively in $BENCHMARK_DIR"
+    exit 1
+fi
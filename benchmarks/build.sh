@@ -4,19 +4,115 @@
 # directory to contain a `Dockerfile` that emits a `benchmark.wasm` file that meets the Sightglass
 # requirements (see `README.md`).
 #
-# Usage: ./build.sh <path to benchmark directory>
+# The image is built with Docker or, where the Docker daemon is prohibited, with Podman (which may
+# run rootless): `--docker` or `--podman` (or `SIGHTGLASS_BUILD_MODE=docker` or `podman`) selects
+# one, and otherwise Docker is used if its daemon is reachable and Podman if it is installed.
+#
+# Where neither is available, `--local` (or `SIGHTGLASS_BUILD_MODE=local`, e.g., for all of
+# `build-all.sh`) builds the benchmark with locally installed toolchains instead: the benchmark
+# directory's `build-local.sh`, if any, or else the same compiler command as its `Dockerfile`, for
+# the common cases of a single C file built with wasi-sdk or Emscripten and a Rust crate. A
+# benchmark directory with a `build-local.sh` but no `Dockerfile` is always built locally.
+#
+# Each built `benchmark.wasm` is cached, keyed by a hash of the benchmark's sources (everything in
+# its directory but what the build writes), the build mode (container or local), and this script,
+# so that rebuilding the suite only rebuilds the benchmarks that changed. The cache lives in
+# `$SIGHTGLASS_CACHE_DIR/benchmarks` (by default, `~/.cache/sightglass/benchmarks`), where `cargo
+# run -- clean` also removes it; `--rebuild` (or `SIGHTGLASS_REBUILD=1`) ignores it.
+#
+# To measure the impact of toolchain choices, `--variant <name>=<flags>` builds a variant of the
+# benchmark with additional compiler flags (e.g., `--variant simd=-msimd128` or `--variant
+# o1=-O1`) into `benchmark-<name>.wasm`, next to a `benchmark-<name>.variant` file recording the
+# name and flags, which `sightglass-cli benchmark --manifest` reports. The `Dockerfile` passes the
+# `VARIANT_FLAGS` build argument to the compiler (`RUSTFLAGS`, for Rust), as does a local build
+# (for a `build-local.sh`, in its environment).
+#
+# To measure the impact of Binaryen's optimizations, `--wasm-opt <name>=<passes>` (e.g.,
+# `--wasm-opt o3=-O3` or `--wasm-opt size="-Oz --converge"`), which may be repeated, also runs
+# `wasm-opt` (or `$WASM_OPT`) with the given passes over the built benchmark into
+# `benchmark-<name>.wasm` (or `benchmark-<variant>-<name>.wasm`, for a `--variant`), recording the
+# passes in its `.variant` file. These are not cached, since `wasm-opt` is quick to rerun.
+#
+# Comparing results across machines only makes sense if they ran the same Wasm, so `--verify`
+# checks that the benchmark builds reproducibly: it builds the benchmark twice, from scratch (for
+# containers, without Docker's or Podman's layer cache), and fails if the two builds, or a
+# previously cached build of the same sources, differ. The differing builds are kept in `/tmp` to
+# be compared.
+#
+# Usage: ./build.sh [--docker | --podman | --local] [--rebuild | --verify]
+#        [--variant <name>=<flags>] [--wasm-opt <name>=<passes>]... <path to benchmark directory>
 
 set -e
+USAGE="./build.sh [--docker | --podman | --local] [--rebuild | --verify] \
+[--variant <name>=<flags>] [--wasm-opt <name>=<passes>]... <path to benchmark directory>"
 
+BUILD_MODE=$SIGHTGLASS_BUILD_MODE
+REBUILD=$SIGHTGLASS_REBUILD
+WASM_OPT_VARIANTS=()
+while [[ $1 == --* ]]; do
+    case $1 in
+        --docker | --podman | --local) BUILD_MODE=${1#--} ;;
+        --rebuild) REBUILD=1 ;;
+        --verify) VERIFY=1 ;;
+        --variant)
+            VARIANT_NAME=${2%%=*}
+            VARIANT_FLAGS=${2#*=}
+            if [[ ! $VARIANT_NAME =~ ^[[:alnum:]_-]+$ || $2 != *=* ]]; then
+                echo "Invalid variant: $2; expected <name>=<flags>, e.g., simd=-msimd128"
+                exit 1
+            fi
+            shift
+            ;;
+        --wasm-opt)
+            if [[ ! ${2%%=*} =~ ^[[:alnum:]_-]+$ || $2 != *=* ]]; then
+                echo "Invalid wasm-opt variant: $2; expected <name>=<passes>, e.g., o3=-O3"
+                exit 1
+            fi
+            WASM_OPT_VARIANTS+=("$2")
+            shift
+            ;;
+        *)
+            echo "Unknown flag: $1; usage: $USAGE"
+            exit 1
+            ;;
+    esac
+    shift
+done
 BENCHMARK_DIR=$1
 if [[ ! -d $BENCHMARK_DIR ]]; then
-    echo "Unknown benchmark directory; usage: ./build.sh <path to benchmark directory>"
+    echo "Unknown benchmark directory; usage: $USAGE"
+    exit 1
+fi
+if [[ ! -f $BENCHMARK_DIR/Dockerfile && -f $BENCHMARK_DIR/build-local.sh ]]; then
+    BUILD_MODE=local
+fi
+if [[ -z $BUILD_MODE ]]; then
+    if docker info &> /dev/null; then
+        BUILD_MODE=docker
+    elif command -v podman > /dev/null; then
+        BUILD_MODE=podman
+    else
+        BUILD_MODE=docker
+    fi
+fi
+if [[ $BUILD_MODE != docker && $BUILD_MODE != podman && $BUILD_MODE != local ]]; then
+    echo "Unknown build mode: $BUILD_MODE; expected 'docker', 'podman', or 'local'"
     exit 1
 fi
 BENCHMARK_NAME=$(readlink -f $BENCHMARK_DIR | xargs basename)
 IMAGE_NAME=sightglass-benchmark-$BENCHMARK_NAME
-TMP_BENCHMARK=$(mktemp /tmp/sightglass-benchmark-XXXXXX.wasm)
->&2 echo "Building $BENCHMARK_DIR"
+OUTPUT_NAME=benchmark
+if [[ -n $VARIANT_NAME ]]; then
+    BENCHMARK_NAME=$BENCHMARK_NAME-$VARIANT_NAME
+    IMAGE_NAME=$IMAGE_NAME-$VARIANT_NAME
+    OUTPUT_NAME=benchmark-$VARIANT_NAME
+fi
+export VARIANT_FLAGS
+WASM_OPT=${WASM_OPT:-wasm-opt}
+if [[ ${#WASM_OPT_VARIANTS[@]} -gt 0 ]] && ! command -v $WASM_OPT > /dev/null; then
+    echo "Unable to find $WASM_OPT; install Binaryen or set WASM_OPT"
+    exit 1
+fi
 
 # Helpful logging function.
 print_header() {
@@ -24,29 +120,176 @@ print_header() {
     >&2 echo ===== $@ =====
 }
 
-# To allow the use of symlinks in the benchmark directories (docker ignores them), we `tar` up the
-# directory and `--dereference` (i.e., follow) all symlinks provided.
-print_header "Create build context"
-TMP_TAR=$(mktemp /tmp/sightglass-benchmark-dir-XXXXXX.tar)
-(set -x; cd $BENCHMARK_DIR && tar --create --file $TMP_TAR --dereference --verbose .)
+# Hash the benchmark's sources, following symlinks (like the build context does), along with the
+# build recipe (including the variant's flags). Docker and Podman build the same images, so they
+# share cached artifacts.
+hash_sources() {
+    local RECIPE=container
+    if [[ $BUILD_MODE == local ]]; then
+        RECIPE=local
+    fi
+    (
+        echo $RECIPE
+        echo "$VARIANT_FLAGS"
+        sha256sum < ${BASH_SOURCE[0]}
+        cd $BENCHMARK_DIR && find -L . -type f \
+            ! -path './benchmark*.wasm' ! -path './benchmark*.variant' ! -path ./benchmark.so \
+            ! -path ./benchmark.toml ! -path './rust-benchmark/target/*' -print0 | LC_ALL=C sort -z | xargs -0 sha256sum
+    ) | sha256sum | cut -d ' ' -f 1
+}
 
-# Build the benchmark image and extract the generated `benchmark.wasm` file from its container.
-print_header "Build benchmark"
-(set -x; docker build --tag $IMAGE_NAME - < $TMP_TAR)
-CONTAINER_ID=$(set -x; docker create $IMAGE_NAME)
-(set -x; docker cp $CONTAINER_ID:/benchmark.wasm $TMP_BENCHMARK)
+# Record the variant next to the benchmark, for `sightglass-cli benchmark` to read.
+write_variant() {
+    if [[ -n $VARIANT_NAME ]]; then
+        printf 'NAME=%s\nFLAGS=%s\n' "$VARIANT_NAME" "$VARIANT_FLAGS" \
+            > $BENCHMARK_DIR/$OUTPUT_NAME.variant
+    fi
+}
+
+# Run `wasm-opt` over the built benchmark for each `--wasm-opt` variant, checking that each result
+# is still a valid benchmark.
+optimize_variants() {
+    for WASM_OPT_VARIANT in "${WASM_OPT_VARIANTS[@]}"; do
+        local NAME=${WASM_OPT_VARIANT%%=*}
+        local PASSES=${WASM_OPT_VARIANT#*=}
+        local OPTIMIZED=$OUTPUT_NAME-$NAME
+        print_header "Optimize benchmark: $NAME"
+        (set -x; $WASM_OPT $PASSES $BENCHMARK_DIR/$OUTPUT_NAME.wasm \
+            -o $BENCHMARK_DIR/$OPTIMIZED.wasm)
+        (set -x; cargo run --manifest-path $SIGHTGLASS_CARGO_TOML --quiet -- \
+            validate $BENCHMARK_DIR/$OPTIMIZED.wasm)
+        printf 'NAME=%s\nFLAGS=%s\nWASM_OPT=%s\n' "${OPTIMIZED#benchmark-}" "$VARIANT_FLAGS" \
+            "$PASSES" > $BENCHMARK_DIR/$OPTIMIZED.variant
+    done
+}
 
-# Verify benchmark is a valid Sightglass benchmark.
-print_header "Verify benchmark"
 # From https://stackoverflow.com/a/246128:
 SCRIPT_DIR="$( cd -- "$( dirname -- "${BASH_SOURCE[0]:-$0}"; )" &> /dev/null && pwd 2> /dev/null; )";
 SIGHTGLASS_CARGO_TOML=$(dirname $SCRIPT_DIR)/Cargo.toml
+
+CACHE_DIR=${SIGHTGLASS_CACHE_DIR:-${XDG_CACHE_HOME:-$HOME/.cache}/sightglass}/benchmarks
+CACHED_BENCHMARK=$CACHE_DIR/$BENCHMARK_NAME-$(hash_sources).wasm
+if [[ -z $REBUILD && -z $VERIFY && -f $CACHED_BENCHMARK ]]; then
+    >&2 echo "Using cached $BENCHMARK_DIR: $CACHED_BENCHMARK"
+    (set -x; cp $CACHED_BENCHMARK $BENCHMARK_DIR/$OUTPUT_NAME.wasm)
+    write_variant
+    optimize_variants
+    exit 0
+fi
+
+>&2 echo "Building $BENCHMARK_DIR ($BUILD_MODE)"
+
+# Check whether the benchmark's `Dockerfile` compiles its single `benchmark.c` with the given
+# compiler command, and nothing else.
+dockerfile_compiles_with() {
+    local LINE="RUN $1 benchmark.c -O3 -g -DNDEBUG -I. \$VARIANT_FLAGS -o benchmark.wasm"
+    [[ -f $BENCHMARK_DIR/Dockerfile ]] && grep -qxF "$LINE" $BENCHMARK_DIR/Dockerfile
+}
+
+# Build the benchmark into `$TMP_BENCHMARK` with local toolchains: wasi-sdk (found at
+# `$WASI_SDK_PATH`, by default `/opt/wasi-sdk`), Emscripten's `emcc`, or `cargo` with a WASI target.
+build_locally() {
+    local OUTPUT=$(realpath $TMP_BENCHMARK)
+    local WASI_SDK=${WASI_SDK_PATH:-/opt/wasi-sdk}
+    local FLAGS="-O3 -g -DNDEBUG -I. $VARIANT_FLAGS"
+    if [[ -f $BENCHMARK_DIR/build-local.sh ]]; then
+        (set -x; cd $BENCHMARK_DIR && ./build-local.sh $OUTPUT)
+    elif dockerfile_compiles_with '$CC $CFLAGS'; then
+        (set -x; cd $BENCHMARK_DIR && $WASI_SDK/bin/clang --sysroot=$WASI_SDK/share/wasi-sysroot \
+            benchmark.c $FLAGS -o $OUTPUT)
+    elif dockerfile_compiles_with emcc; then
+        (set -x; cd $BENCHMARK_DIR && emcc benchmark.c $FLAGS -o $OUTPUT)
+    elif [[ -d $BENCHMARK_DIR/rust-benchmark ]]; then
+        # Newer toolchains call the `wasm32-wasi` target of the Dockerfiles `wasm32-wasip1`.
+        local TARGET=wasm32-wasi
+        if rustc --print target-list | grep -qx wasm32-wasip1; then
+            TARGET=wasm32-wasip1
+        fi
+        local TARGET_DIR=$(mktemp -d /tmp/sightglass-benchmark-target-XXXXXX)
+        (set -x; cd $BENCHMARK_DIR/rust-benchmark && RUSTFLAGS="$VARIANT_FLAGS" \
+            cargo build --release --target $TARGET --target-dir $TARGET_DIR)
+        (set -x; cp $TARGET_DIR/$TARGET/release/*.wasm $OUTPUT)
+        (set -x; rm -rf $TARGET_DIR)
+    else
+        >&2 echo "Unable to build $BENCHMARK_DIR locally; add a build-local.sh that builds it"
+        exit 1
+    fi
+}
+
+# Build the benchmark into a new `$TMP_BENCHMARK`; any arguments are passed to `docker build` or
+# `podman build`.
+build_benchmark() {
+    TMP_BENCHMARK=$(mktemp /tmp/sightglass-benchmark-XXXXXX.wasm)
+    if [[ $BUILD_MODE == local ]]; then
+        print_header "Build benchmark locally"
+        build_locally
+    else
+        build_in_container "$@"
+    fi
+}
+
+build_in_container() {
+    # A `Dockerfile` that ignored the variant's flags would build the benchmark itself instead.
+    BUILD_ARGS=()
+    if [[ -n $VARIANT_NAME ]]; then
+        if ! grep -qx 'ARG VARIANT_FLAGS' $BENCHMARK_DIR/Dockerfile; then
+            >&2 echo "Unable to build variants of $BENCHMARK_DIR;" \
+                "its Dockerfile lacks VARIANT_FLAGS"
+            exit 1
+        fi
+        BUILD_ARGS=(--build-arg "VARIANT_FLAGS=$VARIANT_FLAGS")
+    fi
+    BUILD_ARGS+=("$@")
+
+    # To allow the use of symlinks in the benchmark directories (docker ignores them), we `tar` up
+    # the directory and `--dereference` (i.e., follow) all symlinks provided.
+    print_header "Create build context"
+    TMP_TAR=$(mktemp /tmp/sightglass-benchmark-dir-XXXXXX.tar)
+    (set -x; cd $BENCHMARK_DIR && tar --create --file $TMP_TAR --dereference --verbose .)
+
+    # Build the benchmark image and extract the generated `benchmark.wasm` file from its container.
+    # Podman only reads a `Dockerfile` from `stdin`, not a whole context, so the context is
+    # extracted for it.
+    print_header "Build benchmark"
+    if [[ $BUILD_MODE == podman ]]; then
+        TMP_CONTEXT=$(mktemp -d /tmp/sightglass-benchmark-dir-XXXXXX)
+        (set -x; tar --extract --file $TMP_TAR --directory $TMP_CONTEXT)
+        (set -x; podman build "${BUILD_ARGS[@]}" --tag $IMAGE_NAME $TMP_CONTEXT)
+        (set -x; rm -r $TMP_CONTEXT)
+    else
+        (set -x; docker build "${BUILD_ARGS[@]}" --tag $IMAGE_NAME - < $TMP_TAR)
+    fi
+    CONTAINER_ID=$(set -x; $BUILD_MODE create $IMAGE_NAME)
+    (set -x; $BUILD_MODE cp $CONTAINER_ID:/benchmark.wasm $TMP_BENCHMARK)
+
+    print_header "Clean up"
+    (set -x; rm $TMP_TAR)
+    (set -x; $BUILD_MODE rm $CONTAINER_ID)
+}
+
+build_benchmark
+if [[ -n $VERIFY ]]; then
+    FIRST_BENCHMARK=$TMP_BENCHMARK
+    build_benchmark --no-cache
+    print_header "Verify reproducibility"
+    for OTHER in $FIRST_BENCHMARK $CACHED_BENCHMARK; do
+        if [[ -f $OTHER ]] && ! cmp -s $OTHER $TMP_BENCHMARK; then
+            >&2 echo "$BENCHMARK_DIR does not build reproducibly; the builds differ:"
+            >&2 sha256sum $OTHER $TMP_BENCHMARK
+            exit 1
+        fi
+    done
+    >&2 echo "$BENCHMARK_DIR builds reproducibly: $(sha256sum < $TMP_BENCHMARK | cut -d ' ' -f 1)"
+    (set -x; rm $FIRST_BENCHMARK)
+fi
+
+# Verify benchmark is a valid Sightglass benchmark.
+print_header "Verify benchmark"
 (set -x; cargo run --manifest-path $SIGHTGLASS_CARGO_TOML --quiet -- validate $TMP_BENCHMARK)
-(set -x; mv $TMP_BENCHMARK $BENCHMARK_DIR/benchmark.wasm)
+(set -x; mkdir -p $CACHE_DIR && cp $TMP_BENCHMARK $CACHED_BENCHMARK)
+(set -x; mv $TMP_BENCHMARK $BENCHMARK_DIR/$OUTPUT_NAME.wasm)
+write_variant
+optimize_variants
 
-# Clean up.
-print_header "Clean up"
-(set -x; rm $TMP_TAR)
-(set -x; docker rm $CONTAINER_ID)
 # We do not remove the image and intermediate images here (e.g., `docker rmi $IMAGE_NAME`) to speed
 # up `build-all.sh`; use `clean.sh` instead.
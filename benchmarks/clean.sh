@@ -2,19 +2,27 @@
 
 # Clean any artifacts remaining after using `build.sh` or `build-all.sh`. This attempts to remove
 # the following kinds of `sightglass-benchmark` artifacts:
-# - any temporary files
-# - any Docker containers
-# - any Docker images
+# - any temporary files (and directories)
+# - any Docker or Podman containers
+# - any Docker or Podman images
 #
 # Usage: ./clean.sh
 
 set -e
-(set -x; rm -vf /tmp/sightglass-benchmark-*)
-CONTAINERS=$(docker ps -a | grep 'sightglass-benchmark' | awk '{print $1}')
-if [[ ! -z "$CONTAINERS" ]]; then
-    (set -x; docker rm "$CONTAINERS")
-fi
-IMAGES=$(docker images --format '{{.Repository}}:{{.Tag}}' | grep 'sightglass-benchmark')
-if [[ ! -z "$IMAGES" ]]; then
-    (set -x; docker rmi "$IMAGES")
-fi
+(set -x; rm -rvf /tmp/sightglass-benchmark-*)
+# Benchmarks built with `--local` leave no container artifacts; remove those of whichever of Docker
+# and Podman are installed.
+for ENGINE in docker podman; do
+    if ! command -v $ENGINE > /dev/null; then
+        continue
+    fi
+    CONTAINERS=$($ENGINE ps -a | grep 'sightglass-benchmark' | awk '{print $1}' || true)
+    if [[ ! -z "$CONTAINERS" ]]; then
+        (set -x; $ENGINE rm $CONTAINERS)
+    fi
+    IMAGES=$($ENGINE images --format '{{.Repository}}:{{.Tag}}' \
+        | grep 'sightglass-benchmark' || true)
+    if [[ ! -z "$IMAGES" ]]; then
+        (set -x; $ENGINE rmi $IMAGES)
+    fi
+done
@@ -0,0 +1,486 @@
+//! Estimate the posterior distribution of the speedup between two engines, as an alternative to the
+//! confidence intervals of [crate::effect_size].
+//!
+//! The counts of each engine are modeled as normally distributed with an unknown mean and variance,
+//! under the uninformative (Jeffreys) prior; the posterior of the mean is then a Student's t
+//! distribution with one fewer degrees of freedom than there are samples, centered on their mean.
+//! With few samples its tails are wide, so the credible interval widens rather than claiming a
+//! difference the samples cannot support. The speedup is the ratio of the two engines' means,
+//! whose distribution is estimated by drawing from both posteriors; the draws of the speedups of
+//! several benchmarks combine into the distribution of their geometric mean (see [overall]).
+
+use crate::effect_size::{comparisons, describe, pairs, unpaired, Skip, Skipped};
+use crate::groups::Groups;
+use crate::summarize::run_label;
+use crate::units::Formatting;
+use crate::weights::Weights;
+use anyhow::Result;
+use sightglass_data::{Phase, Posterior};
+use std::{borrow::Cow, collections::BTreeMap, io::Write};
+
+/// How many draws to take from each posterior distribution.
+pub const DRAWS: usize = 2000;
+
+/// Estimate the posterior distribution of the speedup of each pair of engines of the groups of
+/// measurements, with a `credible_level` (e.g., 0.99) credible interval. The engines are paired as
+/// by [crate::effect_size::calculate_against], and the comparisons of engines with fewer than two
+/// samples, which give no estimate of their variance, are skipped. Unlike a confidence interval,
+/// a posterior needs no variance: samples that are all the same give a speedup without doubt.
+///
+/// The draws are deterministic, so that the same measurements always give the same results.
+pub fn calculate(
+    credible_level: f64,
+    baseline: Option<&str>,
+    groups: &Groups,
+) -> Result<(Vec<Posterior<'static>>, Vec<Skipped<'static>>)> {
+    anyhow::ensure!(
+        0.0 < credible_level && credible_level < 1.0,
+        "The credible level must be between 0.0 and 1.0; found {}.",
+        credible_level
+    );
+
+    let mut quantiles = Quantiles::default();
+    let mut results = vec![];
+    let mut skipped = vec![];
+    for (key, engines) in comparisons(groups) {
+        if let Some(reason) = unpaired(&engines, baseline) {
+            skipped.push(Skipped { key, reason });
+            continue;
+        }
+        for ((engine_a, a), (engine_b, b)) in pairs(&key, &engines, baseline)? {
+            if let Some((engine, counts)) = [(engine_a, a), (engine_b, b)]
+                .into_iter()
+                .find(|(_, counts)| counts.len() < 2)
+            {
+                skipped.push(Skipped {
+                    key: key.clone(),
+                    reason: Skip::TooFewSamples {
+                        engine: engine.clone(),
+                        count: counts.len(),
+                    },
+                });
+                continue;
+            }
+            let a: behrens_fisher::Stats = a.iter().map(|&c| c as f64).collect();
+            let b: behrens_fisher::Stats = b.iter().map(|&c| c as f64).collect();
+
+            // Pair the draws of the two means at random, seeded by what is compared.
+            let rng = fastrand::Rng::with_seed(seed(&[&describe(&key), engine_a, engine_b]));
+            let a_means = quantiles.draws(&a);
+            let mut b_means = quantiles.draws(&b);
+            rng.shuffle(&mut b_means);
+            let mut log_speedups: Vec<f64> = a_means
+                .iter()
+                .zip(&b_means)
+                .map(|(a, b)| a.ln() - b.ln())
+                .collect();
+            // Shuffle the speedups too, so that those of different benchmarks are independent.
+            rng.shuffle(&mut log_speedups);
+
+            let speedup = Speedup::of(&log_speedups, credible_level);
+            results.push(Posterior {
+                arch: key.arch.clone().unwrap(),
+                wasm: key.wasm.clone().unwrap(),
+                phase: key.phase.unwrap(),
+                event: key.event.clone().unwrap(),
+                a_engine: engine_a.clone(),
+                a_mean: a.mean,
+                b_engine: engine_b.clone(),
+                b_mean: b.mean,
+                credible_level,
+                speedup: speedup.median,
+                speedup_lower: speedup.lower,
+                speedup_upper: speedup.upper,
+                probability_faster: speedup.probability_faster,
+                threads: key.threads,
+                input_size: key.input_size.clone(),
+                execution: key.execution,
+                log_speedups,
+            });
+        }
+    }
+    Ok((results, skipped))
+}
+
+/// The quantiles of the standard Student's t distribution at evenly spaced probabilities, by
+/// degrees of freedom, which the draws from each posterior scale.
+#[derive(Default)]
+struct Quantiles(BTreeMap<usize, Vec<f64>>);
+
+impl Quantiles {
+    /// [DRAWS] draws from the posterior of the mean of the samples; as counts are never negative,
+    /// neither is a mean, so draws below zero (from the wide tails of few samples) are clamped to
+    /// the smallest positive number.
+    fn draws(&mut self, samples: &behrens_fisher::Stats) -> Vec<f64> {
+        let dof = samples.count - 1;
+        let quantiles = self.0.entry(dof).or_insert_with(|| {
+            (0..DRAWS)
+                .map(|i| {
+                    let p = (i as f64 + 0.5) / DRAWS as f64;
+                    behrens_fisher::student_t::inv_cdf(p, dof as f64)
+                })
+                .collect()
+        });
+        let scale = (samples.var / samples.count as f64).sqrt();
+        quantiles
+            .iter()
+            .map(|q| (samples.mean + scale * q).max(f64::MIN_POSITIVE))
+            .collect()
+    }
+}
+
+/// A seed for the draws of a comparison: the FNV-1a hash of its `parts`.
+fn seed(parts: &[&str]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in parts.iter().flat_map(|part| part.bytes().chain([0])) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// The summary of draws from the distribution of a speedup.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Speedup {
+    /// The median speedup.
+    pub median: f64,
+    /// The lower bound of the credible interval.
+    pub lower: f64,
+    /// The upper bound of the credible interval.
+    pub upper: f64,
+    /// The fraction of the speedups above 1.
+    pub probability_faster: f64,
+}
+
+impl Speedup {
+    /// Summarize the draws of the logarithm of a speedup, with a `credible_level` credible
+    /// interval: the equal-tailed interval, leaving as much probability below it as above.
+    pub fn of(log_speedups: &[f64], credible_level: f64) -> Self {
+        let mut sorted = log_speedups.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let quantile = |p: f64| match sorted.len() {
+            0 => f64::NAN,
+            n => sorted[((n - 1) as f64 * p).round() as usize].exp(),
+        };
+        let tail = (1.0 - credible_level) / 2.0;
+        Speedup {
+            median: quantile(0.5),
+            lower: quantile(tail),
+            upper: quantile(1.0 - tail),
+            probability_faster: sorted.iter().filter(|&&s| s > 0.0).count() as f64
+                / sorted.len() as f64,
+        }
+    }
+}
+
+/// The speedup of one engine over another across several benchmarks: the geometric mean of their
+/// speedups, as [overall] estimates it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overall<'a> {
+    pub arch: Cow<'a, str>,
+    pub phase: Phase,
+    pub event: Cow<'a, str>,
+    pub a_engine: Cow<'a, str>,
+    pub b_engine: Cow<'a, str>,
+    /// How many benchmarks (or runs of a benchmark, e.g., with different input sizes) the
+    /// speedup combines.
+    pub benchmarks: usize,
+    pub speedup: Speedup,
+}
+
+/// Combine the posteriors of each phase and event of each pair of engines into the distribution of
+/// the geometric mean of their speedups: as the draws of each posterior are independent of the
+/// others', the mean of the `i`th draws of their logarithms is a draw from the distribution of the
+/// logarithm of the geometric mean. The mean is weighted by the `weights` of the benchmarks, so that
+/// a benchmark that weighs 0 is left out. Posteriors without draws (e.g., read back from JSON) are
+/// left out too.
+pub fn overall<'a>(posteriors: &'a [Posterior<'_>], weights: &Weights) -> Vec<Overall<'a>> {
+    let mut combined: BTreeMap<_, Vec<(&Posterior, f64)>> = BTreeMap::new();
+    for p in posteriors.iter().filter(|p| p.log_speedups.len() == DRAWS) {
+        let weight = weights.of(&p.wasm);
+        if weight > 0.0 {
+            let key = (&*p.arch, p.phase, &*p.event, &*p.a_engine, &*p.b_engine);
+            combined.entry(key).or_default().push((p, weight));
+        }
+    }
+    combined
+        .into_iter()
+        .map(|((arch, phase, event, a_engine, b_engine), posteriors)| {
+            let total: f64 = posteriors.iter().map(|(_, weight)| weight).sum();
+            let log_speedups: Vec<f64> = (0..DRAWS)
+                .map(|i| {
+                    posteriors
+                        .iter()
+                        .map(|(p, weight)| p.log_speedups[i] * weight)
+                        .sum::<f64>()
+                        / total
+                })
+                .collect();
+            Overall {
+                arch: arch.into(),
+                phase,
+                event: event.into(),
+                a_engine: a_engine.into(),
+                b_engine: b_engine.into(),
+                benchmarks: posteriors.len(),
+                speedup: Speedup::of(&log_speedups, posteriors[0].0.credible_level),
+            }
+        })
+        .collect()
+}
+
+/// Write the posteriors, and their [overall] speedups across benchmarks, as weighted by the
+/// `weights`, to the passed `output_file` in human-readable form. Means are written as the
+/// `formatting` says (see [Formatting::count]):
+///
+/// ```text
+/// Speedups are posterior medians with 99% credible intervals.
+///
+/// execution :: cycles :: benchmarks/bz2/benchmark.wasm
+///
+///   pr.so over main.so: 1.25x (1.19x to 1.31x), 100.0% likely faster
+///   [1.23 M] main.so
+///   [985 k] pr.so
+///
+/// Overall, the geometric mean of each event's speedups:
+///
+///   execution :: cycles (1 benchmarks): pr.so over main.so: 1.25x (1.19x to 1.31x), 100.0% likely faster
+/// ```
+pub fn write(
+    posteriors: &[Posterior<'_>],
+    weights: &Weights,
+    formatting: Formatting,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    let credible_level = match posteriors.first() {
+        Some(p) => p.credible_level,
+        None => return Ok(()),
+    };
+    writeln!(
+        output_file,
+        "Speedups are posterior medians with {}% credible intervals.",
+        credible_level * 100.0
+    )?;
+    for p in posteriors {
+        writeln!(output_file)?;
+        writeln!(
+            output_file,
+            "{} :: {} :: {}{}",
+            p.phase,
+            p.event,
+            p.wasm,
+            run_label(p.threads, p.input_size.as_deref(), p.execution)
+        )?;
+        writeln!(output_file)?;
+        let speedup = Speedup {
+            median: p.speedup,
+            lower: p.speedup_lower,
+            upper: p.speedup_upper,
+            probability_faster: p.probability_faster,
+        };
+        writeln!(
+            output_file,
+            "  {}",
+            describe_speedup(&p.a_engine, &p.b_engine, speedup, formatting)
+        )?;
+        writeln!(
+            output_file,
+            "  [{}] {}",
+            formatting.count(&p.event, p.a_mean),
+            p.a_engine
+        )?;
+        writeln!(
+            output_file,
+            "  [{}] {}",
+            formatting.count(&p.event, p.b_mean),
+            p.b_engine
+        )?;
+    }
+
+    let overall = overall(posteriors, weights);
+    if !overall.is_empty() {
+        writeln!(output_file)?;
+        writeln!(
+            output_file,
+            "Overall, the {}geometric mean of each event's speedups:",
+            if weights.is_empty() { "" } else { "weighted " }
+        )?;
+        writeln!(output_file)?;
+        for o in &overall {
+            writeln!(
+                output_file,
+                "  {} :: {} ({} benchmarks): {}",
+                o.phase,
+                o.event,
+                o.benchmarks,
+                describe_speedup(&o.a_engine, &o.b_engine, o.speedup, formatting)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Describe `b`'s speedup over `a`; e.g., `pr.so over main.so: 1.25x (1.19x to 1.31x), 100.0%
+/// likely faster`.
+fn describe_speedup(a: &str, b: &str, speedup: Speedup, formatting: Formatting) -> String {
+    format!(
+        "{} over {}: {}x ({}x to {}x), {:.1}% likely faster",
+        b,
+        a,
+        formatting.number(speedup.median),
+        formatting.number(speedup.lower),
+        formatting.number(speedup.upper),
+        speedup.probability_faster * 100.0
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Measurement;
+
+    fn groups(samples: &[(&'static str, &'static str, &[u64])]) -> Groups {
+        let mut groups = Groups::default();
+        for (engine, wasm, counts) in samples {
+            for (i, &count) in counts.iter().enumerate() {
+                groups.add(&Measurement {
+                    arch: "x86_64".into(),
+                    engine: (*engine).into(),
+                    wasm: (*wasm).into(),
+                    process: 1,
+                    iteration: i as u32,
+                    phase: Phase::Execution,
+                    event: "cycles".into(),
+                    count,
+                    threads: None,
+                    input_size: None,
+                    execution: None,
+                });
+            }
+        }
+        groups
+    }
+
+    #[test]
+    fn posterior_of_speedup() -> Result<()> {
+        let groups = groups(&[
+            (
+                "main.so",
+                "bz2.wasm",
+                &[100, 102, 98, 101, 99, 100, 103, 97],
+            ),
+            ("pr.so", "bz2.wasm", &[80, 81, 79, 80, 82, 78, 80, 80]),
+            ("main.so", "noop.wasm", &[10, 10]),
+            ("pr.so", "noop.wasm", &[10]),
+        ]);
+        let (posteriors, skipped) = calculate(0.99, None, &groups)?;
+        assert_eq!(posteriors.len(), 1);
+        let p = &posteriors[0];
+        assert_eq!((&*p.a_engine, &*p.b_engine), ("main.so", "pr.so"));
+        assert!((p.speedup - 1.25).abs() < 0.01, "{}", p.speedup);
+        assert!(p.speedup_lower < p.speedup && p.speedup < p.speedup_upper);
+        assert!(p.speedup_lower > 1.2 && p.speedup_upper < 1.3);
+        assert_eq!(p.probability_faster, 1.0);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(
+            skipped[0].reason,
+            Skip::TooFewSamples {
+                engine: "pr.so".into(),
+                count: 1
+            }
+        );
+
+        // The same measurements always give the same draws.
+        let (again, _) = calculate(0.99, None, &groups)?;
+        assert_eq!(again[0].log_speedups, p.log_speedups);
+        Ok(())
+    }
+
+    #[test]
+    fn few_samples_widen_the_interval() -> Result<()> {
+        let interval = |a: &'static [u64], b: &'static [u64]| -> Result<f64> {
+            let groups = groups(&[("main.so", "bz2.wasm", a), ("pr.so", "bz2.wasm", b)]);
+            let p = &calculate(0.95, None, &groups)?.0[0];
+            Ok(p.speedup_upper - p.speedup_lower)
+        };
+        let few = interval(&[100, 104, 96], &[90, 94, 86])?;
+        let many = interval(
+            &[100, 104, 96, 100, 104, 96, 100, 104, 96],
+            &[90, 94, 86, 90, 94, 86, 90, 94, 86],
+        )?;
+        assert!(few > 2.0 * many, "{} vs. {}", few, many);
+
+        // Without variance, there is no doubt.
+        let none = groups(&[
+            ("main.so", "bz2.wasm", &[100, 100]),
+            ("pr.so", "bz2.wasm", &[50, 50]),
+        ]);
+        let p = &calculate(0.95, None, &none)?.0[0];
+        for speedup in [p.speedup_lower, p.speedup, p.speedup_upper] {
+            assert!((speedup - 2.0).abs() < 1e-9, "{}", speedup);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn combine_benchmarks() -> Result<()> {
+        let groups = groups(&[
+            ("main.so", "bz2.wasm", &[100, 102, 98, 101, 99]),
+            ("pr.so", "bz2.wasm", &[50, 51, 49, 50, 50]),
+            ("main.so", "fib.wasm", &[100, 102, 98, 101, 99]),
+            ("pr.so", "fib.wasm", &[200, 204, 196, 202, 198]),
+        ]);
+        let (posteriors, _) = calculate(0.99, None, &groups)?;
+        let combined = overall(&posteriors, &Weights::default());
+        assert_eq!(combined.len(), 1);
+        assert_eq!(combined[0].benchmarks, 2);
+        // Twice as fast and half as fast are, on average, the same.
+        let speedup = combined[0].speedup;
+        assert!((speedup.median - 1.0).abs() < 0.01, "{:?}", speedup);
+        assert!(speedup.lower < 1.0 && 1.0 < speedup.upper);
+        // Combined, the interval is narrower than either benchmark's (in logarithms).
+        let width = |lower: f64, upper: f64| upper.ln() - lower.ln();
+        for p in &posteriors {
+            assert!(width(speedup.lower, speedup.upper) < width(p.speedup_lower, p.speedup_upper));
+        }
+
+        let mut output = vec![];
+        write(
+            &posteriors,
+            &Weights::default(),
+            Formatting::default(),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.starts_with(
+            "Speedups are posterior medians with 99% credible intervals.\n\
+             \n\
+             execution :: cycles :: bz2.wasm\n\
+             \n  pr.so over main.so: 2.00x ("
+        ));
+        assert!(output.contains("), 100.0% likely faster\n  [100] main.so\n  [50] pr.so\n"));
+        assert!(output.contains(
+            "Overall, the geometric mean of each event's speedups:\n\
+             \n  execution :: cycles (2 benchmarks): pr.so over main.so: "
+        ));
+
+        // Weighing the faster benchmark three times as much as the slower, the speedup is that of
+        // `2^3 * 0.5` to the `1/4`.
+        let weights = Weights::read(r#"{"bz2.wasm": 3}"#.as_bytes())?;
+        let weighted = overall(&posteriors, &weights)[0].speedup;
+        assert!(
+            (weighted.median - 2f64.sqrt()).abs() < 0.01,
+            "{:?}",
+            weighted
+        );
+        let weights = Weights::read(r#"{"fib.wasm": 0}"#.as_bytes())?;
+        let combined = overall(&posteriors, &weights);
+        assert_eq!(combined[0].benchmarks, 1);
+        assert!((combined[0].speedup.median - 2.0).abs() < 0.01);
+        let mut output = vec![];
+        write(&posteriors, &weights, Formatting::default(), &mut output)?;
+        assert!(String::from_utf8(output)?
+            .contains("Overall, the weighted geometric mean of each event's speedups:"));
+        Ok(())
+    }
+}
@@ -0,0 +1,131 @@
+use crate::summarize::run_label;
+use anyhow::Result;
+use serde_json::{json, Map, Value};
+use sightglass_data::Summary;
+use std::collections::BTreeSet;
+use std::io::Write;
+
+/// Write [Summary] structures to `output_file` in the [Bencher Metric Format], for `bencher run`
+/// to track. Each phase of each benchmark is a Bencher benchmark (named, e.g., `execution ::
+/// benchmarks/bz2/benchmark.wasm`, followed by the engine if the summaries are of several) and
+/// each event is a measure of it (e.g., `cycles`), whose value is the mean and whose bounds are
+/// the minimum and maximum:
+///
+/// ```text
+/// {
+///   "execution :: benchmarks/noop/benchmark.wasm": {
+///     "cycles": {
+///       "value": 2.0,
+///       "lower_value": 1.0,
+///       "upper_value": 3.0
+///     }
+///   }
+/// }
+/// ```
+///
+/// [Bencher Metric Format]: https://bencher.dev/docs/reference/bencher-metric-format/
+pub fn write(summaries: &[Summary<'_>], output_file: &mut dyn Write) -> Result<()> {
+    let engines: BTreeSet<_> = summaries.iter().map(|s| &s.engine).collect();
+    let mut benchmarks = Map::new();
+    for summary in summaries {
+        let mut name = format!(
+            "{} :: {}{}",
+            summary.phase,
+            summary.wasm,
+            run_label(
+                summary.threads,
+                summary.input_size.as_deref(),
+                summary.execution
+            )
+        );
+        if engines.len() > 1 {
+            name = format!("{} :: {}", name, summary.engine);
+        }
+        let measures = benchmarks
+            .entry(name)
+            .or_insert_with(|| Value::Object(Map::new()));
+        measures[measure_slug(&summary.event)] = json!({
+            "value": summary.mean,
+            "lower_value": summary.min,
+            "upper_value": summary.max,
+        });
+    }
+    serde_json::to_writer_pretty(&mut *output_file, &benchmarks)?;
+    writeln!(output_file)?;
+    Ok(())
+}
+
+/// Bencher identifies measures by slugs of lowercase letters, digits, and dashes; e.g.,
+/// `cycles.user` is `cycles-user`.
+fn measure_slug(event: &str) -> String {
+    event
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    fn summary(engine: &str, event: &str, mean: f64) -> Summary<'static> {
+        Summary {
+            arch: "x86_64".into(),
+            engine: engine.to_string().into(),
+            wasm: "benchmarks/noop/benchmark.wasm".into(),
+            phase: Phase::Execution,
+            event: event.to_string().into(),
+            min: 1,
+            max: 3,
+            median: 2,
+            mean,
+            mean_deviation: 0.5,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_metric_format() -> Result<()> {
+        let mut output = vec![];
+        write(
+            &[
+                summary("libengine.so", "cycles", 2.0),
+                summary("libengine.so", "cycles.user", 2.5),
+            ],
+            &mut output,
+        )?;
+        let output: Value = serde_json::from_slice(&output)?;
+        assert_eq!(
+            output,
+            json!({
+                "execution :: benchmarks/noop/benchmark.wasm": {
+                    "cycles": { "value": 2.0, "lower_value": 1, "upper_value": 3 },
+                    "cycles-user": { "value": 2.5, "lower_value": 1, "upper_value": 3 },
+                }
+            })
+        );
+
+        // With several engines, each is its own benchmark.
+        let mut output = vec![];
+        write(
+            &[
+                summary("a.so", "cycles", 2.0),
+                summary("b.so", "cycles", 2.0),
+            ],
+            &mut output,
+        )?;
+        let output: Value = serde_json::from_slice(&output)?;
+        assert!(output["execution :: benchmarks/noop/benchmark.wasm :: a.so"].is_object());
+        assert!(output["execution :: benchmarks/noop/benchmark.wasm :: b.so"].is_object());
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use crate::units::significant;
+use anyhow::Result;
+use sightglass_data::EffectSize;
+use std::io::Write;
+
+/// Write a vector of [EffectSize] structures to `output_file` as one line per comparison, for
+/// grepping in CI logs and piping to other tools. Each line is a list of `key=value` pairs (in the
+/// `logfmt` style, quoting values with spaces): the benchmark, phase, event, and engines compared;
+/// the verdict on the second engine (`b`) against the first (`a`, the baseline), `faster`,
+/// `slower`, or `same` when the difference is not significant; and `b`'s speedup over `a`, with
+/// its confidence interval (or `-` when a mean is zero). A last line totals the verdicts, with a
+/// `status` of `regressed` if any is `slower` and `ok` otherwise:
+///
+/// ```text
+/// benchmark=bz2.wasm phase=execution event=cycles baseline=main.so engine=pr.so verdict=faster speedup=1.35 ci=1.32..1.38
+/// summary comparisons=1 faster=1 slower=0 same=0 status=ok
+/// ```
+///
+/// Numbers are written to `precision` significant digits. Returns the number of `slower` verdicts.
+pub fn write(
+    effect_sizes: &[EffectSize<'_>],
+    precision: usize,
+    output_file: &mut dyn Write,
+) -> Result<usize> {
+    let (mut faster, mut slower, mut same) = (0, 0, 0);
+    for e in effect_sizes {
+        let verdict = if !e.is_significant() {
+            same += 1;
+            "same"
+        } else if e.b_mean < e.a_mean {
+            faster += 1;
+            "faster"
+        } else {
+            slower += 1;
+            "slower"
+        };
+        let mut line = vec![
+            pair("benchmark", &e.wasm),
+            pair("phase", &e.phase.to_string()),
+            pair("event", &e.event),
+        ];
+        if let Some(input_size) = &e.input_size {
+            line.push(pair("input-size", input_size));
+        }
+        if let Some(threads) = e.threads {
+            line.push(pair("threads", &threads.to_string()));
+        }
+        if let Some(execution) = e.execution {
+            line.push(pair("execution", &execution.to_string()));
+        }
+        line.push(pair("baseline", &e.a_engine));
+        line.push(pair("engine", &e.b_engine));
+        line.push(pair("verdict", verdict));
+        // `a`'s mean over `b`'s is `b`'s speedup over `a`.
+        match e.checked_a_speed_up_over_b() {
+            Some((speedup, ci)) => {
+                line.push(pair("speedup", &significant(speedup, precision)));
+                line.push(pair(
+                    "ci",
+                    &format!(
+                        "{}..{}",
+                        significant(speedup - ci, precision),
+                        significant(speedup + ci, precision)
+                    ),
+                ));
+            }
+            None => {
+                line.push(pair("speedup", "-"));
+                line.push(pair("ci", "-"));
+            }
+        }
+        writeln!(output_file, "{}", line.join(" "))?;
+    }
+    writeln!(
+        output_file,
+        "summary comparisons={} faster={} slower={} same={} status={}",
+        effect_sizes.len(),
+        faster,
+        slower,
+        same,
+        if slower > 0 { "regressed" } else { "ok" }
+    )?;
+    Ok(slower)
+}
+
+/// Write a `key=value` pair, quoting the value if it is empty or has spaces, quotes, or `=`s.
+fn pair(key: &str, value: &str) -> String {
+    if value.is_empty() || value.contains([' ', '"', '=']) {
+        format!("{}={:?}", key, value)
+    } else {
+        format!("{}={}", key, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::{Model, Phase, Statistic, Transform};
+
+    fn effect_size(b_engine: &str, b_mean: f64) -> EffectSize<'static> {
+        EffectSize {
+            arch: "x86_64".into(),
+            wasm: "bz2.wasm".into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            a_engine: "main.so".into(),
+            a_mean: 100.0,
+            b_engine: b_engine.to_string().into(),
+            b_mean,
+            significance_level: 0.01,
+            half_width_confidence_interval: 5.0,
+            statistic: Statistic::Mean,
+            transform: Transform::None,
+            model: Model::Independent,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_lines() -> Result<()> {
+        let effect_sizes = vec![
+            effect_size("pr.so", 80.0),
+            effect_size("pr.so [-O opt-level=0]", 200.0),
+            EffectSize {
+                threads: Some(4),
+                ..effect_size("pr.so", 0.0)
+            },
+            effect_size("pr.so", 101.0),
+        ];
+        let mut output = vec![];
+        assert_eq!(write(&effect_sizes, 3, &mut output)?, 1);
+        assert_eq!(
+            String::from_utf8(output)?,
+            "benchmark=bz2.wasm phase=execution event=cycles baseline=main.so engine=pr.so \
+             verdict=faster speedup=1.25 ci=1.19..1.31\n\
+             benchmark=bz2.wasm phase=execution event=cycles baseline=main.so \
+             engine=\"pr.so [-O opt-level=0]\" verdict=slower speedup=0.500 ci=0.475..0.525\n\
+             benchmark=bz2.wasm phase=execution event=cycles threads=4 baseline=main.so \
+             engine=pr.so verdict=faster speedup=- ci=-\n\
+             benchmark=bz2.wasm phase=execution event=cycles baseline=main.so engine=pr.so \
+             verdict=same speedup=0.990 ci=0.941..1.04\n\
+             summary comparisons=4 faster=2 slower=1 same=1 status=regressed\n"
+        );
+
+        let mut output = vec![];
+        assert_eq!(write(&[], 3, &mut output)?, 0);
+        assert_eq!(
+            String::from_utf8(output)?,
+            "summary comparisons=0 faster=0 slower=0 same=0 status=ok\n"
+        );
+        Ok(())
+    }
+}
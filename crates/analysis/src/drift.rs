@@ -0,0 +1,260 @@
+//! Diagnose groups of measurements whose iterations are not independent samples: e.g., later
+//! iterations that run systematically slower or faster than earlier ones, as the CPU heats up and
+//! throttles or the page cache warms, which the statistics of the other reports assume away.
+
+use crate::effect_size::normal_quantile;
+use crate::groups::Groups;
+use crate::keys::Key;
+use crate::summarize::run_label;
+use anyhow::Result;
+use std::{collections::BTreeMap, fmt, io::Write};
+
+/// The diagnostics of the iterations of a group of measurements; see [calculate].
+#[derive(Clone, Debug)]
+pub struct Drift<'a> {
+    /// The group of measurements.
+    pub key: Key<'a>,
+    /// The number of iterations.
+    pub count: usize,
+    /// The linear trend of the counts over each process's iterations, as a fraction of their mean
+    /// per iteration; e.g., `0.01` for each iteration taking 1% longer than the last.
+    pub slope: f64,
+    /// Whether the trend is significant.
+    pub drifting: bool,
+    /// The lag-1 autocorrelation of each process's counts: how much an iteration's count predicts
+    /// the next one's, from -1 to 1 and about 0 for independent iterations.
+    pub autocorrelation: f64,
+    /// Whether the autocorrelation is significant.
+    pub correlated: bool,
+}
+
+impl fmt::Display for Drift<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut findings = vec![];
+        if self.drifting {
+            findings.push(format!(
+                "drifts by {:+.2}% of the mean per iteration",
+                self.slope * 100.0
+            ));
+        }
+        if self.correlated {
+            findings.push(format!(
+                "has a lag-1 autocorrelation of {:.2}",
+                self.autocorrelation
+            ));
+        }
+        write!(
+            f,
+            "{} :: {} :: {}{} ({}): over {} iterations, {}",
+            self.key.phase.unwrap(),
+            self.key.event.as_deref().unwrap(),
+            self.key.wasm.as_deref().unwrap(),
+            run_label(
+                self.key.threads,
+                self.key.input_size.as_deref(),
+                self.key.execution
+            ),
+            self.key.engine.as_deref().unwrap(),
+            self.count,
+            findings.join(" and ")
+        )
+    }
+}
+
+/// Find the groups whose iterations drift or are autocorrelated, at the `significance_level`.
+///
+/// Each process's iterations are compared with the others of the same process (so that processes
+/// that run some way off each other do not look like drift), when the `groups` keep their processes
+/// (see [Groups::with_processes]); otherwise, all of a group's counts are taken as one sequence.
+/// The drift is the slope of a least-squares line through the counts of each process, tested
+/// against none by its `t` statistic, and the autocorrelation is tested against none by its
+/// large-sample bound, `z / √n`. Groups with too few iterations to test are left out.
+pub fn calculate(significance_level: f64, groups: &Groups) -> Result<Vec<Drift<'static>>> {
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&significance_level),
+        "The significance_level must be between 0.0 and 1.0; found {}.",
+        significance_level,
+    );
+    let mut drifts = vec![];
+    for (key, counts) in groups.iter() {
+        let mut sequences: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+        match groups.processes(key) {
+            Some(processes) => {
+                for (&count, &process) in counts.iter().zip(processes) {
+                    sequences.entry(process).or_default().push(count as f64);
+                }
+            }
+            None => {
+                sequences.insert(0, counts.iter().map(|&c| c as f64).collect());
+            }
+        }
+        if let Some(drift) = diagnose(key, significance_level, sequences.values()) {
+            if drift.drifting || drift.correlated {
+                drifts.push(drift);
+            }
+        }
+    }
+    Ok(drifts)
+}
+
+/// Diagnose the iterations of the `sequences` of counts of a group, one per process; see
+/// [calculate]. Returns `None` if there are too few of them, or they do not vary.
+fn diagnose<'a>(
+    key: &Key<'static>,
+    significance_level: f64,
+    sequences: impl Iterator<Item = &'a Vec<f64>>,
+) -> Option<Drift<'static>> {
+    // Sums of squares and products of each process's counts and iterations, as deviations from
+    // their means in the process.
+    let (mut n, mut k, mut sum) = (0.0, 0.0, 0.0);
+    let (mut sxx, mut sxy, mut syy, mut lagged) = (0.0, 0.0, 0.0, 0.0);
+    for counts in sequences {
+        let len = counts.len() as f64;
+        let mean = counts.iter().sum::<f64>() / len;
+        let middle = (len - 1.0) / 2.0;
+        let deviations: Vec<f64> = counts.iter().map(|count| count - mean).collect();
+        for (i, &y) in deviations.iter().enumerate() {
+            let x = i as f64 - middle;
+            sxx += x * x;
+            sxy += x * y;
+            syy += y * y;
+        }
+        lagged += deviations
+            .windows(2)
+            .map(|pair| pair[0] * pair[1])
+            .sum::<f64>();
+        n += len;
+        k += 1.0;
+        sum += mean * len;
+    }
+    // A slope, and the variance around it, need at least one more iteration than processes.
+    let dof = n - k - 1.0;
+    if dof < 1.0 || sxx == 0.0 || syy == 0.0 {
+        return None;
+    }
+
+    let slope = sxy / sxx;
+    let residual = (syy - slope * sxy).max(0.0);
+    let standard_error = (residual / dof / sxx).sqrt();
+    // The quantile of Student's t takes time linear in its degrees of freedom, and by a thousand
+    // it is as good as normal.
+    let t = behrens_fisher::student_t::inv_cdf(1.0 - significance_level / 2.0, dof.min(1000.0));
+    let drifting = slope.abs() > t * standard_error;
+
+    let autocorrelation = lagged / syy;
+    let correlated =
+        autocorrelation.abs() > normal_quantile(1.0 - significance_level / 2.0) / n.sqrt();
+
+    Some(Drift {
+        key: key.clone(),
+        count: n as usize,
+        slope: slope / (sum / n),
+        drifting,
+        autocorrelation,
+        correlated,
+    })
+}
+
+/// Write the groups whose iterations drift or are autocorrelated (see [calculate]) to
+/// `output_file`, if any, following a human-readable summary.
+pub fn write(drifts: &[Drift<'_>], output_file: &mut dyn Write) -> Result<()> {
+    if drifts.is_empty() {
+        return Ok(());
+    }
+    writeln!(output_file)?;
+    writeln!(
+        output_file,
+        "Iterations that are not independent samples (e.g., of a thermal ramp or a warming cache):"
+    )?;
+    for drift in drifts {
+        writeln!(output_file, "  {}", drift)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::{Measurement, Phase};
+
+    fn measurement(engine: &str, process: u32, count: u64) -> Measurement<'_> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: "bz2.wasm".into(),
+            process,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn diagnose_iterations() -> Result<()> {
+        let noise = [2, 3, -1, -3, 4, 1, 0, -4, -2, 3, -1, -2];
+        let mut measurements = vec![];
+        for process in 0..3 {
+            for (i, n) in noise.iter().enumerate() {
+                // Independent iterations, with each process some way off the others...
+                measurements.push(measurement(
+                    "steady.so",
+                    process,
+                    (1000 + 50 * process as i64 + n) as u64,
+                ));
+                // ...iterations that warm up...
+                measurements.push(measurement(
+                    "warming.so",
+                    process,
+                    (1000 - 5 * i as i64 + n) as u64,
+                ));
+                // ...and iterations that wander, each near the last.
+                let wander = [0, 10, 20, 30, 30, 20, 10, 0, 0, 10, 20, 30][i];
+                measurements.push(measurement(
+                    "wandering.so",
+                    process,
+                    (1000 + wander + n) as u64,
+                ));
+            }
+        }
+        let mut groups = Groups::with_processes();
+        for m in &measurements {
+            groups.add(m);
+        }
+
+        let drifts = calculate(0.01, &groups)?;
+        let engines: Vec<_> = drifts
+            .iter()
+            .map(|d| d.key.engine.as_deref().unwrap())
+            .collect();
+        assert_eq!(engines, ["wandering.so", "warming.so"]);
+        let (wandering, warming) = (&drifts[0], &drifts[1]);
+        assert!(warming.drifting);
+        assert!((warming.slope + 0.0054).abs() < 0.0001);
+        assert!(!wandering.drifting && wandering.correlated);
+        assert!(wandering.autocorrelation > 0.5);
+
+        let mut output = vec![];
+        write(&drifts, &mut output)?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains(
+            "  execution :: cycles :: bz2.wasm (warming.so): over 36 iterations, drifts by -0.54% \
+             of the mean per iteration and has a lag-1 autocorrelation of 0.73\n"
+        ));
+        assert!(output.contains(
+            "  execution :: cycles :: bz2.wasm (wandering.so): over 36 iterations, has a lag-1 \
+             autocorrelation of 0.55\n"
+        ));
+
+        // Without their processes, the processes' offsets look like a trend of the steady engine.
+        let groups: Groups = measurements.iter().collect();
+        let drifts = calculate(0.01, &groups)?;
+        assert!(drifts
+            .iter()
+            .any(|d| d.key.engine.as_deref() == Some("steady.so")));
+        Ok(())
+    }
+}
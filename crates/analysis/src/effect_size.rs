@@ -1,7 +1,16 @@
-use crate::keys::KeyBuilder;
+use crate::groups::Groups;
+use crate::keys::Key;
+use crate::summarize::run_label;
+use crate::units::Formatting;
+use anyhow::Context;
 use anyhow::Result;
-use sightglass_data::{EffectSize, Measurement, Phase, Summary};
-use std::{collections::BTreeSet, io::Write};
+use sightglass_data::{EffectSize, Measurement, Model, Phase, Statistic, Summary, Transform};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Write,
+};
 
 /// Find the effect size (and confidence interval) of between two different
 /// engines (i.e. two different commits of Wasmtime).
@@ -17,6 +26,68 @@ pub fn calculate<'a>(
     significance_level: f64,
     measurements: &[Measurement<'a>],
 ) -> Result<Vec<EffectSize<'a>>> {
+    calculate_grouped(significance_level, &measurements.iter().collect())
+}
+
+/// Find the effect sizes between the two engines of the groups of measurements; e.g., as they were
+/// read from a large results file. See [calculate].
+pub fn calculate_grouped(
+    significance_level: f64,
+    groups: &Groups,
+) -> Result<Vec<EffectSize<'static>>> {
+    calculate_against(significance_level, None, groups)
+}
+
+/// Find the effect sizes between the engines of the groups of measurements: with a `baseline`
+/// engine, between it (as `a`) and each of the other engines (as `b`), however many there are;
+/// e.g., to compare `main` with several experimental branches. Otherwise, as [calculate_grouped],
+/// between exactly two engines. The comparisons that cannot be made are left out; see
+/// [calculate_skipping] for why.
+pub fn calculate_against(
+    significance_level: f64,
+    baseline: Option<&str>,
+    groups: &Groups,
+) -> Result<Vec<EffectSize<'static>>> {
+    Ok(calculate_skipping(significance_level, baseline, groups)?.0)
+}
+
+/// As [calculate_against], but also returning the comparisons skipped for want of results to
+/// compare (e.g., a benchmark run by only one engine, or once), so that they can be listed rather
+/// than failing the whole analysis.
+pub fn calculate_skipping(
+    significance_level: f64,
+    baseline: Option<&str>,
+    groups: &Groups,
+) -> Result<(Vec<EffectSize<'static>>, Vec<Skipped<'static>>)> {
+    calculate_with(Method::default(), significance_level, baseline, groups)
+}
+
+/// How [calculate_with] compares the counts of two engines.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Method {
+    /// The statistic compared: the engines' means or, e.g., for latency-like events whose medians
+    /// are what is reported, their medians (see [hodges_lehmann]). Samples without variance can
+    /// be compared by their medians.
+    pub statistic: Statistic,
+    /// How the counts are first transformed: e.g., comparing their logarithms models a
+    /// multiplicative difference, as for long-tailed timings whose variance grows with their
+    /// mean, rather than an additive one. The statistics and the confidence interval are then
+    /// transformed back into counts.
+    pub transform: Transform,
+    /// How the variation of the counts is modeled: as independent or, with the iterations nested
+    /// within their processes, with the processes as random effects (see [nested_mean]). Only
+    /// means can be compared with nested processes, and only of [Groups::with_processes].
+    pub model: Model,
+}
+
+/// As [calculate_skipping], but comparing the engines by a [Method]. Either way, the confidence
+/// interval of the means is Welch's, which does not assume that the engines' counts vary alike.
+pub fn calculate_with(
+    method: Method,
+    significance_level: f64,
+    baseline: Option<&str>,
+    groups: &Groups,
+) -> Result<(Vec<EffectSize<'static>>, Vec<Skipped<'static>>)> {
     anyhow::ensure!(
         0.0 <= significance_level && significance_level <= 1.0,
         "The significance_level must be between 0.0 and 1.0. \
@@ -24,146 +95,743 @@ pub fn calculate<'a>(
              Found {}.",
         significance_level,
     );
+    let Method {
+        statistic,
+        transform,
+        model,
+    } = method;
+    anyhow::ensure!(
+        statistic == Statistic::Mean || model == Model::Independent,
+        "only means can be compared with the iterations nested within their processes"
+    );
 
-    let keys = KeyBuilder::all().engine(false).keys(measurements);
+    let keys = comparisons(groups);
     let mut results = Vec::with_capacity(keys.len());
+    let mut skipped = vec![];
+    for (key, engines) in keys {
+        if let Some(reason) = unpaired(&engines, baseline) {
+            skipped.push(Skipped { key, reason });
+            continue;
+        }
+        for ((engine_a, a), (engine_b, b)) in pairs(&key, &engines, baseline)? {
+            let processes = |engine: &Cow<'static, str>| -> Result<Option<&[u32]>> {
+                if model == Model::Independent {
+                    return Ok(None);
+                }
+                let key = Key {
+                    engine: Some(engine.clone()),
+                    ..key.clone()
+                };
+                let processes = groups
+                    .processes(&key)
+                    .context("the processes of the measurements were not kept")?;
+                Ok(Some(processes))
+            };
+            let (a_processes, b_processes) = (processes(engine_a)?, processes(engine_b)?);
+            let reason = [(engine_a, a, a_processes), (engine_b, b, b_processes)]
+                .into_iter()
+                .find_map(|(engine, counts, processes)| match counts {
+                    [] | [_] => Some(Skip::TooFewSamples {
+                        engine: engine.clone(),
+                        count: counts.len(),
+                    }),
+                    _ if transform == Transform::Log && counts.contains(&0) => {
+                        Some(Skip::ZeroCount {
+                            engine: engine.clone(),
+                        })
+                    }
+                    [first, rest @ ..]
+                        if statistic == Statistic::Mean && rest.iter().all(|c| c == first) =>
+                    {
+                        Some(Skip::NoVariance {
+                            engine: engine.clone(),
+                        })
+                    }
+                    _ => {
+                        let count = processes?.iter().collect::<BTreeSet<_>>().len();
+                        (count < 2).then(|| Skip::TooFewProcesses {
+                            engine: engine.clone(),
+                            count,
+                        })
+                    }
+                });
+            if let Some(reason) = reason {
+                skipped.push(Skipped {
+                    key: key.clone(),
+                    reason,
+                });
+                continue;
+            }
+            let transformed = |counts: &[u64]| -> Vec<f64> {
+                counts
+                    .iter()
+                    .map(|&c| match transform {
+                        Transform::None => c as f64,
+                        Transform::Log => (c as f64).ln(),
+                    })
+                    .collect()
+            };
+            let (a, b) = (transformed(a), transformed(b));
+            let (a_center, b_center, minus, plus) = match (statistic, a_processes, b_processes) {
+                (Statistic::Mean, Some(a_processes), Some(b_processes)) => {
+                    let (a_mean, a_var, a_dof) = nested_mean(&a, a_processes);
+                    let (b_mean, b_var, b_dof) = nested_mean(&b, b_processes);
+                    // Welch's interval, with the Welch–Satterthwaite degrees of freedom.
+                    let var = a_var + b_var;
+                    let dof = var * var / (a_var * a_var / a_dof + b_var * b_var / b_dof);
+                    let t = behrens_fisher::student_t::inv_cdf(1.0 - significance_level / 2.0, dof);
+                    let ci = t * var.sqrt();
+                    (a_mean, b_mean, ci, ci)
+                }
+                (Statistic::Mean, _, _) => {
+                    let a: behrens_fisher::Stats = a.into_iter().collect();
+                    let b: behrens_fisher::Stats = b.into_iter().collect();
+                    let ci = behrens_fisher::confidence_interval(1.0 - significance_level, a, b)?;
+                    (a.mean, b.mean, ci, ci)
+                }
+                (Statistic::Median, _, _) => hodges_lehmann(significance_level, a, b),
+            };
+            // Transformed back, the interval need not be symmetric; keep its wider half.
+            let (a_mean, b_mean, ci) = match transform {
+                Transform::None => (a_center, b_center, minus.max(plus)),
+                Transform::Log => {
+                    let b_mean = b_center.exp();
+                    let ci = b_mean * (1.0 - (-minus).exp()).max(plus.exp() - 1.0);
+                    (a_center.exp(), b_mean, ci)
+                }
+            };
+            results.push(EffectSize {
+                arch: key.arch.clone().unwrap(),
+                wasm: key.wasm.clone().unwrap(),
+                phase: key.phase.unwrap(),
+                event: key.event.clone().unwrap(),
+                a_engine: engine_a.clone(),
+                a_mean,
+                b_engine: engine_b.clone(),
+                b_mean,
+                significance_level,
+                half_width_confidence_interval: ci,
+                statistic,
+                transform,
+                model,
+                threads: key.threads,
+                input_size: key.input_size.clone(),
+                execution: key.execution,
+            });
+        }
+    }
 
-    for key in keys {
-        let key_measurements: Vec<_> = measurements.iter().filter(|m| key.matches(m)).collect();
+    Ok((results, skipped))
+}
 
-        // NB: `BTreeSet` so they're always sorted.
-        let engines: BTreeSet<_> = key_measurements.iter().map(|m| &m.engine).collect();
-        anyhow::ensure!(
-            engines.len() == 2,
-            "Can only test significance between exactly two different engines. Found {} \
-                 different engines.",
-            engines.len()
-        );
+/// Estimate the mean of the `values` measured in the `processes`, with the iterations nested within
+/// their processes: each process's mean varies around the overall mean as a random effect, and its
+/// iterations around the process's mean. Return the estimate, its variance, and the degrees of
+/// freedom of that variance.
+///
+/// The two variance components are estimated by the method of moments (a one-way analysis of
+/// variance), which also handles processes with different numbers of iterations. When the processes
+/// vary, the estimate is the mean of the processes' means, each weighted by how precisely it is
+/// known, and its variance has one fewer degrees of freedom than there are processes: many
+/// iterations of few processes cannot make it precise. When they do not vary more than their
+/// iterations would have them, the estimate is the mean of all of the values, as though they were
+/// independent. There are at least two processes.
+fn nested_mean(values: &[f64], processes: &[u32]) -> (f64, f64, f64) {
+    let mut by_process: BTreeMap<u32, (f64, f64)> = BTreeMap::new();
+    for (&value, process) in values.iter().zip(processes) {
+        let (sum, count) = by_process.entry(*process).or_default();
+        *sum += value;
+        *count += 1.0;
+    }
+    let means: BTreeMap<u32, (f64, f64)> = by_process
+        .into_iter()
+        .map(|(process, (sum, count))| (process, (sum / count, count)))
+        .collect();
 
-        let mut engines = engines.into_iter();
-        let engine_a = engines.next().unwrap();
-        let engine_b = engines.next().unwrap();
+    let n = values.len() as f64;
+    let k = means.len() as f64;
+    let grand_mean = values.iter().sum::<f64>() / n;
+    let within: f64 = values
+        .iter()
+        .zip(processes)
+        .map(|(value, process)| (value - means[process].0).powi(2))
+        .sum();
+    let between: f64 = means
+        .values()
+        .map(|(mean, count)| count * (mean - grand_mean).powi(2))
+        .sum();
+    let within_ms = if n > k { within / (n - k) } else { 0.0 };
+    let between_ms = between / (k - 1.0);
+    // The effective number of iterations per process, for processes of different numbers.
+    let n0 = (n - means.values().map(|(_, count)| count * count).sum::<f64>() / n) / (k - 1.0);
+    let process_var = ((between_ms - within_ms) / n0).max(0.0);
 
-        let a: behrens_fisher::Stats = key_measurements
-            .iter()
-            .filter(|m| m.engine.as_ref() == engine_a)
-            .map(|m| m.count as f64)
+    if process_var > 0.0 {
+        let weights: Vec<(f64, f64)> = means
+            .values()
+            .map(|&(mean, count)| (mean, 1.0 / (process_var + within_ms / count)))
             .collect();
-        let b: behrens_fisher::Stats = key_measurements
+        let total: f64 = weights.iter().map(|(_, weight)| weight).sum();
+        let mean = weights
             .iter()
-            .filter(|m| m.engine.as_ref() == engine_b)
-            .map(|m| m.count as f64)
-            .collect();
+            .map(|(mean, weight)| mean * weight)
+            .sum::<f64>()
+            / total;
+        (mean, 1.0 / total, k - 1.0)
+    } else {
+        (grand_mean, within_ms / n, n - k)
+    }
+}
 
-        let ci = behrens_fisher::confidence_interval(1.0 - significance_level, a, b)?;
-        results.push(EffectSize {
-            arch: key.arch.unwrap(),
-            wasm: key.wasm.unwrap(),
-            phase: key.phase.unwrap(),
-            event: key.event.unwrap(),
-            a_engine: engine_a.clone(),
-            a_mean: a.mean,
-            b_engine: engine_b.clone(),
-            b_mean: b.mean,
-            significance_level,
-            half_width_confidence_interval: ci,
+/// How the difference of an [EffectSize] was estimated, to label it with: e.g., ` (median)`, or
+/// nothing for the plain difference of the means.
+fn label(effect_size: &EffectSize<'_>) -> String {
+    let mut parts = vec![];
+    match (effect_size.statistic, effect_size.transform) {
+        (Statistic::Mean, Transform::None) => {}
+        (Statistic::Mean, Transform::Log) => parts.push("geometric mean"),
+        (Statistic::Median, Transform::None) => parts.push("median"),
+        (Statistic::Median, Transform::Log) => parts.push("median, log"),
+    }
+    if effect_size.model == Model::Nested {
+        parts.push("nested processes");
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join("; "))
+    }
+}
+
+/// Compare the counts of two engines by their medians: return the median of `a`, that median
+/// shifted by the Hodges–Lehmann estimate of the difference between `b` and `a` (the median of the
+/// differences of every pair of their counts), and how far the estimate's confidence interval at
+/// the `significance_level` extends below and above it. The interval is distribution-free: it runs
+/// between the `k`th smallest and largest differences, where `k` is the critical value of the
+/// Mann–Whitney U statistic, by its normal approximation. With too few counts for any `k` to reach
+/// the significance level, the interval spans all of the differences.
+fn hodges_lehmann(significance_level: f64, mut a: Vec<f64>, b: Vec<f64>) -> (f64, f64, f64, f64) {
+    let mut differences: Vec<f64> = b
+        .iter()
+        .flat_map(|&y| a.iter().map(move |&x| y - x))
+        .collect();
+    differences.sort_by(f64::total_cmp);
+    let estimate = median(&differences);
+
+    let (n, m) = (a.len() as f64, b.len() as f64);
+    let z = normal_quantile(1.0 - significance_level / 2.0);
+    let k = (n * m / 2.0 - z * (n * m * (n + m + 1.0) / 12.0).sqrt()).floor();
+    let k = (k.max(0.0) as usize).min((differences.len() - 1) / 2);
+    let (lower, upper) = (differences[k], differences[differences.len() - 1 - k]);
+
+    a.sort_by(f64::total_cmp);
+    let a_median = median(&a);
+    (
+        a_median,
+        a_median + estimate,
+        estimate - lower,
+        upper - estimate,
+    )
+}
+
+/// The median of the `sorted` numbers, of which there is at least one.
+fn median(sorted: &[f64]) -> f64 {
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+/// The quantile of the standard normal distribution at the probability `p`, by Acklam's rational
+/// approximation (accurate to about 1e-9).
+pub(crate) fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969683028665376e1,
+        2.209460984245205e2,
+        -2.759285104469687e2,
+        1.38357751867269e2,
+        -3.066479806614716e1,
+        2.506628277459239,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e1,
+        1.615858368580409e2,
+        -1.556989798598866e2,
+        6.680131188771972e1,
+        -1.328068155288572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-3,
+        -3.223964580411365e-1,
+        -2.400758277161838,
+        -2.549732539343734,
+        4.374664141464968,
+        2.938163982698783,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-3,
+        3.224671290700398e-1,
+        2.445134137142996,
+        3.754408661907416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    const LOW: f64 = 0.02425;
+    if p < LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// A comparison of engines that [calculate_skipping] could not make.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Skipped<'a> {
+    /// The groups of measurements that were to be compared, but for their engines.
+    pub key: Key<'a>,
+    /// Why the comparison was skipped.
+    pub reason: Skip,
+}
+
+/// Why a comparison was skipped.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Skip {
+    /// Only one engine has results; e.g., a benchmark that another engine skipped or failed.
+    OneEngine { engine: Cow<'static, str> },
+    /// The baseline engine has no results, only these other engines.
+    NoBaseline { engines: Vec<Cow<'static, str>> },
+    /// An engine has too few samples to estimate their variance (at least two are needed).
+    TooFewSamples {
+        engine: Cow<'static, str>,
+        count: usize,
+    },
+    /// An engine's samples are all the same, so their variance, which the confidence interval
+    /// needs, is zero.
+    NoVariance { engine: Cow<'static, str> },
+    /// An engine has a count of zero, which has no logarithm to compare (see [Transform::Log]).
+    ZeroCount { engine: Cow<'static, str> },
+    /// An engine's samples are of too few processes to estimate how the processes vary (see
+    /// [Model::Nested]).
+    TooFewProcesses {
+        engine: Cow<'static, str>,
+        count: usize,
+    },
+}
+
+impl fmt::Display for Skipped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", describe(&self.key))?;
+        match &self.reason {
+            Skip::OneEngine { engine } => write!(f, "only {} has results", engine),
+            Skip::NoBaseline { engines } => {
+                let engines: Vec<_> = engines.iter().map(|e| e.as_ref()).collect();
+                write!(
+                    f,
+                    "the baseline has no results, only {}",
+                    engines.join(", ")
+                )
+            }
+            Skip::TooFewSamples { engine, count } => write!(
+                f,
+                "{} has {} samples, but at least 2 are needed",
+                engine, count
+            ),
+            Skip::NoVariance { engine } => write!(
+                f,
+                "every sample of {} is the same, so its variance cannot be estimated",
+                engine
+            ),
+            Skip::ZeroCount { engine } => write!(
+                f,
+                "{} has a count of zero, which has no logarithm to compare",
+                engine
+            ),
+            Skip::TooFewProcesses { engine, count } => write!(
+                f,
+                "{} has samples of {} processes, but at least 2 are needed",
+                engine, count
+            ),
+        }
+    }
+}
+
+/// Why the engines of a key cannot be paired (see [pairs]) for want of results, if they cannot.
+pub(crate) fn unpaired(engines: &Engines, baseline: Option<&str>) -> Option<Skip> {
+    if let [(engine, _)] = engines.as_slice() {
+        return Some(Skip::OneEngine {
+            engine: engine.clone(),
+        });
+    }
+    let baseline = baseline?;
+    if engines.iter().all(|(engine, _)| engine != baseline) {
+        return Some(Skip::NoBaseline {
+            engines: engines.iter().map(|(engine, _)| engine.clone()).collect(),
         });
     }
+    None
+}
+
+/// Write the [Skipped] comparisons, if any, to the passed `output_file` in human-readable form, at
+/// the end of a report.
+pub fn write_skipped(skipped: &[Skipped<'_>], output_file: &mut dyn Write) -> Result<()> {
+    if skipped.is_empty() {
+        return Ok(());
+    }
+    writeln!(output_file)?;
+    writeln!(output_file, "Skipped {} comparisons:", skipped.len())?;
+    for s in skipped {
+        writeln!(output_file, "  {}", s)?;
+    }
+    Ok(())
+}
+
+/// An engine compared for a key, with its counts.
+pub(crate) type Engine<'a> = (Cow<'static, str>, &'a [u64]);
+
+/// The engines compared for a key.
+pub(crate) type Engines<'a> = Vec<Engine<'a>>;
+
+/// The groups of each key, but for the engine; as the groups are sorted, so are their engines.
+pub(crate) fn comparisons(groups: &Groups) -> BTreeMap<Key<'static>, Engines<'_>> {
+    let mut keys: BTreeMap<Key, Vec<_>> = BTreeMap::new();
+    for (key, counts) in groups.iter() {
+        let engine = key.engine.clone().unwrap();
+        let key = Key {
+            engine: None,
+            ..key.clone()
+        };
+        keys.entry(key).or_default().push((engine, counts));
+    }
+    keys
+}
 
-    Ok(results)
+/// The pairs of engines to compare for a key: its two engines or, given a `baseline`, the baseline
+/// and each other engine.
+pub(crate) fn pairs<'a, 'b>(
+    key: &Key,
+    engines: &'b Engines<'a>,
+    baseline: Option<&str>,
+) -> Result<Vec<(&'b Engine<'a>, &'b Engine<'a>)>> {
+    let names = || {
+        let names: Vec<_> = engines.iter().map(|(engine, _)| engine.as_ref()).collect();
+        names.join(", ")
+    };
+    let baseline = match baseline {
+        Some(baseline) => baseline,
+        None => {
+            anyhow::ensure!(
+                engines.len() == 2,
+                "Can only test significance between exactly two different engines. Found {} \
+                 for {}: {}. Choose one as the baseline to compare the others with.",
+                engines.len(),
+                describe(key),
+                names()
+            );
+            return Ok(vec![(&engines[0], &engines[1])]);
+        }
+    };
+    let a = engines
+        .iter()
+        .find(|(engine, _)| engine == baseline)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "The baseline engine {} has no results for {}; found {}.",
+                baseline,
+                describe(key),
+                names()
+            )
+        })?;
+    Ok(engines
+        .iter()
+        .filter(|(engine, _)| engine != baseline)
+        .map(|b| (a, b))
+        .collect())
+}
+
+/// Describe a key, but for its engine; e.g., `execution :: cycles :: bz2.wasm on x86_64`.
+pub(crate) fn describe(key: &Key) -> String {
+    format!(
+        "{} :: {} :: {}{} on {}",
+        key.phase.unwrap(),
+        key.event.as_deref().unwrap(),
+        key.wasm.as_deref().unwrap(),
+        run_label(key.threads, key.input_size.as_deref(), key.execution),
+        key.arch.as_deref().unwrap()
+    )
+}
+
+/// The statistical power [underpowered] plans for: the chance that a difference as large as the
+/// one sought is found significant.
+pub const POWER: f64 = 0.8;
+
+/// A comparison of two engines with too few samples to detect the difference sought between them,
+/// given the variance of their samples; see [underpowered].
+#[derive(Clone, Debug)]
+pub struct Underpowered<'a> {
+    /// The compared groups of measurements, but for their engines; compared to a baseline, the
+    /// engine compared to it.
+    pub key: Key<'a>,
+    /// The number of samples of each engine.
+    pub counts: (usize, usize),
+    /// The smallest difference between the engines, as a fraction of the first engine's mean,
+    /// that these samples would find significant with [POWER]; infinite when there are too few
+    /// samples to estimate their variance.
+    pub minimum_detectable_effect: f64,
+    /// About how many samples of each engine are needed to detect the difference sought, if it
+    /// can be estimated.
+    pub required_count: Option<usize>,
+}
+
+impl fmt::Display for Underpowered<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} :: {} :: {}{}{}: with {} and {} samples, ",
+            self.key.phase.unwrap(),
+            self.key.event.as_deref().unwrap(),
+            self.key.wasm.as_deref().unwrap(),
+            run_label(
+                self.key.threads,
+                self.key.input_size.as_deref(),
+                self.key.execution
+            ),
+            match &self.key.engine {
+                Some(engine) => format!(" ({})", engine),
+                None => String::new(),
+            },
+            self.counts.0,
+            self.counts.1
+        )?;
+        if self.minimum_detectable_effect.is_finite() {
+            write!(
+                f,
+                "only differences of {:.1}% or more are likely to be found",
+                self.minimum_detectable_effect * 100.0
+            )?;
+        } else {
+            write!(f, "no difference can be found")?;
+        }
+        match self.required_count {
+            Some(count) => write!(f, "; about {} samples of each are needed", count),
+            None => write!(f, "; at least 2 samples of each are needed"),
+        }
+    }
+}
+
+/// Find the comparisons of two engines whose samples are too few, given their variance, to find a
+/// difference of `min_effect` (a fraction of the first engine's mean) significant at the
+/// `significance_level` with [POWER]; e.g., three iterations at 99% confidence find "no
+/// difference" for almost everything. This uses the same (Welch's) approximation as [calculate].
+/// The engines are paired as by [calculate_against]; keys whose engines cannot be are skipped.
+pub fn underpowered(
+    significance_level: f64,
+    min_effect: f64,
+    baseline: Option<&str>,
+    groups: &Groups,
+) -> Vec<Underpowered<'static>> {
+    let mut underpowered = vec![];
+    let comparisons = comparisons(groups);
+    let pairs = comparisons.iter().flat_map(|(key, engines)| {
+        let pairs = pairs(key, engines, baseline).unwrap_or_default();
+        pairs.into_iter().map(move |(a, b)| (key, a, b))
+    });
+    for (key, (_, a), (engine_b, b)) in pairs {
+        let key = Key {
+            engine: baseline.map(|_| engine_b.clone()),
+            ..key.clone()
+        };
+        let a: behrens_fisher::Stats = a.iter().map(|&c| c as f64).collect();
+        let b: behrens_fisher::Stats = b.iter().map(|&c| c as f64).collect();
+        let counts = (a.count, b.count);
+        if a.count < 2 || b.count < 2 {
+            underpowered.push(Underpowered {
+                key,
+                counts,
+                minimum_detectable_effect: f64::INFINITY,
+                required_count: None,
+            });
+            continue;
+        }
+
+        // The variance of the difference of the means, and its (Welch–Satterthwaite) degrees of
+        // freedom.
+        let (a_mean_var, b_mean_var) = (a.var / a.count as f64, b.var / b.count as f64);
+        let var_delta = a_mean_var + b_mean_var;
+        if var_delta == 0.0 || !var_delta.is_finite() {
+            continue;
+        }
+        let dof = var_delta * var_delta
+            / (a_mean_var * a_mean_var / (a.count - 1) as f64
+                + b_mean_var * b_mean_var / (b.count - 1) as f64);
+        let t = |dof: f64| {
+            behrens_fisher::student_t::inv_cdf(1.0 - significance_level / 2.0, dof)
+                + behrens_fisher::student_t::inv_cdf(POWER, dof)
+        };
+
+        let minimum_detectable_effect = t(dof) * var_delta.sqrt() / a.mean;
+        if minimum_detectable_effect > min_effect {
+            // More samples have more degrees of freedom, and so need a smaller `t`: refine the
+            // count a few times, taking the degrees of freedom of equal counts and variances.
+            let effect = min_effect * a.mean;
+            let mut required = f64::NAN;
+            let mut dof = dof;
+            for _ in 0..4 {
+                required = (t(dof).powi(2) * (a.var + b.var) / (effect * effect)).max(2.0);
+                dof = 2.0 * (required - 1.0);
+            }
+            underpowered.push(Underpowered {
+                key,
+                counts,
+                minimum_detectable_effect,
+                required_count: required.is_finite().then(|| required.ceil() as usize),
+            });
+        }
+    }
+    underpowered
+}
+
+/// Write the [underpowered] comparisons, if any, to the passed `output_file` in human-readable form,
+/// after the effect sizes.
+pub fn write_underpowered(
+    underpowered: &[Underpowered<'_>],
+    min_effect: f64,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    if underpowered.is_empty() {
+        return Ok(());
+    }
+    writeln!(output_file)?;
+    writeln!(
+        output_file,
+        "Too few samples to likely find a {}% difference significant ({}% power):",
+        min_effect * 100.0,
+        POWER * 100.0
+    )?;
+    for u in underpowered {
+        writeln!(output_file, "  {}", u)?;
+    }
+    Ok(())
 }
 
 /// Write a vector of [EffectSize] structures to the passed `output_file` in human-readable form.
 /// The `summaries` are needed
+///
+/// Counts and speedups are written as the `formatting` says (see [Formatting::count]).
 pub fn write(
     mut effect_sizes: Vec<EffectSize<'_>>,
     summaries: &[Summary<'_>],
     significance_level: f64,
+    formatting: Formatting,
     output_file: &mut dyn Write,
 ) -> Result<()> {
-    // Sort the effect sizes so that we focus on statistically significant results before
-    // insignificant results and larger relative effect sizes before smaller relative effect sizes.
-    effect_sizes.sort_by(|x, y| {
-        y.is_significant().cmp(&x.is_significant()).then_with(|| {
-            let x_speedup = x.a_speed_up_over_b().0.max(x.b_speed_up_over_a().0);
-            let y_speedup = y.a_speed_up_over_b().0.max(y.b_speed_up_over_a().0);
-            y_speedup.partial_cmp(&x_speedup).unwrap()
-        })
-    });
+    sort(&mut effect_sizes);
 
+    let mut names = EngineNames::default();
     for effect_size in effect_sizes {
         writeln!(output_file)?;
         writeln!(
             output_file,
-            "{} :: {} :: {}",
-            effect_size.phase, effect_size.event, effect_size.wasm
+            "{} :: {} :: {}{}",
+            effect_size.phase,
+            effect_size.event,
+            effect_size.wasm,
+            run_label(
+                effect_size.threads,
+                effect_size.input_size.as_deref(),
+                effect_size.execution
+            )
         )?;
         writeln!(output_file)?;
 
-        // For readability, trim the shared prefix from our two engine names.
-        let end_of_shared_prefix = effect_size
-            .a_engine
-            .char_indices()
-            .zip(effect_size.b_engine.char_indices())
-            .find_map(|((i, a), (j, b))| {
-                if a == b {
-                    None
-                } else {
-                    debug_assert_eq!(i, j);
-                    Some(i)
-                }
-            })
-            .unwrap_or(0);
-        let a_engine = &effect_size.a_engine[end_of_shared_prefix..];
-        let b_engine = &effect_size.b_engine[end_of_shared_prefix..];
+        let (a_engine, b_engine) = names.of(&effect_size);
 
         if effect_size.is_significant() {
             writeln!(
                 output_file,
-                "  Δ = {:.2} ± {:.2} (confidence = {}%)",
-                (effect_size.b_mean - effect_size.a_mean).abs(),
-                effect_size.half_width_confidence_interval.abs(),
+                "  Δ{} = {} ± {} (confidence = {}%)",
+                label(&effect_size),
+                formatting.count(
+                    &effect_size.event,
+                    (effect_size.b_mean - effect_size.a_mean).abs()
+                ),
+                formatting.count(
+                    &effect_size.event,
+                    effect_size.half_width_confidence_interval.abs()
+                ),
                 (1.0 - significance_level) * 100.0,
             )?;
             writeln!(output_file)?;
 
-            if effect_size.a_mean < effect_size.b_mean {
-                let ratio = effect_size.b_mean / effect_size.a_mean;
-                let ratio_ci = effect_size.half_width_confidence_interval / effect_size.a_mean;
-                writeln!(
+            let (faster, slower, speed_up) = faster_and_slower(&effect_size, &a_engine, &b_engine);
+            match speed_up {
+                Some((ratio, ratio_ci)) => writeln!(
                     output_file,
-                    "  {a_engine} is {ratio_min:.2}x to {ratio_max:.2}x faster than {b_engine}!",
-                    a_engine = a_engine,
-                    b_engine = b_engine,
-                    ratio_min = ratio - ratio_ci,
-                    ratio_max = ratio + ratio_ci,
-                )?;
-            } else {
-                let ratio = effect_size.a_mean / effect_size.b_mean;
-                let ratio_ci = effect_size.half_width_confidence_interval / effect_size.b_mean;
-                writeln!(
+                    "  {faster} is {ratio_min}x to {ratio_max}x faster than {slower}!",
+                    faster = faster,
+                    slower = slower,
+                    ratio_min = formatting.number(ratio - ratio_ci),
+                    ratio_max = formatting.number(ratio + ratio_ci),
+                )?,
+                None => writeln!(
                     output_file,
-                    "  {b_engine} is {ratio_min:.2}x to {ratio_max:.2}x faster than {a_engine}!",
-                    a_engine = a_engine,
-                    b_engine = b_engine,
-                    ratio_min = ratio - ratio_ci,
-                    ratio_max = ratio + ratio_ci,
-                )?;
+                    "  {} is faster than {}, with a mean of 0!",
+                    faster, slower
+                )?,
             }
         } else {
             writeln!(output_file, "  No difference in performance.")?;
         }
         writeln!(output_file)?;
 
+        // The statistic compared is shown between the minimum and maximum; a geometric mean, or a
+        // mean of nested processes, is only known from the effect size.
+        let center = |summary: &Summary, mean: f64| match effect_size.statistic {
+            Statistic::Mean
+                if (effect_size.transform, effect_size.model)
+                    == (Transform::None, Model::Independent) =>
+            {
+                summary.mean
+            }
+            Statistic::Mean => mean,
+            Statistic::Median => summary.median as f64,
+        };
         let get_summary = |engine: &str, wasm: &str, phase: Phase, event: &str| {
-            // TODO this sorting is not using `arch` which is not guaranteed to be the same in
-            // result sets; potentially this could re-use `Key` functionality.
             summaries
                 .iter()
                 .find(|s| {
-                    s.engine == engine && s.wasm == wasm && s.phase == phase && s.event == event
+                    s.arch == effect_size.arch
+                        && s.engine == engine
+                        && s.wasm == wasm
+                        && s.phase == phase
+                        && s.event == event
+                        && s.threads == effect_size.threads
+                        && s.input_size == effect_size.input_size
+                        && s.execution == effect_size.execution
+                })
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "no summary of {} on {} for {} :: {} :: {}{}",
+                        engine,
+                        effect_size.arch,
+                        phase,
+                        event,
+                        wasm,
+                        run_label(
+                            effect_size.threads,
+                            effect_size.input_size.as_deref(),
+                            effect_size.execution
+                        )
+                    )
                 })
-                .unwrap()
         };
 
         let a_summary = get_summary(
@@ -171,11 +839,14 @@ pub fn write(
             &effect_size.wasm,
             effect_size.phase,
             &effect_size.event,
-        );
+        )?;
         writeln!(
             output_file,
-            "  [{} {:.2} {}] {}",
-            a_summary.min, a_summary.mean, a_summary.max, a_engine,
+            "  [{} {} {}] {}",
+            formatting.count(&effect_size.event, a_summary.min as f64),
+            formatting.count(&effect_size.event, center(a_summary, effect_size.a_mean)),
+            formatting.count(&effect_size.event, a_summary.max as f64),
+            a_engine,
         )?;
 
         let b_summary = get_summary(
@@ -183,13 +854,732 @@ pub fn write(
             &effect_size.wasm,
             effect_size.phase,
             &effect_size.event,
-        );
+        )?;
         writeln!(
             output_file,
-            "  [{} {:.2} {}] {}",
-            b_summary.min, b_summary.mean, b_summary.max, b_engine,
+            "  [{} {} {}] {}",
+            formatting.count(&effect_size.event, b_summary.min as f64),
+            formatting.count(&effect_size.event, center(b_summary, effect_size.b_mean)),
+            formatting.count(&effect_size.event, b_summary.max as f64),
+            b_engine,
         )?;
     }
 
-    Ok(())
+    names.write(false, output_file)
+}
+
+/// Sort the effect sizes so that we focus on statistically significant results before
+/// insignificant results and larger relative effect sizes before smaller relative effect sizes.
+/// Results whose relative effect size cannot be computed (a mean is zero) come last of their kind.
+fn sort(effect_sizes: &mut [EffectSize<'_>]) {
+    let speed_up = |e: &EffectSize| {
+        let a = e.checked_a_speed_up_over_b()?.0;
+        let b = e.checked_b_speed_up_over_a()?.0;
+        Some(a.max(b))
+    };
+    effect_sizes.sort_by(|x, y| {
+        y.is_significant()
+            .cmp(&x.is_significant())
+            .then_with(|| match (speed_up(x), speed_up(y)) {
+                (Some(x_speedup), Some(y_speedup)) => y_speedup.total_cmp(&x_speedup),
+                (x_speedup, y_speedup) => y_speedup.is_some().cmp(&x_speedup.is_some()),
+            })
+    });
+}
+
+/// The faster and slower of the (trimmed) engine names of an effect size, and the faster engine's
+/// speedup over the slower, unless the faster engine's mean is zero.
+fn faster_and_slower<'a>(
+    effect_size: &EffectSize<'_>,
+    a_engine: &'a str,
+    b_engine: &'a str,
+) -> (&'a str, &'a str, Option<(f64, f64)>) {
+    if effect_size.a_mean < effect_size.b_mean {
+        (a_engine, b_engine, effect_size.checked_b_speed_up_over_a())
+    } else {
+        (b_engine, a_engine, effect_size.checked_a_speed_up_over_b())
+    }
+}
+
+/// The longest engine name a report shows; a longer one is shown as a short hash instead.
+const MAX_ENGINE_NAME: usize = 48;
+
+/// The shortened names a report shows the engines of its effect sizes by, and the full names of
+/// those shortened to hashes.
+#[derive(Default)]
+struct EngineNames {
+    hashed: BTreeMap<String, String>,
+}
+
+impl EngineNames {
+    /// For readability, shorten the two engine names of an effect size: trim the path components
+    /// they share at the start and end; e.g., `/tmp/wasmtime_old.so` and `/tmp/wasmtime_new.so`
+    /// become `wasmtime_old.so` and `wasmtime_new.so`, and `/builds/main/libengine.so` and
+    /// `/builds/pr-1234/libengine.so` become `main` and `pr-1234`. Only whole components are
+    /// trimmed, so labels (`--engine-label`) are left as they are. A name still longer than
+    /// [MAX_ENGINE_NAME] is shown as a hash of the full name, which [EngineNames::write] lists.
+    fn of(&mut self, effect_size: &EffectSize<'_>) -> (String, String) {
+        let a: Vec<_> = effect_size.a_engine.split('/').collect();
+        let b: Vec<_> = effect_size.b_engine.split('/').collect();
+        // Keep at least one component of each name.
+        let keep = a.len().min(b.len()) - 1;
+        let prefix = a
+            .iter()
+            .zip(&b)
+            .take(keep)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = a
+            .iter()
+            .rev()
+            .zip(b.iter().rev())
+            .take(keep - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        (
+            self.shorten(a[prefix..a.len() - suffix].join("/"), &effect_size.a_engine),
+            self.shorten(b[prefix..b.len() - suffix].join("/"), &effect_size.b_engine),
+        )
+    }
+
+    fn shorten(&mut self, name: String, full: &str) -> String {
+        if name.chars().count() <= MAX_ENGINE_NAME {
+            return name;
+        }
+        let hash = format!("#{:08x}", fnv1a(full));
+        self.hashed.insert(hash.clone(), full.to_string());
+        hash
+    }
+
+    /// List the full names of the engines shown as hashes, if any.
+    fn write(&self, markdown: bool, output_file: &mut dyn Write) -> Result<()> {
+        if self.hashed.is_empty() {
+            return Ok(());
+        }
+        writeln!(output_file)?;
+        writeln!(output_file, "Engines shown as hashes:")?;
+        if markdown {
+            writeln!(output_file)?;
+        }
+        for (hash, full) in &self.hashed {
+            if markdown {
+                writeln!(output_file, "- `{}`: `{}`", hash, full)?;
+            } else {
+                writeln!(output_file, "  {}: {}", hash, full)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The 32-bit FNV-1a hash of `text`, which (unlike `std`'s hashers) is the same from one build to
+/// the next, so an engine is shown as the same hash in every report.
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// Write a vector of [EffectSize] structures to the passed `output_file` as a Markdown table; e.g.,
+/// for a GitHub Actions job summary (`$GITHUB_STEP_SUMMARY`). Only the statistically significant
+/// results are listed, largest first; to keep the summary short, at most `max_rows` of them are,
+/// and the rest (like the insignificant results) are only counted. Differences are written in the
+/// unit of their event, for review, to `precision` significant digits.
+pub fn write_markdown(
+    mut effect_sizes: Vec<EffectSize<'_>>,
+    significance_level: f64,
+    max_rows: usize,
+    precision: usize,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    sort(&mut effect_sizes);
+    let significant = effect_sizes.iter().filter(|e| e.is_significant()).count();
+
+    writeln!(output_file, "### Sightglass results")?;
+    writeln!(output_file)?;
+    if let Some(effect_size) = effect_sizes.first() {
+        // Compared to a baseline, there may be several other engines.
+        let mut others: Vec<_> = effect_sizes.iter().map(|e| &e.b_engine).collect();
+        others.sort();
+        others.dedup();
+        let others: Vec<_> = others.iter().map(|e| format!("`{}`", e)).collect();
+        writeln!(
+            output_file,
+            "Comparing `{}` and {} with {}% confidence: {} of {} results differ significantly.",
+            effect_size.a_engine,
+            others.join(", "),
+            (1.0 - significance_level) * 100.0,
+            significant,
+            effect_sizes.len()
+        )?;
+    } else {
+        writeln!(output_file, "No results to compare.")?;
+    }
+    writeln!(output_file)?;
+
+    if significant > 0 {
+        writeln!(output_file, "| Benchmark | Phase | Event | Result | Δ |")?;
+        writeln!(output_file, "|---|---|---|---|---|")?;
+    }
+    let formatting = Formatting {
+        raw: false,
+        precision,
+    };
+    let mut names = EngineNames::default();
+    for effect_size in effect_sizes.iter().take(significant.min(max_rows)) {
+        let (a_engine, b_engine) = names.of(effect_size);
+        let (faster, slower, speed_up) = faster_and_slower(effect_size, &a_engine, &b_engine);
+        let result = match speed_up {
+            Some((ratio, ratio_ci)) => format!(
+                "`{}` is {}x to {}x faster than `{}`",
+                escape_cell(faster),
+                formatting.number(ratio - ratio_ci),
+                formatting.number(ratio + ratio_ci),
+                escape_cell(slower)
+            ),
+            None => format!(
+                "`{}` is faster than `{}`, with a mean of 0",
+                escape_cell(faster),
+                escape_cell(slower)
+            ),
+        };
+        writeln!(
+            output_file,
+            "| `{}`{} | {} | {} | {} | {} ± {} |",
+            escape_cell(&effect_size.wasm),
+            run_label(
+                effect_size.threads,
+                effect_size.input_size.as_deref(),
+                effect_size.execution
+            ),
+            effect_size.phase,
+            escape_cell(&effect_size.event),
+            result,
+            formatting.count(
+                &effect_size.event,
+                (effect_size.b_mean - effect_size.a_mean).abs()
+            ),
+            formatting.count(
+                &effect_size.event,
+                effect_size.half_width_confidence_interval.abs()
+            ),
+        )?;
+    }
+    if significant > max_rows {
+        writeln!(output_file)?;
+        writeln!(
+            output_file,
+            "_...and {} more significant results._",
+            significant - max_rows
+        )?;
+    }
+    names.write(true, output_file)
+}
+
+/// Keep a pipe from ending a Markdown table cell early.
+fn escape_cell(text: &str) -> String {
+    text.replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::DEFAULT_PRECISION;
+
+    fn effect_size(wasm: &str, b_mean: f64) -> EffectSize<'static> {
+        EffectSize {
+            arch: "x86_64".into(),
+            wasm: wasm.to_string().into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            a_engine: "/tmp/main.so".into(),
+            a_mean: 100.0,
+            b_engine: "/tmp/branch.so".into(),
+            b_mean,
+            significance_level: 0.01,
+            half_width_confidence_interval: 5.0,
+            statistic: Statistic::Mean,
+            transform: Transform::None,
+            model: Model::Independent,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_markdown_summary() -> Result<()> {
+        let effect_sizes = vec![
+            effect_size("noop.wasm", 101.0),
+            effect_size("bz2.wasm", 50.0),
+            effect_size("fib|2.wasm", 300.0),
+        ];
+        let mut output = vec![];
+        write_markdown(effect_sizes, 0.01, 1, DEFAULT_PRECISION, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "### Sightglass results\n\n\
+             Comparing `/tmp/main.so` and `/tmp/branch.so` with 99% confidence: 2 of 3 results \
+             differ significantly.\n\n\
+             | Benchmark | Phase | Event | Result | Δ |\n\
+             |---|---|---|---|---|\n\
+             | `fib\\|2.wasm` | execution | cycles | `main.so` is 2.95x to 3.05x faster than \
+             `branch.so` | 200 ± 5 |\n\n\
+             _...and 1 more significant results._\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compare_against_baseline() -> Result<()> {
+        let measurements: Vec<_> = [("main.so", 100), ("fast.so", 50), ("slow.so", 200)]
+            .into_iter()
+            .flat_map(|(engine, mean)| {
+                [mean - 1, mean, mean + 1].map(|count| Measurement {
+                    arch: "x86_64".into(),
+                    engine: engine.into(),
+                    wasm: "bz2.wasm".into(),
+                    process: 1,
+                    iteration: 0,
+                    phase: Phase::Execution,
+                    event: "cycles".into(),
+                    count,
+                    threads: None,
+                    input_size: None,
+                    execution: None,
+                })
+            })
+            .collect();
+        let groups: Groups = measurements.iter().collect();
+        assert!(calculate_grouped(0.01, &groups).is_err());
+        let (effect_sizes, skipped) = calculate_skipping(0.01, Some("other.so"), &groups)?;
+        assert!(effect_sizes.is_empty());
+        assert!(
+            matches!(skipped[0].reason, Skip::NoBaseline { ref engines } if engines.len() == 3)
+        );
+
+        let effect_sizes = calculate_against(0.01, Some("main.so"), &groups)?;
+        let compared: Vec<_> = effect_sizes
+            .iter()
+            .map(|e| (e.a_engine.as_ref(), e.b_engine.as_ref(), e.b_mean))
+            .collect();
+        assert_eq!(
+            compared,
+            [("main.so", "fast.so", 50.0), ("main.so", "slow.so", 200.0)]
+        );
+
+        let mut output = vec![];
+        write_markdown(effect_sizes, 0.01, 10, DEFAULT_PRECISION, &mut output)?;
+        assert!(String::from_utf8(output)?.contains(
+            "Comparing `main.so` and `fast.so`, `slow.so` with 99% confidence: 2 of 2 results"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn compare_medians() -> Result<()> {
+        let counts = [
+            100, 101, 99, 100, 102, 98, 100, 101, 99, 100, 102, 98, 100, 101, 99,
+        ];
+        let measurements: Vec<_> = [
+            (
+                "main.so",
+                counts.iter().chain(&[1000]).copied().collect::<Vec<_>>(),
+            ),
+            (
+                "pr.so",
+                counts.iter().chain(&[101]).map(|c| c - 10).collect(),
+            ),
+        ]
+        .into_iter()
+        .flat_map(|(engine, counts)| {
+            counts.into_iter().map(move |count| Measurement {
+                arch: "x86_64".into(),
+                engine: engine.into(),
+                wasm: "bz2.wasm".into(),
+                process: 1,
+                iteration: 0,
+                phase: Phase::Execution,
+                event: "nanoseconds".into(),
+                count,
+                threads: None,
+                input_size: None,
+                execution: None,
+            })
+        })
+        .collect();
+        let groups: Groups = measurements.iter().collect();
+
+        // The outlier hides the difference of the means...
+        let (means, _) = calculate_with(Method::default(), 0.01, None, &groups)?;
+        assert!(!means[0].is_significant());
+
+        // ...but not that of the medians.
+        let median = Method {
+            statistic: Statistic::Median,
+            ..Method::default()
+        };
+        let (medians, skipped) = calculate_with(median, 0.01, None, &groups)?;
+        assert!(skipped.is_empty());
+        let median = &medians[0];
+        assert_eq!(median.statistic, Statistic::Median);
+        assert_eq!((median.a_mean, median.b_mean), (100.0, 90.0));
+        assert!(median.is_significant());
+        assert!(median.half_width_confidence_interval < 5.0);
+
+        let mut output = vec![];
+        write(
+            medians,
+            &crate::summarize::calculate_grouped(&groups),
+            0.01,
+            Formatting::default(),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("  Δ (median) = 10 ns ± "));
+        assert!(output.contains("  [98 ns 100 ns 1.00 µs] main.so\n  [88 ns 90 ns 92 ns] pr.so\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn compare_logarithms() -> Result<()> {
+        // Long-tailed timings, and the same timings twice as fast.
+        let counts = [80, 100, 124, 90, 110, 400, 86, 104, 96, 250, 84, 118];
+        let measurements = |counts: Vec<u64>| -> Vec<Measurement<'static>> {
+            [("main.so", 1), ("pr.so", 2)]
+                .into_iter()
+                .flat_map(|(engine, divisor)| {
+                    counts.iter().map(move |&count| Measurement {
+                        arch: "x86_64".into(),
+                        engine: engine.into(),
+                        wasm: "bz2.wasm".into(),
+                        process: 1,
+                        iteration: 0,
+                        phase: Phase::Execution,
+                        event: "nanoseconds".into(),
+                        count: count / divisor,
+                        threads: None,
+                        input_size: None,
+                        execution: None,
+                    })
+                })
+                .collect()
+        };
+        let groups: Groups = measurements(counts.to_vec()).iter().collect();
+        let geometric = Method {
+            transform: Transform::Log,
+            ..Method::default()
+        };
+
+        let (raw, _) = calculate_with(Method::default(), 0.01, None, &groups)?;
+        assert!(!raw[0].is_significant());
+
+        let (logs, _) = calculate_with(geometric, 0.01, None, &groups)?;
+        let log = &logs[0];
+        assert_eq!(log.transform, Transform::Log);
+        assert!(log.is_significant());
+        // The geometric means, rather than the (larger) arithmetic ones.
+        assert!((log.a_mean - 119.36).abs() < 0.01);
+        assert!((log.a_mean / log.b_mean - 2.0).abs() < 1e-9);
+
+        let mut output = vec![];
+        write(
+            logs,
+            &crate::summarize::calculate_grouped(&groups),
+            0.01,
+            Formatting::default(),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("  Δ (geometric mean) = 59.7 ns ± "));
+        assert!(output.contains("  [80 ns 119 ns 400 ns] main.so\n"));
+
+        // A zero has no logarithm.
+        let mut counts = counts.to_vec();
+        counts[0] = 0;
+        let groups: Groups = measurements(counts).iter().collect();
+        let (logs, skipped) = calculate_with(geometric, 0.01, None, &groups)?;
+        assert!(logs.is_empty());
+        assert_eq!(
+            skipped[0].reason,
+            Skip::ZeroCount {
+                engine: "main.so".into()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compare_nested_processes() -> Result<()> {
+        // Each process runs some way off the others (e.g., for where its heap happened to land),
+        // and its iterations vary much less around that.
+        let measurements: Vec<_> = [("main.so", [0, 24, -20, 10]), ("pr.so", [-20, 4, -38, -6])]
+            .into_iter()
+            .flat_map(|(engine, offsets)| {
+                offsets
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(move |(process, offset)| {
+                        (0..40).map(move |iteration| Measurement {
+                            arch: "x86_64".into(),
+                            engine: engine.into(),
+                            wasm: "bz2.wasm".into(),
+                            process: process as u32,
+                            iteration,
+                            phase: Phase::Execution,
+                            event: "nanoseconds".into(),
+                            count: (1000 + offset + iteration as i64 % 3) as u64,
+                            threads: None,
+                            input_size: None,
+                            execution: None,
+                        })
+                    })
+            })
+            .collect();
+        let mut groups = Groups::with_processes();
+        for m in &measurements {
+            groups.add(m);
+        }
+
+        // As independent samples, the iterations make the difference look significant...
+        let (independent, _) = calculate_with(Method::default(), 0.01, None, &groups)?;
+        assert!(independent[0].is_significant());
+
+        // ...but there are only four processes of each engine to tell it from how they vary, which
+        // takes a much looser confidence.
+        let nested = Method {
+            model: Model::Nested,
+            ..Method::default()
+        };
+        let (nested_effects, skipped) = calculate_with(nested, 0.01, None, &groups)?;
+        assert!(skipped.is_empty());
+        let effect = &nested_effects[0];
+        assert_eq!(effect.model, Model::Nested);
+        assert!((effect.a_mean - independent[0].a_mean).abs() < 1e-9);
+        assert!(!effect.is_significant());
+        assert!(
+            effect.half_width_confidence_interval
+                > 2.0 * independent[0].half_width_confidence_interval
+        );
+
+        let (loose, _) = calculate_with(nested, 0.5, None, &groups)?;
+        let mut output = vec![];
+        write(
+            loose,
+            &crate::summarize::calculate_grouped(&groups),
+            0.5,
+            Formatting::default(),
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("  Δ (nested processes) = 18.5 ns ± 9.32 ns (confidence = 50%)"));
+
+        // Groups without their processes cannot be compared so.
+        let groups: Groups = measurements.iter().collect();
+        assert!(calculate_with(nested, 0.01, None, &groups).is_err());
+
+        // Nor can a single process's iterations.
+        let mut groups = Groups::with_processes();
+        for m in measurements.iter().filter(|m| m.process == 0) {
+            groups.add(m);
+        }
+        let (_, skipped) = calculate_with(nested, 0.01, None, &groups)?;
+        assert_eq!(
+            skipped[0].reason,
+            Skip::TooFewProcesses {
+                engine: "main.so".into(),
+                count: 1
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn normal_quantiles() {
+        for (p, z) in [
+            (0.5, 0.0),
+            (0.975, 1.959964),
+            (0.995, 2.575829),
+            (0.01, -2.326348),
+        ] {
+            assert!(
+                (normal_quantile(p) - z).abs() < 1e-6,
+                "{}",
+                normal_quantile(p)
+            );
+        }
+    }
+
+    #[test]
+    fn skip_incomparable() -> Result<()> {
+        let measurement = |wasm: &'static str, engine: &'static str, count: u64| Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: wasm.into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        };
+        let measurements = [
+            measurement("compared.wasm", "a.so", 10),
+            measurement("compared.wasm", "a.so", 11),
+            measurement("compared.wasm", "b.so", 20),
+            measurement("compared.wasm", "b.so", 21),
+            // Only one engine ran this benchmark.
+            measurement("one-engine.wasm", "a.so", 10),
+            // Or ran it once.
+            measurement("one-sample.wasm", "a.so", 10),
+            measurement("one-sample.wasm", "a.so", 11),
+            measurement("one-sample.wasm", "b.so", 20),
+            // Or measured the same count every time.
+            measurement("same.wasm", "a.so", 10),
+            measurement("same.wasm", "a.so", 11),
+            measurement("same.wasm", "b.so", 20),
+            measurement("same.wasm", "b.so", 20),
+        ];
+        let groups: Groups = measurements.iter().collect();
+        let (effect_sizes, skipped) = calculate_skipping(0.01, None, &groups)?;
+        assert_eq!(effect_sizes.len(), 1);
+        assert_eq!(effect_sizes[0].wasm, "compared.wasm");
+        let reasons: Vec<_> = skipped.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            reasons,
+            [
+                "execution :: cycles :: one-engine.wasm on x86_64: only a.so has results",
+                "execution :: cycles :: one-sample.wasm on x86_64: b.so has 1 samples, but at \
+                 least 2 are needed",
+                "execution :: cycles :: same.wasm on x86_64: every sample of b.so is the same, \
+                 so its variance cannot be estimated"
+            ]
+        );
+        assert_eq!(calculate(0.01, &measurements)?.len(), 1);
+
+        let mut output = vec![];
+        write_skipped(&skipped, &mut output)?;
+        assert!(String::from_utf8(output)?.starts_with("\nSkipped 3 comparisons:\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn find_underpowered() {
+        let measurement = |wasm: &'static str, engine: &'static str, count: u64| Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: wasm.into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        };
+        let mut measurements = vec![];
+        for engine in ["a.so", "b.so"] {
+            // Few noisy samples, one sample, and many quiet samples.
+            for count in [100, 110, 90] {
+                measurements.push(measurement("noisy.wasm", engine, count));
+            }
+            measurements.push(measurement("single.wasm", engine, 100));
+            for i in 0..100 {
+                measurements.push(measurement("quiet.wasm", engine, 1000 + i % 2));
+            }
+        }
+        let groups: Groups = measurements.iter().collect();
+        let underpowered = underpowered(0.01, 0.05, None, &groups);
+        assert_eq!(underpowered.len(), 2);
+
+        let noisy = &underpowered[0];
+        assert_eq!(noisy.key.wasm.as_deref(), Some("noisy.wasm"));
+        assert_eq!(noisy.counts, (3, 3));
+        assert!(noisy.minimum_detectable_effect > 0.05);
+        assert!(noisy.required_count.unwrap() > 3);
+        assert!(noisy
+            .to_string()
+            .starts_with("execution :: cycles :: noisy.wasm: with 3 and 3 samples, only"));
+
+        let single = &underpowered[1];
+        assert_eq!(single.key.wasm.as_deref(), Some("single.wasm"));
+        assert_eq!(single.minimum_detectable_effect, f64::INFINITY);
+        assert_eq!(single.required_count, None);
+        assert!(single.to_string().ends_with(
+            "with 1 and 1 samples, no difference can be found; at least 2 samples of each are \
+             needed"
+        ));
+    }
+
+    #[test]
+    fn shorten_engine_names() -> Result<()> {
+        let mut names = EngineNames::default();
+        let mut shortened = |a: &str, b: &str| {
+            let mut e = effect_size("bench.wasm", 1.0);
+            e.a_engine = a.to_string().into();
+            e.b_engine = b.to_string().into();
+            names.of(&e)
+        };
+        assert_eq!(
+            shortened("/tmp/bench_api_old.so", "/tmp/bench_api_new.so"),
+            ("bench_api_old.so".into(), "bench_api_new.so".into())
+        );
+        assert_eq!(
+            shortened("a/x/lib.so [-O]", "a/y/lib.so"),
+            ("x/lib.so [-O]".into(), "y/lib.so".into())
+        );
+        // Names differing in their first character, but sharing a file name.
+        assert_eq!(
+            shortened("main/libengine.so", "pr-1234/libengine.so"),
+            ("main".into(), "pr-1234".into())
+        );
+        assert_eq!(
+            shortened("/a/lib.so", "/a/b/lib.so"),
+            ("lib.so".into(), "b/lib.so".into())
+        );
+        assert_eq!(
+            shortened("main", "pr-1234"),
+            ("main".into(), "pr-1234".into())
+        );
+
+        let long = format!("/tmp/{}/lib.so", "x".repeat(MAX_ENGINE_NAME + 1));
+        let (a, b) = shortened(&long, "/tmp/main/lib.so");
+        assert_eq!((a.len(), b.as_str()), (9, "main"));
+        let mut output = vec![];
+        names.write(false, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            format!("\nEngines shown as hashes:\n  {}: {}\n", a, long)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_zero_means() -> Result<()> {
+        let effect_sizes = vec![
+            effect_size("bz2.wasm", 50.0),
+            EffectSize {
+                a_mean: 0.0,
+                ..effect_size("zero.wasm", 50.0)
+            },
+            EffectSize {
+                a_mean: 0.0,
+                ..effect_size("zeros.wasm", 0.0)
+            },
+        ];
+        let mut output = vec![];
+        write_markdown(effect_sizes, 0.01, 10, DEFAULT_PRECISION, &mut output)?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains(
+            "| `bz2.wasm` | execution | cycles | `branch.so` is 1.90x to 2.10x faster than \
+             `main.so` | 50 ± 5 |\n\
+             | `zero.wasm` | execution | cycles | `main.so` is faster than `branch.so`, with a \
+             mean of 0 | 50 ± 5 |\n"
+        ));
+        assert!(!output.contains("zeros.wasm"));
+        Ok(())
+    }
 }
@@ -0,0 +1,286 @@
+//! Generate Grafana dashboards over the results Sightglass stores: the InfluxDB points of
+//! `summarize --influx` (see [crate::influx]) or the PostgreSQL tables of `upload-postgres`.
+use crate::summarize::run_label;
+use anyhow::{bail, Result};
+use serde_json::{json, Value};
+use sightglass_data::{Measurement, Phase};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Where a dashboard's results are stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Datasource {
+    /// An InfluxDB database, queried with InfluxQL.
+    Influx,
+    /// A PostgreSQL database, in the tables of the given schema.
+    Postgres,
+}
+
+impl FromStr for Datasource {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "influx" => Self::Influx,
+            "postgres" => Self::Postgres,
+            _ => bail!(
+                "unknown datasource (expected 'influx' or 'postgres'): {}",
+                s
+            ),
+        })
+    }
+}
+
+impl Datasource {
+    /// The Grafana plugin that queries this datasource.
+    fn plugin(&self) -> &'static str {
+        match self {
+            Self::Influx => "influxdb",
+            Self::Postgres => "grafana-postgresql-datasource",
+        }
+    }
+}
+
+/// A series of results to chart: a phase of a benchmark run with some threads and input size, or
+/// one execution of each instance of it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Panel {
+    pub wasm: String,
+    pub threads: Option<u32>,
+    pub input_size: Option<String>,
+    pub execution: Option<u32>,
+    pub phase: Phase,
+}
+
+impl Panel {
+    /// List the panels, and the events, of a set of `measurements`; e.g., those of a typical run.
+    pub fn find(measurements: &[Measurement<'_>]) -> (Vec<Panel>, Vec<String>) {
+        let mut panels = BTreeSet::new();
+        let mut events = BTreeSet::new();
+        for m in measurements {
+            panels.insert(Panel {
+                wasm: m.wasm.to_string(),
+                threads: m.threads,
+                input_size: m.input_size.as_deref().map(str::to_string),
+                execution: m.execution,
+                phase: m.phase,
+            });
+            events.insert(m.event.to_string());
+        }
+        (panels.into_iter().collect(), events.into_iter().collect())
+    }
+
+    fn title(&self) -> String {
+        format!(
+            "{}{} :: {}",
+            self.wasm,
+            run_label(self.threads, self.input_size.as_deref(), self.execution),
+            self.phase
+        )
+    }
+}
+
+/// Generate the JSON model of a Grafana dashboard charting the median of the selected event (a
+/// dashboard variable, one of `events`) for each of the `panels`, with a series per engine. The
+/// datasource is a dashboard variable too; for PostgreSQL, the tables are those of `schema`.
+pub fn dashboard(
+    title: &str,
+    datasource: Datasource,
+    schema: &str,
+    panels: &[Panel],
+    events: &[String],
+) -> Value {
+    let panels: Vec<Value> = panels
+        .iter()
+        .enumerate()
+        .map(|(i, panel)| {
+            let target = match datasource {
+                Datasource::Influx => json!({
+                    "refId": "A",
+                    "rawQuery": true,
+                    "query": influx_query(panel, "$event"),
+                    "resultFormat": "time_series",
+                    "alias": "$tag_engine",
+                }),
+                Datasource::Postgres => json!({
+                    "refId": "A",
+                    "rawQuery": true,
+                    "editorMode": "code",
+                    "rawSql": postgres_query(schema, panel, "$event"),
+                    "format": "time_series",
+                }),
+            };
+            json!({
+                "id": i + 1,
+                "type": "timeseries",
+                "title": panel.title(),
+                "datasource": { "type": datasource.plugin(), "uid": "${datasource}" },
+                "gridPos": { "x": (i % 2) * 12, "y": (i / 2) * 8, "w": 12, "h": 8 },
+                "fieldConfig": { "defaults": { "unit": "short" }, "overrides": [] },
+                "targets": [target],
+            })
+        })
+        .collect();
+    let event_options: Vec<Value> = events
+        .iter()
+        .enumerate()
+        .map(|(i, event)| json!({ "text": event, "value": event, "selected": i == 0 }))
+        .collect();
+    let default_event = events.first().map(String::as_str).unwrap_or("cycles");
+    json!({
+        "title": title,
+        "uid": null,
+        "schemaVersion": 39,
+        "editable": true,
+        "time": { "from": "now-30d", "to": "now" },
+        "tags": ["sightglass"],
+        "templating": {
+            "list": [
+                {
+                    "name": "datasource",
+                    "label": "Datasource",
+                    "type": "datasource",
+                    "query": datasource.plugin(),
+                },
+                {
+                    "name": "event",
+                    "label": "Event",
+                    "type": "custom",
+                    "query": events.join(","),
+                    "current": { "text": default_event, "value": default_event },
+                    "options": event_options,
+                },
+            ],
+        },
+        "panels": panels,
+    })
+}
+
+/// An InfluxQL query for the median `event` of a panel over the dashboard's time range, grouped by
+/// engine, from the `sightglass` measurement that `summarize --influx` writes.
+pub fn influx_query(panel: &Panel, event: &str) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+    let threads = panel.threads.map(|t| t.to_string()).unwrap_or_default();
+    let execution = panel.execution.map(|e| e.to_string()).unwrap_or_default();
+    format!(
+        "SELECT mean(\"median\") FROM \"{}\" WHERE \"benchmark\" = {} AND \"phase\" = {} \
+         AND \"event\" = {} AND \"threads\" = {} AND \"input_size\" = {} \
+         AND \"execution\" = {} AND $timeFilter GROUP BY time($__interval), \"engine\" fill(none)",
+        crate::influx::MEASUREMENT,
+        quote(&panel.wasm),
+        quote(&panel.phase.to_string()),
+        quote(event),
+        quote(&threads),
+        quote(panel.input_size.as_deref().unwrap_or_default()),
+        quote(&execution)
+    )
+}
+
+/// A PostgreSQL query for the median `event` of a panel over the dashboard's time range, with a
+/// series per engine, from the tables in `schema` that `upload-postgres` writes.
+pub fn postgres_query(schema: &str, panel: &Panel, event: &str) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\'', "''"));
+    let threads = match panel.threads {
+        Some(threads) => format!("= {}", threads),
+        None => "IS NULL".to_string(),
+    };
+    let input_size = match &panel.input_size {
+        Some(input_size) => format!("= {}", quote(input_size)),
+        None => "IS NULL".to_string(),
+    };
+    let execution = match panel.execution {
+        Some(execution) => format!("= {}", execution),
+        None => "IS NULL".to_string(),
+    };
+    // The benchmarks are fingerprinted by their path from the `benchmarks` directory, if any.
+    let wasm = Path::new(&panel.wasm);
+    let path: PathBuf = match wasm.iter().position(|c| c == "benchmarks") {
+        Some(i) => wasm.iter().skip(i).collect(),
+        None => wasm.to_path_buf(),
+    };
+    format!(
+        "SELECT r.datetime AS time, e.fingerprint->>'path' AS metric, s.median AS value \
+         FROM {schema}.summaries s \
+         JOIN {schema}.runs r ON r.id = s.run \
+         JOIN {schema}.engines e ON e.id = s.engine \
+         JOIN {schema}.benchmarks b ON b.id = s.benchmark \
+         WHERE $__timeFilter(r.datetime) AND b.fingerprint->>'path' = {} AND s.phase = {} \
+         AND s.event = {} AND s.threads {} AND s.input_size {} AND s.execution {} \
+         ORDER BY 1",
+        quote(&path.to_string_lossy()),
+        quote(&panel.phase.to_string()),
+        quote(event),
+        threads,
+        input_size,
+        execution,
+        schema = schema
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panel(threads: Option<u32>) -> Panel {
+        Panel {
+            wasm: "benchmarks/bz2/benchmark.wasm".into(),
+            threads,
+            input_size: None,
+            execution: None,
+            phase: Phase::Execution,
+        }
+    }
+
+    #[test]
+    fn generate_dashboard() {
+        let panels = [panel(None), panel(Some(4))];
+        let events = ["cycles".to_string(), "nanoseconds".to_string()];
+        let influx = dashboard("Sightglass", Datasource::Influx, "", &panels, &events);
+        assert_eq!(influx["panels"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            influx["panels"][1]["title"],
+            "benchmarks/bz2/benchmark.wasm (4 threads) :: execution"
+        );
+        assert_eq!(influx["panels"][1]["gridPos"]["x"], 12);
+        assert_eq!(influx["panels"][1]["datasource"]["type"], "influxdb");
+        assert_eq!(
+            influx["templating"]["list"][1]["query"],
+            "cycles,nanoseconds"
+        );
+        let postgres = dashboard(
+            "Sightglass",
+            Datasource::Postgres,
+            "results",
+            &panels,
+            &events,
+        );
+        assert!(postgres["panels"][0]["targets"][0]["rawSql"]
+            .as_str()
+            .unwrap()
+            .contains("FROM results.summaries s"));
+    }
+
+    #[test]
+    fn generate_queries() {
+        assert_eq!(
+            influx_query(&panel(Some(4)), "$event"),
+            "SELECT mean(\"median\") FROM \"sightglass\" WHERE \
+             \"benchmark\" = 'benchmarks/bz2/benchmark.wasm' AND \"phase\" = 'execution' AND \
+             \"event\" = '$event' AND \"threads\" = '4' AND \"input_size\" = '' AND \
+             \"execution\" = '' AND $timeFilter GROUP BY time($__interval), \"engine\" fill(none)"
+        );
+        let query = postgres_query(
+            "sightglass",
+            &Panel {
+                wasm: "/home/me/sightglass/benchmarks/bz2/benchmark.wasm".into(),
+                ..panel(None)
+            },
+            "it's",
+        );
+        assert!(query.contains(
+            "b.fingerprint->>'path' = 'benchmarks/bz2/benchmark.wasm' AND \
+             s.phase = 'execution' AND s.event = 'it''s' AND s.threads IS NULL AND \
+             s.input_size IS NULL AND s.execution IS NULL"
+        ));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::keys::Key;
+use sightglass_data::Measurement;
+use std::collections::BTreeMap;
+
+/// The counts of measurements, grouped by architecture, engine, benchmark file, phase, event,
+/// thread count, and input size: all that summaries and effect sizes need of them. Measurements
+/// can be added one at a time as they are read (see `Format::read_each`), so that a large results
+/// file need not be held in memory.
+#[derive(Default)]
+pub struct Groups {
+    groups: BTreeMap<Key<'static>, Vec<u64>>,
+    /// The process of each count, if they are kept (see [Groups::with_processes]).
+    processes: Option<BTreeMap<Key<'static>, Vec<u32>>>,
+    /// The group of the last measurement added: measurements usually come in runs of the same
+    /// group, which can be found without allocating a key.
+    last: Option<Key<'static>>,
+}
+
+impl Groups {
+    /// Groups that also keep the process of each count; e.g., to model the variation between
+    /// processes (see [sightglass_data::Model::Nested]).
+    pub fn with_processes() -> Self {
+        Self {
+            processes: Some(BTreeMap::new()),
+            ..Self::default()
+        }
+    }
+
+    /// Add the count of a measurement to its group.
+    pub fn add(&mut self, m: &Measurement<'_>) {
+        if let Some(last) = self.last.as_ref().filter(|last| last.matches(m)) {
+            self.groups.get_mut(last).unwrap().push(m.count);
+            if let Some(processes) = &mut self.processes {
+                processes.get_mut(last).unwrap().push(m.process);
+            }
+            return;
+        }
+        let key = Key::of(m);
+        self.groups.entry(key.clone()).or_default().push(m.count);
+        if let Some(processes) = &mut self.processes {
+            processes.entry(key.clone()).or_default().push(m.process);
+        }
+        self.last = Some(key);
+    }
+
+    /// The process of each count of a group, in the same order, if they are kept.
+    pub fn processes(&self, key: &Key<'static>) -> Option<&[u32]> {
+        Some(self.processes.as_ref()?.get(key)?.as_slice())
+    }
+
+    /// The groups, in key order, with their counts in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&Key<'static>, &[u64])> {
+        self.groups
+            .iter()
+            .map(|(key, counts)| (key, counts.as_slice()))
+    }
+
+    /// Whether no measurements were added.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+impl<'a, 'b> FromIterator<&'b Measurement<'a>> for Groups
+where
+    'a: 'b,
+{
+    fn from_iter<I: IntoIterator<Item = &'b Measurement<'a>>>(measurements: I) -> Self {
+        let mut groups = Self::default();
+        for m in measurements {
+            groups.add(m);
+        }
+        groups
+    }
+}
+
+/// Groups can also be made from their keys and counts; e.g., as they were read back from disk.
+impl FromIterator<(Key<'static>, Vec<u64>)> for Groups {
+    fn from_iter<I: IntoIterator<Item = (Key<'static>, Vec<u64>)>>(groups: I) -> Self {
+        Self {
+            groups: groups.into_iter().collect(),
+            processes: None,
+            last: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{effect_size, summarize};
+    use anyhow::Result;
+    use sightglass_data::Format;
+    use std::borrow::Cow;
+
+    const CSV: &str = "\
+arch,engine,wasm,process,iteration,phase,event,count,threads,input_size
+x86_64,main.so,bz2.wasm,1,0,Execution,cycles,100,,
+x86_64,main.so,bz2.wasm,1,1,Execution,cycles,110,,
+x86_64,branch.so,bz2.wasm,2,0,Execution,cycles,50,,
+x86_64,main.so,bz2.wasm,1,2,Execution,cycles,105,,
+x86_64,branch.so,bz2.wasm,2,1,Execution,cycles,55,,
+x86_64,branch.so,bz2.wasm,2,2,Execution,cycles,52,,
+x86_64,main.so,bz2.wasm,1,0,Execution,cycles,900,,large
+x86_64,branch.so,bz2.wasm,2,0,Execution,cycles,450,,large
+x86_64,main.so,bz2.wasm,1,1,Execution,cycles,910,,large
+x86_64,branch.so,bz2.wasm,2,1,Execution,cycles,460,,large
+";
+
+    #[test]
+    fn group_streamed_measurements() -> Result<()> {
+        let measurements: Vec<Measurement> = Format::csv(true).read(CSV.as_bytes())?;
+        let json = serde_json::to_vec(&measurements)?;
+        for (format, input) in [(Format::csv(true), CSV.as_bytes()), (Format::Json, &json)] {
+            // Reading CSV consumes its headers setting, so keep a copy for the second read.
+            let mapped_format = format.clone();
+            let mut groups = Groups::default();
+            format.read_each(input, |m| {
+                groups.add(&m);
+                Ok(())
+            })?;
+            assert_eq!(groups.iter().count(), 4);
+            assert_eq!(
+                summarize::calculate_grouped(&groups),
+                summarize::calculate(&measurements)
+            );
+            let effect_sizes = effect_size::calculate_grouped(0.01, &groups)?;
+            assert_eq!(effect_sizes.len(), 2);
+            assert_eq!(effect_sizes[0].a_engine, "branch.so");
+            assert_eq!(effect_sizes[0].input_size, None);
+            assert_eq!(effect_sizes[1].b_mean, 905.0);
+
+            let mut mapped = Groups::default();
+            mapped_format.read_each_slice(input, |m| {
+                mapped.add(&m);
+                Ok(())
+            })?;
+            assert_eq!(
+                summarize::calculate_grouped(&mapped),
+                summarize::calculate_grouped(&groups)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn borrow_from_memory() -> Result<()> {
+        let json = br#"[{"arch": "x86_64", "engine": "main.so", "wasm": "a\\b.wasm",
+            "process": 1, "iteration": 0, "phase": "Execution", "event": "cycles", "count": 1}]"#;
+        let mut read = 0;
+        Format::Json.read_each_slice(json, |m| {
+            assert!(matches!(m.engine, Cow::Borrowed("main.so")));
+            // Strings with escapes cannot be borrowed.
+            assert!(matches!(m.wasm, Cow::Owned(ref wasm) if wasm == "a\\b.wasm"));
+            read += 1;
+            Ok(())
+        })?;
+        assert_eq!(read, 1);
+        Ok(())
+    }
+}
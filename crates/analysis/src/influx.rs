@@ -0,0 +1,127 @@
+use anyhow::Result;
+use sightglass_data::Summary;
+use std::io::Write;
+
+/// The InfluxDB measurement that summaries are written as.
+pub const MEASUREMENT: &str = "sightglass";
+
+/// Write [Summary] structures to `output_file` in the InfluxDB [line protocol], for time-series
+/// databases and dashboards (e.g., Grafana) to ingest. Each summary is a point of the
+/// `sightglass` measurement: the engine, benchmark, phase, event, architecture, and any thread
+/// count or input size are its tags, the statistics are its fields, and `timestamp` (the time of
+/// the run, in nanoseconds since the Unix epoch) is its time:
+///
+/// ```text
+/// sightglass,engine=libengine.so,benchmark=benchmarks/noop/benchmark.wasm,phase=execution,event=cycles,arch=x86_64 min=1i,max=3i,median=2i,mean=2,mean_deviation=0.5 1700000000000000000
+/// ```
+///
+/// [line protocol]: https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/
+pub fn write(
+    summaries: &[Summary<'_>],
+    timestamp: u128,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    for summary in summaries {
+        let mut tags = vec![
+            ("engine", summary.engine.to_string()),
+            ("benchmark", summary.wasm.to_string()),
+            ("phase", summary.phase.to_string()),
+            ("event", summary.event.to_string()),
+            ("arch", summary.arch.to_string()),
+        ];
+        if let Some(threads) = summary.threads {
+            tags.push(("threads", threads.to_string()));
+        }
+        if let Some(input_size) = &summary.input_size {
+            tags.push(("input_size", input_size.to_string()));
+        }
+        if let Some(execution) = summary.execution {
+            tags.push(("execution", execution.to_string()));
+        }
+        let tags: Vec<_> = tags
+            .iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| format!("{}={}", key, escape(value)))
+            .collect();
+
+        let mut fields = vec![
+            format!("min={}i", summary.min),
+            format!("max={}i", summary.max),
+            format!("median={}i", summary.median),
+        ];
+        // The line protocol has no representation of NaN or infinity.
+        for (key, value) in [
+            ("mean", summary.mean),
+            ("mean_deviation", summary.mean_deviation),
+        ] {
+            if value.is_finite() {
+                fields.push(format!("{}={}", key, value));
+            }
+        }
+
+        writeln!(
+            output_file,
+            "{},{} {} {}",
+            MEASUREMENT,
+            tags.join(","),
+            fields.join(","),
+            timestamp
+        )?;
+    }
+    Ok(())
+}
+
+/// Escape the commas, equals signs, and spaces of a tag value (e.g., in an engine's flags).
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    #[test]
+    fn write_line_protocol() -> Result<()> {
+        let summary = Summary {
+            arch: "x86_64".into(),
+            engine: "libengine.so [-O opt-level=0]".into(),
+            wasm: "benchmarks/noop/benchmark.wasm".into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            min: 1,
+            max: 3,
+            median: 2,
+            mean: 2.0,
+            mean_deviation: 0.5,
+            threads: None,
+            input_size: None,
+            execution: None,
+        };
+        let threaded = Summary {
+            threads: Some(4),
+            input_size: Some("small".into()),
+            mean_deviation: f64::NAN,
+            ..summary.clone()
+        };
+        let mut output = vec![];
+        write(&[summary, threaded], 1_700_000_000_000_000_000, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "sightglass,engine=libengine.so\\ [-O\\ opt-level\\=0],\
+             benchmark=benchmarks/noop/benchmark.wasm,phase=execution,event=cycles,arch=x86_64 \
+             min=1i,max=3i,median=2i,mean=2,mean_deviation=0.5 1700000000000000000\n\
+             sightglass,engine=libengine.so\\ [-O\\ opt-level\\=0],\
+             benchmark=benchmarks/noop/benchmark.wasm,phase=execution,event=cycles,arch=x86_64,\
+             threads=4,input_size=small min=1i,max=3i,median=2i,mean=2 1700000000000000000\n"
+        );
+        Ok(())
+    }
+}
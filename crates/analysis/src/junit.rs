@@ -0,0 +1,180 @@
+use crate::summarize::run_label;
+use anyhow::Result;
+use sightglass_data::{EffectSize, Phase};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/// Write a vector of [EffectSize] structures to `output_file` as a JUnit XML report, for CI systems
+/// to show in their test UI. Each phase of each benchmark is a test case (e.g., `execution` of
+/// `benchmarks/bz2/benchmark.wasm`), which fails if any of its events is a significant regression:
+/// a result for which the second engine (`b`) is slower than the first (`a`, the baseline). The
+/// failure describes each regression like the `effect-size` command does.
+pub fn write(effect_sizes: &[EffectSize<'_>], output_file: &mut dyn Write) -> Result<()> {
+    let mut cases: BTreeMap<(String, Phase), Vec<&EffectSize>> = BTreeMap::new();
+    for effect_size in effect_sizes {
+        let benchmark = format!(
+            "{}{}",
+            effect_size.wasm,
+            run_label(
+                effect_size.threads,
+                effect_size.input_size.as_deref(),
+                effect_size.execution
+            )
+        );
+        cases
+            .entry((benchmark, effect_size.phase))
+            .or_default()
+            .push(effect_size);
+    }
+    let failures: Vec<_> = cases
+        .values()
+        .map(|effect_sizes| {
+            effect_sizes
+                .iter()
+                .filter(|e| is_regression(e))
+                .copied()
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let failed = failures.iter().filter(|f| !f.is_empty()).count();
+
+    writeln!(output_file, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        output_file,
+        r#"<testsuites name="sightglass" tests="{}" failures="{}">"#,
+        cases.len(),
+        failed
+    )?;
+    writeln!(
+        output_file,
+        r#"  <testsuite name="sightglass" tests="{}" failures="{}">"#,
+        cases.len(),
+        failed
+    )?;
+    for (((benchmark, phase), _), regressions) in cases.iter().zip(&failures) {
+        write!(
+            output_file,
+            r#"    <testcase classname="{}" name="{}""#,
+            escape(benchmark),
+            phase
+        )?;
+        if regressions.is_empty() {
+            writeln!(output_file, "/>")?;
+            continue;
+        }
+        writeln!(output_file, ">")?;
+        let events: Vec<_> = regressions.iter().map(|r| r.event.as_ref()).collect();
+        let text: Vec<_> = regressions.iter().map(|r| describe(r)).collect();
+        writeln!(
+            output_file,
+            r#"      <failure type="regression" message="{}">{}</failure>"#,
+            escape(&format!("significant regression in {}", events.join(", "))),
+            escape(&text.join("\n\n"))
+        )?;
+        writeln!(output_file, "    </testcase>")?;
+    }
+    writeln!(output_file, "  </testsuite>")?;
+    writeln!(output_file, "</testsuites>")?;
+    Ok(())
+}
+
+fn is_regression(effect_size: &EffectSize) -> bool {
+    effect_size.is_significant() && effect_size.b_mean > effect_size.a_mean
+}
+
+/// Describe a regression as the `effect-size` command does.
+fn describe(regression: &EffectSize) -> String {
+    let result = match regression.checked_b_speed_up_over_a() {
+        Some((slowdown, slowdown_ci)) => format!(
+            "{} is {:.2}x to {:.2}x faster than {}!",
+            regression.a_engine,
+            slowdown - slowdown_ci,
+            slowdown + slowdown_ci,
+            regression.b_engine
+        ),
+        None => format!(
+            "{} is faster than {}, with a mean of 0!",
+            regression.a_engine, regression.b_engine
+        ),
+    };
+    format!(
+        "{} :: {} :: {}{}\n\
+         Δ = {:.2} ± {:.2} (confidence = {}%)\n\
+         {}",
+        regression.phase,
+        regression.event,
+        regression.wasm,
+        run_label(
+            regression.threads,
+            regression.input_size.as_deref(),
+            regression.execution
+        ),
+        regression.b_mean - regression.a_mean,
+        regression.half_width_confidence_interval.abs(),
+        (1.0 - regression.significance_level) * 100.0,
+        result
+    )
+}
+
+/// Escape the text of an XML attribute or element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::{Model, Statistic, Transform};
+
+    fn effect_size(phase: Phase, event: &str, b_mean: f64) -> EffectSize<'static> {
+        EffectSize {
+            arch: "x86_64".into(),
+            wasm: "benchmarks/bz2/benchmark.wasm".into(),
+            phase,
+            event: event.to_string().into(),
+            a_engine: "main.so".into(),
+            a_mean: 100.0,
+            b_engine: "nightly.so".into(),
+            b_mean,
+            significance_level: 0.01,
+            half_width_confidence_interval: 5.0,
+            statistic: Statistic::Mean,
+            transform: Transform::None,
+            model: Model::Independent,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_report() -> Result<()> {
+        let effect_sizes = vec![
+            effect_size(Phase::Execution, "cycles", 200.0),
+            effect_size(Phase::Execution, "instructions", 101.0),
+            // A speedup is not a failure.
+            effect_size(Phase::Compilation, "cycles", 50.0),
+        ];
+        let mut output = vec![];
+        write(&effect_sizes, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites name="sightglass" tests="2" failures="1">
+  <testsuite name="sightglass" tests="2" failures="1">
+    <testcase classname="benchmarks/bz2/benchmark.wasm" name="compilation"/>
+    <testcase classname="benchmarks/bz2/benchmark.wasm" name="execution">
+      <failure type="regression" message="significant regression in cycles">execution :: cycles :: benchmarks/bz2/benchmark.wasm
+Δ = 100.00 ± 5.00 (confidence = 99%)
+main.so is 1.95x to 2.05x faster than nightly.so!</failure>
+    </testcase>
+  </testsuite>
+</testsuites>
+"#
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,8 @@
 use sightglass_data::{Measurement, Phase};
-use std::{borrow::Cow, collections::BTreeSet};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+};
 
 /// A builder for finding keys in a set of measurements.
 #[derive(Copy, Clone)]
@@ -64,34 +67,77 @@ impl KeyBuilder {
         self
     }
 
-    /// Extract the keys for the groups of measurements to aggregate.
+    /// Extract the keys for the groups of measurements to aggregate. Measurements taken with
+    /// different numbers of threads (see [Measurement::threads]), with different input sizes (see
+    /// [Measurement::input_size]), or during different executions of their instance (see
+    /// [Measurement::execution]) are never grouped together.
     pub fn keys<'a>(self, measurements: &[Measurement<'a>]) -> Vec<Key<'a>> {
-        let set: BTreeSet<_> = measurements
-            .iter()
-            .cloned()
-            .map(|m| Key {
-                arch: if self.arch { Some(m.arch) } else { None },
-                engine: if self.engine { Some(m.engine) } else { None },
-                wasm: if self.wasm { Some(m.wasm) } else { None },
-                phase: if self.phase { Some(m.phase) } else { None },
-                event: if self.event { Some(m.event) } else { None },
-            })
-            .collect();
-        set.into_iter().collect()
+        let set: BTreeSet<_> = measurements.iter().map(|m| self.key(m)).collect();
+        set.into_iter().map(Key::into_owned).collect()
+    }
+
+    /// The key of the group of a single measurement, borrowing its fields.
+    pub fn key<'a>(self, m: &'a Measurement) -> Key<'a> {
+        Key {
+            arch: self.arch.then(|| Cow::Borrowed(&*m.arch)),
+            engine: self.engine.then(|| Cow::Borrowed(&*m.engine)),
+            wasm: self.wasm.then(|| Cow::Borrowed(&*m.wasm)),
+            phase: self.phase.then_some(m.phase),
+            event: self.event.then(|| Cow::Borrowed(&*m.event)),
+            threads: m.threads,
+            input_size: m.input_size.as_deref().map(Cow::Borrowed),
+            execution: m.execution,
+        }
+    }
+
+    /// Index the measurements by key: the indices of the measurements of each group, in order.
+    /// Each measurement's key is computed once, rather than matching each measurement against each
+    /// of the [keys](Self::keys).
+    pub fn index<'a>(self, measurements: &'a [Measurement]) -> BTreeMap<Key<'a>, Vec<usize>> {
+        let mut index: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (i, m) in measurements.iter().enumerate() {
+            index.entry(self.key(m)).or_default().push(i);
+        }
+        index
     }
 }
 
 /// A key for grouping measurements together.
-#[derive(PartialOrd, Ord, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialOrd, Ord, PartialEq, Eq, Hash)]
 pub struct Key<'a> {
     pub arch: Option<Cow<'a, str>>,
     pub engine: Option<Cow<'a, str>>,
     pub wasm: Option<Cow<'a, str>>,
     pub phase: Option<Phase>,
     pub event: Option<Cow<'a, str>>,
+    pub threads: Option<u32>,
+    pub input_size: Option<Cow<'a, str>>,
+    pub execution: Option<u32>,
+}
+
+impl Key<'static> {
+    /// The key of the group of a measurement: all its fields but its process, iteration, and count.
+    pub fn of(m: &Measurement) -> Self {
+        KeyBuilder::all().key(m).into_owned()
+    }
 }
 
 impl Key<'_> {
+    /// This key, owning its fields.
+    pub fn into_owned(self) -> Key<'static> {
+        let owned = |s: Cow<str>| Cow::Owned(s.into_owned());
+        Key {
+            arch: self.arch.map(owned),
+            engine: self.engine.map(owned),
+            wasm: self.wasm.map(owned),
+            phase: self.phase,
+            event: self.event.map(owned),
+            threads: self.threads,
+            input_size: self.input_size.map(owned),
+            execution: self.execution,
+        }
+    }
+
     /// Does the given measurement match this key?
     pub fn matches(&self, m: &Measurement) -> bool {
         self.arch.as_ref().map_or(true, |x| *x == m.arch)
@@ -99,6 +145,9 @@ impl Key<'_> {
             && self.wasm.as_ref().map_or(true, |x| *x == m.wasm)
             && self.phase.as_ref().map_or(true, |x| *x == m.phase)
             && self.event.as_ref().map_or(true, |x| *x == m.event)
+            && self.threads == m.threads
+            && self.input_size == m.input_size
+            && self.execution == m.execution
     }
 }
 
@@ -115,6 +164,9 @@ mod tests {
             wasm: Some("bench.wasm".into()),
             phase: Some(Phase::Compilation),
             event: Some("cycles".into()),
+            threads: None,
+            input_size: None,
+            execution: None,
         };
 
         // More test cases are needed, but this provides a sanity check for the matched key and
@@ -128,6 +180,90 @@ mod tests {
             phase: Phase::Compilation,
             event: "cycles".into(),
             count: 42,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }));
+        assert!(!key.matches(&Measurement {
+            arch: "x86".into(),
+            engine: "wasmtime".into(),
+            wasm: "bench.wasm".into(),
+            process: 42,
+            iteration: 0,
+            phase: Phase::Compilation,
+            event: "cycles".into(),
+            count: 42,
+            threads: Some(4),
+            input_size: None,
+            execution: None,
         }));
+        assert!(!key.matches(&Measurement {
+            arch: "x86".into(),
+            engine: "wasmtime".into(),
+            wasm: "bench.wasm".into(),
+            process: 42,
+            iteration: 0,
+            phase: Phase::Compilation,
+            event: "cycles".into(),
+            count: 42,
+            threads: None,
+            input_size: Some("large".into()),
+            execution: None,
+        }));
+    }
+
+    #[test]
+    fn separate_executions() {
+        let measurement = |execution| Measurement {
+            arch: "x86".into(),
+            engine: "wasmtime".into(),
+            wasm: "bench.wasm".into(),
+            process: 42,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count: 42,
+            threads: None,
+            input_size: None,
+            execution,
+        };
+        let measurements = [
+            measurement(Some(1)),
+            measurement(Some(2)),
+            measurement(None),
+        ];
+        let keys = KeyBuilder::all().keys(&measurements);
+        assert_eq!(keys.len(), 3);
+        assert!(keys[1].matches(&measurements[0]));
+        assert!(!keys[1].matches(&measurements[1]));
+    }
+
+    #[test]
+    fn index_by_key() {
+        let measurement = |engine: &'static str, wasm: &'static str| Measurement {
+            arch: "x86".into(),
+            engine: engine.into(),
+            wasm: wasm.into(),
+            process: 42,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count: 42,
+            threads: None,
+            input_size: None,
+            execution: None,
+        };
+        let measurements = [
+            measurement("a.so", "x.wasm"),
+            measurement("b.so", "y.wasm"),
+            measurement("b.so", "x.wasm"),
+        ];
+        let builder = KeyBuilder::all().engine(false);
+        let index = builder.index(&measurements);
+        assert_eq!(index.values().collect::<Vec<_>>(), [&[0, 2][..], &[1]]);
+        for (key, indices) in &index {
+            assert!(indices.iter().all(|&i| key.matches(&measurements[i])));
+        }
+        assert!(index.keys().eq(builder.keys(&measurements).iter()));
     }
 }
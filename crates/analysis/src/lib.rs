@@ -1,3 +1,18 @@
+pub mod bayes;
+pub mod bencher;
+pub mod brief;
+pub mod drift;
 pub mod effect_size;
+pub mod grafana;
+pub mod groups;
+pub mod influx;
+pub mod junit;
 pub mod keys;
+pub mod manifest;
+pub mod matrix;
+pub mod prometheus;
+pub mod running;
 pub mod summarize;
+pub mod trend;
+pub mod units;
+pub mod weights;
@@ -0,0 +1,393 @@
+use anyhow::Result;
+use sightglass_data::Manifest;
+use std::{collections::BTreeMap, io::Write};
+
+/// Write a human-readable description of the engines in a run's manifest; e.g., to show which
+/// builds of the engines produced the results that follow. How the run was configured (e.g., its
+/// command line) and the machine it ran on come first, if recorded, then the packaged suite the
+/// benchmarks came from, if any, then any notes about the run (e.g., what else the machine was
+/// doing); the benchmarks described by a `benchmark.toml` or noted, any benchmark variants, and any
+/// benchmarks that failed or were skipped are listed after the engines.
+pub fn write(manifest: &Manifest, output_file: &mut dyn Write) -> Result<()> {
+    if let Some(run) = &manifest.run {
+        writeln!(output_file, "run")?;
+        writeln!(output_file, "  command: {}", run.args.join(" "))?;
+        writeln!(output_file, "  directory: {}", run.directory)?;
+        writeln!(output_file, "  datetime: {}", run.datetime)?;
+        writeln!(output_file, "  sightglass: {}", run.sightglass_version)?;
+        writeln!(
+            output_file,
+            "  processes: {} (of {} iterations each)",
+            run.processes, run.iterations_per_process
+        )?;
+        writeln!(output_file, "  measure: {}", run.measure)?;
+        writeln!(output_file, "  events: {}", run.events.join(", "))?;
+        if let Some(serde_json::Value::Object(machine)) = &run.machine {
+            writeln!(output_file, "  machine")?;
+            for (key, value) in machine {
+                match value {
+                    serde_json::Value::String(value) => {
+                        writeln!(output_file, "    {}: {}", key, value)?
+                    }
+                    value => writeln!(output_file, "    {}: {}", key, value)?,
+                }
+            }
+        }
+    }
+    if let Some(suite) = &manifest.suite {
+        writeln!(
+            output_file,
+            "suite: {} (sha256 {})",
+            suite.version, suite.sha256
+        )?;
+    }
+    if !manifest.notes.is_empty() {
+        writeln!(output_file, "notes")?;
+    }
+    for note in &manifest.notes {
+        writeln!(output_file, "  {}", note)?;
+    }
+    writeln!(output_file, "engines")?;
+    for engine in &manifest.engines {
+        writeln!(output_file, "  {}", engine.engine)?;
+        if let Some(kind) = &engine.kind {
+            writeln!(output_file, "    kind: {}", kind)?;
+        }
+        if let Some(path) = &engine.path {
+            writeln!(output_file, "    path: {}", path)?;
+        }
+        if let Some(sha256) = &engine.sha256 {
+            writeln!(output_file, "    sha256: {}", sha256)?;
+        }
+        if let Some(flags) = &engine.flags {
+            writeln!(output_file, "    flags: {}", flags)?;
+        }
+        for (key, value) in &engine.build_info {
+            writeln!(output_file, "    {}: {}", key, value)?;
+        }
+    }
+    if !manifest.benchmarks.is_empty() {
+        writeln!(output_file, "benchmarks")?;
+    }
+    for benchmark in &manifest.benchmarks {
+        writeln!(output_file, "  {}", benchmark.wasm)?;
+        if !benchmark.tags.is_empty() {
+            writeln!(output_file, "    tags: {}", benchmark.tags.join(", "))?;
+        }
+        if let Some(flags) = &benchmark.engine_flags {
+            writeln!(output_file, "    engine flags: {}", flags)?;
+        }
+        for note in &benchmark.notes {
+            writeln!(output_file, "    note: {}", note)?;
+        }
+    }
+    if !manifest.variants.is_empty() {
+        writeln!(output_file, "variants")?;
+    }
+    for variant in &manifest.variants {
+        writeln!(output_file, "  {}", variant.wasm)?;
+        writeln!(output_file, "    variant: {}", variant.name)?;
+        if !variant.flags.is_empty() || variant.wasm_opt.is_none() {
+            writeln!(output_file, "    flags: {}", variant.flags)?;
+        }
+        if let Some(passes) = &variant.wasm_opt {
+            writeln!(output_file, "    wasm-opt: {}", passes)?;
+        }
+    }
+    if !manifest.failures.is_empty() {
+        writeln!(output_file, "failures")?;
+    }
+    for failure in &manifest.failures {
+        writeln!(output_file, "  {} :: {}", failure.engine, failure.wasm)?;
+        if let Some(phase) = failure.phase {
+            writeln!(output_file, "    phase: {}", phase)?;
+        }
+        if let Some(threads) = failure.threads {
+            writeln!(output_file, "    threads: {}", threads)?;
+        }
+        if let Some(status) = failure.status {
+            writeln!(output_file, "    status: {}", status)?;
+        }
+        let mut error = failure.error.lines();
+        writeln!(
+            output_file,
+            "    error: {}",
+            error.next().unwrap_or_default()
+        )?;
+        for line in error {
+            writeln!(output_file, "      {}", line)?;
+        }
+        for line in failure.stderr.lines() {
+            writeln!(output_file, "    | {}", line)?;
+        }
+    }
+    if !manifest.skipped.is_empty() {
+        writeln!(output_file, "skipped")?;
+    }
+    for skipped in &manifest.skipped {
+        writeln!(output_file, "  {} :: {}", skipped.engine, skipped.wasm)?;
+        writeln!(
+            output_file,
+            "    unsupported: {}",
+            skipped.proposals.join(", ")
+        )?;
+    }
+    writeln!(output_file)?;
+    Ok(())
+}
+
+/// Find the engines whose shared library has the same path in two of the `manifests` (each named
+/// by, e.g., its file) but a different digest: most likely, the library was rebuilt in place
+/// between the runs, so that their results for "the same" engine are of different engines. Each
+/// is described as a warning.
+pub fn conflicting_engines(manifests: &[(String, Manifest)]) -> Vec<String> {
+    let mut first: BTreeMap<&str, (&str, &str)> = BTreeMap::new();
+    let mut conflicts = vec![];
+    for (name, manifest) in manifests {
+        for engine in &manifest.engines {
+            let (path, sha256) = match (&engine.path, &engine.sha256) {
+                (Some(path), Some(sha256)) => (path.as_str(), sha256.as_str()),
+                _ => continue,
+            };
+            match first.get(path) {
+                Some(&(first_name, first_sha256)) if first_sha256 != sha256 => {
+                    conflicts.push(format!(
+                        "the engine {} differs between {} (sha256 {}) and {} (sha256 {}); it was \
+                         likely rebuilt in place between the runs, so their results may not be \
+                         comparable",
+                        path, first_name, first_sha256, name, sha256
+                    ))
+                }
+                Some(_) => {}
+                None => {
+                    first.insert(path, (name, sha256));
+                }
+            }
+        }
+    }
+    conflicts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::{
+        BenchmarkManifest, EngineManifest, Failure, RunManifest, Skipped, SuiteManifest, Variant,
+    };
+
+    #[test]
+    fn write_engines() -> Result<()> {
+        let manifest = Manifest {
+            engines: vec![
+                EngineManifest {
+                    engine: "wasmtime:main".into(),
+                    path: Some("/tmp/libengine.so".into()),
+                    kind: Some("wasmtime".into()),
+                    sha256: Some("abc".into()),
+                    flags: None,
+                    build_info: [
+                        ("PROFILE".to_string(), "release".to_string()),
+                        ("WASMTIME_VERSION".to_string(), "1.0.0".to_string()),
+                    ]
+                    .into_iter()
+                    .collect(),
+                },
+                EngineManifest {
+                    engine: "native".into(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "engines\n  wasmtime:main\n    kind: wasmtime\n    path: /tmp/libengine.so\n    sha256: \
+             abc\n    PROFILE: release\n    WASMTIME_VERSION: 1.0.0\n  native\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn find_conflicting_engines() {
+        let manifest = |engines: &[(&str, &str)]| Manifest {
+            engines: engines
+                .iter()
+                .map(|(path, sha256)| EngineManifest {
+                    engine: path.to_string(),
+                    path: Some(path.to_string()),
+                    sha256: Some(sha256.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let manifests = vec![
+            (
+                "main.json".into(),
+                manifest(&[("main.so", "1"), ("branch.so", "2")]),
+            ),
+            (
+                "rerun.json".into(),
+                manifest(&[("main.so", "1"), ("branch.so", "3")]),
+            ),
+        ];
+        assert_eq!(
+            conflicting_engines(&manifests),
+            [
+                "the engine branch.so differs between main.json (sha256 2) and rerun.json (sha256 \
+              3); it was likely rebuilt in place between the runs, so their results may not be \
+              comparable"
+            ]
+        );
+        assert!(conflicting_engines(&manifests[..1]).is_empty());
+    }
+
+    #[test]
+    fn write_run() -> Result<()> {
+        let manifest = Manifest {
+            run: Some(RunManifest {
+                sightglass_version: "0.1.0".into(),
+                args: vec![
+                    "sightglass-cli".into(),
+                    "benchmark".into(),
+                    "bz2.wasm".into(),
+                ],
+                directory: "/home/me/sightglass".into(),
+                datetime: "2024-06-01T03:00:00+00:00".into(),
+                processes: 10,
+                iterations_per_process: 5,
+                measure: "cycles".into(),
+                events: vec!["cycles".into()],
+                machine: Some(serde_json::json!({"cpu": "Xeon", "memory": "16 GiB"})),
+            }),
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "run\n  command: sightglass-cli benchmark bz2.wasm\n  directory: /home/me/sightglass\n  \
+             datetime: 2024-06-01T03:00:00+00:00\n  sightglass: 0.1.0\n  processes: 10 (of 5 \
+             iterations each)\n  measure: cycles\n  events: cycles\n  machine\n    cpu: Xeon\n    \
+             memory: 16 GiB\nengines\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_suite() -> Result<()> {
+        let manifest = Manifest {
+            suite: Some(SuiteManifest {
+                version: "2024-01".into(),
+                sha256: "abc".into(),
+            }),
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "suite: 2024-01 (sha256 abc)\nengines\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_failures() -> Result<()> {
+        let manifest = Manifest {
+            failures: vec![Failure {
+                engine: "wasmtime:main".into(),
+                wasm: "benchmarks/noop/benchmark.wasm".into(),
+                phase: Some(sightglass_data::Phase::Execution),
+                threads: Some(4),
+                status: Some(1),
+                error: "wasm trap: unreachable".into(),
+                stderr: "a\nb\n".into(),
+            }],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "engines\nfailures\n  wasmtime:main :: benchmarks/noop/benchmark.wasm\n    phase: \
+             execution\n    threads: 4\n    status: 1\n    error: wasm trap: unreachable\n    | a\n    | b\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_skipped() -> Result<()> {
+        let manifest = Manifest {
+            skipped: vec![Skipped {
+                engine: "libengine.so".into(),
+                wasm: "benchmarks/gc/benchmark.wasm".into(),
+                proposals: vec!["gc".into(), "tail-call".into()],
+            }],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "engines\nskipped\n  libengine.so :: benchmarks/gc/benchmark.wasm\n    unsupported: \
+             gc, tail-call\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_benchmarks() -> Result<()> {
+        let manifest = Manifest {
+            benchmarks: vec![BenchmarkManifest {
+                wasm: "benchmarks/bz2/benchmark.wasm".into(),
+                tags: vec!["compile-heavy".into(), "wasi".into()],
+                engine_flags: Some("-W simd=y".into()),
+                notes: vec!["reads its input through WASI".into()],
+            }],
+            notes: vec!["the machine was also running a kernel build".into()],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "notes\n  the machine was also running a kernel build\nengines\nbenchmarks\n  \
+             benchmarks/bz2/benchmark.wasm\n    tags: compile-heavy, wasi\n    engine flags: -W \
+             simd=y\n    note: reads its input through WASI\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn write_variants() -> Result<()> {
+        let manifest = Manifest {
+            variants: vec![
+                Variant {
+                    wasm: "benchmarks/bz2/benchmark-simd.wasm".into(),
+                    name: "simd".into(),
+                    flags: "-msimd128".into(),
+                    wasm_opt: None,
+                },
+                Variant {
+                    wasm: "benchmarks/bz2/benchmark-o3.wasm".into(),
+                    name: "o3".into(),
+                    flags: String::new(),
+                    wasm_opt: Some("-O3".into()),
+                },
+            ],
+            ..Default::default()
+        };
+        let mut output = vec![];
+        write(&manifest, &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "engines\nvariants\n  benchmarks/bz2/benchmark-simd.wasm\n    variant: simd\n    \
+             flags: -msimd128\n  benchmarks/bz2/benchmark-o3.wasm\n    variant: o3\n    \
+             wasm-opt: -O3\n\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+use crate::summarize::run_label;
+use crate::units::Formatting;
+use anyhow::{bail, Result};
+use sightglass_data::{Phase, Summary};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// Write the summaries of one `phase` and `event` to `output_file` as a table with a row per
+/// benchmark and a column per engine, so that the whole suite can be scanned at a glance. Each cell
+/// is the mean of the engine's counts, followed (after the `baseline` engine's column, by default
+/// the first) by its change from the baseline's mean; a benchmark an engine did not run is left
+/// blank (`-`). Means are written as the `formatting` says (see [Formatting::count]):
+///
+/// ```text
+/// execution :: cycles (mean; change from main.so)
+///
+/// benchmark                        main.so            pr.so
+/// benchmarks/bz2/benchmark.wasm     1.23 M   1.17 M (-5.0%)
+/// benchmarks/noop/benchmark.wasm        12       12 (+0.0%)
+/// ```
+pub fn write(
+    summaries: &[Summary<'_>],
+    phase: Phase,
+    event: &str,
+    baseline: Option<&str>,
+    formatting: Formatting,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    let summaries: Vec<_> = summaries
+        .iter()
+        .filter(|s| s.phase == phase && s.event == event)
+        .collect();
+    let mut engines: Vec<&str> = summaries
+        .iter()
+        .map(|s| &*s.engine)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if let Some(baseline) = baseline {
+        match engines.iter().position(|&e| e == baseline) {
+            Some(i) => {
+                let baseline = engines.remove(i);
+                engines.insert(0, baseline);
+            }
+            None => bail!("no {} :: {} measurements of {}", phase, event, baseline),
+        }
+    }
+
+    // Mixing architectures, a benchmark is named with its architecture.
+    let archs: BTreeSet<_> = summaries.iter().map(|s| &s.arch).collect();
+    let mut rows: BTreeMap<_, BTreeMap<&str, f64>> = BTreeMap::new();
+    for s in &summaries {
+        let mut name = format!(
+            "{}{}",
+            s.wasm,
+            run_label(s.threads, s.input_size.as_deref(), s.execution)
+        );
+        if archs.len() > 1 {
+            name = format!("{}: {}", s.arch, name);
+        }
+        rows.entry(name).or_default().insert(&s.engine, s.mean);
+    }
+
+    let mut table = vec![];
+    table.push(
+        std::iter::once("benchmark".to_string())
+            .chain(engines.iter().map(|e| e.to_string()))
+            .collect::<Vec<_>>(),
+    );
+    for (name, means) in &rows {
+        let mut row = vec![name.clone()];
+        let base = engines.first().and_then(|e| means.get(e));
+        for (i, engine) in engines.iter().enumerate() {
+            row.push(match (means.get(engine), base) {
+                (None, _) => "-".to_string(),
+                (Some(&mean), _) if i == 0 => formatting.count(event, mean),
+                (Some(&mean), Some(&base)) if base != 0.0 => format!(
+                    "{} ({:+.1}%)",
+                    formatting.count(event, mean),
+                    (mean - base) / base * 100.0
+                ),
+                (Some(&mean), _) => formatting.count(event, mean),
+            });
+        }
+        table.push(row);
+    }
+
+    writeln!(
+        output_file,
+        "{} :: {} (mean; change from {})",
+        phase,
+        event,
+        engines.first().unwrap_or(&"-")
+    )?;
+    writeln!(output_file)?;
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|i| {
+            table
+                .iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect();
+    for row in &table {
+        let mut line = format!("{:<width$}", row[0], width = widths[0]);
+        for (cell, width) in row.iter().zip(&widths).skip(1) {
+            line.push_str(&format!("   {:>width$}", cell, width = width));
+        }
+        writeln!(output_file, "{}", line)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(engine: &str, wasm: &str, event: &str, mean: f64) -> Summary<'static> {
+        Summary {
+            arch: "x86_64".into(),
+            engine: engine.to_string().into(),
+            wasm: wasm.to_string().into(),
+            phase: Phase::Execution,
+            event: event.to_string().into(),
+            min: 0,
+            max: 0,
+            median: 0,
+            mean,
+            mean_deviation: 0.0,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_grid() -> Result<()> {
+        let raw = Formatting {
+            raw: true,
+            ..Formatting::default()
+        };
+        let summaries = vec![
+            summary("main.so", "bz2.wasm", "cycles", 1000.0),
+            summary("pr.so", "bz2.wasm", "cycles", 950.0),
+            summary("main.so", "noop.wasm", "cycles", 10.0),
+            summary("main.so", "noop.wasm", "instructions", 5.0),
+            summary("pr.so", "fib.wasm", "cycles", 20.0),
+        ];
+        let mut output = vec![];
+        write(
+            &summaries,
+            Phase::Execution,
+            "cycles",
+            None,
+            raw,
+            &mut output,
+        )?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "execution :: cycles (mean; change from main.so)\n\
+             \n\
+             benchmark   main.so         pr.so\n\
+             bz2.wasm       1000   950 (-5.0%)\n\
+             fib.wasm          -            20\n\
+             noop.wasm        10             -\n"
+        );
+
+        let mut output = vec![];
+        write(
+            &summaries,
+            Phase::Execution,
+            "cycles",
+            Some("pr.so"),
+            raw,
+            &mut output,
+        )?;
+        let output = String::from_utf8(output)?;
+        assert!(output.contains("bz2.wasm      950   1000 (+5.3%)\n"));
+
+        assert!(write(
+            &summaries,
+            Phase::Execution,
+            "cycles",
+            Some("x.so"),
+            raw,
+            &mut vec![]
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use anyhow::Result;
+use sightglass_data::Summary;
+use std::io::Write;
+
+/// A gauge: its name, its help text, and the statistic of a summary it reports.
+type Gauge = (&'static str, &'static str, fn(&Summary) -> f64);
+
+/// Write [Summary] structures to `output_file` in the Prometheus [text format]; e.g., to push to
+/// a Prometheus pushgateway after a run, for alerting rules to watch for regressions. Each
+/// statistic is a gauge (`sightglass_min`, `sightglass_max`, `sightglass_median`,
+/// `sightglass_mean`, and `sightglass_mean_deviation`), labeled with the engine, benchmark, phase,
+/// event, architecture, and any thread count or input size of its summary:
+///
+/// ```text
+/// sightglass_median{engine="libengine.so",benchmark="benchmarks/noop/benchmark.wasm",phase="execution",event="cycles",arch="x86_64"} 2
+/// ```
+///
+/// [text format]: https://prometheus.io/docs/instrumenting/exposition_formats/#text-based-format
+pub fn write(summaries: &[Summary<'_>], output_file: &mut dyn Write) -> Result<()> {
+    let statistics: [Gauge; 5] = [
+        ("min", "The minimum count of the event.", |s| s.min as f64),
+        ("max", "The maximum count of the event.", |s| s.max as f64),
+        ("median", "The median count of the event.", |s| {
+            s.median as f64
+        }),
+        ("mean", "The arithmetic mean count of the event.", |s| {
+            s.mean
+        }),
+        (
+            "mean_deviation",
+            "The mean deviation of the count of the event.",
+            |s| s.mean_deviation,
+        ),
+    ];
+    for (name, help, statistic) in statistics {
+        writeln!(output_file, "# HELP sightglass_{} {}", name, help)?;
+        writeln!(output_file, "# TYPE sightglass_{} gauge", name)?;
+        for summary in summaries {
+            writeln!(
+                output_file,
+                "sightglass_{}{{{}}} {}",
+                name,
+                labels(summary),
+                statistic(summary)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn labels(summary: &Summary<'_>) -> String {
+    let mut labels = vec![
+        ("engine", summary.engine.to_string()),
+        ("benchmark", summary.wasm.to_string()),
+        ("phase", summary.phase.to_string()),
+        ("event", summary.event.to_string()),
+        ("arch", summary.arch.to_string()),
+    ];
+    if let Some(threads) = summary.threads {
+        labels.push(("threads", threads.to_string()));
+    }
+    if let Some(input_size) = &summary.input_size {
+        labels.push(("input_size", input_size.to_string()));
+    }
+    if let Some(execution) = summary.execution {
+        labels.push(("execution", execution.to_string()));
+    }
+    let labels: Vec<_> = labels
+        .iter()
+        .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+        .collect();
+    labels.join(",")
+}
+
+/// Escape the backslashes, double quotes, and line feeds of a label value.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    #[test]
+    fn write_text_format() -> Result<()> {
+        let summary = Summary {
+            arch: "x86_64".into(),
+            engine: "libengine.so [-W \"x\"]".into(),
+            wasm: "benchmarks/noop/benchmark.wasm".into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            min: 1,
+            max: 3,
+            median: 2,
+            mean: 2.0,
+            mean_deviation: 0.5,
+            threads: Some(4),
+            input_size: None,
+            execution: None,
+        };
+        let mut output = vec![];
+        write(&[summary], &mut output)?;
+        let output = String::from_utf8(output)?;
+        let labels = "engine=\"libengine.so [-W \\\"x\\\"]\",\
+                      benchmark=\"benchmarks/noop/benchmark.wasm\",phase=\"execution\",\
+                      event=\"cycles\",arch=\"x86_64\",threads=\"4\"";
+        assert!(output.starts_with(
+            "# HELP sightglass_min The minimum count of the event.\n\
+             # TYPE sightglass_min gauge\n"
+        ));
+        assert!(output.contains(&format!("sightglass_median{{{}}} 2\n", labels)));
+        assert!(output.contains(&format!("sightglass_mean_deviation{{{}}} 0.5\n", labels)));
+        assert_eq!(output.lines().count(), 15);
+        Ok(())
+    }
+}
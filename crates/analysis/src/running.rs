@@ -0,0 +1,264 @@
+use crate::keys::Key;
+use serde::{Deserialize, Serialize};
+use sightglass_data::{Measurement, Phase, Summary};
+use std::collections::BTreeMap;
+
+/// The relative accuracy of the medians and mean deviations of [RunningSummaries].
+pub const RELATIVE_ACCURACY: f64 = 0.01;
+
+/// Summaries that are updated as measurements are added rather than recomputed from all of them;
+/// e.g., to summarize a results file that is appended to every night. Each group keeps only its
+/// number of counts, their sum, minimum, and maximum, and a sketch of their distribution, from which
+/// the median and mean deviation are estimated to within [RELATIVE_ACCURACY]. Running summaries
+/// serialize, to be kept from one update to the next.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Entry>", into = "Vec<Entry>")]
+pub struct RunningSummaries {
+    groups: BTreeMap<Key<'static>, Stats>,
+    /// The group of the last measurement added; see `Groups`.
+    last: Option<Key<'static>>,
+}
+
+impl RunningSummaries {
+    /// Add the count of a measurement to the statistics of its group.
+    pub fn add(&mut self, m: &Measurement<'_>) {
+        if let Some(last) = self.last.as_ref().filter(|last| last.matches(m)) {
+            self.groups.get_mut(last).unwrap().add(m.count);
+            return;
+        }
+        let key = Key::of(m);
+        self.groups.entry(key.clone()).or_default().add(m.count);
+        self.last = Some(key);
+    }
+
+    /// Whether no measurements were added.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Summarize each group, as `summarize::calculate` would, but with estimated medians and mean
+    /// deviations.
+    pub fn summaries(&self) -> Vec<Summary<'static>> {
+        self.groups
+            .iter()
+            .map(|(k, stats)| {
+                let mean = stats.sum / stats.n as f64;
+                Summary {
+                    arch: k.arch.clone().unwrap(),
+                    engine: k.engine.clone().unwrap(),
+                    wasm: k.wasm.clone().unwrap(),
+                    phase: k.phase.unwrap(),
+                    event: k.event.clone().unwrap(),
+                    min: stats.min,
+                    max: stats.max,
+                    median: (stats.sketch.value_at(stats.n / 2).round() as u64)
+                        .clamp(stats.min, stats.max),
+                    mean,
+                    mean_deviation: stats.sketch.mean_deviation(mean, stats.n),
+                    threads: k.threads,
+                    input_size: k.input_size.clone(),
+                    execution: k.execution,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The sufficient statistics of a group of counts.
+#[derive(Clone, Serialize, Deserialize)]
+struct Stats {
+    n: u64,
+    sum: f64,
+    min: u64,
+    max: u64,
+    sketch: Sketch,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            n: 0,
+            sum: 0.0,
+            min: u64::MAX,
+            max: 0,
+            sketch: Sketch::default(),
+        }
+    }
+}
+
+impl Stats {
+    fn add(&mut self, count: u64) {
+        self.n += 1;
+        self.sum += count as f64;
+        self.min = self.min.min(count);
+        self.max = self.max.max(count);
+        self.sketch.add(count);
+    }
+}
+
+/// A histogram of counts in logarithmically sized buckets, each of whose values are within
+/// [RELATIVE_ACCURACY] of its midpoint (as in DDSketch), so that a few hundred buckets cover all
+/// the counts a benchmark could measure.
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Sketch {
+    zeros: u64,
+    buckets: BTreeMap<i32, u64>,
+}
+
+impl Sketch {
+    fn gamma() -> f64 {
+        (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY)
+    }
+
+    fn add(&mut self, count: u64) {
+        if count == 0 {
+            self.zeros += 1;
+        } else {
+            let bucket = ((count as f64).ln() / Self::gamma().ln()).ceil() as i32;
+            *self.buckets.entry(bucket).or_default() += 1;
+        }
+    }
+
+    /// The (estimated) value of the bucket `i`.
+    fn value(i: i32) -> f64 {
+        2.0 * Self::gamma().powi(i) / (Self::gamma() + 1.0)
+    }
+
+    /// The (estimated) value of rank `rank`, counting from 0, of the counts in order.
+    fn value_at(&self, rank: u64) -> f64 {
+        let mut seen = self.zeros;
+        if rank < seen {
+            return 0.0;
+        }
+        for (&i, &n) in &self.buckets {
+            seen += n;
+            if rank < seen {
+                return Self::value(i);
+            }
+        }
+        self.buckets
+            .keys()
+            .next_back()
+            .map_or(0.0, |&i| Self::value(i))
+    }
+
+    /// The (estimated) mean deviation of the `n` counts from their `mean`.
+    fn mean_deviation(&self, mean: f64, n: u64) -> f64 {
+        let deviations: f64 = self
+            .buckets
+            .iter()
+            .map(|(&i, &count)| count as f64 * (mean - Self::value(i)).abs())
+            .sum();
+        (deviations + self.zeros as f64 * mean) / n as f64
+    }
+}
+
+/// The serialized form of a group of [RunningSummaries].
+#[derive(Clone, Serialize, Deserialize)]
+struct Entry {
+    arch: String,
+    engine: String,
+    wasm: String,
+    phase: Phase,
+    event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    threads: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    input_size: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    execution: Option<u32>,
+    stats: Stats,
+}
+
+impl From<Vec<Entry>> for RunningSummaries {
+    fn from(entries: Vec<Entry>) -> Self {
+        let groups = entries
+            .into_iter()
+            .map(|e| {
+                let key = Key {
+                    arch: Some(e.arch.into()),
+                    engine: Some(e.engine.into()),
+                    wasm: Some(e.wasm.into()),
+                    phase: Some(e.phase),
+                    event: Some(e.event.into()),
+                    threads: e.threads,
+                    input_size: e.input_size.map(Into::into),
+                    execution: e.execution,
+                };
+                (key, e.stats)
+            })
+            .collect();
+        Self { groups, last: None }
+    }
+}
+
+impl From<RunningSummaries> for Vec<Entry> {
+    fn from(summaries: RunningSummaries) -> Self {
+        summaries
+            .groups
+            .into_iter()
+            .map(|(k, stats)| Entry {
+                arch: k.arch.unwrap().into_owned(),
+                engine: k.engine.unwrap().into_owned(),
+                wasm: k.wasm.unwrap().into_owned(),
+                phase: k.phase.unwrap(),
+                event: k.event.unwrap().into_owned(),
+                threads: k.threads,
+                input_size: k.input_size.map(|s| s.into_owned()),
+                execution: k.execution,
+                stats,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summarize;
+    use anyhow::Result;
+
+    fn measurement(engine: &str, count: u64) -> Measurement<'_> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: "bench.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn update_summaries() -> Result<()> {
+        let measurements: Vec<_> = (0..1000u64)
+            .map(|i| measurement(["a.so", "b.so"][i as usize % 2], 1_000_000 + i * i * 7))
+            .chain([measurement("b.so", 0)])
+            .collect();
+
+        // Add the first half, keep the summaries, then add the rest.
+        let (old, new) = measurements.split_at(600);
+        let mut running = RunningSummaries::default();
+        old.iter().for_each(|m| running.add(m));
+        let mut running: RunningSummaries = serde_json::from_slice(&serde_json::to_vec(&running)?)?;
+        new.iter().for_each(|m| running.add(m));
+
+        let expected = summarize::calculate(&measurements);
+        let actual = running.summaries();
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            assert_eq!(actual.engine, expected.engine);
+            assert_eq!((actual.min, actual.max), (expected.min, expected.max));
+            assert!((actual.mean - expected.mean).abs() < 1e-6);
+            let error = |a: f64, e: f64| (a - e).abs() / e;
+            assert!(error(actual.median as f64, expected.median as f64) <= RELATIVE_ACCURACY);
+            assert!(error(actual.mean_deviation, expected.mean_deviation) <= RELATIVE_ACCURACY);
+        }
+        Ok(())
+    }
+}
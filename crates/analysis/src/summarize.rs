@@ -1,23 +1,25 @@
-use crate::keys::KeyBuilder;
+use crate::groups::Groups;
+use crate::units::Formatting;
 use anyhow::Result;
 use sightglass_data::{Measurement, Summary};
 use std::io::Write;
 
 /// Summarize measurements grouped by: architecture, engine, benchmark file, phase and event.
 pub fn calculate<'a>(measurements: &[Measurement<'a>]) -> Vec<Summary<'a>> {
+    calculate_grouped(&measurements.iter().collect())
+}
+
+/// Summarize each group of measurements; e.g., as they were read from a large results file.
+pub fn calculate_grouped(groups: &Groups) -> Vec<Summary<'static>> {
     let mut summaries = Vec::new();
-    for k in KeyBuilder::all().keys(&measurements) {
-        let mut grouped_counts: Vec<_> = measurements
-            .iter()
-            .filter(|m| k.matches(m))
-            .map(|m| m.count)
-            .collect();
+    for (k, counts) in groups.iter() {
+        let mut grouped_counts = counts.to_vec();
         summaries.push(Summary {
-            arch: k.arch.unwrap(),
-            engine: k.engine.unwrap(),
-            wasm: k.wasm.unwrap(),
+            arch: k.arch.clone().unwrap(),
+            engine: k.engine.clone().unwrap(),
+            wasm: k.wasm.clone().unwrap(),
             phase: k.phase.unwrap(),
-            event: k.event.unwrap(),
+            event: k.event.clone().unwrap(),
             min: grouped_counts
                 .iter()
                 .cloned()
@@ -31,6 +33,9 @@ pub fn calculate<'a>(measurements: &[Measurement<'a>]) -> Vec<Summary<'a>> {
             mean: mean(&grouped_counts),
             mean_deviation: mean_deviation(&grouped_counts),
             median: median(grouped_counts.as_mut_slice()),
+            threads: k.threads,
+            input_size: k.input_size.clone(),
+            execution: k.execution,
         })
     }
     summaries
@@ -59,8 +64,13 @@ fn median(numbers: &mut [u64]) -> u64 {
     numbers[numbers.len() / 2]
 }
 
-/// Write a vector of [Summary] structures to the passed `output_file` in human-readable form.
-pub fn write(mut summaries: Vec<Summary<'_>>, output_file: &mut dyn Write) -> Result<()> {
+/// Write a vector of [Summary] structures to the passed `output_file` in human-readable form. Counts
+/// are written as the `formatting` says (see [Formatting::count]).
+pub fn write(
+    mut summaries: Vec<Summary<'_>>,
+    formatting: Formatting,
+    output_file: &mut dyn Write,
+) -> Result<()> {
     // TODO this sorting is not using `arch` which is not guaranteed to be the same in result sets;
     // potentially this could re-use `Key` functionality.
     summaries.sort_by(|x, y| {
@@ -69,6 +79,9 @@ pub fn write(mut summaries: Vec<Summary<'_>>, output_file: &mut dyn Write) -> Re
             .then_with(|| x.wasm.cmp(&y.wasm))
             .then_with(|| x.event.cmp(&y.event))
             .then_with(|| x.engine.cmp(&y.engine))
+            .then_with(|| x.input_size.cmp(&y.input_size))
+            .then_with(|| x.threads.cmp(&y.threads))
+            .then_with(|| x.execution.cmp(&y.execution))
     });
 
     let mut last_phase = None;
@@ -95,14 +108,44 @@ pub fn write(mut summaries: Vec<Summary<'_>>, output_file: &mut dyn Write) -> Re
 
         writeln!(
             output_file,
-            "      [{} {:.2} {}] {}",
-            summary.min, summary.mean, summary.max, summary.engine,
+            "      [{} {} {}] {}{}",
+            formatting.count(&summary.event, summary.min as f64),
+            formatting.count(&summary.event, summary.mean),
+            formatting.count(&summary.event, summary.max as f64),
+            summary.engine,
+            run_label(
+                summary.threads,
+                summary.input_size.as_deref(),
+                summary.execution
+            ),
         )?;
     }
 
     Ok(())
 }
 
+/// Describe the input size, number of threads, and execution of its instance a benchmark ran with,
+/// to follow its engine; e.g., ` (large input, 4 threads, execution 2)`.
+pub fn run_label(threads: Option<u32>, input_size: Option<&str>, execution: Option<u32>) -> String {
+    let mut parts = vec![];
+    if let Some(input_size) = input_size {
+        parts.push(format!("{} input", input_size));
+    }
+    match threads {
+        Some(1) => parts.push("1 thread".to_string()),
+        Some(threads) => parts.push(format!("{} threads", threads)),
+        None => {}
+    }
+    if let Some(execution) = execution {
+        parts.push(format!("execution {}", execution));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", parts.join(", "))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -120,6 +163,9 @@ mod tests {
                 phase: Phase::Compilation,
                 event: "cycles".into(),
                 count,
+                threads: None,
+                input_size: None,
+                execution: None,
             }
         }
 
@@ -138,6 +184,9 @@ mod tests {
                 median: 1,
                 max: 2,
                 mean_deviation: 2f64 / 3f64,
+                threads: None,
+                input_size: None,
+                execution: None,
             }]
         );
     }
@@ -154,6 +203,9 @@ mod tests {
                 phase,
                 event: "cycles".into(),
                 count,
+                threads: None,
+                input_size: None,
+                execution: None,
             }
         }
         let measurements = vec![
@@ -164,4 +216,75 @@ mod tests {
 
         assert_eq!(calculate(&measurements).len(), 2);
     }
+
+    #[test]
+    fn thread_counts() -> Result<()> {
+        fn measurement<'a>(threads: u32, count: u64) -> Measurement<'a> {
+            Measurement {
+                arch: "x86".into(),
+                engine: "wasmtime".into(),
+                wasm: "bench.wasm".into(),
+                process: 42,
+                iteration: 0,
+                phase: Phase::Execution,
+                event: "cycles".into(),
+                count,
+                threads: Some(threads),
+                input_size: None,
+                execution: None,
+            }
+        }
+        let measurements = vec![
+            measurement(4, 30),
+            measurement(1, 100),
+            measurement(4, 20),
+            measurement(1, 110),
+        ];
+
+        let mut output = vec![];
+        write(calculate(&measurements), Formatting::default(), &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "execution\n  bench.wasm\n    cycles\n      [100 105 110] wasmtime (1 thread)\n      \
+             [20 25 30] wasmtime (4 threads)\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn input_sizes() -> Result<()> {
+        fn measurement<'a>(input_size: Option<&'a str>, count: u64) -> Measurement<'a> {
+            Measurement {
+                arch: "x86".into(),
+                engine: "wasmtime".into(),
+                wasm: "bench.wasm".into(),
+                process: 42,
+                iteration: 0,
+                phase: Phase::Execution,
+                event: "cycles".into(),
+                count,
+                threads: None,
+                input_size: input_size.map(Into::into),
+                execution: None,
+            }
+        }
+        let measurements = vec![
+            measurement(Some("large"), 1000),
+            measurement(None, 100),
+            measurement(Some("large"), 1100),
+        ];
+
+        let mut output = vec![];
+        write(calculate(&measurements), Formatting::default(), &mut output)?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "execution\n  bench.wasm\n    cycles\n      [100 100 100] wasmtime\n      \
+             [1.00 k 1.05 k 1.10 k] wasmtime (large input)\n"
+        );
+        assert_eq!(
+            run_label(Some(4), Some("small"), Some(2)),
+            " (small input, 4 threads, execution 2)"
+        );
+        Ok(())
+    }
 }
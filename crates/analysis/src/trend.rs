@@ -0,0 +1,237 @@
+use crate::summarize::run_label;
+use crate::units::Formatting;
+use anyhow::{bail, Result};
+use sightglass_data::{Phase, Summary};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+/// The bars of a sparkline, lowest first.
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Write the trend of each benchmark's mean across `runs` (each the time of a run and its
+/// summaries, oldest first) to `output_file`, for one `phase` and `event`, so that drifts can be
+/// spotted in a terminal. Each benchmark has a row with a sparkline of its mean in each run (blank
+/// where it did not run), scaled from its lowest mean to its highest; its mean in the last run;
+/// its change from the first run to the last; and the range of its means (the highest over the
+/// lowest), to tell a drift from noise. The last mean is written as the `formatting` says (see
+/// [Formatting::count]):
+///
+/// ```text
+/// execution :: cycles, 5 runs from 2024-06-01 03:00:00 to 2024-06-05 03:00:00
+///
+/// benchmark   trend     last   change   range
+/// bz2.wasm    ▁▁▂▇█   1.30 k   +30.0%   30.0%
+/// noop.wasm   █▁ ▁█       12    +0.0%    9.1%
+/// ```
+///
+/// A run must have one engine, or one whose name contains `engine`.
+pub fn write(
+    runs: &[(String, Vec<Summary<'_>>)],
+    phase: Phase,
+    event: &str,
+    engine: Option<&str>,
+    formatting: Formatting,
+    output_file: &mut dyn Write,
+) -> Result<()> {
+    let selected = |s: &&Summary| {
+        s.phase == phase && s.event == event && engine.is_none_or(|e| s.engine.contains(e))
+    };
+    let archs: BTreeSet<_> = runs
+        .iter()
+        .flat_map(|(_, summaries)| summaries.iter().filter(selected).map(|s| &s.arch))
+        .collect();
+    let mut rows: BTreeMap<String, Vec<Option<&Summary>>> = BTreeMap::new();
+    for (i, (datetime, summaries)) in runs.iter().enumerate() {
+        for s in summaries.iter().filter(selected) {
+            let mut name = format!(
+                "{}{}",
+                s.wasm,
+                run_label(s.threads, s.input_size.as_deref(), s.execution)
+            );
+            // Mixing architectures, a benchmark is named with its architecture.
+            if archs.len() > 1 {
+                name = format!("{}: {}", s.arch, name);
+            }
+            let row = rows
+                .entry(name.clone())
+                .or_insert_with(|| vec![None; runs.len()]);
+            if let Some(other) = row[i].filter(|other| other.engine != s.engine) {
+                bail!(
+                    "the run of {} measured {} with several engines ({} and {}); pick one with \
+                     --engine",
+                    datetime,
+                    name,
+                    other.engine,
+                    s.engine
+                );
+            }
+            row[i] = Some(s);
+        }
+    }
+
+    let mut table = vec![[
+        "benchmark".to_string(),
+        "trend".to_string(),
+        "last".to_string(),
+        "change".to_string(),
+        "range".to_string(),
+    ]];
+    for (name, means) in &rows {
+        let means: Vec<_> = means.iter().map(|s| s.map(|s| s.mean)).collect();
+        let present: Vec<f64> = means.iter().flatten().copied().collect();
+        let (first, last) = (present[0], present[present.len() - 1]);
+        let min = present.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = present.iter().copied().fold(0.0, f64::max);
+        table.push([
+            name.clone(),
+            sparkline(&means, min, max),
+            formatting.count(event, last),
+            if first == 0.0 {
+                "-".to_string()
+            } else {
+                format!("{:+.1}%", (last - first) / first * 100.0)
+            },
+            if min == 0.0 {
+                "-".to_string()
+            } else {
+                format!("{:.1}%", (max - min) / min * 100.0)
+            },
+        ]);
+    }
+
+    match (runs.first(), runs.last()) {
+        (Some((first, _)), Some((last, _))) => writeln!(
+            output_file,
+            "{} :: {}, {} runs from {} to {}",
+            phase,
+            event,
+            runs.len(),
+            first,
+            last
+        )?,
+        _ => writeln!(output_file, "{} :: {}, no runs", phase, event)?,
+    }
+    if rows.is_empty() {
+        return Ok(());
+    }
+    writeln!(output_file)?;
+    let widths: Vec<usize> = (0..5)
+        .map(|i| {
+            table
+                .iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap()
+        })
+        .collect();
+    for row in &table {
+        writeln!(
+            output_file,
+            "{:<w0$}   {:<w1$}   {:>w2$}   {:>w3$}   {:>w4$}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3],
+            w4 = widths[4],
+        )?;
+    }
+    Ok(())
+}
+
+/// Draw `means` as bars scaled from `min` to `max`, with a blank for each missing one.
+fn sparkline(means: &[Option<f64>], min: f64, max: f64) -> String {
+    means
+        .iter()
+        .map(|mean| match mean {
+            None => ' ',
+            Some(_) if max == min => BARS[0],
+            Some(mean) => {
+                let level = (mean - min) / (max - min) * (BARS.len() - 1) as f64;
+                BARS[level.round() as usize]
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(engine: &str, wasm: &str, mean: f64) -> Summary<'static> {
+        Summary {
+            arch: "x86_64".into(),
+            engine: engine.to_string().into(),
+            wasm: wasm.to_string().into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            min: 0,
+            max: 0,
+            median: 0,
+            mean,
+            mean_deviation: 0.0,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn write_sparklines() -> Result<()> {
+        let runs: Vec<(String, Vec<Summary>)> = [1000.0, 1000.0, 1050.0, 1250.0, 1300.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &mean)| {
+                let mut summaries = vec![summary("main.so", "bz2.wasm", mean)];
+                if i != 2 {
+                    summaries.push(summary("main.so", "noop.wasm", [11.0, 12.0][i % 2]));
+                }
+                (format!("2024-06-0{}", i + 1), summaries)
+            })
+            .collect();
+        let mut output = vec![];
+        write(
+            &runs,
+            Phase::Execution,
+            "cycles",
+            None,
+            Formatting::default(),
+            &mut output,
+        )?;
+        assert_eq!(
+            String::from_utf8(output)?,
+            "execution :: cycles, 5 runs from 2024-06-01 to 2024-06-05\n\
+             \n\
+             benchmark   trend     last   change   range\n\
+             bz2.wasm    ▁▁▂▇█   1.30 k   +30.0%   30.0%\n\
+             noop.wasm   ▁█ █▁       11    +0.0%    9.1%\n"
+        );
+
+        // Several engines in a run need choosing between.
+        let mut runs = runs;
+        runs[1].1.push(summary("pr.so", "bz2.wasm", 900.0));
+        assert!(write(
+            &runs,
+            Phase::Execution,
+            "cycles",
+            None,
+            Formatting::default(),
+            &mut vec![]
+        )
+        .is_err());
+        assert!(write(
+            &runs,
+            Phase::Execution,
+            "cycles",
+            Some("main"),
+            Formatting::default(),
+            &mut vec![]
+        )
+        .is_ok());
+        Ok(())
+    }
+}
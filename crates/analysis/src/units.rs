@@ -0,0 +1,173 @@
+//! Format counts for reports. Numbers are always written the same way, whatever the locale: with a
+//! `.` for a decimal point and no thousands separators, so that reports can be diffed and parsed.
+
+/// The significant digits reports write numbers with, unless given a `--precision`.
+pub const DEFAULT_PRECISION: usize = 3;
+
+/// How reports write numbers: in the unit of their event or exactly (`raw`), and to how many
+/// significant digits (`precision`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Formatting {
+    pub raw: bool,
+    pub precision: usize,
+}
+
+impl Default for Formatting {
+    fn default() -> Self {
+        Self {
+            raw: false,
+            precision: DEFAULT_PRECISION,
+        }
+    }
+}
+
+impl Formatting {
+    /// Format `value`, a count of `event` (or a statistic of its counts), for a report: in the
+    /// event's unit (see [Unit::format]) or, if `raw`, unscaled; either way, a whole number of the
+    /// event's unit is written as it is.
+    pub fn count(&self, event: &str, value: f64) -> String {
+        if self.raw {
+            exact(value, self.precision)
+        } else {
+            Unit::of(event).format(value, self.precision)
+        }
+    }
+
+    /// Format `value`, e.g., a speedup ratio, to the `precision`.
+    pub fn number(&self, value: f64) -> String {
+        significant(value, self.precision)
+    }
+}
+
+/// The unit of an event's counts, which decides how reports format them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    /// Wall time (e.g., `nanoseconds`), formatted as `ns`, `µs`, `ms`, or `s`.
+    Nanoseconds,
+    /// Energy (`microjoules`), formatted as `µJ`, `mJ`, or `J`.
+    Microjoules,
+    /// Sizes (e.g., `peak-rss-bytes`), formatted as `B`, `KiB`, `MiB`, or `GiB`.
+    Bytes,
+    /// Anything else (e.g., `cycles`, `instructions-retired`), formatted with SI suffixes: `k`,
+    /// `M`, `G`, or `T`.
+    Count,
+}
+
+impl Unit {
+    /// The unit of `event`.
+    pub fn of(event: &str) -> Self {
+        if event.starts_with("nanoseconds") {
+            Unit::Nanoseconds
+        } else if event == "microjoules" {
+            Unit::Microjoules
+        } else if event.split('-').any(|word| word == "bytes") {
+            Unit::Bytes
+        } else {
+            Unit::Count
+        }
+    }
+
+    /// Format `value` (e.g., a count or a mean) in this unit, scaled to the largest prefix it
+    /// reaches, to `precision` significant digits; a value too small to scale is written as it
+    /// would be exactly.
+    pub fn format(self, value: f64, precision: usize) -> String {
+        let (base, symbol, prefixes): (f64, &str, &[&str]) = match self {
+            Unit::Nanoseconds => (1000.0, "s", &["n", "µ", "m", ""]),
+            Unit::Microjoules => (1000.0, "J", &["µ", "m", ""]),
+            Unit::Bytes => (1024.0, "B", &["", "Ki", "Mi", "Gi", "Ti"]),
+            Unit::Count => (1000.0, "", &["", "k", "M", "G", "T"]),
+        };
+        let mut scaled = value;
+        let mut prefix = 0;
+        while scaled.abs() >= base && prefix + 1 < prefixes.len() {
+            scaled /= base;
+            prefix += 1;
+        }
+        let number = if prefix == 0 {
+            exact(scaled, precision)
+        } else {
+            significant(scaled, precision)
+        };
+        let unit = format!("{}{}", prefixes[prefix], symbol);
+        if unit.is_empty() {
+            number
+        } else {
+            format!("{} {}", number, unit)
+        }
+    }
+}
+
+/// Write `value` with `digits` significant digits, or with as many as it has before its decimal
+/// point, which are never rounded away; e.g., `1.23`, `0.0123`, or `1235` with three.
+pub fn significant(value: f64, digits: usize) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return format!("{:.*}", digits.saturating_sub(1), value);
+    }
+    let whole_digits = value.abs().log10().floor() as i64 + 1;
+    let decimals = (digits as i64 - whole_digits).max(0) as usize;
+    format!("{:.*}", decimals, value)
+}
+
+/// Like [significant], but a whole number (e.g., a count, rather than a mean) is written without
+/// decimals.
+fn exact(value: f64, digits: usize) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.0}", value)
+    } else {
+        significant(value, digits)
+    }
+}
+
+/// Round `value` to `digits` significant digits, as [significant] writes it; e.g., for
+/// machine-readable output given a `--precision`.
+pub fn round(value: f64, digits: usize) -> f64 {
+    significant(value, digits).parse().unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_in_units() {
+        let humanized = Formatting::default();
+        let raw = Formatting {
+            raw: true,
+            ..Formatting::default()
+        };
+        assert_eq!(humanized.count("cycles", 1234567890.0), "1.23 G");
+        assert_eq!(raw.count("cycles", 1234567890.0), "1234567890");
+        assert_eq!(humanized.count("cycles", 843.456), "843");
+        assert_eq!(raw.count("cycles", 12.3456), "12.3");
+        assert_eq!(humanized.count("instructions-retired", 45600.0), "45.6 k");
+        assert_eq!(humanized.count("nanoseconds", 1_500_000.0), "1.50 ms");
+        assert_eq!(humanized.count("nanoseconds", 250.0), "250 ns");
+        assert_eq!(humanized.count("nanoseconds", 3.2e10), "32.0 s");
+        assert_eq!(humanized.count("microjoules", 2500.0), "2.50 mJ");
+        assert_eq!(
+            humanized.count("peak-rss-bytes", 3.0 * 1024.0 * 1024.0),
+            "3.00 MiB"
+        );
+        assert_eq!(humanized.count("bytes-read", 512.0), "512 B");
+        assert_eq!(humanized.count("cycles", -2500.0), "-2.50 k");
+
+        let precise = Formatting {
+            precision: 5,
+            ..Formatting::default()
+        };
+        assert_eq!(precise.count("nanoseconds", 1_234_567.0), "1.2346 ms");
+        assert_eq!(precise.count("cycles", 7.0), "7");
+        assert_eq!(precise.number(1.05), "1.0500");
+    }
+
+    #[test]
+    fn significant_digits() {
+        assert_eq!(significant(1.23456, 3), "1.23");
+        assert_eq!(significant(0.0123456, 3), "0.0123");
+        assert_eq!(significant(1234.56, 3), "1235");
+        assert_eq!(significant(-12.345, 2), "-12");
+        assert_eq!(significant(0.0, 3), "0.00");
+        assert_eq!(round(1234.5678, 6), 1234.57);
+        assert_eq!(round(0.000123456, 2), 0.00012);
+    }
+}
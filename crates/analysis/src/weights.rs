@@ -0,0 +1,81 @@
+//! Weigh benchmarks in the aggregates of a whole suite, so that, e.g., production-representative
+//! workloads count for more than toy microbenchmarks.
+
+use anyhow::{Context, Result};
+use std::{collections::BTreeMap, io::Read, path::Path};
+
+/// The weight of each benchmark in a suite-level aggregate (e.g., [crate::bayes::overall]). A
+/// benchmark is named by its Wasm file, as in the results (e.g., `benchmarks/bz2/benchmark.wasm`),
+/// or by the directory of its Wasm file (e.g., `bz2`); those not named weigh 1, and those that
+/// weigh 0 are left out.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Weights(BTreeMap<String, f64>);
+
+impl Weights {
+    /// Read the weights from a JSON object of benchmark names and weights:
+    ///
+    /// ```json
+    /// { "spidermonkey": 10, "bz2": 2, "benchmarks/noop/benchmark.wasm": 0 }
+    /// ```
+    pub fn read(reader: impl Read) -> Result<Self> {
+        let weights: BTreeMap<String, f64> = serde_json::from_reader(reader)?;
+        for (benchmark, &weight) in &weights {
+            anyhow::ensure!(
+                weight.is_finite() && weight >= 0.0,
+                "the weight of {} must be a number of at least 0; found {}",
+                benchmark,
+                weight
+            );
+        }
+        Ok(Self(weights))
+    }
+
+    /// Read the weights from the file at `path`; see [Weights::read].
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        Self::read(std::io::BufReader::new(file))
+            .with_context(|| format!("failed to read the weights in {}", path.display()))
+    }
+
+    /// The weight of the benchmark whose Wasm file is `wasm`.
+    pub fn of(&self, wasm: &str) -> f64 {
+        if let Some(&weight) = self.0.get(wasm) {
+            return weight;
+        }
+        let directory = Path::new(wasm)
+            .parent()
+            .and_then(|parent| parent.file_name())
+            .and_then(|name| name.to_str());
+        directory
+            .and_then(|directory| self.0.get(directory))
+            .copied()
+            .unwrap_or(1.0)
+    }
+
+    /// Whether every benchmark weighs the same.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weigh_benchmarks() -> Result<()> {
+        let weights = Weights::read(
+            r#"{"bz2": 2, "benchmarks/noop/benchmark.wasm": 0, "noop": 5}"#.as_bytes(),
+        )?;
+        assert_eq!(weights.of("benchmarks/bz2/benchmark.wasm"), 2.0);
+        assert_eq!(weights.of("benchmarks/noop/benchmark.wasm"), 0.0);
+        assert_eq!(weights.of("benchmarks/fib/benchmark.wasm"), 1.0);
+        assert_eq!(weights.of("bz2.wasm"), 1.0);
+        assert!(Weights::default().is_empty());
+
+        assert!(Weights::read(r#"{"bz2": -1}"#.as_bytes()).is_err());
+        assert!(Weights::read(r#"{"bz2": "heavy"}"#.as_bytes()).is_err());
+        Ok(())
+    }
+}
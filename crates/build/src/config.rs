@@ -0,0 +1,323 @@
+//! Describe a benchmark with a `benchmark.toml` next to its Wasm file: how to select it, how to
+//! check its output, and how to run it. Every field is optional, as is the file itself:
+//!
+//! ```toml
+//! tags = ["compile-heavy", "wasi"]
+//! stdout-blake3 = "5d6f..."
+//! engine-flags = "-W simd=y"
+//! inputs = ["default.input"]
+//! timeout = 60
+//! notes = ["Reads its input through WASI, so its I/O phase is noisy."]
+//!
+//! [input-sizes]
+//! small = "inputs/small"
+//! large = "inputs/large"
+//!
+//! [[fetch]]
+//! file = "benchmark.wasm"
+//! url = "oci://ghcr.io/example/benchmarks/spidermonkey:v1"
+//! sha256 = "9f86..."
+//! ```
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// The name of the file describing the benchmarks in a directory.
+pub const BENCHMARK_CONFIG: &str = "benchmark.toml";
+
+/// The input size of a benchmark's own directory, which every benchmark has.
+pub const DEFAULT_INPUT_SIZE: &str = "default";
+
+/// The contents of a benchmark's `benchmark.toml`; see the [module documentation](self).
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BenchmarkConfig {
+    /// Tags for selecting the benchmark (see `sightglass-cli benchmark --tags`); e.g.,
+    /// `compile-heavy`, `simd`, or `wasi`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The BLAKE3 hash, in hexadecimal, of the benchmark's `stdout` when executed once.
+    pub stdout_blake3: Option<String>,
+
+    /// The engine flags to run the benchmark with when none are given on the command line.
+    pub engine_flags: Option<String>,
+
+    /// The files, relative to the benchmark's directory, that the benchmark reads.
+    #[serde(default)]
+    pub inputs: Vec<String>,
+
+    /// The most seconds that one iteration of the benchmark may take before its process is killed.
+    pub timeout: Option<u64>,
+
+    /// Free-form notes about the benchmark, recorded in the manifest of each run so that they are
+    /// at hand when its results are reviewed; e.g., why its measurements vary.
+    #[serde(default)]
+    pub notes: Vec<String>,
+
+    /// The benchmark's other input sizes (e.g., `small` and `large`; see `sightglass-cli benchmark
+    /// --input-size`), each a directory, relative to the benchmark's directory, to run the
+    /// benchmark in instead. Such a directory holds the same input files as the benchmark's
+    /// directory and, optionally, its own `stdout.expected` and `stderr.expected`.
+    #[serde(default)]
+    pub input_sizes: BTreeMap<String, PathBuf>,
+
+    /// The files, e.g., the Wasm file of a benchmark too large to keep in the repository, that
+    /// `sightglass-cli fetch` downloads into the benchmark's directory.
+    #[serde(default)]
+    pub fetch: Vec<FetchedFile>,
+}
+
+/// A file to download into a benchmark's directory.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FetchedFile {
+    /// Where to save the file, relative to the benchmark's directory.
+    pub file: PathBuf,
+
+    /// Where to download the file from: an `https://` URL, or an `oci://<registry>/<repository>:<tag>`
+    /// (or `@<digest>`) artifact, from which the layer titled with the file's name is downloaded.
+    pub url: String,
+
+    /// The SHA-256 digest, in hexadecimal, that the downloaded file must have.
+    pub sha256: String,
+}
+
+impl FetchedFile {
+    /// Check that the file would be saved within the benchmark's directory: its path is relative,
+    /// does not climb out with `..`, and names a file rather than, e.g., the directory itself (as
+    /// `""` or `.` would).
+    pub fn check_path(&self) -> Result<()> {
+        let within = self
+            .file
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+        let named = matches!(
+            self.file.components().next_back(),
+            Some(Component::Normal(_))
+        );
+        if !within || !named {
+            bail!(
+                "fetched files must be files within the benchmark's directory: `{}`",
+                self.file.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl BenchmarkConfig {
+    /// Read the `benchmark.toml` in the directory of the Wasm file at `wasm_file`, or the default
+    /// configuration if there is none.
+    pub fn read(wasm_file: &Path) -> Result<Self> {
+        let path = wasm_file.with_file_name(BENCHMARK_CONFIG);
+        let config = Self::parse(&path)?;
+        let dir = wasm_file.parent().unwrap_or_else(|| Path::new("."));
+        if !wasm_file.exists() && config.fetch.iter().any(|f| dir.join(&f.file) == wasm_file) {
+            bail!(
+                "missing {}; fetch it with `sightglass-cli fetch {}`",
+                wasm_file.display(),
+                dir.display()
+            );
+        }
+        config
+            .validate(dir)
+            .with_context(|| format!("invalid {}", path.display()))?;
+        Ok(config)
+    }
+
+    /// Parse the `benchmark.toml` at `path`, or return the default configuration if there is
+    /// none, without checking that its files exist (e.g., before they are fetched).
+    pub fn parse(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        toml::from_str(&contents).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    /// Check that the configuration is usable for the benchmark in `dir`: its input files exist
+    /// and its checksums are BLAKE3 and SHA-256 hashes.
+    pub fn validate(&self, dir: &Path) -> Result<()> {
+        for input in &self.inputs {
+            if !dir.join(input).is_file() {
+                if self.fetch.iter().any(|f| f.file == Path::new(input)) {
+                    bail!(
+                        "missing input file: {}; fetch it with `sightglass-cli fetch {}`",
+                        dir.join(input).display(),
+                        dir.display()
+                    );
+                }
+                bail!("missing input file: {}", dir.join(input).display());
+            }
+        }
+        for fetched in &self.fetch {
+            if !is_hex_digest(&fetched.sha256, 64) {
+                bail!(
+                    "sha256 is not a hexadecimal SHA-256 digest: {}",
+                    fetched.sha256
+                );
+            }
+            fetched.check_path()?;
+        }
+        if let Some(checksum) = &self.stdout_blake3 {
+            if !is_hex_digest(checksum, 64) {
+                bail!(
+                    "stdout-blake3 is not a hexadecimal BLAKE3 hash: {}",
+                    checksum
+                );
+            }
+        }
+        if self.timeout == Some(0) {
+            bail!("timeout must be greater than zero");
+        }
+        for (size, size_dir) in &self.input_sizes {
+            if size == DEFAULT_INPUT_SIZE {
+                bail!("the `{}` input size is the benchmark's directory", size);
+            }
+            if !dir.join(size_dir).is_dir() {
+                bail!(
+                    "missing {} input directory: {}",
+                    size,
+                    dir.join(size_dir).display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The directory, relative to the benchmark's, holding the benchmark's inputs of the given size:
+    /// `Some("")` for the default size and `None` if the benchmark does not have the size.
+    pub fn input_dir(&self, input_size: &str) -> Option<&Path> {
+        if input_size == DEFAULT_INPUT_SIZE {
+            Some(Path::new(""))
+        } else {
+            self.input_sizes.get(input_size).map(PathBuf::as_path)
+        }
+    }
+
+    /// Check whether the benchmark has any of the `tags`.
+    pub fn has_any_tag(&self, tags: &[String]) -> bool {
+        self.tags.iter().any(|tag| tags.contains(tag))
+    }
+
+    /// Check a benchmark's `stdout`, as written by one execution, against its expected checksum,
+    /// if it has one.
+    pub fn check_stdout(&self, stdout: &[u8]) -> Result<()> {
+        if let Some(expected) = &self.stdout_blake3 {
+            let actual = blake3::hash(stdout).to_hex();
+            if !actual.eq_ignore_ascii_case(expected) {
+                bail!(
+                    "the benchmark's stdout has BLAKE3 hash {} rather than the expected {}",
+                    actual,
+                    expected
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_hex_digest(digest: &str, len: usize) -> bool {
+    digest.len() == len && digest.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        let config: BenchmarkConfig = toml::from_str(
+            r#"
+            tags = ["simd", "wasi"]
+            engine-flags = "-W simd=y"
+            inputs = ["default.input"]
+            timeout = 60
+            notes = ["noisy I/O"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.tags, ["simd", "wasi"]);
+        assert_eq!(config.engine_flags.as_deref(), Some("-W simd=y"));
+        assert_eq!(config.timeout, Some(60));
+        assert_eq!(config.notes, ["noisy I/O"]);
+        assert!(config.has_any_tag(&["simd".to_string()]));
+        assert!(!config.has_any_tag(&["compile-heavy".to_string()]));
+
+        assert!(toml::from_str::<BenchmarkConfig>("tag = [\"simd\"]").is_err());
+
+        let config: BenchmarkConfig = toml::from_str("[input-sizes]\nlarge = \"large\"").unwrap();
+        assert_eq!(config.input_dir("large"), Some(Path::new("large")));
+        assert_eq!(config.input_dir("default"), Some(Path::new("")));
+        assert_eq!(config.input_dir("small"), None);
+        assert_eq!(
+            toml::from_str::<BenchmarkConfig>("").unwrap(),
+            BenchmarkConfig::default()
+        );
+    }
+
+    #[test]
+    fn validate_config() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let config = BenchmarkConfig {
+            inputs: vec!["Cargo.toml".into()],
+            stdout_blake3: Some(blake3::hash(b"").to_hex().to_string()),
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_ok());
+        assert!(config.check_stdout(b"").is_ok());
+        assert!(config.check_stdout(b"unexpected").is_err());
+
+        let config = BenchmarkConfig {
+            inputs: vec!["missing.input".into()],
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_err());
+        let config = BenchmarkConfig {
+            stdout_blake3: Some("abc".into()),
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_err());
+        let config = BenchmarkConfig {
+            input_sizes: [("large".to_string(), PathBuf::from("src"))].into(),
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_ok());
+        let config = BenchmarkConfig {
+            input_sizes: [("large".to_string(), PathBuf::from("missing"))].into(),
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_err());
+
+        let fetched = FetchedFile {
+            file: "benchmark.wasm".into(),
+            url: "https://example.com/benchmark.wasm".into(),
+            sha256: "a".repeat(64),
+        };
+        let config = BenchmarkConfig {
+            fetch: vec![fetched.clone()],
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_ok());
+        let config = BenchmarkConfig {
+            fetch: vec![FetchedFile {
+                file: "../benchmark.wasm".into(),
+                ..fetched.clone()
+            }],
+            ..Default::default()
+        };
+        assert!(config.validate(dir).is_err());
+        let config = BenchmarkConfig {
+            inputs: vec!["benchmark.wasm".into()],
+            fetch: vec![fetched],
+            ..Default::default()
+        };
+        let error = config.validate(dir).unwrap_err().to_string();
+        assert!(error.contains("sightglass-cli fetch"), "{}", error);
+    }
+}
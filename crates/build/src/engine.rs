@@ -0,0 +1,176 @@
+//! Cache engine libraries that Sightglass fetches or builds, so that an engine is only retrieved
+//! once per revision. Each engine lives in its own directory, named by the engine and its revision
+//! (e.g., `wasmtime-<commit hash>`), next to a `.build-info` file like the ones written by the
+//! `engines/*/build.rs` scripts. `benchmarks/build.sh` caches the benchmarks it builds here too,
+//! under `benchmarks/`.
+
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The environment variable that overrides where Sightglass caches its artifacts.
+pub const CACHE_DIR_ENV: &str = "SIGHTGLASS_CACHE_DIR";
+
+/// The directory in which Sightglass caches its artifacts: `$SIGHTGLASS_CACHE_DIR` if set, or
+/// otherwise the user's cache directory (e.g., `~/.cache/sightglass`).
+pub fn cache_dir() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+        return Ok(PathBuf::from(dir));
+    }
+    let dir = dirs::cache_dir().context("unable to find the user's cache directory")?;
+    Ok(dir.join("sightglass"))
+}
+
+/// The directory in which to cache the engine `name` at `revision`; e.g.,
+/// `~/.cache/sightglass/engines/wasmtime-<commit hash>`.
+pub fn engine_dir(name: &str, revision: &str) -> Result<PathBuf> {
+    Ok(cache_dir()?
+        .join("engines")
+        .join(format!("{}-{}", name, revision)))
+}
+
+/// The path of the engine library cached for the engine `name` at `revision`, if it is already
+/// there.
+pub fn cached_engine(name: &str, revision: &str) -> Result<Option<PathBuf>> {
+    let library = engine_dir(name, revision)?.join(crate::get_engine_filename());
+    Ok(if library.is_file() {
+        Some(library)
+    } else {
+        None
+    })
+}
+
+/// The repository from which to build Wasmtime by default.
+pub const WASMTIME_REPOSITORY: &str = "https://github.com/bytecodealliance/wasmtime";
+
+/// Parse an engine built from source, like `wasmtime:main` or `wasmtime:<commit hash>`, into its
+/// revision; any other engine (e.g., a path to a library) is `None`.
+pub fn parse_wasmtime_revision(engine: &str) -> Option<&str> {
+    engine
+        .strip_prefix("wasmtime:")
+        .filter(|revision| !revision.is_empty())
+}
+
+/// Build Wasmtime's bench API library at `revision` (a branch, tag, or full commit hash) of
+/// `repository`, returning the path to the library. The library is cached by the commit that
+/// `revision` resolves to, so a commit is only built once; the source checkout is cached as well,
+/// so that building a new commit is incremental.
+pub fn build_wasmtime(repository: &str, revision: &str) -> Result<PathBuf> {
+    let source_dir = cache_dir()?.join("wasmtime");
+    if !source_dir.join(".git").exists() {
+        fs::create_dir_all(&source_dir)
+            .with_context(|| format!("failed to create {}", source_dir.display()))?;
+        exec(&["git", "init", "--quiet"], &source_dir)?;
+    }
+
+    // Fetching the revision alone is more space-efficient (and thus faster) than cloning the entire
+    // repository; it also resolves the revision to its commit.
+    exec(
+        &[
+            "git", "fetch", "--quiet", "--depth", "1", repository, revision,
+        ],
+        &source_dir,
+    )?;
+    let commit = exec(&["git", "rev-parse", "FETCH_HEAD"], &source_dir)?;
+    if let Some(library) = cached_engine("wasmtime", &commit)? {
+        log::info!("Using cached Wasmtime {}: {}", commit, library.display());
+        return Ok(library);
+    }
+
+    log::info!(
+        "Building Wasmtime {} ({}); this may take a few minutes",
+        revision,
+        commit
+    );
+    exec(
+        &["git", "checkout", "--quiet", "--force", &commit],
+        &source_dir,
+    )?;
+    exec(
+        &["git", "submodule", "update", "--init", "--depth", "1"],
+        &source_dir,
+    )?;
+    exec(
+        &["cargo", "build", "--release", "-p", "wasmtime-bench-api"],
+        &source_dir,
+    )?;
+
+    // Record what was built, like `engines/wasmtime/build.rs` does, and copy the library into the
+    // cache last so that a failed build is never mistaken for a cached engine.
+    let datetime = exec(
+        &["git", "show", "--no-patch", "--no-notes", "--pretty=%cI"],
+        &source_dir,
+    )?;
+    let cargo = exec(&["cargo", "--version"], &source_dir)?;
+    let rustc = exec(&["rustc", "--version"], &source_dir)?;
+    let engine_dir = engine_dir("wasmtime", &commit)?;
+    fs::create_dir_all(&engine_dir)
+        .with_context(|| format!("failed to create {}", engine_dir.display()))?;
+    let mut build_info = fs::File::create(engine_dir.join(".build-info"))?;
+    writeln!(build_info, "NAME=wasmtime")?;
+    writeln!(build_info, "REPOSITORY={}", repository)?;
+    writeln!(build_info, "REVISION={}", revision)?;
+    writeln!(build_info, "_COMMIT={}", commit)?;
+    writeln!(build_info, "_COMMIT_DATETIME={}", datetime)?;
+    writeln!(build_info, "_CARGO={}", cargo)?;
+    writeln!(build_info, "_RUSTC={}", rustc)?;
+
+    let built = source_dir.join("target/release").join(format!(
+        "{}wasmtime_bench_api{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    ));
+    let library = engine_dir.join(crate::get_engine_filename());
+    fs::copy(&built, &library).with_context(|| {
+        format!(
+            "failed to copy {} to {}",
+            built.display(),
+            library.display()
+        )
+    })?;
+    log::info!("Built Wasmtime {}: {}", commit, library.display());
+    Ok(library)
+}
+
+/// Run a command in `dir`, failing unless it succeeds, and return its trimmed `stdout`.
+fn exec(command: &[&str], dir: &Path) -> Result<String> {
+    log::debug!("> {}", command.join(" "));
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .current_dir(dir)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run `{}`", command[0]))?;
+    if !output.status.success() {
+        bail!("`{}` failed with {}", command.join(" "), output.status);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Remove all of the cached artifacts.
+pub fn clean() -> Result<()> {
+    let dir = cache_dir()?;
+    if dir.exists() {
+        log::info!("Removing cache directory: {}", dir.display());
+        fs::remove_dir_all(&dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_revisions() {
+        assert_eq!(parse_wasmtime_revision("wasmtime:main"), Some("main"));
+        assert_eq!(parse_wasmtime_revision("wasmtime:abc123"), Some("abc123"));
+        assert_eq!(parse_wasmtime_revision("wasmtime:"), None);
+        assert_eq!(
+            parse_wasmtime_revision("engines/wasmtime/libengine.so"),
+            None
+        );
+    }
+}
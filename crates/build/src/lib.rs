@@ -1,6 +1,15 @@
+mod config;
+mod engine;
+mod proposals;
 mod wasm;
 
-pub use wasm::WasmBenchmark;
+pub use config::{BenchmarkConfig, FetchedFile, BENCHMARK_CONFIG, DEFAULT_INPUT_SIZE};
+pub use engine::{
+    build_wasmtime, cache_dir, cached_engine, clean, engine_dir, parse_wasmtime_revision,
+    CACHE_DIR_ENV, WASMTIME_REPOSITORY,
+};
+pub use proposals::{required_proposals, Proposal};
+pub use wasm::{entry_points, is_component, read_variant, BuildVariant, WasmBenchmark};
 
 /// Calculate the library name for a sightglass library on the target operating system: e.g.
 /// `engine.dll`, `libengine.so`.
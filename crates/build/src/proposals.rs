@@ -0,0 +1,157 @@
+//! Detect which WebAssembly proposals a benchmark requires, so that a benchmark can be skipped by
+//! engines (or engine flags) that cannot run it rather than failing part way through a suite.
+
+use anyhow::Result;
+use std::fmt;
+use std::str::FromStr;
+use wasmparser::{Validator, WasmFeatures};
+
+/// A WebAssembly proposal that a benchmark may require. The features that every engine is expected
+/// to support (e.g., bulk memory, reference types, and multi-value) are not listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Proposal {
+    /// 128-bit SIMD.
+    Simd,
+    /// Relaxed SIMD.
+    RelaxedSimd,
+    /// Threads: shared memories and atomics.
+    Threads,
+    /// Tail calls.
+    TailCall,
+    /// 64-bit memories.
+    Memory64,
+    /// Multiple memories.
+    MultiMemory,
+    /// Exception handling.
+    Exceptions,
+    /// Extended constant expressions.
+    ExtendedConst,
+    /// Garbage collection: struct and array types and typed references.
+    Gc,
+}
+
+impl Proposal {
+    /// All of the proposals that are detected.
+    pub const ALL: [Proposal; 9] = [
+        Proposal::Simd,
+        Proposal::RelaxedSimd,
+        Proposal::Threads,
+        Proposal::TailCall,
+        Proposal::Memory64,
+        Proposal::MultiMemory,
+        Proposal::Exceptions,
+        Proposal::ExtendedConst,
+        Proposal::Gc,
+    ];
+
+    /// The validator's feature for this proposal.
+    fn feature(self) -> WasmFeatures {
+        match self {
+            Proposal::Simd => WasmFeatures::SIMD,
+            Proposal::RelaxedSimd => WasmFeatures::RELAXED_SIMD,
+            Proposal::Threads => WasmFeatures::THREADS,
+            Proposal::TailCall => WasmFeatures::TAIL_CALL,
+            Proposal::Memory64 => WasmFeatures::MEMORY64,
+            Proposal::MultiMemory => WasmFeatures::MULTI_MEMORY,
+            Proposal::Exceptions => WasmFeatures::EXCEPTIONS,
+            Proposal::ExtendedConst => WasmFeatures::EXTENDED_CONST,
+            Proposal::Gc => WasmFeatures::GC,
+        }
+    }
+}
+
+impl fmt::Display for Proposal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Proposal::Simd => write!(f, "simd"),
+            Proposal::RelaxedSimd => write!(f, "relaxed-simd"),
+            Proposal::Threads => write!(f, "threads"),
+            Proposal::TailCall => write!(f, "tail-call"),
+            Proposal::Memory64 => write!(f, "memory64"),
+            Proposal::MultiMemory => write!(f, "multi-memory"),
+            Proposal::Exceptions => write!(f, "exceptions"),
+            Proposal::ExtendedConst => write!(f, "extended-const"),
+            Proposal::Gc => write!(f, "gc"),
+        }
+    }
+}
+
+impl FromStr for Proposal {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Proposal::ALL
+            .iter()
+            .copied()
+            .find(|p| p.to_string() == s)
+            .ok_or_else(|| format!("unknown proposal: {}", s))
+    }
+}
+
+/// List the proposals that the Wasm `bytes` require, by validating them with each proposal
+/// disabled in turn. A component is not checked; the engine checks it when compiling it.
+pub fn required_proposals(bytes: &[u8]) -> Result<Vec<Proposal>> {
+    if crate::is_component(bytes) {
+        return Ok(vec![]);
+    }
+    let validate = |disabled: Option<Proposal>| {
+        let mut features = WasmFeatures::default();
+        for proposal in Proposal::ALL {
+            features.set(proposal.feature(), Some(proposal) != disabled);
+        }
+        Validator::new_with_features(features).validate_all(bytes)
+    };
+
+    validate(None)?;
+    Ok(Proposal::ALL
+        .iter()
+        .copied()
+        .filter(|&proposal| validate(Some(proposal)).is_err())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_proposals() {
+        let mvp = wat::parse_str(r#"(module (func (export "_start")))"#).unwrap();
+        assert!(required_proposals(&mvp).unwrap().is_empty());
+
+        let simd =
+            wat::parse_str(r#"(module (func (result v128) (v128.const i64x2 0 0)))"#).unwrap();
+        assert_eq!(required_proposals(&simd).unwrap(), [Proposal::Simd]);
+
+        let tail_calls = wat::parse_str(r#"(module (func $f (return_call $f)))"#).unwrap();
+        assert_eq!(
+            required_proposals(&tail_calls).unwrap(),
+            [Proposal::TailCall]
+        );
+
+        let memory64 = wat::parse_str(r#"(module (memory i64 1))"#).unwrap();
+        assert_eq!(required_proposals(&memory64).unwrap(), [Proposal::Memory64]);
+
+        let gc = wat::parse_str(r#"(module (type (struct)))"#).unwrap();
+        assert_eq!(required_proposals(&gc).unwrap(), [Proposal::Gc]);
+
+        // GC does not hide the other proposals a benchmark requires.
+        let gc_simd = wat::parse_str(
+            r#"(module (type $s (struct (field v128))) (func (result (ref null $s)) (ref.null $s)))"#,
+        )
+        .unwrap();
+        assert_eq!(
+            required_proposals(&gc_simd).unwrap(),
+            [Proposal::Simd, Proposal::Gc]
+        );
+
+        assert!(required_proposals(b"\0asm\x01\x00\x00\x00\x01").is_err());
+    }
+
+    #[test]
+    fn name_proposals() {
+        for proposal in Proposal::ALL {
+            assert_eq!(proposal.to_string().parse::<Proposal>(), Ok(proposal));
+        }
+        assert!("simd128".parse::<Proposal>().is_err());
+    }
+}
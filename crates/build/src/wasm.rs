@@ -11,7 +11,7 @@ use std::{
     path::{Path, PathBuf},
 };
 use thiserror::Error;
-use wasmparser::{Import, Payload, TypeRef};
+use wasmparser::{ExternalKind, Import, Payload, TypeRef};
 use wasmprinter;
 
 pub struct WasmBenchmark(PathBuf);
@@ -41,11 +41,14 @@ impl WasmBenchmark {
             }
         };
 
-        // Check that it contains valid Wasm.
-        let mut features = wasmparser::WasmFeatures::default();
-        features.simd = true;
-        let mut validator = wasmparser::Validator::new_with_features(features);
-        if let Err(_) = validator.validate_all(&bytes) {
+        // The validator predates the component binary format, so a component is only checked for
+        // its header; the engine checks the rest when compiling it.
+        if is_component(&bytes) {
+            return Ok(());
+        }
+
+        // Check that it contains valid Wasm, using any of the proposals an engine may support.
+        if crate::required_proposals(&bytes).is_err() {
             return ValidationErrorKind::InvalidWasm.with(&self);
         }
 
@@ -60,6 +63,13 @@ impl WasmBenchmark {
         Ok(())
     }
 
+    /// List the benchmark's entry points: the functions it exports with a `bench_` prefix (e.g.,
+    /// `bench_parse`). A benchmark with entry points is run once for each of them, calling the
+    /// entry point rather than `_start`; a benchmark without any is run through `_start`.
+    pub fn entry_points(&self) -> Result<Vec<String>> {
+        entry_points(&fs::read(&self.0)?)
+    }
+
     /// Emit the WebAssembly Text (WAT) version of the Wasm benchmark. This will calculate a path to
     /// write to by replacing the benchmark's `.wasm` extension with `.wat`. On success, this will
     /// return the path to the written WAT.
@@ -137,7 +147,7 @@ fn has_import_function(bytes: &[u8], expected_module: &str, expected_field: &str
     for payload in parser.parse_all(&bytes) {
         match payload? {
             Payload::ImportSection(imports) => {
-                for import in imports {
+                for import in imports.into_imports() {
                     match import? {
                         Import {
                             module,
@@ -157,3 +167,125 @@ fn has_import_function(bytes: &[u8], expected_module: &str, expected_field: &str
     }
     Ok(false)
 }
+
+/// Check whether the Wasm `bytes` are a component rather than a core module: both start with the
+/// `\0asm` magic number, but a component's header has a layer of 1 where a module's has 0.
+pub fn is_component(bytes: &[u8]) -> bool {
+    bytes.len() >= 8 && bytes[..4] == *b"\0asm" && bytes[6..8] == [1, 0]
+}
+
+/// List the functions exported by the Wasm `bytes` whose names start with `bench_`; see
+/// [WasmBenchmark::entry_points]. A component has no entry points; it is run through its
+/// `wasi:cli/run` export.
+pub fn entry_points(bytes: &[u8]) -> Result<Vec<String>> {
+    let mut entry_points = vec![];
+    if is_component(bytes) {
+        return Ok(entry_points);
+    }
+    for payload in wasmparser::Parser::new(0).parse_all(bytes) {
+        if let Payload::ExportSection(exports) = payload? {
+            for export in exports {
+                let export = export?;
+                if export.kind == ExternalKind::Func && export.name.starts_with("bench_") {
+                    entry_points.push(export.name.to_string());
+                }
+            }
+        }
+    }
+    Ok(entry_points)
+}
+
+/// How a variant of a benchmark was built; see [read_variant].
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildVariant {
+    /// The name of the variant; e.g., `simd`.
+    pub name: String,
+    /// The extra compiler flags the variant was built with; e.g., `-msimd128`.
+    pub flags: String,
+    /// The `wasm-opt` passes the variant was post-processed with, if any; e.g., `-O3`.
+    pub wasm_opt: Option<String>,
+}
+
+/// Read the variant that the Wasm file at `path` was built as, if any: `benchmarks/build.sh
+/// --variant <name>=<flags>` builds `benchmark-<name>.wasm` with the extra compiler `flags`,
+/// recording both in `benchmark-<name>.variant` next to it, as `NAME=<name>` and `FLAGS=<flags>`
+/// lines, and `--wasm-opt <name>=<passes>` adds a `WASM_OPT=<passes>` line for a benchmark it
+/// post-processed with `wasm-opt`.
+pub fn read_variant(path: &Path) -> Result<Option<BuildVariant>> {
+    let variant = path.with_extension("variant");
+    if !variant.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(&variant)?;
+    parse_variant(&contents)
+        .map(Some)
+        .ok_or_else(|| anyhow::anyhow!("{} has no NAME", variant.display()))
+}
+
+fn parse_variant(contents: &str) -> Option<BuildVariant> {
+    let value = |key: &str| {
+        contents
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .find(|(k, _)| k.trim() == key)
+            .map(|(_, v)| v.trim().to_string())
+    };
+    Some(BuildVariant {
+        name: value("NAME")?,
+        flags: value("FLAGS").unwrap_or_default(),
+        wasm_opt: value("WASM_OPT"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_variants() {
+        assert_eq!(
+            parse_variant("NAME=simd\nFLAGS=-msimd128 -O2\n"),
+            Some(BuildVariant {
+                name: "simd".to_string(),
+                flags: "-msimd128 -O2".to_string(),
+                wasm_opt: None,
+            })
+        );
+        assert_eq!(
+            parse_variant("NAME=plain\n"),
+            Some(BuildVariant {
+                name: "plain".to_string(),
+                flags: String::new(),
+                wasm_opt: None,
+            })
+        );
+        assert_eq!(
+            parse_variant("NAME=o3\nFLAGS=\nWASM_OPT=-O3 --converge\n").and_then(|v| v.wasm_opt),
+            Some("-O3 --converge".to_string())
+        );
+        assert_eq!(parse_variant("FLAGS=-O1\n"), None);
+    }
+
+    #[test]
+    fn find_entry_points() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (func (export "_initialize"))
+                (func (export "bench_parse"))
+                (func (export "bench_print"))
+                (global (export "bench_global") i32 (i32.const 0)))"#,
+        )
+        .unwrap();
+        assert_eq!(entry_points(&wasm).unwrap(), ["bench_parse", "bench_print"]);
+    }
+
+    #[test]
+    fn detect_components() {
+        let module = wat::parse_str("(module)").unwrap();
+        assert!(!is_component(&module));
+        let component = b"\0asm\x0d\x00\x01\x00";
+        assert!(is_component(component));
+        assert!(entry_points(component).unwrap().is_empty());
+        assert!(!is_component(b"\0asm"));
+    }
+}
@@ -0,0 +1,342 @@
+// The Sightglass dashboard: browse the results that `sightglass-cli serve` serves, through its API.
+"use strict";
+
+const COLORS = ["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#b07aa1", "#9c755f"];
+const SVG = "http://www.w3.org/2000/svg";
+
+let runs = [];
+let trends = { runs: [], summaries: [] };
+let effectSizes = [];
+const measurementsByRun = new Map();
+
+const $ = (id) => document.getElementById(id);
+
+async function get(path) {
+  const response = await fetch(path);
+  const body = await response.json();
+  if (!response.ok) {
+    throw new Error(body.error);
+  }
+  return body;
+}
+
+function status(text) {
+  $("status").textContent = text;
+}
+
+// Report the errors of an event handler in the header.
+function guarded(handler) {
+  return () => handler().catch((e) => status(`Error: ${e.message}`));
+}
+
+// Name a benchmark as the CLI does; e.g., `benchmarks/bz2/benchmark.wasm (4 threads)`.
+function benchmark(result) {
+  const parts = [];
+  if (result.input_size != null) {
+    parts.push(`${result.input_size} input`);
+  }
+  if (result.threads != null) {
+    parts.push(result.threads === 1 ? "1 thread" : `${result.threads} threads`);
+  }
+  return parts.length ? `${result.wasm} (${parts.join(", ")})` : result.wasm;
+}
+
+function format(n) {
+  const units = [[1e12, "T"], [1e9, "G"], [1e6, "M"], [1e3, "k"]];
+  for (const [scale, unit] of units) {
+    if (Math.abs(n) >= scale) {
+      return `${(n / scale).toFixed(2)}${unit}`;
+    }
+  }
+  return n.toFixed(Number.isInteger(n) ? 0 : 2);
+}
+
+function element(tag, attributes = {}, text = "") {
+  const e = document.createElement(tag);
+  Object.entries(attributes).forEach(([k, v]) => e.setAttribute(k, v));
+  e.textContent = text;
+  return e;
+}
+
+function svg(tag, attributes, parent) {
+  const e = document.createElementNS(SVG, tag);
+  Object.entries(attributes).forEach(([k, v]) => e.setAttribute(k, v));
+  parent.appendChild(e);
+  return e;
+}
+
+function fillSelect(select, values, selected) {
+  select.replaceChildren(...values.map((v) => element("option", { value: v }, v)));
+  if (values.includes(selected)) {
+    select.value = selected;
+  }
+}
+
+// A chart: an SVG of `width` by `height` with axes for the `y` range and a legend for `series`.
+function chart(container, title, width, height, [yMin, yMax], series) {
+  const div = element("div", { class: "chart" });
+  div.appendChild(element("h3", {}, title));
+  const plot = svg("svg", { viewBox: `0 0 ${width} ${height}` }, div);
+  const legend = element("div", { class: "legend" });
+  series.forEach((name, i) => {
+    const span = element("span", {}, `■ ${name}`);
+    span.style.color = COLORS[i % COLORS.length];
+    legend.appendChild(span);
+  });
+  div.appendChild(legend);
+  container.appendChild(div);
+
+  const margin = { left: 50, right: 10, top: 10, bottom: 20 };
+  if (yMin === yMax) {
+    yMin -= Math.abs(yMin) * 0.1 || 1;
+    yMax += Math.abs(yMax) * 0.1 || 1;
+  }
+  const y = (v) =>
+    margin.top + (1 - (v - yMin) / (yMax - yMin)) * (height - margin.top - margin.bottom);
+  for (const v of [yMin, (yMin + yMax) / 2, yMax]) {
+    svg("line", { x1: margin.left, x2: width - margin.right, y1: y(v), y2: y(v), stroke: "#eee" }, plot);
+    svg("text", { x: margin.left - 4, y: y(v) + 4, "text-anchor": "end" }, plot).textContent =
+      format(v);
+  }
+  const x = (fraction) => margin.left + fraction * (width - margin.left - margin.right);
+  return { plot, x, y, bottom: height - margin.bottom };
+}
+
+function color(i) {
+  return COLORS[i % COLORS.length];
+}
+
+// --- Trends ---
+
+async function loadTrends() {
+  const count = Math.max(2, parseInt($("trend-runs").value, 10) || 20);
+  const recent = runs.slice(-count);
+  status(`Loading the summaries of ${recent.length} runs…`);
+  const summaries = await Promise.all(
+    recent.map((run) => get(`runs/${encodeURIComponent(run.id)}/summaries`))
+  );
+  trends = { runs: recent, summaries };
+  const events = [...new Set(summaries.flat().map((s) => s.event))].sort();
+  const selected = $("trend-event").value || (events.includes("cycles") ? "cycles" : events[0]);
+  fillSelect($("trend-event"), events, selected);
+  renderTrends();
+  status(`${runs.length} runs`);
+}
+
+function renderTrends() {
+  const event = $("trend-event").value;
+  const filter = $("trend-filter").value.trim();
+  // Runs of a single engine each (e.g., from `daemon`) form one series; otherwise, each engine
+  // is a series of its own.
+  const single = trends.summaries.every((run) => new Set(run.map((s) => s.engine)).size <= 1);
+  const charts = new Map();
+  trends.summaries.forEach((run, i) => {
+    for (const s of run) {
+      const title = `${benchmark(s)} :: ${s.phase.toLowerCase()}`;
+      if (s.event !== event || (filter && !title.includes(filter))) {
+        continue;
+      }
+      if (!charts.has(title)) {
+        charts.set(title, new Map());
+      }
+      const series = charts.get(title);
+      const name = single ? "mean" : s.engine;
+      if (!series.has(name)) {
+        series.set(name, []);
+      }
+      series.get(name).push({ run: i, s });
+    }
+  });
+
+  const container = $("trend-charts");
+  container.replaceChildren();
+  if (charts.size === 0) {
+    container.appendChild(element("p", { class: "help" }, "No results."));
+  }
+  const last = Math.max(1, trends.runs.length - 1);
+  for (const title of [...charts.keys()].sort()) {
+    const series = charts.get(title);
+    const values = [...series.values()].flat().flatMap(({ s }) => [s.min, s.max, s.mean]);
+    const c = chart(container, title, 360, 180, [Math.min(...values), Math.max(...values)], [
+      ...series.keys(),
+    ]);
+    [...series.values()].forEach((points, i) => {
+      const coordinates = points.map(({ run, s }) => `${c.x(run / last)},${c.y(s.mean)}`);
+      svg("polyline", { points: coordinates.join(" "), fill: "none", stroke: color(i) }, c.plot);
+      for (const { run, s } of points) {
+        svg("line", {
+          x1: c.x(run / last), x2: c.x(run / last), y1: c.y(s.min), y2: c.y(s.max),
+          stroke: color(i), "stroke-opacity": 0.3,
+        }, c.plot);
+        const dot = svg("circle", { cx: c.x(run / last), cy: c.y(s.mean), r: 3, fill: color(i) }, c.plot);
+        svg("title", {}, dot).textContent =
+          `${trends.runs[run].id}: mean ${format(s.mean)} (min ${format(s.min)}, max ${format(s.max)})`;
+      }
+    });
+    const first = svg("text", { x: c.x(0), y: c.bottom + 14 }, c.plot);
+    first.textContent = trends.runs[0].id;
+    const latest = svg("text", { x: c.x(1), y: c.bottom + 14, "text-anchor": "end" }, c.plot);
+    latest.textContent = trends.runs[trends.runs.length - 1].id;
+  }
+}
+
+// --- Comparison ---
+
+function isSignificant(e) {
+  return Math.abs(e.a_mean - e.b_mean) > Math.abs(e.half_width_confidence_interval);
+}
+
+async function loadComparison() {
+  const [a, b] = [$("run-a").value, $("run-b").value];
+  const rows = $("comparison-rows");
+  if (!a || !b || a === b) {
+    effectSizes = [];
+    rows.replaceChildren(message("Select two different runs."));
+    return;
+  }
+  status(`Comparing ${a} with ${b}…`);
+  const query = new URLSearchParams({
+    a,
+    b,
+    "significance-level": $("significance-level").value,
+  });
+  effectSizes = await get(`effect-size?${query}`);
+  effectSizes.sort(
+    (x, y) =>
+      isSignificant(y) - isSignificant(x) ||
+      Math.abs(y.b_mean / y.a_mean - 1) - Math.abs(x.b_mean / x.a_mean - 1)
+  );
+  renderComparison();
+  status(`${runs.length} runs`);
+}
+
+function message(text) {
+  const row = element("tr");
+  row.appendChild(element("td", { colspan: 7, class: "same" }, text));
+  return row;
+}
+
+function renderComparison() {
+  const rows = $("comparison-rows");
+  const onlySignificant = $("only-significant").checked;
+  const shown = effectSizes.filter((e) => !onlySignificant || isSignificant(e));
+  rows.replaceChildren();
+  if (shown.length === 0) {
+    rows.appendChild(message("No significant differences."));
+  }
+  for (const e of shown) {
+    const change = ((e.b_mean - e.a_mean) / e.a_mean) * 100;
+    const ci = (Math.abs(e.half_width_confidence_interval) / e.a_mean) * 100;
+    const result = !isSignificant(e) ? "same" : e.b_mean < e.a_mean ? "faster" : "slower";
+    const row = element("tr");
+    row.append(
+      element("td", {}, benchmark(e)),
+      element("td", {}, e.phase.toLowerCase()),
+      element("td", {}, e.event),
+      element("td", { class: "number" }, format(e.a_mean)),
+      element("td", { class: "number" }, format(e.b_mean)),
+      element("td", { class: `number ${result}` }, `${change.toFixed(2)}% ± ${ci.toFixed(2)}%`),
+      element("td", { class: result }, result === "same" ? "no difference" : result)
+    );
+    row.addEventListener(
+      "click",
+      guarded(async () => {
+        rows.querySelectorAll(".selected").forEach((r) => r.classList.remove("selected"));
+        row.classList.add("selected");
+        await plotDistribution(e);
+      })
+    );
+    rows.appendChild(row);
+  }
+}
+
+// --- Distribution ---
+
+function measurements(run) {
+  if (!measurementsByRun.has(run)) {
+    measurementsByRun.set(run, get(`runs/${encodeURIComponent(run)}/measurements`));
+  }
+  return measurementsByRun.get(run);
+}
+
+async function plotDistribution(e) {
+  const runNames = [$("run-a").value, $("run-b").value];
+  const matches = (m) =>
+    m.wasm === e.wasm &&
+    m.phase === e.phase &&
+    m.event === e.event &&
+    (m.threads ?? null) === (e.threads ?? null) &&
+    (m.input_size ?? null) === (e.input_size ?? null);
+  const counts = (await Promise.all(runNames.map(measurements))).map((run) =>
+    run.filter(matches).map((m) => m.count)
+  );
+  $("distribution-title").textContent =
+    `${benchmark(e)} :: ${e.phase.toLowerCase()} :: ${e.event}`;
+  const container = $("distribution-chart");
+  container.replaceChildren();
+  histogram(container, runNames, counts);
+}
+
+// Overlay a histogram of each run's counts, with a dashed line at each mean.
+function histogram(container, names, counts) {
+  const all = counts.flat();
+  const [min, max] = [Math.min(...all), Math.max(...all)];
+  const bins = 30;
+  const width = (max - min) / bins || 1;
+  const bin = (v) => Math.min(bins - 1, Math.floor((v - min) / width));
+  const frequencies = counts.map((values) => {
+    const f = new Array(bins).fill(0);
+    values.forEach((v) => f[bin(v)]++);
+    return f;
+  });
+  const c = chart(container, "", 860, 260, [0, Math.max(...frequencies.flat())], names);
+  container.querySelector("h3").remove();
+  const x = (v) => c.x((v - min) / (max - min || 1));
+  frequencies.forEach((f, i) => {
+    f.forEach((n, b) => {
+      if (n === 0) {
+        return;
+      }
+      const rect = svg("rect", {
+        x: x(min + b * width), width: Math.max(1, x(min + (b + 1) * width) - x(min + b * width) - 1),
+        y: c.y(n), height: c.bottom - c.y(n), fill: color(i), "fill-opacity": 0.5,
+      }, c.plot);
+      svg("title", {}, rect).textContent = `${names[i]}: ${n} in [${format(min + b * width)}, ${format(min + (b + 1) * width)})`;
+    });
+    const mean = counts[i].reduce((sum, v) => sum + v, 0) / counts[i].length;
+    svg("line", {
+      x1: x(mean), x2: x(mean), y1: c.y(Math.max(...frequencies.flat())), y2: c.bottom,
+      stroke: color(i), "stroke-dasharray": "4 3",
+    }, c.plot);
+  });
+  svg("text", { x: x(min), y: c.bottom + 14 }, c.plot).textContent = format(min);
+  svg("text", { x: x(max), y: c.bottom + 14, "text-anchor": "end" }, c.plot).textContent =
+    format(max);
+}
+
+// --- Setup ---
+
+async function init() {
+  status("Loading runs…");
+  runs = await get("runs");
+  if (runs.length === 0) {
+    status("No runs stored");
+    return;
+  }
+  // Newest first, comparing the latest run with the one before it by default.
+  const ids = runs.map((run) => run.id).reverse();
+  fillSelect($("run-a"), ids, ids[Math.min(1, ids.length - 1)]);
+  fillSelect($("run-b"), ids, ids[0]);
+
+  $("trend-event").addEventListener("change", renderTrends);
+  $("trend-filter").addEventListener("input", renderTrends);
+  $("trend-runs").addEventListener("change", guarded(loadTrends));
+  for (const id of ["run-a", "run-b", "significance-level"]) {
+    $(id).addEventListener("change", guarded(loadComparison));
+  }
+  $("only-significant").addEventListener("change", renderComparison);
+
+  await Promise.all([loadTrends(), loadComparison()]);
+}
+
+guarded(init)();
@@ -0,0 +1,141 @@
+use crate::fetch_engine::sha256;
+use anyhow::{Context, Result};
+use flate2::{write::GzEncoder, Compression};
+use sightglass_data::{Manifest, Measurement};
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Archive the raw `measurements` of a run, gzipped as JSON, and its `manifest` under
+/// `<location>/<sha256>/`, content-addressed by the SHA-256 of the measurements; `location` is an
+/// `s3://bucket/prefix` URL (uploaded with the `aws` CLI), a `gs://bucket/prefix` URL (uploaded
+/// with `gsutil`), or a local directory. Returns where the run was archived.
+pub fn archive(
+    location: &str,
+    measurements: &[Measurement<'_>],
+    manifest: &Manifest,
+) -> Result<String> {
+    let json = serde_json::to_vec(measurements)?;
+    let destination = format!("{}/{}", location.trim_end_matches('/'), sha256(&json));
+
+    let mut compressed = GzEncoder::new(vec![], Compression::default());
+    compressed.write_all(&json)?;
+    let mut manifest_json = vec![];
+    manifest.write(&mut manifest_json)?;
+    let files = [
+        ("measurements.json.gz", compressed.finish()?),
+        ("manifest.json", manifest_json),
+    ];
+
+    let staging = std::env::temp_dir().join(format!("sightglass-archive-{}", std::process::id()));
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("failed to create {}", staging.display()))?;
+    let result = files.iter().try_for_each(|(name, bytes)| {
+        let local = staging.join(name);
+        fs::write(&local, bytes).with_context(|| format!("failed to write {}", local.display()))?;
+        copy(&local, &format!("{}/{}", destination, name))
+    });
+    fs::remove_dir_all(&staging)?;
+    result?;
+
+    log::info!("Archived the run's raw results to {}", destination);
+    Ok(destination)
+}
+
+/// Copy the `local` file to `remote`: a cloud storage URL or a local path.
+fn copy(local: &Path, remote: &str) -> Result<()> {
+    let mut command = match copy_command(local, remote) {
+        Some(command) => command,
+        None => {
+            let remote = PathBuf::from(remote);
+            if let Some(dir) = remote.parent() {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+            }
+            fs::copy(local, &remote)
+                .with_context(|| format!("failed to copy to {}", remote.display()))?;
+            return Ok(());
+        }
+    };
+    log::debug!("Running: {:?}", command);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .status()
+        .with_context(|| format!("failed to run `{}`; is it installed?", program))?;
+    anyhow::ensure!(status.success(), "failed to upload {} ({})", remote, status);
+    Ok(())
+}
+
+/// The command that uploads `local` to the cloud storage URL `remote`, if it is one.
+fn copy_command(local: &Path, remote: &str) -> Option<Command> {
+    let mut command = if remote.starts_with("s3://") {
+        let mut command = Command::new("aws");
+        command.args(["s3", "cp", "--only-show-errors"]);
+        command
+    } else if remote.starts_with("gs://") {
+        let mut command = Command::new("gsutil");
+        command.args(["-q", "cp"]);
+        command
+    } else {
+        return None;
+    };
+    command.arg(local).arg(remote);
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use sightglass_data::Phase;
+    use std::io::Read;
+
+    #[test]
+    fn archive_to_directory() -> Result<()> {
+        let measurements = vec![Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: "benchmark.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count: 42,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }];
+        let dir = tempfile::tempdir()?;
+        let location = dir.path().to_str().unwrap();
+        let destination = archive(location, &measurements, &Manifest::default())?;
+        assert_eq!(
+            destination,
+            format!(
+                "{}/{}",
+                location,
+                sha256(&serde_json::to_vec(&measurements)?)
+            )
+        );
+
+        let mut json = String::new();
+        GzDecoder::new(fs::File::open(
+            Path::new(&destination).join("measurements.json.gz"),
+        )?)
+        .read_to_string(&mut json)?;
+        assert_eq!(json, serde_json::to_string(&measurements)?);
+        assert!(Path::new(&destination).join("manifest.json").is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn upload_commands() {
+        let command = copy_command(Path::new("a.json"), "s3://bucket/prefix/a.json").unwrap();
+        assert_eq!(command.get_program(), "aws");
+        let command = copy_command(Path::new("a.json"), "gs://bucket/a.json").unwrap();
+        assert_eq!(command.get_program(), "gsutil");
+        assert!(copy_command(Path::new("a.json"), "/tmp/archive/a.json").is_none());
+    }
+}
@@ -1,15 +1,41 @@
-use anyhow::{anyhow, Context, Result};
+use crate::github::PullRequest;
+use crate::notify::notify_regressions;
+use crate::package::Suite;
+use crate::store::Store;
+use crate::stream::{Flush, FlushPhases, ResultStream, SharedStream};
+use crate::tui::LiveTable;
+use anyhow::{anyhow, bail, Context, Result};
+use memmap2::Mmap;
 use rand::{rngs::SmallRng, Rng, SeedableRng};
-use sightglass_data::{Format, Measurement, Phase};
+use sha2::{Digest, Sha256};
+use sightglass_build::{BenchmarkConfig, Proposal, DEFAULT_INPUT_SIZE};
+use sightglass_data::{
+    BenchmarkManifest, EngineManifest, Failure, Format, Manifest, Measurement, Phase, RunManifest,
+    Skipped, Variant,
+};
+use sightglass_fingerprint::Machine;
+use sightglass_recorder::calibrate::{Overhead, OverheadMode, CALIBRATION_ITERATIONS};
 use sightglass_recorder::cpu_affinity::bind_to_single_core;
-use sightglass_recorder::measure::Measurements;
-use sightglass_recorder::{bench_api::BenchApi, benchmark::benchmark, measure::MeasureType};
+use sightglass_recorder::measure::{Measure, Measurements};
+use sightglass_recorder::profile::{self, ProfileType};
+use sightglass_recorder::{
+    bench_api::{engine_kind, BenchApi, Capability},
+    benchmark::{benchmark, precompile, supported_proposals, BenchmarkOptions},
+    measure::MeasureType,
+};
 use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
     fs,
-    io::{self, BufWriter, Write},
+    io::{self, BufWriter, IsTerminal, Write},
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
     process::Command,
-    process::Stdio,
+    process::{Output, Stdio},
+    rc::Rc,
+    thread,
+    time::{Duration, Instant},
 };
 use structopt::StructOpt;
 
@@ -19,27 +45,80 @@ use structopt::StructOpt;
 /// NUMBER_OF_ITERATIONS_PER_PROCESS`.
 #[derive(StructOpt, Debug)]
 pub struct BenchmarkCommand {
-    /// The path to the Wasm file(s) to benchmark.
+    /// The path to the Wasm file(s) to benchmark. A Wasm file exporting
+    /// `bench_*` entry points is benchmarked once per entry point. A Wasm
+    /// component is run through its `wasi:cli/run` export, if the engine
+    /// supports components. With `--suite`, the Wasm files are named by
+    /// their path in the suite (e.g., `bz2/benchmark.wasm`), and all of the
+    /// suite's benchmarks run if none are named.
     #[structopt(
         index = 1,
-        required = true,
+        required_unless = "suite",
         value_name = "WASMFILE",
         parse(from_os_str)
     )]
     wasm_files: Vec<PathBuf>,
 
+    /// Run the benchmarks of a suite archive made by the `package` command,
+    /// pinning exactly which benchmarks (and inputs) the results are for. The
+    /// archive is extracted into the cache and checked against its checksums,
+    /// and its version is recorded in the `--manifest`.
+    #[structopt(long, value_name = "ARCHIVE", parse(from_os_str))]
+    suite: Option<PathBuf>,
+
+    /// Only run the benchmarks with any of these tags (e.g., `--tags
+    /// simd,wasi`), as listed in the `benchmark.toml` next to each Wasm file.
+    /// A `benchmark.toml` may also give a benchmark's expected `stdout`
+    /// checksum, its default engine flags, its input files, and a timeout for
+    /// each of its iterations (only enforced when running benchmarks in
+    /// subprocesses); see the benchmarks README.
+    #[structopt(long, use_delimiter = true, value_name = "TAGS")]
+    tags: Vec<String>,
+
     /// The benchmark engine(s) with which to run the benchmark.
     ///
     /// This is one or more paths to a shared library implementing the
-    /// benchmarking engine specification. See `engines/wasmtime` for an example
-    /// script to build an engine.
+    /// benchmarking engine specification. See `engines/wasmtime`,
+    /// `engines/wasmer`, `engines/wamr`, and `engines/node` for example
+    /// scripts to build an engine. On Linux, `native` selects the natively
+    /// compiled library shipped alongside each Wasm file (e.g.,
+    /// `benchmark.so`) as a baseline; see `benchmarks/build-native.sh`.
+    /// `wasmtime:<REVISION>` (e.g., `wasmtime:main`) builds Wasmtime's
+    /// library at a branch, tag, or full commit hash, caching it by commit.
     #[structopt(long("engine"), short("e"), value_name = "PATH", empty_values = false)]
     engines: Vec<String>,
 
     /// Configure an engine using engine-specific flags. (For the Wasmtime
     /// engine, these can be a subset of flags from `wasmtime run --help`).
-    #[structopt(long("engine-flags"), value_name = "ENGINE_FLAGS")]
-    engine_flags: Option<String>,
+    ///
+    /// This may be repeated to run each engine once per set of flags (e.g.,
+    /// `--engine-flags="-O opt-level=0" --engine-flags="-O opt-level=2"`); the
+    /// measurements of each set are recorded separately, under the engine
+    /// labeled with its flags (e.g., `libengine.so [-O opt-level=0]`).
+    #[structopt(
+        long("engine-flags"),
+        value_name = "ENGINE_FLAGS",
+        number_of_values = 1
+    )]
+    engine_flags: Vec<String>,
+
+    /// A human name to record (and report) the measurements of an engine
+    /// under, rather than its path and flags; e.g., `--engine-label main
+    /// --engine-label pr-1234`. One is given for each run of an engine, in
+    /// order: for each `--engine`, once per set of `--engine-flags` (and per
+    /// compiler, with `--compilers`). The manifest records the path and flags
+    /// of each label.
+    #[structopt(long("engine-label"), value_name = "LABEL", number_of_values = 1)]
+    engine_labels: Vec<String>,
+
+    /// Compare Wasmtime's compilers (winch, cranelift): run each engine once
+    /// per compiler, passing it the flags that select that compiler (e.g.,
+    /// `-C compiler=winch`) after any `--engine-flags`, and record the
+    /// measurements of each under the compiler's name. With two compilers
+    /// (e.g., `--compilers winch,cranelift`) the results are reported as an
+    /// effect size.
+    #[structopt(long, use_delimiter = true, value_name = "COMPILERS")]
+    compilers: Vec<Compiler>,
 
     /// How many processes should we use for each Wasm benchmark?
     #[structopt(long = "processes", default_value = "10", value_name = "PROCESSES")]
@@ -53,6 +132,32 @@ pub struct BenchmarkCommand {
     )]
     iterations_per_process: usize,
 
+    /// Run every iteration in a fresh process, rather than running
+    /// `NUMBER_OF_ITERATIONS_PER_PROCESS` iterations in each of `PROCESSES`
+    /// processes; the same total number of samples is taken, but no iteration
+    /// sees the state (e.g., caches, allocator state, or JIT warmup) left
+    /// behind by an earlier one.
+    #[structopt(long)]
+    fresh_process: bool,
+
+    /// How many times to execute each instance. Each iteration instantiates
+    /// the benchmark once and executes it this many times in a row; the
+    /// measurements of the `n`th execution record `n` in their `execution`
+    /// field, so that the first execution (warmup) can be compared with the
+    /// later ones (steady state). The engine must support
+    /// repeated execution.
+    #[structopt(long, default_value = "1", value_name = "EXECUTIONS")]
+    executions_per_instance: u32,
+
+    /// Run each benchmark with the threads proposal enabled, once per thread
+    /// count given (e.g., `--wasm-threads 1,2,4,8`): the engine enables
+    /// shared memories and tells the benchmark how many threads to use
+    /// through its `SIGHTGLASS_THREADS` environment variable. The thread count
+    /// is recorded with each measurement, so that results can be plotted as a
+    /// scaling curve. The engine must support threads.
+    #[structopt(long, use_delimiter = true, value_name = "THREADS")]
+    wasm_threads: Vec<u32>,
+
     /// Output raw data, rather than the summarized, human-readable analysis
     /// results.
     #[structopt(long)]
@@ -68,11 +173,171 @@ pub struct BenchmarkCommand {
     #[structopt(short = "o", long = "output-file")]
     output_file: Option<String>,
 
-    /// The type of measurement to use (cycles, perf-counters, noop, vtune)
-    /// when recording the benchmark performance.
+    /// Write the raw output data as it is produced rather than all at once
+    /// after the run, flushing it after each `phase`, `iteration`, or
+    /// `benchmark`. A finer granularity loses less of an interrupted run, but
+    /// writes closer to the measurements (though only ever between phases).
+    /// With several processes, each subprocess's measurements are written as
+    /// it finishes. As the measurements are already written, those of a
+    /// benchmark that later fails stay in the output (the manifest lists the
+    /// failure).
+    #[structopt(long, value_name = "GRANULARITY", requires = "raw")]
+    flush_every: Option<Flush>,
+
+    /// Compress the raw output data with zstd as it is written, which spares
+    /// the disk during long runs. The output is streamed as with
+    /// `--flush-every` (by default, after each benchmark), each flush being a
+    /// zstd frame; decompress it with `zstd -d`.
+    #[structopt(long, requires = "raw")]
+    zstd: bool,
+
+    /// Show a live table of the benchmarks on stderr as they run: for each
+    /// benchmark and engine, the iterations run so far, their mean count, and
+    /// how much the counts of the last few iterations vary (for the first event
+    /// of the last phase measured, e.g. `execution :: cycles`). A broken run
+    /// (e.g., failing benchmarks, or wildly varying counts) can then be stopped
+    /// early. The table is only shown when stderr is a terminal.
+    #[structopt(long)]
+    tui: bool,
+
+    /// Path to a JSON file in which to describe the run: e.g., the engines
+    /// and how they were built, as reported by each engine, the benchmarks'
+    /// tags, and any benchmarks that failed (e.g., by trapping or crashing),
+    /// with the phase they reached, their exit status, and the end of their
+    /// `stderr`, or that were skipped because the engine does not support a
+    /// Wasm proposal they require. The `summarize` and `effect-size` commands
+    /// can display it alongside the results. It also records the SHA-256
+    /// digest of each Wasm file (see `--compare-wasm`).
+    #[structopt(long, value_name = "MANIFEST", parse(from_os_str))]
+    manifest: Option<PathBuf>,
+
+    /// Before running, check that each Wasm file is the same as the one of
+    /// the same path in the manifest of another run (see `--manifest`); e.g.,
+    /// one on another machine, whose results are to be compared with this
+    /// run's. Benchmarks that differ, e.g., because they do not build
+    /// reproducibly (see `benchmarks/build.sh --verify`), fail the run.
+    #[structopt(long, value_name = "MANIFEST", parse(from_os_str))]
+    compare_wasm: Option<PathBuf>,
+
+    /// After the run, push the summary statistics of the results as
+    /// Prometheus gauges (e.g., `sightglass_median`, labeled with the engine,
+    /// benchmark, phase, and event) to the pushgateway at this URL (e.g.,
+    /// `http://localhost:9091`), replacing the metrics last pushed for the
+    /// `--pushgateway-job`.
+    #[structopt(long, value_name = "URL")]
+    pushgateway: Option<String>,
+
+    /// The job under which to push metrics to the `--pushgateway`.
+    #[structopt(long, value_name = "JOB", default_value = "sightglass")]
+    pushgateway_job: String,
+
+    /// When comparing two engines, also append a Markdown table of the
+    /// significant effect sizes to the file named by `$GITHUB_STEP_SUMMARY`,
+    /// so that they show in the summary of a GitHub Actions job.
+    #[structopt(long)]
+    github_step_summary: bool,
+
+    /// When comparing two engines, also post a Markdown table of the
+    /// significant effect sizes as a comment on this GitHub pull request
+    /// (e.g., `bytecodealliance/wasmtime#1234`), or update the comment posted
+    /// by an earlier run. The API token is read from `$GITHUB_TOKEN`.
+    #[structopt(long, value_name = "OWNER/REPO#NUMBER")]
+    post_github_comment: Option<PullRequest>,
+
+    /// When comparing two engines, POST a notification to this URL when the
+    /// run has any significant regressions of `--notify-threshold` percent or
+    /// more: results for which the second engine is slower than the first
+    /// (the baseline). The notification is a JSON description of the
+    /// regressions or, with `--notify-slack`, a Slack message.
+    #[structopt(long, value_name = "URL")]
+    notify_url: Option<String>,
+
+    /// How much slower, in percent, a significant result must be to notify
+    /// `--notify-url` of it.
+    #[structopt(long, value_name = "PERCENT", default_value = "5")]
+    notify_threshold: f64,
+
+    /// Send `--notify-url` a message for a Slack incoming webhook instead.
+    #[structopt(long)]
+    notify_slack: bool,
+
+    /// After the run, archive its raw measurements (as gzipped JSON, whatever
+    /// the `--output-format`) and its manifest under `<ARCHIVE>/<SHA-256>/`,
+    /// named by the SHA-256 of the measurements. This is an
+    /// `s3://bucket/prefix` URL (uploaded with the `aws` CLI), a
+    /// `gs://bucket/prefix` URL (uploaded with `gsutil`), or a local directory.
+    #[structopt(long, value_name = "ARCHIVE")]
+    archive: Option<String>,
+
+    /// Label the run in the local results store, to find it later with `runs
+    /// list --matching`.
+    #[structopt(long, value_name = "LABEL")]
+    label: Option<String>,
+
+    /// Note something about the run, to be recorded in its `--manifest` and
+    /// printed by `effect-size --manifest`, so that the context of its results
+    /// is not lost by the time they are reviewed; e.g., `--note "the machine
+    /// was also running a kernel build"`.
+    #[structopt(long("note"), value_name = "NOTE", number_of_values = 1)]
+    notes: Vec<String>,
+
+    /// Note something about one benchmark of the run, as `BENCHMARK=NOTE`,
+    /// where `BENCHMARK` is its Wasm file as given; e.g.,
+    /// `--benchmark-note benchmarks/bz2/benchmark.wasm="input cached"`. The
+    /// note is recorded in the `--manifest` with any `notes` of the
+    /// benchmark's `benchmark.toml`.
+    #[structopt(
+        long("benchmark-note"),
+        value_name = "BENCHMARK=NOTE",
+        number_of_values = 1
+    )]
+    benchmark_notes: Vec<String>,
+
+    /// Do not record the run in the local results store (see `runs`).
+    #[structopt(long)]
+    no_record: bool,
+
+    /// Path to a JSON file in which to write a digest of each benchmark's
+    /// output, so that a parent process can compare the output of each
+    /// engine.
+    #[structopt(long, hidden = true, parse(from_os_str))]
+    output_digests: Option<PathBuf>,
+
+    /// Path to a JSON file in which to list the benchmarks that failed, so
+    /// that a parent process can record them in its manifest.
+    #[structopt(long, hidden = true, parse(from_os_str))]
+    failures: Option<PathBuf>,
+
+    /// The type of measurement to use (cycles, wall-time, perf-counters,
+    /// energy, windows-counters, callgrind, peak-memory, io, noop, vtune) when
+    /// recording the benchmark performance. The clock used by `wall-time` can be selected
+    /// with, e.g., `wall-time:monotonic-raw` or `wall-time:rdtscp`. Raw PMU
+    /// events can be measured with, e.g., `perf-counters:l1d-cache-refill,0x24`
+    /// and a measurement plugin can be loaded with
+    /// `plugin:/path/to/libmeasure.so`. The `io` measure counts only the
+    /// `read`- and `write`-family system calls (as `read-syscalls` and
+    /// `write-syscalls`), not every system call.
     #[structopt(long, short, default_value = "cycles")]
     measure: MeasureType,
 
+    /// Profile each phase of each benchmark with the given profiler (perf).
+    /// Profiles are written to `--profile-dir` in files named by the engine,
+    /// benchmark, process, and phase; e.g.,
+    /// `libengine-noop-1234.execution.perf.data`.
+    #[structopt(long, value_name = "PROFILER")]
+    profile: Option<ProfileType>,
+
+    /// The directory in which to write the profiles recorded by `--profile`.
+    #[structopt(long, default_value = ".", parse(from_os_str))]
+    profile_dir: PathBuf,
+
+    /// What to do with the overhead of the measurement itself (ignore, record,
+    /// subtract). Unless ignored, the overhead is estimated before running the
+    /// benchmarks by measuring empty regions; it is then either recorded as
+    /// `<event>-overhead` events or subtracted from every measurement.
+    #[structopt(long, default_value = "ignore", value_name = "MODE")]
+    overhead: OverheadMode,
+
     /// Pass this flag to only run benchmarks over "small" workloads (rather
     /// than the larger, default workloads).
     ///
@@ -88,21 +353,51 @@ pub struct BenchmarkCommand {
     #[structopt(long, alias = "small-workload")]
     small_workloads: bool,
 
+    /// Run each benchmark with the given input size (e.g., `small` or
+    /// `large`), as declared in its `benchmark.toml`: the benchmark runs in
+    /// that size's input directory and is checked against the expected output
+    /// there. Benchmarks without the input size are skipped. Measurements
+    /// record any size but `default`, so that the sizes are never grouped
+    /// together.
+    #[structopt(long, default_value = DEFAULT_INPUT_SIZE, value_name = "SIZE")]
+    input_size: String,
+
     /// The directory to preopen as the benchmark working directory. If the
     /// benchmark accesses files using WASI, it will see this directory as its
     /// current working directory (i.e. `.`). If the working directory is not
-    /// specified, the Wasm file's parent directory is used instead.
+    /// specified, the Wasm file's parent directory (or its `--input-size`
+    /// directory) is used instead.
     #[structopt(short("d"), long("working-dir"), parse(from_os_str))]
     working_dir: Option<PathBuf>,
 
+    /// The directory that the engine and Wasm paths in the measurements are
+    /// recorded relative to, so that the same benchmarks run from two
+    /// checkouts (e.g., by absolute path) group together when analyzed.
+    /// Paths outside of it are recorded as given.
+    #[structopt(long, default_value = ".", value_name = "DIR", parse(from_os_str))]
+    path_root: PathBuf,
+
+    /// Record the engine and Wasm paths in the measurements as given, rather
+    /// than relative to `--path-root`.
+    #[structopt(long)]
+    keep_paths: bool,
+
     /// Stop measuring after the given phase (compilation/instantiation/execution).
     #[structopt(long("stop-after"))]
     stop_after_phase: Option<Phase>,
 
+    /// Measure loading a precompiled module instead of compiling the Wasm:
+    /// each Wasm file is compiled ahead of time once per process, without
+    /// being measured, and each iteration measures deserializing the result
+    /// (e.g., Wasmtime's `Module::deserialize` of a `.cwasm`) as the
+    /// deserialization phase. The engine must support precompilation.
+    #[structopt(long)]
+    precompiled: bool,
+
     /// The significance level for confidence intervals. Typical values are 0.01
     /// and 0.05, which correspond to 99% and 95% confidence respectively. This
     /// is ignored when using `--raw` or when there aren't exactly two engines
-    /// supplied.
+    /// (or engine and flag combinations) supplied.
     #[structopt(short, long, default_value = "0.01")]
     significance_level: f64,
 
@@ -119,12 +414,21 @@ impl BenchmarkCommand {
             self.iterations_per_process > 0,
             "iterations-per-process must be greater than zero"
         );
+        anyhow::ensure!(
+            self.executions_per_instance > 0,
+            "executions-per-instance must be greater than zero"
+        );
+        anyhow::ensure!(
+            self.wasm_threads.iter().all(|&threads| threads > 0),
+            "wasm-threads must be greater than zero"
+        );
         anyhow::ensure!(
             !self.engines.is_empty(),
             "must pass one or more engines to benchmark with -e/--engine"
         );
+        self.check_engine_labels()?;
 
-        if self.processes == 1 {
+        if self.processes == 1 && !self.fresh_process {
             self.execute_in_current_process()
         } else {
             self.execute_in_multiple_processes()
@@ -133,51 +437,229 @@ impl BenchmarkCommand {
 
     /// Execute benchmark(s) in the provided engine(s) using the current process.
     pub fn execute_in_current_process(&self) -> Result<()> {
-        let mut output_file: Box<dyn Write> = if let Some(file) = self.output_file.as_ref() {
-            Box::new(BufWriter::new(fs::File::create(file)?))
-        } else {
-            Box::new(io::stdout())
-        };
+        let (mut output_file, stream) = self.output()?;
+        let mut live = self.live_table();
 
         if self.pin {
             bind_to_single_core().context("attempting to pin execution to a single core")?;
         }
 
-        let wasm_files: Vec<_> = self
-            .wasm_files
-            .iter()
-            .map(|f| f.display().to_string())
+        // A Wasm file exporting entry points (`bench_*` functions) is benchmarked once for each
+        // of them, labeled like `benchmark.wasm#bench_parse`; see `WasmBenchmark::entry_points`.
+        let configs = self.benchmarks()?;
+        let mapped = map_wasm(&configs)?;
+        let mut benchmarks = vec![];
+        let mut required_proposals = BTreeMap::new();
+        let mut config_of = BTreeMap::new();
+        for (wasm_file, config) in &configs {
+            let bytes = &mapped[&wasm_file.display().to_string()];
+            let entry_points = sightglass_build::entry_points(bytes)
+                .with_context(|| format!("failed to parse {}", wasm_file.display()))?;
+            let recorded = self.recorded_path(wasm_file);
+            let wasm_file = wasm_file.display().to_string();
+            required_proposals.insert(wasm_file.clone(), proposals_of(&wasm_file, bytes));
+            config_of.insert(wasm_file.clone(), config);
+            if entry_points.is_empty() {
+                benchmarks.push((wasm_file, None, recorded));
+            } else {
+                for entry_point in entry_points {
+                    let label = format!("{}#{}", recorded, entry_point);
+                    benchmarks.push((wasm_file.clone(), Some(entry_point), label));
+                }
+            }
+        }
+        let benchmarks: Vec<_> = benchmarks
+            .into_iter()
+            .flat_map(|(wasm_file, entry_point, label)| {
+                self.thread_counts().into_iter().map(move |threads| {
+                    (
+                        wasm_file.clone(),
+                        entry_point.clone(),
+                        label.clone(),
+                        threads,
+                    )
+                })
+            })
             .collect();
+        let engine_runs = self.engine_runs();
         let mut all_measurements = vec![];
+        let mut manifest = Manifest {
+            benchmarks: self.benchmark_manifests(&configs)?,
+            variants: variants(&configs)?,
+            wasm_sha256: self.wasm_hashes(&mapped),
+            suite: self.suite()?.map(|suite| suite.manifest()),
+            notes: self.notes.clone(),
+            ..Default::default()
+        };
+        self.compare_wasm(&manifest)?;
+        let mut outputs = OutputDigests::default();
 
-        for engine in &self.engines {
-            let engine_path = check_engine_path(engine)?;
-            log::info!("Using benchmark engine: {}", engine_path.display());
-            let lib = unsafe { libloading::Library::new(&engine_path)? };
-            let mut bench_api = unsafe { BenchApi::new(&lib)? };
+        for (engine, engine_flags, engine_label) in &engine_runs {
+            let engine_flags = engine_flags.as_deref();
+            if let Some(flags) = engine_flags {
+                log::info!("Using engine flags: {}", flags);
+            }
 
-            for wasm_file in &wasm_files {
-                log::info!("Using Wasm benchmark: {}", wasm_file);
+            // The native baseline has no engine library to load.
+            let (engine_path, lib) = if is_native(engine) {
+                log::info!("Using the native baseline");
+                (PathBuf::from(engine), None)
+            } else {
+                let engine_path = check_engine_path(engine)?;
+                log::info!("Using benchmark engine: {}", engine_path.display());
+                let lib = unsafe { libloading::Library::new(&engine_path)? };
+                (engine_path, Some(lib))
+            };
+            let mut bench_api = match &lib {
+                Some(lib) => Some(
+                    unsafe { BenchApi::new(lib) }
+                        .with_context(|| format!("incompatible engine: {}", engine))?,
+                ),
+                None => None,
+            };
+            if self.precompiled {
+                check_precompile(engine, bench_api.as_ref())?;
+            }
+            if self.executions_per_instance > 1 {
+                check_repeated_execution(engine, bench_api.as_ref())?;
+            }
+            if !self.wasm_threads.is_empty() {
+                check_threads(engine, bench_api.as_ref())?;
+            }
+            let supported_proposals = bench_api
+                .as_mut()
+                .and_then(|bench_api| supported_proposals(bench_api, Path::new("."), engine_flags));
+            manifest.engines.push(engine_manifest(
+                engine_label,
+                lib.as_ref().map(|_| engine_path.display().to_string()),
+                match &lib {
+                    Some(_) => self.engine_hash(&engine_path)?,
+                    None => None,
+                },
+                engine_flags.map(ToString::to_string),
+                // Only ask for the build information if it will be written out.
+                match (&bench_api, &self.manifest) {
+                    (Some(bench_api), Some(_)) => bench_api.build_info(),
+                    _ => Default::default(),
+                },
+            ));
+
+            for (wasm_file, entry_point, label, threads) in &benchmarks {
+                log::info!("Using Wasm benchmark: {}", label);
+                if let Some(threads) = threads {
+                    log::info!("Using {} threads", threads);
+                }
+                let entry_point = entry_point.as_deref();
+                let config = config_of[wasm_file];
+                // A benchmark's own engine flags apply when none are given.
+                let benchmark_flags = engine_flags.or(config.engine_flags.as_deref());
+                let unsupported = unsupported_proposals(
+                    &required_proposals[wasm_file],
+                    supported_proposals.as_deref(),
+                );
+                if !unsupported.is_empty() {
+                    log::warn!(
+                        "Skipping {} in {}, which does not support {}",
+                        label,
+                        engine_label,
+                        unsupported.join(", ")
+                    );
+                    manifest.skipped.push(Skipped {
+                        engine: engine_label.clone(),
+                        wasm: label.clone(),
+                        proposals: unsupported,
+                    });
+                    continue;
+                }
+                if let (Some(bench_api), Some(_)) = (&bench_api, entry_point) {
+                    anyhow::ensure!(
+                        bench_api.capabilities().contains(&Capability::EntryPoints),
+                        "the engine cannot run {}, which has several entry points: {}",
+                        wasm_file,
+                        engine
+                    );
+                }
 
                 // Use the provided --working-dir, otherwise find the Wasm file's parent directory.
-                let working_dir = self.get_working_directory(&wasm_file)?;
+                let working_dir = self.get_working_directory(&wasm_file, config)?;
                 log::info!("Using working directory: {}", working_dir.display());
 
-                // Read the Wasm bytes.
-                let bytes = fs::read(&wasm_file).context("Attempting to read Wasm bytes")?;
+                let mut bytes = Cow::Borrowed(&mapped[wasm_file][..]);
                 log::debug!("Wasm benchmark size: {} bytes", bytes.len());
+                if let (Some(bench_api), true) =
+                    (&bench_api, sightglass_build::is_component(&bytes))
+                {
+                    anyhow::ensure!(
+                        bench_api.capabilities().contains(&Capability::Components),
+                        "the engine cannot run {}, which is a component: {}",
+                        wasm_file,
+                        engine
+                    );
+                    anyhow::ensure!(
+                        !self.precompiled,
+                        "precompiled components are not supported: {}",
+                        wasm_file
+                    );
+                }
 
-                let mut measurements = Measurements::new(this_arch(), engine, wasm_file);
+                // Compile the Wasm ahead of time, once, if only loading it is measured.
+                if let (true, Some(bench_api)) = (self.precompiled, &mut bench_api) {
+                    bytes = precompile(bench_api, &working_dir, &bytes, *threads, benchmark_flags)?
+                        .into();
+                    log::debug!("Precompiled module size: {} bytes", bytes.len());
+                }
+
+                let mut measurements = Measurements::new(this_arch(), engine_label, label);
+                measurements.set_threads(*threads);
+                measurements.set_input_size(self.recorded_input_size());
                 let mut measure = self.measure.build();
+                let overhead = match self.overhead {
+                    OverheadMode::Ignore => None,
+                    _ => Some(Overhead::measure(
+                        &mut measure,
+                        CALIBRATION_ITERATIONS,
+                        self.precompiled,
+                    )),
+                };
+                if self.overhead == OverheadMode::Record {
+                    overhead.as_ref().unwrap().record(&mut measurements);
+                }
+                if let Some(profile) = self.profile {
+                    let mut prefix = profile::output_prefix(
+                        &self.profile_dir,
+                        &engine_path,
+                        Path::new(wasm_file),
+                    )
+                    .into_os_string();
+                    if let Some(entry_point) = entry_point {
+                        prefix.push(format!("-{}", entry_point));
+                    }
+                    if self.engine_flags.len() > 1 {
+                        // Keep the profiles of each set of engine flags apart.
+                        let index = self
+                            .engine_flags
+                            .iter()
+                            .position(|f| Some(f.as_str()) == engine_flags);
+                        prefix.push(format!("-flags{}", index.unwrap()));
+                    }
+                    prefix.push(format!("-{}", std::process::id()));
+                    measure = Box::new((profile.build(Path::new(&prefix)), measure));
+                }
+                let reached = Rc::new(Cell::new(None));
+                measure = Box::new((PhaseTracker(reached.clone()), measure));
+                if let (Some(stream), Some(Flush::Phase)) = (&stream, self.flush()) {
+                    measure = Box::new((FlushPhases(stream.clone()), measure));
+                }
 
                 // Run the benchmark (compilation, instantiation, and execution) several times in
                 // this process.
+                let mut failure = None;
                 for i in 0..self.iterations_per_process {
                     let wasm_hash = {
                         use std::collections::hash_map::DefaultHasher;
                         use std::hash::{Hash, Hasher};
                         let mut hasher = DefaultHasher::new();
-                        wasm_file.hash(&mut hasher);
+                        label.hash(&mut hasher);
                         hasher.finish()
                     };
                     let stdout = format!("stdout-{:x}-{}-{}.log", wasm_hash, std::process::id(), i);
@@ -186,33 +668,131 @@ impl BenchmarkCommand {
                     let stderr = Path::new(&stderr);
                     let stdin = None;
 
-                    benchmark(
-                        &mut bench_api,
-                        &working_dir,
-                        stdout,
-                        stderr,
-                        stdin,
-                        &bytes,
-                        self.stop_after_phase.clone(),
-                        self.engine_flags.as_deref(),
-                        &mut measure,
-                        &mut measurements,
-                    )?;
-
-                    self.check_output(Path::new(wasm_file), stdout, stderr)?;
+                    // Catch a failing benchmark (e.g., a trap, which the bench API reports by
+                    // panicking) so that it can be recorded and the other benchmarks still run.
+                    // The failure is logged below, so the panic is not reported as it happens.
+                    reached.set(None);
+                    let recorded = measurements.recorded().len();
+                    let hook = panic::take_hook();
+                    panic::set_hook(Box::new(|_| {}));
+                    let options = BenchmarkOptions {
+                        stdin_path: stdin,
+                        precompiled: self.precompiled,
+                        entry_point,
+                        executions: self.executions_per_instance,
+                        threads: *threads,
+                        stop_after_phase: self.stop_after_phase,
+                        execution_flags: benchmark_flags,
+                        ..BenchmarkOptions::new(&working_dir, stdout, stderr)
+                    };
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| match &mut bench_api {
+                        Some(bench_api) => {
+                            benchmark(bench_api, &bytes, &options, &mut measure, &mut measurements)
+                        }
+                        None => {
+                            #[cfg(target_os = "linux")]
+                            return sightglass_recorder::native::benchmark(
+                                &sightglass_recorder::native::native_library_path(Path::new(
+                                    wasm_file,
+                                )),
+                                &options,
+                                &mut measure,
+                                &mut measurements,
+                            );
+                            #[cfg(not(target_os = "linux"))]
+                            unreachable!("the native baseline only runs on Linux")
+                        }
+                    }));
+                    panic::set_hook(hook);
+                    let result = result
+                        .unwrap_or_else(|panic| Err(anyhow!("{}", panic_message(&*panic))))
+                        .and_then(|()| {
+                            self.check_output(
+                                Path::new(wasm_file),
+                                config,
+                                entry_point,
+                                stdout,
+                                stderr,
+                            )
+                        })
+                        .and_then(|()| match self.stop_after_phase {
+                            None => outputs.check(label, engine_label, &output_digest(stdout)?),
+                            Some(_) => Ok(()),
+                        });
+                    if let Err(error) = result {
+                        log::error!("{} failed in {}: {:#}", label, engine_label, error);
+                        if let Some(live) = &mut live {
+                            live.fail(label, engine_label);
+                            live.draw();
+                        }
+                        failure = Some(Failure {
+                            engine: engine_label.clone(),
+                            wasm: label.clone(),
+                            phase: reached.get(),
+                            threads: *threads,
+                            status: None,
+                            error: format!("{:#}", error),
+                            stderr: tail(stderr),
+                        });
+                        break;
+                    }
+                    if let Some(live) = &mut live {
+                        live.add(&measurements.recorded()[recorded..]);
+                        live.draw();
+                    }
+                    if let (Some(stream), Some(Flush::Iteration)) = (&stream, self.flush()) {
+                        stream.borrow_mut().write(measurements.pending())?;
+                    }
                     measurements.next_iteration();
                 }
 
-                all_measurements.extend(measurements.finish());
+                // A failed benchmark's measurements are incomplete, so they are dropped.
+                if let Some(failure) = failure {
+                    manifest.failures.push(failure);
+                    continue;
+                }
+                let mut measurements = measurements.finish();
+                if self.overhead == OverheadMode::Subtract {
+                    overhead.as_ref().unwrap().subtract(&mut measurements);
+                }
+                if let (Some(stream), Some(Flush::Benchmark)) = (&stream, self.flush()) {
+                    stream.borrow_mut().write(&measurements)?;
+                }
+                all_measurements.extend(measurements);
             }
         }
 
-        self.write_results(&all_measurements, &mut output_file)?;
-        Ok(())
+        if let Some(path) = &self.output_digests {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            serde_json::to_writer(file, &outputs.digests())?;
+        }
+        if let Some(path) = &self.failures {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            serde_json::to_writer(file, &manifest.failures)?;
+        }
+        manifest.run = self.run_manifest(&all_measurements);
+        self.write_manifest(&manifest)?;
+        self.write_results(&all_measurements, stream, &mut output_file)?;
+        self.archive(&all_measurements, &manifest)?;
+        self.record(&all_measurements, &manifest);
+        check_failures(&manifest)
     }
 
-    /// Assert that our actual `stdout` and `stderr` match our expectations.
-    fn check_output(&self, wasm_file: &Path, stdout: &Path, stderr: &Path) -> Result<()> {
+    /// Assert that our actual `stdout` and `stderr` match our expectations. Each entry point of a
+    /// benchmark has its own expectations; e.g., `stdout.bench_parse.expected`. An instance
+    /// executed several times (see `--executions-per-instance`) is expected to repeat its output
+    /// each time. A benchmark run through `_start` is also checked against the `stdout` checksum
+    /// in its `benchmark.toml`, if any.
+    fn check_output(
+        &self,
+        wasm_file: &Path,
+        config: &BenchmarkConfig,
+        entry_point: Option<&str>,
+        stdout: &Path,
+        stderr: &Path,
+    ) -> Result<()> {
         // If we aren't going through all phases and executing the Wasm, then we
         // won't have any actual output to check.
         if self.stop_after_phase.is_some() {
@@ -224,15 +804,45 @@ impl BenchmarkCommand {
         } else {
             ".".into()
         };
+        // Each input size has its own expectations.
+        let wasm_file_dir = wasm_file_dir.join(config.input_dir(&self.input_size).unwrap());
+
+        let expected = |stream: &str| match entry_point {
+            Some(entry_point) => wasm_file_dir.join(format!("{}.{}.expected", stream, entry_point)),
+            None => wasm_file_dir.join(format!("{}.expected", stream)),
+        };
 
-        let stdout_expected = wasm_file_dir.join("stdout.expected");
+        // The checksum is of the output with the default input.
+        let checksum = entry_point.is_none()
+            && config.stdout_blake3.is_some()
+            && self.recorded_input_size().is_none();
+        if checksum {
+            let stdout_actual_data = std::fs::read(stdout)
+                .with_context(|| format!("failed to read `{}`", stdout.display()))?;
+            let executions = self.executions_per_instance as usize;
+            let once = &stdout_actual_data[..stdout_actual_data.len() / executions];
+            anyhow::ensure!(
+                stdout_actual_data == once.repeat(executions),
+                "Actual `stdout` does not repeat for each execution; it is located at `{}`",
+                stdout.display(),
+            );
+            config
+                .check_stdout(once)
+                .with_context(|| format!("Actual `stdout` is located at `{}`", stdout.display()))?;
+        }
+
+        let stdout_expected = expected("stdout");
         if stdout_expected.exists() {
             let stdout_expected_data = std::fs::read_to_string(&stdout_expected)
                 .with_context(|| format!("failed to read `{}`", stdout_expected.display()))?;
             let stdout_actual_data = std::fs::read_to_string(stdout)
                 .with_context(|| format!("failed to read `{}`", stdout.display()))?;
             // Compare lines so that we ignore `\n` on *nix vs `\r\n` on Windows.
-            let stdout_expected_data = stdout_expected_data.lines().collect::<Vec<_>>();
+            let stdout_expected_data = stdout_expected_data
+                .lines()
+                .cycle()
+                .take(self.executions_per_instance as usize * stdout_expected_data.lines().count())
+                .collect::<Vec<_>>();
             let stdout_actual_data = stdout_actual_data.lines().collect::<Vec<_>>();
             anyhow::ensure!(
                 stdout_expected_data == stdout_actual_data,
@@ -242,7 +852,7 @@ impl BenchmarkCommand {
                 stdout.display(),
                 stdout_expected.display(),
             );
-        } else {
+        } else if !checksum {
             log::warn!(
                 "Did not find `{}` for `{}`! Cannot assert that actual \
                  `stdout` matches expectation.",
@@ -251,14 +861,18 @@ impl BenchmarkCommand {
             );
         }
 
-        let stderr_expected = wasm_file_dir.join("stderr.expected");
+        let stderr_expected = expected("stderr");
         if stderr_expected.exists() {
             let stderr_expected_data = std::fs::read_to_string(&stderr_expected)
                 .with_context(|| format!("failed to read `{}`", stderr_expected.display()))?;
             let stderr_actual_data = std::fs::read_to_string(stderr)
                 .with_context(|| format!("failed to read `{}`", stderr.display()))?;
             // Compare lines so that we ignore `\n` on *nix vs `\r\n` on Windows.
-            let stderr_expected_data = stderr_expected_data.lines().collect::<Vec<_>>();
+            let stderr_expected_data = stderr_expected_data
+                .lines()
+                .cycle()
+                .take(self.executions_per_instance as usize * stderr_expected_data.lines().count())
+                .collect::<Vec<_>>();
             let stderr_actual_data = stderr_actual_data.lines().collect::<Vec<_>>();
             anyhow::ensure!(
                 stderr_expected_data == stderr_actual_data,
@@ -283,11 +897,8 @@ impl BenchmarkCommand {
     /// Execute the benchmark(s) by spawning multiple processes. Each of the spawned processes will
     /// run the `execute_in_current_process` function above.
     fn execute_in_multiple_processes(&self) -> Result<()> {
-        let mut output_file: Box<dyn Write> = if let Some(file) = self.output_file.as_ref() {
-            Box::new(BufWriter::new(fs::File::create(file)?))
-        } else {
-            Box::new(io::stdout())
-        };
+        let (mut output_file, stream) = self.output()?;
+        let mut live = self.live_table();
 
         let this_exe =
             std::env::current_exe().context("failed to get the current executable's path")?;
@@ -299,76 +910,326 @@ impl BenchmarkCommand {
 
         let mut rng = SmallRng::seed_from_u64(0x1337_4242);
 
+        let (processes, iterations_per_process) = self.schedule();
+
         // Worklist that we randomly sample from.
         let mut choices = vec![];
+        let benchmarks = self.benchmarks()?;
+        let mapped = map_wasm(&benchmarks)?;
+        let mut manifest = Manifest {
+            benchmarks: self.benchmark_manifests(&benchmarks)?,
+            variants: variants(&benchmarks)?,
+            wasm_sha256: self.wasm_hashes(&mapped),
+            suite: self.suite()?.map(|suite| suite.manifest()),
+            notes: self.notes.clone(),
+            ..Default::default()
+        };
+        self.compare_wasm(&manifest)?;
+        let mut required_proposals = BTreeMap::new();
+        for (wasm, _) in &benchmarks {
+            let wasm_file = wasm.display().to_string();
+            required_proposals.insert(wasm, proposals_of(&wasm_file, &mapped[&wasm_file]));
+        }
 
-        for engine in &self.engines {
+        for (engine, engine_flags, engine_label) in self.engine_runs() {
             // Ensure that each of our engines is built before we spawn any
             // child processes (potentially in a different working directory,
             // and therefore potentially invalidating relative paths used here).
-            let engine = check_engine_path(engine)?;
-
-            for wasm in &self.wasm_files {
-                choices.push((engine.clone(), wasm, self.processes));
+            let (engine, path, build_info, supported_proposals) = if is_native(&engine) {
+                (PathBuf::from(engine), None, vec![], None)
+            } else {
+                let engine = check_engine_path(&engine)?;
+                let checked = check_engine_api(
+                    &engine,
+                    engine_flags.as_deref(),
+                    self.precompiled,
+                    !self.wasm_threads.is_empty(),
+                )?;
+                let path = Some(engine.display().to_string());
+                (
+                    engine,
+                    path,
+                    checked.build_info,
+                    checked.supported_proposals,
+                )
+            };
+            let sha256 = match &path {
+                Some(_) => self.engine_hash(&engine)?,
+                None => None,
+            };
+            manifest.engines.push(engine_manifest(
+                &engine_label,
+                path,
+                sha256,
+                engine_flags.clone(),
+                build_info,
+            ));
+
+            for (wasm, config) in &benchmarks {
+                let unsupported = unsupported_proposals(
+                    &required_proposals[wasm],
+                    supported_proposals.as_deref(),
+                );
+                if !unsupported.is_empty() {
+                    let recorded = self.recorded_path(wasm);
+                    log::warn!(
+                        "Skipping {} in {}, which does not support {}",
+                        recorded,
+                        engine_label,
+                        unsupported.join(", ")
+                    );
+                    manifest.skipped.push(Skipped {
+                        engine: engine_label.clone(),
+                        wasm: recorded,
+                        proposals: unsupported,
+                    });
+                    continue;
+                }
+                // A subprocess runs several iterations, each of which may take the timeout.
+                let timeout = config
+                    .timeout
+                    .map(|timeout| Duration::from_secs(timeout * iterations_per_process as u64));
+                choices.push((
+                    engine.clone(),
+                    engine_flags.clone(),
+                    engine_label.clone(),
+                    wasm,
+                    timeout,
+                    processes,
+                ));
             }
         }
 
         // Accumulated measurements from all of our subprocesses.
         let mut measurements = vec![];
 
+        // Each subprocess reports a digest of the output of each benchmark it runs, so that the
+        // output of every engine can be compared.
+        let mut outputs = OutputDigests::default();
+        let digests_file =
+            std::env::temp_dir().join(format!("sightglass-outputs-{}.json", std::process::id()));
+        let failures_file =
+            std::env::temp_dir().join(format!("sightglass-failures-{}.json", std::process::id()));
+
+        // Callgrind's dumps are kept out of the working directory, and removed with the run.
+        #[cfg(target_os = "linux")]
+        let callgrind_dir = match self.measure {
+            MeasureType::Callgrind => Some(tempfile::tempdir()?),
+            _ => None,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let callgrind_dir: Option<tempfile::TempDir> = None;
+
         while !choices.is_empty() {
             let index = rng.gen_range(0, choices.len());
-            let (engine, wasm, procs_left) = &mut choices[index];
+            let (engine, engine_flags, engine_label, wasm, timeout, procs_left) =
+                &mut choices[index];
 
-            let mut command = Command::new(&this_exe);
+            let mut command =
+                self.subprocess_command(&this_exe, callgrind_dir.as_ref().map(|dir| dir.path()));
             command
                 .stdin(Stdio::null())
                 .stdout(Stdio::piped())
-                .stderr(Stdio::inherit())
+                .stderr(Stdio::piped())
                 .arg("benchmark")
                 .arg("--processes")
                 .arg("1")
                 .arg("--iterations-per-process")
-                .arg(self.iterations_per_process.to_string())
+                .arg(iterations_per_process.to_string())
                 .arg("--engine")
                 .arg(&engine)
                 .arg("--measure")
                 .arg(self.measure.to_string())
+                .arg("--overhead")
+                .arg(self.overhead.to_string())
                 .arg("--raw")
                 .arg("--output-format")
                 // Always use JSON when privately communicating with a
                 // subprocess.
-                .arg(Format::Json.to_string());
+                .arg(Format::Json.to_string())
+                // The parent process records the whole run.
+                .arg("--no-record");
 
             if self.pin {
                 command.arg("--pin");
             }
 
+            if self.precompiled {
+                command.arg("--precompiled");
+            }
+
+            if self.executions_per_instance > 1 {
+                command
+                    .arg("--executions-per-instance")
+                    .arg(self.executions_per_instance.to_string());
+            }
+
+            if !self.wasm_threads.is_empty() {
+                let threads: Vec<_> = self.wasm_threads.iter().map(u32::to_string).collect();
+                command.arg("--wasm-threads").arg(threads.join(","));
+            }
+
+            if let Some(flags) = engine_flags {
+                // Attach the flags so that they are not parsed as our own (e.g., `-O ...`).
+                command.arg(format!("--engine-flags={}", flags));
+            }
+
+            if let Some(profile) = self.profile {
+                command
+                    .arg("--profile")
+                    .arg(profile.to_string())
+                    .arg("--profile-dir")
+                    .arg(&self.profile_dir);
+            }
+
             if self.small_workloads {
                 command.env("WASM_BENCH_USE_SMALL_WORKLOAD", "1");
             }
 
+            command.arg("--input-size").arg(&self.input_size);
+
+            // The subprocess records the paths as this process does.
+            if self.keep_paths {
+                command.arg("--keep-paths");
+            } else {
+                command.arg("--path-root").arg(&self.path_root);
+            }
+
             if let Some(phase) = self.stop_after_phase {
                 command.arg("--stop-after").arg(phase.to_string());
+            } else {
+                command.arg("--output-digests").arg(&digests_file);
             }
 
+            command.arg("--failures").arg(&failures_file);
             command.arg("--").arg(&wasm);
 
-            let output = command
-                .output()
+            let (output, timed_out) = run_with_timeout(&mut command, *timeout)
                 .context("failed to run benchmark subprocess")?;
+            // Pass along the subprocess's logs, which are also kept in case it fails.
+            if let (Some(live), false) = (&mut live, output.stderr.is_empty()) {
+                live.clear();
+            }
+            io::stderr().write_all(&output.stderr)?;
+
+            let recorded = self.recorded_path(wasm);
+            if timed_out {
+                let timeout = timeout.unwrap().as_secs();
+                log::error!("{} timed out in {}", recorded, engine_label);
+                if let Some(live) = &mut live {
+                    live.fail(&recorded, engine_label);
+                    live.draw();
+                }
+                measurements.retain(|m: &Measurement<'_>| {
+                    m.engine != *engine_label || !m.wasm.starts_with(&recorded)
+                });
+                manifest.failures.push(Failure {
+                    engine: engine_label.clone(),
+                    wasm: recorded,
+                    error: format!("timed out after {} seconds", timeout),
+                    stderr: tail_lines(&String::from_utf8_lossy(&output.stderr)),
+                    ..Default::default()
+                });
+                let _ = fs::remove_file(&failures_file);
+                choices.swap_remove(index);
+                continue;
+            }
 
-            anyhow::ensure!(
-                output.status.success(),
-                "benchmark subprocess did not exit successfully"
-            );
+            if !output.status.success() {
+                // The subprocess records the benchmarks that failed within it (e.g., by trapping);
+                // if it crashed instead, record what it left behind.
+                let mut failures = fs::read(&failures_file)
+                    .ok()
+                    .and_then(|failures| serde_json::from_slice::<Vec<Failure>>(&failures).ok())
+                    .unwrap_or_default();
+                if failures.is_empty() {
+                    failures.push(Failure {
+                        wasm: recorded,
+                        error: format!("the benchmark subprocess failed: {}", output.status),
+                        stderr: tail_lines(&String::from_utf8_lossy(&output.stderr)),
+                        ..Default::default()
+                    });
+                }
+                for mut failure in failures {
+                    // An engine reports a trap on the process's `stderr`, not the benchmark's.
+                    if failure.stderr.is_empty() {
+                        failure.stderr = tail_lines(&String::from_utf8_lossy(&output.stderr));
+                    }
+                    log::error!(
+                        "{} failed in {}: {}",
+                        failure.wasm,
+                        engine_label,
+                        failure.error
+                    );
+                    if let Some(live) = &mut live {
+                        live.fail(&failure.wasm, engine_label);
+                        live.draw();
+                    }
+                    failure.engine = engine_label.clone();
+                    failure.status = output.status.code();
+                    // Drop the measurements of the failed benchmark from earlier subprocesses.
+                    measurements.retain(|m: &Measurement<'_>| {
+                        m.engine != *engine_label || m.wasm != failure.wasm
+                    });
+                    manifest.failures.push(failure);
+                }
+                let _ = fs::remove_file(&failures_file);
+                choices.swap_remove(index);
+                continue;
+            }
 
             // Parse the subprocess's output and add its measurements to our
-            // accumulation.
-            measurements.extend(
+            // accumulation. The subprocess only sees one set of engine flags, so
+            // it records the engine unlabeled.
+            let mut subprocess_measurements =
                 serde_json::from_slice::<Vec<Measurement<'_>>>(&output.stdout)
-                    .context("failed to read benchmark subprocess's results")?,
-            );
+                    .context("failed to read benchmark subprocess's results")?;
+            for m in &mut subprocess_measurements {
+                m.engine = engine_label.clone().into();
+            }
+            if let Some(live) = &mut live {
+                live.add(&subprocess_measurements);
+                live.draw();
+            }
+            if let Some(stream) = &stream {
+                stream.borrow_mut().write(&subprocess_measurements)?;
+            }
+            measurements.extend(subprocess_measurements);
+
+            if self.stop_after_phase.is_none() {
+                let digests = fs::read(&digests_file)
+                    .context("failed to read benchmark subprocess's output digests")?;
+                let digests: BTreeMap<String, String> = serde_json::from_slice(&digests)?;
+                fs::remove_file(&digests_file)?;
+                // A benchmark whose output differs fails in this engine, like one that traps, but
+                // the rest of the run goes on.
+                let mut mismatched = false;
+                for (benchmark, digest) in digests {
+                    if let Err(error) = outputs.check(&benchmark, engine_label, &digest) {
+                        log::error!("{} failed in {}: {}", benchmark, engine_label, error);
+                        if let Some(live) = &mut live {
+                            live.fail(&benchmark, engine_label);
+                            live.draw();
+                        }
+                        measurements.retain(|m: &Measurement<'_>| {
+                            m.engine != *engine_label || m.wasm != benchmark
+                        });
+                        manifest.failures.push(Failure {
+                            engine: engine_label.clone(),
+                            wasm: benchmark,
+                            phase: Some(Phase::Execution),
+                            error: error.to_string(),
+                            stderr: tail_lines(&String::from_utf8_lossy(&output.stderr)),
+                            ..Default::default()
+                        });
+                        mismatched = true;
+                    }
+                }
+                if mismatched {
+                    choices.swap_remove(index);
+                    continue;
+                }
+            }
 
             *procs_left -= 1;
             if *procs_left == 0 {
@@ -376,18 +1237,282 @@ impl BenchmarkCommand {
             }
         }
 
-        self.write_results(&measurements, &mut output_file)?;
+        manifest.run = self.run_manifest(&measurements);
+        self.write_manifest(&manifest)?;
+        self.write_results(&measurements, stream, &mut output_file)?;
+        self.archive(&measurements, &manifest)?;
+        self.record(&measurements, &manifest);
+        check_failures(&manifest)
+    }
+
+    /// Decide how many subprocesses to spawn for each benchmark and how many iterations each of
+    /// them runs.
+    fn schedule(&self) -> (usize, usize) {
+        if self.fresh_process {
+            (self.processes * self.iterations_per_process, 1)
+        } else {
+            (self.processes, self.iterations_per_process)
+        }
+    }
+
+    /// Construct the command for spawning a benchmark subprocess. Some measures need to wrap the
+    /// subprocess in another tool; e.g., the `callgrind` measure only works when the benchmark is
+    /// running under Valgrind, dumping its statistics into the `callgrind_dir` of the run.
+    fn subprocess_command(&self, this_exe: &Path, callgrind_dir: Option<&Path>) -> Command {
+        #[cfg(target_os = "linux")]
+        if let (MeasureType::Callgrind, Some(dir)) = (&self.measure, callgrind_dir) {
+            let mut command = Command::new("valgrind");
+            command
+                .env(sightglass_recorder::measure::callgrind::OUT_DIR_VAR, dir)
+                .arg("--tool=callgrind")
+                .arg("--quiet")
+                .arg("--collect-atstart=no")
+                .arg("--cache-sim=yes")
+                .arg("--branch-sim=yes")
+                // JIT-compiled Wasm code is not backed by a file, so Valgrind must check it for
+                // self-modifying code.
+                .arg("--smc-check=all-non-file")
+                .arg(format!(
+                    "--callgrind-out-file={}",
+                    dir.join("callgrind.out.%p").display()
+                ))
+                .arg(this_exe);
+            return command;
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = callgrind_dir;
+        Command::new(this_exe)
+    }
+
+    /// Read the `benchmark.toml` of each Wasm file, keeping the benchmarks with any of the
+    /// `--tags` and with the `--input-size`.
+    fn benchmarks(&self) -> Result<Vec<(PathBuf, BenchmarkConfig)>> {
+        let wasm_files = match self.suite()? {
+            Some(suite) => suite.wasm_files(&self.wasm_files)?,
+            None => self.wasm_files.clone(),
+        };
+        let mut benchmarks = vec![];
+        for wasm in wasm_files {
+            let config = BenchmarkConfig::read(&wasm)?;
+            if !self.tags.is_empty() && !config.has_any_tag(&self.tags) {
+                log::info!("Skipping {}, which has none of the tags", wasm.display());
+            } else if config.input_dir(&self.input_size).is_none() {
+                log::warn!(
+                    "Skipping {}, which has no {} input",
+                    wasm.display(),
+                    self.input_size
+                );
+            } else {
+                benchmarks.push((wasm, config));
+            }
+        }
+        if benchmarks.is_empty() {
+            match self.tags.as_slice() {
+                [] => anyhow::bail!("none of the benchmarks have a {} input", self.input_size),
+                tags => anyhow::bail!(
+                    "none of the benchmarks have any of the tags ({}) and a {} input",
+                    tags.join(", "),
+                    self.input_size
+                ),
+            }
+        }
+        Ok(benchmarks)
+    }
+
+    /// Open the `--suite`, if any.
+    fn suite(&self) -> Result<Option<Suite>> {
+        self.suite.as_deref().map(Suite::open).transpose()
+    }
+
+    /// The input size to record in each measurement: `None` for the default input.
+    fn recorded_input_size(&self) -> Option<&str> {
+        Some(self.input_size.as_str()).filter(|&size| size != DEFAULT_INPUT_SIZE)
+    }
+
+    /// Check the Wasm files of this run against those in the manifest given by `--compare-wasm`,
+    /// if any.
+    fn compare_wasm(&self, manifest: &Manifest) -> Result<()> {
+        let path = match &self.compare_wasm {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let other = Manifest::read(io::BufReader::new(
+            fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?,
+        ))?;
+        let mut differing = vec![];
+        for (wasm, digest) in &manifest.wasm_sha256 {
+            match other.wasm_sha256.get(wasm) {
+                Some(other_digest) if other_digest == digest => {}
+                Some(other_digest) => {
+                    differing.push(format!("{} ({} vs. {})", wasm, digest, other_digest))
+                }
+                None => log::warn!("{} is not in {}", wasm, path.display()),
+            }
+        }
+        anyhow::ensure!(
+            differing.is_empty(),
+            "these Wasm files differ from those in {}:\n  {}",
+            path.display(),
+            differing.join("\n  ")
+        );
+        Ok(())
+    }
+
+    /// The digests of the Wasm files for the manifest, computed only when the manifest is used:
+    /// hashing a large Wasm file is not free, and the child processes of a multi-process run have
+    /// no use for them.
+    fn wasm_hashes(&self, mapped: &BTreeMap<String, Mmap>) -> BTreeMap<String, String> {
+        if self.manifest.is_none() && self.archive.is_none() && self.compare_wasm.is_none() {
+            return BTreeMap::new();
+        }
+        wasm_hashes(mapped)
+    }
+
+    /// The digest of an engine's shared library for the manifest, computed only when the manifest
+    /// is used (see [Self::wasm_hashes]).
+    fn engine_hash(&self, engine: &Path) -> Result<Option<String>> {
+        if self.manifest.is_none() && self.archive.is_none() {
+            return Ok(None);
+        }
+        let file = fs::File::open(engine)
+            .with_context(|| format!("failed to open {}", engine.display()))?;
+        // SAFETY: as for the Wasm files (see `map_wasm`), an engine rebuilt during the run would
+        // invalidate it anyway.
+        let map = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("failed to memory-map {}", engine.display()))?;
+        Ok(Some(crate::fetch_engine::sha256(&map)))
+    }
+
+    /// Describe how the run was configured, and the machine it ran on, for the manifest; only when
+    /// the manifest is used or the run is recorded (see [Self::wasm_hashes]).
+    fn run_manifest(&self, measurements: &[Measurement<'_>]) -> Option<RunManifest> {
+        if self.manifest.is_none() && self.archive.is_none() && self.no_record {
+            return None;
+        }
+        let (processes, iterations_per_process) = self.schedule();
+        let events: BTreeSet<_> = measurements.iter().map(|m| m.event.to_string()).collect();
+        let machine = match Machine::fingerprint().and_then(|m| Ok(serde_json::to_value(m)?)) {
+            Ok(machine) => Some(machine),
+            Err(e) => {
+                log::warn!("Failed to describe the machine for the manifest: {:#}", e);
+                None
+            }
+        };
+        Some(RunManifest {
+            sightglass_version: env!("CARGO_PKG_VERSION").into(),
+            args: std::env::args().collect(),
+            directory: std::env::current_dir()
+                .map(|dir| dir.display().to_string())
+                .unwrap_or_default(),
+            datetime: chrono::Local::now().to_rfc3339(),
+            processes,
+            iterations_per_process,
+            measure: self.measure.to_string(),
+            events: events.into_iter().collect(),
+            machine,
+        })
+    }
+
+    /// Write the run's manifest to the file given by `--manifest`, if any.
+    fn write_manifest(&self, manifest: &Manifest) -> Result<()> {
+        if let Some(path) = &self.manifest {
+            let file = fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            manifest.write(BufWriter::new(file))?;
+        }
         Ok(())
     }
 
+    /// Record the run, with its `manifest`, in the local results store, unless `--no-record` is
+    /// passed. A run is worth more than its record, so failing to record it is only a warning.
+    fn record(&self, measurements: &[Measurement<'_>], manifest: &Manifest) {
+        if self.no_record || measurements.is_empty() {
+            return;
+        }
+        match Store::open()
+            .and_then(|mut store| store.record(measurements, self.label.as_deref(), Some(manifest)))
+        {
+            Ok(run) => log::info!("Recorded the run in the results store as run {}", run),
+            Err(e) => log::warn!("Failed to record the run in the results store: {:#}", e),
+        }
+    }
+
+    /// Archive the run's raw results to the `--archive` location, if any.
+    fn archive(&self, measurements: &[Measurement<'_>], manifest: &Manifest) -> Result<()> {
+        if let Some(location) = &self.archive {
+            crate::archive::archive(location, measurements, manifest)?;
+        }
+        Ok(())
+    }
+
+    /// Open the output: the `--output-file` or `stdout`. When the raw results are streamed (see
+    /// `--flush-every`), the output is the stream's, and the rest of the output is discarded.
+    fn output(&self) -> Result<(Box<dyn Write>, Option<SharedStream>)> {
+        let output: Box<dyn Write> = if let Some(file) = self.output_file.as_ref() {
+            Box::new(BufWriter::new(fs::File::create(file)?))
+        } else {
+            Box::new(io::stdout())
+        };
+        if self.flush().is_none() {
+            return Ok((output, None));
+        }
+        if self.flush() != Some(Flush::Benchmark) && self.overhead == OverheadMode::Subtract {
+            bail!("the overhead can only be subtracted when flushing after each benchmark");
+        }
+        let stream = ResultStream::new(self.output_format.clone(), self.zstd, output);
+        Ok((Box::new(io::sink()), Some(Rc::new(RefCell::new(stream)))))
+    }
+
+    /// The live table of the run (see `--tui`), if it is shown.
+    fn live_table(&self) -> Option<LiveTable> {
+        if !self.tui {
+            return None;
+        }
+        if !io::stderr().is_terminal() {
+            log::warn!("Not showing the live table (--tui): stderr is not a terminal");
+            return None;
+        }
+        Some(LiveTable::default())
+    }
+
+    /// How often to flush streamed raw results, if they are streamed.
+    fn flush(&self) -> Option<Flush> {
+        self.flush_every.or(self.zstd.then_some(Flush::Benchmark))
+    }
+
+    /// Write the results of the run to `output_file` (or finish the `stream` of them), then report
+    /// them to each of the other sinks asked for.
     fn write_results(
         &self,
         measurements: &[Measurement<'_>],
+        stream: Option<SharedStream>,
         output_file: &mut dyn Write,
     ) -> Result<()> {
-        if self.raw {
-            self.output_format.write(measurements, output_file)?;
-        } else if self.engines.len() == 2 {
+        self.write_output(measurements, stream, output_file)?;
+        self.report_effect_sizes(measurements)?;
+        if let Some(url) = &self.pushgateway {
+            push_summaries(url, &self.pushgateway_job, measurements)?;
+        }
+        Ok(())
+    }
+
+    /// Write the raw measurements, their effect size, or their summaries to `output_file`; streamed
+    /// raw results have already been written, so the `stream` is just finished.
+    fn write_output(
+        &self,
+        measurements: &[Measurement<'_>],
+        stream: Option<SharedStream>,
+        output_file: &mut dyn Write,
+    ) -> Result<()> {
+        if let Some(stream) = stream {
+            Rc::try_unwrap(stream)
+                .map_err(|_| anyhow!("the results are still being written"))?
+                .into_inner()
+                .finish()?;
+        } else if self.raw {
+            self.output_format
+                .write_measurements(measurements, output_file)?;
+        } else if self.engine_runs().len() == 2 {
             display_effect_size(measurements, self.significance_level, output_file)?;
         } else {
             display_summaries(measurements, output_file)?;
@@ -395,15 +1520,199 @@ impl BenchmarkCommand {
         Ok(())
     }
 
+    /// Report the effect size of a comparison of two engines to the GitHub step summary, a pull
+    /// request comment, and a regression webhook, as asked for.
+    fn report_effect_sizes(&self, measurements: &[Measurement<'_>]) -> Result<()> {
+        let reports_effect_sizes = self.github_step_summary
+            || self.post_github_comment.is_some()
+            || self.notify_url.is_some();
+        if !reports_effect_sizes || self.engine_runs().len() != 2 {
+            return Ok(());
+        }
+        let effect_sizes =
+            sightglass_analysis::effect_size::calculate(self.significance_level, measurements)?;
+        if self.github_step_summary {
+            crate::effect_size::write_github_step_summary(
+                effect_sizes.clone(),
+                self.significance_level,
+                sightglass_analysis::units::DEFAULT_PRECISION,
+            )?;
+        }
+        if let Some(url) = &self.notify_url {
+            notify_regressions(url, self.notify_slack, self.notify_threshold, &effect_sizes)?;
+        }
+        if let Some(pr) = &self.post_github_comment {
+            crate::effect_size::post_github_comment(
+                pr,
+                effect_sizes,
+                self.significance_level,
+                sightglass_analysis::units::DEFAULT_PRECISION,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// List each engine to run with each set of engine flags, as `(engine, flags, label)`. When
+    /// several sets of flags are passed, each is recorded under its own engine label; the native
+    /// baseline takes no flags and runs once. With `--compilers`, each of these runs once per
+    /// compiler, labeled by the compiler (and, if there are several, the engine and flags).
+    fn engine_runs(&self) -> Vec<(String, Option<String>, String)> {
+        let mut runs = vec![];
+        for engine in &self.engines {
+            let name = if is_native(engine)
+                || sightglass_build::parse_wasmtime_revision(engine).is_some()
+            {
+                engine.clone()
+            } else {
+                self.recorded_path(Path::new(engine))
+            };
+            if is_native(engine) || (self.engine_flags.is_empty() && self.compilers.is_empty()) {
+                runs.push((engine.clone(), None, name));
+                continue;
+            }
+            let all_flags = match self.engine_flags.as_slice() {
+                [] => vec![None],
+                flags => flags.iter().map(Some).collect(),
+            };
+            for flags in all_flags {
+                if self.compilers.is_empty() {
+                    let flags = flags.unwrap();
+                    let label = engine_label(&name, flags, self.engine_flags.len() > 1);
+                    runs.push((engine.clone(), Some(flags.clone()), label));
+                    continue;
+                }
+                for compiler in &self.compilers {
+                    let mut label = compiler.to_string();
+                    if self.engines.len() > 1 {
+                        label = format!("{} {}", name, label);
+                    }
+                    if let (Some(flags), true) = (flags, self.engine_flags.len() > 1) {
+                        label = format!("{} [{}]", label, flags);
+                    }
+                    let flags = match flags {
+                        Some(flags) => format!("{} {}", flags, compiler.flags()),
+                        None => compiler.flags().to_string(),
+                    };
+                    runs.push((engine.clone(), Some(flags), label));
+                }
+            }
+        }
+        if self.engine_labels.len() == runs.len() {
+            for (run, label) in runs.iter_mut().zip(&self.engine_labels) {
+                run.2 = label.clone();
+            }
+        }
+        runs
+    }
+
+    /// Check that the `--engine-label`s, if any, name each engine run once.
+    fn check_engine_labels(&self) -> Result<()> {
+        if self.engine_labels.is_empty() {
+            return Ok(());
+        }
+        let runs = self.engine_runs();
+        anyhow::ensure!(
+            self.engine_labels.len() == runs.len(),
+            "found {} engine labels for {} engine runs; pass one --engine-label for each, in \
+             order: {}",
+            self.engine_labels.len(),
+            runs.len(),
+            runs.iter()
+                .map(|(engine, flags, _)| match flags {
+                    Some(flags) => format!("{} [{}]", engine, flags),
+                    None => engine.clone(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let mut seen = BTreeSet::new();
+        for label in &self.engine_labels {
+            anyhow::ensure!(
+                seen.insert(label),
+                "the engine label {} is given more than once",
+                label
+            );
+        }
+        Ok(())
+    }
+
+    /// Describe the benchmarks with tags, engine flags, or notes in their `benchmark.toml`, or
+    /// with a `--benchmark-note`, for the run's manifest.
+    fn benchmark_manifests(
+        &self,
+        benchmarks: &[(PathBuf, BenchmarkConfig)],
+    ) -> Result<Vec<BenchmarkManifest>> {
+        let mut notes: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for note in &self.benchmark_notes {
+            let (wasm, note) = match note.split_once('=') {
+                Some(split) => split,
+                None => bail!("a benchmark note must be BENCHMARK=NOTE: {}", note),
+            };
+            anyhow::ensure!(
+                benchmarks.iter().any(|(w, _)| w == Path::new(wasm)),
+                "the benchmark note names {}, which is not one of the benchmarks run",
+                wasm
+            );
+            notes.entry(wasm).or_default().push(note.to_string());
+        }
+        Ok(benchmarks
+            .iter()
+            .map(|(wasm, config)| {
+                let wasm = wasm.display().to_string();
+                let mut benchmark_notes = config.notes.clone();
+                benchmark_notes.extend(notes.remove(wasm.as_str()).unwrap_or_default());
+                BenchmarkManifest {
+                    wasm,
+                    tags: config.tags.clone(),
+                    engine_flags: config.engine_flags.clone(),
+                    notes: benchmark_notes,
+                }
+            })
+            .filter(|b| !b.tags.is_empty() || b.engine_flags.is_some() || !b.notes.is_empty())
+            .collect())
+    }
+
+    /// The path of an engine or Wasm file as recorded in the measurements: relative to the
+    /// `--path-root` if it is within it (both with their symbolic links resolved), and otherwise as
+    /// given, normalized (see `normalize_path`). With `--keep-paths`, it is recorded as given.
+    fn recorded_path(&self, path: &Path) -> String {
+        let given = path.display().to_string();
+        if self.keep_paths {
+            return given;
+        }
+        if let (Ok(root), Ok(path)) = (self.path_root.canonicalize(), path.canonicalize()) {
+            if let Ok(relative) = path.strip_prefix(root) {
+                let components: Vec<_> = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                return components.join("/");
+            }
+        }
+        sightglass_data::normalize_path(&given).into_owned()
+    }
+
+    /// The thread counts with which to run each benchmark; `None` runs it without threads.
+    fn thread_counts(&self) -> Vec<Option<u32>> {
+        match self.wasm_threads.as_slice() {
+            [] => vec![None],
+            counts => counts.iter().copied().map(Some).collect(),
+        }
+    }
+
     /// Determine the working directory in which to run the benchmark using:
     /// - first, any directory specified with `--working-dir`
-    /// - then, the parent directory of the Wasm file
+    /// - then, the parent directory of the Wasm file (or, for `--input-size`, the input directory)
     /// - and if all else fails, the current working directory of the process.
-    fn get_working_directory(&self, wasm_file: &impl AsRef<Path>) -> Result<PathBuf> {
+    fn get_working_directory(
+        &self,
+        wasm_file: &impl AsRef<Path>,
+        config: &BenchmarkConfig,
+    ) -> Result<PathBuf> {
         let working_dir = if let Some(dir) = self.working_dir.clone() {
             dir
         } else if let Some(dir) = wasm_file.as_ref().parent() {
-            dir.into()
+            dir.join(config.input_dir(&self.input_size).unwrap())
         } else {
             std::env::current_dir().context("failed to get the current working directory")?
         };
@@ -411,7 +1720,7 @@ impl BenchmarkCommand {
     }
 }
 
-fn this_arch() -> &'static str {
+pub fn this_arch() -> &'static str {
     if cfg!(target_arch = "x86_64") {
         "x86_64"
     } else if cfg!(target_arch = "aarch64") {
@@ -426,26 +1735,443 @@ fn display_effect_size(
     significance_level: f64,
     output_file: &mut dyn Write,
 ) -> Result<()> {
-    let effect_sizes =
-        sightglass_analysis::effect_size::calculate(significance_level, measurements)?;
+    let (effect_sizes, skipped) = sightglass_analysis::effect_size::calculate_skipping(
+        significance_level,
+        None,
+        &measurements.iter().collect(),
+    )?;
     let summaries = sightglass_analysis::summarize::calculate(measurements);
     sightglass_analysis::effect_size::write(
         effect_sizes,
         &summaries,
         significance_level,
+        sightglass_analysis::units::Formatting::default(),
         output_file,
-    )
+    )?;
+    sightglass_analysis::effect_size::write_skipped(&skipped, output_file)
 }
 
 fn display_summaries(measurements: &[Measurement<'_>], output_file: &mut dyn Write) -> Result<()> {
     let summaries = sightglass_analysis::summarize::calculate(measurements);
-    sightglass_analysis::summarize::write(summaries, output_file)
+    sightglass_analysis::summarize::write(
+        summaries,
+        sightglass_analysis::units::Formatting::default(),
+        output_file,
+    )
+}
+
+/// Push the summaries of `measurements` to the Prometheus pushgateway at `url`, replacing the
+/// metrics of `job` there.
+fn push_summaries(url: &str, job: &str, measurements: &[Measurement<'_>]) -> Result<()> {
+    let summaries = sightglass_analysis::summarize::calculate(measurements);
+    let mut body = vec![];
+    sightglass_analysis::prometheus::write(&summaries, &mut body)?;
+    let url = format!("{}/metrics/job/{}", url.trim_end_matches('/'), job);
+    reqwest::blocking::Client::new()
+        .put(&url)
+        .body(body)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to push metrics to {}", url))?;
+    log::info!("Pushed {} summaries to {}", summaries.len(), url);
+    Ok(())
+}
+
+/// Label an engine with the flags it runs with, if there is more than one set of flags to tell
+/// apart; e.g., `engines/wasmtime/libengine.so [-O opt-level=0]`.
+fn engine_label(engine: &str, flags: &str, labeled: bool) -> String {
+    if labeled {
+        format!("{} [{}]", engine, flags)
+    } else {
+        engine.to_string()
+    }
+}
+
+/// Describe the Wasm files that were built as variants of a benchmark, for the run's manifest.
+fn variants(benchmarks: &[(PathBuf, BenchmarkConfig)]) -> Result<Vec<Variant>> {
+    let mut variants = vec![];
+    for (wasm, _) in benchmarks {
+        if let Some(variant) = sightglass_build::read_variant(wasm)
+            .with_context(|| format!("failed to read the variant of {}", wasm.display()))?
+        {
+            variants.push(Variant {
+                wasm: wasm.display().to_string(),
+                name: variant.name,
+                flags: variant.flags,
+                wasm_opt: variant.wasm_opt,
+            });
+        }
+    }
+    Ok(variants)
+}
+
+/// Hash each Wasm file, for the run's manifest.
+fn wasm_hashes(wasm: &BTreeMap<String, Mmap>) -> BTreeMap<String, String> {
+    wasm.iter()
+        .map(|(wasm, bytes)| (wasm.clone(), crate::fetch_engine::sha256(bytes)))
+        .collect()
+}
+
+/// Memory-map each benchmark's Wasm file, by path, once for the whole run: the same bytes serve
+/// each engine, entry point, and thread count, and processes benchmarking the same file share its
+/// pages rather than each reading its own copy (e.g., of a multi-hundred-MB SpiderMonkey).
+fn map_wasm(benchmarks: &[(PathBuf, BenchmarkConfig)]) -> Result<BTreeMap<String, Mmap>> {
+    let mut maps = BTreeMap::new();
+    for (wasm, _) in benchmarks {
+        let file =
+            fs::File::open(wasm).with_context(|| format!("failed to open {}", wasm.display()))?;
+        // SAFETY: the Wasm files must not be modified while they are benchmarked, which would
+        // invalidate the run anyway.
+        let map = unsafe { Mmap::map(&file) }
+            .with_context(|| format!("failed to memory-map {}", wasm.display()))?;
+        maps.insert(wasm.display().to_string(), map);
+    }
+    Ok(maps)
+}
+
+/// Run `command` to completion, collecting its output, unless it is still running after `timeout`,
+/// in which case it is killed; the `bool` is whether it was.
+fn run_with_timeout(command: &mut Command, timeout: Option<Duration>) -> Result<(Output, bool)> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok((command.output()?, false)),
+    };
+    let mut child = command.spawn()?;
+    // Read the pipes as the child writes them, so that it cannot block on a full pipe.
+    let read = |pipe: Option<Box<dyn io::Read + Send>>| {
+        thread::spawn(move || {
+            let mut buf = vec![];
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buf);
+            }
+            buf
+        })
+    };
+    let stdout = read(child.stdout.take().map(|p| Box::new(p) as _));
+    let stderr = read(child.stderr.take().map(|p| Box::new(p) as _));
+    let deadline = Instant::now() + timeout;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill()?;
+            timed_out = true;
+            break child.wait()?;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap_or_default(),
+        stderr: stderr.join().unwrap_or_default(),
+    };
+    Ok((output, timed_out))
+}
+
+/// Fail the run if any of its benchmarks failed, once everything else has been written out.
+fn check_failures(manifest: &Manifest) -> Result<()> {
+    match manifest.failures.len() {
+        0 => Ok(()),
+        1 => Err(anyhow!("1 benchmark failed")),
+        n => Err(anyhow!("{} benchmarks failed", n)),
+    }
+}
+
+/// Track the last phase that a benchmark started, so that a failure can be attributed to it.
+struct PhaseTracker(Rc<Cell<Option<Phase>>>);
+
+impl Measure for PhaseTracker {
+    fn start(&mut self, phase: Phase) {
+        self.0.set(Some(phase));
+    }
+
+    fn end(&mut self, _phase: Phase, _measurements: &mut Measurements) {}
+}
+
+/// Describe the payload of a caught panic; e.g., a failed bench API assertion.
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "the benchmark panicked".to_string()
+    }
+}
+
+/// The last lines of a benchmark's `stderr` file, for recording a failure.
+fn tail(stderr: &Path) -> String {
+    match fs::read(stderr) {
+        Ok(bytes) => tail_lines(&String::from_utf8_lossy(&bytes)),
+        Err(_) => String::new(),
+    }
+}
+
+fn tail_lines(output: &str) -> String {
+    const LINES: usize = 20;
+    let lines: Vec<_> = output.lines().collect();
+    let mut tail = lines[lines.len().saturating_sub(LINES)..].join("\n");
+    if !tail.is_empty() {
+        tail.push('\n');
+    }
+    tail
+}
+
+/// A digest of each benchmark's output, by benchmark label, along with the engine that first
+/// produced it.
+#[derive(Default)]
+struct OutputDigests(BTreeMap<String, (String, String)>);
+
+impl OutputDigests {
+    /// Check that the output of `benchmark` run in `engine` matches that of every earlier run; a
+    /// speedup is meaningless if one engine computes a different answer than another.
+    fn check(&mut self, benchmark: &str, engine: &str, digest: &str) -> Result<()> {
+        match self.0.get(benchmark) {
+            Some((first_engine, first_digest)) if first_digest != digest => {
+                if first_engine == engine {
+                    anyhow::bail!(
+                        "the output of {} differs between runs in {}",
+                        benchmark,
+                        engine
+                    );
+                }
+                anyhow::bail!(
+                    "the output of {} differs between engines {} and {}; check their \
+                     `stdout-*.log` and `stderr-*.log` files",
+                    benchmark,
+                    first_engine,
+                    engine
+                );
+            }
+            Some(_) => {}
+            None => {
+                self.0.insert(
+                    benchmark.to_string(),
+                    (engine.to_string(), digest.to_string()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// The digest of each benchmark's output.
+    fn digests(&self) -> BTreeMap<&str, &str> {
+        self.0
+            .iter()
+            .map(|(benchmark, (_, digest))| (benchmark.as_str(), digest.as_str()))
+            .collect()
+    }
+}
+
+/// Compute a digest of a benchmark's `stdout`, comparing lines so that `\n` on *nix and `\r\n` on
+/// Windows are the same. The benchmark's `stderr` is left out: engines log to it (e.g., warnings)
+/// in ways that need not match between engines, or even runs.
+fn output_digest(stdout: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let output = fs::read_to_string(stdout)
+        .with_context(|| format!("failed to read `{}`", stdout.display()))?;
+    for line in output.lines() {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+/// One of Wasmtime's compilers, selected with `--compilers`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compiler {
+    Winch,
+    Cranelift,
+}
+
+impl Compiler {
+    /// The Wasmtime flags that select this compiler.
+    fn flags(&self) -> &'static str {
+        match self {
+            Compiler::Winch => "-C compiler=winch",
+            Compiler::Cranelift => "-C compiler=cranelift",
+        }
+    }
+}
+
+impl std::fmt::Display for Compiler {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Compiler::Winch => write!(f, "winch"),
+            Compiler::Cranelift => write!(f, "cranelift"),
+        }
+    }
+}
+
+impl std::str::FromStr for Compiler {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "winch" => Ok(Self::Winch),
+            "cranelift" => Ok(Self::Cranelift),
+            _ => Err("unknown compiler; expected winch or cranelift"),
+        }
+    }
+}
+
+/// Describe an engine of the run for its manifest: the engine `label`ed so in the measurements, with
+/// its library's `path` (none for the native baseline) and `sha256`, its `flags`, and the
+/// `build_info` it reports, which names its kind.
+fn engine_manifest(
+    label: &str,
+    path: Option<String>,
+    sha256: Option<String>,
+    flags: Option<String>,
+    build_info: Vec<(String, String)>,
+) -> EngineManifest {
+    EngineManifest {
+        engine: label.to_string(),
+        kind: match &path {
+            Some(_) => engine_kind(&build_info),
+            None => Some("native".into()),
+        },
+        path,
+        sha256,
+        flags,
+        build_info: build_info.into_iter().collect(),
+    }
+}
+
+/// What an engine library reports of itself through its bench API; see [check_engine_api].
+struct CheckedEngine {
+    /// The build information the engine reports, for the run's manifest.
+    build_info: Vec<(String, String)>,
+    /// The Wasm proposals the engine supports with its flags, if it reports them.
+    supported_proposals: Option<Vec<String>>,
+}
+
+/// Check the engine library's bench API handshake before spawning any benchmark processes, so that
+/// an incompatible library is reported once and clearly rather than by every subprocess. This
+/// returns what the engine reports of itself with `engine_flags`.
+fn check_engine_api(
+    engine: &Path,
+    engine_flags: Option<&str>,
+    precompiled: bool,
+    threaded: bool,
+) -> Result<CheckedEngine> {
+    let lib = unsafe { libloading::Library::new(engine)? };
+    let mut bench_api = unsafe { BenchApi::new(&lib) }
+        .with_context(|| format!("incompatible engine: {}", engine.display()))?;
+    if precompiled {
+        check_precompile(&engine.display().to_string(), Some(&bench_api))?;
+    }
+    if threaded {
+        check_threads(&engine.display().to_string(), Some(&bench_api))?;
+    }
+    Ok(CheckedEngine {
+        supported_proposals: supported_proposals(&mut bench_api, Path::new("."), engine_flags),
+        build_info: bench_api.build_info(),
+    })
+}
+
+/// Detect the Wasm proposals that a benchmark requires. The detection may not know every proposal
+/// an engine does, so a benchmark it cannot validate is left to the engine.
+fn proposals_of(wasm_file: &str, bytes: &[u8]) -> Vec<Proposal> {
+    match sightglass_build::required_proposals(bytes) {
+        Ok(proposals) => {
+            if !proposals.is_empty() {
+                let names: Vec<_> = proposals.iter().map(ToString::to_string).collect();
+                log::info!("{} requires: {}", wasm_file, names.join(", "));
+            }
+            proposals
+        }
+        Err(e) => {
+            log::warn!(
+                "Unable to detect the proposals {} requires: {}",
+                wasm_file,
+                e
+            );
+            vec![]
+        }
+    }
+}
+
+/// List the `required` proposals that are not among those an engine reports as `supported`; an
+/// engine that does not report them is assumed to support them all.
+fn unsupported_proposals(required: &[Proposal], supported: Option<&[String]>) -> Vec<String> {
+    let supported = match supported {
+        Some(supported) => supported,
+        None => return vec![],
+    };
+    required
+        .iter()
+        .map(ToString::to_string)
+        .filter(|proposal| !supported.contains(proposal))
+        .collect()
+}
+
+/// Check that the engine can be used with `--precompiled`; the native baseline (without a
+/// `BenchApi`) has nothing to precompile.
+fn check_precompile(engine: &str, bench_api: Option<&BenchApi<'_>>) -> Result<()> {
+    match bench_api {
+        Some(bench_api) => anyhow::ensure!(
+            bench_api.capabilities().contains(&Capability::Precompile),
+            "the engine does not support precompiled modules: {}",
+            engine
+        ),
+        None => anyhow::bail!("the native baseline does not support precompiled modules"),
+    }
+    Ok(())
+}
+
+fn check_repeated_execution(engine: &str, bench_api: Option<&BenchApi<'_>>) -> Result<()> {
+    match bench_api {
+        Some(bench_api) => anyhow::ensure!(
+            bench_api
+                .capabilities()
+                .contains(&Capability::RepeatedExecution),
+            "the engine cannot execute an instance repeatedly: {}",
+            engine
+        ),
+        None => anyhow::bail!("the native baseline cannot execute an instance repeatedly"),
+    }
+    Ok(())
+}
+
+/// Check that the engine can run benchmarks with `--wasm-threads`.
+fn check_threads(engine: &str, bench_api: Option<&BenchApi<'_>>) -> Result<()> {
+    match bench_api {
+        Some(bench_api) => anyhow::ensure!(
+            bench_api.capabilities().contains(&Capability::Threads),
+            "the engine cannot run threaded benchmarks: {}",
+            engine
+        ),
+        None => anyhow::bail!("the native baseline cannot run threaded benchmarks"),
+    }
+    Ok(())
+}
+
+/// Check whether the passed engine selects the native baseline, which runs the natively compiled
+/// library shipped alongside each Wasm benchmark (e.g., `benchmark.so`) instead of the Wasm.
+#[cfg(target_os = "linux")]
+pub(crate) fn is_native(engine: &str) -> bool {
+    engine == sightglass_recorder::native::NATIVE_ENGINE
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn is_native(_engine: &str) -> bool {
+    false
 }
 
 // Check that a passed engine path is indeed a valid path; the returned value is a path to the built
-// engine's dylib.
+// engine's dylib. An engine like `wasmtime:main` is built from that revision of Wasmtime first (or
+// found in the cache, if it has been built before).
 pub fn check_engine_path(engine: &str) -> Result<PathBuf> {
-    if Path::new(engine).exists() {
+    if let Some(revision) = sightglass_build::parse_wasmtime_revision(engine) {
+        sightglass_build::build_wasmtime(sightglass_build::WASMTIME_REPOSITORY, revision)
+            .with_context(|| format!("failed to build engine: {}", engine))
+    } else if Path::new(engine).exists() {
         log::debug!("Using engine path: {}", engine);
         Ok(PathBuf::from(engine))
     } else {
@@ -472,33 +2198,33 @@ mod tests {
 compilation
   benchmarks/pulldown-cmark/benchmark.wasm
     cycles
-      [696450758 740410589.60 823537015] /tmp/old_backend.so
-      [688475571 710846289.20 796284592] /tmp/old_backend_2.so
-      [721352134 776890922.40 933479759] /tmp/old_backend_3.so
+      [696 M 740 M 824 M] /tmp/old_backend.so
+      [688 M 711 M 796 M] /tmp/old_backend_2.so
+      [721 M 777 M 933 M] /tmp/old_backend_3.so
     nanoseconds
-      [239819667 254957035.80 283581244] /tmp/old_backend.so
-      [237074550 244777841.50 274198271] /tmp/old_backend_2.so
-      [248392822 267517235.10 321437562] /tmp/old_backend_3.so
+      [240 ms 255 ms 284 ms] /tmp/old_backend.so
+      [237 ms 245 ms 274 ms] /tmp/old_backend_2.so
+      [248 ms 268 ms 321 ms] /tmp/old_backend_3.so
 instantiation
   benchmarks/pulldown-cmark/benchmark.wasm
     cycles
-      [186145 213469.60 229974] /tmp/old_backend.so
-      [200003 220099.00 308810] /tmp/old_backend_2.so
-      [203474 233069.30 300269] /tmp/old_backend_3.so
+      [186 k 213 k 230 k] /tmp/old_backend.so
+      [200 k 220 k 309 k] /tmp/old_backend_2.so
+      [203 k 233 k 300 k] /tmp/old_backend_3.so
     nanoseconds
-      [64098 73506.90 79190] /tmp/old_backend.so
-      [68870 75789.90 106337] /tmp/old_backend_2.so
-      [70064 80255.30 103395] /tmp/old_backend_3.so
+      [64.1 µs 73.5 µs 79.2 µs] /tmp/old_backend.so
+      [68.9 µs 75.8 µs 106 µs] /tmp/old_backend_2.so
+      [70.1 µs 80.3 µs 103 µs] /tmp/old_backend_3.so
 execution
   benchmarks/pulldown-cmark/benchmark.wasm
     cycles
-      [10334150 12342413.00 14169904] /tmp/old_backend.so
-      [10328193 10829803.50 12631959] /tmp/old_backend_2.so
-      [10569938 11690281.50 16792916] /tmp/old_backend_3.so
+      [10.3 M 12.3 M 14.2 M] /tmp/old_backend.so
+      [10.3 M 10.8 M 12.6 M] /tmp/old_backend_2.so
+      [10.6 M 11.7 M 16.8 M] /tmp/old_backend_3.so
     nanoseconds
-      [3558517 4250053.60 4879342] /tmp/old_backend.so
-      [3556483 3729210.70 4349778] /tmp/old_backend_2.so
-      [3639688 4025470.30 5782529] /tmp/old_backend_3.so
+      [3.56 ms 4.25 ms 4.88 ms] /tmp/old_backend.so
+      [3.56 ms 3.73 ms 4.35 ms] /tmp/old_backend_2.so
+      [3.64 ms 4.03 ms 5.78 ms] /tmp/old_backend_3.so
 "#;
         eprintln!("=== Expected ===\n{}", expected);
 
@@ -520,57 +2246,450 @@ execution
         let expected = r#"
 compilation :: cycles :: benchmarks/pulldown-cmark/benchmark.wasm
 
-  Δ = 231879938.88 ± 5920528.32 (confidence = 95%)
+  Δ = 232 M ± 5.92 M (confidence = 95%)
 
   old_backend.so is 1.32x to 1.34x faster than new_backend.so!
 
-  [889384088 935555419.78 1045075629] new_backend.so
-  [688072501 703675480.90 826253416] old_backend.so
+  [889 M 936 M 1.05 G] new_backend.so
+  [688 M 704 M 826 M] old_backend.so
 
 compilation :: nanoseconds :: benchmarks/pulldown-cmark/benchmark.wasm
 
-  Δ = 79845660.57 ± 2038688.33 (confidence = 95%)
+  Δ = 79.8 ms ± 2.04 ms (confidence = 95%)
 
   old_backend.so is 1.32x to 1.34x faster than new_backend.so!
 
-  [306252409 322151144.14 359863566] new_backend.so
-  [236932712 242305483.57 284514295] old_backend.so
+  [306 ms 322 ms 360 ms] new_backend.so
+  [237 ms 242 ms 285 ms] old_backend.so
 
 execution :: nanoseconds :: benchmarks/pulldown-cmark/benchmark.wasm
 
-  Δ = 467229.61 ± 57708.35 (confidence = 95%)
+  Δ = 467 µs ± 57.7 µs (confidence = 95%)
 
   new_backend.so is 1.13x to 1.16x faster than old_backend.so!
 
-  [3061587 3240065.98 4419514] new_backend.so
-  [3510983 3707295.59 5811112] old_backend.so
+  [3.06 ms 3.24 ms 4.42 ms] new_backend.so
+  [3.51 ms 3.71 ms 5.81 ms] old_backend.so
 
 execution :: cycles :: benchmarks/pulldown-cmark/benchmark.wasm
 
-  Δ = 1356859.60 ± 167590.00 (confidence = 95%)
+  Δ = 1.36 M ± 168 k (confidence = 95%)
 
   new_backend.so is 1.13x to 1.16x faster than old_backend.so!
 
-  [8891120 9409439.69 12834660] new_backend.so
-  [10196192 10766299.29 16875960] old_backend.so
+  [8.89 M 9.41 M 12.8 M] new_backend.so
+  [10.2 M 10.8 M 16.9 M] old_backend.so
 
 instantiation :: cycles :: benchmarks/pulldown-cmark/benchmark.wasm
 
   No difference in performance.
 
-  [191466 207762.01 325810] new_backend.so
-  [179617 200451.81 334016] old_backend.so
+  [191 k 208 k 326 k] new_backend.so
+  [180 k 200 k 334 k] old_backend.so
 
 instantiation :: nanoseconds :: benchmarks/pulldown-cmark/benchmark.wasm
 
   No difference in performance.
 
-  [65929 71540.70 112190] new_backend.so
-  [61849 69023.59 115015] old_backend.so
+  [65.9 µs 71.5 µs 112 µs] new_backend.so
+  [61.8 µs 69.0 µs 115 µs] old_backend.so
 "#;
         eprintln!("=== Expected ===\n{}", expected);
 
         assert_eq!(actual.trim(), expected.trim());
         Ok(())
     }
+
+    #[test]
+    fn test_schedule() {
+        let command = |args: &[&str]| {
+            let mut all = vec!["benchmark", "--engine", "a.so"];
+            all.extend_from_slice(args);
+            all.extend_from_slice(&["--", "benchmark.wasm"]);
+            BenchmarkCommand::from_iter(all)
+        };
+        assert_eq!(command(&[]).schedule(), (10, 10));
+        assert_eq!(
+            command(&["--processes=2", "--iterations-per-process=3"]).schedule(),
+            (2, 3)
+        );
+        assert_eq!(
+            command(&[
+                "--processes=2",
+                "--iterations-per-process=3",
+                "--fresh-process"
+            ])
+            .schedule(),
+            (6, 1)
+        );
+    }
+
+    #[test]
+    fn test_engine_runs() {
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--engine",
+            "a.so",
+            "--engine",
+            "b.so",
+            "--engine-flags=-O opt-level=0",
+            "--engine-flags=-O opt-level=2",
+            "--",
+            "benchmark.wasm",
+        ]);
+        let labels: Vec<_> = command
+            .engine_runs()
+            .into_iter()
+            .map(|(_, _, label)| label)
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "a.so [-O opt-level=0]",
+                "a.so [-O opt-level=2]",
+                "b.so [-O opt-level=0]",
+                "b.so [-O opt-level=2]"
+            ]
+        );
+
+        // A single set of flags leaves the engines unlabeled.
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--engine",
+            "a.so",
+            "--engine-flags=-O opt-level=0",
+            "--",
+            "benchmark.wasm",
+        ]);
+        assert_eq!(
+            command.engine_runs(),
+            [(
+                "a.so".to_string(),
+                Some("-O opt-level=0".to_string()),
+                "a.so".to_string()
+            )]
+        );
+
+        // Labels name the runs in order, and must name each one once.
+        let labeled = |labels: &[&str]| {
+            let mut args = vec!["benchmark", "--engine", "a.so", "--engine", "b.so"];
+            for label in labels {
+                args.extend(["--engine-label", label]);
+            }
+            args.extend(["--", "benchmark.wasm"]);
+            BenchmarkCommand::from_iter(args)
+        };
+        let command = labeled(&["main", "pr-1234"]);
+        assert!(command.check_engine_labels().is_ok());
+        let labels: Vec<_> = command
+            .engine_runs()
+            .into_iter()
+            .map(|(engine, _, label)| (engine, label))
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                ("a.so".to_string(), "main".to_string()),
+                ("b.so".to_string(), "pr-1234".to_string())
+            ]
+        );
+        assert!(labeled(&["main"]).check_engine_labels().is_err());
+        assert!(labeled(&["main", "main"]).check_engine_labels().is_err());
+    }
+
+    #[test]
+    fn test_tail_lines() {
+        assert_eq!(tail_lines(""), "");
+        assert_eq!(tail_lines("a\nb"), "a\nb\n");
+        let long: String = (0..30).map(|i| format!("{}\n", i)).collect();
+        assert!(tail_lines(&long).starts_with("10\n"));
+    }
+
+    #[test]
+    fn test_engine_manifest() {
+        let wasmer = engine_manifest(
+            "wasmer",
+            Some("libengine.so".into()),
+            None,
+            None,
+            vec![
+                ("ENGINE".into(), "wasmer".into()),
+                ("WASMER_VERSION".into(), "6.0.0".into()),
+            ],
+        );
+        assert_eq!(wasmer.kind.as_deref(), Some("wasmer"));
+        assert_eq!(wasmer.build_info["WASMER_VERSION"], "6.0.0");
+        let manifest = Manifest {
+            engines: vec![wasmer],
+            ..Default::default()
+        };
+        let mut written = vec![];
+        manifest.write(&mut written).unwrap();
+        let read = Manifest::read(&written[..]).unwrap();
+        assert_eq!(read.engines[0].kind.as_deref(), Some("wasmer"));
+
+        let native = engine_manifest("native", None, None, None, vec![]);
+        assert_eq!(native.kind.as_deref(), Some("native"));
+        let unknown = engine_manifest("custom", Some("libcustom.so".into()), None, None, vec![]);
+        assert_eq!(unknown.kind, None);
+    }
+
+    #[test]
+    fn test_output_digests() {
+        let mut outputs = OutputDigests::default();
+        outputs.check("noop", "a.so", "1234").unwrap();
+        outputs.check("noop", "b.so", "1234").unwrap();
+        outputs.check("other", "b.so", "5678").unwrap();
+        let error = outputs.check("noop", "c.so", "5678").unwrap_err();
+        assert!(error.to_string().contains("between engines a.so and c.so"));
+        assert_eq!(outputs.digests().len(), 2);
+    }
+
+    #[test]
+    fn test_thread_counts() {
+        let command =
+            BenchmarkCommand::from_iter(["benchmark", "--engine", "a.so", "--", "a.wasm"]);
+        assert_eq!(command.thread_counts(), [None]);
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--engine",
+            "a.so",
+            "--wasm-threads",
+            "1,4",
+            "--",
+            "a.wasm",
+        ]);
+        assert_eq!(command.thread_counts(), [Some(1), Some(4)]);
+    }
+
+    #[test]
+    fn test_unsupported_proposals() {
+        let required = [Proposal::Simd, Proposal::TailCall];
+        assert!(unsupported_proposals(&required, None).is_empty());
+        assert_eq!(
+            unsupported_proposals(&required, Some(&["simd".to_string()])),
+            ["tail-call"]
+        );
+        assert!(unsupported_proposals(&[], Some(&[])).is_empty());
+    }
+
+    #[test]
+    fn test_select_by_tags() -> Result<()> {
+        let benchmarks = concat!(env!("CARGO_MANIFEST_DIR"), "/../../benchmarks");
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--tags",
+            "simd,missing",
+            "--",
+            &format!("{}/noop/benchmark.wasm", benchmarks),
+            &format!("{}/blake3-simd/benchmark.wasm", benchmarks),
+        ]);
+        let selected = command.benchmarks()?;
+        assert_eq!(selected.len(), 1);
+        assert!(selected[0].0.ends_with("blake3-simd/benchmark.wasm"));
+        assert_eq!(command.benchmark_manifests(&selected)?[0].tags, ["simd"]);
+
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--tags",
+            "missing",
+            "--",
+            &format!("{}/noop/benchmark.wasm", benchmarks),
+        ]);
+        assert!(command.benchmarks().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_benchmark_notes() -> Result<()> {
+        let wasm = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../benchmarks/noop/benchmark.wasm"
+        );
+        let note = format!("{}=input cached", wasm);
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--note",
+            "kernel build running",
+            "--benchmark-note",
+            &note,
+            "--",
+            wasm,
+        ]);
+        assert_eq!(command.notes, ["kernel build running"]);
+        let manifests = command.benchmark_manifests(&command.benchmarks()?)?;
+        assert_eq!(manifests.len(), 1);
+        assert_eq!(manifests[0].notes, ["input cached"]);
+
+        for note in ["input cached", "other.wasm=input cached"] {
+            let command =
+                BenchmarkCommand::from_iter(["benchmark", "--benchmark-note", note, "--", wasm]);
+            assert!(command.benchmark_manifests(&command.benchmarks()?).is_err());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_by_input_size() -> Result<()> {
+        let wasm = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../benchmarks/noop/benchmark.wasm"
+        );
+        let command = BenchmarkCommand::from_iter(["benchmark", "--", wasm]);
+        assert_eq!(command.benchmarks()?.len(), 1);
+        assert_eq!(command.recorded_input_size(), None);
+
+        let command =
+            BenchmarkCommand::from_iter(["benchmark", "--input-size", "large", "--", wasm]);
+        assert_eq!(command.recorded_input_size(), Some("large"));
+        assert!(command.benchmarks().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare_wasm() -> Result<()> {
+        let wasm = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../../benchmarks/noop/benchmark.wasm"
+        );
+        let dir = tempfile::tempdir()?;
+        let other = dir.path().join("manifest.json");
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--compare-wasm",
+            other.to_str().unwrap(),
+            "--",
+            wasm,
+        ]);
+        let manifest = Manifest {
+            wasm_sha256: wasm_hashes(&map_wasm(&command.benchmarks()?)?),
+            ..Default::default()
+        };
+        assert_eq!(
+            manifest.wasm_sha256[wasm],
+            crate::fetch_engine::sha256(&std::fs::read(wasm)?)
+        );
+
+        // The same Wasm, or a Wasm the other run did not have, is fine.
+        manifest.write(std::fs::File::create(&other)?)?;
+        command.compare_wasm(&manifest)?;
+        Manifest::default().write(std::fs::File::create(&other)?)?;
+        command.compare_wasm(&manifest)?;
+
+        let mut different = manifest.clone();
+        different
+            .wasm_sha256
+            .insert(wasm.to_string(), "0".repeat(64));
+        different.write(std::fs::File::create(&other)?)?;
+        assert!(command.compare_wasm(&manifest).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_with_timeout() -> Result<()> {
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo done"]).stdout(Stdio::piped());
+        let (output, timed_out) = run_with_timeout(&mut command, Some(Duration::from_secs(10)))?;
+        assert!(!timed_out && output.status.success());
+        assert_eq!(output.stdout, b"done\n");
+
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let (_, timed_out) = run_with_timeout(&mut command, Some(Duration::from_millis(50)))?;
+        assert!(timed_out);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compiler_runs() {
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--engine",
+            "a.so",
+            "--compilers",
+            "winch,cranelift",
+            "--",
+            "benchmark.wasm",
+        ]);
+        assert_eq!(
+            command.engine_runs(),
+            [
+                (
+                    "a.so".to_string(),
+                    Some("-C compiler=winch".to_string()),
+                    "winch".to_string()
+                ),
+                (
+                    "a.so".to_string(),
+                    Some("-C compiler=cranelift".to_string()),
+                    "cranelift".to_string()
+                )
+            ]
+        );
+
+        // Engine flags are passed to each compiler.
+        let command = BenchmarkCommand::from_iter([
+            "benchmark",
+            "--engine",
+            "a.so",
+            "--engine",
+            "b.so",
+            "--engine-flags=-W simd",
+            "--compilers=winch",
+            "--",
+            "benchmark.wasm",
+        ]);
+        assert_eq!(
+            command.engine_runs(),
+            [
+                (
+                    "a.so".to_string(),
+                    Some("-W simd -C compiler=winch".to_string()),
+                    "a.so winch".to_string()
+                ),
+                (
+                    "b.so".to_string(),
+                    Some("-W simd -C compiler=winch".to_string()),
+                    "b.so winch".to_string()
+                )
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recorded_path() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let checkout = root.path().join("checkout");
+        fs::create_dir_all(checkout.join("benchmarks/bz2"))?;
+        let wasm = checkout.join("benchmarks/bz2/benchmark.wasm");
+        fs::write(&wasm, b"")?;
+        let command = |args: &[&str]| {
+            let mut all = vec!["benchmark", "--engine", "a.so"];
+            all.extend_from_slice(args);
+            all.extend_from_slice(&["--", "benchmark.wasm"]);
+            BenchmarkCommand::from_iter(all)
+        };
+
+        // Within the root, a path is recorded relative to it, however it is given.
+        let path_root = checkout.display().to_string();
+        let relative = command(&["--path-root", &path_root]);
+        assert_eq!(
+            relative.recorded_path(&wasm),
+            "benchmarks/bz2/benchmark.wasm"
+        );
+        let indirect = checkout.join("benchmarks/./bz2/../bz2/benchmark.wasm");
+        assert_eq!(
+            relative.recorded_path(&indirect),
+            "benchmarks/bz2/benchmark.wasm"
+        );
+        // Outside of it, or with `--keep-paths`, it is recorded as given.
+        assert_eq!(
+            relative.recorded_path(Path::new("./elsewhere/a.so")),
+            "elsewhere/a.so"
+        );
+        let kept = command(&["--path-root", &path_root, "--keep-paths"]);
+        assert_eq!(kept.recorded_path(&wasm), wasm.display().to_string());
+        Ok(())
+    }
 }
@@ -4,12 +4,12 @@ use structopt::StructOpt;
 
 /// Remove cached artifacts and log files.
 #[derive(StructOpt, Debug)]
-#[structopt(name = "validate")]
+#[structopt(name = "clean")]
 pub struct CleanCommand {}
 
 impl CleanCommand {
     pub fn execute(&self) -> Result<()> {
-        // Remove cached data, e.g. engines.
+        // Remove cached data, e.g. engines, built benchmarks, fetched files, and extracted suites.
         sightglass_build::clean()?;
 
         // Remove log files.
@@ -0,0 +1,378 @@
+use anyhow::{bail, Context, Result};
+use flate2::read::GzDecoder;
+use reqwest::blocking::Client;
+use reqwest::StatusCode;
+use sightglass_analysis::{effect_size, keys::KeyBuilder, summarize, units::Formatting};
+use sightglass_data::{Format, Measurement, Measurements, Provenance};
+use sightglass_fingerprint::Machine;
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+use structopt::StructOpt;
+
+/// What the reference's engine is named in the comparison.
+const REFERENCE: &str = "reference";
+/// What the local engine is named in the comparison.
+const LOCAL: &str = "local";
+
+/// Compare local results with a published reference dataset (e.g., nightly
+/// Wasmtime results on a known kind of machine), as a sanity check without
+/// access to the machines that produced it; accepts raw benchmark results in
+/// `stdin` (i.e., from `sightglass-cli benchmark ...`).
+///
+/// Results from different machines differ for many reasons besides the
+/// engines, so such comparisons are only indicative: a difference may be the
+/// machine's rather than the engine's.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "compare-reference")]
+pub struct CompareReferenceCommand {
+    /// Path to the file that will be read from, or none to indicate stdin
+    /// (default).
+    #[structopt(short = "f", long = "input-file")]
+    input_file: Option<String>,
+
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// The URL (or path) of the reference dataset: raw results in JSON,
+    /// optionally gzipped. `{arch}` is replaced with this machine's
+    /// architecture (e.g., `x86_64`) and `{os}` with its OS (e.g., `linux`).
+    /// If the dataset was signed (see `sign`), its provenance is read from
+    /// `<URL>.provenance.json` to describe the machine it was measured on.
+    #[structopt(long, env = "SIGHTGLASS_REFERENCE_URL", value_name = "URL")]
+    reference: String,
+
+    /// Compare the local engine whose name contains this text, if the results
+    /// have more than one.
+    #[structopt(long, value_name = "TEXT")]
+    engine: Option<String>,
+
+    /// Compare the reference engine whose name contains this text, if the
+    /// dataset has more than one.
+    #[structopt(long, value_name = "TEXT")]
+    reference_engine: Option<String>,
+
+    /// The format of the output data. Either 'json' or 'csv'; if unspecified, print the output in
+    /// human-readable form.
+    #[structopt(short = "o", long = "output-format")]
+    output_format: Option<Format>,
+
+    /// The significance level for the confidence interval. Typical values are
+    /// 0.01 and 0.05, which correspond to 99% and 95% confidence respectively.
+    #[structopt(short, long, default_value = "0.01")]
+    significance_level: f64,
+}
+
+impl CompareReferenceCommand {
+    pub fn execute(&self) -> Result<()> {
+        let file: Box<dyn Read> = if let Some(file) = self.input_file.as_ref() {
+            Box::new(BufReader::new(
+                File::open(file).context("unable to open --input-file")?,
+            ))
+        } else {
+            Box::new(io::stdin())
+        };
+        let mut local = Measurements::default();
+        local.read(&self.input_format, file)?;
+
+        let url = reference_url(&self.reference);
+        let client = Client::builder()
+            .user_agent(concat!("sightglass/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let bytes =
+            download(&client, &url)?.with_context(|| format!("no reference dataset at {}", url))?;
+        let provenance = match download(&client, &format!("{}.provenance.json", url))? {
+            Some(json) => {
+                let provenance = Provenance::read(json.as_slice())?;
+                let actual = crate::fetch_engine::sha256(&bytes);
+                if actual != provenance.results.sha256 {
+                    bail!(
+                        "the reference dataset does not match its provenance: expected SHA-256 \
+                         {}, found {}",
+                        provenance.results.sha256,
+                        actual
+                    );
+                }
+                Some(provenance)
+            }
+            None => None,
+        };
+        let mut reference = Measurements::default();
+        reference.read(&Format::Json, decompress(&bytes)?)?;
+
+        let mut measurements = relabel(local.to_vec(), LOCAL, self.engine.as_deref())?;
+        measurements.append(&mut relabel(
+            reference.to_vec(),
+            REFERENCE,
+            self.reference_engine.as_deref(),
+        )?);
+        let measurements = common(measurements);
+        if measurements.is_empty() {
+            bail!(
+                "the reference dataset has no results for the same benchmarks, phases, and \
+                 events on this architecture ({})",
+                std::env::consts::ARCH
+            );
+        }
+
+        let mut effect_sizes = effect_size::calculate(self.significance_level, &measurements)?;
+        // The engines are compared in name order; make the reference the baseline.
+        for e in &mut effect_sizes {
+            if e.a_engine != REFERENCE {
+                std::mem::swap(&mut e.a_engine, &mut e.b_engine);
+                std::mem::swap(&mut e.a_mean, &mut e.b_mean);
+            }
+        }
+
+        let reference_machine = provenance
+            .as_ref()
+            .map(|p| describe_machine(&p.machine))
+            .unwrap_or_else(|| "an unknown machine".into());
+        let local_machine = describe_machine(&serde_json::to_value(Machine::fingerprint()?)?);
+        if let Some(output_format) = &self.output_format {
+            log::warn!(
+                "The reference was measured on {}, not this machine ({}): the comparison is \
+                 indicative only",
+                reference_machine,
+                local_machine
+            );
+            output_format.write(&effect_sizes, io::stdout())
+        } else {
+            let mut stdout = io::stdout();
+            writeln!(
+                stdout,
+                "Reference: {} (measured on {})",
+                url, reference_machine
+            )?;
+            writeln!(stdout, "Local: measured on {}", local_machine)?;
+            writeln!(stdout)?;
+            writeln!(
+                stdout,
+                "NOTE: these results were measured on different machines, so the comparison is \
+                 indicative only; a difference may come from the machines (CPU, memory, OS, \
+                 load) rather than the engines. Compare engines on the same machine before \
+                 drawing conclusions."
+            )?;
+            let summaries = summarize::calculate(&measurements);
+            effect_size::write(
+                effect_sizes,
+                &summaries,
+                self.significance_level,
+                Formatting::default(),
+                &mut stdout,
+            )
+        }
+    }
+}
+
+/// Fill in the placeholders of the reference URL template.
+fn reference_url(template: &str) -> String {
+    template
+        .replace("{arch}", std::env::consts::ARCH)
+        .replace("{os}", std::env::consts::OS)
+}
+
+/// Read the file at `url` (an `http://` or `https://` URL, or a local path), if it exists.
+fn download(client: &Client, url: &str) -> Result<Option<Vec<u8>>> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        let path = Path::new(url);
+        if !path.exists() {
+            return Ok(None);
+        }
+        return Ok(Some(
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?,
+        ));
+    }
+    log::info!("Downloading {}", url);
+    let response = client
+        .get(url)
+        .send()
+        .with_context(|| format!("failed to download {}", url))?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let bytes = response
+        .error_for_status()
+        .and_then(|r| r.bytes())
+        .with_context(|| format!("failed to download {}", url))?;
+    Ok(Some(bytes.to_vec()))
+}
+
+/// Read `bytes`, gunzipping them if they are gzipped.
+fn decompress(bytes: &[u8]) -> Result<Box<dyn Read + '_>> {
+    Ok(if bytes.starts_with(&[0x1f, 0x8b]) {
+        Box::new(GzDecoder::new(bytes))
+    } else {
+        Box::new(bytes)
+    })
+}
+
+/// Name the engine of `measurements` (the one whose name contains `engine`, if given) `label`,
+/// and name each benchmark by the end of its path (e.g., `bz2/benchmark.wasm`), so that results
+/// from different machines, with different paths, can be compared.
+fn relabel<'a>(
+    measurements: Vec<Measurement<'a>>,
+    label: &'static str,
+    engine: Option<&str>,
+) -> Result<Vec<Measurement<'a>>> {
+    let mut measurements: Vec<_> = measurements
+        .into_iter()
+        .filter(|m| engine.is_none_or(|e| m.engine.contains(e)))
+        .collect();
+    let engines: BTreeSet<_> = measurements.iter().map(|m| m.engine.to_string()).collect();
+    if engines.len() != 1 {
+        bail!(
+            "expected the {} results to have one engine{}, but found {}: {}; select one with \
+             --{}",
+            label,
+            engine
+                .map(|e| format!(" matching `{}`", e))
+                .unwrap_or_default(),
+            engines.len(),
+            engines.into_iter().collect::<Vec<_>>().join(", "),
+            if label == REFERENCE {
+                "reference-engine"
+            } else {
+                "engine"
+            }
+        );
+    }
+    for m in &mut measurements {
+        m.engine = label.into();
+        m.wasm = benchmark_name(&m.wasm).into();
+    }
+    Ok(measurements)
+}
+
+/// The last two components of a benchmark's path; e.g., `bz2/benchmark.wasm`.
+fn benchmark_name(wasm: &str) -> String {
+    let components: Vec<_> = wasm.split(['/', '\\']).filter(|c| !c.is_empty()).collect();
+    components[components.len().saturating_sub(2)..].join("/")
+}
+
+/// Keep only the measurements of what both the local results and the reference measured.
+fn common<'a>(measurements: Vec<Measurement<'a>>) -> Vec<Measurement<'a>> {
+    let mut keep = vec![false; measurements.len()];
+    for indices in KeyBuilder::all()
+        .engine(false)
+        .index(&measurements)
+        .values()
+    {
+        let engines: BTreeSet<_> = indices.iter().map(|&i| &measurements[i].engine).collect();
+        if engines.len() == 2 {
+            indices.iter().for_each(|&i| keep[i] = true);
+        }
+    }
+    measurements
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(m, keep)| keep.then_some(m))
+        .collect()
+}
+
+/// Describe a machine fingerprint (see `fingerprint --kind machine`) on one line.
+fn describe_machine(machine: &serde_json::Value) -> String {
+    let field = |name: &str| machine[name].as_str().unwrap_or("unknown").to_string();
+    format!(
+        "{}: {}, {}, {} of memory",
+        field("name"),
+        field("cpu"),
+        field("os"),
+        field("memory")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    fn measurement(
+        engine: &'static str,
+        wasm: &'static str,
+        event: &'static str,
+    ) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: wasm.into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: event.into(),
+            count: 42,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn compare_common_results() -> Result<()> {
+        let local = vec![
+            measurement(
+                "/tmp/libengine.so",
+                "benchmarks/bz2/benchmark.wasm",
+                "cycles",
+            ),
+            measurement(
+                "/tmp/libengine.so",
+                "benchmarks/bz2/benchmark.wasm",
+                "nanoseconds",
+            ),
+            measurement("/tmp/other.so", "benchmarks/bz2/benchmark.wasm", "cycles"),
+        ];
+        let reference = vec![
+            measurement(
+                "wasmtime-main",
+                "/ci/benchmarks/bz2/benchmark.wasm",
+                "cycles",
+            ),
+            measurement(
+                "wasmtime-main",
+                "/ci/benchmarks/noop/benchmark.wasm",
+                "cycles",
+            ),
+        ];
+        assert!(relabel(local.clone(), LOCAL, None).is_err());
+
+        let mut measurements = relabel(local, LOCAL, Some("libengine"))?;
+        measurements.append(&mut relabel(reference, REFERENCE, None)?);
+        let measurements = common(measurements);
+        let described: Vec<_> = measurements
+            .iter()
+            .map(|m| format!("{} {} {}", m.engine, m.wasm, m.event))
+            .collect();
+        assert_eq!(
+            described,
+            [
+                "local bz2/benchmark.wasm cycles",
+                "reference bz2/benchmark.wasm cycles"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn read_gzipped_datasets() -> Result<()> {
+        let json = br#"[{"arch": "x86_64", "engine": "e", "wasm": "w", "process": 1,
+            "iteration": 0, "phase": "Execution", "event": "cycles", "count": 1}]"#;
+        let mut gzipped = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        gzipped.write_all(json)?;
+        let gzipped = gzipped.finish()?;
+        for bytes in [&json[..], &gzipped] {
+            let measurements: Vec<Measurement> = Format::Json.read(decompress(bytes)?)?;
+            assert_eq!(measurements.len(), 1);
+        }
+        assert_eq!(benchmark_name("benchmark.wasm"), "benchmark.wasm");
+        assert_eq!(
+            benchmark_name("C:\\sightglass\\benchmarks\\bz2\\benchmark.wasm"),
+            "bz2/benchmark.wasm"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+use crate::schedule::Schedule;
+use anyhow::{bail, Context, Result};
+use sightglass_analysis::{effect_size, summarize, units::Formatting};
+use sightglass_data::{Format, Measurements};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+use structopt::StructOpt;
+
+/// Continuously benchmark new engine builds: watch a directory for new engine
+/// libraries (or poll a branch of Wasmtime for new commits, building each),
+/// run the benchmark plan against each new build, store its raw results, and
+/// compare them with the previous build's.
+///
+/// For each build, `<RESULTS_DIR>/<BUILD>.json` holds the raw measurements
+/// and `<RESULTS_DIR>/<BUILD>.effect-size.txt` the comparison with the build
+/// before it; a build that fails to run has a `<BUILD>.failed` file with the
+/// error instead, and is not retried. `<RESULTS_DIR>/LATEST` names the last
+/// build benchmarked, so that the daemon picks up where it left off when
+/// restarted.
+///
+/// With `--schedule`, the daemon looks for new builds (e.g., building the
+/// latest commit of `--poll-git`) at the times of a cron expression rather
+/// than every `--interval`, so that no `cron` job is needed for nightly runs.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "daemon")]
+pub struct DaemonCommand {
+    /// Watch this directory for new engine libraries (e.g., `*.so`), each
+    /// named by its build (e.g., `wasmtime-1234.so`), benchmarking them in
+    /// order of modification time. To keep a library from being benchmarked
+    /// before it is fully written, copy it elsewhere on the same file system
+    /// and move it into the directory.
+    #[structopt(
+        long,
+        value_name = "DIR",
+        required_unless = "poll-git",
+        parse(from_os_str)
+    )]
+    watch_dir: Option<PathBuf>,
+
+    /// Poll this branch of the Wasmtime `--repository` for new commits
+    /// instead, building (and caching) the bench API library of each.
+    #[structopt(long, value_name = "BRANCH", conflicts_with = "watch-dir")]
+    poll_git: Option<String>,
+
+    /// The Wasmtime repository to poll with `--poll-git`.
+    #[structopt(long, default_value = sightglass_build::WASMTIME_REPOSITORY)]
+    repository: String,
+
+    /// The directory in which to store the results of each build.
+    #[structopt(long, value_name = "RESULTS_DIR", parse(from_os_str))]
+    results_dir: PathBuf,
+
+    /// How long to wait between looking for new builds.
+    #[structopt(long, value_name = "SECONDS", default_value = "300")]
+    interval: u64,
+
+    /// Look for new builds at the times of this cron expression (in local
+    /// time) instead of every `--interval`; e.g., `0 2 * * *` for 2am every
+    /// day. The fields are `MINUTE HOUR DAY-OF-MONTH MONTH DAY-OF-WEEK`, each
+    /// `*`, a number, a range (`1-5`), or a list (`1,15`), optionally stepped
+    /// (`*/15`); `@hourly`, `@daily`, `@weekly`, and `@monthly` also work.
+    #[structopt(long, value_name = "CRON", conflicts_with = "once")]
+    schedule: Option<Schedule>,
+
+    /// Benchmark the builds that are already waiting, then exit (e.g., to run
+    /// from `cron`).
+    #[structopt(long)]
+    once: bool,
+
+    /// The significance level for the confidence intervals of the
+    /// comparisons. Typical values are 0.01 and 0.05, which correspond to 99%
+    /// and 95% confidence respectively.
+    #[structopt(short, long, default_value = "0.01")]
+    significance_level: f64,
+
+    /// The benchmark plan: the arguments to pass to `benchmark` for each
+    /// build, after its `--engine` (e.g., `--processes 5 -- benchmarks/*/benchmark.wasm`).
+    #[structopt(last = true, value_name = "BENCHMARK_ARGS")]
+    benchmark_args: Vec<String>,
+}
+
+impl DaemonCommand {
+    pub fn execute(&self) -> Result<()> {
+        fs::create_dir_all(&self.results_dir)
+            .with_context(|| format!("failed to create {}", self.results_dir.display()))?;
+        loop {
+            if let Some(schedule) = &self.schedule {
+                wait_until_next(schedule)?;
+            }
+            // A daemon keeps going when looking for builds fails (e.g., the network is down);
+            // it will look again next time.
+            if let Err(e) = self.poll() {
+                log::error!("Failed to look for new builds: {:?}", e);
+            }
+            if self.once {
+                return Ok(());
+            }
+            if self.schedule.is_none() {
+                log::debug!("Waiting {} seconds for new builds", self.interval);
+                thread::sleep(Duration::from_secs(self.interval));
+            }
+        }
+    }
+
+    /// Benchmark each new build.
+    fn poll(&self) -> Result<()> {
+        if let Some(dir) = &self.watch_dir {
+            for (build, library) in pending_builds(dir, &self.results_dir)? {
+                self.benchmark(&build, || Ok(library))?;
+            }
+        } else if let Some(branch) = &self.poll_git {
+            let commit = remote_commit(&self.repository, branch)?;
+            let build = format!("wasmtime-{}", commit);
+            if !is_done(&self.results_dir, &build) {
+                self.benchmark(&build, || {
+                    sightglass_build::build_wasmtime(&self.repository, &commit)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Benchmark the `build` whose engine library `engine` finds (e.g., by building it), storing
+    /// its results and comparing them with those of the last build. A build that fails is marked
+    /// as such rather than failing the daemon.
+    fn benchmark(&self, build: &str, engine: impl FnOnce() -> Result<PathBuf>) -> Result<()> {
+        log::info!("Benchmarking build {}", build);
+        let previous = self.latest()?;
+        match engine().and_then(|engine| self.run(build, &engine)) {
+            Ok(()) => {
+                if let Some(previous) = previous {
+                    if let Err(e) = self.compare(&previous, build) {
+                        log::warn!(
+                            "Failed to compare build {} with {}: {:?}",
+                            build,
+                            previous,
+                            e
+                        );
+                    }
+                }
+                let latest = self.results_dir.join("LATEST");
+                fs::write(&latest, build)
+                    .with_context(|| format!("failed to write {}", latest.display()))?;
+            }
+            Err(e) => {
+                log::error!("Failed to benchmark build {}: {:?}", build, e);
+                let failed = self.results_dir.join(format!("{}.failed", build));
+                fs::write(&failed, format!("{:?}\n", e))
+                    .with_context(|| format!("failed to write {}", failed.display()))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the benchmark plan with the `engine` library of `build`, storing the raw results.
+    fn run(&self, build: &str, engine: &Path) -> Result<()> {
+        let results = self.results_dir.join(format!("{}.json", build));
+        let partial = results.with_extension("partial");
+        let mut command = Command::new(
+            std::env::current_exe().context("failed to get the current executable's path")?,
+        );
+        command
+            .arg("benchmark")
+            .args(["--raw", "--output-format", "json", "--output-file"])
+            .arg(&partial)
+            .arg("--engine")
+            .arg(engine)
+            .args(&self.benchmark_args);
+        log::debug!("Running: {:?}", command);
+        let status = command.status().context("failed to run `benchmark`")?;
+        if !status.success() {
+            let _ = fs::remove_file(&partial);
+            bail!("`benchmark` failed with {}", status);
+        }
+        // Only complete results are stored under the build's name, so that a run that is
+        // interrupted is not mistaken for a finished one.
+        fs::rename(&partial, &results)
+            .with_context(|| format!("failed to write {}", results.display()))?;
+        log::info!("Stored the results of build {}", build);
+        Ok(())
+    }
+
+    /// Compare the results of `build` with those of the `previous` build.
+    fn compare(&self, previous: &str, build: &str) -> Result<()> {
+        let mut measurements = Measurements::default();
+        self.read_results(previous, &mut measurements)?;
+        self.read_results(build, &mut measurements)?;
+        let measurements = measurements.to_vec();
+        let effect_sizes = effect_size::calculate(self.significance_level, &measurements)?;
+        let significant = effect_sizes.iter().filter(|e| e.is_significant()).count();
+        let summaries = summarize::calculate(&measurements);
+        let mut output = vec![];
+        effect_size::write(
+            effect_sizes,
+            &summaries,
+            self.significance_level,
+            Formatting::default(),
+            &mut output,
+        )?;
+        let comparison = self.results_dir.join(format!("{}.effect-size.txt", build));
+        fs::write(&comparison, output)
+            .with_context(|| format!("failed to write {}", comparison.display()))?;
+        log::info!(
+            "Compared build {} with {}: {} significant differences (see {})",
+            build,
+            previous,
+            significant,
+            comparison.display()
+        );
+        Ok(())
+    }
+
+    /// Read the stored results of `build` into `measurements`.
+    fn read_results(&self, build: &str, measurements: &mut Measurements) -> Result<()> {
+        let path = self.results_dir.join(format!("{}.json", build));
+        let file =
+            fs::File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        measurements.read(&Format::Json, std::io::BufReader::new(file))
+    }
+
+    /// The last build benchmarked, if any.
+    fn latest(&self) -> Result<Option<String>> {
+        let latest = self.results_dir.join("LATEST");
+        if !latest.exists() {
+            return Ok(None);
+        }
+        let build = fs::read_to_string(&latest)
+            .with_context(|| format!("failed to read {}", latest.display()))?;
+        Ok(Some(build.trim().to_string()))
+    }
+}
+
+/// Sleep until the next time the `schedule` matches.
+fn wait_until_next(schedule: &Schedule) -> Result<()> {
+    let now = chrono::Local::now().naive_local();
+    let next = schedule
+        .next_after(now)
+        .context("the schedule never matches")?;
+    log::info!("Waiting until {} to look for new builds", next);
+    // Sleep a minute at a time, so that a clock that changes (e.g., for daylight saving time or
+    // after a suspend) is followed.
+    while chrono::Local::now().naive_local() < next {
+        let left = (next - chrono::Local::now().naive_local())
+            .to_std()
+            .unwrap_or_default();
+        thread::sleep(left.min(Duration::from_secs(60)));
+    }
+    Ok(())
+}
+
+/// Whether `build` has already been benchmarked (or failed to be).
+fn is_done(results_dir: &Path, build: &str) -> bool {
+    results_dir.join(format!("{}.json", build)).exists()
+        || results_dir.join(format!("{}.failed", build)).exists()
+}
+
+/// List the engine libraries in `dir` that are not yet benchmarked, as `(build, library)`, oldest
+/// first.
+fn pending_builds(dir: &Path, results_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut builds = vec![];
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+        let build = match name.strip_suffix(std::env::consts::DLL_SUFFIX) {
+            Some(build) if path.is_file() && !build.is_empty() => build.to_string(),
+            _ => continue,
+        };
+        if !is_done(results_dir, &build) {
+            let modified = fs::metadata(&path)?.modified()?;
+            builds.push((modified, build, path));
+        }
+    }
+    builds.sort();
+    Ok(builds
+        .into_iter()
+        .map(|(_, build, path)| (build, path))
+        .collect())
+}
+
+/// Find the commit at the head of `branch` in the git `repository`.
+fn remote_commit(repository: &str, branch: &str) -> Result<String> {
+    let output = Command::new("git")
+        .args(["ls-remote", "--heads", repository, branch])
+        .output()
+        .context("failed to run `git ls-remote`")?;
+    if !output.status.success() {
+        bail!("`git ls-remote` failed with {}", output.status);
+    }
+    parse_ls_remote(&String::from_utf8_lossy(&output.stdout), branch)
+        .with_context(|| format!("no branch {} in {}", branch, repository))
+}
+
+/// Find the commit of `branch` in the output of `git ls-remote`.
+fn parse_ls_remote(output: &str, branch: &str) -> Option<String> {
+    let reference = format!("refs/heads/{}", branch);
+    output.lines().find_map(|line| {
+        let (commit, name) = line.split_once('\t')?;
+        (name == reference).then(|| commit.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_pending_builds() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let results = tempfile::tempdir()?;
+        let library = |build: &str| format!("{}{}", build, std::env::consts::DLL_SUFFIX);
+        for build in ["done", "failed", "new"] {
+            fs::write(dir.path().join(library(build)), "")?;
+        }
+        fs::write(dir.path().join("notes.txt"), "")?;
+        fs::write(results.path().join("done.json"), "[]")?;
+        fs::write(results.path().join("failed.failed"), "")?;
+
+        let pending = pending_builds(dir.path(), results.path())?;
+        assert_eq!(
+            pending,
+            [("new".to_string(), dir.path().join(library("new")))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn parse_remote_commit() {
+        let output = "0123abcd\trefs/heads/main\n4567ef01\trefs/heads/main-backport\n";
+        assert_eq!(
+            parse_ls_remote(output, "main"),
+            Some("0123abcd".to_string())
+        );
+        assert_eq!(
+            parse_ls_remote(output, "main-backport"),
+            Some("4567ef01".to_string())
+        );
+        assert_eq!(parse_ls_remote(output, "release"), None);
+    }
+}
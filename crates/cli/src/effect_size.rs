@@ -1,9 +1,22 @@
-use anyhow::Result;
-use sightglass_analysis::{effect_size, summarize};
-use sightglass_data::Format;
+use crate::github::{self, PullRequest};
+use crate::notify::notify_regressions;
+use crate::spill::SpilledGroups;
+use crate::summarize::{parse_precision, read_each, warn_empty, write_empty};
+use anyhow::{bail, Context, Result};
+use sightglass_analysis::effect_size::{self, Method, Skipped, Underpowered};
+use sightglass_analysis::units::{self, Formatting, DEFAULT_PRECISION};
+use sightglass_analysis::weights::Weights;
+use sightglass_analysis::{bayes, brief, groups::Groups, junit, manifest, summarize};
+use sightglass_data::{
+    EffectSize, Format, Manifest, Measurement, Model, Posterior, Statistic, Summary, Transform,
+};
 use std::{
-    fs::File,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    fs::{File, OpenOptions},
     io::{self, BufReader},
+    path::PathBuf,
+    str::FromStr,
 };
 use structopt::StructOpt;
 
@@ -20,41 +33,677 @@ pub struct EffectSizeCommand {
     #[structopt(short = "i", long = "input-format", default_value = "json")]
     input_format: Format,
 
-    /// The format of the output data. Either 'json' or 'csv'; if unspecified, print the output in
-    /// human-readable form.
-    #[structopt(short = "o", long = "output-format")]
-    output_format: Option<Format>,
+    /// Memory-map the input files rather than reading them, so that JSON
+    /// results are deserialized without copying their strings; e.g., to
+    /// re-analyze large archived datasets faster. Only for finished files:
+    /// never map a file that is still being written (e.g., by `benchmark
+    /// --flush-every` or `--zstd`), whose changes the mapping must not see.
+    #[structopt(long)]
+    mmap: bool,
+
+    /// Analyze datasets too large to fit in memory: the measurements are
+    /// spilled to a temporary file as they are read, then analyzed in chunks
+    /// of benchmarks whose measurements take at most about this many MiB.
+    #[structopt(long, value_name = "MIB")]
+    max_memory: Option<u64>,
+
+    /// The format of the output data. Either 'json' or 'csv'; or 'brief' for exactly one line per
+    /// comparison, for grepping in CI logs and piping to other tools: `key=value` pairs of the
+    /// benchmark, phase, event, engines, verdict (`faster`, `slower`, or `same`), speedup, and
+    /// confidence interval, then a `summary` line totaling the verdicts, failing if any comparison
+    /// is significantly slower. If unspecified, print the output in human-readable form.
+    #[structopt(short = "o", long = "output-format", alias = "format")]
+    output_format: Option<OutputFormat>,
+
+    /// Print a JUnit XML report instead, for CI systems to show in their test
+    /// UI: each phase of each benchmark is a test case, which fails if any of
+    /// its events is significantly slower with the second engine than with
+    /// the first.
+    #[structopt(long, conflicts_with = "output-format")]
+    junit: bool,
+
+    /// Estimate the posterior distribution of each speedup instead (see the
+    /// `bayes` module of `sightglass-analysis`): its median and credible
+    /// interval, at the `--significance-level`, and the probability that the
+    /// second engine is faster. With few samples, the credible interval
+    /// widens rather than finding no difference, and the speedups of each
+    /// event combine into the distribution of their geometric mean across
+    /// the benchmarks. With `--output-format`, the posteriors are written
+    /// instead of the effect sizes (but not in brief).
+    #[structopt(long, conflicts_with = "junit")]
+    bayesian: bool,
+
+    /// Weigh the benchmarks in the geometric mean of their speedups (see
+    /// `--bayesian`) by this JSON file of benchmark names and weights, e.g.,
+    /// `{"spidermonkey": 10, "bz2": 2}`, so that production-representative
+    /// workloads count for more than microbenchmarks. A benchmark is named by
+    /// its Wasm file or the directory of its Wasm file; those not named
+    /// weigh 1, and those that weigh 0 are left out.
+    #[structopt(long, value_name = "FILE", requires = "bayesian")]
+    weights: Option<PathBuf>,
+
+    /// Path to the manifest of the run (see `benchmark --manifest`), or of each run whose results
+    /// are compared; when printing human-readable output, the engines each describes (e.g., how
+    /// each was built) are printed first. An engine library with the same path but a different
+    /// digest in two manifests (e.g., rebuilt in place between the runs) is warned about.
+    #[structopt(long, value_name = "MANIFEST", number_of_values = 1)]
+    manifest: Vec<String>,
+
+    /// Compare each of the other engines with this one, by its name in the
+    /// results, rather than requiring exactly two engines; e.g., to compare
+    /// `main` with several experimental branches.
+    #[structopt(long, value_name = "ENGINE")]
+    baseline: Option<String>,
+
+    /// The statistic to compare the engines by: `mean`, or `median` for the
+    /// Hodges–Lehmann estimate of the difference of the medians and its
+    /// distribution-free confidence interval, which outliers (e.g., a
+    /// descheduled iteration) sway much less. Medians are what is usually
+    /// reported for latency-like events. (`--bayesian` always compares means.)
+    #[structopt(long, default_value = "mean")]
+    statistic: Statistic,
+
+    /// Transform the counts before comparing them: `none`, or `log` to
+    /// compare their logarithms, so that the difference is modeled as a
+    /// ratio; e.g., for long-tailed timings whose variance grows with their
+    /// mean, which an additive confidence interval misrepresents. The means
+    /// compared are then geometric means. (The confidence interval of the
+    /// means is always Welch's, which does not assume that the engines'
+    /// counts vary alike.)
+    #[structopt(long, default_value = "none")]
+    transform: Transform,
+
+    /// How the counts vary: `independent`, or `nested` for iterations
+    /// nested within their processes, whose means vary as random effects;
+    /// e.g., with address-space layout randomization, each process can run
+    /// some way off the others, and treating its iterations as independent
+    /// makes differences look significant that are not. Comparing nested
+    /// processes needs at least two processes of each engine, and means.
+    /// (Not with `--max-memory`.)
+    #[structopt(long, default_value = "independent")]
+    model: Model,
 
     /// The significance level for the confidence interval. Typical values are
     /// 0.01 and 0.05, which correspond to 99% and 95% confidence respectively.
     #[structopt(short, long, default_value = "0.01")]
     significance_level: f64,
+
+    /// When printing human-readable output, print exact counts rather than
+    /// formatting them in the unit of their event: e.g., `1.23 G` cycles,
+    /// `4.56 ms`, or `7.89 MiB`.
+    #[structopt(long)]
+    raw: bool,
+
+    /// The significant digits to print numbers with (by default, 3); e.g.,
+    /// `4.56 ms`, or `4563219` with `--raw`, as the digits before a decimal
+    /// point are never rounded away. Numbers never depend on the locale: they
+    /// have a `.` for a decimal point and no thousands separators. With
+    /// `--output-format`, the means and confidence intervals are rounded to this precision too, which
+    /// are otherwise written exactly.
+    #[structopt(long, value_name = "DIGITS", parse(try_from_str = parse_precision))]
+    precision: Option<usize>,
+
+    /// The smallest difference between the engines, in percent of the first
+    /// engine's mean, that the results should be able to find significant.
+    /// Comparisons with too few samples to (likely) find it, given their
+    /// variance, are reported with the smallest difference they can find.
+    #[structopt(long, value_name = "PERCENT", default_value = "5")]
+    min_effect: f64,
+
+    /// Fail, rather than warn, when any comparison has too few samples to
+    /// find a difference of `--min-effect` percent.
+    #[structopt(long)]
+    require_power: bool,
+
+    /// Also append a Markdown table of the significant results to the file
+    /// named by `$GITHUB_STEP_SUMMARY`, so that they show in the summary of a
+    /// GitHub Actions job.
+    #[structopt(long)]
+    github_step_summary: bool,
+
+    /// Also post a Markdown table of the significant results as a comment on
+    /// this GitHub pull request (e.g., `bytecodealliance/wasmtime#1234`), or
+    /// update the comment posted by an earlier run. The API token is read
+    /// from `$GITHUB_TOKEN`.
+    #[structopt(long, value_name = "OWNER/REPO#NUMBER")]
+    post_github_comment: Option<PullRequest>,
+
+    /// POST a notification to this URL when there are any significant
+    /// regressions of `--notify-threshold` percent or more: results for which
+    /// the second engine is slower than the first (the baseline). The
+    /// notification is a JSON description of the regressions or, with
+    /// `--notify-slack`, a Slack message.
+    #[structopt(long, value_name = "URL")]
+    notify_url: Option<String>,
+
+    /// How much slower, in percent, a significant result must be to notify
+    /// `--notify-url` of it.
+    #[structopt(long, value_name = "PERCENT", default_value = "5")]
+    notify_threshold: f64,
+
+    /// Send `--notify-url` a message for a Slack incoming webhook instead.
+    #[structopt(long)]
+    notify_slack: bool,
+}
+
+/// The format of the output of `effect-size`: the effect sizes (or posteriors) as data, or brief.
+#[derive(Debug)]
+enum OutputFormat {
+    Data(Format),
+    Brief,
+}
+
+impl FromStr for OutputFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, &'static str> {
+        match s {
+            "brief" => Ok(Self::Brief),
+            _ => s
+                .parse()
+                .map(Self::Data)
+                .map_err(|_| "output format must be either 'json', 'csv', or 'brief'"),
+        }
+    }
 }
 
 impl EffectSizeCommand {
     pub fn execute(&self) -> Result<()> {
-        let measurements = if let Some(files) = self.input_file.as_ref() {
-            let mut ms = Vec::new();
-            for file in files {
-                let reader = BufReader::new(File::open(file)?);
-                ms.append(&mut self.input_format.read(reader)?);
+        if self.bayesian && matches!(self.output_format, Some(OutputFormat::Brief)) {
+            bail!("the posteriors of --bayesian have no brief format");
+        }
+        let weights = match &self.weights {
+            Some(path) => Weights::open(path)?,
+            None => Weights::default(),
+        };
+        let Calculated {
+            effects,
+            posteriors,
+            summaries,
+            underpowered,
+            skipped,
+            empty,
+        } = match self.max_memory {
+            Some(_) if self.model == Model::Nested => {
+                bail!("the processes of the measurements are not kept with --max-memory")
             }
-            ms
+            Some(mib) => self.calculate_in_chunks(mib * 1024 * 1024)?,
+            None => {
+                let mut groups = match self.model {
+                    Model::Independent => Groups::default(),
+                    Model::Nested => Groups::with_processes(),
+                };
+                let empty = self.read(|m| {
+                    groups.add(m);
+                    Ok(())
+                })?;
+                let mut calculated = Calculated {
+                    empty,
+                    ..Default::default()
+                };
+                self.calculate(&groups, &mut calculated)?;
+                calculated
+            }
+        };
+        let formatting = Formatting {
+            raw: self.raw,
+            precision: self.precision.unwrap_or(DEFAULT_PRECISION),
+        };
+        if self.github_step_summary {
+            write_github_step_summary(
+                effects.clone(),
+                self.significance_level,
+                formatting.precision,
+            )?;
+        }
+        if let Some(pr) = &self.post_github_comment {
+            post_github_comment(
+                pr,
+                effects.clone(),
+                self.significance_level,
+                formatting.precision,
+            )?;
+        }
+        if let Some(url) = &self.notify_url {
+            notify_regressions(url, self.notify_slack, self.notify_threshold, &effects)?;
+        }
+        let manifests = self
+            .manifest
+            .iter()
+            .map(|file| {
+                let opened =
+                    File::open(file).with_context(|| format!("failed to open {}", file))?;
+                Ok((file.clone(), Manifest::read(BufReader::new(opened))?))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let conflicts = manifest::conflicting_engines(&manifests);
+        let human_readable = !self.junit && self.output_format.is_none();
+        let mut regressions = 0;
+        if self.junit {
+            junit::write(&effects, &mut io::stdout())?;
+        } else if let Some(OutputFormat::Brief) = &self.output_format {
+            regressions = brief::write(&effects, formatting.precision, &mut io::stdout())?;
+        } else if let (Some(OutputFormat::Data(output_format)), true) =
+            (&self.output_format, self.bayesian)
+        {
+            let mut posteriors = posteriors;
+            if let Some(digits) = self.precision {
+                round_posteriors(&mut posteriors, digits);
+            }
+            output_format.write(&posteriors, io::stdout())?;
+        } else if let Some(OutputFormat::Data(output_format)) = &self.output_format {
+            let mut effects = effects;
+            if let Some(digits) = self.precision {
+                round_effect_sizes(&mut effects, digits);
+            }
+            output_format.write(&effects, io::stdout())?;
         } else {
-            self.input_format.read(io::stdin())?
+            for (_, manifest) in &manifests {
+                manifest::write(manifest, &mut io::stdout())?;
+            }
+            for conflict in &conflicts {
+                println!("warning: {}\n", conflict);
+            }
+            if self.bayesian {
+                bayes::write(&posteriors, &weights, formatting, &mut io::stdout())?;
+            } else {
+                effect_size::write(
+                    effects,
+                    &summaries,
+                    self.significance_level,
+                    formatting,
+                    &mut io::stdout(),
+                )?;
+                effect_size::write_underpowered(
+                    &underpowered,
+                    self.min_effect / 100.0,
+                    &mut io::stdout(),
+                )?;
+            }
+            effect_size::write_skipped(&skipped, &mut io::stdout())?;
+            write_empty(&empty, &mut io::stdout())?;
+        }
+        if !human_readable {
+            for conflict in &conflicts {
+                log::warn!("{}", conflict);
+            }
+            if !self.bayesian {
+                for u in &underpowered {
+                    log::warn!("Too few samples: {}", u);
+                }
+            }
+            for s in &skipped {
+                log::warn!("Skipped {}", s);
+            }
+            warn_empty(&empty);
+        }
+        anyhow::ensure!(
+            !self.require_power || underpowered.is_empty(),
+            "{} comparisons have too few samples to find a {}% difference significant",
+            underpowered.len(),
+            self.min_effect
+        );
+        anyhow::ensure!(
+            regressions == 0,
+            "{} comparisons are significantly slower than the baseline",
+            regressions
+        );
+        Ok(())
+    }
+
+    /// Read the measurements of each input file (or `stdin`), passing each to `add`, then check
+    /// that they can be compared: see [Dimensions]. Returns the input files without measurements.
+    fn read(&self, mut add: impl FnMut(&Measurement) -> Result<()>) -> Result<Vec<String>> {
+        let mut dimensions = Dimensions::default();
+        let mut empty = vec![];
+        let inputs: Vec<_> = match &self.input_file {
+            Some(files) => files.iter().map(|file| Some(file.clone())).collect(),
+            None => vec![None],
         };
+        for input in inputs {
+            let name = input.clone().unwrap_or_else(|| "<stdin>".to_string());
+            let mut row = 0;
+            // Each file has its own CSV headers, which reading takes.
+            let format = self.input_format.clone();
+            empty.extend(read_each(
+                input.as_ref().map(std::slice::from_ref),
+                &format,
+                self.mmap,
+                |m| {
+                    row += 1;
+                    dimensions.add(&name, row, &m);
+                    add(&m)
+                },
+            )?);
+        }
+        dimensions.check(self.baseline.as_deref())?;
+        Ok(empty)
+    }
 
-        let effects = effect_size::calculate(self.significance_level, &measurements)?;
-        if let Some(output_format) = &self.output_format {
-            output_format.write(&effects, io::stdout())
+    /// Add the effect sizes of the `groups`, against the `--baseline` engine if any, their
+    /// posteriors if `--bayesian`, their summaries and underpowered comparisons, and the
+    /// comparisons of the report skipped for want of results to `calculated`.
+    fn calculate(&self, groups: &Groups, calculated: &mut Calculated) -> Result<()> {
+        let baseline = self.baseline.as_deref();
+        let method = Method {
+            statistic: self.statistic,
+            transform: self.transform,
+            model: self.model,
+        };
+        let (effects, skipped) =
+            effect_size::calculate_with(method, self.significance_level, baseline, groups)?;
+        calculated.effects.extend(effects);
+        if self.bayesian {
+            let (posteriors, skipped) =
+                bayes::calculate(1.0 - self.significance_level, baseline, groups)?;
+            calculated.posteriors.extend(posteriors);
+            calculated.skipped.extend(skipped);
         } else {
-            let summaries = summarize::calculate(&measurements);
-            effect_size::write(
-                effects,
-                &summaries,
-                self.significance_level,
-                &mut io::stdout(),
-            )
+            calculated.skipped.extend(skipped);
         }
+        calculated
+            .summaries
+            .extend(summarize::calculate_grouped(groups));
+        // How many samples are needed is estimated for the means of the counts.
+        if method == Method::default() {
+            calculated.underpowered.extend(self.underpowered(groups));
+        }
+        Ok(())
+    }
+
+    /// The comparisons of the `groups` with too few samples to find a difference of `--min-effect`.
+    fn underpowered(&self, groups: &Groups) -> Vec<Underpowered<'static>> {
+        effect_size::underpowered(
+            self.significance_level,
+            self.min_effect / 100.0,
+            self.baseline.as_deref(),
+            groups,
+        )
+    }
+
+    /// Calculate the effect sizes and summaries a chunk of the measurements at a time, holding at
+    /// most about `max_memory` bytes of them in memory (see [SpilledGroups]).
+    fn calculate_in_chunks(&self, max_memory: u64) -> Result<Calculated> {
+        let mut spilled = SpilledGroups::new()?;
+        let empty = self.read(|m| spilled.add(m))?;
+        let mut calculated = Calculated {
+            empty,
+            ..Default::default()
+        };
+        spilled.for_each_chunk(max_memory, |groups| self.calculate(groups, &mut calculated))?;
+        Ok(calculated)
+    }
+}
+
+/// Where each architecture, and each event of each engine, first appears in the input, to explain
+/// up front why its measurements cannot be compared, rather than failing (or, worse, comparing the
+/// wrong groups) deep in the analysis.
+#[derive(Default)]
+struct Dimensions {
+    archs: BTreeMap<String, Origin>,
+    events: BTreeMap<String, BTreeMap<String, Origin>>,
+}
+
+/// The file and row in which a dimension first appears, and how many measurements have it.
+struct Origin {
+    file: String,
+    row: usize,
+    count: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} measurements, the first in {} (measurement {})",
+            self.count, self.file, self.row
+        )
+    }
+}
+
+impl Dimensions {
+    fn add(&mut self, file: &str, row: usize, m: &Measurement) {
+        let origin = |map: &mut BTreeMap<String, Origin>, key: &str| match map.get_mut(key) {
+            Some(origin) => origin.count += 1,
+            None => {
+                let origin = Origin {
+                    file: file.to_string(),
+                    row,
+                    count: 1,
+                };
+                map.insert(key.to_string(), origin);
+            }
+        };
+        origin(&mut self.archs, &m.arch);
+        match self.events.get_mut(m.engine.as_ref()) {
+            Some(events) => origin(events, &m.event),
+            None => {
+                let mut events = BTreeMap::new();
+                origin(&mut events, &m.event);
+                self.events.insert(m.engine.to_string(), events);
+            }
+        }
+    }
+
+    /// Check that the measurements are of one architecture, that each engine measured the same
+    /// events, and that the `baseline` engine, if any, has measurements at all: comparisons
+    /// without it are otherwise only skipped, as though it had skipped those benchmarks.
+    fn check(&self, baseline: Option<&str>) -> Result<()> {
+        if let Some(baseline) = baseline {
+            if !self.events.is_empty() && !self.events.contains_key(baseline) {
+                let engines: Vec<_> = self.events.keys().map(String::as_str).collect();
+                bail!(
+                    "the baseline engine {} has no measurements; the engines are: {}",
+                    baseline,
+                    engines.join(", ")
+                );
+            }
+        }
+        if self.archs.len() > 1 {
+            let archs: Vec<_> = self
+                .archs
+                .iter()
+                .map(|(arch, origin)| format!("  {}: {}", arch, origin))
+                .collect();
+            bail!(
+                "the results mix architectures, which cannot be compared; analyze each \
+                 architecture's results separately:\n{}",
+                archs.join("\n")
+            );
+        }
+        let all: BTreeSet<_> = self.events.values().flat_map(|e| e.keys()).collect();
+        let mut missing = vec![];
+        for (engine, events) in &self.events {
+            for &event in &all {
+                if !events.contains_key(event) {
+                    let (other, origin) = self
+                        .events
+                        .iter()
+                        .find_map(|(other, events)| Some((other, events.get(event)?)))
+                        .unwrap();
+                    missing.push(format!(
+                        "  {} has no {} measurements, unlike {}: {}",
+                        engine, event, other, origin
+                    ));
+                }
+            }
+        }
+        if !missing.is_empty() {
+            bail!(
+                "the engines measured different events, which cannot be compared; measure the \
+                 same events (see `benchmark --measure`) with each engine:\n{}",
+                missing.join("\n")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The effect sizes, posteriors, summaries, and underpowered and skipped comparisons of the measurements, and
+/// the input files without any.
+#[derive(Default)]
+struct Calculated {
+    effects: Vec<EffectSize<'static>>,
+    posteriors: Vec<Posterior<'static>>,
+    summaries: Vec<Summary<'static>>,
+    underpowered: Vec<Underpowered<'static>>,
+    skipped: Vec<Skipped<'static>>,
+    empty: Vec<String>,
+}
+
+/// Round the means and confidence intervals of the `effect_sizes` to `digits` significant digits,
+/// for machine-readable output given a `--precision`.
+fn round_effect_sizes(effect_sizes: &mut [EffectSize<'_>], digits: usize) {
+    for e in effect_sizes {
+        e.a_mean = units::round(e.a_mean, digits);
+        e.b_mean = units::round(e.b_mean, digits);
+        e.half_width_confidence_interval = units::round(e.half_width_confidence_interval, digits);
+    }
+}
+
+/// Round the means and speedups of the `posteriors` to `digits` significant digits, as
+/// [round_effect_sizes].
+fn round_posteriors(posteriors: &mut [Posterior<'_>], digits: usize) {
+    for p in posteriors {
+        p.a_mean = units::round(p.a_mean, digits);
+        p.b_mean = units::round(p.b_mean, digits);
+        p.speedup = units::round(p.speedup, digits);
+        p.speedup_lower = units::round(p.speedup_lower, digits);
+        p.speedup_upper = units::round(p.speedup_upper, digits);
+    }
+}
+
+/// The most results to list in a Markdown report; the rest are only counted.
+const MARKDOWN_ROWS: usize = 50;
+
+/// Append a Markdown table of the significant `effect_sizes` to the file named by
+/// `$GITHUB_STEP_SUMMARY`, the summary of the current GitHub Actions job. Outside of GitHub
+/// Actions, where it is unset, this only warns.
+pub(crate) fn write_github_step_summary(
+    effect_sizes: Vec<EffectSize<'_>>,
+    significance_level: f64,
+    precision: usize,
+) -> Result<()> {
+    let path = match std::env::var_os("GITHUB_STEP_SUMMARY") {
+        Some(path) => path,
+        None => {
+            log::warn!("$GITHUB_STEP_SUMMARY is not set; not writing a job summary");
+            return Ok(());
+        }
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.to_string_lossy()))?;
+    effect_size::write_markdown(
+        effect_sizes,
+        significance_level,
+        MARKDOWN_ROWS,
+        precision,
+        &mut file,
+    )
+}
+
+/// Post a Markdown table of the significant `effect_sizes` as a comment on the pull request `pr`,
+/// replacing the one posted by an earlier run, if any.
+pub(crate) fn post_github_comment(
+    pr: &PullRequest,
+    effect_sizes: Vec<EffectSize<'_>>,
+    significance_level: f64,
+    precision: usize,
+) -> Result<()> {
+    let mut body = vec![];
+    effect_size::write_markdown(
+        effect_sizes,
+        significance_level,
+        MARKDOWN_ROWS,
+        precision,
+        &mut body,
+    )?;
+    let url = github::upsert_comment(pr, &String::from_utf8(body)?)?;
+    log::info!("Posted the results to {}", url);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    fn measurement(
+        arch: &'static str,
+        engine: &'static str,
+        event: &'static str,
+    ) -> Measurement<'static> {
+        Measurement {
+            arch: arch.into(),
+            engine: engine.into(),
+            wasm: "bz2.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: event.into(),
+            count: 42,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn incomparable_dimensions() {
+        let check = |measurements: &[(&str, Measurement)]| {
+            let mut dimensions = Dimensions::default();
+            for (row, (file, m)) in measurements.iter().enumerate() {
+                dimensions.add(file, row + 1, m);
+            }
+            dimensions.check(None).map_err(|e| e.to_string())
+        };
+        let main = ("main.json", measurement("x86_64", "main.so", "cycles"));
+        assert!(check(&[
+            main.clone(),
+            ("branch.json", measurement("x86_64", "branch.so", "cycles"))
+        ])
+        .is_ok());
+
+        let error = check(&[
+            main.clone(),
+            ("branch.json", measurement("aarch64", "branch.so", "cycles")),
+        ])
+        .unwrap_err();
+        assert!(error.contains(
+            "  aarch64: 1 measurements, the first in branch.json (measurement 2)\n  \
+             x86_64: 1 measurements, the first in main.json (measurement 1)"
+        ));
+
+        let error = check(&[
+            main.clone(),
+            (
+                "main.json",
+                measurement("x86_64", "main.so", "instructions"),
+            ),
+            ("branch.json", measurement("x86_64", "branch.so", "cycles")),
+        ])
+        .unwrap_err();
+        assert!(error.ends_with(
+            "  branch.so has no instructions measurements, unlike main.so: 1 measurements, the \
+             first in main.json (measurement 2)"
+        ));
+    }
+
+    #[test]
+    fn output_formats() {
+        let parse =
+            |args: &[&str]| EffectSizeCommand::from_iter_safe(args).map(|c| c.output_format);
+        assert!(matches!(
+            parse(&["effect-size", "--format", "brief"]),
+            Ok(Some(OutputFormat::Brief))
+        ));
+        assert!(matches!(
+            parse(&["effect-size", "-o", "csv"]),
+            Ok(Some(OutputFormat::Data(Format::Csv { .. })))
+        ));
+        assert!(parse(&["effect-size", "--output-format", "xml"]).is_err());
+        assert!(parse(&["effect-size", "--brief"]).is_err());
     }
 }
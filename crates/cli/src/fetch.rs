@@ -0,0 +1,342 @@
+use crate::fetch_engine::sha256;
+use anyhow::{bail, Context, Result};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{ACCEPT, AUTHORIZATION, WWW_AUTHENTICATE};
+use reqwest::StatusCode;
+use sightglass_build::{BenchmarkConfig, FetchedFile, BENCHMARK_CONFIG};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+/// Download the files listed in the `[[fetch]]` entries of each benchmark's
+/// `benchmark.toml` (e.g., the Wasm files of benchmarks too large to keep in
+/// the repository) into the benchmark's directory.
+///
+/// Each file comes from an `https://` URL or an `oci://` artifact registry
+/// and is checked against its SHA-256 digest. Downloads are cached by digest
+/// (see `SIGHTGLASS_CACHE_DIR`), and files already in place with the right
+/// digest are left alone.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "fetch")]
+pub struct FetchCommand {
+    /// The benchmark directories (or their `benchmark.toml` files) for which
+    /// to fetch files.
+    #[structopt(
+        index = 1,
+        required = true,
+        value_name = "BENCHMARK_DIR",
+        parse(from_os_str)
+    )]
+    benchmarks: Vec<PathBuf>,
+}
+
+impl FetchCommand {
+    pub fn execute(&self) -> Result<()> {
+        let client = Client::builder()
+            .user_agent(concat!("sightglass/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        for benchmark in &self.benchmarks {
+            let (dir, config) = if benchmark.is_dir() {
+                (benchmark.clone(), benchmark.join(BENCHMARK_CONFIG))
+            } else {
+                let dir = benchmark.parent().unwrap_or_else(|| Path::new("."));
+                (dir.to_path_buf(), benchmark.clone())
+            };
+            let fetched = BenchmarkConfig::parse(&config)?.fetch;
+            if fetched.is_empty() {
+                log::warn!("Nothing to fetch for {}", dir.display());
+            }
+            for fetched in &fetched {
+                fetched
+                    .check_path()
+                    .with_context(|| format!("invalid {}", config.display()))?;
+            }
+            for fetched in fetched {
+                fetch(&client, &dir, &fetched)
+                    .with_context(|| format!("failed to fetch {}", fetched.url))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Place the `fetched` file in the benchmark directory `dir`, downloading it unless it is already
+/// there or in the download cache.
+fn fetch(client: &Client, dir: &Path, fetched: &FetchedFile) -> Result<()> {
+    let path = dir.join(&fetched.file);
+    let expected = fetched.sha256.to_lowercase();
+    if has_digest(&path, &expected) {
+        log::info!("Already fetched: {}", path.display());
+        return Ok(());
+    }
+
+    let cached = sightglass_build::cache_dir()?
+        .join("downloads")
+        .join(format!("sha256-{}", expected));
+    if has_digest(&cached, &expected) {
+        log::info!("Using cached download: {}", cached.display());
+    } else {
+        log::info!("Downloading {}", fetched.url);
+        let bytes = match fetched.url.strip_prefix("oci://") {
+            Some(reference) => {
+                let title = fetched.file.file_name().unwrap().to_string_lossy();
+                download_oci(client, &reference.parse()?, &title)?
+            }
+            None => {
+                anyhow::ensure!(
+                    fetched.url.starts_with("https://"),
+                    "only https:// and oci:// URLs can be fetched"
+                );
+                send(client.get(&fetched.url))?.bytes()?.to_vec()
+            }
+        };
+        let actual = sha256(&bytes);
+        if actual != expected {
+            bail!(
+                "the checksum of {} does not match: expected {}, found {}",
+                fetched.url,
+                expected,
+                actual
+            );
+        }
+        write(&cached, &bytes)?;
+    }
+
+    write(&path, &fs::read(&cached)?)?;
+    log::info!("Fetched {}", path.display());
+    Ok(())
+}
+
+/// Check whether the file at `path` exists with the SHA-256 digest `expected`.
+fn has_digest(path: &Path, expected: &str) -> bool {
+    fs::read(path).is_ok_and(|bytes| sha256(&bytes) == expected)
+}
+
+/// Write a file under a temporary name first so that an interrupted write is never mistaken for a
+/// fetched file.
+fn write(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    let partial = path.with_extension("partial");
+    fs::write(&partial, bytes)
+        .and_then(|()| fs::rename(&partial, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn send(request: RequestBuilder) -> Result<Response> {
+    Ok(request.send()?.error_for_status()?)
+}
+
+/// A reference to an artifact in an OCI registry: `<registry>/<repository>:<tag>` or
+/// `<registry>/<repository>@<digest>`.
+#[derive(Debug, PartialEq)]
+struct OciReference {
+    registry: String,
+    repository: String,
+    reference: String,
+}
+
+impl std::str::FromStr for OciReference {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let (registry, rest) = s
+            .split_once('/')
+            .with_context(|| format!("the OCI reference has no repository: {}", s))?;
+        let (repository, reference) = match rest.split_once('@') {
+            Some((repository, digest)) => (repository, digest),
+            None => match rest.rsplit_once(':') {
+                Some((repository, tag)) if !tag.contains('/') => (repository, tag),
+                _ => (rest, "latest"),
+            },
+        };
+        anyhow::ensure!(
+            !registry.is_empty() && !repository.is_empty() && !reference.is_empty(),
+            "invalid OCI reference: {}",
+            s
+        );
+        Ok(OciReference {
+            registry: registry.to_string(),
+            repository: repository.to_string(),
+            reference: reference.to_string(),
+        })
+    }
+}
+
+/// Download the layer titled `title` (by the `org.opencontainers.image.title` annotation, as with
+/// `oras push`) of an OCI artifact, or its only layer. Public artifacts are fetched with an
+/// anonymous token when the registry requires one.
+fn download_oci(client: &Client, oci: &OciReference, title: &str) -> Result<Vec<u8>> {
+    let base = format!("https://{}/v2/{}", oci.registry, oci.repository);
+    let manifest_url = format!("{}/manifests/{}", base, oci.reference);
+    let accept = "application/vnd.oci.image.manifest.v1+json, \
+                  application/vnd.docker.distribution.manifest.v2+json";
+    let response = client.get(&manifest_url).header(ACCEPT, accept).send()?;
+    let token = if response.status() == StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|h| h.to_str().ok())
+            .context("the registry requires authentication but did not say how")?;
+        Some(anonymous_token(client, challenge)?)
+    } else {
+        None
+    };
+    let authorize = |request: RequestBuilder| match &token {
+        Some(token) => request.header(AUTHORIZATION, format!("Bearer {}", token)),
+        None => request,
+    };
+
+    let manifest: serde_json::Value =
+        send(authorize(client.get(&manifest_url).header(ACCEPT, accept)))?.json()?;
+    let layers = manifest["layers"]
+        .as_array()
+        .context("the OCI manifest has no layers")?;
+    let layer = match layers.as_slice() {
+        [layer] => layer,
+        layers => layers
+            .iter()
+            .find(|l| l["annotations"]["org.opencontainers.image.title"] == title)
+            .with_context(|| format!("the OCI artifact has no layer titled {}", title))?,
+    };
+    let digest = layer["digest"]
+        .as_str()
+        .context("the OCI layer has no digest")?;
+    let bytes = send(authorize(client.get(format!("{}/blobs/{}", base, digest))))?
+        .bytes()?
+        .to_vec();
+    anyhow::ensure!(
+        digest == format!("sha256:{}", sha256(&bytes)),
+        "the OCI layer does not match its digest: {}",
+        digest
+    );
+    Ok(bytes)
+}
+
+/// Request an anonymous token as described by a `WWW-Authenticate: Bearer realm=...,service=...,
+/// scope=...` challenge.
+fn anonymous_token(client: &Client, challenge: &str) -> Result<String> {
+    let params = challenge
+        .strip_prefix("Bearer ")
+        .with_context(|| format!("unsupported authentication: {}", challenge))?;
+    let params = parse_challenge(params);
+    let realm = params
+        .iter()
+        .find(|(k, _)| k == "realm")
+        .map(|(_, v)| v.clone())
+        .context("the authentication challenge has no realm")?;
+    let query: Vec<_> = params.iter().filter(|(k, _)| k != "realm").collect();
+    let response: serde_json::Value = send(client.get(&realm).query(&query))?.json()?;
+    response["token"]
+        .as_str()
+        .or_else(|| response["access_token"].as_str())
+        .map(ToString::to_string)
+        .context("the registry did not return a token")
+}
+
+/// Split the parameters of an authentication challenge: `a="b",c="d"`.
+fn parse_challenge(params: &str) -> Vec<(String, String)> {
+    let mut parsed = vec![];
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        parsed.push((key.trim().to_string(), value.to_string()));
+        rest = after.trim_start_matches(',').trim();
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_oci_references() {
+        assert_eq!(
+            "ghcr.io/org/benchmarks/spidermonkey:v1"
+                .parse::<OciReference>()
+                .unwrap(),
+            OciReference {
+                registry: "ghcr.io".into(),
+                repository: "org/benchmarks/spidermonkey".into(),
+                reference: "v1".into(),
+            }
+        );
+        let digest = format!("sha256:{}", "a".repeat(64));
+        let oci: OciReference = format!("localhost:5000/llvm@{}", digest).parse().unwrap();
+        assert_eq!(oci.registry, "localhost:5000");
+        assert_eq!(oci.repository, "llvm");
+        assert_eq!(oci.reference, digest);
+        let oci: OciReference = "localhost:5000/llvm".parse().unwrap();
+        assert_eq!(oci.reference, "latest");
+        assert!("ghcr.io".parse::<OciReference>().is_err());
+    }
+
+    #[test]
+    fn parse_challenges() {
+        assert_eq!(
+            parse_challenge(
+                r#"realm="https://ghcr.io/token",service="ghcr.io",scope="repository:org/x:pull""#
+            ),
+            [
+                ("realm".to_string(), "https://ghcr.io/token".to_string()),
+                ("service".to_string(), "ghcr.io".to_string()),
+                ("scope".to_string(), "repository:org/x:pull".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn skip_fetched_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("benchmark.wasm"), b"abc")?;
+        let fetched = FetchedFile {
+            file: "benchmark.wasm".into(),
+            // Never contacted, since the file is already in place.
+            url: "https://example.invalid/benchmark.wasm".into(),
+            sha256: sha256(b"abc"),
+        };
+        fetch(&Client::new(), dir.path(), &fetched)?;
+
+        let fetched = FetchedFile {
+            url: "ftp://example.invalid/benchmark.wasm".into(),
+            sha256: sha256(b"abcd"),
+            ..fetched
+        };
+        assert!(fetch(&Client::new(), dir.path(), &fetched).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn reject_files_outside_the_benchmark() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let dir = root.path().join("benchmark");
+        fs::create_dir(&dir)?;
+        let outside = root.path().join("outside.wasm");
+        for file in [outside.to_str().unwrap(), "../outside.wasm", "", "."] {
+            let config = format!(
+                "[[fetch]]\nfile = {:?}\nurl = \"https://example.invalid/outside.wasm\"\n\
+                 sha256 = \"{}\"\n",
+                file,
+                "a".repeat(64)
+            );
+            fs::write(dir.join(BENCHMARK_CONFIG), config)?;
+            let command = FetchCommand {
+                benchmarks: vec![dir.clone()],
+            };
+            let error = format!("{:#}", command.execute().unwrap_err());
+            assert!(
+                error.contains("within the benchmark's directory"),
+                "{}",
+                error
+            );
+            assert!(!outside.exists());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use anyhow::{bail, Context, Result};
+use reqwest::blocking::Client;
+use sha2::{Digest, Sha256};
+use std::{fs, io::Write, path::PathBuf};
+use structopt::StructOpt;
+
+/// Download the Wasmtime bench API library built by Wasmtime's CI for a
+/// commit (or the head of a pull request) and print its path; e.g.,
+/// `sightglass-cli benchmark --engine $(sightglass-cli fetch-engine --pr
+/// 1234) ...`.
+///
+/// The library is checked against the SHA-256 checksum published next to it
+/// and cached by commit (see `SIGHTGLASS_CACHE_DIR`), so each commit is only
+/// downloaded once.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "fetch-engine")]
+pub struct FetchEngineCommand {
+    /// The full hash of the Wasmtime commit to fetch the engine for.
+    #[structopt(index = 1, value_name = "COMMIT", required_unless = "pr")]
+    commit: Option<String>,
+
+    /// Fetch the engine for the head commit of this pull request instead.
+    #[structopt(long, value_name = "NUMBER", conflicts_with = "commit")]
+    pr: Option<u32>,
+
+    /// The GitHub repository in which to find the pull request.
+    #[structopt(long, default_value = "bytecodealliance/wasmtime")]
+    repository: String,
+
+    /// Where to download the library from. `{commit}` is replaced with the
+    /// commit hash, `{target}` with the platform (e.g., `x86_64-linux`), and
+    /// `{library}` with the library's file name (e.g.,
+    /// `libwasmtime_bench_api.so`); its checksum is downloaded from the same
+    /// URL with a `.sha256` suffix.
+    #[structopt(long, env = "SIGHTGLASS_ENGINE_URL", value_name = "URL")]
+    url: String,
+}
+
+impl FetchEngineCommand {
+    pub fn execute(&self) -> Result<()> {
+        let client = Client::builder()
+            .user_agent(concat!("sightglass/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        let commit = match (&self.commit, self.pr) {
+            (Some(commit), _) => commit.to_lowercase(),
+            (None, Some(pr)) => pull_request_head(&client, &self.repository, pr)?,
+            (None, None) => unreachable!(),
+        };
+        anyhow::ensure!(
+            is_commit_hash(&commit),
+            "the commit must be a full commit hash: {}",
+            commit
+        );
+
+        let library = match sightglass_build::cached_engine("wasmtime", &commit)? {
+            Some(library) => {
+                log::info!("Using cached engine: {}", library.display());
+                library
+            }
+            None => self.download(&client, &commit)?,
+        };
+        println!("{}", library.display());
+        Ok(())
+    }
+
+    /// Download and verify the library for `commit`, saving it to the engine cache.
+    fn download(&self, client: &Client, commit: &str) -> Result<PathBuf> {
+        let url = library_url(&self.url, commit);
+        log::info!("Downloading engine: {}", url);
+        let bytes = client
+            .get(&url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.bytes())
+            .with_context(|| format!("failed to download {}", url))?;
+        let checksum_url = format!("{}.sha256", url);
+        let checksum = client
+            .get(&checksum_url)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.text())
+            .with_context(|| format!("failed to download {}", checksum_url))?;
+        let expected = parse_checksum(&checksum)
+            .with_context(|| format!("invalid checksum file: {}", checksum_url))?;
+        let actual = sha256(&bytes);
+        if actual != expected {
+            bail!(
+                "the checksum of {} does not match: expected {}, found {}",
+                url,
+                expected,
+                actual
+            );
+        }
+
+        // Write the library under a temporary name first so that an interrupted download is never
+        // mistaken for a cached engine.
+        let dir = sightglass_build::engine_dir("wasmtime", commit)?;
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let mut build_info = fs::File::create(dir.join(".build-info"))?;
+        writeln!(build_info, "NAME=wasmtime")?;
+        writeln!(build_info, "REVISION={}", commit)?;
+        writeln!(build_info, "_COMMIT={}", commit)?;
+        writeln!(build_info, "_SOURCE={}", url)?;
+        writeln!(build_info, "_SHA256={}", actual)?;
+        let library = dir.join(sightglass_build::get_engine_filename());
+        let partial = library.with_extension("partial");
+        fs::write(&partial, &bytes)
+            .and_then(|()| fs::rename(&partial, &library))
+            .with_context(|| format!("failed to write {}", library.display()))?;
+        log::info!("Saved engine: {}", library.display());
+        Ok(library)
+    }
+}
+
+/// Find the commit at the head of a GitHub pull request.
+fn pull_request_head(client: &Client, repository: &str, pr: u32) -> Result<String> {
+    let url = format!("https://api.github.com/repos/{}/pulls/{}", repository, pr);
+    let response: serde_json::Value = client
+        .get(&url)
+        .header("Accept", "application/vnd.github+json")
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .with_context(|| format!("failed to look up pull request {}", pr))?;
+    let commit = response["head"]["sha"]
+        .as_str()
+        .with_context(|| format!("pull request {} has no head commit", pr))?;
+    log::info!("Pull request {} is at commit {}", pr, commit);
+    Ok(commit.to_string())
+}
+
+/// Fill in the placeholders of the download URL template.
+fn library_url(template: &str, commit: &str) -> String {
+    let target = format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS);
+    let library = format!(
+        "{}wasmtime_bench_api{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    );
+    template
+        .replace("{commit}", commit)
+        .replace("{target}", &target)
+        .replace("{library}", &library)
+}
+
+fn is_commit_hash(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Read the digest out of a checksum file in the format of `sha256sum`; i.e., `<digest>
+/// <file name>`.
+fn parse_checksum(contents: &str) -> Option<String> {
+    let digest = contents.split_whitespace().next()?.to_lowercase();
+    if digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        Some(digest)
+    } else {
+        None
+    }
+}
+
+pub(crate) fn sha256(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_in_url() {
+        let url = library_url(
+            "https://example.com/{commit}/{target}/{library}",
+            "0123456789abcdef0123456789abcdef01234567",
+        );
+        assert!(url.starts_with("https://example.com/0123456789abcdef0123456789abcdef01234567/"));
+        assert!(!url.contains('{'));
+    }
+
+    #[test]
+    fn check_checksums() {
+        let digest = sha256(b"abc");
+        assert_eq!(
+            digest,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        let file = format!("{}  libwasmtime_bench_api.so\n", digest.to_uppercase());
+        assert_eq!(parse_checksum(&file), Some(digest));
+        assert_eq!(parse_checksum("not a checksum"), None);
+        assert!(is_commit_hash("0123456789abcdef0123456789abcdef01234567"));
+        assert!(!is_commit_hash("main"));
+    }
+}
@@ -0,0 +1,380 @@
+use crate::benchmark::{check_engine_path, this_arch};
+use anyhow::{Context, Result};
+use sightglass_data::Phase;
+use sightglass_recorder::measure::{noop::NoopMeasure, Measurements};
+use sightglass_recorder::profile::{self, perf::PerfProfile, ProfileType};
+use sightglass_recorder::{
+    bench_api::BenchApi,
+    benchmark::{benchmark, BenchmarkOptions},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use structopt::StructOpt;
+
+/// Profile a Wasm benchmark with `perf` and generate a flamegraph of each
+/// phase.
+///
+/// For each phase, this writes the folded stacks (`*.folded`) and a
+/// flamegraph (`*.svg`) to the output directory.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "flamegraph")]
+pub struct FlamegraphCommand {
+    /// The path to the benchmark engine's shared library.
+    #[structopt(index = 1, required = true, value_name = "ENGINE")]
+    engine: String,
+
+    /// The path to the Wasm file to profile.
+    #[structopt(
+        index = 2,
+        required = true,
+        value_name = "WASMFILE",
+        parse(from_os_str)
+    )]
+    wasm_file: PathBuf,
+
+    /// How many times to run the benchmark; more iterations collect more
+    /// samples.
+    #[structopt(long, default_value = "10")]
+    iterations: usize,
+
+    /// Configure the engine using engine-specific flags.
+    #[structopt(long("engine-flags"), value_name = "ENGINE_FLAGS")]
+    engine_flags: Option<String>,
+
+    /// Do not ask the engine to emit a perf map (`--profile=perfmap`) for its
+    /// JIT-compiled code. Without it, frames in Wasm code show up as
+    /// `[unknown]`; use this for engines that do not support the flag.
+    #[structopt(long)]
+    no_jit_symbols: bool,
+
+    /// The directory to preopen as the benchmark working directory; defaults to
+    /// the Wasm file's parent directory.
+    #[structopt(short("d"), long("working-dir"), parse(from_os_str))]
+    working_dir: Option<PathBuf>,
+
+    /// The directory in which to write the profiles and flamegraphs.
+    #[structopt(
+        short("o"),
+        long("output-dir"),
+        default_value = ".",
+        parse(from_os_str)
+    )]
+    output_dir: PathBuf,
+}
+
+impl FlamegraphCommand {
+    pub fn execute(&self) -> Result<()> {
+        anyhow::ensure!(self.iterations > 0, "iterations must be greater than zero");
+        let engine_path = check_engine_path(&self.engine)?;
+        let wasm_file = self.wasm_file.display().to_string();
+        let working_dir = match &self.working_dir {
+            Some(dir) => dir.clone(),
+            None => self
+                .wasm_file
+                .parent()
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf),
+        };
+        let bytes = fs::read(&self.wasm_file).context("Attempting to read Wasm bytes")?;
+        let engine_flags = self.engine_flags();
+        let prefix = profile::output_prefix(&self.output_dir, &engine_path, &self.wasm_file);
+
+        // Run the benchmark under `perf`; the profiler only writes its data once it is dropped.
+        {
+            let lib = unsafe { libloading::Library::new(&engine_path)? };
+            let mut bench_api = unsafe { BenchApi::new(&lib)? };
+            let mut measurements = Measurements::new(this_arch(), &self.engine, &wasm_file);
+            let mut measure = (ProfileType::Perf.build(&prefix), NoopMeasure::new());
+            for i in 0..self.iterations {
+                let stdout = format!("stdout-flamegraph-{}-{}.log", std::process::id(), i);
+                let stderr = format!("stderr-flamegraph-{}-{}.log", std::process::id(), i);
+                let options = BenchmarkOptions {
+                    execution_flags: engine_flags.as_deref(),
+                    ..BenchmarkOptions::new(&working_dir, Path::new(&stdout), Path::new(&stderr))
+                };
+                benchmark(
+                    &mut bench_api,
+                    &bytes,
+                    &options,
+                    &mut measure,
+                    &mut measurements,
+                )?;
+                measurements.next_iteration();
+            }
+        }
+
+        for phase in [Phase::Compilation, Phase::Instantiation, Phase::Execution] {
+            let perf_data = PerfProfile::output_file(&prefix, phase);
+            let output = Command::new("perf")
+                .arg("script")
+                .arg("--input")
+                .arg(&perf_data)
+                .output()
+                .context("failed to run `perf script`")?;
+            anyhow::ensure!(
+                output.status.success(),
+                "`perf script` failed for {}:\n{}",
+                perf_data.display(),
+                String::from_utf8_lossy(&output.stderr)
+            );
+
+            let stacks = fold(&String::from_utf8_lossy(&output.stdout));
+            let folded_file = with_suffix(&prefix, &format!(".{}.folded", phase));
+            let mut folded = String::new();
+            for (stack, count) in &stacks {
+                writeln!(folded, "{} {}", stack, count)?;
+            }
+            fs::write(&folded_file, folded)
+                .with_context(|| format!("failed to write {}", folded_file.display()))?;
+
+            let svg_file = with_suffix(&prefix, &format!(".{}.svg", phase));
+            let title = format!("{} :: {}", phase, self.wasm_file.display());
+            fs::write(&svg_file, render_svg(&title, &stacks))
+                .with_context(|| format!("failed to write {}", svg_file.display()))?;
+            log::info!("Wrote {} flamegraph to {}", phase, svg_file.display());
+        }
+
+        Ok(())
+    }
+
+    /// Add the flags necessary for resolving JIT-compiled frames to any user-provided flags.
+    fn engine_flags(&self) -> Option<String> {
+        if self.no_jit_symbols {
+            self.engine_flags.clone()
+        } else {
+            Some(match &self.engine_flags {
+                Some(flags) => format!("{} --profile=perfmap", flags),
+                None => "--profile=perfmap".to_string(),
+            })
+        }
+    }
+}
+
+fn with_suffix(prefix: &Path, suffix: &str) -> PathBuf {
+    let mut path = prefix.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+/// Fold the samples printed by `perf script` into `outermost;...;innermost` stacks and count how
+/// many samples share each stack.
+fn fold(perf_script: &str) -> BTreeMap<String, u64> {
+    let mut stacks = BTreeMap::new();
+    let mut frames: Vec<String> = vec![];
+    let mut in_sample = false;
+    for line in perf_script.lines().chain(std::iter::once("")) {
+        if line.trim().is_empty() {
+            if in_sample && !frames.is_empty() {
+                frames.reverse();
+                *stacks.entry(frames.join(";")).or_insert(0) += 1;
+            }
+            frames.clear();
+            in_sample = false;
+        } else if line.starts_with(char::is_whitespace) {
+            // A stack frame: `<address> <symbol>+<offset> (<object>)`.
+            let frame = line.trim();
+            let frame = frame.split_once(' ').map_or(frame, |(_, rest)| rest);
+            let (symbol, object) = match frame.rsplit_once(" (") {
+                Some((symbol, object)) => (symbol, object.trim_end_matches(')')),
+                None => (frame, ""),
+            };
+            let symbol = match symbol.rsplit_once("+0x") {
+                Some((name, _)) => name,
+                None => symbol,
+            };
+            let symbol = if symbol == "[unknown]" && !object.is_empty() {
+                let object = Path::new(object)
+                    .file_name()
+                    .map_or(object.into(), |n| n.to_string_lossy());
+                format!("[{}]", object)
+            } else {
+                symbol.to_string()
+            };
+            // Semicolons separate frames in the folded format.
+            frames.push(symbol.replace(';', ":"));
+        } else {
+            // A sample header: `<command> <pid> <time>: <period> <event>:`.
+            in_sample = true;
+        }
+    }
+    stacks
+}
+
+const IMAGE_WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+const PADDING: f64 = 10.0;
+const TITLE_HEIGHT: f64 = 30.0;
+
+/// A node in the tree of folded stacks.
+#[derive(Default)]
+struct Frame {
+    samples: u64,
+    children: BTreeMap<String, Frame>,
+}
+
+impl Frame {
+    fn depth(&self) -> usize {
+        1 + self.children.values().map(Frame::depth).max().unwrap_or(0)
+    }
+}
+
+/// Render folded stacks as a flamegraph SVG: the width of each frame is proportional to the number
+/// of samples in which it appears, and the frames of a stack are drawn from the bottom up.
+fn render_svg(title: &str, stacks: &BTreeMap<String, u64>) -> String {
+    let mut root = Frame::default();
+    for (stack, count) in stacks {
+        root.samples += count;
+        let mut node = &mut root;
+        for frame in stack.split(';') {
+            node = node.children.entry(frame.to_string()).or_default();
+            node.samples += count;
+        }
+    }
+
+    let height = TITLE_HEIGHT + (root.depth() as f64) * FRAME_HEIGHT + PADDING;
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r##"<?xml version="1.0" standalone="no"?>
+<svg version="1.1" width="{w}" height="{h}" viewBox="0 0 {w} {h}" xmlns="http://www.w3.org/2000/svg">
+<rect x="0" y="0" width="{w}" height="{h}" fill="#f8f8f8"/>
+<text x="{cx}" y="20" font-size="15" font-family="Verdana" text-anchor="middle">{title}</text>"##,
+        w = IMAGE_WIDTH,
+        h = height,
+        cx = IMAGE_WIDTH / 2.0,
+        title = escape(title),
+    )
+    .unwrap();
+    if root.samples > 0 {
+        let mut renderer = Renderer {
+            svg: &mut svg,
+            total: root.samples,
+            height,
+            scale: (IMAGE_WIDTH - 2.0 * PADDING) / root.samples as f64,
+        };
+        renderer.frame("all", &root, PADDING, 0);
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Draws frames, recursively, into an SVG document.
+struct Renderer<'a> {
+    svg: &'a mut String,
+    total: u64,
+    height: f64,
+    scale: f64,
+}
+
+impl Renderer<'_> {
+    fn frame(&mut self, name: &str, frame: &Frame, x: f64, depth: usize) {
+        let width = frame.samples as f64 * self.scale;
+        let y = self.height - PADDING - (depth as f64 + 1.0) * FRAME_HEIGHT;
+        let percent = frame.samples as f64 * 100.0 / self.total as f64;
+        writeln!(
+            self.svg,
+            r#"<g><title>{name} ({samples} samples, {percent:.2}%)</title><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{fh}" fill="{color}" rx="2"/>"#,
+            name = escape(name),
+            samples = frame.samples,
+            percent = percent,
+            x = x,
+            y = y,
+            width = width,
+            fh = FRAME_HEIGHT - 1.0,
+            color = color(name),
+        )
+        .unwrap();
+        // Only label frames wide enough to fit a few characters (at roughly 7px per character).
+        let max_chars = ((width - 6.0) / 7.0) as usize;
+        if max_chars >= 3 {
+            let label = if name.chars().count() > max_chars {
+                let truncated: String = name.chars().take(max_chars - 2).collect();
+                format!("{}..", truncated)
+            } else {
+                name.to_string()
+            };
+            writeln!(
+                self.svg,
+                r#"<text x="{:.1}" y="{:.1}" font-size="12" font-family="Verdana">{}</text>"#,
+                x + 3.0,
+                y + FRAME_HEIGHT - 4.0,
+                escape(&label)
+            )
+            .unwrap();
+        }
+        self.svg.push_str("</g>\n");
+
+        let mut child_x = x;
+        for (child_name, child) in &frame.children {
+            self.frame(child_name, child, child_x, depth + 1);
+            child_x += child.samples as f64 * self.scale;
+        }
+    }
+}
+
+/// Pick a deterministic, warm color for a frame based on its name.
+fn color(name: &str) -> String {
+    let hash = name
+        .bytes()
+        .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+    let red = 205 + (hash % 50);
+    let green = (hash / 50) % 230;
+    let blue = (hash / 11500) % 55;
+    format!("rgb({},{},{})", red, green, blue)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PERF_SCRIPT: &str = "\
+sightglass-cli 1234 100.000001:     250000 cpu-clock:u:
+\t    7f0000001000 wasm[0]::function[3]+0x10 (/tmp/perf-1234.map)
+\t    7f0000002000 wasmtime_bench_api::execute+0x20 (/tmp/libengine.so)
+\t    7f0000003000 main+0x30 (/usr/bin/sightglass-cli)
+
+sightglass-cli 1234 100.000002:     250000 cpu-clock:u:
+\t    7f0000001000 [unknown] (/tmp/libengine.so)
+\t    7f0000003000 main+0x30 (/usr/bin/sightglass-cli)
+
+sightglass-cli 1234 100.000003:     250000 cpu-clock:u:
+\t    7f0000001004 wasm[0]::function[3]+0x14 (/tmp/perf-1234.map)
+\t    7f0000002000 wasmtime_bench_api::execute+0x20 (/tmp/libengine.so)
+\t    7f0000003000 main+0x30 (/usr/bin/sightglass-cli)
+";
+
+    #[test]
+    fn fold_perf_script() {
+        let stacks = fold(PERF_SCRIPT);
+        assert_eq!(
+            stacks.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("main;[libengine.so]".to_string(), 1),
+                (
+                    "main;wasmtime_bench_api::execute;wasm[0]::function[3]".to_string(),
+                    2
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn render_folded_stacks() {
+        let svg = render_svg("execution :: noop", &fold(PERF_SCRIPT));
+        assert!(svg.starts_with("<?xml"));
+        assert!(svg.contains("<title>all (3 samples, 100.00%)</title>"));
+        assert!(svg.contains("<title>wasm[0]::function[3] (2 samples, 66.67%)</title>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, RequestBuilder};
+use std::fmt;
+
+/// Marks the comment that Sightglass posts on a pull request so that later runs update it rather
+/// than adding another.
+const MARKER: &str = "<!-- sightglass-effect-size -->";
+
+/// A GitHub pull request, written `owner/repo#123`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PullRequest {
+    pub repository: String,
+    pub number: u64,
+}
+
+impl std::str::FromStr for PullRequest {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = || {
+            let (repository, number) = s.split_once('#')?;
+            let (owner, repo) = repository.split_once('/')?;
+            if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+                return None;
+            }
+            Some(PullRequest {
+                repository: repository.to_string(),
+                number: number.parse().ok()?,
+            })
+        };
+        parse().with_context(|| format!("expected a pull request like `owner/repo#123`: {}", s))
+    }
+}
+
+impl fmt::Display for PullRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.repository, self.number)
+    }
+}
+
+/// Post `body` as a comment on the pull request `pr`, or, if Sightglass has already commented
+/// there, replace that comment's body so that the pull request keeps a single, up-to-date report.
+/// The API token is read from `$GITHUB_TOKEN` and the API from `$GITHUB_API_URL` (set by GitHub
+/// Actions, e.g., for GitHub Enterprise), defaulting to `https://api.github.com`. Returns the
+/// comment's URL.
+pub fn upsert_comment(pr: &PullRequest, body: &str) -> Result<String> {
+    let token = std::env::var("GITHUB_TOKEN")
+        .context("$GITHUB_TOKEN must be set to comment on a pull request")?;
+    let api = std::env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".into());
+    let api = api.trim_end_matches('/');
+    let client = Client::builder()
+        .user_agent(concat!("sightglass/", env!("CARGO_PKG_VERSION")))
+        .build()?;
+    let authorize = |request: RequestBuilder| {
+        request
+            .bearer_auth(&token)
+            .header("Accept", "application/vnd.github+json")
+    };
+
+    // Look through the comments, a page at a time, for the one posted before.
+    let mut existing = None;
+    for page in 1.. {
+        let url = format!(
+            "{}/repos/{}/issues/{}/comments?per_page=100&page={}",
+            api, pr.repository, pr.number, page
+        );
+        let comments: Vec<serde_json::Value> = authorize(client.get(&url))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .and_then(|r| r.json())
+            .with_context(|| format!("failed to list the comments on {}", pr))?;
+        existing = find_comment(&comments);
+        if existing.is_some() || comments.len() < 100 {
+            break;
+        }
+    }
+
+    let body = serde_json::json!({ "body": format!("{}\n{}", MARKER, body) });
+    let request = match existing {
+        Some(id) => {
+            log::info!("Updating comment {} on {}", id, pr);
+            client.patch(format!(
+                "{}/repos/{}/issues/comments/{}",
+                api, pr.repository, id
+            ))
+        }
+        None => {
+            log::info!("Commenting on {}", pr);
+            client.post(format!(
+                "{}/repos/{}/issues/{}/comments",
+                api, pr.repository, pr.number
+            ))
+        }
+    };
+    let comment: serde_json::Value = authorize(request)
+        .json(&body)
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.json())
+        .with_context(|| format!("failed to comment on {}", pr))?;
+    Ok(comment["html_url"].as_str().unwrap_or_default().to_string())
+}
+
+/// Find the ID of the comment Sightglass posted among a page of issue `comments`.
+fn find_comment(comments: &[serde_json::Value]) -> Option<u64> {
+    comments
+        .iter()
+        .find(|comment| {
+            comment["body"]
+                .as_str()
+                .is_some_and(|body| body.starts_with(MARKER))
+        })
+        .and_then(|comment| comment["id"].as_u64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_pull_request() {
+        let pr: PullRequest = "bytecodealliance/wasmtime#1234".parse().unwrap();
+        assert_eq!(pr.repository, "bytecodealliance/wasmtime");
+        assert_eq!(pr.number, 1234);
+        assert_eq!(pr.to_string(), "bytecodealliance/wasmtime#1234");
+        for invalid in ["wasmtime#1234", "a/b", "a/b#", "a/b/c#1", "/b#1", "a/b#x"] {
+            assert!(invalid.parse::<PullRequest>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn find_previous_comment() {
+        let comments = serde_json::json!([
+            { "id": 1, "body": "Looks good!" },
+            { "id": 2, "body": format!("{}\n### Sightglass results", MARKER) },
+        ]);
+        let comments = comments.as_array().unwrap();
+        assert_eq!(find_comment(comments), Some(2));
+        assert_eq!(find_comment(&comments[..1]), None);
+    }
+}
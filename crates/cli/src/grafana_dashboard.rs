@@ -0,0 +1,62 @@
+use anyhow::Result;
+use sightglass_analysis::grafana::{self, Datasource, Panel};
+use sightglass_data::{Format, Measurements};
+use std::{
+    fs::File,
+    io::{self, BufReader},
+};
+use structopt::StructOpt;
+
+/// Print a Grafana dashboard (as JSON, to import or provision) charting
+/// results stored by `summarize --influx` or `upload-postgres`; accepts raw
+/// benchmark results in `stdin` (i.e., from `sightglass-cli benchmark ...`)
+/// to find which benchmarks, phases, and events to chart.
+///
+/// Each phase of each benchmark is a panel charting the median of the event
+/// selected on the dashboard, with a series per engine.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "grafana-dashboard")]
+pub struct GrafanaDashboardCommand {
+    /// Path to the file(s) that will be read from, or none to indicate stdin (default).
+    #[structopt(short = "f")]
+    input_file: Option<Vec<String>>,
+
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Where the results are stored. Either 'influx' (the points written by
+    /// `summarize --influx`) or 'postgres' (the tables written by
+    /// `upload-postgres`).
+    #[structopt(long, default_value = "influx")]
+    datasource: Datasource,
+
+    /// The schema of the PostgreSQL tables.
+    #[structopt(long, default_value = "sightglass")]
+    schema: String,
+
+    /// The title of the dashboard.
+    #[structopt(long, default_value = "Sightglass")]
+    title: String,
+}
+
+impl GrafanaDashboardCommand {
+    pub fn execute(&self) -> Result<()> {
+        let mut measurements = Measurements::default();
+        if let Some(files) = self.input_file.as_ref() {
+            for file in files {
+                let reader = BufReader::new(File::open(file)?);
+                measurements.read(&self.input_format.clone(), reader)?;
+            }
+        } else {
+            measurements.read(&self.input_format, io::stdin())?;
+        }
+
+        let (panels, events) = Panel::find(&measurements.to_vec());
+        let dashboard =
+            grafana::dashboard(&self.title, self.datasource, &self.schema, &panels, &events);
+        serde_json::to_writer_pretty(io::stdout(), &dashboard)?;
+        println!();
+        Ok(())
+    }
+}
@@ -1,19 +1,67 @@
+mod archive;
 mod benchmark;
+mod clean;
+mod compare_reference;
+mod daemon;
 mod effect_size;
+mod fetch;
+mod fetch_engine;
 mod fingerprint;
+#[cfg(target_os = "linux")]
+mod flamegraph;
+mod github;
+mod grafana_dashboard;
+mod merge;
+mod notify;
+mod package;
+mod runs;
+mod schedule;
+mod serve;
+mod sign;
+mod spill;
+mod store;
+mod stream;
 mod summarize;
+mod tui;
 mod upload;
+mod upload_codespeed;
+mod upload_conbench;
+mod upload_otlp;
+#[cfg(feature = "postgres")]
+mod upload_postgres;
 mod validate;
+mod validate_results;
+mod verify;
 
 use anyhow::Result;
 use benchmark::BenchmarkCommand;
+use clean::CleanCommand;
+use compare_reference::CompareReferenceCommand;
+use daemon::DaemonCommand;
 use effect_size::EffectSizeCommand;
+use fetch::FetchCommand;
+use fetch_engine::FetchEngineCommand;
 use fingerprint::FingerprintCommand;
+#[cfg(target_os = "linux")]
+use flamegraph::FlamegraphCommand;
+use grafana_dashboard::GrafanaDashboardCommand;
 use log::trace;
+use merge::MergeCommand;
+use package::PackageCommand;
+use runs::RunsCommand;
+use serve::ServeCommand;
+use sign::SignCommand;
 use structopt::{clap::AppSettings, StructOpt};
 use summarize::SummarizeCommand;
 use upload::UploadCommand;
+use upload_codespeed::UploadCodespeedCommand;
+use upload_conbench::UploadConbenchCommand;
+use upload_otlp::UploadOtlpCommand;
+#[cfg(feature = "postgres")]
+use upload_postgres::UploadPostgresCommand;
 use validate::ValidateCommand;
+use validate_results::ValidateResultsCommand;
+use verify::VerifyCommand;
 
 /// Main entry point for CLI.
 fn main() -> Result<()> {
@@ -33,12 +81,32 @@ fn main() -> Result<()> {
     ],
 )]
 enum SightglassCommand {
-    Benchmark(BenchmarkCommand),
+    Benchmark(Box<BenchmarkCommand>),
+    Clean(CleanCommand),
+    CompareReference(CompareReferenceCommand),
+    Daemon(DaemonCommand),
     EffectSize(EffectSizeCommand),
+    Fetch(FetchCommand),
+    FetchEngine(FetchEngineCommand),
     Fingerprint(FingerprintCommand),
+    #[cfg(target_os = "linux")]
+    Flamegraph(FlamegraphCommand),
+    GrafanaDashboard(GrafanaDashboardCommand),
+    Merge(MergeCommand),
+    Package(PackageCommand),
+    Runs(RunsCommand),
+    Serve(ServeCommand),
+    Sign(SignCommand),
     Summarize(SummarizeCommand),
+    UploadCodespeed(UploadCodespeedCommand),
+    UploadConbench(UploadConbenchCommand),
     UploadElastic(UploadCommand),
+    UploadOtlp(UploadOtlpCommand),
+    #[cfg(feature = "postgres")]
+    UploadPostgres(UploadPostgresCommand),
     Validate(ValidateCommand),
+    ValidateResults(ValidateResultsCommand),
+    Verify(VerifyCommand),
 }
 
 impl SightglassCommand {
@@ -46,11 +114,31 @@ impl SightglassCommand {
         trace!("Executing command: {:?}", &self);
         match self {
             SightglassCommand::Benchmark(benchmark) => benchmark.execute(),
+            SightglassCommand::Clean(clean) => clean.execute(),
+            SightglassCommand::CompareReference(compare) => compare.execute(),
+            SightglassCommand::Daemon(daemon) => daemon.execute(),
             SightglassCommand::EffectSize(effect_size) => effect_size.execute(),
+            SightglassCommand::Fetch(fetch) => fetch.execute(),
+            SightglassCommand::FetchEngine(fetch_engine) => fetch_engine.execute(),
             SightglassCommand::Fingerprint(fingerprint) => fingerprint.execute(),
+            #[cfg(target_os = "linux")]
+            SightglassCommand::Flamegraph(flamegraph) => flamegraph.execute(),
+            SightglassCommand::GrafanaDashboard(grafana) => grafana.execute(),
+            SightglassCommand::Merge(merge) => merge.execute(),
+            SightglassCommand::Package(package) => package.execute(),
+            SightglassCommand::Runs(runs) => runs.execute(),
+            SightglassCommand::Serve(serve) => serve.execute(),
+            SightglassCommand::Sign(sign) => sign.execute(),
             SightglassCommand::Summarize(summarize) => summarize.execute(),
+            SightglassCommand::UploadCodespeed(upload) => upload.execute(),
+            SightglassCommand::UploadConbench(upload) => upload.execute(),
             SightglassCommand::UploadElastic(upload) => upload.execute(),
+            SightglassCommand::UploadOtlp(upload) => upload.execute(),
+            #[cfg(feature = "postgres")]
+            SightglassCommand::UploadPostgres(upload) => upload.execute(),
             SightglassCommand::Validate(validate) => validate.execute(),
+            SightglassCommand::ValidateResults(validate) => validate.execute(),
+            SightglassCommand::Verify(verify) => verify.execute(),
         }
     }
 }
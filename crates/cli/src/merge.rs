@@ -0,0 +1,197 @@
+use anyhow::{Context, Result};
+use sightglass_analysis::keys::Key;
+use sightglass_data::{Format, Measurement};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::PathBuf,
+};
+use structopt::StructOpt;
+
+/// Merge results files into one. Measurements are told apart by their process ID and iteration,
+/// but process IDs are reused (e.g., after a reboot), so two runs can record distinct samples under
+/// the same ones; a process whose measurements collide with those of an earlier file is renumbered.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "merge")]
+pub struct MergeCommand {
+    /// Path to the results files to merge, in order.
+    #[structopt(short = "f", required = true, min_values = 1)]
+    input_file: Vec<String>,
+
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// The format of the merged results. Either 'json' or 'csv'.
+    #[structopt(long = "output-format", default_value = "json")]
+    output_format: Format,
+
+    /// Path to the file that will contain the merged results, or none to write them to stdout.
+    #[structopt(short = "o", long = "output-file", parse(from_os_str))]
+    output_file: Option<PathBuf>,
+}
+
+impl MergeCommand {
+    pub fn execute(&self) -> Result<()> {
+        let mut files = vec![];
+        for file in &self.input_file {
+            let opened = File::open(file).with_context(|| format!("failed to open {}", file))?;
+            // Reading CSV consumes its headers setting, so each file reads with its own copy.
+            let measurements: Vec<Measurement> = self
+                .input_format
+                .clone()
+                .read(BufReader::new(opened))
+                .with_context(|| format!("failed to read {}", file))?;
+            files.push(measurements);
+        }
+        let (merged, renumbered) = merge(files);
+        for r in &renumbered {
+            log::warn!(
+                "Renumbered process {} of {} as {}: its measurements collide with those of an \
+                 earlier file (e.g., its process ID was reused after a reboot){}",
+                r.from,
+                self.input_file[r.file],
+                r.to,
+                if r.same_counts {
+                    ", but have the same counts, so they may be the same measurements merged twice"
+                } else {
+                    ""
+                }
+            );
+        }
+        match &self.output_file {
+            Some(path) => {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                self.output_format
+                    .write_measurements(&merged, BufWriter::new(file))?;
+            }
+            None => self
+                .output_format
+                .write_measurements(&merged, io::stdout())?,
+        }
+        Ok(())
+    }
+}
+
+/// A process of a merged file that was given a new ID.
+#[derive(Debug, PartialEq)]
+struct Renumbered {
+    /// The index of the file.
+    file: usize,
+    from: u32,
+    to: u32,
+    /// Whether each colliding measurement had the same count as the one it collided with.
+    same_counts: bool,
+}
+
+/// Merge the measurements of each file, in order. A process of a file that measured the same
+/// iteration of the same benchmark (phase, event, etc.) as an earlier file is renumbered, with all
+/// its measurements, to the next ID above those of every file; the renumbering is deterministic,
+/// depending only on the files and their order. (Measurements repeated within a file are left as
+/// they are; `validate-results` reports them.)
+fn merge(files: Vec<Vec<Measurement<'_>>>) -> (Vec<Measurement<'_>>, Vec<Renumbered>) {
+    let mut next = files
+        .iter()
+        .flatten()
+        .map(|m| m.process)
+        .max()
+        .map_or(0, |max| max + 1);
+    let mut taken: HashMap<(Key, u32, u32), u64> = HashMap::new();
+    let mut merged = vec![];
+    let mut renumbered = vec![];
+    for (file, mut measurements) in files.into_iter().enumerate() {
+        // Whether each colliding process's measurements had the same counts.
+        let mut colliding: BTreeMap<u32, bool> = BTreeMap::new();
+        for m in &measurements {
+            if let Some(&count) = taken.get(&(Key::of(m), m.process, m.iteration)) {
+                *colliding.entry(m.process).or_insert(true) &= count == m.count;
+            }
+        }
+        let mut new_ids = BTreeMap::new();
+        for (from, same_counts) in colliding {
+            new_ids.insert(from, next);
+            renumbered.push(Renumbered {
+                file,
+                from,
+                to: next,
+                same_counts,
+            });
+            next += 1;
+        }
+        for m in &mut measurements {
+            if let Some(&to) = new_ids.get(&m.process) {
+                m.process = to;
+            }
+            taken.insert((Key::of(m), m.process, m.iteration), m.count);
+        }
+        merged.extend(measurements);
+    }
+    (merged, renumbered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    fn measurement(event: &str, process: u32, iteration: u32, count: u64) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: "bench.wasm".into(),
+            process,
+            iteration,
+            phase: Phase::Execution,
+            event: event.to_string().into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn renumber_colliding_processes() {
+        let files = vec![
+            vec![
+                measurement("cycles", 7, 0, 100),
+                measurement("cycles", 7, 1, 101),
+            ],
+            // Process 7 is reused for other samples; process 3 is new.
+            vec![
+                measurement("cycles", 7, 0, 102),
+                measurement("instructions", 7, 0, 300),
+                measurement("cycles", 3, 0, 103),
+            ],
+            // Process 7 measured another event, which does not collide.
+            vec![measurement("instructions", 7, 1, 301)],
+            // The same sample again.
+            vec![measurement("cycles", 7, 1, 101)],
+        ];
+        let (merged, renumbered) = merge(files);
+        assert_eq!(
+            renumbered,
+            [
+                Renumbered {
+                    file: 1,
+                    from: 7,
+                    to: 8,
+                    same_counts: false
+                },
+                Renumbered {
+                    file: 3,
+                    from: 7,
+                    to: 9,
+                    same_counts: true
+                }
+            ]
+        );
+        let ids: Vec<_> = merged.iter().map(|m| (m.process, m.iteration)).collect();
+        assert_eq!(
+            ids,
+            [(7, 0), (7, 1), (8, 0), (8, 0), (3, 0), (7, 1), (9, 1)]
+        );
+    }
+}
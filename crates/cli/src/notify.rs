@@ -0,0 +1,204 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use sightglass_data::EffectSize;
+
+/// POST a notification to `url` if any of `effect_sizes` is a significant regression: one in
+/// which the second engine (`b`, e.g., a nightly build) is slower than the first (`a`, the
+/// baseline) by `threshold` percent or more. The notification is a JSON description of the
+/// regressions or, with `slack`, a message for a Slack incoming webhook.
+pub(crate) fn notify_regressions(
+    url: &str,
+    slack: bool,
+    threshold: f64,
+    effect_sizes: &[EffectSize<'_>],
+) -> Result<()> {
+    let regressions = regressions(effect_sizes, threshold);
+    if regressions.is_empty() {
+        log::info!("No significant regressions of {}% or more", threshold);
+        return Ok(());
+    }
+    let payload = if slack {
+        slack_message(&regressions, threshold)
+    } else {
+        payload(&regressions, threshold)
+    };
+    reqwest::blocking::Client::new()
+        .post(url)
+        .json(&payload)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to send a notification to {}", url))?;
+    log::info!(
+        "Sent a notification of {} regressions to {}",
+        regressions.len(),
+        url
+    );
+    Ok(())
+}
+
+/// Find the significant regressions of `threshold` percent or more, the largest first. A
+/// regression from a baseline mean of zero is larger than any other.
+fn regressions<'a, 'b>(
+    effect_sizes: &'a [EffectSize<'b>],
+    threshold: f64,
+) -> Vec<&'a EffectSize<'b>> {
+    let slowdown = |e: &EffectSize| e.checked_b_speed_up_over_a().map_or(f64::INFINITY, |s| s.0);
+    let mut regressions: Vec<_> = effect_sizes
+        .iter()
+        .filter(|e| {
+            e.is_significant() && e.b_mean > e.a_mean && slowdown(e) >= 1.0 + threshold / 100.0
+        })
+        .collect();
+    regressions.sort_by(|x, y| slowdown(y).total_cmp(&slowdown(x)));
+    regressions
+}
+
+/// Describe the regressions as JSON; each one's `slowdown` is how many times slower the
+/// candidate is than the baseline, give or take `slowdown_confidence_interval`.
+fn payload(regressions: &[&EffectSize<'_>], threshold: f64) -> Value {
+    let first = regressions[0];
+    json!({
+        "baseline": first.a_engine,
+        "candidate": first.b_engine,
+        "significance_level": first.significance_level,
+        "threshold_percent": threshold,
+        "regressions": regressions
+            .iter()
+            .map(|r| {
+                // A regression from a baseline mean of zero has no finite slowdown.
+                let (slowdown, slowdown_ci) = r.checked_b_speed_up_over_a().unzip();
+                json!({
+                    "arch": r.arch,
+                    "benchmark": r.wasm,
+                    "phase": r.phase.to_string(),
+                    "event": r.event,
+                    "threads": r.threads,
+                    "input_size": r.input_size,
+                    "execution": r.execution,
+                    "baseline_mean": r.a_mean,
+                    "candidate_mean": r.b_mean,
+                    "slowdown": slowdown,
+                    "slowdown_confidence_interval": slowdown_ci,
+                })
+            })
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Describe the regressions as a Slack message, in Slack's `mrkdwn` text format.
+fn slack_message(regressions: &[&EffectSize<'_>], threshold: f64) -> Value {
+    let first = regressions[0];
+    let mut text = format!(
+        ":warning: Sightglass found {} significant regressions of {}% or more in `{}` compared \
+         to `{}` ({}% confidence):",
+        regressions.len(),
+        threshold,
+        first.b_engine,
+        first.a_engine,
+        (1.0 - first.significance_level) * 100.0
+    );
+    for r in regressions {
+        let slowdown = match r.checked_b_speed_up_over_a() {
+            Some((slowdown, slowdown_ci)) => format!(
+                "{:.2}x to {:.2}x slower",
+                slowdown - slowdown_ci,
+                slowdown + slowdown_ci
+            ),
+            None => "slower than a mean of 0".to_string(),
+        };
+        text.push_str(&format!(
+            "\n• `{}`{} {} {}: {}",
+            r.wasm,
+            sightglass_analysis::summarize::run_label(
+                r.threads,
+                r.input_size.as_deref(),
+                r.execution
+            ),
+            r.phase,
+            r.event,
+            slowdown
+        ));
+    }
+    json!({ "text": text })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::{Model, Phase, Statistic, Transform};
+
+    fn effect_size(wasm: &str, b_mean: f64) -> EffectSize<'static> {
+        EffectSize {
+            arch: "x86_64".into(),
+            wasm: wasm.to_string().into(),
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            a_engine: "main.so".into(),
+            a_mean: 100.0,
+            b_engine: "nightly.so".into(),
+            b_mean,
+            significance_level: 0.01,
+            half_width_confidence_interval: 2.0,
+            statistic: Statistic::Mean,
+            transform: Transform::None,
+            model: Model::Independent,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn find_regressions() {
+        let effect_sizes = vec![
+            // Faster, slower by less than the threshold, insignificant, and two regressions.
+            effect_size("faster.wasm", 50.0),
+            effect_size("slightly-slower.wasm", 104.0),
+            effect_size("insignificant.wasm", 101.0),
+            effect_size("slower.wasm", 110.0),
+            effect_size("much-slower.wasm", 200.0),
+        ];
+        let regressions = regressions(&effect_sizes, 5.0);
+        let names: Vec<_> = regressions.iter().map(|r| r.wasm.as_ref()).collect();
+        assert_eq!(names, ["much-slower.wasm", "slower.wasm"]);
+
+        let payload = payload(&regressions, 5.0);
+        assert_eq!(payload["baseline"], "main.so");
+        assert_eq!(payload["candidate"], "nightly.so");
+        assert_eq!(payload["regressions"][0]["benchmark"], "much-slower.wasm");
+        assert_eq!(payload["regressions"][0]["phase"], "execution");
+        assert_eq!(payload["regressions"][1]["slowdown"], 1.1);
+
+        let message = slack_message(&regressions, 5.0);
+        assert_eq!(
+            message["text"],
+            ":warning: Sightglass found 2 significant regressions of 5% or more in `nightly.so` \
+             compared to `main.so` (99% confidence):\n\
+             • `much-slower.wasm` execution cycles: 1.98x to 2.02x slower\n\
+             • `slower.wasm` execution cycles: 1.08x to 1.12x slower"
+        );
+    }
+
+    #[test]
+    fn find_regressions_from_zero() {
+        let effect_sizes = vec![
+            effect_size("slower.wasm", 110.0),
+            EffectSize {
+                a_mean: 0.0,
+                ..effect_size("from-zero.wasm", 10.0)
+            },
+            EffectSize {
+                a_mean: 0.0,
+                ..effect_size("zeros.wasm", 0.0)
+            },
+        ];
+        let regressions = regressions(&effect_sizes, 5.0);
+        let names: Vec<_> = regressions.iter().map(|r| r.wasm.as_ref()).collect();
+        assert_eq!(names, ["from-zero.wasm", "slower.wasm"]);
+        assert!(payload(&regressions, 5.0)["regressions"][0]["slowdown"].is_null());
+        assert!(slack_message(&regressions, 5.0)["text"]
+            .as_str()
+            .unwrap()
+            .contains("• `from-zero.wasm` execution cycles: slower than a mean of 0\n"));
+    }
+}
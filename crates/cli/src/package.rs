@@ -0,0 +1,374 @@
+use crate::fetch_engine::sha256;
+use anyhow::{bail, Context, Result};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sightglass_build::{BenchmarkConfig, BENCHMARK_CONFIG};
+use sightglass_data::SuiteManifest;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+use structopt::StructOpt;
+
+/// The file of a packaged suite holding the suite's version.
+const VERSION_FILE: &str = "VERSION";
+
+/// The file of a packaged suite holding the SHA-256 digest of each of its other files, in the
+/// format of `sha256sum`.
+const CHECKSUMS_FILE: &str = "SHA256SUMS";
+
+/// Package built benchmarks into a versioned archive of the benchmark suite,
+/// `sightglass-suite-<VERSION>.tar.gz`, so that results can be tracked over
+/// time against exactly the same benchmarks (see `benchmark --suite`).
+///
+/// The archive holds each Wasm file and, from its benchmark directory, its
+/// `benchmark.toml`, `.variant` file, expected output, inputs (as listed in its
+/// `benchmark.toml`, or named `*.input*`), and license files, along with a
+/// `SHA256SUMS` lockfile of every file in the suite. The archive is built
+/// reproducibly, so packaging the same files again gives the same archive.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "package")]
+pub struct PackageCommand {
+    /// The version of the suite; e.g., `2024-01`.
+    #[structopt(long("suite-version"), value_name = "VERSION")]
+    version: String,
+
+    /// Where to write the archive; by default,
+    /// `sightglass-suite-<VERSION>.tar.gz` in the current directory.
+    #[structopt(short, long, value_name = "ARCHIVE", parse(from_os_str))]
+    output_file: Option<PathBuf>,
+
+    /// The Wasm files of the benchmarks to package; e.g.,
+    /// `benchmarks/*/benchmark.wasm`. Each is packaged under the name of its
+    /// benchmark directory (e.g., `bz2/benchmark.wasm`).
+    #[structopt(
+        index = 1,
+        required = true,
+        value_name = "WASMFILE",
+        parse(from_os_str)
+    )]
+    wasm_files: Vec<PathBuf>,
+}
+
+impl PackageCommand {
+    pub fn execute(&self) -> Result<()> {
+        anyhow::ensure!(
+            !self.version.is_empty()
+                && !self
+                    .version
+                    .contains(|c: char| c == '/' || c == '\\' || c.is_whitespace()),
+            "invalid suite version: {:?}",
+            self.version
+        );
+
+        let mut files = BTreeMap::new();
+        for wasm in &self.wasm_files {
+            add_benchmark(&mut files, wasm)?;
+        }
+
+        let output_file = self
+            .output_file
+            .clone()
+            .unwrap_or_else(|| format!("sightglass-suite-{}.tar.gz", self.version).into());
+        let file = fs::File::create(&output_file)
+            .with_context(|| format!("failed to create {}", output_file.display()))?;
+        let mut archive =
+            tar::Builder::new(GzEncoder::new(BufWriter::new(file), Compression::best()));
+        let root = format!("sightglass-suite-{}", self.version);
+        let version = format!("{}\n", self.version);
+        let mut checksums = format!("{}  {}\n", sha256(version.as_bytes()), VERSION_FILE);
+        append(&mut archive, &root, VERSION_FILE, version.as_bytes())?;
+        for (path, source) in &files {
+            let bytes =
+                fs::read(source).with_context(|| format!("failed to read {}", source.display()))?;
+            checksums.push_str(&format!("{}  {}\n", sha256(&bytes), path));
+            append(&mut archive, &root, path, &bytes)?;
+        }
+        append(&mut archive, &root, CHECKSUMS_FILE, checksums.as_bytes())?;
+        archive.into_inner()?.finish()?.flush()?;
+
+        log::info!(
+            "Packaged {} files into {}",
+            files.len(),
+            output_file.display()
+        );
+        println!(
+            "{}  {}",
+            sha256(&fs::read(&output_file)?),
+            output_file.display()
+        );
+        Ok(())
+    }
+}
+
+/// Add the files of the benchmark of the Wasm file `wasm` to those to package, by their path in
+/// the suite.
+fn add_benchmark(files: &mut BTreeMap<String, PathBuf>, wasm: &Path) -> Result<()> {
+    let config = BenchmarkConfig::read(wasm)?;
+    let dir = match wasm.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = dir
+        .canonicalize()
+        .with_context(|| format!("failed to find {}", dir.display()))?
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .with_context(|| format!("{} has no benchmark directory", wasm.display()))?;
+
+    add(files, &name, &dir, Path::new(wasm.file_name().unwrap()))?;
+    let variant = wasm.with_extension("variant");
+    if variant.is_file() {
+        add(files, &name, &dir, Path::new(variant.file_name().unwrap()))?;
+    }
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let packaged = file_name == BENCHMARK_CONFIG
+            || file_name.ends_with(".expected")
+            || file_name.contains(".input")
+            || file_name.starts_with("LICENSE");
+        if packaged && entry.path().is_file() {
+            add(files, &name, &dir, Path::new(&file_name))?;
+        }
+    }
+    for input in &config.inputs {
+        add(files, &name, &dir, Path::new(input))?;
+    }
+    for size_dir in config.input_sizes.values() {
+        add_dir(files, &name, &dir, size_dir)?;
+    }
+    Ok(())
+}
+
+/// Add every file under the directory `relative` (to the benchmark directory `dir`).
+fn add_dir(
+    files: &mut BTreeMap<String, PathBuf>,
+    name: &str,
+    dir: &Path,
+    relative: &Path,
+) -> Result<()> {
+    for entry in fs::read_dir(dir.join(relative))? {
+        let relative = relative.join(entry?.file_name());
+        if dir.join(&relative).is_dir() {
+            add_dir(files, name, dir, &relative)?;
+        } else {
+            add(files, name, dir, &relative)?;
+        }
+    }
+    Ok(())
+}
+
+/// Add the file `relative` (to the benchmark directory `dir`) to those to package, as
+/// `<name>/<relative>`.
+fn add(
+    files: &mut BTreeMap<String, PathBuf>,
+    name: &str,
+    dir: &Path,
+    relative: &Path,
+) -> Result<()> {
+    let components: Vec<_> = relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect();
+    let path = format!("{}/{}", name, components.join("/"));
+    let source = dir.join(relative);
+    match files.get(&path) {
+        Some(other) if other != &source => bail!(
+            "both {} and {} would be packaged as {}",
+            other.display(),
+            source.display(),
+            path
+        ),
+        _ => {
+            files.insert(path, source);
+        }
+    }
+    Ok(())
+}
+
+/// Append a file to the archive with fixed metadata, so that the archive is reproducible.
+fn append<W: Write>(
+    archive: &mut tar::Builder<W>,
+    root: &str,
+    path: &str,
+    bytes: &[u8],
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    archive
+        .append_data(&mut header, format!("{}/{}", root, path), bytes)
+        .with_context(|| format!("failed to package {}", path))
+}
+
+/// A packaged benchmark suite (see [PackageCommand]), extracted into the cache.
+#[derive(Debug)]
+pub struct Suite {
+    /// The directory the suite was extracted into.
+    dir: PathBuf,
+    version: String,
+    sha256: String,
+    /// The Wasm files of the suite, by their path in the suite; e.g., `bz2/benchmark.wasm`.
+    benchmarks: Vec<String>,
+}
+
+impl Suite {
+    /// Extract the suite archive at `path` into the cache (see `SIGHTGLASS_CACHE_DIR`), unless it
+    /// is already there, and check its files against its checksums.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::extract(path, &sightglass_build::cache_dir()?.join("suites"))
+    }
+
+    fn extract(path: &Path, cache: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let digest = sha256(&bytes);
+        let extracted = cache.join(format!("sha256-{}", digest));
+        if !extracted.is_dir() {
+            let partial = extracted.with_extension("partial");
+            if partial.exists() {
+                fs::remove_dir_all(&partial)?;
+            }
+            fs::create_dir_all(&partial)?;
+            tar::Archive::new(GzDecoder::new(bytes.as_slice()))
+                .unpack(&partial)
+                .with_context(|| format!("failed to extract {}", path.display()))?;
+            fs::rename(&partial, &extracted)?;
+        }
+
+        let roots = fs::read_dir(&extracted)?.collect::<Result<Vec<_>, _>>()?;
+        let dir = match roots.as_slice() {
+            [root] => root.path(),
+            _ => bail!("{} does not hold a single suite directory", path.display()),
+        };
+        let version = fs::read_to_string(dir.join(VERSION_FILE))
+            .with_context(|| format!("{} has no {}", path.display(), VERSION_FILE))?
+            .trim()
+            .to_string();
+        let checksums = fs::read_to_string(dir.join(CHECKSUMS_FILE))
+            .with_context(|| format!("{} has no {}", path.display(), CHECKSUMS_FILE))?;
+        let mut benchmarks = vec![];
+        for line in checksums.lines() {
+            let (expected, file) = line
+                .split_once("  ")
+                .with_context(|| format!("invalid {} line: {}", CHECKSUMS_FILE, line))?;
+            let actual = fs::read(dir.join(file))
+                .map(|bytes| sha256(&bytes))
+                .with_context(|| format!("{} is missing from {}", file, dir.display()))?;
+            anyhow::ensure!(
+                actual == expected,
+                "{} does not match its checksum in {}",
+                file,
+                path.display()
+            );
+            if file.ends_with(".wasm") {
+                benchmarks.push(file.to_string());
+            }
+        }
+        log::info!(
+            "Using suite {} ({} benchmarks) from {}",
+            version,
+            benchmarks.len(),
+            dir.display()
+        );
+        Ok(Suite {
+            dir,
+            version,
+            sha256: digest,
+            benchmarks,
+        })
+    }
+
+    /// The paths of the suite's Wasm files named by `names` (by their path in the suite; e.g.,
+    /// `bz2/benchmark.wasm`), or of all of them if none are named.
+    pub fn wasm_files(&self, names: &[PathBuf]) -> Result<Vec<PathBuf>> {
+        if names.is_empty() {
+            return Ok(self.benchmarks.iter().map(|b| self.dir.join(b)).collect());
+        }
+        names
+            .iter()
+            .map(|name| {
+                let name = name.to_string_lossy().replace('\\', "/");
+                anyhow::ensure!(
+                    self.benchmarks.contains(&name),
+                    "{} is not in suite {}",
+                    name,
+                    self.version
+                );
+                Ok(self.dir.join(name))
+            })
+            .collect()
+    }
+
+    /// Describe the suite, for the run's manifest.
+    pub fn manifest(&self) -> SuiteManifest {
+        SuiteManifest {
+            version: self.version.clone(),
+            sha256: self.sha256.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_and_extract() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let benchmark = dir.path().join("bench");
+        fs::create_dir(&benchmark)?;
+        for file in [
+            "benchmark.wasm",
+            "benchmark-simd.wasm",
+            "benchmark-simd.variant",
+            "default.input",
+            "stdout.expected",
+            "Dockerfile",
+        ] {
+            fs::write(benchmark.join(file), file)?;
+        }
+
+        let archive = dir.path().join("suite.tar.gz");
+        let package = |version: &str| {
+            PackageCommand::from_iter([
+                "package",
+                "--suite-version",
+                version,
+                "--output-file",
+                archive.to_str().unwrap(),
+                benchmark.join("benchmark-simd.wasm").to_str().unwrap(),
+            ])
+            .execute()
+        };
+        package("1.0")?;
+        let packaged = fs::read(&archive)?;
+        package("1.0")?;
+        assert_eq!(fs::read(&archive)?, packaged, "packaging is reproducible");
+        assert!(package("1 0").is_err());
+
+        let cache = dir.path().join("cache");
+        let suite = Suite::extract(&archive, &cache)?;
+        assert_eq!(suite.version, "1.0");
+        assert_eq!(suite.sha256, sha256(&packaged));
+        assert_eq!(suite.benchmarks, ["bench/benchmark-simd.wasm"]);
+        for file in ["benchmark-simd.variant", "default.input", "stdout.expected"] {
+            assert!(suite.dir.join("bench").join(file).is_file());
+        }
+        assert!(!suite.dir.join("bench/benchmark.wasm").exists());
+        assert!(!suite.dir.join("bench/Dockerfile").exists());
+
+        assert_eq!(
+            suite.wasm_files(&[])?,
+            [suite.dir.join("bench/benchmark-simd.wasm")]
+        );
+        assert!(suite.wasm_files(&["bench/benchmark.wasm".into()]).is_err());
+
+        // A modified file in the cache is caught on the next run.
+        fs::write(suite.dir.join("bench/default.input"), "modified")?;
+        assert!(Suite::extract(&archive, &cache).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+use crate::store::{Run, Store, DATETIME_FORMAT};
+use crate::summarize::parse_precision;
+use anyhow::{bail, Result};
+use sightglass_analysis::{
+    manifest, summarize, trend,
+    units::{Formatting, DEFAULT_PRECISION},
+};
+use sightglass_data::{Format, Phase};
+use std::{io, path::Path};
+use structopt::StructOpt;
+
+/// Find, show, and maintain the runs in the local results store, where
+/// `benchmark` records each run (unless passed `--no-record`).
+///
+/// The store is `results.db` in the cache directory (e.g.,
+/// `~/.cache/sightglass/results.db`; see `$SIGHTGLASS_CACHE_DIR`).
+#[derive(StructOpt, Debug)]
+#[structopt(name = "runs")]
+pub struct RunsCommand {
+    #[structopt(subcommand)]
+    command: RunsSubcommand,
+}
+
+#[derive(StructOpt, Debug)]
+enum RunsSubcommand {
+    /// List the recorded runs, oldest first.
+    List {
+        /// Only list the runs on or after this local date or time (e.g.,
+        /// `2024-06-04` or `2024-06-04 13:00`).
+        #[structopt(long, value_name = "DATE")]
+        since: Option<String>,
+
+        /// Only list the runs on or before this local date or time.
+        #[structopt(long, value_name = "DATE")]
+        until: Option<String>,
+
+        /// Only list the runs whose label, engines, benchmarks, command, or
+        /// directory contain this text.
+        #[structopt(long, value_name = "TEXT")]
+        matching: Option<String>,
+    },
+
+    /// Print the trend of each benchmark across the runs, oldest first: a
+    /// sparkline of its mean in each run, its mean in the last, and its
+    /// change and range across them; e.g., to spot a drift in nightly runs.
+    Trend {
+        /// Only include the runs on or after this local date or time.
+        #[structopt(long, value_name = "DATE")]
+        since: Option<String>,
+
+        /// Only include the runs on or before this local date or time.
+        #[structopt(long, value_name = "DATE")]
+        until: Option<String>,
+
+        /// Only include the runs whose label, engines, benchmarks, command,
+        /// or directory contain this text.
+        #[structopt(long, value_name = "TEXT")]
+        matching: Option<String>,
+
+        /// Only include the newest this many of the runs.
+        #[structopt(long, value_name = "RUNS", default_value = "40")]
+        last: usize,
+
+        /// The phase to follow.
+        #[structopt(long, default_value = "execution")]
+        phase: Phase,
+
+        /// The event to follow.
+        #[structopt(long, default_value = "cycles")]
+        event: String,
+
+        /// The engine to follow, by a part of its name, in runs of several
+        /// engines.
+        #[structopt(long, value_name = "TEXT")]
+        engine: Option<String>,
+
+        /// Print exact means rather than formatting them in the unit of the
+        /// event (e.g., `1.23 G` cycles).
+        #[structopt(long)]
+        raw: bool,
+
+        /// The significant digits to print the means with (by default, 3).
+        #[structopt(long, value_name = "DIGITS", parse(try_from_str = parse_precision))]
+        precision: Option<usize>,
+    },
+
+    /// Print the summaries of a run, after its manifest if it was recorded
+    /// with one, or, with `--raw`, its raw measurements (e.g., to pipe into
+    /// `effect-size`).
+    Show {
+        /// The ID of the run, as listed by `runs list`.
+        #[structopt(index = 1, value_name = "RUN")]
+        run: i64,
+
+        /// Print the raw measurements, which compacted runs no longer have.
+        #[structopt(long)]
+        raw: bool,
+
+        /// Print the run's manifest as JSON instead (see `benchmark
+        /// --manifest`); e.g., to reproduce the run or to pass to
+        /// `effect-size --manifest`.
+        #[structopt(long, conflicts_with = "raw")]
+        manifest: bool,
+
+        /// The format of the raw measurements. Either 'json' or 'csv'.
+        #[structopt(short = "o", long = "output-format", default_value = "json")]
+        output_format: Format,
+    },
+
+    /// Remove runs: the given ones, those older than some number of days,
+    /// and those beyond the newest `--keep`.
+    Gc {
+        /// The IDs of runs to remove.
+        #[structopt(index = 1, value_name = "RUN")]
+        runs: Vec<i64>,
+
+        /// Remove the runs recorded more than this many days ago.
+        #[structopt(long, value_name = "DAYS")]
+        older_than: Option<u32>,
+
+        /// Remove all but this many of the newest runs.
+        #[structopt(long, value_name = "RUNS")]
+        keep: Option<usize>,
+
+        /// List the runs that would be removed, without removing them.
+        #[structopt(short = "d", long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// Shrink the store by dropping the raw measurements of old runs, keeping
+    /// their summaries.
+    Compact {
+        /// Compact the runs recorded more than this many days ago.
+        #[structopt(long, value_name = "DAYS", default_value = "7")]
+        older_than: u32,
+    },
+}
+
+impl RunsCommand {
+    pub fn execute(&self) -> Result<()> {
+        let mut store = Store::open()?;
+        match &self.command {
+            RunsSubcommand::List {
+                since,
+                until,
+                matching,
+            } => {
+                for run in store.runs()? {
+                    if selected(
+                        &run,
+                        since.as_deref(),
+                        until.as_deref(),
+                        matching.as_deref(),
+                    ) {
+                        println!("{}", describe(&run));
+                    }
+                }
+            }
+            RunsSubcommand::Trend {
+                since,
+                until,
+                matching,
+                last,
+                phase,
+                event,
+                engine,
+                raw,
+                precision,
+            } => {
+                let runs: Vec<_> = store
+                    .runs()?
+                    .into_iter()
+                    .filter(|run| {
+                        selected(run, since.as_deref(), until.as_deref(), matching.as_deref())
+                    })
+                    .collect();
+                let mut summaries = vec![];
+                for run in &runs[runs.len().saturating_sub(*last)..] {
+                    summaries.push((run.datetime.clone(), store.summaries(run.id)?));
+                }
+                trend::write(
+                    &summaries,
+                    *phase,
+                    event,
+                    engine.as_deref(),
+                    Formatting {
+                        raw: *raw,
+                        precision: precision.unwrap_or(DEFAULT_PRECISION),
+                    },
+                    &mut io::stdout(),
+                )?;
+            }
+            RunsSubcommand::Show {
+                run,
+                raw,
+                manifest,
+                output_format,
+            } => {
+                if *raw {
+                    output_format.write_measurements(&store.measurements(*run)?, io::stdout())?;
+                } else if *manifest {
+                    match store.manifest(*run)? {
+                        Some(manifest) => {
+                            manifest.write(io::stdout())?;
+                            println!();
+                        }
+                        None => bail!("run {} was recorded without a manifest", run),
+                    }
+                } else {
+                    if let Some(manifest) = store.manifest(*run)? {
+                        manifest::write(&manifest, &mut io::stdout())?;
+                    }
+                    summarize::write(
+                        store.summaries(*run)?,
+                        Formatting::default(),
+                        &mut io::stdout(),
+                    )?;
+                }
+            }
+            RunsSubcommand::Gc {
+                runs: ids,
+                older_than,
+                keep,
+                dry_run,
+            } => {
+                let runs = store.runs()?;
+                let cutoff = older_than.map(days_ago);
+                let first_kept = keep.map(|keep| runs.len().saturating_sub(keep));
+                let removed: Vec<_> = runs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, run)| {
+                        ids.contains(&run.id)
+                            || cutoff.as_ref().is_some_and(|c| run.datetime < *c)
+                            || first_kept.is_some_and(|first| *i < first)
+                    })
+                    .map(|(_, run)| run)
+                    .collect();
+                for run in &removed {
+                    println!("{}", describe(run));
+                }
+                if !dry_run {
+                    let ids: Vec<_> = removed.iter().map(|run| run.id).collect();
+                    store.remove(&ids)?;
+                    store.vacuum()?;
+                    log::info!("Removed {} runs", ids.len());
+                }
+            }
+            RunsSubcommand::Compact { older_than } => {
+                let cutoff = days_ago(*older_than);
+                let runs: Vec<_> = store
+                    .runs()?
+                    .into_iter()
+                    .filter(|run| !run.compacted && run.datetime < cutoff)
+                    .collect();
+                for run in &runs {
+                    println!("{}", describe(run));
+                }
+                let before = file_size(&Store::path()?);
+                let ids: Vec<_> = runs.iter().map(|run| run.id).collect();
+                store.compact(&ids)?;
+                store.vacuum()?;
+                log::info!(
+                    "Compacted {} runs; the store went from {} to {} bytes",
+                    ids.len(),
+                    before,
+                    file_size(&Store::path()?)
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Describe a run on one line; e.g., `12  2024-06-04 13:02:11  [regalloc] 2 engines, 3 benchmarks
+/// (libengine.so, other.so)`.
+fn describe(run: &Run) -> String {
+    let names = |paths: &[String]| {
+        paths
+            .iter()
+            .map(|p| {
+                Path::new(p)
+                    .file_name()
+                    .map_or(p.clone(), |n| n.to_string_lossy().into())
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    format!(
+        "{:<5} {}  {}{} engine{}, {} benchmark{} ({}){}",
+        run.id,
+        run.datetime,
+        run.label
+            .as_ref()
+            .map(|l| format!("[{}] ", l))
+            .unwrap_or_default(),
+        run.engines.len(),
+        if run.engines.len() == 1 { "" } else { "s" },
+        run.benchmarks.len(),
+        if run.benchmarks.len() == 1 { "" } else { "s" },
+        names(&run.engines),
+        if run.compacted { " (compacted)" } else { "" }
+    )
+}
+
+/// Whether a run was recorded between `since` and `until` and `matches` the text, if given.
+fn selected(run: &Run, since: Option<&str>, until: Option<&str>, matching: Option<&str>) -> bool {
+    since.is_none_or(|s| prefix(&run.datetime, s) >= s)
+        && until.is_none_or(|u| prefix(&run.datetime, u) <= u)
+        && matching.is_none_or(|m| matches(run, m))
+}
+
+fn matches(run: &Run, text: &str) -> bool {
+    run.label.as_deref().unwrap_or_default().contains(text)
+        || run.engines.iter().any(|e| e.contains(text))
+        || run.benchmarks.iter().any(|b| b.contains(text))
+        || run.command.contains(text)
+        || run.directory.contains(text)
+}
+
+/// The start of `datetime` as long as `bound`, to compare them; e.g., the date of a time.
+fn prefix<'a>(datetime: &'a str, bound: &str) -> &'a str {
+    datetime.get(..bound.len()).unwrap_or(datetime)
+}
+
+/// The local time some `days` ago, as the store records times.
+fn days_ago(days: u32) -> String {
+    (chrono::Local::now() - chrono::Duration::days(days.into()))
+        .format(DATETIME_FORMAT)
+        .to_string()
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map(|m| m.len()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describe_runs() {
+        let run = Run {
+            id: 12,
+            datetime: "2024-06-04 13:02:11".into(),
+            directory: "/home/me/sightglass".into(),
+            command: "sightglass-cli benchmark -e /tmp/libengine.so".into(),
+            label: Some("regalloc".into()),
+            engines: vec!["/tmp/libengine.so".into(), "other.so".into()],
+            benchmarks: vec!["benchmarks/bz2/benchmark.wasm".into()],
+            compacted: true,
+        };
+        assert_eq!(
+            describe(&run),
+            "12    2024-06-04 13:02:11  [regalloc] 2 engines, 1 benchmark \
+             (libengine.so, other.so) (compacted)"
+        );
+        assert!(matches(&run, "bz2"));
+        assert!(!matches(&run, "spidermonkey"));
+        assert_eq!(prefix(&run.datetime, "2024-06-04"), "2024-06-04");
+        assert_eq!(prefix(&run.datetime, "2024-06-04 13:02:11.5"), run.datetime);
+    }
+}
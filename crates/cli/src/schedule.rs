@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+use std::str::FromStr;
+
+/// When to run, as a cron expression: `MINUTE HOUR DAY-OF-MONTH MONTH DAY-OF-WEEK`, e.g.,
+/// `0 2 * * *` for 2am every day. Each field is `*`, a number, a range (`1-5`), or a list of these
+/// (`1,15`), and may be stepped (`*/15`, `0-30/10`). Days of the week run from 0 (Sunday) to 6 (or
+/// 7, also Sunday). As with cron, when both days of the month and of the week are restricted, a day
+/// matching either will do. `@hourly`, `@daily`, `@weekly`, and `@monthly` are also understood.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+/// The values a field of a cron expression matches, as a bit set, and whether it was unrestricted
+/// (`*`, as cron takes any field starting with it).
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Field {
+    values: u64,
+    any: bool,
+}
+
+impl Field {
+    fn parse(field: &str, min: u32, max: u32) -> Result<Self> {
+        let mut values = 0;
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse().ok().filter(|s| *s > 0)),
+                None => (part, Some(1)),
+            };
+            let step = step.ok_or_else(|| anyhow!("invalid step: {}", part))?;
+            let number = |n: &str| -> Result<u32> {
+                let n = n.parse().map_err(|_| anyhow!("invalid value: {}", part))?;
+                if n < min || n > max {
+                    bail!("{} is out of the range {}-{}", n, min, max);
+                }
+                Ok(n)
+            };
+            let (first, last) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((first, last)) => (number(first)?, number(last)?),
+                    None if part.contains('/') => (number(range)?, max),
+                    None => (number(range)?, number(range)?),
+                },
+            };
+            for n in (first..=last).step_by(step) {
+                values |= 1 << n;
+            }
+        }
+        Ok(Self {
+            values,
+            any: field.starts_with('*'),
+        })
+    }
+
+    fn matches(&self, n: u32) -> bool {
+        self.values & (1 << n) != 0
+    }
+}
+
+impl Schedule {
+    /// The first minute after `time` that the schedule matches, if any in the next few years (e.g.,
+    /// `0 0 30 2 *` never matches).
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut next = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let end = time + Duration::days(5 * 366);
+        while next < end {
+            if !self.months.matches(next.month()) {
+                let (year, month) = match next.month() {
+                    12 => (next.year() + 1, 1),
+                    month => (next.year(), month + 1),
+                };
+                next = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.matches_day(next.date()) {
+                next = next.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if !self.hours.matches(next.hour()) {
+                next = next.with_minute(0)? + Duration::hours(1);
+            } else if !self.minutes.matches(next.minute()) {
+                next += Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days.matches(date.day());
+        let weekday = self.weekdays.matches(date.weekday().num_days_from_sunday());
+        match (self.days.any, self.weekdays.any) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let expression = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            expression => expression,
+        };
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        if fields.len() != 5 {
+            bail!(
+                "expected a cron expression of five fields \
+                 (MINUTE HOUR DAY-OF-MONTH MONTH DAY-OF-WEEK): {}",
+                s
+            );
+        }
+        let field = |i: usize, name: &str, min, max| {
+            Field::parse(fields[i], min, max).map_err(|e| anyhow!("invalid {} field: {}", name, e))
+        };
+        let mut weekdays = field(4, "day-of-week", 0, 7)?;
+        // Both 0 and 7 are Sunday.
+        if weekdays.matches(7) {
+            weekdays.values |= 1;
+        }
+        Ok(Self {
+            minutes: field(0, "minute", 0, 59)?,
+            hours: field(1, "hour", 0, 23)?,
+            days: field(2, "day-of-month", 1, 31)?,
+            months: field(3, "month", 1, 12)?,
+            weekdays,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<NaiveDateTime> {
+        schedule
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(time(after))
+    }
+
+    #[test]
+    fn next_scheduled_time() {
+        assert_eq!(
+            next("0 2 * * *", "2024-06-04 13:02"),
+            Some(time("2024-06-05 02:00"))
+        );
+        assert_eq!(
+            next("0 2 * * *", "2024-06-04 01:59"),
+            Some(time("2024-06-04 02:00"))
+        );
+        assert_eq!(
+            next("*/15 * * * *", "2024-06-04 13:02"),
+            Some(time("2024-06-04 13:15"))
+        );
+        assert_eq!(
+            next("30 9-17/4 * * 1-5", "2024-06-07 18:00"),
+            Some(time("2024-06-10 09:30"))
+        );
+        // Sunday, as 7.
+        assert_eq!(
+            next("0 0 * * 7", "2024-06-04 13:02"),
+            Some(time("2024-06-09 00:00"))
+        );
+        // Either the 15th of the month or a Monday.
+        assert_eq!(
+            next("0 0 15 * 1", "2024-06-25 00:00"),
+            Some(time("2024-07-01 00:00"))
+        );
+        assert_eq!(
+            next("@monthly", "2024-12-15 00:00"),
+            Some(time("2025-01-01 00:00"))
+        );
+        assert_eq!(
+            next("0 0 29 2 *", "2024-03-01 00:00"),
+            Some(time("2028-02-29 00:00"))
+        );
+        assert_eq!(next("0 0 30 2 *", "2024-03-01 00:00"), None);
+    }
+
+    #[test]
+    fn invalid_schedules() {
+        for schedule in [
+            "0 2 * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "a * * * *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{}", schedule);
+        }
+    }
+}
@@ -0,0 +1,503 @@
+use crate::store::Store;
+use anyhow::{anyhow, bail, Context, Result};
+use flate2::read::GzDecoder;
+use reqwest::Url;
+use serde_json::{json, Value};
+use sightglass_analysis::{effect_size, summarize};
+use sightglass_data::{Format, Measurement, Measurements};
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    thread,
+    time::UNIX_EPOCH,
+};
+use structopt::StructOpt;
+
+/// Serve a web dashboard and a small HTTP API over stored results, for
+/// browsing them and for web frontends and bots.
+///
+/// By default, the runs are those of the local results store, where
+/// `benchmark` records each run (see `runs`), identified by their IDs. With
+/// `--results-dir`, a run is either the raw results of a build stored by
+/// `daemon` (`<RESULTS_DIR>/<RUN>.json`) or a run archived to a local
+/// directory with `benchmark --archive`
+/// (`<RESULTS_DIR>/<RUN>/measurements.json.gz`). The API answers `GET`
+/// requests with JSON:
+///
+/// - `/runs`: the runs, oldest first, with their IDs and their times (and
+///   labels, in the store) or modification times (in a directory).
+///
+/// - `/runs/<RUN>/measurements`: the raw measurements of a run, unless it was
+///   compacted in the store.
+///
+/// - `/runs/<RUN>/summaries`: the summaries of a run, as `summarize` computes
+///   them.
+///
+/// - `/effect-size?a=<RUN>&b=<RUN>`: the effect sizes between two runs of one
+///   engine each, as `effect-size` computes them, naming each engine by its
+///   run; `significance-level` may be given too (by default, 0.01).
+///
+/// The dashboard, at `/`, charts the trend of each benchmark over the recent
+/// runs, compares two runs, and plots the distributions of their measurements.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "serve")]
+pub struct ServeCommand {
+    /// The directory of stored results to serve, rather than the local results
+    /// store.
+    #[structopt(long, value_name = "RESULTS_DIR", parse(from_os_str))]
+    results_dir: Option<PathBuf>,
+
+    /// The address to listen on.
+    #[structopt(long, default_value = "127.0.0.1:8080")]
+    address: String,
+}
+
+impl ServeCommand {
+    pub fn execute(&self) -> Result<()> {
+        let source = match &self.results_dir {
+            Some(results_dir) => {
+                anyhow::ensure!(
+                    results_dir.is_dir(),
+                    "no such results directory: {}",
+                    results_dir.display()
+                );
+                Source::Directory(results_dir.clone())
+            }
+            None => {
+                // Create the store now, so that a store that cannot be opened fails the command
+                // rather than each request.
+                Store::open()?;
+                Source::Store(Store::path()?)
+            }
+        };
+        let listener = TcpListener::bind(&self.address)
+            .with_context(|| format!("failed to listen on {}", self.address))?;
+        log::info!(
+            "Serving {} on http://{}",
+            source.path().display(),
+            listener.local_addr()?
+        );
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    log::warn!("Failed to accept a connection: {}", e);
+                    continue;
+                }
+            };
+            let source = source.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(&source, stream) {
+                    log::warn!("Failed to answer a request: {:?}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Where the served runs are stored.
+#[derive(Clone, Debug)]
+enum Source {
+    /// A directory of the results of `daemon` or `benchmark --archive`.
+    Directory(PathBuf),
+    /// The local results store at this path; each request opens its own connection.
+    Store(PathBuf),
+}
+
+impl Source {
+    fn path(&self) -> &Path {
+        match self {
+            Source::Directory(path) | Source::Store(path) => path,
+        }
+    }
+
+    /// List the runs, oldest first.
+    fn runs(&self) -> Result<Value> {
+        match self {
+            Source::Directory(results_dir) => runs(results_dir),
+            Source::Store(path) => {
+                let runs = Store::open_at(path)?.runs()?;
+                Ok(runs
+                    .into_iter()
+                    .map(|run| {
+                        json!({ "id": run.id.to_string(), "datetime": run.datetime, "label": run.label })
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// Read the measurements of `run`.
+    fn measurements(&self, run: &str) -> Result<Measurements> {
+        match self {
+            Source::Directory(results_dir) => read_run(results_dir, run),
+            Source::Store(path) => {
+                let (store, id) = find_run(path, run)?;
+                if store.run(id)?.compacted {
+                    bail!(BadRequest(format!(
+                        "run {} was compacted; only its summaries remain",
+                        run
+                    )));
+                }
+                Ok(store.measurements(id)?.into_iter().collect())
+            }
+        }
+    }
+
+    /// Summarize the measurements of `run`; the store keeps the summaries of compacted runs too.
+    fn summaries(&self, run: &str) -> Result<Value> {
+        match self {
+            Source::Directory(results_dir) => {
+                let measurements = read_run(results_dir, run)?;
+                Ok(json!(summarize::calculate(&measurements.to_vec())))
+            }
+            Source::Store(path) => {
+                let (store, id) = find_run(path, run)?;
+                Ok(json!(store.summaries(id)?))
+            }
+        }
+    }
+}
+
+/// Open the store at `path` to find the run with the ID `run`.
+fn find_run(path: &Path, run: &str) -> Result<(Store, i64)> {
+    let store = Store::open_at(path)?;
+    match run.parse() {
+        Ok(id) if store.run(id).is_ok() => Ok((store, id)),
+        _ => bail!(NotFound(run.to_string())),
+    }
+}
+
+/// Answer the HTTP request on `stream`.
+fn handle(source: &Source, mut stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Skip the headers; no request has a body.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next());
+    let (status, content_type, body) = match (method, target.map(asset)) {
+        ("GET", Some(Some((content_type, contents)))) => (200, content_type, contents.into()),
+        ("GET", Some(None)) => {
+            let (status, body) = respond(source, target.unwrap());
+            (status, "application/json", serde_json::to_vec(&body)?)
+        }
+        _ => {
+            let body = json!({ "error": "only GET requests are supported" });
+            (405, "application/json", serde_json::to_vec(&body)?)
+        }
+    };
+    log::debug!("{} {} -> {}", method, target.unwrap_or_default(), status);
+
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: {}\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        reason(status),
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    Ok(())
+}
+
+/// The content type and contents of the dashboard's file at `target`, if it is one.
+fn asset(target: &str) -> Option<(&'static str, &'static str)> {
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    Some(match path {
+        "/" | "/index.html" => (
+            "text/html; charset=utf-8",
+            include_str!("../assets/dashboard/index.html"),
+        ),
+        "/dashboard.js" => (
+            "text/javascript; charset=utf-8",
+            include_str!("../assets/dashboard/dashboard.js"),
+        ),
+        "/dashboard.css" => (
+            "text/css; charset=utf-8",
+            include_str!("../assets/dashboard/dashboard.css"),
+        ),
+        _ => return None,
+    })
+}
+
+/// The response (a status code and JSON body) to a `GET` of `target`.
+fn respond(source: &Source, target: &str) -> (u16, Value) {
+    let url = match Url::parse("http://localhost/").and_then(|base| base.join(target)) {
+        Ok(url) => url,
+        Err(e) => return (400, json!({ "error": e.to_string() })),
+    };
+    let segments: Vec<_> = url.path_segments().into_iter().flatten().collect();
+    let result = match segments.as_slice() {
+        ["runs"] => source.runs(),
+        ["runs", run, "measurements"] => source.measurements(run).map(|m| json!(m)),
+        ["runs", run, "summaries"] => source.summaries(run),
+        ["effect-size"] => compare(source, &url),
+        _ => {
+            return (
+                404,
+                json!({ "error": format!("not found: {}", url.path()) }),
+            )
+        }
+    };
+    match result {
+        Ok(body) => (200, body),
+        Err(e) if e.is::<NotFound>() => (404, json!({ "error": e.to_string() })),
+        Err(e) if e.is::<BadRequest>() => (400, json!({ "error": e.to_string() })),
+        Err(e) => (500, json!({ "error": format!("{:#}", e) })),
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("no such run: {0}")]
+struct NotFound(String);
+
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct BadRequest(String);
+
+/// List the runs in `results_dir`, oldest first.
+fn runs(results_dir: &Path) -> Result<Value> {
+    let mut runs = vec![];
+    for entry in fs::read_dir(results_dir)? {
+        let path = entry?.path();
+        let run = if path.extension().is_some_and(|e| e == "json") && path.is_file() {
+            path.file_stem()
+        } else if path.join("measurements.json.gz").is_file() {
+            path.file_name()
+        } else {
+            continue;
+        };
+        let run = run.unwrap_or_default().to_string_lossy().into_owned();
+        let modified = fs::metadata(&path)?
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        runs.push((modified, run));
+    }
+    runs.sort();
+    Ok(runs
+        .into_iter()
+        .map(|(modified, run)| json!({ "id": run, "modified": modified }))
+        .collect())
+}
+
+/// Read the measurements of `run`.
+fn read_run(results_dir: &Path, run: &str) -> Result<Measurements> {
+    if run.is_empty() || run.starts_with('.') || run.contains(['/', '\\']) {
+        bail!(NotFound(run.to_string()));
+    }
+    let json = results_dir.join(format!("{}.json", run));
+    let archived = results_dir.join(run).join("measurements.json.gz");
+    let mut measurements = Measurements::default();
+    if json.is_file() {
+        let file =
+            fs::File::open(&json).with_context(|| format!("failed to open {}", json.display()))?;
+        measurements.read(&Format::Json, BufReader::new(file))?;
+    } else if archived.is_file() {
+        let file = fs::File::open(&archived)
+            .with_context(|| format!("failed to open {}", archived.display()))?;
+        measurements.read(&Format::Json, BufReader::new(GzDecoder::new(file)))?;
+    } else {
+        bail!(NotFound(run.to_string()))
+    }
+    Ok(measurements)
+}
+
+/// Compute the effect sizes between the runs `a` and `b` of the query.
+fn compare(source: &Source, url: &Url) -> Result<Value> {
+    let query = |name: &str| {
+        url.query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+    let significance_level = match query("significance-level") {
+        Some(level) => level
+            .parse()
+            .map_err(|_| BadRequest(format!("invalid significance-level: {}", level)))?,
+        None => 0.01,
+    };
+    let mut measurements = Measurements::default();
+    let mut runs = vec![];
+    for name in ["a", "b"] {
+        let run = query(name).ok_or_else(|| BadRequest(format!("missing run `{}`", name)))?;
+        if runs.contains(&run) {
+            bail!(BadRequest(format!(
+                "cannot compare run {} with itself",
+                run
+            )));
+        }
+        let run_measurements = source.measurements(&run)?;
+        if run_measurements
+            .iter()
+            .any(|m| m.engine != run_measurements.get(0).engine)
+        {
+            bail!(BadRequest(format!(
+                "run {} measured more than one engine",
+                run
+            )));
+        }
+        for m in run_measurements.iter() {
+            measurements.push(&Measurement {
+                engine: run.as_str().into(),
+                ..m
+            });
+        }
+        runs.push(run);
+    }
+    let mut effect_sizes = effect_size::calculate(significance_level, &measurements.to_vec())
+        .map_err(|e| anyhow!(BadRequest(e.to_string())))?;
+    // The engines are compared in name order; answer in the order asked, `a` being the baseline.
+    for e in &mut effect_sizes {
+        if e.a_engine != runs[0] {
+            std::mem::swap(&mut e.a_engine, &mut e.b_engine);
+            std::mem::swap(&mut e.a_mean, &mut e.b_mean);
+        }
+    }
+    Ok(json!(effect_sizes))
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    fn write_run(dir: &Path, run: &str, counts: &[u64]) {
+        let measurements: Vec<_> = counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| Measurement {
+                arch: "x86_64".into(),
+                engine: "libengine.so".into(),
+                wasm: "benchmark.wasm".into(),
+                process: 1,
+                iteration: i as u32,
+                phase: Phase::Execution,
+                event: "cycles".into(),
+                count,
+                threads: None,
+                input_size: None,
+                execution: None,
+            })
+            .collect();
+        let json = serde_json::to_vec(&measurements).unwrap();
+        fs::write(dir.join(format!("{}.json", run)), json).unwrap();
+    }
+
+    #[test]
+    fn respond_to_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        write_run(dir.path(), "main", &[100, 101, 99, 100]);
+        write_run(dir.path(), "branch", &[200, 201, 199, 200]);
+        fs::write(dir.path().join("branch.effect-size.txt"), "").unwrap();
+        let source = Source::Directory(dir.path().into());
+
+        let (status, body) = respond(&source, "/runs");
+        assert_eq!(status, 200);
+        let mut runs: Vec<_> = body
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["id"].as_str().unwrap())
+            .collect();
+        runs.sort();
+        assert_eq!(runs, ["branch", "main"]);
+
+        let (status, body) = respond(&source, "/runs/main/summaries");
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["mean"], 100.0);
+
+        let (status, body) = respond(&source, "/effect-size?a=main&b=branch");
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["a_engine"], "main");
+        assert_eq!(body[0]["b_mean"], 200.0);
+
+        assert_eq!(respond(&source, "/runs/other/measurements").0, 404);
+        assert_eq!(respond(&source, "/runs/..%2Fmain/measurements").0, 404);
+        assert_eq!(respond(&source, "/effect-size?a=main").0, 400);
+        assert_eq!(respond(&source, "/effect-size?a=main&b=main").0, 400);
+        assert_eq!(respond(&source, "/other").0, 404);
+    }
+
+    #[test]
+    fn respond_from_the_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("results.db");
+        let mut store = Store::open_at(&path).unwrap();
+        let measurement = |count| Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: "benchmark.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        };
+        let main = store
+            .record(&[measurement(100), measurement(101)], Some("main"), None)
+            .unwrap();
+        let branch = store
+            .record(&[measurement(200), measurement(201)], None, None)
+            .unwrap();
+        store.compact(&[main]).unwrap();
+        let source = Source::Store(path);
+
+        let (status, body) = respond(&source, "/runs");
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["id"], main.to_string());
+        assert_eq!(body[0]["label"], "main");
+        assert_eq!(body[1]["id"], branch.to_string());
+
+        // A compacted run keeps its summaries, but not its measurements.
+        let (status, body) = respond(&source, &format!("/runs/{}/summaries", main));
+        assert_eq!(status, 200);
+        assert_eq!(body[0]["mean"], 100.5);
+        assert_eq!(
+            respond(&source, &format!("/runs/{}/measurements", main)).0,
+            400
+        );
+        let (status, body) = respond(&source, &format!("/runs/{}/measurements", branch));
+        assert_eq!(status, 200);
+        assert_eq!(body.as_array().unwrap().len(), 2);
+
+        assert_eq!(respond(&source, "/runs/42/summaries").0, 404);
+        assert_eq!(respond(&source, "/runs/main/summaries").0, 404);
+    }
+
+    #[test]
+    fn serve_dashboard() {
+        let (content_type, contents) = asset("/?run=main").unwrap();
+        assert_eq!(content_type, "text/html; charset=utf-8");
+        assert!(contents.contains(r#"<script src="dashboard.js">"#));
+        assert!(asset("/dashboard.js").is_some());
+        assert!(asset("/dashboard.css").is_some());
+        assert!(asset("/runs").is_none());
+    }
+}
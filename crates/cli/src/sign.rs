@@ -0,0 +1,372 @@
+use crate::{benchmark::is_native, fetch_engine::sha256};
+use anyhow::{Context, Result};
+use sightglass_data::{normalize_path, FileDigest, Format, Manifest, Measurement, Provenance};
+use sightglass_fingerprint::Machine;
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+use structopt::StructOpt;
+
+/// Attest to a results file: write its provenance, the SHA-256 digests of the
+/// results, of the run's manifest, and of the engines and benchmarks they
+/// name, with the fingerprint of this machine, to
+/// `<RESULTS>.provenance.json`, and sign that, so that `verify` can later
+/// show the results to be unmodified.
+///
+/// Run this where the benchmarks ran, since the results name the engines and
+/// benchmarks by the paths they ran with.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "sign")]
+pub struct SignCommand {
+    /// The format of the results. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Path to the results file to sign.
+    #[structopt(short = "f", long = "input-file", parse(from_os_str))]
+    input_file: PathBuf,
+
+    /// Path to the manifest of the run that produced the results (see
+    /// `benchmark --manifest`), to sign along with them.
+    #[structopt(long, value_name = "MANIFEST", parse(from_os_str))]
+    manifest: Option<PathBuf>,
+
+    /// How to sign the provenance: 'minisign', with the `minisign` CLI and
+    /// the `--key`, writing `<PROVENANCE>.minisig`; 'sigstore', with
+    /// `cosign sign-blob` and its keyless flow, writing
+    /// `<PROVENANCE>.sigstore.json`; or 'none', to only write the
+    /// provenance.
+    #[structopt(long, value_name = "SIGNER", default_value = "minisign")]
+    signer: Signer,
+
+    /// The minisign secret key to sign with; `minisign` defaults to
+    /// `~/.minisign/minisign.key`.
+    #[structopt(short = "k", long, value_name = "KEY", parse(from_os_str))]
+    key: Option<PathBuf>,
+}
+
+impl SignCommand {
+    pub fn execute(&self) -> Result<()> {
+        let provenance = provenance(
+            &self.input_file,
+            self.manifest.as_deref(),
+            &self.input_format,
+        )?;
+        let path = provenance_path(&self.input_file);
+        provenance.write(
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?,
+        )?;
+        println!("{}", path.display());
+
+        if let Some(mut command) = sign_command(self.signer, &path, self.key.as_deref()) {
+            log::debug!("Running: {:?}", command);
+            let program = command.get_program().to_string_lossy().into_owned();
+            let status = command
+                .status()
+                .with_context(|| format!("failed to run `{}`; is it installed?", program))?;
+            anyhow::ensure!(
+                status.success(),
+                "failed to sign {} ({})",
+                path.display(),
+                status
+            );
+            println!("{}", signature_path(self.signer, &path).display());
+        }
+        Ok(())
+    }
+}
+
+/// How to sign a provenance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Signer {
+    Minisign,
+    Sigstore,
+    None,
+}
+
+impl FromStr for Signer {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "minisign" => Ok(Self::Minisign),
+            "sigstore" => Ok(Self::Sigstore),
+            "none" => Ok(Self::None),
+            _ => anyhow::bail!(
+                "unknown signer (expected minisign, sigstore, or none): {}",
+                s
+            ),
+        }
+    }
+}
+
+/// Describe the `results` file (and the `manifest` of its run), digesting the engines and
+/// benchmarks it names.
+///
+/// The results name the engines and benchmarks as they ran, which need not be their files: an
+/// engine run with several sets of flags is named by its label, and a benchmark with several entry
+/// points by `<WASM>#<ENTRY>`. With the run's manifest, each engine is its library's path and
+/// digest there, and each benchmark's digest is the one the manifest records for its Wasm file;
+/// anything the manifest does not record is digested from its file.
+pub fn provenance(results: &Path, manifest: Option<&Path>, format: &Format) -> Result<Provenance> {
+    let file =
+        File::open(results).with_context(|| format!("failed to open {}", results.display()))?;
+    let measurements: Vec<Measurement> = format.read(BufReader::new(file))?;
+    let run = match manifest {
+        Some(path) => {
+            let file =
+                File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+            Manifest::read(BufReader::new(file))
+                .with_context(|| format!("failed to read the manifest {}", path.display()))?
+        }
+        None => Manifest::default(),
+    };
+
+    let mut engines = BTreeSet::new();
+    let names: BTreeSet<_> = measurements.iter().map(|m| m.engine.as_ref()).collect();
+    for name in names.into_iter().filter(|name| !is_native(name)) {
+        match run.engines.iter().find(|e| e.engine == name) {
+            Some(engine) => match (&engine.path, &engine.sha256) {
+                (Some(path), Some(sha256)) => engines.insert(FileDigest {
+                    path: path.clone(),
+                    sha256: sha256.clone(),
+                }),
+                (Some(path), None) => engines.insert(digest(Path::new(path))?),
+                // An engine without a library, e.g. the native baseline, has nothing to digest.
+                (None, _) => false,
+            },
+            None => engines.insert(digest(Path::new(name))?),
+        };
+    }
+
+    let mut benchmarks = BTreeSet::new();
+    let names: BTreeSet<_> = measurements.iter().map(|m| m.wasm.as_ref()).collect();
+    for name in names {
+        let path = wasm_path(name);
+        let recorded = run
+            .wasm_sha256
+            .iter()
+            .find(|(wasm, _)| normalize_path(wasm) == normalize_path(path));
+        benchmarks.insert(match recorded {
+            Some((_, sha256)) => FileDigest {
+                path: path.to_string(),
+                sha256: sha256.clone(),
+            },
+            None => digest(Path::new(path))?,
+        });
+    }
+
+    Ok(Provenance {
+        results: digest(results)?,
+        manifest: manifest.map(digest).transpose()?,
+        engines: engines.into_iter().collect(),
+        benchmarks: benchmarks.into_iter().collect(),
+        machine: serde_json::to_value(Machine::fingerprint()?)?,
+        sightglass_version: env!("CARGO_PKG_VERSION").into(),
+        datetime: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+/// The path of the Wasm file of the benchmark named `wasm` in the results: without the `#<ENTRY>`
+/// of a benchmark with several entry points, unless a file is named that.
+fn wasm_path(wasm: &str) -> &str {
+    match wasm.rsplit_once('#') {
+        Some((path, _)) if !Path::new(wasm).exists() => path,
+        _ => wasm,
+    }
+}
+
+/// The SHA-256 digest of the file at `path`.
+pub fn digest(path: &Path) -> Result<FileDigest> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(FileDigest {
+        path: path.display().to_string(),
+        sha256: sha256(&bytes),
+    })
+}
+
+/// Where the provenance of a `results` file is written: `<RESULTS>.provenance.json`.
+pub fn provenance_path(results: &Path) -> PathBuf {
+    let mut path = results.as_os_str().to_owned();
+    path.push(".provenance.json");
+    path.into()
+}
+
+/// Where the `signer` writes the signature of the `provenance` file.
+pub fn signature_path(signer: Signer, provenance: &Path) -> PathBuf {
+    let mut path = provenance.as_os_str().to_owned();
+    path.push(match signer {
+        Signer::Minisign => ".minisig",
+        Signer::Sigstore => ".sigstore.json",
+        Signer::None => "",
+    });
+    path.into()
+}
+
+/// The command that signs the `provenance` file, if it is to be signed.
+fn sign_command(signer: Signer, provenance: &Path, key: Option<&Path>) -> Option<Command> {
+    let signature = signature_path(signer, provenance);
+    let mut command = match signer {
+        Signer::Minisign => {
+            let mut command = Command::new("minisign");
+            command.arg("-S");
+            if let Some(key) = key {
+                command.arg("-s").arg(key);
+            }
+            command.arg("-x").arg(signature).arg("-m");
+            command
+        }
+        Signer::Sigstore => {
+            let mut command = Command::new("cosign");
+            command
+                .args(["sign-blob", "--yes", "--bundle"])
+                .arg(signature);
+            command
+        }
+        Signer::None => return None,
+    };
+    command.arg(provenance);
+    Some(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::EngineManifest;
+
+    #[test]
+    fn digest_results() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let engine = dir.path().join("libengine.so");
+        let wasm = dir.path().join("benchmark.wasm");
+        fs::write(&engine, "engine")?;
+        fs::write(&wasm, "wasm")?;
+        let results = dir.path().join("results.json");
+        fs::write(
+            &results,
+            serde_json::json!([{
+                "arch": "x86_64",
+                "engine": engine,
+                "wasm": wasm,
+                "process": 1,
+                "iteration": 0,
+                "phase": "Execution",
+                "event": "cycles",
+                "count": 42
+            }])
+            .to_string(),
+        )?;
+
+        let attested = provenance(&results, None, &Format::Json)?;
+        assert_eq!(attested.results, digest(&results)?);
+        assert_eq!(attested.manifest, None);
+        assert_eq!(
+            attested.engines,
+            vec![FileDigest {
+                path: engine.display().to_string(),
+                sha256: sha256(b"engine"),
+            }]
+        );
+        assert_eq!(attested.benchmarks, vec![digest(&wasm)?]);
+
+        fs::remove_file(&wasm)?;
+        assert!(provenance(&results, None, &Format::Json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn digest_results_with_manifest() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let engine = dir.path().join("libengine.so");
+        let wasm = dir.path().join("benchmark.wasm");
+        fs::write(&engine, "engine")?;
+        fs::write(&wasm, "wasm")?;
+        let measurement = |engine: &str, entry: &str| {
+            serde_json::json!({
+                "arch": "x86_64",
+                "engine": engine,
+                "wasm": format!("{}#{}", wasm.display(), entry),
+                "process": 1,
+                "iteration": 0,
+                "phase": "Execution",
+                "event": "cycles",
+                "count": 42
+            })
+        };
+        let results = dir.path().join("results.json");
+        fs::write(
+            &results,
+            serde_json::json!([
+                measurement("cranelift", "bench_a"),
+                measurement("winch", "bench_b"),
+            ])
+            .to_string(),
+        )?;
+
+        // Without the manifest, the labeled engines cannot be found.
+        assert!(provenance(&results, None, &Format::Json).is_err());
+        let path = wasm.display().to_string();
+        assert_eq!(wasm_path(&format!("{}#bench_a", path)), path);
+        assert_eq!(wasm_path(&path), path);
+
+        // With it, both labels name the same library, with the digests the manifest records.
+        let engine_manifest = |label: &str| EngineManifest {
+            engine: label.into(),
+            path: Some(engine.display().to_string()),
+            sha256: Some("e".repeat(64)),
+            ..Default::default()
+        };
+        let manifest = Manifest {
+            engines: vec![engine_manifest("cranelift"), engine_manifest("winch")],
+            wasm_sha256: [(wasm.display().to_string(), "w".repeat(64))].into(),
+            ..Default::default()
+        };
+        let manifest_path = dir.path().join("manifest.json");
+        manifest.write(File::create(&manifest_path)?)?;
+        let attested = provenance(&results, Some(&manifest_path), &Format::Json)?;
+        assert_eq!(attested.manifest, Some(digest(&manifest_path)?));
+        assert_eq!(
+            attested.engines,
+            vec![FileDigest {
+                path: engine.display().to_string(),
+                sha256: "e".repeat(64),
+            }]
+        );
+        assert_eq!(
+            attested.benchmarks,
+            vec![FileDigest {
+                path: wasm.display().to_string(),
+                sha256: "w".repeat(64),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn signing_commands() {
+        let provenance = provenance_path(Path::new("results.json"));
+        assert_eq!(provenance, Path::new("results.json.provenance.json"));
+        let command =
+            sign_command(Signer::Minisign, &provenance, Some(Path::new("my.key"))).unwrap();
+        assert_eq!(command.get_program(), "minisign");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "-S",
+                "-s",
+                "my.key",
+                "-x",
+                "results.json.provenance.json.minisig",
+                "-m",
+                "results.json.provenance.json"
+            ]
+        );
+        let command = sign_command(Signer::Sigstore, &provenance, None).unwrap();
+        assert_eq!(command.get_program(), "cosign");
+        assert!(sign_command(Signer::None, &provenance, None).is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use sightglass_analysis::{groups::Groups, keys::Key};
+use sightglass_data::Measurement;
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write},
+};
+
+/// The size of a spilled measurement: the index of its group (`u32`) and its count (`u64`).
+const RECORD: usize = 12;
+
+/// The groups of measurements too many to hold in memory, to analyze a chunk of groups at a time.
+/// Only the keys of the groups are kept in memory; the counts are spilled to an anonymous temporary
+/// file as they are added, and read back for each chunk.
+pub(crate) struct SpilledGroups {
+    keys: Vec<Key<'static>>,
+    ids: BTreeMap<Key<'static>, u32>,
+    /// The number of counts in each group.
+    sizes: Vec<u64>,
+    /// The group of the last measurement added; see [Groups].
+    last: Option<u32>,
+    spill: BufWriter<File>,
+}
+
+impl SpilledGroups {
+    pub fn new() -> Result<Self> {
+        let file = tempfile::tempfile().context("failed to create a temporary file to spill to")?;
+        Ok(Self {
+            keys: vec![],
+            ids: BTreeMap::new(),
+            sizes: vec![],
+            last: None,
+            spill: BufWriter::new(file),
+        })
+    }
+
+    /// Spill the count of a measurement, noting its group.
+    pub fn add(&mut self, m: &Measurement<'_>) -> Result<()> {
+        let id = match self.last.filter(|&id| self.keys[id as usize].matches(m)) {
+            Some(id) => id,
+            None => {
+                let key = Key::of(m);
+                let id = match self.ids.get(&key) {
+                    Some(&id) => id,
+                    None => {
+                        let id = u32::try_from(self.keys.len()).context("too many groups")?;
+                        self.keys.push(key.clone());
+                        self.ids.insert(key, id);
+                        self.sizes.push(0);
+                        id
+                    }
+                };
+                self.last = Some(id);
+                id
+            }
+        };
+        self.sizes[id as usize] += 1;
+        self.spill.write_all(&id.to_le_bytes())?;
+        self.spill.write_all(&m.count.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Read the groups back in chunks whose counts take about `max_memory` bytes, calling `analyze`
+    /// with each. The groups compared by an effect size (those differing only by engine) are always
+    /// in the same chunk, so the effect sizes (or summaries) of the chunks together are those of all
+    /// the groups. Each chunk reads the whole spilled file again.
+    pub fn for_each_chunk(
+        self,
+        max_memory: u64,
+        mut analyze: impl FnMut(&Groups) -> Result<()>,
+    ) -> Result<()> {
+        let chunks = self.chunks(max_memory);
+        let mut file = self
+            .spill
+            .into_inner()
+            .map_err(|e| e.into_error())
+            .context("failed to spill the measurements")?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            log::info!(
+                "Analyzing chunk {} of {} ({} groups)",
+                i + 1,
+                chunks.len(),
+                chunk.len()
+            );
+            let mut counts: BTreeMap<u32, Vec<u64>> = chunk
+                .iter()
+                .map(|&id| (id, Vec::with_capacity(self.sizes[id as usize] as usize)))
+                .collect();
+            file.seek(SeekFrom::Start(0))?;
+            let mut reader = BufReader::new(&file);
+            let mut record = [0; RECORD];
+            loop {
+                match reader.read_exact(&mut record) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                    Err(e) => return Err(e).context("failed to read the spilled measurements"),
+                }
+                let id = u32::from_le_bytes(record[..4].try_into().unwrap());
+                if let Some(counts) = counts.get_mut(&id) {
+                    counts.push(u64::from_le_bytes(record[4..].try_into().unwrap()));
+                }
+            }
+            let groups = counts
+                .into_iter()
+                .map(|(id, counts)| (self.keys[id as usize].clone(), counts))
+                .collect();
+            analyze(&groups)?;
+        }
+        Ok(())
+    }
+
+    /// Split the groups into chunks of about `max_memory` bytes of counts, keeping together the
+    /// groups that differ only by engine. A chunk holds at least one set of these, however large.
+    fn chunks(&self, max_memory: u64) -> Vec<Vec<u32>> {
+        let mut comparisons: BTreeMap<Key, Vec<u32>> = BTreeMap::new();
+        for (key, &id) in &self.ids {
+            let key = Key {
+                engine: None,
+                ..key.clone()
+            };
+            comparisons.entry(key).or_default().push(id);
+        }
+        let mut chunks = vec![];
+        let mut chunk: Vec<u32> = vec![];
+        let mut bytes = 0;
+        for ids in comparisons.into_values() {
+            let size: u64 = ids.iter().map(|&id| self.sizes[id as usize] * 8).sum();
+            if !chunk.is_empty() && bytes + size > max_memory {
+                chunks.push(std::mem::take(&mut chunk));
+                bytes = 0;
+            }
+            chunk.extend(ids);
+            bytes += size;
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        chunks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_analysis::{effect_size, summarize};
+    use sightglass_data::Format;
+
+    #[test]
+    fn analyze_in_chunks() -> Result<()> {
+        let mut measurements: Vec<Measurement> =
+            Format::Json.read(std::fs::read("tests/results.json")?.as_slice())?;
+        // Compare the results with those of a second, slower engine.
+        let slower: Vec<_> = measurements
+            .iter()
+            .map(|m| Measurement {
+                engine: "slower.so".into(),
+                count: m.count + m.count / 10,
+                ..m.clone()
+            })
+            .collect();
+        measurements.extend(slower);
+        let groups: Groups = measurements.iter().collect();
+        let expected_effect_sizes = effect_size::calculate_grouped(0.01, &groups)?;
+        let expected_summaries = summarize::calculate_grouped(&groups);
+
+        for max_memory in [0, 1024, u64::MAX] {
+            let mut spilled = SpilledGroups::new()?;
+            for m in &measurements {
+                spilled.add(m)?;
+            }
+            let mut chunks = 0;
+            let mut effect_sizes = vec![];
+            let mut summaries = vec![];
+            spilled.for_each_chunk(max_memory, |groups| {
+                chunks += 1;
+                effect_sizes.extend(effect_size::calculate_grouped(0.01, groups)?);
+                summaries.extend(summarize::calculate_grouped(groups));
+                Ok(())
+            })?;
+            assert_eq!(chunks == 1, max_memory == u64::MAX);
+            assert_eq!(
+                serde_json::to_value(&effect_sizes)?,
+                serde_json::to_value(&expected_effect_sizes)?
+            );
+            // The summaries come in chunk order rather than key order.
+            assert_eq!(summaries.len(), expected_summaries.len());
+            assert!(expected_summaries.iter().all(|s| summaries.contains(s)));
+        }
+        Ok(())
+    }
+}
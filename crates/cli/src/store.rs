@@ -0,0 +1,436 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use sightglass_analysis::summarize;
+use sightglass_data::{Manifest, Measurement, Phase, Summary};
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The tables of the store, created if they do not already exist. Each run of `benchmark` is a
+/// run, with its raw measurements and their summaries; compacting a run drops its measurements but
+/// keeps its summaries. The engines and benchmarks of a run are JSON arrays, and its manifest (see
+/// `benchmark --manifest`), if recorded, is JSON.
+const TABLES: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    datetime TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    command TEXT NOT NULL,
+    label TEXT,
+    engines TEXT NOT NULL,
+    benchmarks TEXT NOT NULL,
+    compacted INTEGER NOT NULL DEFAULT 0,
+    manifest TEXT
+);
+CREATE TABLE IF NOT EXISTS measurements (
+    run INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    arch TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    wasm TEXT NOT NULL,
+    process INTEGER NOT NULL,
+    iteration INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    event TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    threads INTEGER,
+    input_size TEXT,
+    execution INTEGER
+);
+CREATE INDEX IF NOT EXISTS measurements_run ON measurements (run);
+CREATE TABLE IF NOT EXISTS summaries (
+    run INTEGER NOT NULL REFERENCES runs (id) ON DELETE CASCADE,
+    arch TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    wasm TEXT NOT NULL,
+    phase TEXT NOT NULL,
+    event TEXT NOT NULL,
+    min INTEGER NOT NULL,
+    max INTEGER NOT NULL,
+    median INTEGER NOT NULL,
+    mean REAL NOT NULL,
+    mean_deviation REAL NOT NULL,
+    threads INTEGER,
+    input_size TEXT,
+    execution INTEGER
+);
+CREATE INDEX IF NOT EXISTS summaries_run ON summaries (run);
+";
+
+/// How the store records the local time of a run; it sorts as text, so that a date (e.g.,
+/// `2024-06-04`) is a prefix of the times on that date.
+pub(crate) const DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A run recorded in the store.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Run {
+    pub id: i64,
+    /// The local time the run was recorded; see [DATETIME_FORMAT].
+    pub datetime: String,
+    /// The working directory of the run.
+    pub directory: String,
+    /// The command line of the run.
+    pub command: String,
+    /// The `--label` of the run, if any.
+    pub label: Option<String>,
+    pub engines: Vec<String>,
+    pub benchmarks: Vec<String>,
+    /// Whether the run's raw measurements were dropped, leaving only its summaries.
+    pub compacted: bool,
+}
+
+/// The local store of the runs of `benchmark`, an SQLite database.
+pub(crate) struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    /// Where the store is: `results.db` in the cache directory (e.g.,
+    /// `~/.cache/sightglass/results.db`).
+    pub fn path() -> Result<PathBuf> {
+        Ok(sightglass_build::cache_dir()?.join("results.db"))
+    }
+
+    /// Open the store, creating it if needed.
+    pub fn open() -> Result<Self> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        Self::open_at(&path)
+    }
+
+    /// Open the store at `path`, creating it if needed.
+    pub fn open_at(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("failed to open the results store {}", path.display()))?;
+        connection.execute_batch("PRAGMA foreign_keys = ON;")?;
+        connection.execute_batch(TABLES)?;
+        // Stores created before runs recorded their manifests, or measurements their executions,
+        // lack the columns.
+        for (table, column, kind) in [
+            ("runs", "manifest", "TEXT"),
+            ("measurements", "execution", "INTEGER"),
+            ("summaries", "execution", "INTEGER"),
+        ] {
+            let has_column = connection
+                .prepare(&format!(
+                    "SELECT 1 FROM pragma_table_info('{}') WHERE name = '{}'",
+                    table, column
+                ))?
+                .exists([])?;
+            if !has_column {
+                connection.execute_batch(&format!(
+                    "ALTER TABLE {} ADD COLUMN {} {}",
+                    table, column, kind
+                ))?;
+            }
+        }
+        Ok(Self { connection })
+    }
+
+    /// Record the `measurements` of a run, and their summaries, as a new run labeled `label` and
+    /// described by `manifest`; returns the run's ID.
+    pub fn record(
+        &mut self,
+        measurements: &[Measurement<'_>],
+        label: Option<&str>,
+        manifest: Option<&Manifest>,
+    ) -> Result<i64> {
+        let engines: BTreeSet<_> = measurements.iter().map(|m| m.engine.as_ref()).collect();
+        let benchmarks: BTreeSet<_> = measurements.iter().map(|m| m.wasm.as_ref()).collect();
+        let directory = std::env::current_dir()?;
+        let command: Vec<_> = std::env::args().collect();
+
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (datetime, directory, command, label, engines, benchmarks, manifest) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                chrono::Local::now().format(DATETIME_FORMAT).to_string(),
+                directory.to_string_lossy(),
+                command.join(" "),
+                label,
+                serde_json::to_string(&engines)?,
+                serde_json::to_string(&benchmarks)?,
+                manifest.map(serde_json::to_string).transpose()?,
+            ],
+        )?;
+        let run = transaction.last_insert_rowid();
+        {
+            let mut insert = transaction.prepare(
+                "INSERT INTO measurements \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            )?;
+            for m in measurements {
+                insert.execute(params![
+                    run,
+                    m.arch,
+                    m.engine,
+                    m.wasm,
+                    m.process,
+                    m.iteration,
+                    m.phase.to_string(),
+                    m.event,
+                    m.count as i64,
+                    m.threads,
+                    m.input_size,
+                    m.execution,
+                ])?;
+            }
+            let mut insert = transaction.prepare(
+                "INSERT INTO summaries \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?;
+            for s in summarize::calculate(measurements) {
+                insert.execute(params![
+                    run,
+                    s.arch,
+                    s.engine,
+                    s.wasm,
+                    s.phase.to_string(),
+                    s.event,
+                    s.min as i64,
+                    s.max as i64,
+                    s.median as i64,
+                    s.mean,
+                    s.mean_deviation,
+                    s.threads,
+                    s.input_size,
+                    s.execution,
+                ])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(run)
+    }
+
+    /// List the recorded runs, oldest first.
+    pub fn runs(&self) -> Result<Vec<Run>> {
+        let mut select = self.connection.prepare(
+            "SELECT id, datetime, directory, command, label, engines, benchmarks, compacted \
+             FROM runs ORDER BY id",
+        )?;
+        let runs = select.query_map([], read_run)?;
+        Ok(runs.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Find the run `id`.
+    pub fn run(&self, id: i64) -> Result<Run> {
+        let run = self
+            .connection
+            .query_row(
+                "SELECT id, datetime, directory, command, label, engines, benchmarks, compacted \
+                 FROM runs WHERE id = ?1",
+                [id],
+                read_run,
+            )
+            .optional()?;
+        run.with_context(|| format!("no such run in the results store: {}", id))
+    }
+
+    /// Read the manifest of the run `id`, if it was recorded with one.
+    pub fn manifest(&self, id: i64) -> Result<Option<Manifest>> {
+        self.run(id)?;
+        let json: Option<String> =
+            self.connection
+                .query_row("SELECT manifest FROM runs WHERE id = ?1", [id], |row| {
+                    row.get(0)
+                })?;
+        json.map(|json| Manifest::read(json.as_bytes())).transpose()
+    }
+
+    /// Read the raw measurements of the run `id`, unless it was compacted.
+    pub fn measurements(&self, id: i64) -> Result<Vec<Measurement<'static>>> {
+        if self.run(id)?.compacted {
+            bail!("run {} was compacted; only its summaries remain", id);
+        }
+        let mut select = self.connection.prepare(
+            "SELECT arch, engine, wasm, process, iteration, phase, event, count, threads, \
+             input_size, execution FROM measurements WHERE run = ?1 ORDER BY rowid",
+        )?;
+        let measurements = select.query_map([id], |row| {
+            Ok(Measurement {
+                arch: Cow::Owned(row.get(0)?),
+                engine: Cow::Owned(row.get(1)?),
+                wasm: Cow::Owned(row.get(2)?),
+                process: row.get(3)?,
+                iteration: row.get(4)?,
+                phase: read_phase(row, 5)?,
+                event: Cow::Owned(row.get(6)?),
+                count: row.get::<_, i64>(7)? as u64,
+                threads: row.get(8)?,
+                input_size: row.get::<_, Option<String>>(9)?.map(Cow::Owned),
+                execution: row.get(10)?,
+            })
+        })?;
+        Ok(measurements.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Read the summaries of the run `id`.
+    pub fn summaries(&self, id: i64) -> Result<Vec<Summary<'static>>> {
+        self.run(id)?;
+        let mut select = self.connection.prepare(
+            "SELECT arch, engine, wasm, phase, event, min, max, median, mean, mean_deviation, \
+             threads, input_size, execution FROM summaries WHERE run = ?1 ORDER BY rowid",
+        )?;
+        let summaries = select.query_map([id], |row| {
+            Ok(Summary {
+                arch: Cow::Owned(row.get(0)?),
+                engine: Cow::Owned(row.get(1)?),
+                wasm: Cow::Owned(row.get(2)?),
+                phase: read_phase(row, 3)?,
+                event: Cow::Owned(row.get(4)?),
+                min: row.get::<_, i64>(5)? as u64,
+                max: row.get::<_, i64>(6)? as u64,
+                median: row.get::<_, i64>(7)? as u64,
+                mean: row.get(8)?,
+                mean_deviation: row.get(9)?,
+                threads: row.get(10)?,
+                input_size: row.get::<_, Option<String>>(11)?.map(Cow::Owned),
+                execution: row.get(12)?,
+            })
+        })?;
+        Ok(summaries.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Remove the runs `ids`, with their measurements and summaries.
+    pub fn remove(&mut self, ids: &[i64]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for id in ids {
+            transaction.execute("DELETE FROM runs WHERE id = ?1", [id])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Drop the raw measurements of the runs `ids`, keeping their summaries.
+    pub fn compact(&mut self, ids: &[i64]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for id in ids {
+            transaction.execute("DELETE FROM measurements WHERE run = ?1", [id])?;
+            transaction.execute("UPDATE runs SET compacted = 1 WHERE id = ?1", [id])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Return the space of removed rows to the file system.
+    pub fn vacuum(&self) -> Result<()> {
+        self.connection.execute_batch("VACUUM")?;
+        Ok(())
+    }
+}
+
+fn read_run(row: &Row) -> rusqlite::Result<Run> {
+    let json = |i| -> rusqlite::Result<Vec<String>> {
+        serde_json::from_str(&row.get::<_, String>(i)?).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, e.into())
+        })
+    };
+    Ok(Run {
+        id: row.get(0)?,
+        datetime: row.get(1)?,
+        directory: row.get(2)?,
+        command: row.get(3)?,
+        label: row.get(4)?,
+        engines: json(5)?,
+        benchmarks: json(6)?,
+        compacted: row.get(7)?,
+    })
+}
+
+fn read_phase(row: &Row, i: usize) -> rusqlite::Result<Phase> {
+    row.get::<_, String>(i)?.parse().map_err(|e: String| {
+        rusqlite::Error::FromSqlConversionFailure(i, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(engine: &'static str, count: u64) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: engine.into(),
+            wasm: "benchmark.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: Some(2),
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn record_and_maintain_runs() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut store = Store::open_at(&dir.path().join("results.db"))?;
+        let measurements = vec![measurement("a.so", 10), measurement("a.so", 20)];
+        let manifest = Manifest {
+            notes: vec!["quiet machine".into()],
+            ..Default::default()
+        };
+        let first = store.record(&measurements, Some("before"), Some(&manifest))?;
+        let second = store.record(&[measurement("b.so", 30)], None, None)?;
+
+        let runs = store.runs()?;
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].id, first);
+        assert_eq!(runs[0].label.as_deref(), Some("before"));
+        assert_eq!(runs[0].engines, ["a.so"]);
+        assert_eq!(runs[0].benchmarks, ["benchmark.wasm"]);
+        assert_eq!(store.measurements(first)?.len(), 2);
+        assert_eq!(store.measurements(first)?[1].count, 20);
+        assert_eq!(store.measurements(first)?[1].threads, Some(2));
+        assert_eq!(store.summaries(first)?[0].mean, 15.0);
+        assert_eq!(store.manifest(first)?, Some(manifest));
+        assert_eq!(store.manifest(second)?, None);
+
+        store.compact(&[first])?;
+        assert!(store.run(first)?.compacted);
+        assert!(store.measurements(first).is_err());
+        assert_eq!(store.summaries(first)?[0].median, 20);
+
+        store.remove(&[first])?;
+        store.vacuum()?;
+        assert!(store.run(first).is_err());
+        assert_eq!(store.runs()?.len(), 1);
+        assert_eq!(store.measurements(second)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn open_older_store() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("results.db");
+        Connection::open(&path)?.execute_batch(
+            "CREATE TABLE runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                datetime TEXT NOT NULL,
+                directory TEXT NOT NULL,
+                command TEXT NOT NULL,
+                label TEXT,
+                engines TEXT NOT NULL,
+                benchmarks TEXT NOT NULL,
+                compacted INTEGER NOT NULL DEFAULT 0
+            );
+            INSERT INTO runs (datetime, directory, command, engines, benchmarks)
+            VALUES ('2024-06-01 03:00:00', '/', 'benchmark', '[]', '[]');",
+        )?;
+        let mut store = Store::open_at(&path)?;
+        assert_eq!(store.manifest(1)?, None);
+        let run = store.record(&[measurement("a.so", 10)], None, Some(&Manifest::default()))?;
+        assert_eq!(store.manifest(run)?, Some(Manifest::default()));
+        // Reopening does not add the column again.
+        Store::open_at(&path)?;
+        Ok(())
+    }
+}
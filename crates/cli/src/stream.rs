@@ -0,0 +1,212 @@
+use anyhow::Result;
+use sightglass_data::{Format, Measurement, Phase};
+use sightglass_recorder::measure::{Measure, Measurements};
+use std::{cell::RefCell, fmt, io::Write, rc::Rc, str::FromStr};
+
+/// The zstd compression level of streamed results: fast enough to keep up with recording while
+/// still shrinking raw results several times over.
+const LEVEL: i32 = 3;
+
+/// How often streamed results are written out (see [ResultStream]).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Flush {
+    /// After each phase, between the end of its measurement and the start of the next phase's.
+    Phase,
+    /// After each iteration.
+    Iteration,
+    /// After each benchmark.
+    Benchmark,
+}
+
+impl fmt::Display for Flush {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Flush::Phase => write!(f, "phase"),
+            Flush::Iteration => write!(f, "iteration"),
+            Flush::Benchmark => write!(f, "benchmark"),
+        }
+    }
+}
+
+impl FromStr for Flush {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phase" => Ok(Self::Phase),
+            "iteration" => Ok(Self::Iteration),
+            "benchmark" => Ok(Self::Benchmark),
+            _ => Err("unknown flush granularity; expected phase, iteration, or benchmark"),
+        }
+    }
+}
+
+/// Writes raw measurements in batches as they are produced (e.g., after each benchmark), rather
+/// than all at once after the run. Each batch is flushed, so that the output holds every finished
+/// batch even if the run is interrupted. With zstd, each batch is compressed as its own zstd frame;
+/// decompressing the frames together (e.g., with `zstd -d`) gives the same JSON or CSV as writing
+/// the measurements uncompressed.
+pub(crate) struct ResultStream {
+    format: Format,
+    zstd: bool,
+    started: bool,
+    output: Box<dyn Write>,
+}
+
+impl ResultStream {
+    pub fn new(format: Format, zstd: bool, output: Box<dyn Write>) -> Self {
+        Self {
+            format,
+            zstd,
+            started: false,
+            output,
+        }
+    }
+
+    /// Write a batch of measurements and flush it.
+    pub fn write(&mut self, measurements: &[Measurement<'_>]) -> Result<()> {
+        if measurements.is_empty() {
+            return Ok(());
+        }
+        if self.zstd {
+            let mut encoder = zstd::Encoder::new(&mut self.output, LEVEL)?;
+            write_batch(&self.format, self.started, measurements, &mut encoder)?;
+            encoder.finish()?;
+        } else {
+            write_batch(&self.format, self.started, measurements, &mut self.output)?;
+        }
+        self.output.flush()?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Finish the output: for JSON, close the array of measurements.
+    pub fn finish(mut self) -> Result<()> {
+        if let Format::Json = self.format {
+            let end: &[u8] = if self.started { b"\n]\n" } else { b"[]\n" };
+            if self.zstd {
+                let mut encoder = zstd::Encoder::new(&mut self.output, LEVEL)?;
+                encoder.write_all(end)?;
+                encoder.finish()?;
+            } else {
+                self.output.write_all(end)?;
+            }
+        }
+        self.output.flush()?;
+        Ok(())
+    }
+}
+
+/// A [ResultStream] shared with the [FlushPhases] of each benchmark.
+pub(crate) type SharedStream = Rc<RefCell<ResultStream>>;
+
+/// Write a batch of measurements in `format`, after the first batch if `started`.
+fn write_batch(
+    format: &Format,
+    started: bool,
+    measurements: &[Measurement<'_>],
+    output: &mut dyn Write,
+) -> Result<()> {
+    match format {
+        // The batches are the parts of one JSON array, which `finish` closes.
+        Format::Json => {
+            for (i, m) in measurements.iter().enumerate() {
+                output.write_all(if started || i > 0 { b",\n" } else { b"[\n" })?;
+                serde_json::to_writer(&mut *output, m)?;
+            }
+        }
+        // Only the first batch has the headers, if any, as the format takes them once.
+        Format::Csv { .. } => format.write_measurements(measurements, output)?,
+    }
+    Ok(())
+}
+
+/// Writes the measurements of each phase to the stream as the phase ends (see [Flush::Phase]). As
+/// the outermost [Measure], it runs after the others have recorded the phase.
+pub(crate) struct FlushPhases(pub SharedStream);
+
+impl Measure for FlushPhases {
+    fn start(&mut self, _phase: Phase) {}
+
+    fn end(&mut self, _phase: Phase, measurements: &mut Measurements) {
+        // Like a failing measurement, a failing write panics, failing the benchmark.
+        if let Err(e) = self.0.borrow_mut().write(measurements.pending()) {
+            panic!("failed to write the results: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(wasm: &str, count: u64) -> Measurement<'_> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: wasm.into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    /// A shared buffer, to read the stream's output back.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn stream_batches() -> Result<()> {
+        let batches = [
+            vec![measurement("a.wasm", 1), measurement("a.wasm", 2)],
+            vec![],
+            vec![measurement("b.wasm", 3)],
+        ];
+        let all: Vec<_> = batches.iter().flatten().cloned().collect();
+        for (format, zstd) in [
+            (Format::Json, true),
+            (Format::csv(true), true),
+            (Format::Json, false),
+            (Format::csv(true), false),
+        ] {
+            let output = Buffer::default();
+            let mut stream = ResultStream::new(format.clone(), zstd, Box::new(output.clone()));
+            for batch in &batches {
+                stream.write(batch)?;
+            }
+            stream.finish()?;
+
+            let mut written = output.0.borrow().clone();
+            if zstd {
+                written = zstd::decode_all(written.as_slice())?;
+            }
+            let read: Vec<Measurement> = format.read(written.as_slice())?;
+            assert_eq!(read.len(), all.len());
+            assert!(read
+                .iter()
+                .zip(&all)
+                .all(|(r, m)| r.wasm == m.wasm && r.count == m.count));
+        }
+
+        // No measurements are still valid JSON.
+        let output = Buffer::default();
+        ResultStream::new(Format::Json, true, Box::new(output.clone())).finish()?;
+        let written = zstd::decode_all(output.0.borrow().as_slice())?;
+        let read: Vec<Measurement> = Format::Json.read(written.as_slice())?;
+        assert!(read.is_empty());
+        Ok(())
+    }
+}
@@ -1,9 +1,21 @@
-use anyhow::Result;
-use sightglass_analysis::summarize;
-use sightglass_data::Format;
+use anyhow::{bail, Context, Result};
+use memmap2::Mmap;
+use serde_json::{json, Value};
+use sightglass_analysis::{
+    bencher, drift,
+    groups::Groups,
+    influx, manifest, matrix,
+    running::RunningSummaries,
+    summarize,
+    units::{self, Formatting, DEFAULT_PRECISION},
+};
+use sightglass_data::{Format, Manifest, Measurement, Phase, Summary};
 use std::{
-    fs::File,
-    io::{self, BufReader},
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use structopt::StructOpt;
 
@@ -21,30 +33,427 @@ pub struct SummarizeCommand {
     #[structopt(short = "i", long = "input-format", default_value = "json")]
     input_format: Format,
 
+    /// Memory-map the input files rather than reading them, so that JSON
+    /// results are deserialized without copying their strings; e.g., to
+    /// re-analyze large archived datasets faster. Only for finished files:
+    /// never map a file that is still being written (e.g., by `benchmark
+    /// --flush-every` or `--zstd`), whose changes the mapping must not see.
+    #[structopt(long)]
+    mmap: bool,
+
+    /// Keep running statistics of the summaries in this file and update them
+    /// with only the new measurements, rather than re-summarizing all of them;
+    /// e.g., for a nightly pipeline. Measurements appended to CSV input files
+    /// since the last update are read from where it left off, JSON input files
+    /// already summarized are skipped, and stdin is always new. Medians and
+    /// mean deviations are then estimated, to within 1%.
+    #[structopt(
+        long,
+        value_name = "STATE",
+        conflicts_with = "mmap",
+        parse(from_os_str)
+    )]
+    state: Option<PathBuf>,
+
     /// The format of the output data. Either 'json' or 'csv'; if unspecified, print the output in
     /// human-readable form.
     #[structopt(short = "o", long = "output-format")]
     output_format: Option<Format>,
+
+    /// Path to the manifest of the run (see `benchmark --manifest`); when printing human-readable
+    /// output, the engines it describes (e.g., how each was built) are printed first.
+    #[structopt(long, value_name = "MANIFEST")]
+    manifest: Option<String>,
+
+    /// Print the summaries in the InfluxDB line protocol instead, for
+    /// time-series dashboards (e.g., Grafana) to ingest: each summary is a
+    /// point of the `sightglass` measurement, tagged with its engine,
+    /// benchmark, phase, and event, with the statistics as its fields.
+    #[structopt(long, conflicts_with = "output-format")]
+    influx: bool,
+
+    /// The time of the run, in seconds since the Unix epoch (e.g., `date
+    /// +%s`), for `--influx`; defaults to now.
+    #[structopt(long, value_name = "SECONDS", requires = "influx")]
+    timestamp: Option<u64>,
+
+    /// Print the summaries in the Bencher Metric Format instead, for `bencher
+    /// run` to track: each phase of each benchmark is a Bencher benchmark,
+    /// and each event (e.g., `cycles`) one of its measures, valued at the
+    /// mean and bounded by the minimum and maximum.
+    #[structopt(long, conflicts_with_all = &["output-format", "influx"])]
+    bencher: bool,
+
+    /// Print the summaries of one phase and event as a table instead, with a
+    /// row per benchmark and a column per engine: each the engine's mean, and
+    /// its change from that of the first engine (or `--baseline`).
+    #[structopt(long, conflicts_with_all = &["output-format", "influx", "bencher"])]
+    matrix: bool,
+
+    /// The phase to print with `--matrix`.
+    #[structopt(long, default_value = "execution")]
+    phase: Phase,
+
+    /// The event to print with `--matrix`.
+    #[structopt(long, default_value = "cycles")]
+    event: String,
+
+    /// The engine, by its name in the results, to compare the others with
+    /// for `--matrix`.
+    #[structopt(long, value_name = "ENGINE", requires = "matrix")]
+    baseline: Option<String>,
+
+    /// When printing human-readable output, print exact counts rather than
+    /// formatting them in the unit of their event: e.g., `1.23 G` cycles,
+    /// `4.56 ms`, or `7.89 MiB`.
+    #[structopt(long)]
+    raw: bool,
+
+    /// The significant digits to print numbers with (by default, 3); e.g.,
+    /// `4.56 ms`, or `4563219` with `--raw`, as the digits before a decimal
+    /// point are never rounded away. Numbers never depend on the locale: they
+    /// have a `.` for a decimal point and no thousands separators. With
+    /// `--output-format`, the means are rounded to this precision too, which
+    /// are otherwise written exactly.
+    #[structopt(long, value_name = "DIGITS", parse(try_from_str = parse_precision))]
+    precision: Option<usize>,
+
+    /// The significance level of the diagnostics of the human-readable
+    /// output, which flag the benchmarks whose iterations drift (e.g., as
+    /// the CPU heats up or the page cache warms) or are autocorrelated.
+    #[structopt(short, long, default_value = "0.01")]
+    significance_level: f64,
 }
 
 impl SummarizeCommand {
     pub fn execute(&self) -> Result<()> {
-        let measurements = if let Some(files) = self.input_file.as_ref() {
-            let mut ms = Vec::new();
-            for file in files {
-                let reader = BufReader::new(File::open(file)?);
-                ms.append(&mut self.input_format.read(reader)?);
+        let human_readable = !self.influx && !self.bencher && self.output_format.is_none();
+        // The iterations of running statistics are not kept to diagnose.
+        let (summaries, drifts, empty) = match &self.state {
+            Some(state) => {
+                let (summaries, empty) =
+                    update(state, self.input_file.as_deref(), &self.input_format)?;
+                (summaries, vec![], empty)
+            }
+            None => {
+                let (groups, empty) = read_groups(
+                    self.input_file.as_deref(),
+                    &self.input_format,
+                    self.mmap,
+                    human_readable,
+                )?;
+                let drifts = if human_readable {
+                    drift::calculate(self.significance_level, &groups)?
+                } else {
+                    vec![]
+                };
+                (summarize::calculate_grouped(&groups), drifts, empty)
+            }
+        };
+        if !human_readable {
+            warn_empty(&empty);
+        }
+        if self.influx {
+            let timestamp = match self.timestamp {
+                Some(seconds) => Duration::from_secs(seconds),
+                None => SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .context("the system clock is before the Unix epoch")?,
+            };
+            influx::write(&summaries, timestamp.as_nanos(), &mut io::stdout())
+        } else if self.bencher {
+            bencher::write(&summaries, &mut io::stdout())
+        } else if let Some(output_format) = &self.output_format {
+            let mut summaries = summaries;
+            if let Some(digits) = self.precision {
+                round_summaries(&mut summaries, digits);
             }
-            ms
+            output_format.write(&summaries, io::stdout())
         } else {
-            self.input_format.read(io::stdin())?
+            if let Some(file) = &self.manifest {
+                let manifest = Manifest::read(BufReader::new(File::open(file)?))?;
+                manifest::write(&manifest, &mut io::stdout())?;
+            }
+            if self.matrix {
+                matrix::write(
+                    &summaries,
+                    self.phase,
+                    &self.event,
+                    self.baseline.as_deref(),
+                    self.formatting(),
+                    &mut io::stdout(),
+                )?;
+            } else {
+                summarize::write(summaries, self.formatting(), &mut io::stdout())?;
+            }
+            drift::write(&drifts, &mut io::stdout())?;
+            write_empty(&empty, &mut io::stdout())
+        }
+    }
+
+    /// How to write the numbers of a human-readable report: see `--raw` and `--precision`.
+    fn formatting(&self) -> Formatting {
+        Formatting {
+            raw: self.raw,
+            precision: self.precision.unwrap_or(DEFAULT_PRECISION),
+        }
+    }
+}
+
+/// Parse a `--precision`, which must be at least one significant digit.
+pub(crate) fn parse_precision(digits: &str) -> Result<usize> {
+    match digits.parse()? {
+        0 => bail!("the precision must be at least one significant digit"),
+        digits => Ok(digits),
+    }
+}
+
+/// Round the means of the `summaries` to `digits` significant digits, for machine-readable output
+/// given a `--precision`; the other statistics are whole counts.
+fn round_summaries(summaries: &mut [Summary<'_>], digits: usize) {
+    for s in summaries {
+        s.mean = units::round(s.mean, digits);
+        s.mean_deviation = units::round(s.mean_deviation, digits);
+    }
+}
+
+/// Write the input files without measurements, if any, at the end of a human-readable report: they
+/// were skipped, rather than failing the analysis, but were likely meant to have some.
+pub(crate) fn write_empty(empty: &[String], output_file: &mut dyn Write) -> Result<()> {
+    if empty.is_empty() {
+        return Ok(());
+    }
+    writeln!(output_file)?;
+    writeln!(
+        output_file,
+        "Skipped {} input files without measurements:",
+        empty.len()
+    )?;
+    for file in empty {
+        writeln!(output_file, "  {}", file)?;
+    }
+    Ok(())
+}
+
+/// Warn of the input files without measurements, if any, when the report is not human-readable.
+pub(crate) fn warn_empty(empty: &[String]) {
+    for file in empty {
+        log::warn!("Skipped {}, which has no measurements", file);
+    }
+}
+
+/// Update the running summaries kept in the `state` file with the new measurements of the input
+/// `files` (or `stdin`), returning the summaries and the files that are empty. The state also
+/// records how much of each file was read, so that an append-only file is only read from where the
+/// last update left off. Of a CSV file, only complete rows are read: one still being written may
+/// end mid-row, which the next update reads whole.
+fn update(
+    state: &Path,
+    files: Option<&[String]>,
+    format: &Format,
+) -> Result<(Vec<Summary<'static>>, Vec<String>)> {
+    let (mut offsets, mut running): (BTreeMap<String, u64>, RunningSummaries) = if state.exists() {
+        let json: Value = serde_json::from_reader(BufReader::new(
+            File::open(state).with_context(|| format!("failed to open {}", state.display()))?,
+        ))
+        .with_context(|| format!("failed to parse {}", state.display()))?;
+        (
+            serde_json::from_value(json["files"].clone())?,
+            serde_json::from_value(json["summaries"].clone())?,
+        )
+    } else {
+        Default::default()
+    };
+
+    let mut empty = vec![];
+    for file in files.unwrap_or_default() {
+        let mut opened = File::open(file).with_context(|| format!("failed to open {}", file))?;
+        let len = opened.metadata()?.len();
+        if len == 0 {
+            empty.push(file.clone());
+            continue;
+        }
+        let offset = offsets.get(file).copied().unwrap_or(0);
+        if offset == len {
+            log::info!("Skipping {}: no new measurements", file);
+            continue;
+        }
+        anyhow::ensure!(
+            offset < len,
+            "{} has been truncated since it was summarized",
+            file
+        );
+        // Only read up to the current end, in case the file is being appended to.
+        let end = match format {
+            Format::Csv { .. } => complete_rows(&mut opened, offset, len)?,
+            Format::Json => len,
+        };
+        if offset == end {
+            log::info!("Skipping {}: no complete new measurements", file);
+            continue;
+        }
+        let new = end - offset;
+        opened.seek(SeekFrom::Start(offset))?;
+        let mut add = |m: Measurement<'_>| {
+            running.add(&m);
+            Ok(())
         };
+        match format {
+            _ if offset == 0 => format
+                .clone()
+                .read_each(BufReader::new(opened.take(new)), &mut add)?,
+            // The headers were read with the start of the file.
+            Format::Csv { .. } => {
+                Format::csv(false).read_each(BufReader::new(opened.take(new)), &mut add)?
+            }
+            Format::Json => bail!(
+                "{} has changed since it was summarized; only CSV results can be appended to",
+                file
+            ),
+        }
+        log::info!("Summarized {} new bytes of {}", new, file);
+        offsets.insert(file.clone(), end);
+    }
+    if files.is_none() {
+        format.read_each(io::stdin(), |m| {
+            running.add(&m);
+            Ok(())
+        })?;
+    }
 
-        let summaries = summarize::calculate(&measurements);
-        if let Some(output_format) = &self.output_format {
-            output_format.write(&summaries, io::stdout())
+    let summaries = running.summaries();
+    // Replace the state whole, so that an interrupted update leaves the last one intact.
+    let json = serde_json::to_vec(&json!({ "files": offsets, "summaries": running }))?;
+    let partial = state.with_extension("partial");
+    fs::write(&partial, json).with_context(|| format!("failed to write {}", partial.display()))?;
+    fs::rename(&partial, state).with_context(|| format!("failed to write {}", state.display()))?;
+    Ok((summaries, empty))
+}
+
+/// The end of the complete rows of a CSV `file` between `offset` and `len`: just after the last
+/// newline, or `offset` if there is none; e.g., when the last write to a file being appended to
+/// ended mid-row.
+fn complete_rows(file: &mut File, offset: u64, len: u64) -> Result<u64> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut end = len;
+    while end > offset {
+        let start = end.saturating_sub(buffer.len() as u64).max(offset);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        if let Some(newline) = chunk.iter().rposition(|&b| b == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(offset)
+}
+
+/// Read the measurements of the input `files` (or `stdin`), grouping them as they are read rather
+/// than reading them all first, so that a large results file does not need several times its size
+/// in memory. With `mmap`, the files are memory-mapped, and with `processes`, the groups keep the
+/// process of each count (see [Groups::with_processes]). The files without measurements are
+/// returned with the groups.
+pub(crate) fn read_groups(
+    files: Option<&[String]>,
+    format: &Format,
+    mmap: bool,
+    processes: bool,
+) -> Result<(Groups, Vec<String>)> {
+    let mut groups = if processes {
+        Groups::with_processes()
+    } else {
+        Groups::default()
+    };
+    let empty = read_each(files, format, mmap, |m| {
+        groups.add(&m);
+        Ok(())
+    })?;
+    Ok((groups, empty))
+}
+
+/// Call `add` with each measurement of the input `files` (or `stdin`) as it is read; with `mmap`,
+/// the files are memory-mapped. See [read_groups]. Returns the files without measurements (e.g.,
+/// left empty by a failed run), which are skipped rather than failing to parse.
+pub(crate) fn read_each(
+    files: Option<&[String]>,
+    format: &Format,
+    mmap: bool,
+    mut add: impl FnMut(Measurement<'_>) -> Result<()>,
+) -> Result<Vec<String>> {
+    let mut empty = vec![];
+    for file in files.unwrap_or_default() {
+        let opened = File::open(file).with_context(|| format!("failed to open {}", file))?;
+        if opened.metadata()?.len() == 0 {
+            empty.push(file.clone());
+            continue;
+        }
+        let mut read = 0;
+        let mut add = |m: Measurement<'_>| {
+            read += 1;
+            add(m)
+        };
+        if mmap {
+            // SAFETY: the file must not be modified while it is mapped. Results files can be
+            // appended to while `benchmark` runs (with `--flush-every` or `--zstd`), so `--mmap` is
+            // documented as only for finished files, and `--state`, which reads growing files,
+            // conflicts with it.
+            let map = unsafe { Mmap::map(&opened) }
+                .with_context(|| format!("failed to memory-map {}", file))?;
+            format.read_each_slice(&map, &mut add)?;
         } else {
-            summarize::write(summaries, &mut io::stdout())
+            format.read_each(BufReader::new(opened), &mut add)?;
+        }
+        if read == 0 {
+            empty.push(file.clone());
+        }
+    }
+    if files.is_none() {
+        format.read_each(io::stdin(), &mut add)?;
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(iteration: u32, count: u64) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: "engine.so".into(),
+            wasm: "bz2.wasm".into(),
+            process: 1,
+            iteration,
+            phase: Phase::Execution,
+            event: "cycles".into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
         }
     }
+
+    #[test]
+    fn update_with_partial_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let state = dir.path().join("state.json");
+        let results = dir.path().join("results.csv");
+        let files = [results.display().to_string()];
+        let mut first = vec![];
+        Format::csv(true).write(&[measurement(0, 100)], &mut first)?;
+        let mut second = vec![];
+        Format::csv(false).write(&[measurement(1, 123456)], &mut second)?;
+
+        // The second row is cut inside its count, as by a flush mid-row.
+        let cut = second.len() - 5;
+        fs::write(&results, [&first[..], &second[..cut]].concat())?;
+        let (summaries, _) = update(&state, Some(&files), &Format::csv(true))?;
+        assert_eq!((summaries[0].min, summaries[0].max), (100, 100));
+
+        fs::write(&results, [first, second].concat())?;
+        let (summaries, _) = update(&state, Some(&files), &Format::csv(true))?;
+        assert_eq!((summaries[0].min, summaries[0].max), (100, 123456));
+        Ok(())
+    }
 }
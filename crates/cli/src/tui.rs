@@ -0,0 +1,221 @@
+use sightglass_analysis::units::Formatting;
+use sightglass_data::{Measurement, Phase};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, Write},
+};
+
+/// The number of recent counts whose variation a [LiveTable] shows.
+const ROLLING: usize = 10;
+
+/// A table of the benchmarks run so far, redrawn on `stderr` as measurements come in (see
+/// `benchmark --tui`), so that a broken run (e.g., failing benchmarks, or counts varying wildly)
+/// can be stopped early rather than found hours later. Each benchmark and engine has a row with
+/// its number of iterations, the mean of its counts, and their variation (the standard deviation
+/// over the mean) across the last [ROLLING] iterations. Only one phase and event is shown: the
+/// first event of the last phase (e.g., execution) of the first measurements added.
+#[derive(Default)]
+pub(crate) struct LiveTable {
+    shown: Option<(Phase, String)>,
+    rows: BTreeMap<(String, String), Row>,
+    /// The number of lines last drawn, to draw over.
+    drawn: usize,
+}
+
+#[derive(Default)]
+struct Row {
+    iterations: u64,
+    sum: f64,
+    recent: VecDeque<u64>,
+    failed: bool,
+}
+
+impl LiveTable {
+    /// Add measurements (e.g., of an iteration).
+    pub fn add(&mut self, measurements: &[Measurement<'_>]) {
+        if self.shown.is_none() {
+            let last = measurements.iter().map(|m| m.phase).max();
+            self.shown = measurements
+                .iter()
+                .find(|m| Some(m.phase) == last)
+                .map(|m| (m.phase, m.event.to_string()));
+        }
+        let (phase, event) = match self.shown.clone() {
+            Some(shown) => shown,
+            None => return,
+        };
+        for m in measurements {
+            if m.phase != phase || m.event != event.as_str() {
+                continue;
+            }
+            let row = self.row(&m.wasm, &m.engine);
+            row.iterations += 1;
+            row.sum += m.count as f64;
+            if row.recent.len() == ROLLING {
+                row.recent.pop_front();
+            }
+            row.recent.push_back(m.count);
+        }
+    }
+
+    /// Mark a benchmark as failed in an engine.
+    pub fn fail(&mut self, wasm: &str, engine: &str) {
+        self.row(wasm, engine).failed = true;
+    }
+
+    fn row(&mut self, wasm: &str, engine: &str) -> &mut Row {
+        self.rows
+            .entry((wasm.to_string(), engine.to_string()))
+            .or_default()
+    }
+
+    /// Clear the table drawn last, so that other output (e.g., a subprocess's logs) is not drawn
+    /// over; the next [LiveTable::draw] draws it again below.
+    pub fn clear(&mut self) {
+        if self.drawn > 0 {
+            let mut stderr = io::stderr().lock();
+            // Move up to the start of the table and clear from there down.
+            let _ = write!(stderr, "\x1b[{}A\x1b[J", self.drawn);
+            let _ = stderr.flush();
+            self.drawn = 0;
+        }
+    }
+
+    /// Draw the table over the last one drawn. Like the progress of the run, the table is only
+    /// informative, so a failure to draw it is ignored.
+    pub fn draw(&mut self) {
+        self.clear();
+        let lines = self.render();
+        let mut stderr = io::stderr().lock();
+        for line in &lines {
+            let _ = writeln!(stderr, "{}", line);
+        }
+        let _ = stderr.flush();
+        self.drawn = lines.len();
+    }
+
+    fn render(&self) -> Vec<String> {
+        let event = self.shown.as_ref().map_or("", |(_, event)| event.as_str());
+        let mut table = vec![[
+            "benchmark".to_string(),
+            "engine".to_string(),
+            "iterations".to_string(),
+            "mean".to_string(),
+            "variation".to_string(),
+        ]];
+        for ((wasm, engine), row) in &self.rows {
+            let mean = row.sum / row.iterations as f64;
+            table.push([
+                wasm.clone(),
+                engine.clone(),
+                row.iterations.to_string(),
+                if row.iterations == 0 {
+                    "-".to_string()
+                } else {
+                    Formatting::default().count(event, mean)
+                },
+                if row.failed {
+                    "FAILED".to_string()
+                } else {
+                    variation(&row.recent).map_or("-".to_string(), |v| format!("±{:.1}%", v))
+                },
+            ]);
+        }
+        let widths: Vec<usize> = (0..5)
+            .map(|i| {
+                table
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .max()
+                    .unwrap()
+            })
+            .collect();
+        let mut lines = vec![match &self.shown {
+            Some((phase, event)) => format!("{} :: {}", phase, event),
+            None => "waiting for measurements...".to_string(),
+        }];
+        lines.extend(table.iter().map(|row| {
+            format!(
+                "{:<w0$}   {:<w1$}   {:>w2$}   {:>w3$}   {:>w4$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            )
+        }));
+        lines
+    }
+}
+
+/// The standard deviation of `counts` as a percentage of their mean, if there are enough of them.
+fn variation(counts: &VecDeque<u64>) -> Option<f64> {
+    if counts.len() < 2 {
+        return None;
+    }
+    let n = counts.len() as f64;
+    let mean = counts.iter().map(|&c| c as f64).sum::<f64>() / n;
+    if mean == 0.0 {
+        return None;
+    }
+    let variance = counts
+        .iter()
+        .map(|&c| (c as f64 - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    Some(variance.sqrt() / mean * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(wasm: &str, event: &str, count: u64) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: wasm.to_string().into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: event.to_string().into(),
+            count,
+            threads: None,
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn render_live_table() {
+        let mut table = LiveTable::default();
+        assert_eq!(table.render()[0], "waiting for measurements...");
+        for count in [90, 110, 100] {
+            table.add(&[
+                Measurement {
+                    phase: Phase::Compilation,
+                    ..measurement("bz2.wasm", "cycles", 1)
+                },
+                measurement("bz2.wasm", "cycles", count),
+                measurement("bz2.wasm", "instructions", 5),
+            ]);
+        }
+        table.add(&[measurement("noop.wasm", "cycles", 7)]);
+        table.fail("fib.wasm", "libengine.so");
+        assert_eq!(
+            table.render(),
+            [
+                "execution :: cycles",
+                "benchmark   engine         iterations   mean   variation",
+                "bz2.wasm    libengine.so            3    100      ±10.0%",
+                "fib.wasm    libengine.so            0      -      FAILED",
+                "noop.wasm   libengine.so            1      7           -",
+            ]
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use sightglass_data::{Format, Measurement};
+use sightglass_upload::{upload_codespeed, CodespeedRun};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+};
+use structopt::StructOpt;
+
+/// Upload benchmark summaries to a Codespeed server; accepts raw benchmark
+/// results in `stdin` (i.e., from `sightglass-cli benchmark ...`).
+///
+/// Each phase and event of a benchmark is a Codespeed benchmark (e.g.,
+/// `benchmarks/bz2/benchmark.wasm execution cycles`), whose result is the
+/// mean, standard deviation, minimum, and maximum of its measurements.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "upload-codespeed")]
+pub struct UploadCodespeedCommand {
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Path to the file that will be read from, or none to indicate stdin
+    /// (default).
+    #[structopt(short = "f", long = "input-file")]
+    input_file: Option<String>,
+
+    /// The URL of the Codespeed server; e.g., `http://localhost:8000`.
+    #[structopt(index = 1, value_name = "URL")]
+    url: String,
+
+    /// The Codespeed project the results are for.
+    #[structopt(long)]
+    project: String,
+
+    /// The Codespeed executable the results are for; defaults to the engine
+    /// of each result.
+    #[structopt(long)]
+    executable: Option<String>,
+
+    /// The Codespeed environment (i.e., machine) the results were measured
+    /// on, which must already exist in Codespeed.
+    #[structopt(long)]
+    environment: String,
+
+    /// The commit of the project the results are for.
+    #[structopt(long)]
+    commit: String,
+
+    /// The branch of the commit.
+    #[structopt(long, default_value = "main")]
+    branch: String,
+
+    /// Setting this flag will prevent any uploading to the server; the
+    /// results are printed instead.
+    #[structopt(short = "d", long = "dry-run")]
+    dry_run: bool,
+}
+
+impl UploadCodespeedCommand {
+    pub fn execute(&self) -> Result<()> {
+        let file: Box<dyn Read> = if let Some(file) = self.input_file.as_ref() {
+            Box::new(BufReader::new(
+                File::open(file).context("unable to open --input-file")?,
+            ))
+        } else {
+            Box::new(io::stdin())
+        };
+        let measurements: Vec<Measurement> = self.input_format.read(file)?;
+        let run = CodespeedRun {
+            project: &self.project,
+            executable: self.executable.as_deref(),
+            environment: &self.environment,
+            commit: &self.commit,
+            branch: &self.branch,
+        };
+        upload_codespeed(&self.url, &run, self.dry_run, &measurements)
+    }
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use sightglass_data::{Format, Measurement};
+use sightglass_upload::{upload_conbench, ConbenchRun};
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+};
+use structopt::StructOpt;
+
+/// Upload benchmark results to a Conbench server; accepts raw benchmark
+/// results in `stdin` (i.e., from `sightglass-cli benchmark ...`).
+///
+/// Conbench only records durations, so only the wall time (`nanoseconds`)
+/// of each benchmark is uploaded, in seconds. Each phase of a benchmark on an
+/// engine is a Conbench benchmark result (e.g., named
+/// `benchmarks/bz2/benchmark.wasm execution` and tagged with the engine);
+/// Conbench calculates its statistics from the raw samples.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "upload-conbench")]
+pub struct UploadConbenchCommand {
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Path to the file that will be read from, or none to indicate stdin
+    /// (default).
+    #[structopt(short = "f", long = "input-file")]
+    input_file: Option<String>,
+
+    /// The URL of the Conbench server; e.g., `http://localhost:5000`.
+    #[structopt(index = 1, value_name = "URL")]
+    url: String,
+
+    /// The email address with which to log in to Conbench.
+    #[structopt(long, env = "CONBENCH_EMAIL", requires = "password")]
+    email: Option<String>,
+
+    /// The password with which to log in to Conbench.
+    #[structopt(long, env = "CONBENCH_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// The Conbench run ID under which to group the results; defaults to the
+    /// commit.
+    #[structopt(long)]
+    run_id: Option<String>,
+
+    /// The commit of the project the results are for.
+    #[structopt(long)]
+    commit: String,
+
+    /// The repository of the project.
+    #[structopt(long, default_value = sightglass_build::WASMTIME_REPOSITORY)]
+    repository: String,
+
+    /// The branch of the commit.
+    #[structopt(long)]
+    branch: Option<String>,
+
+    /// Setting this flag will prevent any uploading to the server; the
+    /// results are printed instead.
+    #[structopt(short = "d", long = "dry-run")]
+    dry_run: bool,
+}
+
+impl UploadConbenchCommand {
+    pub fn execute(&self) -> Result<()> {
+        let file: Box<dyn Read> = if let Some(file) = self.input_file.as_ref() {
+            Box::new(BufReader::new(
+                File::open(file).context("unable to open --input-file")?,
+            ))
+        } else {
+            Box::new(io::stdin())
+        };
+        let measurements: Vec<Measurement> = self.input_format.read(file)?;
+        let run = ConbenchRun {
+            run_id: self.run_id.as_deref().unwrap_or(&self.commit),
+            commit: &self.commit,
+            repository: &self.repository,
+            branch: self.branch.as_deref(),
+        };
+        let credentials = self.email.as_deref().zip(self.password.as_deref());
+        upload_conbench(&self.url, credentials, &run, self.dry_run, &measurements)
+    }
+}
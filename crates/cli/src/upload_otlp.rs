@@ -0,0 +1,80 @@
+use anyhow::{anyhow, Context, Result};
+use sightglass_data::{Format, Measurement};
+use sightglass_upload::upload_otlp;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+};
+use structopt::StructOpt;
+
+/// Export benchmark results to an OpenTelemetry collector over OTLP/HTTP;
+/// accepts raw benchmark results in `stdin` (i.e., from `sightglass-cli
+/// benchmark ...`).
+///
+/// Each measurement is a data point of a gauge named for its event (e.g.,
+/// `sightglass.cycles`); with `--traces`, each benchmark run is also a span
+/// with a child span per phase.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "upload-otlp")]
+pub struct UploadOtlpCommand {
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Path to the file that will be read from, or none to indicate stdin
+    /// (default).
+    #[structopt(short = "f", long = "input-file")]
+    input_file: Option<String>,
+
+    /// The base URL of the OTLP/HTTP receiver; e.g., `http://localhost:4318`.
+    #[structopt(
+        index = 1,
+        value_name = "URL",
+        env = "OTEL_EXPORTER_OTLP_ENDPOINT",
+        default_value = "http://localhost:4318"
+    )]
+    endpoint: String,
+
+    /// A header to send with each request, as `NAME=VALUE`; e.g., to
+    /// authenticate with the receiver. May be repeated.
+    #[structopt(long = "header", value_name = "NAME=VALUE", parse(try_from_str = parse_header))]
+    headers: Vec<(String, String)>,
+
+    /// Also export each benchmark run as a trace, with a span per phase timed
+    /// by the `nanoseconds` event.
+    #[structopt(long)]
+    traces: bool,
+
+    /// Setting this flag will prevent any exporting to the receiver; the
+    /// requests are printed instead.
+    #[structopt(short = "d", long = "dry-run")]
+    dry_run: bool,
+}
+
+impl UploadOtlpCommand {
+    pub fn execute(&self) -> Result<()> {
+        let file: Box<dyn Read> = if let Some(file) = self.input_file.as_ref() {
+            Box::new(BufReader::new(
+                File::open(file).context("unable to open --input-file")?,
+            ))
+        } else {
+            Box::new(io::stdin())
+        };
+        let measurements: Vec<Measurement> = self.input_format.read(file)?;
+        upload_otlp(
+            &self.endpoint,
+            &self.headers,
+            self.traces,
+            self.dry_run,
+            &measurements,
+        )
+    }
+}
+
+/// Parse a `NAME=VALUE` header.
+fn parse_header(header: &str) -> Result<(String, String)> {
+    let (name, value) = header
+        .split_once('=')
+        .ok_or_else(|| anyhow!("expected a header of the form NAME=VALUE: {}", header))?;
+    Ok((name.trim().to_string(), value.trim().to_string()))
+}
@@ -1,5 +1,5 @@
 use anyhow::Result;
-use sightglass_build::WasmBenchmark;
+use sightglass_build::{BenchmarkConfig, WasmBenchmark};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
@@ -22,6 +22,22 @@ impl ValidateCommand {
     pub fn execute(&self) -> Result<()> {
         WasmBenchmark::from(&self.benchmark).is_valid()?;
         log::info!("benchmark is valid");
+        let bytes = std::fs::read(&self.benchmark)?;
+        let proposals: Vec<_> = sightglass_build::required_proposals(&bytes)?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        if !proposals.is_empty() {
+            log::info!("benchmark requires: {}", proposals.join(", "));
+        }
+        let config = BenchmarkConfig::read(&self.benchmark)?;
+        if !config.tags.is_empty() {
+            log::info!("benchmark tags: {}", config.tags.join(", "));
+        }
+        if !config.input_sizes.is_empty() {
+            let sizes: Vec<_> = config.input_sizes.keys().map(String::as_str).collect();
+            log::info!("benchmark input sizes: default, {}", sizes.join(", "));
+        }
         Ok(())
     }
 }
@@ -0,0 +1,426 @@
+use anyhow::{bail, Context, Result};
+use csv::{ReaderBuilder, StringRecord};
+use serde::de::DeserializeOwned;
+use sightglass_data::{Format, Measurement, Summary};
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    fs::File,
+    io::{self, Read},
+};
+use structopt::StructOpt;
+
+/// Check results files for what would otherwise fail (or quietly skew) their analysis: malformed
+/// or concatenated files, duplicate measurements, mixed architectures, and impossible values. Each
+/// problem is described with how to fix it. (Measurements do not record the machine they were
+/// taken on, so mixing machines cannot be checked.)
+#[derive(Debug, StructOpt)]
+#[structopt(name = "validate-results")]
+pub struct ValidateResultsCommand {
+    /// Path to the file(s) to check, or none to check stdin (default).
+    #[structopt(short = "f")]
+    input_file: Option<Vec<String>>,
+
+    /// The format of the input data. Either 'json' or 'csv'.
+    #[structopt(short = "i", long = "input-format", default_value = "json")]
+    input_format: Format,
+
+    /// Check summaries (see `summarize --output-format`) rather than raw measurements.
+    #[structopt(long)]
+    summaries: bool,
+}
+
+impl ValidateResultsCommand {
+    pub fn execute(&self) -> Result<()> {
+        let inputs: Vec<Option<&str>> = match &self.input_file {
+            Some(files) => files.iter().map(|f| Some(f.as_str())).collect(),
+            None => vec![None],
+        };
+        let mut invalid = 0;
+        for input in inputs {
+            let name = input.unwrap_or("<stdin>");
+            let mut bytes = vec![];
+            match input {
+                Some(file) => File::open(file)
+                    .with_context(|| format!("failed to open {}", file))?
+                    .read_to_end(&mut bytes)?,
+                None => io::stdin().read_to_end(&mut bytes)?,
+            };
+            let mut problems = Problems::default();
+            let (count, kind) = if self.summaries {
+                let summaries: Vec<Summary> = parse(&self.input_format, &bytes, &mut problems);
+                check_summaries(&summaries, &mut problems);
+                (summaries.len(), "summaries")
+            } else {
+                let measurements: Vec<Measurement> =
+                    parse(&self.input_format, &bytes, &mut problems);
+                check_measurements(&measurements, &mut problems);
+                (measurements.len(), "measurements")
+            };
+            if problems.is_empty() {
+                println!("{}: {} {}, no problems found", name, count, kind);
+            } else {
+                invalid += 1;
+                println!("{}: {} {}, {} problems:", name, count, kind, problems.len());
+                print!("{}", problems);
+            }
+        }
+        if invalid > 0 {
+            bail!("found problems in {} of the results files", invalid);
+        }
+        Ok(())
+    }
+}
+
+/// The most examples of each kind of problem to describe; the rest are only counted.
+const EXAMPLES: usize = 5;
+
+/// The problems found in a results file, grouped by kind, in the order they were first found.
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+struct Problem {
+    kind: &'static str,
+    /// How to fix the problem, whatever its example.
+    fix: &'static str,
+    count: usize,
+    examples: Vec<String>,
+}
+
+impl Problems {
+    fn add(&mut self, kind: &'static str, fix: &'static str, example: String) {
+        let index = match self.0.iter().position(|p| p.kind == kind) {
+            Some(index) => index,
+            None => {
+                self.0.push(Problem {
+                    kind,
+                    fix,
+                    count: 0,
+                    examples: vec![],
+                });
+                self.0.len() - 1
+            }
+        };
+        let problem = &mut self.0[index];
+        problem.count += 1;
+        if problem.examples.len() < EXAMPLES {
+            problem.examples.push(example);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|p| p.count).sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Display for Problems {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for problem in &self.0 {
+            writeln!(f, "  {} ({}): {}", problem.kind, problem.count, problem.fix)?;
+            for example in &problem.examples {
+                writeln!(f, "    {}", example)?;
+            }
+            if problem.count > problem.examples.len() {
+                writeln!(
+                    f,
+                    "    ...and {} more",
+                    problem.count - problem.examples.len()
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse the results in `bytes`, noting where they are malformed: rather than stopping at the first
+/// error, as analysis does, a JSON file is read as a sequence of arrays (to recognize concatenated
+/// files) and a CSV file a row at a time.
+fn parse<T: DeserializeOwned>(format: &Format, bytes: &[u8], problems: &mut Problems) -> Vec<T> {
+    let mut results = vec![];
+    match format {
+        Format::Json => {
+            let mut arrays = 0;
+            for array in serde_json::Deserializer::from_slice(bytes).into_iter::<Vec<T>>() {
+                match array {
+                    Ok(array) => {
+                        arrays += 1;
+                        results.extend(array);
+                    }
+                    Err(e) => {
+                        problems.add(
+                            "malformed JSON",
+                            "fix or remove the results at this position; nothing after it was \
+                             checked",
+                            e.to_string(),
+                        );
+                        break;
+                    }
+                }
+            }
+            if arrays > 1 {
+                problems.add(
+                    "concatenated JSON",
+                    "combine the arrays into one, e.g., with `jq -s add`",
+                    format!("{} JSON arrays follow one another", arrays),
+                );
+            }
+        }
+        Format::Csv { headers } => {
+            let mut reader = ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(bytes);
+            let mut header: Option<StringRecord> = None;
+            for record in reader.records() {
+                let record = match record {
+                    Ok(record) => record,
+                    Err(e) => {
+                        problems.add(
+                            "malformed CSV",
+                            "fix or remove the row; nothing after it was checked",
+                            e.to_string(),
+                        );
+                        break;
+                    }
+                };
+                let line = record.position().map_or(0, |p| p.line());
+                if headers.get() && header.is_none() {
+                    header = Some(record);
+                    continue;
+                }
+                if header.as_ref() == Some(&record) {
+                    problems.add(
+                        "repeated CSV headers",
+                        "remove the repeated header rows (e.g., left by concatenating results \
+                         files)",
+                        format!("line {}", line),
+                    );
+                    continue;
+                }
+                match record.deserialize(header.as_ref()) {
+                    Ok(result) => results.push(result),
+                    Err(e) => problems.add(
+                        "malformed CSV row",
+                        "fix or remove the row",
+                        format!("line {}: {}", line, e),
+                    ),
+                }
+            }
+        }
+    }
+    results
+}
+
+/// Check the measurements for duplicates, mixed architectures, and impossible values.
+fn check_measurements(measurements: &[Measurement<'_>], problems: &mut Problems) {
+    check_architectures(measurements.iter().map(|m| m.arch.as_ref()), problems);
+    let mut seen = HashMap::new();
+    for (i, m) in measurements.iter().enumerate() {
+        let at = format!(
+            "measurement {} ({} {} {} {})",
+            i + 1,
+            m.engine,
+            m.wasm,
+            m.phase,
+            m.event
+        );
+        let key = (
+            &m.arch,
+            &m.engine,
+            &m.wasm,
+            m.threads,
+            &m.input_size,
+            m.execution,
+            m.process,
+            m.iteration,
+            m.phase,
+            &m.event,
+        );
+        if let Some(first) = seen.insert(key, i) {
+            problems.add(
+                "duplicate measurement",
+                "remove the duplicates, e.g., of a results file read or concatenated twice",
+                format!(
+                    "{} repeats measurement {} of process {}, iteration {}",
+                    at,
+                    first + 1,
+                    m.process,
+                    m.iteration
+                ),
+            );
+        }
+        for (field, value) in [
+            ("arch", &m.arch),
+            ("engine", &m.engine),
+            ("wasm", &m.wasm),
+            ("event", &m.event),
+        ] {
+            if value.is_empty() {
+                problems.add(
+                    "impossible value",
+                    "fix or remove the measurement",
+                    format!("{} has an empty {}", at, field),
+                );
+            }
+        }
+        if m.threads == Some(0) {
+            problems.add(
+                "impossible value",
+                "fix or remove the measurement",
+                format!("{} ran with 0 threads", at),
+            );
+        }
+        if m.execution == Some(0) {
+            problems.add(
+                "impossible value",
+                "fix or remove the measurement; executions are counted from 1",
+                format!("{} is of execution 0", at),
+            );
+        }
+        if m.count > i64::MAX as u64 {
+            problems.add(
+                "impossible value",
+                "fix or remove the measurement (e.g., of a counter that wrapped around)",
+                format!("{} has a count of {}", at, m.count),
+            );
+        }
+    }
+}
+
+/// Check the summaries for duplicates, mixed architectures, and impossible values.
+fn check_summaries(summaries: &[Summary<'_>], problems: &mut Problems) {
+    check_architectures(summaries.iter().map(|s| s.arch.as_ref()), problems);
+    let mut seen = HashMap::new();
+    for (i, s) in summaries.iter().enumerate() {
+        let at = format!(
+            "summary {} ({} {} {} {})",
+            i + 1,
+            s.engine,
+            s.wasm,
+            s.phase,
+            s.event
+        );
+        let key = (
+            &s.arch,
+            &s.engine,
+            &s.wasm,
+            s.threads,
+            &s.input_size,
+            s.execution,
+            s.phase,
+            &s.event,
+        );
+        if let Some(first) = seen.insert(key, i) {
+            problems.add(
+                "duplicate summary",
+                "summarize the measurements together rather than concatenating summaries",
+                format!("{} repeats summary {}", at, first + 1),
+            );
+        }
+        let (min, max) = (s.min as f64, s.max as f64);
+        if !(s.min <= s.median && s.median <= s.max && min <= s.mean && s.mean <= max) {
+            problems.add(
+                "impossible value",
+                "summarize the measurements again",
+                format!(
+                    "{} has a median of {} and a mean of {}, outside its range of {} to {}",
+                    at, s.median, s.mean, s.min, s.max
+                ),
+            );
+        }
+        if !(s.mean_deviation.is_finite() && s.mean_deviation >= 0.0) {
+            problems.add(
+                "impossible value",
+                "summarize the measurements again",
+                format!("{} has a mean deviation of {}", at, s.mean_deviation),
+            );
+        }
+    }
+}
+
+/// Results from several architectures cannot be compared with one another.
+fn check_architectures<'a>(archs: impl Iterator<Item = &'a str>, problems: &mut Problems) {
+    let archs: BTreeSet<_> = archs.collect();
+    if archs.len() > 1 {
+        let archs: Vec<_> = archs.into_iter().collect();
+        problems.add(
+            "mixed architectures",
+            "analyze the results of each architecture separately",
+            archs.join(", "),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validate(format: Format, results: &str) -> Problems {
+        let mut problems = Problems::default();
+        let measurements: Vec<Measurement> = parse(&format, results.as_bytes(), &mut problems);
+        check_measurements(&measurements, &mut problems);
+        problems
+    }
+
+    fn kinds(problems: &Problems) -> Vec<(&str, usize)> {
+        problems.0.iter().map(|p| (p.kind, p.count)).collect()
+    }
+
+    #[test]
+    fn valid_results() -> Result<()> {
+        let json = std::fs::read_to_string("tests/results.json")?;
+        let problems = validate(Format::Json, &json);
+        assert!(problems.is_empty(), "{}", problems);
+        Ok(())
+    }
+
+    #[test]
+    fn concatenated_json() {
+        let json = r#"[{"arch": "x86_64", "engine": "a.so", "wasm": "bz2.wasm",
+            "process": 1, "iteration": 0, "phase": "Execution", "event": "cycles", "count": 42}]"#;
+        let problems = validate(Format::Json, &format!("{}\n{}", json, json));
+        assert_eq!(
+            kinds(&problems),
+            [("concatenated JSON", 1), ("duplicate measurement", 1)]
+        );
+
+        let problems = validate(
+            Format::Json,
+            &json.replace("\"count\": 42", "\"count\": -1"),
+        );
+        assert_eq!(kinds(&problems), [("malformed JSON", 1)]);
+    }
+
+    #[test]
+    fn concatenated_csv() {
+        let header = "arch,engine,wasm,process,iteration,phase,event,count,threads,input_size\n";
+        let csv = format!(
+            "{header}\
+             x86_64,a.so,bz2.wasm,1,0,Execution,cycles,100,,\n\
+             aarch64,a.so,bz2.wasm,1,0,Execution,cycles,100,0,\n\
+             {header}\
+             x86_64,a.so,bz2.wasm,1,0,Execution,cycles,100,,\n\
+             x86_64,a.so,bz2.wasm,1,1,Execution,cycles,lots,,\n\
+             x86_64,,bz2.wasm,1,2,Execution,cycles,18446744073709551615,,\n",
+            header = header
+        );
+        let problems = validate(Format::csv(true), &csv);
+        assert_eq!(
+            kinds(&problems),
+            [
+                ("repeated CSV headers", 1),
+                ("malformed CSV row", 1),
+                ("mixed architectures", 1),
+                ("impossible value", 3),
+                ("duplicate measurement", 1),
+            ]
+        );
+        let output = problems.to_string();
+        assert!(output.contains("    line 4\n"), "{}", output);
+        assert!(output.contains("measurement 2 (a.so bz2.wasm execution cycles) ran with 0"));
+    }
+}
@@ -0,0 +1,219 @@
+use crate::sign::{digest, provenance_path, signature_path, Signer};
+use anyhow::{anyhow, bail, Context, Result};
+use minisign_verify::{PublicKey, Signature};
+use sightglass_data::{FileDigest, Provenance};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use structopt::StructOpt;
+
+/// Check that a results file is the one attested to by its signed provenance
+/// (see `sign`): that the provenance's signature is valid and that the
+/// results (and the run's manifest) have the digests it records.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "verify")]
+pub struct VerifyCommand {
+    /// Path to the results file to check.
+    #[structopt(short = "f", long = "input-file", parse(from_os_str))]
+    input_file: PathBuf,
+
+    /// Path to the provenance of the results; by default,
+    /// `<RESULTS>.provenance.json`.
+    #[structopt(long, value_name = "PROVENANCE", parse(from_os_str))]
+    provenance: Option<PathBuf>,
+
+    /// Path to the manifest of the run, to check it too.
+    #[structopt(long, value_name = "MANIFEST", parse(from_os_str))]
+    manifest: Option<PathBuf>,
+
+    /// The minisign public key to check the signature
+    /// (`<PROVENANCE>.minisig`) with.
+    #[structopt(short = "p", long, value_name = "KEY", parse(from_os_str))]
+    public_key: Option<PathBuf>,
+
+    /// The identity (e.g., an email address) that must have signed the
+    /// provenance with sigstore; the signature (`<PROVENANCE>.sigstore.json`)
+    /// is checked with `cosign verify-blob`.
+    #[structopt(
+        long,
+        value_name = "IDENTITY",
+        requires = "certificate-oidc-issuer",
+        conflicts_with = "public-key"
+    )]
+    certificate_identity: Option<String>,
+
+    /// The OIDC issuer of the sigstore `--certificate-identity`; e.g.,
+    /// `https://github.com/login/oauth`.
+    #[structopt(long, value_name = "URL")]
+    certificate_oidc_issuer: Option<String>,
+
+    /// Path to the signature, if not next to the provenance.
+    #[structopt(long, value_name = "SIGNATURE", parse(from_os_str))]
+    signature: Option<PathBuf>,
+
+    /// Only check the digests, not the signature; without one, anyone could
+    /// have written the provenance.
+    #[structopt(long, conflicts_with_all = &["public-key", "certificate-identity"])]
+    unsigned: bool,
+
+    /// Also check that the engines and benchmarks at the paths the results
+    /// name are the ones that produced them; e.g., before reproducing a run.
+    #[structopt(long)]
+    check_inputs: bool,
+}
+
+impl VerifyCommand {
+    pub fn execute(&self) -> Result<()> {
+        let path = self
+            .provenance
+            .clone()
+            .unwrap_or_else(|| provenance_path(&self.input_file));
+        let bytes =
+            fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+
+        if let Some(public_key) = &self.public_key {
+            let signature = self
+                .signature
+                .clone()
+                .unwrap_or_else(|| signature_path(Signer::Minisign, &path));
+            let comment = verify_minisign(public_key, &signature, &bytes)?;
+            println!("Signature: OK ({})", comment);
+        } else if let Some(identity) = &self.certificate_identity {
+            let bundle = self
+                .signature
+                .clone()
+                .unwrap_or_else(|| signature_path(Signer::Sigstore, &path));
+            let issuer = self.certificate_oidc_issuer.as_deref().unwrap_or_default();
+            let mut command = cosign_command(&path, &bundle, identity, issuer);
+            log::debug!("Running: {:?}", command);
+            let status = command
+                .status()
+                .context("failed to run `cosign`; is it installed?")?;
+            anyhow::ensure!(status.success(), "the signature is invalid ({})", status);
+            println!("Signature: OK (signed by {})", identity);
+        } else if !self.unsigned {
+            bail!(
+                "nothing to check the signature with: pass --public-key or \
+                 --certificate-identity, or --unsigned to only check the digests"
+            );
+        }
+
+        let provenance = Provenance::read(bytes.as_slice())?;
+        check(&provenance.results, &self.input_file)?;
+        match (&provenance.manifest, &self.manifest) {
+            (Some(expected), Some(manifest)) => check(expected, manifest)?,
+            (None, Some(_)) => bail!("the provenance does not record a manifest"),
+            (_, None) => {}
+        }
+        if self.check_inputs {
+            for expected in provenance.engines.iter().chain(&provenance.benchmarks) {
+                check(expected, Path::new(&expected.path))?;
+            }
+        }
+
+        let machine = &provenance.machine;
+        println!(
+            "Recorded {} on {} ({}, {}) with sightglass {} (engines: {}, benchmarks: {})",
+            provenance.datetime,
+            machine["name"].as_str().unwrap_or("an unknown machine"),
+            machine["cpu"].as_str().unwrap_or_default(),
+            machine["os"].as_str().unwrap_or_default(),
+            provenance.sightglass_version,
+            provenance.engines.len(),
+            provenance.benchmarks.len()
+        );
+        Ok(())
+    }
+}
+
+/// Check the file at `path` against its `expected` digest, printing it if it matches.
+fn check(expected: &FileDigest, path: &Path) -> Result<()> {
+    let actual = digest(path)?;
+    anyhow::ensure!(
+        actual.sha256 == expected.sha256,
+        "{} has been modified: its SHA-256 is {}, not {}",
+        path.display(),
+        actual.sha256,
+        expected.sha256
+    );
+    println!("{}: OK ({})", path.display(), actual.sha256);
+    Ok(())
+}
+
+/// Check the minisign `signature` of `bytes` with the `public_key`, returning its trusted comment.
+fn verify_minisign(public_key: &Path, signature: &Path, bytes: &[u8]) -> Result<String> {
+    let public_key = PublicKey::from_file(public_key)
+        .map_err(|e| anyhow!("failed to read {}: {}", public_key.display(), e))?;
+    let signature = Signature::from_file(signature)
+        .map_err(|e| anyhow!("failed to read {}: {}", signature.display(), e))?;
+    public_key
+        .verify(bytes, &signature, false)
+        .map_err(|e| anyhow!("the signature is invalid: {}", e))?;
+    Ok(signature.trusted_comment().to_string())
+}
+
+/// The command that checks the sigstore `bundle` of the `provenance` file.
+fn cosign_command(provenance: &Path, bundle: &Path, identity: &str, issuer: &str) -> Command {
+    let mut command = Command::new("cosign");
+    command
+        .args(["verify-blob", "--bundle"])
+        .arg(bundle)
+        .args(["--certificate-identity", identity])
+        .args(["--certificate-oidc-issuer", issuer])
+        .arg(provenance);
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A key pair and signature of `hello\n` in minisign's formats, made with a fixed Ed25519 key.
+    const PUBLIC_KEY: &str = "untrusted comment: minisign public key 0807060504030201
+RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4
+";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCJW9EkMWxs6buKMtyf9iQstZnl/JVX6bcNnk6YHUtSNV2t+uis8Y6wBHAirrTCY2AaaAft2KdItK60I8DOSfeAk=
+trusted comment: timestamp:1700000000\tfile:hello
+GWFYYGkexVeYnBimUWsfPslnSzGFjNi2SEoWm+SfkuW6WtLbVxYKBgNZb95oZSj5dTdh7J+bPcVVhV069a5jDQ==
+";
+
+    #[test]
+    fn verify_signatures() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let public_key = dir.path().join("minisign.pub");
+        let signature = dir.path().join("hello.minisig");
+        fs::write(&public_key, PUBLIC_KEY)?;
+        fs::write(&signature, SIGNATURE)?;
+
+        assert_eq!(
+            verify_minisign(&public_key, &signature, b"hello\n")?,
+            "timestamp:1700000000\tfile:hello"
+        );
+        assert!(verify_minisign(&public_key, &signature, b"hellO\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn check_digests() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let results = dir.path().join("results.json");
+        fs::write(&results, "[]")?;
+        let expected = digest(&results)?;
+        check(&expected, &results)?;
+        fs::write(&results, "[ ]")?;
+        assert!(check(&expected, &results).is_err());
+
+        let command = cosign_command(
+            Path::new("p.json"),
+            Path::new("p.json.sigstore.json"),
+            "me@example.com",
+            "https://github.com/login/oauth",
+        );
+        assert_eq!(command.get_program(), "cosign");
+        assert_eq!(command.get_args().last().unwrap(), "p.json");
+        Ok(())
+    }
+}
@@ -4,6 +4,10 @@ use predicates::prelude::*;
 use sightglass_data::Measurement;
 use std::path::PathBuf;
 
+/// A summary's minimum, mean, and maximum, each formatted in its event's unit; e.g., `[843 1.02 k
+/// 1.98 k]`.
+const SUMMARY: &str = r#"\[[\d.]+( \w+)? [\d.]+( \w+)? [\d.]+( \w+)?\]"#;
+
 #[test]
 fn benchmark_stop_after_compilation() {
     sightglass_cli_benchmark()
@@ -120,7 +124,7 @@ fn benchmark_summary() {
                 .and(predicate::str::contains("instantiation"))
                 .and(predicate::str::contains("execution"))
                 .and(predicate::str::contains(benchmark("noop")))
-                .and(predicate::str::is_match(r#"\[\d+ \d+\.\d+ \d+\]"#).unwrap())
+                .and(predicate::str::is_match(SUMMARY).unwrap())
                 .and(predicate::str::contains(
                     test_engine().display().to_string(),
                 )),
@@ -160,7 +164,7 @@ fn benchmark_effect_size() -> anyhow::Result<()> {
                     "execution :: cycles :: {}",
                     benchmark("noop")
                 )))
-                .and(predicate::str::is_match(r#"\[\d+ \d+\.\d+ \d+\]"#).unwrap())
+                .and(predicate::str::is_match(SUMMARY).unwrap())
                 .and(
                     predicate::str::contains("Δ = ")
                         .or(predicate::str::contains("No difference in performance.")),
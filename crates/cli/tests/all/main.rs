@@ -1,6 +1,7 @@
 mod benchmark;
 mod fingerprint;
 mod help;
+mod summarize;
 mod upload;
 mod util;
 
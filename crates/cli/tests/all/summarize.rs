@@ -0,0 +1,73 @@
+use super::util::sightglass_cli;
+use assert_cmd::prelude::*;
+use predicates::prelude::*;
+
+#[test]
+fn summarize() {
+    sightglass_cli()
+        .arg("summarize")
+        .arg("-f")
+        .arg("tests/results.json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("execution").and(predicate::str::contains("cycles")));
+}
+
+#[test]
+fn summarize_matrix() {
+    sightglass_cli()
+        .arg("summarize")
+        .arg("--matrix")
+        .arg("--phase")
+        .arg("compilation")
+        .arg("-f")
+        .arg("tests/results.json")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::starts_with("compilation :: cycles")
+                .and(predicate::str::contains("benchmarks/noop/benchmark.wasm")),
+        );
+}
+
+#[test]
+fn summarize_precision() {
+    sightglass_cli()
+        .arg("summarize")
+        .arg("--precision")
+        .arg("5")
+        .arg("-f")
+        .arg("tests/results.json")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("[21.349 M 66.022 M 324.28 M]"));
+
+    sightglass_cli()
+        .arg("summarize")
+        .arg("--precision")
+        .arg("0")
+        .arg("-f")
+        .arg("tests/results.json")
+        .assert()
+        .failure();
+}
+
+#[test]
+fn summarize_drift() {
+    // The iterations of each process in the results warm up.
+    sightglass_cli()
+        .arg("summarize")
+        .arg("-f")
+        .arg("tests/results.json")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("Iterations that are not independent samples").and(
+                predicate::str::contains(
+                    "  execution :: cycles :: ../../benchmarks/noop/benchmark.wasm \
+                     (../../engines/wasmtime/libengine.so): over 100 iterations, drifts by \
+                     -15.99% of the mean per iteration",
+                ),
+            ),
+        );
+}
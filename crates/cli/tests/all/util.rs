@@ -61,7 +61,10 @@ pub fn test_engine() -> PathBuf {
 /// Get a `sightglass-cli benchmark` command that is configured to use our test engine.
 pub fn sightglass_cli_benchmark() -> Command {
     let mut cmd = sightglass_cli();
-    cmd.arg("benchmark").arg("--engine").arg(test_engine());
+    cmd.arg("benchmark")
+        .arg("--no-record")
+        .arg("--engine")
+        .arg(test_engine());
     cmd
 }
 
@@ -1,11 +1,16 @@
 //! Describe the serialization formats used in sightglass data.
+use crate::{Measurement, Phase};
 use anyhow::Result;
 use core::fmt;
-use csv::ReaderBuilder;
-use serde::{de::DeserializeOwned, Serialize};
+use csv::{ReaderBuilder, StringRecord};
+use serde::{
+    de::{self, DeserializeOwned, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize,
+};
 use std::{
+    borrow::Cow,
     cell::Cell,
-    io::{Read, Write},
+    io::{BufReader, BufWriter, Read, Write},
     str::FromStr,
 };
 
@@ -41,11 +46,56 @@ impl Format {
                 let mut reader = ReaderBuilder::new()
                     .has_headers(headers.take())
                     .from_reader(reader);
-                reader.deserialize().map(|r| r.unwrap()).collect()
+                let columns = columns::<T>();
+                let headers = if reader.has_headers() {
+                    let headers = reader.headers()?.clone();
+                    check_headers(&headers, columns)?;
+                    Some(headers)
+                } else {
+                    None
+                };
+                let mut record = StringRecord::new();
+                let mut objects = vec![];
+                while reader.read_record(&mut record)? {
+                    if headers.is_none() && objects.is_empty() {
+                        check_row(&record, columns)?;
+                    }
+                    objects.push(record.deserialize(headers.as_ref())?);
+                }
+                objects
             }
         })
     }
 
+    /// Read measurements one at a time using the selected format, passing each to `f`, rather than
+    /// collecting them; e.g., to summarize a results file too large to hold in memory. CSV rows are
+    /// read into a reused buffer, which the measurements borrow their strings from.
+    pub fn read_each<R, F>(&self, reader: R, mut f: F) -> Result<()>
+    where
+        R: Read,
+        F: FnMut(Measurement<'_>) -> Result<()>,
+    {
+        match self {
+            Format::Json => each_json(
+                serde_json::Deserializer::from_reader(BufReader::new(reader)),
+                &mut f,
+            ),
+            Format::Csv { headers } => each_csv(reader, headers.take(), &mut f),
+        }
+    }
+
+    /// Like [Format::read_each], but reading from `bytes` in memory (e.g., a memory-mapped results
+    /// file): JSON measurements borrow their strings directly from `bytes`, without copying them.
+    pub fn read_each_slice<F>(&self, bytes: &[u8], mut f: F) -> Result<()>
+    where
+        F: FnMut(Measurement<'_>) -> Result<()>,
+    {
+        match self {
+            Format::Json => each_json(serde_json::Deserializer::from_slice(bytes), &mut f),
+            Format::Csv { headers } => each_csv(bytes, headers.take(), &mut f),
+        }
+    }
+
     /// Write a list of `T` using the selected format.
     pub fn write<T, W>(&self, objects: &[T], writer: W) -> Result<()>
     where
@@ -66,6 +116,19 @@ impl Format {
         })
     }
 
+    /// Like [Format::write], but for measurements, which are written to CSV by hand rather than
+    /// through serde: recording many events over many iterations writes enough measurements for
+    /// that to show. The output is the same.
+    pub fn write_measurements<W>(&self, measurements: &[Measurement<'_>], writer: W) -> Result<()>
+    where
+        W: Write + Sized,
+    {
+        match self {
+            Format::Json => self.write(measurements, writer),
+            Format::Csv { headers } => write_csv(measurements, headers.take(), writer),
+        }
+    }
+
     /// Write a list of `T` using the selected format.
     pub fn write_one<T, W>(&self, object: T, writer: W) -> Result<()>
     where
@@ -85,6 +148,390 @@ impl Format {
     }
 }
 
+/// Write measurements as CSV rows by hand. As with serde, the optional fields are only written when
+/// present, and the headers are those of the first measurement's fields, which every measurement
+/// must then have.
+fn write_csv<W: Write>(
+    measurements: &[Measurement<'_>],
+    has_headers: bool,
+    writer: W,
+) -> Result<()> {
+    let first = match measurements.first() {
+        Some(first) => first,
+        None => return Ok(()),
+    };
+    let fields = |m: &Measurement| {
+        (
+            m.threads.is_some(),
+            m.input_size.is_some(),
+            m.execution.is_some(),
+        )
+    };
+    let mut out = BufWriter::new(writer);
+    if has_headers {
+        out.write_all(b"arch,engine,wasm,process,iteration,phase,event,count")?;
+        if first.threads.is_some() {
+            out.write_all(b",threads")?;
+        }
+        if first.input_size.is_some() {
+            out.write_all(b",input_size")?;
+        }
+        if first.execution.is_some() {
+            out.write_all(b",execution")?;
+        }
+        out.write_all(b"\n")?;
+    }
+    let mut number = Number::default();
+    for m in measurements {
+        anyhow::ensure!(
+            fields(m) == fields(first),
+            "the measurements must all have the same optional fields (threads, input size, and \
+             execution) to be written as CSV"
+        );
+        write_csv_field(&mut out, &m.arch)?;
+        out.write_all(b",")?;
+        write_csv_field(&mut out, &m.engine)?;
+        out.write_all(b",")?;
+        write_csv_field(&mut out, &m.wasm)?;
+        out.write_all(b",")?;
+        out.write_all(number.format(m.process.into()))?;
+        out.write_all(b",")?;
+        out.write_all(number.format(m.iteration.into()))?;
+        out.write_all(match m.phase {
+            Phase::Compilation => b",Compilation,",
+            Phase::Deserialization => b",Deserialization,",
+            Phase::Instantiation => b",Instantiation,",
+            Phase::Execution => b",Execution,",
+        })?;
+        write_csv_field(&mut out, &m.event)?;
+        out.write_all(b",")?;
+        out.write_all(number.format(m.count))?;
+        if let Some(threads) = m.threads {
+            out.write_all(b",")?;
+            out.write_all(number.format(threads.into()))?;
+        }
+        if let Some(input_size) = &m.input_size {
+            out.write_all(b",")?;
+            write_csv_field(&mut out, input_size)?;
+        }
+        if let Some(execution) = m.execution {
+            out.write_all(b",")?;
+            out.write_all(number.format(execution.into()))?;
+        }
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Write a CSV field, quoted (as the `csv` crate would) if it has to be.
+fn write_csv_field(out: &mut impl Write, field: &str) -> std::io::Result<()> {
+    if !field
+        .bytes()
+        .any(|b| matches!(b, b',' | b'"' | b'\n' | b'\r'))
+    {
+        return out.write_all(field.as_bytes());
+    }
+    out.write_all(b"\"")?;
+    out.write_all(field.replace('"', "\"\"").as_bytes())?;
+    out.write_all(b"\"")
+}
+
+/// A buffer to format integers in without allocating.
+#[derive(Default)]
+struct Number([u8; 20]);
+
+impl Number {
+    fn format(&mut self, mut n: u64) -> &[u8] {
+        let mut i = self.0.len();
+        loop {
+            i -= 1;
+            self.0[i] = b'0' + (n % 10) as u8;
+            n /= 10;
+            if n == 0 {
+                return &self.0[i..];
+            }
+        }
+    }
+}
+
+/// Pass each measurement of a JSON array to `f`.
+fn each_json<'de, R, F>(mut deserializer: serde_json::Deserializer<R>, f: &mut F) -> Result<()>
+where
+    R: serde_json::de::Read<'de>,
+    F: FnMut(Measurement<'_>) -> Result<()>,
+{
+    deserializer.deserialize_seq(EachMeasurement(f))?;
+    deserializer.end()?;
+    Ok(())
+}
+
+/// Pass each measurement of a CSV file to `f`, reading each row into the same buffer.
+fn each_csv<R, F>(reader: R, has_headers: bool, f: &mut F) -> Result<()>
+where
+    R: Read,
+    F: FnMut(Measurement<'_>) -> Result<()>,
+{
+    let mut reader = ReaderBuilder::new()
+        .has_headers(has_headers)
+        .from_reader(reader);
+    let columns = columns::<MeasurementRow>();
+    let headers = if reader.has_headers() {
+        let headers = reader.headers()?.clone();
+        check_headers(&headers, columns)?;
+        Some(headers)
+    } else {
+        None
+    };
+    let mut record = StringRecord::new();
+    let mut first = true;
+    while reader.read_record(&mut record)? {
+        if headers.is_none() && std::mem::take(&mut first) {
+            check_row(&record, columns)?;
+        }
+        let row: MeasurementRow = record.deserialize(headers.as_ref())?;
+        f(row.into())?;
+    }
+    Ok(())
+}
+
+/// The columns that results written by older versions of sightglass may lack: those of fields added
+/// since, which are optional.
+const OPTIONAL_COLUMNS: &[&str] = &["threads", "input_size", "execution"];
+
+/// The columns of a CSV file of `T`: the names of its fields, in order. These are found by asking
+/// `T` to deserialize itself from a deserializer that only records the fields it asks for; a `T`
+/// that is not a plain struct has none, and its CSV headers are not checked.
+fn columns<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    struct Columns<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Columns<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            _visitor: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("only the fields are needed"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+            ignored_any
+        }
+    }
+
+    let mut columns: &'static [&'static str] = &[];
+    let _ = T::deserialize(Columns(&mut columns));
+    columns
+}
+
+/// Check that the `headers` of a CSV file are the `columns` expected of it, less any of the
+/// [OPTIONAL_COLUMNS], naming those missing and unexpected rather than leaving serde to fail on
+/// whichever field it happens to reach first.
+fn check_headers(headers: &StringRecord, columns: &[&str]) -> Result<()> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let missing: Vec<_> = columns
+        .iter()
+        .filter(|c| !OPTIONAL_COLUMNS.contains(c) && !headers.iter().any(|h| h == **c))
+        .collect();
+    let unexpected: Vec<_> = headers
+        .iter()
+        .enumerate()
+        .filter(|&(i, h)| !columns.contains(&h) || headers.iter().take(i).any(|d| d == h))
+        .map(|(_, h)| h)
+        .collect();
+    if missing.is_empty() && unexpected.is_empty() {
+        return Ok(());
+    }
+    fn quote<'a>(names: impl IntoIterator<Item = &'a str>) -> String {
+        let quoted: Vec<_> = names.into_iter().map(|n| format!("`{}`", n)).collect();
+        quoted.join(", ")
+    }
+    let mut problems = vec![];
+    if !missing.is_empty() {
+        problems.push(format!("missing {}", quote(missing.iter().map(|c| **c))));
+    }
+    if !unexpected.is_empty() {
+        problems.push(format!(
+            "unexpected (or repeated) {}",
+            quote(unexpected.iter().copied())
+        ));
+    }
+    let version = match (missing.is_empty(), unexpected.is_empty()) {
+        (false, true) => "an older",
+        (true, false) => "a newer",
+        _ => "a different",
+    };
+    anyhow::bail!(
+        "the CSV header does not have the expected columns: {}. The file may have been written by \
+         {} version of sightglass, or not hold this kind of data; expected the columns {} (of \
+         which {} are optional)",
+        problems.join("; "),
+        version,
+        quote(columns.iter().copied()),
+        quote(
+            OPTIONAL_COLUMNS
+                .iter()
+                .copied()
+                .filter(|c| columns.contains(c))
+        )
+    )
+}
+
+/// Check that the first `row` of a CSV file without headers has as many fields as the `columns`
+/// expected of it, or as those but the trailing [OPTIONAL_COLUMNS]: the fields are matched to
+/// columns by position, so any other number of them would shift some into the wrong column.
+fn check_row(row: &StringRecord, columns: &[&str]) -> Result<()> {
+    if columns.is_empty() {
+        return Ok(());
+    }
+    let required = columns.len()
+        - columns
+            .iter()
+            .rev()
+            .take_while(|c| OPTIONAL_COLUMNS.contains(c))
+            .count();
+    anyhow::ensure!(
+        (required..=columns.len()).contains(&row.len()),
+        "the first CSV row has {} fields, but rows without headers must have {} to {}, for the \
+         columns {}; the file may have been written by a different version of sightglass, or \
+         have headers (which must then be read as such)",
+        row.len(),
+        required,
+        columns.len(),
+        columns.join(", ")
+    );
+    Ok(())
+}
+
+/// Visit a JSON array of measurements, passing each to a function as it is read.
+struct EachMeasurement<'f, F>(&'f mut F);
+
+impl<'de, F> Visitor<'de> for EachMeasurement<'_, F>
+where
+    F: FnMut(Measurement<'_>) -> Result<()>,
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a list of measurements")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element::<MeasurementRow<'de>>()? {
+            (self.0)(row.into()).map_err(de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+/// A [Measurement] as it is read, borrowing its strings from the input where it can (i.e., from a
+/// CSV row, or from JSON in memory when the strings have no escapes).
+#[derive(Deserialize)]
+struct MeasurementRow<'a> {
+    #[serde(borrow)]
+    arch: Cow<'a, str>,
+    #[serde(borrow)]
+    engine: Cow<'a, str>,
+    #[serde(borrow)]
+    wasm: Cow<'a, str>,
+    process: u32,
+    iteration: u32,
+    phase: Phase,
+    #[serde(borrow)]
+    event: Cow<'a, str>,
+    count: u64,
+    #[serde(default)]
+    threads: Option<u32>,
+    #[serde(default, borrow)]
+    input_size: Option<Cow<'a, str>>,
+    #[serde(default)]
+    execution: Option<u32>,
+}
+
+impl<'a> From<MeasurementRow<'a>> for Measurement<'a> {
+    fn from(row: MeasurementRow<'a>) -> Self {
+        Measurement {
+            arch: row.arch,
+            engine: normalized_engine(row.engine),
+            wasm: normalized(row.wasm),
+            process: row.process,
+            iteration: row.iteration,
+            phase: row.phase,
+            event: row.event,
+            count: row.count,
+            threads: row.threads,
+            input_size: row.input_size,
+            execution: row.execution,
+        }
+    }
+}
+
+/// Normalize a path as [normalize_path] does, keeping it (e.g., borrowed from the input) if it is
+/// already normal.
+fn normalized(path: Cow<'_, str>) -> Cow<'_, str> {
+    if let Cow::Owned(normal) = normalize_path(&path) {
+        return normal.into();
+    }
+    path
+}
+
+/// Normalize the path of an engine label, leaving alone the flags it may be labeled with (e.g.,
+/// `libengine.so [-O opt-level=0]`).
+fn normalized_engine(engine: Cow<'_, str>) -> Cow<'_, str> {
+    let end = engine.find(" [").unwrap_or(engine.len());
+    match normalize_path(&engine[..end]) {
+        Cow::Borrowed(_) => engine,
+        Cow::Owned(normal) => format!("{}{}", normal, &engine[end..]).into(),
+    }
+}
+
+/// Normalize a `/`-separated path lexically, without looking at the file system: drop `.` and empty
+/// components, and resolve each `..` against the component before it, if any. This way, the same
+/// file named in different ways (e.g., `./benchmarks/bz2/benchmark.wasm` and
+/// `benchmarks/bz2//benchmark.wasm`) groups together when its results are analyzed. A path that is
+/// already normal is borrowed.
+pub fn normalize_path(path: &str) -> Cow<'_, str> {
+    let absolute = path.starts_with('/');
+    let mut components: Vec<&str> = vec![];
+    let mut changed = false;
+    for (i, component) in path.split('/').enumerate() {
+        match component {
+            "" if i == 0 => {}
+            "" | "." => changed = true,
+            ".." => match components.last() {
+                Some(&last) if last != ".." => {
+                    components.pop();
+                    changed = true;
+                }
+                _ if absolute => changed = true,
+                _ => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    if !changed {
+        return Cow::Borrowed(path);
+    }
+    let joined = components.join("/");
+    match (absolute, joined.is_empty()) {
+        (true, _) => format!("/{}", joined).into(),
+        (false, true) => ".".into(),
+        (false, false) => joined.into(),
+    }
+}
+
 impl fmt::Display for Format {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -0,0 +1,145 @@
+//! Hold many measurements in memory compactly.
+
+use crate::{Format, Measurement, Phase};
+use anyhow::Result;
+use serde::{ser::SerializeSeq, Serialize, Serializer};
+use std::{borrow::Cow, collections::HashMap, io::Read};
+
+/// Measurements held in memory with their strings interned: the few distinct architectures,
+/// engines, benchmarks, events, and input sizes of a results file are each stored once, and each
+/// measurement refers to them by index. This takes several times less memory than a
+/// `Vec<Measurement>` of owned strings for a large results file.
+///
+/// ```
+/// # use sightglass_data::{Format, Measurements};
+/// let json = r#"[{"arch": "x86_64", "engine": "libengine.so", "wasm": "benchmark.wasm",
+///     "process": 1, "iteration": 0, "phase": "Execution", "event": "cycles", "count": 42}]"#;
+/// let mut measurements = Measurements::default();
+/// measurements.read(&Format::Json, json.as_bytes()).unwrap();
+/// assert_eq!(measurements.len(), 1);
+/// assert_eq!(measurements.get(0).engine, "libengine.so");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Measurements {
+    strings: Vec<Box<str>>,
+    ids: HashMap<Box<str>, u32>,
+    rows: Vec<Row>,
+}
+
+/// A [Measurement] whose strings are indices into [Measurements::strings].
+#[derive(Clone, Copy, Debug)]
+struct Row {
+    arch: u32,
+    engine: u32,
+    wasm: u32,
+    event: u32,
+    input_size: Option<u32>,
+    process: u32,
+    iteration: u32,
+    phase: Phase,
+    count: u64,
+    threads: Option<u32>,
+    execution: Option<u32>,
+}
+
+impl Measurements {
+    /// Read the measurements of `reader` in the given `format`, adding them to these.
+    pub fn read<R: Read>(&mut self, format: &Format, reader: R) -> Result<()> {
+        format.read_each(reader, |m| {
+            self.push(&m);
+            Ok(())
+        })
+    }
+
+    /// Add a measurement.
+    pub fn push(&mut self, m: &Measurement<'_>) {
+        let row = Row {
+            arch: self.intern(&m.arch),
+            engine: self.intern(&m.engine),
+            wasm: self.intern(&m.wasm),
+            event: self.intern(&m.event),
+            input_size: m.input_size.as_deref().map(|s| self.intern(s)),
+            process: m.process,
+            iteration: m.iteration,
+            phase: m.phase,
+            count: m.count,
+            threads: m.threads,
+            execution: m.execution,
+        };
+        self.rows.push(row);
+    }
+
+    /// The number of measurements.
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Whether there are no measurements.
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// The `i`th measurement, borrowing its strings from these.
+    pub fn get(&self, i: usize) -> Measurement<'_> {
+        self.measurement(&self.rows[i])
+    }
+
+    /// The measurements, in the order they were added, borrowing their strings from these.
+    pub fn iter(&self) -> impl Iterator<Item = Measurement<'_>> + '_ {
+        self.rows.iter().map(|row| self.measurement(row))
+    }
+
+    /// The measurements as a `Vec`, e.g., for the analyses that take a slice, borrowing their
+    /// strings from these.
+    pub fn to_vec(&self) -> Vec<Measurement<'_>> {
+        self.iter().collect()
+    }
+
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(&id) = self.ids.get(s) {
+            return id;
+        }
+        let id = u32::try_from(self.strings.len()).expect("too many distinct strings");
+        self.strings.push(s.into());
+        self.ids.insert(s.into(), id);
+        id
+    }
+
+    fn measurement(&self, row: &Row) -> Measurement<'_> {
+        let string = |id: u32| Cow::Borrowed(&*self.strings[id as usize]);
+        Measurement {
+            arch: string(row.arch),
+            engine: string(row.engine),
+            wasm: string(row.wasm),
+            process: row.process,
+            iteration: row.iteration,
+            phase: row.phase,
+            event: string(row.event),
+            count: row.count,
+            threads: row.threads,
+            input_size: row.input_size.map(string),
+            execution: row.execution,
+        }
+    }
+}
+
+impl<'a> FromIterator<Measurement<'a>> for Measurements {
+    fn from_iter<I: IntoIterator<Item = Measurement<'a>>>(measurements: I) -> Self {
+        let mut interned = Self::default();
+        for m in measurements {
+            interned.push(&m);
+        }
+        interned
+    }
+}
+
+/// Measurements serialize as a list of [Measurement]s.
+impl Serialize for Measurements {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for m in self.iter() {
+            seq.serialize_element(&m)?;
+        }
+        seq.end()
+    }
+}
@@ -7,7 +7,16 @@
 #![deny(missing_docs, missing_debug_implementations)]
 
 mod format;
-pub use format::Format;
+mod interned;
+mod manifest;
+mod provenance;
+pub use format::{normalize_path, Format};
+pub use interned::Measurements;
+pub use manifest::{
+    BenchmarkManifest, EngineManifest, Failure, Manifest, RunManifest, Skipped, SuiteManifest,
+    Variant,
+};
+pub use provenance::{FileDigest, Provenance};
 
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, str::FromStr};
@@ -52,6 +61,22 @@ pub struct Measurement<'a> {
     /// of microseconds if the event is wall time, or it might be a count of
     /// instructions if the event is instructions retired.
     pub count: u64,
+
+    /// The number of threads the benchmark was run with, if it was run with
+    /// the threads proposal enabled (see `--wasm-threads`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The input size the benchmark was run with (e.g., `small` or `large`;
+    /// see `--input-size`), if not its default input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<Cow<'a, str>>,
+
+    /// Which execution of its instance this measurement was taken during,
+    /// counting from 1, if each instance was executed several times in a row
+    /// (see `--executions-per-instance`); only for the execution phase.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<u32>,
 }
 
 /// A phase in a Wasm program's lifecycle.
@@ -60,6 +85,9 @@ pub enum Phase {
     /// The compilation phase, where Wasm bytes are translated into native
     /// machine code.
     Compilation,
+    /// The deserialization phase, where a module compiled ahead of time (e.g.,
+    /// a `.cwasm` file) is loaded in place of compiling the Wasm bytes.
+    Deserialization,
     /// The instantiation phase, where imports are provided and memories,
     /// globals, and tables are initialized.
     Instantiation,
@@ -72,6 +100,7 @@ impl std::fmt::Display for Phase {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Phase::Compilation => write!(f, "compilation"),
+            Phase::Deserialization => write!(f, "deserialization"),
             Phase::Instantiation => write!(f, "instantiation"),
             Phase::Execution => write!(f, "execution"),
         }
@@ -84,6 +113,7 @@ impl FromStr for Phase {
         let s = s.to_ascii_lowercase();
         match s.as_str() {
             "compilation" => Ok(Self::Compilation),
+            "deserialization" => Ok(Self::Deserialization),
             "instantiation" => Ok(Self::Instantiation),
             "execution" => Ok(Self::Execution),
             _ => Err("invalid phase".into()),
@@ -127,6 +157,137 @@ pub struct Summary<'a> {
 
     /// The mean deviation (note: not standard deviation) of the `count` field.
     pub mean_deviation: f64,
+
+    /// The number of threads the benchmark was run with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The input size the benchmark was run with, if not its default input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<Cow<'a, str>>,
+
+    /// The execution of each instance summarized, if each was executed
+    /// several times in a row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<u32>,
+}
+
+/// The statistic an effect size compares the engines' counts by.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Statistic {
+    /// The arithmetic mean, with Welch's confidence interval of the difference of the means.
+    #[default]
+    Mean,
+    /// The median, with the Hodges–Lehmann estimate of the difference (the median of the
+    /// differences of every pair of counts) and its distribution-free confidence interval, which
+    /// outliers (e.g., a descheduled iteration) sway much less than the mean.
+    Median,
+}
+
+impl Statistic {
+    fn is_mean(&self) -> bool {
+        *self == Statistic::Mean
+    }
+}
+
+impl std::fmt::Display for Statistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Statistic::Mean => write!(f, "mean"),
+            Statistic::Median => write!(f, "median"),
+        }
+    }
+}
+
+impl FromStr for Statistic {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "mean" => Ok(Self::Mean),
+            "median" => Ok(Self::Median),
+            _ => Err("invalid statistic; expected 'mean' or 'median'".into()),
+        }
+    }
+}
+
+/// How an effect size transforms the engines' counts before comparing them.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Transform {
+    /// Compare the counts as they are: the difference is additive.
+    #[default]
+    None,
+    /// Compare the logarithms of the counts: the difference is multiplicative, as it is for
+    /// long-tailed timings whose variance grows with their mean. The compared means are then
+    /// geometric means.
+    Log,
+}
+
+impl Transform {
+    fn is_none(&self) -> bool {
+        *self == Transform::None
+    }
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Transform::None => write!(f, "none"),
+            Transform::Log => write!(f, "log"),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "log" => Ok(Self::Log),
+            _ => Err("invalid transform; expected 'none' or 'log'".into()),
+        }
+    }
+}
+
+/// How an effect size models the variation of the engines' counts.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Model {
+    /// Every count varies independently of the others.
+    #[default]
+    Independent,
+    /// The iterations are nested within their processes: each process varies (e.g., by where its
+    /// code and heap were laid out) as a random effect, and its iterations vary around it. As the
+    /// iterations of a process are correlated, the uncertainty of the mean is mostly that of the
+    /// process effects, which only more processes reduce.
+    Nested,
+}
+
+impl Model {
+    fn is_independent(&self) -> bool {
+        *self == Model::Independent
+    }
+}
+
+impl std::fmt::Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Model::Independent => write!(f, "independent"),
+            Model::Nested => write!(f, "nested"),
+        }
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "independent" => Ok(Self::Independent),
+            "nested" => Ok(Self::Nested),
+            _ => Err("invalid model; expected 'independent' or 'nested'".into()),
+        }
+    }
 }
 
 /// The effect size (and confidence interval) between two different engines
@@ -158,7 +319,8 @@ pub struct EffectSize<'a> {
     /// to record this measurement.
     pub a_engine: Cow<'a, str>,
 
-    /// The first engine's result's arithmetic mean of the `count` field.
+    /// The first engine's result's arithmetic mean of the `count` field; or,
+    /// comparing medians (see `statistic`), its median.
     pub a_mean: f64,
 
     /// The second engine being compared.
@@ -167,7 +329,9 @@ pub struct EffectSize<'a> {
     /// to record this measurement.
     pub b_engine: Cow<'a, str>,
 
-    /// The second engine's result's arithmetic mean of the `count` field.
+    /// The second engine's result's arithmetic mean of the `count` field; or,
+    /// comparing medians, the first engine's median shifted by the estimated
+    /// difference, so that `b_mean - a_mean` is always the estimate.
     pub b_mean: f64,
 
     /// The significance level for the confidence interval.
@@ -181,7 +345,41 @@ pub struct EffectSize<'a> {
     /// ```text
     /// b_mean - a_mean ± i
     /// ```
+    ///
+    /// Comparing medians, or transformed counts, whose confidence interval
+    /// need not be symmetric around the estimate, this is the wider of its two
+    /// halves.
     pub half_width_confidence_interval: f64,
+
+    /// The statistic compared; the mean unless given.
+    #[serde(default, skip_serializing_if = "Statistic::is_mean")]
+    pub statistic: Statistic,
+
+    /// How the counts were transformed before comparing them; comparing
+    /// their logarithms, the means and the confidence interval are
+    /// transformed back into counts (so that the means are geometric means).
+    #[serde(default, skip_serializing_if = "Transform::is_none")]
+    pub transform: Transform,
+
+    /// How the variation of the counts was modeled; with processes as random
+    /// effects, the means are estimated from the processes' means, weighted
+    /// by how precisely each is known.
+    #[serde(default, skip_serializing_if = "Model::is_independent")]
+    pub model: Model,
+
+    /// The number of threads both engines ran the benchmark with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The input size both engines ran the benchmark with, if not its default
+    /// input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<Cow<'a, str>>,
+
+    /// The execution of each instance compared, if each was executed several
+    /// times in a row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<u32>,
 }
 
 impl EffectSize<'_> {
@@ -191,7 +389,9 @@ impl EffectSize<'_> {
         (self.a_mean - self.b_mean).abs() > self.half_width_confidence_interval.abs()
     }
 
-    /// Return `b`'s speedup over `a` and the speedup's confidence interval.
+    /// Return `b`'s speedup over `a` and the speedup's confidence interval. These are infinite or
+    /// NaN when `a`'s mean is zero (e.g., a phase too short to measure); see
+    /// [EffectSize::checked_b_speed_up_over_a].
     pub fn b_speed_up_over_a(&self) -> (f64, f64) {
         (
             self.b_mean / self.a_mean,
@@ -199,11 +399,99 @@ impl EffectSize<'_> {
         )
     }
 
-    /// Return `a`'s speed up over `b` and the speed up's confidence interval.
+    /// Return `a`'s speed up over `b` and the speed up's confidence interval. These are infinite or
+    /// NaN when `b`'s mean is zero; see [EffectSize::checked_a_speed_up_over_b].
     pub fn a_speed_up_over_b(&self) -> (f64, f64) {
         (
             self.a_mean / self.b_mean,
             self.half_width_confidence_interval / self.b_mean,
         )
     }
+
+    /// Return `b`'s speedup over `a` and its confidence interval, or `None` if they are not finite.
+    pub fn checked_b_speed_up_over_a(&self) -> Option<(f64, f64)> {
+        finite(self.b_speed_up_over_a())
+    }
+
+    /// Return `a`'s speed up over `b` and its confidence interval, or `None` if they are not
+    /// finite.
+    pub fn checked_a_speed_up_over_b(&self) -> Option<(f64, f64)> {
+        finite(self.a_speed_up_over_b())
+    }
+}
+
+fn finite((ratio, ratio_ci): (f64, f64)) -> Option<(f64, f64)> {
+    (ratio.is_finite() && ratio_ci.is_finite()).then_some((ratio, ratio_ci))
+}
+
+/// The posterior distribution of the speedup between two different engines, as a Bayesian
+/// analysis estimates it: rather than a confidence interval around the difference of their means,
+/// how likely each ratio of their means is, given the measurements.
+///
+/// This allows us to justify statements like "there is a 99% probability that the new register
+/// allocator is 1.12x to 1.16x faster than the old register allocator, and a 99.9% probability
+/// that it is faster at all."
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Posterior<'a> {
+    /// The CPU architecture on which this measurement was taken, for example
+    /// "aarch64" or "x86_64".
+    pub arch: Cow<'a, str>,
+
+    /// The file path of the Wasm benchmark program.
+    pub wasm: Cow<'a, str>,
+
+    /// The phase in a Wasm program's lifecycle that was measured: compilation,
+    /// instantiation, or execution.
+    pub phase: Phase,
+
+    /// The event that was measured: micro seconds of wall time, CPU cycles
+    /// executed, instructions retired, cache misses, etc.
+    pub event: Cow<'a, str>,
+
+    /// The first engine being compared, the baseline.
+    pub a_engine: Cow<'a, str>,
+
+    /// The first engine's result's arithmetic mean of the `count` field.
+    pub a_mean: f64,
+
+    /// The second engine being compared.
+    pub b_engine: Cow<'a, str>,
+
+    /// The second engine's result's arithmetic mean of the `count` field.
+    pub b_mean: f64,
+
+    /// The probability of the credible interval, e.g. 0.99.
+    pub credible_level: f64,
+
+    /// The median of the posterior distribution of `b`'s speedup over `a`: the ratio of `a`'s
+    /// mean over `b`'s, which is above 1 when `b` is faster.
+    pub speedup: f64,
+
+    /// The lower bound of the speedup's credible interval.
+    pub speedup_lower: f64,
+
+    /// The upper bound of the speedup's credible interval.
+    pub speedup_upper: f64,
+
+    /// The probability that `b` is faster than `a`, i.e. that its speedup is above 1.
+    pub probability_faster: f64,
+
+    /// The number of threads both engines ran the benchmark with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The input size both engines ran the benchmark with, if not its default
+    /// input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<Cow<'a, str>>,
+
+    /// The execution of each instance compared, if each was executed several
+    /// times in a row.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<u32>,
+
+    /// Draws from the posterior distribution of the logarithm of the speedup, which combine with
+    /// those of other benchmarks into the distribution of their geometric mean; not serialized.
+    #[serde(skip)]
+    pub log_speedups: Vec<f64>,
 }
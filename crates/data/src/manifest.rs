@@ -0,0 +1,234 @@
+//! Describe what a benchmark run executed, alongside the measurements it recorded.
+
+use crate::Phase;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+};
+
+/// A description of a benchmark run; e.g., which builds of which engines the benchmarks ran in.
+/// This is written next to the run's measurements (see `sightglass-cli benchmark --manifest`) so
+/// that the results can be traced back to what produced them.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    /// How the run was configured and where it ran, to reproduce it; absent from the manifests of
+    /// older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<RunManifest>,
+
+    /// The engines that the benchmarks ran in.
+    pub engines: Vec<EngineManifest>,
+
+    /// The packaged benchmark suite the benchmarks were run from, if any; see `sightglass-cli
+    /// package`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suite: Option<SuiteManifest>,
+
+    /// The benchmarks that are described by a `benchmark.toml`; e.g., with tags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub benchmarks: Vec<BenchmarkManifest>,
+
+    /// The benchmarks that failed (e.g., by trapping or crashing); these have no measurements.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<Failure>,
+
+    /// The benchmarks that were skipped because their engine does not support the Wasm proposals
+    /// they require.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<Skipped>,
+
+    /// The benchmarks that were built as variants of another benchmark (e.g., with SIMD enabled);
+    /// see `benchmarks/build.sh --variant`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub variants: Vec<Variant>,
+
+    /// The SHA-256 digest of each Wasm file that was run, by its path, so that runs (e.g., on
+    /// different machines) can be checked to have run the same benchmarks.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub wasm_sha256: BTreeMap<String, String>,
+
+    /// Free-form notes about the run (see `sightglass-cli benchmark --note`); e.g., "the machine
+    /// was also running a kernel build".
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// The configuration of a benchmark run, as resolved from its command line, and the machine it ran
+/// on.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct RunManifest {
+    /// The version of Sightglass that ran the benchmarks.
+    pub sightglass_version: String,
+
+    /// The command line of the run.
+    pub args: Vec<String>,
+
+    /// The working directory of the run, which relative paths (e.g., of the engines) are
+    /// relative to.
+    pub directory: String,
+
+    /// When the run finished, in RFC 3339 form.
+    pub datetime: String,
+
+    /// The number of processes each benchmark ran in, for each engine.
+    pub processes: usize,
+
+    /// The number of iterations each process ran.
+    pub iterations_per_process: usize,
+
+    /// The measure the benchmarks were measured with (see `sightglass-cli benchmark --measure`);
+    /// e.g., `perf-counters`.
+    pub measure: String,
+
+    /// The events that were measured; e.g., `cycles` and `instructions-retired`.
+    pub events: Vec<String>,
+
+    /// The machine the run ran on, as `sightglass-cli fingerprint --kind machine` describes it,
+    /// if it could be described.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub machine: Option<serde_json::Value>,
+}
+
+/// A description of one engine of a benchmark run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EngineManifest {
+    /// The engine as it is named in the measurements (see [Measurement::engine]).
+    ///
+    /// [Measurement::engine]: crate::Measurement::engine
+    pub engine: String,
+
+    /// The path to the engine's shared library, if it has one (the native baseline does not).
+    pub path: Option<String>,
+
+    /// The kind of engine: `native` for the native baseline, or the runtime the engine's library
+    /// embeds (e.g., `wasmtime`, `wasmer`, `wamr`, or `node`), if the library reports it through
+    /// its build information; so that results of different runtimes can be told apart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+
+    /// The SHA-256 digest of the engine's shared library as it was loaded, if it was hashed (when
+    /// the manifest is written). Libraries are often rebuilt in place, so the same path in two runs
+    /// need not be the same engine.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// The engine-specific flags the engine ran with, if any.
+    pub flags: Option<String>,
+
+    /// How the engine was built, as reported by the engine itself through the bench API; e.g.,
+    /// `WASMTIME_VERSION`, `CRANELIFT_VERSION`, `PROFILE`, and `TARGET_FEATURES`.
+    pub build_info: BTreeMap<String, String>,
+}
+
+/// A packaged benchmark suite, pinning the exact benchmarks of a run so that runs over time can be
+/// compared.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SuiteManifest {
+    /// The version the suite was packaged as; e.g., `2024-01`.
+    pub version: String,
+
+    /// The SHA-256 digest of the suite's archive.
+    pub sha256: String,
+}
+
+/// A description of one benchmark of a benchmark run, from its `benchmark.toml` and any notes
+/// given for it on the command line.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct BenchmarkManifest {
+    /// The benchmark, as it is named in the measurements (see [Measurement::wasm]).
+    ///
+    /// [Measurement::wasm]: crate::Measurement::wasm
+    pub wasm: String,
+
+    /// The benchmark's tags; e.g., `compile-heavy`, `simd`, or `wasi`.
+    pub tags: Vec<String>,
+
+    /// The engine flags the benchmark runs with when no others are given, if any.
+    pub engine_flags: Option<String>,
+
+    /// Free-form notes about the benchmark in this run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub notes: Vec<String>,
+}
+
+/// A benchmark that failed to run in one of the engines of a benchmark run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Failure {
+    /// The engine, as it is named in the manifest's [EngineManifest::engine].
+    pub engine: String,
+
+    /// The benchmark, as it is named in the measurements (see [Measurement::wasm]).
+    ///
+    /// [Measurement::wasm]: crate::Measurement::wasm
+    pub wasm: String,
+
+    /// The last phase that the benchmark started, if known.
+    pub phase: Option<Phase>,
+
+    /// The number of threads the benchmark was run with, if any (see [Measurement::threads]).
+    ///
+    /// [Measurement::threads]: crate::Measurement::threads
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The exit status of the benchmark process, if it exited with one (rather than, e.g., being
+    /// killed by a signal).
+    pub status: Option<i32>,
+
+    /// What went wrong; e.g., the engine's trap message.
+    pub error: String,
+
+    /// The last lines the benchmark wrote to `stderr`.
+    pub stderr: String,
+}
+
+/// A benchmark that was not run in one of the engines of a benchmark run.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Skipped {
+    /// The engine, as it is named in the manifest's [EngineManifest::engine].
+    pub engine: String,
+
+    /// The benchmark, as it is named in the measurements (see [Measurement::wasm]).
+    ///
+    /// [Measurement::wasm]: crate::Measurement::wasm
+    pub wasm: String,
+
+    /// The Wasm proposals that the benchmark requires but the engine, with its flags, does not
+    /// support; e.g., `tail-call`.
+    pub proposals: Vec<String>,
+}
+
+/// A benchmark built with different compiler flags than the benchmark it is a variant of, or
+/// post-processed with `wasm-opt`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Variant {
+    /// The benchmark, as it is named in the measurements (see [Measurement::wasm]).
+    ///
+    /// [Measurement::wasm]: crate::Measurement::wasm
+    pub wasm: String,
+
+    /// The name of the variant; e.g., `simd`.
+    pub name: String,
+
+    /// The compiler flags the variant was built with, in addition to the benchmark's own; e.g.,
+    /// `-msimd128`.
+    pub flags: String,
+
+    /// The `wasm-opt` passes the variant was post-processed with, if any; e.g., `-O3`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wasm_opt: Option<String>,
+}
+
+impl Manifest {
+    /// Read a manifest from its JSON form.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write a manifest in its JSON form.
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+}
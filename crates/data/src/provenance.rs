@@ -0,0 +1,57 @@
+//! Attest to what produced a results file, so that published results can be checked to be
+//! unmodified and reproduced with the same inputs.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// A description of a results file and of the inputs that produced it (see `sightglass-cli sign`).
+/// Its JSON form is what gets signed: the results and manifest are bound to it by their digests.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct Provenance {
+    /// The results file.
+    pub results: FileDigest,
+
+    /// The manifest of the run that produced the results (see `sightglass-cli benchmark
+    /// --manifest`), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub manifest: Option<FileDigest>,
+
+    /// The engines named in the results, except the native baseline, which has no library.
+    pub engines: Vec<FileDigest>,
+
+    /// The Wasm benchmarks named in the results.
+    pub benchmarks: Vec<FileDigest>,
+
+    /// The machine the provenance was recorded on, as `sightglass-cli fingerprint --kind machine`
+    /// describes it.
+    pub machine: serde_json::Value,
+
+    /// The version of Sightglass that recorded the provenance.
+    pub sightglass_version: String,
+
+    /// When the provenance was recorded, in RFC 3339 form.
+    pub datetime: String,
+}
+
+/// The SHA-256 digest of a file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FileDigest {
+    /// The path to the file, as it was given (e.g., as the results name an engine).
+    pub path: String,
+
+    /// The hex-encoded SHA-256 digest of the file's contents.
+    pub sha256: String,
+}
+
+impl Provenance {
+    /// Read a provenance from its JSON form.
+    pub fn read<R: Read>(reader: R) -> Result<Self> {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write a provenance in its JSON form.
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        Ok(serde_json::to_writer_pretty(writer, self)?)
+    }
+}
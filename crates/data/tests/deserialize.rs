@@ -1,4 +1,4 @@
-use sightglass_data::{Format, Measurement};
+use sightglass_data::{normalize_path, Format, Measurement};
 use std::fs::File;
 
 #[test]
@@ -23,3 +23,136 @@ fn csv_with_headers() {
     let measurements: Vec<Measurement> = format.read(file).unwrap();
     assert_eq!(measurements.len(), 9);
 }
+
+#[test]
+fn csv_with_threads() {
+    let measurement = Measurement {
+        arch: "x86_64".into(),
+        engine: "wasmtime".into(),
+        wasm: "benchmarks/noop/benchmark.wasm".into(),
+        process: 1711158,
+        iteration: 0,
+        phase: sightglass_data::Phase::Execution,
+        event: "cycles".into(),
+        count: 1075,
+        threads: Some(4),
+        input_size: Some("large".into()),
+        execution: Some(2),
+    };
+    let mut csv = vec![];
+    Format::csv(true).write(&[measurement], &mut csv).unwrap();
+    let measurements: Vec<Measurement> = Format::csv(true).read(&csv[..]).unwrap();
+    assert_eq!(measurements[0].threads, Some(4));
+    assert_eq!(measurements[0].input_size.as_deref(), Some("large"));
+    assert_eq!(measurements[0].execution, Some(2));
+}
+
+#[test]
+fn csv_written_by_hand() {
+    let file = File::open("tests/results.json").unwrap();
+    let mut measurements: Vec<Measurement> = Format::Json.read(file).unwrap();
+    // Fields that need quoting.
+    measurements[1].wasm = "benchmarks/a,b/\"benchmark\".wasm".into();
+    for headers in [true, false] {
+        let mut serialized = vec![];
+        Format::csv(headers)
+            .write(&measurements, &mut serialized)
+            .unwrap();
+        let mut by_hand = vec![];
+        Format::csv(headers)
+            .write_measurements(&measurements, &mut by_hand)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(by_hand).unwrap(),
+            String::from_utf8(serialized).unwrap()
+        );
+    }
+
+    for m in &mut measurements {
+        m.threads = Some(4);
+        m.input_size = Some("large".into());
+        m.execution = Some(1);
+    }
+    let mut serialized = vec![];
+    Format::csv(true)
+        .write(&measurements, &mut serialized)
+        .unwrap();
+    let mut by_hand = vec![];
+    Format::csv(true)
+        .write_measurements(&measurements, &mut by_hand)
+        .unwrap();
+    assert_eq!(by_hand, serialized);
+}
+
+#[test]
+fn csv_with_mismatched_headers() {
+    // A column renamed (or shifted) names both the missing and the unexpected columns.
+    let csv = "arch,engine,wasm,process,iteration,phase,event,counts\n\
+               x86_64,main.so,bz2.wasm,1,0,Execution,cycles,100\n";
+    let err = Format::csv(true)
+        .read::<Measurement, _>(csv.as_bytes())
+        .unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("missing `count`; unexpected (or repeated) `counts`"));
+    assert!(message.contains("a different version of sightglass"));
+
+    // Old results without the optional columns are still read.
+    let csv = "arch,engine,wasm,process,iteration,phase,event,count\n\
+               x86_64,main.so,bz2.wasm,1,0,Execution,cycles,100\n";
+    assert!(Format::csv(true)
+        .read::<Measurement, _>(csv.as_bytes())
+        .is_ok());
+    let mut read = 0;
+    Format::csv(true)
+        .read_each(csv.as_bytes(), |_| {
+            read += 1;
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(read, 1);
+
+    // Without headers, a row with too few fields is named rather than misread.
+    let csv = "x86_64,main.so,bz2.wasm,1,0,Execution,100\n";
+    let err = Format::csv(false)
+        .read_each(csv.as_bytes(), |_| Ok(()))
+        .unwrap_err();
+    assert!(err.to_string().contains("has 7 fields"));
+}
+
+#[test]
+fn normalize_paths() {
+    for (path, normal) in [
+        (
+            "benchmarks/bz2/benchmark.wasm",
+            "benchmarks/bz2/benchmark.wasm",
+        ),
+        (
+            "./benchmarks//bz2/./benchmark.wasm",
+            "benchmarks/bz2/benchmark.wasm",
+        ),
+        (
+            "benchmarks/noop/../bz2/benchmark.wasm",
+            "benchmarks/bz2/benchmark.wasm",
+        ),
+        (
+            "../../engines/wasmtime/libengine.so",
+            "../../engines/wasmtime/libengine.so",
+        ),
+        ("/checkout/../engines/libengine.so", "/engines/libengine.so"),
+        ("/../libengine.so", "/libengine.so"),
+        ("a/..", "."),
+    ] {
+        assert_eq!(normalize_path(path), normal);
+    }
+
+    // Measurements are read with normal paths, keeping an engine's flags as they are.
+    let csv = "arch,engine,wasm,process,iteration,phase,event,count\n\
+               x86_64,./engines/libengine.so [--dir=./a],./bz2/benchmark.wasm,1,0,Execution,cycles,1\n";
+    Format::csv(true)
+        .read_each(csv.as_bytes(), |m| {
+            assert_eq!(m.engine, "engines/libengine.so [--dir=./a]");
+            assert_eq!(m.wasm, "bz2/benchmark.wasm");
+            Ok(())
+        })
+        .unwrap();
+}
@@ -1,4 +1,4 @@
-use sightglass_data::{EffectSize, Phase};
+use sightglass_data::{EffectSize, Model, Phase, Statistic, Transform};
 
 #[test]
 fn effect_size_serialized_to_csv() {
@@ -17,6 +17,12 @@ fn effect_size_serialized_to_csv() {
             b_mean: 110.0,
             significance_level: 0.05,
             half_width_confidence_interval: 1.3,
+            statistic: Statistic::Mean,
+            transform: Transform::None,
+            model: Model::Independent,
+            threads: None,
+            input_size: None,
+            execution: None,
         })
         .unwrap();
     let csv = writer.into_inner().unwrap();
@@ -1,12 +1,13 @@
 use crate::measure::{Measure, Measurements};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use sightglass_data::Phase;
+use std::fmt;
 use std::path::Path;
 use std::ptr;
 use std::{cell::UnsafeCell, ffi::c_void};
 
 /// NB: Keep this in sync with the version defined in
-/// `wasmtime-bench-api`!
+/// `wasmtime-bench-api` (and in `engines/wasmer/bench-api`)!
 #[repr(C)]
 struct WasmBenchConfig {
     working_dir_ptr: *const u8,
@@ -37,8 +38,104 @@ struct WasmBenchConfig {
     execution_flags_len: usize,
 }
 
+/// The latest version of the bench API that this recorder implements. An engine reports the version
+/// it implements by exporting `wasm_bench_api_version`; engines that do not export it implement
+/// version 1, the original API.
+pub const BENCH_API_VERSION: u32 = 1;
+
+/// An optional part of the bench API, which an engine may or may not implement.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// `wasm_bench_code_size`: report the size of the compiled code.
+    CodeSize,
+    /// `wasm_bench_fuel_consumed`: report the fuel consumed by an execution.
+    FuelConsumed,
+    /// `wasm_bench_set_event_callback`: emit custom events.
+    CustomEvents,
+    /// `wasm_bench_pass_timings`: report the time spent in each compiler pass.
+    PassTimings,
+    /// `wasm_bench_precompile` and `wasm_bench_deserialize`: compile a module ahead of time and
+    /// load it later.
+    Precompile,
+    /// `wasm_bench_execute_entry`: execute a benchmark's named entry point rather than `_start`.
+    EntryPoints,
+    /// `wasm_bench_build_info`: report how the engine was built (e.g., its version and build
+    /// profile).
+    BuildInfo,
+    /// `wasm_bench_set_executions`: execute each instance several times, to measure steady-state
+    /// execution.
+    RepeatedExecution,
+    /// `wasm_bench_compile_component`: compile a component rather than a core module, and
+    /// instantiate and execute it through the component model.
+    Components,
+    /// `wasm_bench_set_threads`: enable shared memories and let the benchmark spawn threads (e.g.,
+    /// through `wasi-threads`), telling it how many to use.
+    Threads,
+    /// `wasm_bench_proposals`: report which Wasm proposals the engine, with its flags, supports.
+    Proposals,
+}
+
+impl Capability {
+    /// All of the capabilities this recorder knows about.
+    pub const ALL: [Capability; 11] = [
+        Capability::CodeSize,
+        Capability::FuelConsumed,
+        Capability::CustomEvents,
+        Capability::PassTimings,
+        Capability::Precompile,
+        Capability::EntryPoints,
+        Capability::BuildInfo,
+        Capability::RepeatedExecution,
+        Capability::Components,
+        Capability::Threads,
+        Capability::Proposals,
+    ];
+
+    /// The bit that represents this capability in the result of `wasm_bench_capabilities`.
+    pub fn bit(self) -> u64 {
+        1 << self as u32
+    }
+
+    /// The function an engine exports to implement this capability.
+    fn symbol(self) -> &'static str {
+        match self {
+            Capability::CodeSize => "wasm_bench_code_size",
+            Capability::FuelConsumed => "wasm_bench_fuel_consumed",
+            Capability::CustomEvents => "wasm_bench_set_event_callback",
+            Capability::PassTimings => "wasm_bench_pass_timings",
+            Capability::Precompile => "wasm_bench_precompile",
+            Capability::EntryPoints => "wasm_bench_execute_entry",
+            Capability::BuildInfo => "wasm_bench_build_info",
+            Capability::RepeatedExecution => "wasm_bench_set_executions",
+            Capability::Components => "wasm_bench_compile_component",
+            Capability::Threads => "wasm_bench_set_threads",
+            Capability::Proposals => "wasm_bench_proposals",
+        }
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::CodeSize => write!(f, "code-size"),
+            Capability::FuelConsumed => write!(f, "fuel-consumed"),
+            Capability::CustomEvents => write!(f, "custom-events"),
+            Capability::PassTimings => write!(f, "pass-timings"),
+            Capability::Precompile => write!(f, "precompile"),
+            Capability::EntryPoints => write!(f, "entry-points"),
+            Capability::BuildInfo => write!(f, "build-info"),
+            Capability::RepeatedExecution => write!(f, "repeated-execution"),
+            Capability::Components => write!(f, "components"),
+            Capability::Threads => write!(f, "threads"),
+            Capability::Proposals => write!(f, "proposals"),
+        }
+    }
+}
+
 /// An shared library that implements our in-process benchmarking API.
 pub struct BenchApi<'a> {
+    /// The version of the bench API that the engine implements.
+    version: u32,
     wasm_bench_create:
         libloading::Symbol<'a, unsafe extern "C" fn(WasmBenchConfig, *mut *mut c_void) -> i32>,
     wasm_bench_free: libloading::Symbol<'a, unsafe extern "C" fn(*const c_void)>,
@@ -46,8 +143,78 @@ pub struct BenchApi<'a> {
         libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *const u8, usize) -> i32>,
     wasm_bench_instantiate: libloading::Symbol<'a, unsafe extern "C" fn(*const c_void) -> i32>,
     wasm_bench_execute: libloading::Symbol<'a, unsafe extern "C" fn(*const c_void) -> i32>,
+    /// Optional: report the size in bytes of the compiled module's machine code.
+    wasm_bench_code_size:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *mut usize) -> i32>>,
+    /// Optional: report the fuel consumed by the last execution.
+    wasm_bench_fuel_consumed:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *mut u64) -> i32>>,
+    /// Optional: register a callback through which the engine emits its own events.
+    wasm_bench_set_event_callback:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*mut c_void, *mut u8, EventCallback)>>,
+    /// Optional: report the time spent in each compiler pass during the last compilation.
+    wasm_bench_pass_timings: Option<
+        libloading::Symbol<
+            'a,
+            unsafe extern "C" fn(*const c_void, *mut u8, PassTimingCallback) -> i32,
+        >,
+    >,
+    /// Optional: compile a module ahead of time, untimed, and load such a module.
+    wasm_bench_precompile: Option<(
+        libloading::Symbol<'a, PrecompileFn>,
+        libloading::Symbol<'a, DeserializeFn>,
+    )>,
+    /// Optional: execute a named entry point of the instance.
+    wasm_bench_execute_entry: Option<
+        libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *const u8, usize) -> i32>,
+    >,
+    /// Optional: report how the engine was built.
+    wasm_bench_build_info:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*mut u8, BuildInfoCallback)>>,
+    /// Optional: execute each instance several times.
+    wasm_bench_set_executions:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*mut c_void, u32) -> i32>>,
+    /// Optional: compile a component.
+    wasm_bench_compile_component: Option<
+        libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *const u8, usize) -> i32>,
+    >,
+    /// Optional: run threaded benchmarks.
+    wasm_bench_set_threads:
+        Option<libloading::Symbol<'a, unsafe extern "C" fn(*mut c_void, u32) -> i32>>,
+    /// Optional: report the supported Wasm proposals.
+    wasm_bench_proposals: Option<
+        libloading::Symbol<'a, unsafe extern "C" fn(*const c_void, *mut u8, ProposalCallback)>,
+    >,
 }
 
+/// The callback an engine calls to emit a custom event: `(data, phase, name_ptr, name_len,
+/// count)`, where `phase` is 0 for compilation, 1 for instantiation, 2 for execution, and 3 for
+/// deserialization and the name is UTF-8.
+type EventCallback = extern "C" fn(*mut u8, u32, *const u8, usize, u64);
+
+/// The callback an engine calls to report the time spent in one compiler pass: `(data, pass_ptr,
+/// pass_len, nanoseconds)`, where the pass name is UTF-8.
+type PassTimingCallback = extern "C" fn(*mut u8, *const u8, usize, u64);
+
+/// The callback an engine calls with the bytes of a module it compiled ahead of time: `(data,
+/// bytes_ptr, bytes_len)`.
+type PrecompileCallback = extern "C" fn(*mut u8, *const u8, usize);
+
+/// An engine's `wasm_bench_precompile(engine, wasm_ptr, wasm_len, data, callback) -> i32`.
+type PrecompileFn =
+    unsafe extern "C" fn(*const c_void, *const u8, usize, *mut u8, PrecompileCallback) -> i32;
+
+/// An engine's `wasm_bench_deserialize(engine, bytes_ptr, bytes_len) -> i32`.
+type DeserializeFn = unsafe extern "C" fn(*const c_void, *const u8, usize) -> i32;
+
+/// The callback an engine calls with the name of one Wasm proposal it supports: `(data, name_ptr,
+/// name_len)`, where the name is UTF-8 (e.g., `tail-call`; see [sightglass_build::Proposal]).
+type ProposalCallback = extern "C" fn(*mut u8, *const u8, usize);
+
+/// The callback an engine calls with one item of its build information: `(data, key_ptr, key_len,
+/// value_ptr, value_len)`, where both the key and the value are UTF-8.
+type BuildInfoCallback = extern "C" fn(*mut u8, *const u8, usize, *const u8, usize);
+
 impl<'a> BenchApi<'a> {
     /// Create a new `BenchApi` from the given shared library.
     ///
@@ -56,14 +223,262 @@ impl<'a> BenchApi<'a> {
     /// The given shared library must export bench API functions with the
     /// correct signatures, since we have no way to check that it gets them
     /// right.
+    ///
+    /// Besides the required functions, an engine may export
+    /// `wasm_bench_code_size(engine, *mut usize) -> i32` to report the size of
+    /// the code it compiled; this is recorded as a `code-size` event during the
+    /// compilation phase. Similarly, an engine running with fuel metering
+    /// enabled (e.g., Wasmtime with `--engine-flags=--consume-fuel`) may export
+    /// `wasm_bench_fuel_consumed(engine, *mut u64) -> i32` to report the fuel
+    /// consumed by each execution; this is recorded as a `fuel-consumed` event
+    /// during the execution phase. The engine should return a non-zero value if
+    /// fuel metering is not enabled, in which case no event is recorded.
+    ///
+    /// Finally, an engine may export `wasm_bench_set_event_callback(engine,
+    /// data, callback)` to receive a callback through which it can emit its
+    /// own named counters (e.g., `regalloc-spills`) during any phase; see
+    /// [EventCallback]. To break compilation down further, an engine may
+    /// export `wasm_bench_pass_timings(engine, data, callback) -> i32`, which
+    /// is called after each compilation and should call `callback` once per
+    /// compiler pass (e.g., Cranelift's register allocation) with the time
+    /// spent in it; each pass is recorded as a `pass-<name>-nanoseconds` event
+    /// in the compilation phase.
+    ///
+    /// To measure loading modules compiled ahead of time (e.g., Wasmtime's
+    /// `Module::deserialize`), an engine may export
+    /// `wasm_bench_precompile(engine, wasm_ptr, wasm_len, data, callback) ->
+    /// i32`, which compiles the Wasm and passes the serialized module to
+    /// `callback`, and `wasm_bench_deserialize(engine, bytes_ptr, bytes_len) ->
+    /// i32`, which loads such a module in place of `wasm_bench_compile`. The
+    /// latter is measured as the deserialization phase.
+    ///
+    /// For benchmarks with several entry points (see
+    /// [sightglass_build::entry_points]), an engine exports
+    /// `wasm_bench_execute_entry(engine, name_ptr, name_len) -> i32`, which
+    /// executes the instance like `wasm_bench_execute` but calls the exported
+    /// function `name` (UTF-8) rather than `_start`.
+    ///
+    /// To record which build of an engine produced a set of results, an engine
+    /// may export `wasm_bench_build_info(data, callback)`, which calls
+    /// `callback` once per item of build information; see [BuildInfoCallback]
+    /// and [BenchApi::build_info]. Its `ENGINE` item names the kind of engine
+    /// (see [engine_kind]).
+    ///
+    /// To separate warmup from steady-state execution, an engine may export
+    /// `wasm_bench_set_executions(engine, count) -> i32`, after which each
+    /// call to `wasm_bench_execute` (or `wasm_bench_execute_entry`) executes
+    /// the same instance `count` times in a row, calling the execution timer's
+    /// start and end functions around each execution; see
+    /// [Engine::set_executions].
+    ///
+    /// To benchmark components (see [sightglass_build::is_component]), an
+    /// engine exports `wasm_bench_compile_component(engine, wasm_ptr,
+    /// wasm_len) -> i32`, which is called in place of `wasm_bench_compile`;
+    /// the engine then instantiates the component with its component linker
+    /// in `wasm_bench_instantiate` and calls its `wasi:cli/run` export in
+    /// `wasm_bench_execute`.
+    ///
+    /// To benchmark Wasm using the threads proposal, an engine exports
+    /// `wasm_bench_set_threads(engine, count) -> i32`, which is called before
+    /// compilation; the engine then enables shared memories and atomics, lets
+    /// the benchmark spawn threads (e.g., through `wasi-threads`), and sets the
+    /// benchmark's `SIGHTGLASS_THREADS` environment variable to `count`, the
+    /// number of threads it should use; see [Engine::set_threads].
+    ///
+    /// So that benchmarks it cannot run are skipped rather than failing, an
+    /// engine may export `wasm_bench_proposals(engine, data, callback)`, which
+    /// calls `callback` with the name of each Wasm proposal that the engine,
+    /// configured with its flags, supports; see [Engine::proposals].
+    ///
+    /// Before any of this, the library is checked with a handshake: an engine
+    /// may export `wasm_bench_api_version() -> u32` to report the version of
+    /// the bench API it implements (see [BENCH_API_VERSION]) and
+    /// `wasm_bench_capabilities() -> u64` to report which of the optional
+    /// functions above it implements, as a set of [Capability] bits. An engine
+    /// implementing a newer version than this recorder is used through the
+    /// parts of the API that this recorder knows. Without
+    /// `wasm_bench_capabilities`, the capabilities are those whose functions
+    /// the library exports.
     pub unsafe fn new(lib: &'a libloading::Library) -> Result<Self> {
-        Ok(BenchApi {
-            wasm_bench_create: lib.get(b"wasm_bench_create")?,
-            wasm_bench_free: lib.get(b"wasm_bench_free")?,
-            wasm_bench_compile: lib.get(b"wasm_bench_compile")?,
-            wasm_bench_instantiate: lib.get(b"wasm_bench_instantiate")?,
-            wasm_bench_execute: lib.get(b"wasm_bench_execute")?,
-        })
+        let version = match lib.get::<unsafe extern "C" fn() -> u32>(b"wasm_bench_api_version") {
+            Ok(version) => version(),
+            Err(_) => 1,
+        };
+        anyhow::ensure!(
+            version > 0,
+            "the engine library reports an invalid bench API version: {}",
+            version
+        );
+        if version > BENCH_API_VERSION {
+            log::warn!(
+                "The engine implements version {} of the bench API but this version of Sightglass \
+                 only knows version {}; newer features of the engine will not be used",
+                version,
+                BENCH_API_VERSION
+            );
+        }
+
+        let declared = lib
+            .get::<unsafe extern "C" fn() -> u64>(b"wasm_bench_capabilities")
+            .ok()
+            .map(|capabilities| capabilities());
+        if let Some(declared) = declared {
+            let known = Capability::ALL.iter().fold(0, |bits, c| bits | c.bit());
+            if declared & !known != 0 {
+                log::debug!(
+                    "Ignoring unknown bench API capabilities: {:#x}",
+                    declared & !known
+                );
+            }
+        }
+
+        let bench_api = BenchApi {
+            version,
+            wasm_bench_create: required(lib, "wasm_bench_create", version)?,
+            wasm_bench_free: required(lib, "wasm_bench_free", version)?,
+            wasm_bench_compile: required(lib, "wasm_bench_compile", version)?,
+            wasm_bench_instantiate: required(lib, "wasm_bench_instantiate", version)?,
+            wasm_bench_execute: required(lib, "wasm_bench_execute", version)?,
+            wasm_bench_code_size: optional(lib, Capability::CodeSize, declared),
+            wasm_bench_fuel_consumed: optional(lib, Capability::FuelConsumed, declared),
+            wasm_bench_set_event_callback: optional(lib, Capability::CustomEvents, declared),
+            wasm_bench_pass_timings: optional(lib, Capability::PassTimings, declared),
+            wasm_bench_precompile: optional(lib, Capability::Precompile, declared).and_then(
+                |precompile| match lib.get(b"wasm_bench_deserialize") {
+                    Ok(deserialize) => Some((precompile, deserialize)),
+                    Err(_) => {
+                        log::warn!(
+                            "The engine exports `wasm_bench_precompile` but not \
+                             `wasm_bench_deserialize`; ignoring it"
+                        );
+                        None
+                    }
+                },
+            ),
+            wasm_bench_execute_entry: optional(lib, Capability::EntryPoints, declared),
+            wasm_bench_build_info: optional(lib, Capability::BuildInfo, declared),
+            wasm_bench_set_executions: optional(lib, Capability::RepeatedExecution, declared),
+            wasm_bench_compile_component: optional(lib, Capability::Components, declared),
+            wasm_bench_set_threads: optional(lib, Capability::Threads, declared),
+            wasm_bench_proposals: optional(lib, Capability::Proposals, declared),
+        };
+        log::info!(
+            "Engine implements bench API version {} with capabilities: [{}]",
+            bench_api.version(),
+            bench_api
+                .capabilities()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        Ok(bench_api)
+    }
+
+    /// The version of the bench API that the engine implements.
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// The optional parts of the bench API that the engine implements and that will be used.
+    pub fn capabilities(&self) -> Vec<Capability> {
+        Capability::ALL
+            .iter()
+            .copied()
+            .filter(|c| match c {
+                Capability::CodeSize => self.wasm_bench_code_size.is_some(),
+                Capability::FuelConsumed => self.wasm_bench_fuel_consumed.is_some(),
+                Capability::CustomEvents => self.wasm_bench_set_event_callback.is_some(),
+                Capability::PassTimings => self.wasm_bench_pass_timings.is_some(),
+                Capability::Precompile => self.wasm_bench_precompile.is_some(),
+                Capability::EntryPoints => self.wasm_bench_execute_entry.is_some(),
+                Capability::BuildInfo => self.wasm_bench_build_info.is_some(),
+                Capability::RepeatedExecution => self.wasm_bench_set_executions.is_some(),
+                Capability::Components => self.wasm_bench_compile_component.is_some(),
+                Capability::Threads => self.wasm_bench_set_threads.is_some(),
+                Capability::Proposals => self.wasm_bench_proposals.is_some(),
+            })
+            .collect()
+    }
+
+    /// How the engine was built, as reported by the engine: e.g., `WASMTIME_VERSION`,
+    /// `CRANELIFT_VERSION`, `PROFILE` (`release` or `debug`), and `TARGET_FEATURES`. This is empty
+    /// if the engine does not report it.
+    pub fn build_info(&self) -> Vec<(String, String)> {
+        let mut build_info = vec![];
+        if let Some(report) = &self.wasm_bench_build_info {
+            unsafe { report(&mut build_info as *mut _ as *mut u8, build_info_item) };
+        }
+        build_info
+    }
+}
+
+/// The build information item naming the kind of engine: the runtime its library embeds.
+pub const ENGINE_KIND: &str = "ENGINE";
+
+/// The kind of engine that reported the `build_info` (see [BenchApi::build_info]): the runtime its
+/// library embeds, e.g., `wasmer` or `wamr`, as its `ENGINE` item names it. Wasmtime's own bench
+/// API predates the item, so an engine reporting a `WASMTIME_VERSION` instead is `wasmtime`.
+pub fn engine_kind(build_info: &[(String, String)]) -> Option<String> {
+    let find = |key: &str| build_info.iter().find(|(k, _)| k == key);
+    match (find(ENGINE_KIND), find("WASMTIME_VERSION")) {
+        (Some((_, kind)), _) => Some(kind.clone()),
+        (None, Some(_)) => Some("wasmtime".into()),
+        (None, None) => None,
+    }
+}
+
+/// Bench API callback for one item of the engine's build information.
+extern "C" fn build_info_item(
+    data: *mut u8,
+    key_ptr: *const u8,
+    key_len: usize,
+    value_ptr: *const u8,
+    value_len: usize,
+) {
+    let build_info = unsafe { (data as *mut Vec<(String, String)>).as_mut().unwrap() };
+    let key = unsafe { std::slice::from_raw_parts(key_ptr, key_len) };
+    let value = unsafe { std::slice::from_raw_parts(value_ptr, value_len) };
+    build_info.push((
+        String::from_utf8_lossy(key).into_owned(),
+        String::from_utf8_lossy(value).into_owned(),
+    ));
+}
+
+/// Look up a function that every engine implementing `version` of the bench API must export.
+unsafe fn required<'a, T>(
+    lib: &'a libloading::Library,
+    symbol: &str,
+    version: u32,
+) -> Result<libloading::Symbol<'a, T>> {
+    lib.get(symbol.as_bytes()).with_context(|| {
+        format!(
+            "the engine library does not export `{}`, which is required by version {} of the \
+             bench API; is it a Sightglass engine (see `engines/`)?",
+            symbol, version
+        )
+    })
+}
+
+/// Look up the function implementing an optional `capability`; `declared` holds the capabilities
+/// the engine reported through `wasm_bench_capabilities`, if it exports it.
+unsafe fn optional<'a, T>(
+    lib: &'a libloading::Library,
+    capability: Capability,
+    declared: Option<u64>,
+) -> Option<libloading::Symbol<'a, T>> {
+    let function = lib.get(capability.symbol().as_bytes()).ok();
+    match declared {
+        Some(declared) if declared & capability.bit() == 0 => None,
+        Some(_) if function.is_none() => {
+            log::warn!(
+                "The engine declares the {} capability but does not export `{}`; ignoring it",
+                capability,
+                capability.symbol()
+            );
+            None
+        }
+        _ => function,
     }
 }
 
@@ -124,8 +539,11 @@ where
         let mut engine = ptr::null_mut();
         unsafe {
             let result = (bench_api.wasm_bench_create)(config, &mut engine);
-            assert_eq!(result, 0);
+            assert!(result == 0, "the engine failed to start");
             assert!(!engine.is_null());
+            if let Some(set_event_callback) = &bench_api.wasm_bench_set_event_callback {
+                set_event_callback(engine, measurement_data as *mut u8, Self::custom_event);
+            }
         };
         Engine {
             bench_api,
@@ -134,11 +552,53 @@ where
         }
     }
 
-    /// Compile the Wasm into a module.
+    /// Compile the Wasm into a module (or, if it is a component, into a
+    /// component).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Wasm is a component and the engine does not have the
+    /// [Capability::Components] capability.
     pub fn compile(self, wasm: &[u8]) -> Module<'a, 'b, 'c, M> {
-        let result =
-            unsafe { (self.bench_api.wasm_bench_compile)(self.engine, wasm.as_ptr(), wasm.len()) };
-        assert_eq!(result, 0);
+        let compile = if sightglass_build::is_component(wasm) {
+            self.bench_api
+                .wasm_bench_compile_component
+                .as_ref()
+                .expect("the engine cannot compile components")
+        } else {
+            &self.bench_api.wasm_bench_compile
+        };
+        let result = unsafe { compile(self.engine, wasm.as_ptr(), wasm.len()) };
+        assert!(result == 0, "the engine failed to compile the module");
+        self.record_code_size();
+        self.record_pass_timings();
+        Module { engine: self }
+    }
+
+    /// Load a module compiled ahead of time by [Engine::precompile], measuring
+    /// this as the deserialization phase.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the engine does not have the [Capability::Precompile]
+    /// capability.
+    pub fn deserialize(self, bytes: &[u8]) -> Module<'a, 'b, 'c, M> {
+        let (_, deserialize) = self
+            .bench_api
+            .wasm_bench_precompile
+            .as_ref()
+            .expect("the engine cannot load precompiled modules");
+        let data = self.measurement_data as *mut (*mut M, *mut Measurements<'c>);
+        let (measure, measurements) = unsafe {
+            let data = data.as_mut().unwrap();
+            (data.0.as_mut().unwrap(), data.1.as_mut().unwrap())
+        };
+        log::debug!("Starting deserialization measurement");
+        measure.start(Phase::Deserialization);
+        let result = unsafe { deserialize(self.engine, bytes.as_ptr(), bytes.len()) };
+        measure.end(Phase::Deserialization, measurements);
+        log::debug!("Finished measuring deserialization");
+        assert!(result == 0, "the engine failed to deserialize the module");
         Module { engine: self }
     }
 
@@ -162,7 +622,11 @@ where
     extern "C" fn execution_start(data: *mut u8) {
         log::debug!("Starting execution measurement");
         let data = data as *mut (*mut M, *mut Measurements<'b>);
-        let measure = unsafe { data.as_mut().unwrap().0.as_mut().unwrap() };
+        let (measure, measurements) = unsafe {
+            let data = data.as_mut().unwrap();
+            (data.0.as_mut().unwrap(), data.1.as_mut().unwrap())
+        };
+        measurements.next_execution();
         measure.start(Phase::Execution);
     }
 
@@ -200,6 +664,173 @@ where
     }
 }
 
+impl<'a, 'b, 'c, M> Engine<'a, 'b, 'c, M> {
+    /// Compile the Wasm ahead of time, without measuring it, returning the
+    /// module's serialized bytes for [Engine::deserialize]; this fails if the
+    /// engine does not have the [Capability::Precompile] capability.
+    pub fn precompile(&self, wasm: &[u8]) -> Result<Vec<u8>> {
+        let (precompile, _) = self
+            .bench_api
+            .wasm_bench_precompile
+            .as_ref()
+            .context("the engine cannot precompile modules")?;
+        let mut bytes: Vec<u8> = vec![];
+        let result = unsafe {
+            precompile(
+                self.engine,
+                wasm.as_ptr(),
+                wasm.len(),
+                &mut bytes as *mut Vec<u8> as *mut u8,
+                Self::precompiled,
+            )
+        };
+        anyhow::ensure!(result == 0, "the engine failed to precompile the module");
+        Ok(bytes)
+    }
+
+    /// Execute each instance `count` times per [Instance::execute] (or
+    /// [Instance::execute_entry]) rather than once; this fails if the engine
+    /// does not have the [Capability::RepeatedExecution] capability.
+    pub fn set_executions(&self, count: u32) -> Result<()> {
+        let set_executions = self
+            .bench_api
+            .wasm_bench_set_executions
+            .as_ref()
+            .context("the engine cannot execute an instance repeatedly")?;
+        let result = unsafe { set_executions(self.engine, count) };
+        anyhow::ensure!(
+            result == 0,
+            "the engine failed to set the number of executions"
+        );
+        Ok(())
+    }
+
+    /// Let the benchmark run with `count` threads, enabling shared memories;
+    /// this must be called before compilation, and fails if the engine does
+    /// not have the [Capability::Threads] capability.
+    pub fn set_threads(&self, count: u32) -> Result<()> {
+        let set_threads = self
+            .bench_api
+            .wasm_bench_set_threads
+            .as_ref()
+            .context("the engine cannot run threaded benchmarks")?;
+        let result = unsafe { set_threads(self.engine, count) };
+        anyhow::ensure!(
+            result == 0,
+            "the engine failed to set the number of threads"
+        );
+        Ok(())
+    }
+
+    /// The Wasm proposals that the engine, configured with its flags,
+    /// supports (e.g., `simd` and `tail-call`), or `None` if the engine does
+    /// not have the [Capability::Proposals] capability.
+    pub fn proposals(&self) -> Option<Vec<String>> {
+        let report = self.bench_api.wasm_bench_proposals.as_ref()?;
+        let mut proposals = vec![];
+        unsafe {
+            report(
+                self.engine,
+                &mut proposals as *mut Vec<String> as *mut u8,
+                Self::proposal,
+            )
+        };
+        Some(proposals)
+    }
+
+    /// Bench API callback for the name of a supported proposal.
+    extern "C" fn proposal(data: *mut u8, name_ptr: *const u8, name_len: usize) {
+        let proposals = unsafe { (data as *mut Vec<String>).as_mut().unwrap() };
+        let name = unsafe { std::slice::from_raw_parts(name_ptr, name_len) };
+        proposals.push(String::from_utf8_lossy(name).into_owned());
+    }
+
+    /// Bench API callback for the bytes of a precompiled module.
+    extern "C" fn precompiled(data: *mut u8, bytes_ptr: *const u8, bytes_len: usize) {
+        let bytes = unsafe { (data as *mut Vec<u8>).as_mut().unwrap() };
+        bytes.extend_from_slice(unsafe { std::slice::from_raw_parts(bytes_ptr, bytes_len) });
+    }
+
+    /// Record the size of the compiled code, if the engine is able to report it.
+    fn record_code_size(&self) {
+        if let Some(code_size) = &self.bench_api.wasm_bench_code_size {
+            let mut size = 0;
+            let result = unsafe { code_size(self.engine, &mut size) };
+            assert_eq!(result, 0);
+            let data = self.measurement_data as *mut (*mut M, *mut Measurements<'c>);
+            let measurements = unsafe { data.as_mut().unwrap().1.as_mut().unwrap() };
+            measurements.add(Phase::Compilation, "code-size".into(), size as u64);
+        }
+    }
+
+    /// Record the time spent in each compiler pass, if the engine is able to report it.
+    fn record_pass_timings(&self) {
+        if let Some(pass_timings) = &self.bench_api.wasm_bench_pass_timings {
+            let result = unsafe {
+                pass_timings(
+                    self.engine,
+                    self.measurement_data as *mut u8,
+                    Self::pass_timing,
+                )
+            };
+            assert_eq!(result, 0);
+        }
+    }
+
+    /// Bench API callback for the time spent in a single compiler pass.
+    extern "C" fn pass_timing(data: *mut u8, pass_ptr: *const u8, pass_len: usize, nanos: u64) {
+        let pass = unsafe { std::slice::from_raw_parts(pass_ptr, pass_len) };
+        let event = pass_event_name(&String::from_utf8_lossy(pass));
+        let data = data as *mut (*mut M, *mut Measurements<'c>);
+        let measurements = unsafe { data.as_mut().unwrap().1.as_mut().unwrap() };
+        measurements.add(Phase::Compilation, event.into(), nanos);
+    }
+
+    /// Record the fuel consumed by the last execution, if the engine is metering fuel.
+    fn record_fuel_consumed(&self) {
+        if let Some(fuel_consumed) = &self.bench_api.wasm_bench_fuel_consumed {
+            let mut fuel = 0;
+            if unsafe { fuel_consumed(self.engine, &mut fuel) } == 0 {
+                let data = self.measurement_data as *mut (*mut M, *mut Measurements<'c>);
+                let measurements = unsafe { data.as_mut().unwrap().1.as_mut().unwrap() };
+                measurements.add(Phase::Execution, "fuel-consumed".into(), fuel);
+            }
+        }
+    }
+
+    /// Bench API callback for a custom event emitted by the engine.
+    extern "C" fn custom_event(
+        data: *mut u8,
+        phase: u32,
+        name_ptr: *const u8,
+        name_len: usize,
+        count: u64,
+    ) {
+        let phase = match phase {
+            0 => Phase::Compilation,
+            1 => Phase::Instantiation,
+            2 => Phase::Execution,
+            3 => Phase::Deserialization,
+            _ => {
+                // Panicking cannot unwind out of this callback, so the event is dropped instead.
+                let name = unsafe { std::slice::from_raw_parts(name_ptr, name_len) };
+                log::warn!(
+                    "Dropping the engine's event {} for an unknown phase: {}",
+                    String::from_utf8_lossy(name),
+                    phase
+                );
+                return;
+            }
+        };
+        let name = unsafe { std::slice::from_raw_parts(name_ptr, name_len) };
+        let name = String::from_utf8_lossy(name).into_owned();
+        log::debug!("Engine emitted event {} = {} ({})", name, count, phase);
+        let data = data as *mut (*mut M, *mut Measurements<'c>);
+        let measurements = unsafe { data.as_mut().unwrap().1.as_mut().unwrap() };
+        measurements.add(phase, name.into(), count);
+    }
+}
+
 impl<'a, 'b, 'c, M> Drop for Engine<'a, 'b, 'c, M> {
     fn drop(&mut self) {
         unsafe {
@@ -218,7 +849,7 @@ impl<'a, 'b, 'c, M> Module<'a, 'b, 'c, M> {
     /// Instantiate this module, returning the resulting `Instance`.
     pub fn instantiate(self) -> Instance<'a, 'b, 'c, M> {
         let result = unsafe { (self.engine.bench_api.wasm_bench_instantiate)(self.engine.engine) };
-        assert_eq!(result, 0);
+        assert!(result == 0, "the engine failed to instantiate the module");
         Instance {
             engine: self.engine,
         }
@@ -247,9 +878,119 @@ impl<'a, 'b, 'c, M> Instance<'a, 'b, 'c, M> {
     /// be instantiated and executed again.
     pub fn execute(self) -> Module<'a, 'b, 'c, M> {
         let result = unsafe { (self.engine.bench_api.wasm_bench_execute)(self.engine.engine) };
-        assert_eq!(result, 0);
+        assert!(result == 0, "the engine failed to execute the instance");
+        self.engine.record_fuel_consumed();
         Module {
             engine: self.engine,
         }
     }
+
+    /// Like [Instance::execute], but call the exported function `entry_point`
+    /// rather than `_start`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the engine does not have the [Capability::EntryPoints]
+    /// capability.
+    pub fn execute_entry(self, entry_point: &str) -> Module<'a, 'b, 'c, M> {
+        let execute_entry = self
+            .engine
+            .bench_api
+            .wasm_bench_execute_entry
+            .as_ref()
+            .expect("the engine cannot execute entry points");
+        let result =
+            unsafe { execute_entry(self.engine.engine, entry_point.as_ptr(), entry_point.len()) };
+        assert!(
+            result == 0,
+            "the engine failed to execute the instance's entry point"
+        );
+        self.engine.record_fuel_consumed();
+        Module {
+            engine: self.engine,
+        }
+    }
+}
+
+/// Name the event for a compiler pass, e.g., `pass-vcode-lowering-nanoseconds` for Cranelift's
+/// "VCode lowering" pass.
+fn pass_event_name(pass: &str) -> String {
+    let pass: Vec<_> = pass
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    format!("pass-{}-nanoseconds", pass.join("-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capability_bits_are_distinct() {
+        let bits = Capability::ALL.iter().fold(0, |bits, c| {
+            assert_eq!(bits & c.bit(), 0, "{} shares a bit", c);
+            bits | c.bit()
+        });
+        assert_eq!(bits, 0b11111111111);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn reject_libraries_without_the_bench_api() {
+        let lib = unsafe { libloading::Library::new("libc.so.6") }.unwrap();
+        let error = unsafe { BenchApi::new(&lib) }.err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("does not export `wasm_bench_create`"));
+    }
+
+    #[test]
+    fn name_pass_events() {
+        assert_eq!(pass_event_name("regalloc"), "pass-regalloc-nanoseconds");
+        assert_eq!(
+            pass_event_name("VCode lowering"),
+            "pass-vcode-lowering-nanoseconds"
+        );
+        assert_eq!(
+            pass_event_name("Remove constant phi-nodes"),
+            "pass-remove-constant-phi-nodes-nanoseconds"
+        );
+    }
+
+    #[test]
+    fn kinds_of_engines() {
+        let build_info = |items: &[(&str, &str)]| -> Vec<(String, String)> {
+            items
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            engine_kind(&build_info(&[("ENGINE", "wasmer"), ("PROFILE", "release")])),
+            Some("wasmer".into())
+        );
+        assert_eq!(
+            engine_kind(&build_info(&[("WASMTIME_VERSION", "30.0.0")])),
+            Some("wasmtime".into())
+        );
+        assert_eq!(engine_kind(&build_info(&[("PROFILE", "release")])), None);
+    }
+
+    #[test]
+    fn drop_events_of_unknown_phases() {
+        let mut measurements = Measurements::new("x86_64", "engine.so", "benchmark.wasm");
+        let mut data = (std::ptr::null_mut::<()>(), &mut measurements as *mut _);
+        let data = &mut data as *mut _ as *mut u8;
+        let name = "gc-count";
+        Engine::<()>::custom_event(data, 7, name.as_ptr(), name.len(), 1);
+        Engine::<()>::custom_event(data, 2, name.as_ptr(), name.len(), 2);
+        let events: Vec<_> = measurements
+            .finish()
+            .into_iter()
+            .map(|m| (m.phase, m.count))
+            .collect();
+        assert_eq!(events, [(Phase::Execution, 2)]);
+    }
 }
@@ -1,34 +1,83 @@
 use crate::bench_api::{BenchApi, Engine};
+use crate::measure::noop::NoopMeasure;
 use crate::measure::{Measure, Measurements};
 use anyhow::Result;
 use log::info;
 use sightglass_data::Phase;
 use std::path::Path;
 
-/// Measure various phases of a Wasm module's lifetime.
-///
-/// Provide paths to files created for logging the Wasm's `stdout` and `stderr`
-/// and (optionally) a file read and piped into the Wasm execution as `stdin`.
-///
-/// Optionally stop after the given `stop_after_phase`, rather than running all
-/// phases.
+/// How to run a benchmark; see [benchmark].
+#[derive(Clone, Copy, Debug)]
+pub struct BenchmarkOptions<'a> {
+    /// The directory the benchmark runs in.
+    pub working_dir: &'a Path,
+    /// The files created for logging the benchmark's `stdout` and `stderr`.
+    pub stdout_path: &'a Path,
+    pub stderr_path: &'a Path,
+    /// A file read and piped into the benchmark's execution as `stdin`, if any.
+    pub stdin_path: Option<&'a Path>,
+    /// Whether the Wasm is a module returned by [precompile], whose deserialization is measured in
+    /// place of compilation.
+    pub precompiled: bool,
+    /// The exported function to execute rather than `_start`.
+    pub entry_point: Option<&'a str>,
+    /// How many times to execute the instance in a row; if more than one, the measurements of each
+    /// execution are recorded apart (see [Measurements::set_execution]), so that warmup can be
+    /// told apart from steady-state execution.
+    pub executions: u32,
+    /// How many threads the engine runs the benchmark with, if set; see [Engine::set_threads].
+    pub threads: Option<u32>,
+    /// The phase to stop after, rather than running all phases.
+    pub stop_after_phase: Option<Phase>,
+    /// The flags to configure the engine with.
+    pub execution_flags: Option<&'a str>,
+}
+
+impl<'a> BenchmarkOptions<'a> {
+    /// Run a benchmark in `working_dir`, logging its output to `stdout_path` and `stderr_path`,
+    /// through all phases with the engine's defaults.
+    pub fn new(working_dir: &'a Path, stdout_path: &'a Path, stderr_path: &'a Path) -> Self {
+        Self {
+            working_dir,
+            stdout_path,
+            stderr_path,
+            stdin_path: None,
+            precompiled: false,
+            entry_point: None,
+            executions: 1,
+            threads: None,
+            stop_after_phase: None,
+            execution_flags: None,
+        }
+    }
+}
+
+/// Measure various phases of a Wasm module's lifetime, run as the `options` say.
 pub fn benchmark<'a, 'b, 'c>(
     bench_api: &'a mut BenchApi<'b>,
-    working_dir: &Path,
-    stdout_path: &Path,
-    stderr_path: &Path,
-    stdin_path: Option<&Path>,
     wasm_bytes: &[u8],
-    stop_after_phase: Option<Phase>,
-    execution_flags: Option<&str>,
+    options: &BenchmarkOptions<'_>,
     measure: &'a mut impl Measure,
     measurements: &'a mut Measurements<'c>,
 ) -> Result<()> {
+    let BenchmarkOptions {
+        working_dir,
+        stdout_path,
+        stderr_path,
+        stdin_path,
+        precompiled,
+        entry_point,
+        executions,
+        threads,
+        stop_after_phase,
+        execution_flags,
+    } = *options;
     #[cfg(target_os = "linux")]
     info!("Benchmark scheduled on CPU: {}", unsafe {
         libc::sched_getcpu()
     });
 
+    measurements.set_execution(if executions > 1 { Some(0) } else { None });
     let engine = Engine::new(
         bench_api,
         working_dir,
@@ -39,12 +88,28 @@ pub fn benchmark<'a, 'b, 'c>(
         measure,
         execution_flags,
     );
+    if executions > 1 {
+        engine.set_executions(executions)?;
+    }
+    if let Some(threads) = threads {
+        engine.set_threads(threads)?;
+    }
 
-    // Measure the module compilation.
-    let module = engine.compile(wasm_bytes);
-    info!("Compiled successfully");
+    // Measure the module compilation (or deserialization).
+    let module = if precompiled {
+        let module = engine.deserialize(wasm_bytes);
+        info!("Deserialized successfully");
+        module
+    } else {
+        let module = engine.compile(wasm_bytes);
+        info!("Compiled successfully");
+        module
+    };
 
-    if stop_after_phase == Some(Phase::Compilation) {
+    if matches!(
+        stop_after_phase,
+        Some(Phase::Compilation | Phase::Deserialization)
+    ) {
         return Ok(());
     }
 
@@ -56,8 +121,64 @@ pub fn benchmark<'a, 'b, 'c>(
         return Ok(());
     }
 
-    instance.execute();
+    match entry_point {
+        Some(entry_point) => instance.execute_entry(entry_point),
+        None => instance.execute(),
+    };
     info!("Executed successfully");
 
     Ok(())
 }
+
+/// Compile a Wasm module ahead of time, without measuring it, for later use by
+/// [benchmark] with `precompiled` set (and the same `threads`).
+pub fn precompile(
+    bench_api: &mut BenchApi<'_>,
+    working_dir: &Path,
+    wasm_bytes: &[u8],
+    threads: Option<u32>,
+    execution_flags: Option<&str>,
+) -> Result<Vec<u8>> {
+    // The engine is never run, so its output goes nowhere.
+    let null = Path::new(if cfg!(windows) { "NUL" } else { "/dev/null" });
+    let mut measure = NoopMeasure::new();
+    let mut measurements = Measurements::new("", "", "");
+    let engine = Engine::new(
+        bench_api,
+        working_dir,
+        null,
+        null,
+        None,
+        &mut measurements,
+        &mut measure,
+        execution_flags,
+    );
+    if let Some(threads) = threads {
+        engine.set_threads(threads)?;
+    }
+    engine.precompile(wasm_bytes)
+}
+
+/// Ask the engine, configured with `execution_flags`, which Wasm proposals it
+/// supports; see [Engine::proposals].
+pub fn supported_proposals(
+    bench_api: &mut BenchApi<'_>,
+    working_dir: &Path,
+    execution_flags: Option<&str>,
+) -> Option<Vec<String>> {
+    // The engine is never run, so its output goes nowhere.
+    let null = Path::new(if cfg!(windows) { "NUL" } else { "/dev/null" });
+    let mut measure = NoopMeasure::new();
+    let mut measurements = Measurements::new("", "", "");
+    let engine = Engine::new(
+        bench_api,
+        working_dir,
+        null,
+        null,
+        None,
+        &mut measurements,
+        &mut measure,
+        execution_flags,
+    );
+    engine.proposals()
+}
@@ -0,0 +1,170 @@
+//! Calibrate a [Measure] by measuring its own overhead.
+//!
+//! Starting and ending a measurement is not free: reading a counter, crossing into the kernel, and
+//! the bench API callbacks themselves are all counted as part of each phase. For phases that run
+//! for well under a millisecond this overhead can be a visible fraction of the result. To estimate
+//! it, we measure empty regions (a `start` immediately followed by an `end`) several times and keep
+//! the minimum of each event; using the minimum means we never subtract more than the overhead.
+use crate::measure::{Measure, Measurements};
+use sightglass_data::{Measurement, Phase};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+/// The number of empty regions measured, per phase, to estimate the overhead.
+pub const CALIBRATION_ITERATIONS: u32 = 100;
+
+/// What to do with the measured overhead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverheadMode {
+    /// Do not measure the overhead.
+    Ignore,
+    /// Record the overhead of each event as a separate `<event>-overhead` event.
+    Record,
+    /// Subtract the overhead from each measurement.
+    Subtract,
+}
+
+impl fmt::Display for OverheadMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OverheadMode::Ignore => write!(f, "ignore"),
+            OverheadMode::Record => write!(f, "record"),
+            OverheadMode::Subtract => write!(f, "subtract"),
+        }
+    }
+}
+
+impl FromStr for OverheadMode {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ignore" => Ok(Self::Ignore),
+            "record" => Ok(Self::Record),
+            "subtract" => Ok(Self::Subtract),
+            _ => Err("unknown overhead mode; expected ignore, record, or subtract"),
+        }
+    }
+}
+
+/// The estimated overhead of a [Measure], per phase and event.
+#[derive(Debug, Default)]
+pub struct Overhead {
+    counts: HashMap<(Phase, String), u64>,
+}
+
+impl Overhead {
+    /// Estimate the overhead of `measure` by measuring `iterations` empty regions for each phase.
+    /// With `precompiled` modules, the deserialization phase is measured in place of compilation.
+    pub fn measure(measure: &mut impl Measure, iterations: u32, precompiled: bool) -> Self {
+        let mut measurements = Measurements::new("", "", "");
+        let loading = if precompiled {
+            Phase::Deserialization
+        } else {
+            Phase::Compilation
+        };
+        for _ in 0..iterations {
+            for phase in [loading, Phase::Instantiation, Phase::Execution] {
+                measure.start(phase);
+                measure.end(phase, &mut measurements);
+            }
+        }
+        Self::from_measurements(&measurements.finish())
+    }
+
+    fn from_measurements(measurements: &[Measurement]) -> Self {
+        let mut counts = HashMap::new();
+        for m in measurements {
+            counts
+                .entry((m.phase, m.event.to_string()))
+                .and_modify(|c: &mut u64| *c = (*c).min(m.count))
+                .or_insert(m.count);
+        }
+        log::debug!("Measured overhead: {:?}", counts);
+        Self { counts }
+    }
+
+    /// Record the overhead of each event as an `<event>-overhead` event.
+    pub fn record(&self, measurements: &mut Measurements) {
+        let mut counts: Vec<_> = self.counts.iter().collect();
+        counts.sort();
+        measurements.reserve(counts.len());
+        for ((phase, event), count) in counts {
+            measurements.add(*phase, format!("{}-overhead", event).into(), *count);
+        }
+    }
+
+    /// Subtract the overhead from each of the `measurements`, saturating at zero.
+    pub fn subtract(&self, measurements: &mut [Measurement]) {
+        for m in measurements {
+            if let Some(overhead) = self.counts.get(&(m.phase, m.event.to_string())) {
+                m.count = m.count.saturating_sub(*overhead);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake measure that counts the number of times it has been ended.
+    struct CountingMeasure(u64);
+    impl Measure for CountingMeasure {
+        fn start(&mut self, _phase: Phase) {}
+        fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+            self.0 += 1;
+            measurements.add(phase, "ends".into(), self.0);
+        }
+    }
+
+    #[test]
+    fn keep_minimum_overhead() {
+        let overhead = Overhead::measure(&mut CountingMeasure(10), 5, false);
+        assert_eq!(overhead.counts[&(Phase::Compilation, "ends".into())], 11);
+        assert_eq!(overhead.counts[&(Phase::Execution, "ends".into())], 13);
+        assert!(!overhead
+            .counts
+            .contains_key(&(Phase::Deserialization, "ends".into())));
+    }
+
+    #[test]
+    fn precompiled_overhead() {
+        let overhead = Overhead::measure(&mut CountingMeasure(10), 5, true);
+        assert_eq!(
+            overhead.counts[&(Phase::Deserialization, "ends".into())],
+            11
+        );
+        assert!(!overhead
+            .counts
+            .contains_key(&(Phase::Compilation, "ends".into())));
+
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        measurements.add(Phase::Deserialization, "ends".into(), 100);
+        let mut measurements = measurements.finish();
+        overhead.subtract(&mut measurements);
+        assert_eq!(measurements[0].count, 89);
+    }
+
+    #[test]
+    fn record_and_subtract_overhead() {
+        let overhead = Overhead::measure(&mut CountingMeasure(10), 1, false);
+
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        overhead.record(&mut measurements);
+        let mut measurements = measurements.finish();
+        let events: Vec<_> = measurements.iter().map(|m| m.event.as_ref()).collect();
+        assert_eq!(events, ["ends-overhead"; 3]);
+
+        let mut measurements2 = Measurements::new("arch", "engine", "wasm");
+        measurements2.add(Phase::Instantiation, "ends".into(), 100);
+        measurements2.add(Phase::Execution, "ends".into(), 5);
+        measurements2.add(Phase::Execution, "other".into(), 5);
+        measurements2.set_execution(Some(0));
+        measurements2.next_execution();
+        measurements2.add(Phase::Execution, "ends".into(), 20);
+        measurements = measurements2.finish();
+        overhead.subtract(&mut measurements);
+        assert_eq!(measurements[3].execution, Some(1));
+        let counts: Vec<_> = measurements.iter().map(|m| m.count).collect();
+        assert_eq!(counts, [88, 0, 5, 7]);
+    }
+}
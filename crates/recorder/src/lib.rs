@@ -1,4 +1,9 @@
 pub mod bench_api;
 pub mod benchmark;
+pub mod calibrate;
 pub mod cpu_affinity;
 pub mod measure;
+#[cfg(target_os = "linux")]
+pub mod native;
+pub mod profile;
+pub mod static_engine;
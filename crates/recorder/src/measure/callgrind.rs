@@ -0,0 +1,250 @@
+//! Measure deterministic instruction and simulated cache counts using Valgrind's Callgrind tool.
+//! The benchmarking process must be run under Callgrind with collection initially disabled; e.g.:
+//!
+//! ```text
+//! valgrind --tool=callgrind --collect-atstart=no --cache-sim=yes --smc-check=all-non-file \
+//!     sightglass-cli benchmark --processes 1 --measure callgrind ...
+//! ```
+//!
+//! `sightglass-cli` does this automatically for each benchmark subprocess when `--measure
+//! callgrind` is used with more than one process. Each phase is delimited using Callgrind's client
+//! requests: collection is toggled on at `start` and, at `end`, toggled off and the statistics
+//! dumped to a `callgrind.out.<pid>.<part>` file, which is then parsed for the event totals and
+//! removed. The dumps are written to the current directory, or to the directory named by the
+//! [OUT_DIR_VAR] environment variable, which must match Callgrind's `--callgrind-out-file`;
+//! `sightglass-cli` points both at a temporary directory of its run, so that no dumps are left
+//! behind and none of an earlier run can be read. Because the counts are simulated, they are
+//! unaffected by other activity on a noisy machine, though they are far slower to collect.
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::{
+    borrow::Cow,
+    env,
+    ffi::CString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The environment variable naming the directory of the Callgrind dumps, as Valgrind was told with
+/// `--callgrind-out-file=<dir>/callgrind.out.%p`.
+pub const OUT_DIR_VAR: &str = "SIGHTGLASS_CALLGRIND_DIR";
+
+/// Measure instruction and simulated cache counts with Callgrind.
+pub struct CallgrindMeasure {
+    /// The base name of the Callgrind output files; each dump is written to `<base>.<part>`.
+    out_file: PathBuf,
+}
+
+impl CallgrindMeasure {
+    pub fn new() -> Self {
+        assert!(
+            running_on_valgrind(),
+            "The callgrind measure must be run under Valgrind, e.g., `valgrind --tool=callgrind \
+            --collect-atstart=no --cache-sim=yes sightglass-cli benchmark ...`"
+        );
+        let dir = env::var_os(OUT_DIR_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_default();
+        Self {
+            out_file: dir.join(format!("callgrind.out.{}", std::process::id())),
+        }
+    }
+}
+
+impl Measure for CallgrindMeasure {
+    fn start(&mut self, _phase: Phase) {
+        client_request(CALLGRIND_ZERO_STATS, 0);
+        client_request(CALLGRIND_TOGGLE_COLLECT, 0);
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        client_request(CALLGRIND_TOGGLE_COLLECT, 0);
+        let trigger = CString::new(phase.to_string()).unwrap();
+        client_request(CALLGRIND_DUMP_STATS_AT, trigger.as_ptr() as usize);
+
+        let dump = latest_dump(&self.out_file).unwrap_or_else(|| {
+            panic!(
+                "Unable to find a Callgrind dump for {}",
+                self.out_file.display()
+            )
+        });
+        let contents = fs::read_to_string(&dump)
+            .unwrap_or_else(|e| panic!("Unable to read {}: {}", dump.display(), e));
+        let totals = parse_totals(&contents)
+            .unwrap_or_else(|| panic!("Unable to find the event totals in {}", dump.display()));
+        // The next phase's dump must not be mistaken for this one.
+        fs::remove_file(&dump)
+            .unwrap_or_else(|e| panic!("Unable to remove {}: {}", dump.display(), e));
+
+        measurements.reserve(totals.len());
+        for (event, count) in totals {
+            measurements.add(phase, event_name(event), count);
+        }
+    }
+}
+
+/// Find the most recent dump, i.e., the `<out_file>.<part>` file with the largest part number.
+fn latest_dump(out_file: &Path) -> Option<PathBuf> {
+    let dir = match out_file.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = format!("{}.", out_file.file_name()?.to_str()?);
+    fs::read_dir(&dir)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let name = e.file_name().into_string().ok()?;
+            let part: u32 = name.strip_prefix(&prefix)?.parse().ok()?;
+            Some((part, e.path()))
+        })
+        .max_by_key(|(part, _)| *part)
+        .map(|(_, path)| path)
+}
+
+/// Parse the `events:` and `totals:` (or, if missing, `summary:`) lines of a Callgrind dump.
+fn parse_totals(contents: &str) -> Option<Vec<(&str, u64)>> {
+    let mut events = None;
+    let mut summary = None;
+    let mut totals = None;
+    for line in contents.lines() {
+        if let Some(rest) = line.strip_prefix("events:") {
+            events = Some(rest);
+        } else if let Some(rest) = line.strip_prefix("summary:") {
+            summary = Some(rest);
+        } else if let Some(rest) = line.strip_prefix("totals:") {
+            totals = Some(rest);
+        }
+    }
+    let counts = totals.or(summary)?;
+    let counts = counts
+        .split_whitespace()
+        .map(|c| c.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    // Callgrind may omit trailing zero counts.
+    Some(
+        events?
+            .split_whitespace()
+            .enumerate()
+            .map(|(i, e)| (e, counts.get(i).copied().unwrap_or(0)))
+            .collect(),
+    )
+}
+
+/// Translate Callgrind's abbreviated event names into Sightglass-style event names.
+fn event_name(event: &str) -> Cow<'static, str> {
+    match event {
+        "Ir" => "instructions-retired".into(),
+        "Dr" => "data-reads".into(),
+        "Dw" => "data-writes".into(),
+        "I1mr" => "l1-instruction-read-misses".into(),
+        "D1mr" => "l1-data-read-misses".into(),
+        "D1mw" => "l1-data-write-misses".into(),
+        "ILmr" => "ll-instruction-read-misses".into(),
+        "DLmr" => "ll-data-read-misses".into(),
+        "DLmw" => "ll-data-write-misses".into(),
+        "Bc" => "conditional-branches".into(),
+        "Bcm" => "conditional-branch-misses".into(),
+        "Bi" => "indirect-branches".into(),
+        "Bim" => "indirect-branch-misses".into(),
+        other => other.to_lowercase().into(),
+    }
+}
+
+// Client request codes; see `valgrind.h` and `callgrind.h`.
+const RUNNING_ON_VALGRIND: usize = 0x1001;
+const CALLGRIND_BASE: usize = ((b'C' as usize) << 24) | ((b'T' as usize) << 16);
+const CALLGRIND_ZERO_STATS: usize = CALLGRIND_BASE + 1;
+const CALLGRIND_TOGGLE_COLLECT: usize = CALLGRIND_BASE + 2;
+const CALLGRIND_DUMP_STATS_AT: usize = CALLGRIND_BASE + 3;
+
+fn running_on_valgrind() -> bool {
+    client_request(RUNNING_ON_VALGRIND, 0) != 0
+}
+
+/// Issue a Valgrind client request. When not running under Valgrind, the magic instruction sequence
+/// has no effect and `0` is returned.
+#[cfg(target_arch = "x86_64")]
+fn client_request(request: usize, arg: usize) -> usize {
+    let args: [usize; 6] = [request, arg, 0, 0, 0, 0];
+    let mut result: usize = 0;
+    unsafe {
+        std::arch::asm!(
+            "rol rdi, 3",
+            "rol rdi, 13",
+            "rol rdi, 61",
+            "rol rdi, 51",
+            "xchg rbx, rbx",
+            inout("rdx") result,
+            in("rax") args.as_ptr(),
+            options(nostack),
+        );
+    }
+    result
+}
+
+/// Issue a Valgrind client request. When not running under Valgrind, the magic instruction sequence
+/// has no effect and `0` is returned.
+#[cfg(target_arch = "aarch64")]
+fn client_request(request: usize, arg: usize) -> usize {
+    let args: [usize; 6] = [request, arg, 0, 0, 0, 0];
+    let mut result: usize = 0;
+    unsafe {
+        std::arch::asm!(
+            "ror x12, x12, #3",
+            "ror x12, x12, #13",
+            "ror x12, x12, #51",
+            "ror x12, x12, #61",
+            "orr x10, x10, x10",
+            inout("x3") result,
+            in("x4") args.as_ptr(),
+            options(nostack),
+        );
+    }
+    result
+}
+
+/// Valgrind client requests are not implemented for this architecture.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn client_request(_request: usize, _arg: usize) -> usize {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn not_running_on_valgrind() {
+        // The test suite is not expected to run under Valgrind, so this checks that the magic
+        // sequence is harmless when executed natively.
+        assert!(!running_on_valgrind());
+    }
+
+    #[test]
+    fn parse_dump() {
+        let dump = "version: 1\n\
+            creator: callgrind-3.18.1\n\
+            pid: 1234\n\
+            part: 2\n\
+            desc: Trigger: Client Request: execution\n\
+            events: Ir Dr Dw I1mr D1mr D1mw\n\
+            summary: 1000 200 100 3\n\
+            \n\
+            fn=main\n\
+            0 1000 200 100 3\n\
+            totals: 1000 200 100 3\n";
+        assert_eq!(
+            parse_totals(dump).unwrap(),
+            vec![
+                ("Ir", 1000),
+                ("Dr", 200),
+                ("Dw", 100),
+                ("I1mr", 3),
+                ("D1mr", 0),
+                ("D1mw", 0)
+            ]
+        );
+        assert_eq!(event_name("Ir"), "instructions-retired");
+    }
+}
@@ -2,11 +2,24 @@
 //! work (and it will only work on Linux systems currently), you may need to tweak
 //! `/proc/sys/kernel/perf_event_paranoid` by running a command such as: `sudo sysctl -w
 //! kernel.perf_event_paranoid=0`.
+//!
+//! By default, a portable selection of hardware counters is measured. Alternately, a list of raw
+//! PMU events can be measured instead (see [PmuEvent]); this is useful on, e.g., aarch64 where the
+//! generic cache counters are not always available and the interesting events (L1D refills, stall
+//! cycles) are only exposed by raw event code.
 use super::Measure;
 use crate::measure::Measurements;
 use perf_event::{events::Hardware, Builder, Counter, Group};
+use perf_event_open_sys as sys;
 use serde::{Deserialize, Serialize};
 use sightglass_data::Phase;
+use std::{
+    fmt,
+    fs::File,
+    io::Read,
+    os::unix::io::{AsRawFd, FromRawFd},
+    str::FromStr,
+};
 
 /// Measure CPU counters.
 pub struct CounterMeasure {
@@ -83,6 +96,202 @@ impl Measure for CounterMeasure {
     }
 }
 
+/// A raw PMU event, identified by the event code the processor uses for it. It can be parsed from
+/// a hexadecimal code (`0x23` or, as `perf` spells it, `r23`) or, on aarch64, from the name of one
+/// of the common Arm architectural events (`l1d-cache-refill` or `L1D_CACHE_REFILL`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PmuEvent {
+    name: Option<&'static str>,
+    code: u64,
+}
+
+impl PmuEvent {
+    /// The name of the event recorded in the measurements, e.g., `l1d-cache-refill` or `raw-0x23`.
+    pub fn event_name(&self) -> String {
+        match self.name {
+            Some(name) => name.to_string(),
+            None => format!("raw-{:#x}", self.code),
+        }
+    }
+}
+
+/// Some of the common architectural and microarchitectural events defined by the Armv8 PMU (see the
+/// "PMU events and event numbers" section of the Arm Architecture Reference Manual). Not every
+/// implementation counts every event.
+const ARM_PMU_EVENTS: &[(&str, u64)] = &[
+    ("l1i-cache-refill", 0x01),
+    ("l1i-tlb-refill", 0x02),
+    ("l1d-cache-refill", 0x03),
+    ("l1d-cache", 0x04),
+    ("l1d-tlb-refill", 0x05),
+    ("inst-retired", 0x08),
+    ("br-mis-pred", 0x10),
+    ("cpu-cycles", 0x11),
+    ("br-pred", 0x12),
+    ("l2d-cache", 0x16),
+    ("l2d-cache-refill", 0x17),
+    ("br-retired", 0x21),
+    ("br-mis-pred-retired", 0x22),
+    ("stall-frontend", 0x23),
+    ("stall-backend", 0x24),
+    ("ll-cache-miss-rd", 0x37),
+    ("stall", 0x3c),
+];
+
+/// Find the Arm event named `name`, accepting both the `perf`-style (`l1d-cache-refill`) and the
+/// Arm-style (`L1D_CACHE_REFILL`) spelling.
+fn arm_pmu_event(name: &str) -> Option<PmuEvent> {
+    let name = name.to_ascii_lowercase().replace('_', "-");
+    ARM_PMU_EVENTS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|&(name, code)| PmuEvent {
+            name: Some(name),
+            code,
+        })
+}
+
+impl FromStr for PmuEvent {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix("0x").or_else(|| s.strip_prefix('r'));
+        if let Some(code) = hex.and_then(|h| u64::from_str_radix(h, 16).ok()) {
+            return Ok(PmuEvent { name: None, code });
+        }
+        if cfg!(target_arch = "aarch64") {
+            arm_pmu_event(s).ok_or("unknown PMU event")
+        } else {
+            Err("unknown PMU event; only raw event codes (e.g., 0x23) are supported on this architecture")
+        }
+    }
+}
+
+impl fmt::Display for PmuEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{:#x}", self.code),
+        }
+    }
+}
+
+/// Measure a group of raw PMU events. The `perf-event` crate only exposes the generic hardware
+/// events so this opens the events with `perf_event_open` directly; all events are scheduled onto
+/// the PMU together, as a group, so that their counts are comparable.
+pub struct PmuMeasure {
+    events: Vec<PmuEvent>,
+    leader: File,
+    // The group members are kept open for as long as the leader is.
+    _members: Vec<File>,
+}
+
+impl PmuMeasure {
+    pub fn new(events: &[PmuEvent]) -> Self {
+        assert!(
+            !events.is_empty(),
+            "at least one PMU event must be measured"
+        );
+        let leader = open_raw_event(&events[0], None);
+        let members = events[1..]
+            .iter()
+            .map(|event| open_raw_event(event, Some(&leader)))
+            .collect();
+        Self {
+            events: events.to_vec(),
+            leader,
+            _members: members,
+        }
+    }
+
+    fn group_ioctl(&self, ioctl: unsafe fn(i32, u32) -> i32) {
+        let result = unsafe {
+            ioctl(
+                self.leader.as_raw_fd(),
+                sys::bindings::perf_event_ioc_flags_PERF_IOC_FLAG_GROUP,
+            )
+        };
+        assert_ne!(
+            result,
+            -1,
+            "Unable to control the PMU event group: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+fn open_raw_event(event: &PmuEvent, leader: Option<&File>) -> File {
+    let mut attrs = sys::bindings::perf_event_attr {
+        size: std::mem::size_of::<sys::bindings::perf_event_attr>() as u32,
+        type_: sys::bindings::perf_type_id_PERF_TYPE_RAW,
+        config: event.code,
+        ..Default::default()
+    };
+    attrs.set_exclude_kernel(1);
+    attrs.set_exclude_hv(1);
+    let group_fd = match leader {
+        Some(leader) => leader.as_raw_fd(),
+        None => {
+            // Only the leader is disabled; the members are counted whenever the leader is.
+            attrs.set_disabled(1);
+            attrs.read_format = (sys::bindings::perf_event_read_format_PERF_FORMAT_GROUP
+                | sys::bindings::perf_event_read_format_PERF_FORMAT_TOTAL_TIME_ENABLED
+                | sys::bindings::perf_event_read_format_PERF_FORMAT_TOTAL_TIME_RUNNING)
+                as u64;
+            -1
+        }
+    };
+    let fd = unsafe {
+        sys::perf_event_open(
+            &mut attrs,
+            0,
+            -1,
+            group_fd,
+            sys::bindings::PERF_FLAG_FD_CLOEXEC as _,
+        )
+    };
+    assert_ne!(
+        fd,
+        -1,
+        "Unable to open PMU event {}: {}. Does this processor implement this event? You may also \
+        need to set /proc/sys/kernel/perf_event_paranoid to 2 or below.",
+        event,
+        std::io::Error::last_os_error()
+    );
+    unsafe { File::from_raw_fd(fd) }
+}
+
+impl Measure for PmuMeasure {
+    fn start(&mut self, _phase: Phase) {
+        self.group_ioctl(sys::ioctls::RESET);
+        self.group_ioctl(sys::ioctls::ENABLE);
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        self.group_ioctl(sys::ioctls::DISABLE);
+
+        // With `PERF_FORMAT_GROUP`, the leader reads as `{ nr, time_enabled, time_running,
+        // values[nr] }`.
+        let mut bytes = vec![0; (3 + self.events.len()) * std::mem::size_of::<u64>()];
+        self.leader
+            .read_exact(&mut bytes)
+            .expect("Unable to read the PMU event group");
+        let values: Vec<u64> = bytes
+            .chunks_exact(std::mem::size_of::<u64>())
+            .map(|b| u64::from_ne_bytes(b.try_into().unwrap()))
+            .collect();
+        let (time_enabled, time_running) = (values[1], values[2]);
+        assert!(
+            time_enabled == 0 || time_running != 0,
+            "The PMU event group was never scheduled; try measuring fewer events at once"
+        );
+
+        measurements.reserve(self.events.len());
+        for (event, &count) in self.events.iter().zip(&values[3..]) {
+            measurements.add(phase, event.event_name().into(), count);
+        }
+    }
+}
+
 /// A recording of time and performance counter information. `PerfCounters::default()` provides a
 /// useful zero to accumulate into.
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
@@ -165,4 +374,23 @@ mod tests {
         println!("Result: {}", a);
         println!("Measurements: {:?}", measurements);
     }
+
+    #[test]
+    fn parse_raw_pmu_events() {
+        let event: PmuEvent = "0x23".parse().unwrap();
+        assert_eq!(event.code, 0x23);
+        assert_eq!(event.event_name(), "raw-0x23");
+        assert_eq!(event.to_string().parse::<PmuEvent>().unwrap(), event);
+        assert_eq!("r1b".parse::<PmuEvent>().unwrap().code, 0x1b);
+        assert!("0xzz".parse::<PmuEvent>().is_err());
+    }
+
+    #[test]
+    fn lookup_arm_pmu_events() {
+        let event = arm_pmu_event("L1D_CACHE_REFILL").unwrap();
+        assert_eq!(event.code, 0x03);
+        assert_eq!(event.event_name(), "l1d-cache-refill");
+        assert_eq!(arm_pmu_event("stall-backend").unwrap().code, 0x24);
+        assert!(arm_pmu_event("l3-cache-refill").is_none());
+    }
 }
@@ -0,0 +1,124 @@
+//! Measure the energy consumed by the CPU packages using Intel's Running Average Power Limit
+//! (RAPL) counters, as exposed by the Linux powercap framework in
+//! `/sys/class/powercap/intel-rapl:*`. The counters are only readable by root on recent kernels, so
+//! you may need to run the benchmark with `sudo` or adjust the permissions of the `energy_uj` files.
+//!
+//! Note that RAPL measures the entire package, not just the benchmarking process: other activity on
+//! the system will be included in the result.
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The directory in which the Linux powercap framework exposes the RAPL zones.
+const POWERCAP_DIR: &str = "/sys/class/powercap";
+
+/// Measure the energy consumed, in microjoules, by all CPU packages.
+pub struct EnergyMeasure {
+    zones: Vec<RaplZone>,
+    start: Option<Vec<u64>>,
+}
+
+impl EnergyMeasure {
+    pub fn new() -> Self {
+        let zones = RaplZone::find_packages(Path::new(POWERCAP_DIR)).expect(
+            "Unable to read the RAPL zones in /sys/class/powercap; is the `intel_rapl` kernel \
+            module loaded?",
+        );
+        assert!(
+            !zones.is_empty(),
+            "Unable to find any RAPL package zones in /sys/class/powercap; does this processor \
+            support RAPL?"
+        );
+        Self { zones, start: None }
+    }
+
+    fn read_all(&self) -> Vec<u64> {
+        self.zones.iter().map(RaplZone::read).collect()
+    }
+}
+
+impl Measure for EnergyMeasure {
+    fn start(&mut self, _phase: Phase) {
+        self.start = Some(self.read_all());
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let end = self.read_all();
+        let start = self.start.take().expect("must call start before end");
+        let microjoules = self
+            .zones
+            .iter()
+            .zip(start.into_iter().zip(end))
+            .map(|(zone, (start, end))| elapsed(start, end, zone.max_energy_range))
+            .sum();
+        measurements.add(phase, "microjoules".into(), microjoules);
+    }
+}
+
+/// A top-level RAPL zone, i.e., a CPU package.
+struct RaplZone {
+    energy_path: PathBuf,
+    max_energy_range: u64,
+}
+
+impl RaplZone {
+    /// Find the package zones (e.g., `intel-rapl:0`) but not their subzones (e.g.,
+    /// `intel-rapl:0:0`), since the subzone energy is already included in the package energy.
+    fn find_packages(powercap_dir: &Path) -> std::io::Result<Vec<Self>> {
+        let mut zones = vec![];
+        for entry in fs::read_dir(powercap_dir)? {
+            let path = entry?.path();
+            let is_package = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix("intel-rapl:"))
+                .is_some_and(|id| !id.contains(':'));
+            if !is_package {
+                continue;
+            }
+            let max_energy_range = read_u64(&path.join("max_energy_range_uj"));
+            zones.push(Self {
+                energy_path: path.join("energy_uj"),
+                max_energy_range,
+            });
+        }
+        zones.sort_by(|a, b| a.energy_path.cmp(&b.energy_path));
+        Ok(zones)
+    }
+
+    fn read(&self) -> u64 {
+        read_u64(&self.energy_path)
+    }
+}
+
+fn read_u64(path: &Path) -> u64 {
+    fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Unable to read {}: {}", path.display(), e))
+        .trim()
+        .parse()
+        .unwrap_or_else(|e| panic!("Unable to parse {}: {}", path.display(), e))
+}
+
+/// Calculate the energy consumed between two readings of a RAPL counter; the counter wraps around
+/// to zero once it reaches `max_energy_range`.
+fn elapsed(start: u64, end: u64, max_energy_range: u64) -> u64 {
+    if end >= start {
+        end - start
+    } else {
+        max_energy_range - start + end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_wraparound() {
+        assert_eq!(elapsed(10, 25, 100), 15);
+        assert_eq!(elapsed(90, 5, 100), 15);
+    }
+}
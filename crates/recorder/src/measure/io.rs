@@ -0,0 +1,85 @@
+//! Measure the I/O performed during each phase using the process' I/O accounting in
+//! `/proc/self/io`. This helps to tell apart benchmarks dominated by WASI I/O from purely
+//! computational ones. Only available on Linux.
+//!
+//! Note that the `read`/`write` family of system calls is counted, not every system call, and that
+//! reading `/proc/self/io` at the start of each phase is itself counted as one read; use
+//! `--overhead subtract` to remove it.
+
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::fs;
+
+/// The fields of `/proc/self/io` that are measured and the events they are recorded as.
+const FIELDS: [(&str, &str); 4] = [
+    ("rchar", "bytes-read"),
+    ("wchar", "bytes-written"),
+    ("syscr", "read-syscalls"),
+    ("syscw", "write-syscalls"),
+];
+
+pub struct IoMeasure(Option<[u64; 4]>);
+
+impl IoMeasure {
+    pub fn new() -> Self {
+        Self(None)
+    }
+}
+
+impl Measure for IoMeasure {
+    fn start(&mut self, _phase: Phase) {
+        self.0 = Some(read_io());
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let end = read_io();
+        let start = self.0.take().expect("must call start before end");
+        measurements.reserve(FIELDS.len());
+        for (i, (_, event)) in FIELDS.iter().enumerate() {
+            measurements.add(phase, (*event).into(), end[i].saturating_sub(start[i]));
+        }
+    }
+}
+
+fn read_io() -> [u64; 4] {
+    let io = fs::read_to_string("/proc/self/io").expect("Unable to read /proc/self/io");
+    parse_io(&io).expect("Unable to parse /proc/self/io")
+}
+
+fn parse_io(io: &str) -> Option<[u64; 4]> {
+    let mut counts = [0; 4];
+    for (count, (field, _)) in counts.iter_mut().zip(FIELDS.iter()) {
+        *count = io.lines().find_map(|line| {
+            let value = line.strip_prefix(field)?.strip_prefix(':')?;
+            value.trim().parse().ok()
+        })?;
+    }
+    Some(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proc_io() {
+        let io = "rchar: 3980\nwchar: 12\nsyscr: 9\nsyscw: 1\nread_bytes: 0\nwrite_bytes: 0\n";
+        assert_eq!(parse_io(io), Some([3980, 12, 9, 1]));
+        assert_eq!(parse_io("rchar: 1\n"), None);
+    }
+
+    #[test]
+    fn sanity() {
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        let mut measure = IoMeasure::new();
+        measure.start(Phase::Execution);
+        fs::read("/proc/self/status").unwrap();
+        measure.end(Phase::Execution, &mut measurements);
+        let measurements = measurements.finish();
+        let reads = measurements
+            .iter()
+            .find(|m| m.event == "read-syscalls")
+            .unwrap();
+        assert!(reads.count >= 1, "{:?}", measurements);
+    }
+}
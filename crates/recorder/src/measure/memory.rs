@@ -0,0 +1,76 @@
+//! Measure the peak memory used during each phase. Before a phase starts, the process' resident set
+//! size high-water mark is reset (by writing `5` to `/proc/self/clear_refs`, available since Linux
+//! 4.0) and the current resident set size is noted; when the phase ends, the high-water mark is read
+//! back from `/proc/self/status`. The difference is the additional memory the phase needed at its
+//! peak--e.g., the compiler's working memory during compilation--independent of whatever the
+//! process was already using. Only available on Linux.
+
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::fs;
+
+pub struct PeakMemoryMeasure(Option<u64>);
+
+impl PeakMemoryMeasure {
+    pub fn new() -> Self {
+        Self(None)
+    }
+}
+
+impl Measure for PeakMemoryMeasure {
+    fn start(&mut self, _phase: Phase) {
+        fs::write("/proc/self/clear_refs", "5")
+            .expect("Unable to reset the peak RSS through /proc/self/clear_refs");
+        self.0 = Some(read_status_kb("VmRSS"));
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let peak = read_status_kb("VmHWM");
+        let start = self.0.take().expect("must call start before end");
+        measurements.add(
+            phase,
+            "peak-rss-bytes".into(),
+            peak.saturating_sub(start) * 1024,
+        );
+    }
+}
+
+/// Read a field, in kilobytes, from `/proc/self/status`.
+fn read_status_kb(field: &str) -> u64 {
+    let status = fs::read_to_string("/proc/self/status").expect("Unable to read /proc/self/status");
+    parse_status_kb(&status, field)
+        .unwrap_or_else(|| panic!("Unable to find {} in /proc/self/status", field))
+}
+
+fn parse_status_kb(status: &str, field: &str) -> Option<u64> {
+    status.lines().find_map(|line| {
+        let value = line.strip_prefix(field)?.strip_prefix(':')?;
+        value.trim().strip_suffix("kB")?.trim().parse().ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        let status = "Name:\tcat\nVmHWM:\t    1234 kB\nVmRSS:\t     987 kB\n";
+        assert_eq!(parse_status_kb(status, "VmHWM"), Some(1234));
+        assert_eq!(parse_status_kb(status, "VmRSS"), Some(987));
+        assert_eq!(parse_status_kb(status, "VmSwap"), None);
+    }
+
+    #[test]
+    fn sanity() {
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        let mut measure = PeakMemoryMeasure::new();
+        measure.start(Phase::Compilation);
+        let buffer = vec![1u8; 16 << 20];
+        measure.end(Phase::Compilation, &mut measurements);
+        drop(buffer);
+        let measurements = measurements.finish();
+        assert_eq!(measurements[0].event, "peak-rss-bytes");
+        assert!(measurements[0].count >= 8 << 20, "{:?}", measurements);
+    }
+}
@@ -2,6 +2,7 @@ use sightglass_data::{Measurement, Phase};
 use std::{
     borrow::Cow,
     fmt::{self, Debug},
+    path::PathBuf,
     str::FromStr,
 };
 
@@ -12,7 +13,12 @@ pub struct Measurements<'a> {
     wasm: &'a str,
     process: u32,
     iteration: u32,
+    execution: Option<u32>,
+    threads: Option<u32>,
+    input_size: Option<&'a str>,
     measurements: Vec<Measurement<'a>>,
+    /// The number of measurements already returned by `pending`.
+    flushed: usize,
 }
 
 impl<'a> Measurements<'a> {
@@ -24,7 +30,11 @@ impl<'a> Measurements<'a> {
             wasm,
             process: std::process::id(),
             iteration: 0,
+            execution: None,
+            threads: None,
+            input_size: None,
             measurements: vec![],
+            flushed: 0,
         }
     }
 
@@ -33,6 +43,34 @@ impl<'a> Measurements<'a> {
         self.iteration += 1;
     }
 
+    /// Count the executions of each instance, starting over at zero, so that
+    /// the execution-phase measurements of the `n`th execution record `n` as
+    /// their [Measurement::execution]; `None` records no execution.
+    pub fn set_execution(&mut self, execution: Option<u32>) {
+        self.execution = execution;
+    }
+
+    /// Advance the execution counter, if executions are being counted.
+    pub fn next_execution(&mut self) {
+        if let Some(execution) = &mut self.execution {
+            *execution += 1;
+        }
+    }
+
+    /// Record the number of threads the benchmark was run with (see
+    /// `--wasm-threads`) in each measurement, or `None` for a benchmark run
+    /// without threads.
+    pub fn set_threads(&mut self, threads: Option<u32>) {
+        self.threads = threads;
+    }
+
+    /// Record the input size the benchmark was run with (see `--input-size`)
+    /// in each measurement, or `None` for a benchmark run with its default
+    /// input.
+    pub fn set_input_size(&mut self, input_size: Option<&'a str>) {
+        self.input_size = input_size;
+    }
+
     /// Reserve additional capacity for more measurements internally.
     pub fn reserve(&mut self, capacity: usize) {
         self.measurements.reserve(capacity);
@@ -50,9 +88,24 @@ impl<'a> Measurements<'a> {
             phase,
             event,
             count,
+            threads: self.threads,
+            input_size: self.input_size.map(Into::into),
+            execution: self.execution.filter(|_| phase == Phase::Execution),
         });
     }
 
+    /// The measurements recorded since this was last called; e.g., to write them out while the
+    /// benchmark is still running. `finish` still returns all of them.
+    pub fn pending(&mut self) -> &[Measurement<'a>] {
+        let start = std::mem::replace(&mut self.flushed, self.measurements.len());
+        &self.measurements[start..]
+    }
+
+    /// The measurements recorded so far; unlike `pending`, this does not mark them as returned.
+    pub fn recorded(&self) -> &[Measurement<'a>] {
+        &self.measurements
+    }
+
     /// When all measurements have been recorded, call this method to get the
     /// underlying measurements data.
     pub fn finish(self) -> Vec<Measurement<'a>> {
@@ -77,11 +130,23 @@ pub trait Measure: 'static {
     fn end(&mut self, phase: Phase, measurements: &mut Measurements);
 }
 
+#[cfg(target_os = "linux")]
+pub mod callgrind;
 #[cfg(target_os = "linux")]
 pub mod counters;
 pub mod cycles;
+#[cfg(target_os = "linux")]
+pub mod energy;
+#[cfg(target_os = "linux")]
+pub mod io;
+#[cfg(target_os = "linux")]
+pub mod memory;
 pub mod noop;
+pub mod plugin;
 pub mod vtune;
+pub mod wall_time;
+#[cfg(target_os = "windows")]
+pub mod windows;
 
 /// [MeasureType] enumerates the implementations of [Measure] and allows us to `build` an instance
 /// from its name:
@@ -90,7 +155,7 @@ pub mod vtune;
 /// let ty: MeasureType = "noop".parse().unwrap();
 /// let measure = ty.build();
 /// ```
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum MeasureType {
     /// No measurement.
     Noop,
@@ -98,9 +163,33 @@ pub enum MeasureType {
     Cycles,
     /// Measure using VTune; this will return `0` values.
     VTune,
-    /// Measure a combination of HW counters using `perf_event_open`.
+    /// Measure elapsed wall time using the given time source, e.g., `wall-time:monotonic-raw`.
+    WallTime(wall_time::TimeSource),
+    /// Measure a combination of HW counters using `perf_event_open`. If any raw PMU events are
+    /// listed (e.g., `perf-counters:l1d-cache-refill,0x24`), those are measured instead of the
+    /// default counters.
+    #[cfg(target_os = "linux")]
+    PerfCounters(Vec<counters::PmuEvent>),
+    /// Measure the energy consumed by the CPU packages using RAPL.
+    #[cfg(target_os = "linux")]
+    Energy,
+    /// Measure thread cycles and wall time using `QueryThreadCycleTime` and
+    /// `QueryPerformanceCounter`.
+    #[cfg(target_os = "windows")]
+    WindowsCounters,
+    /// Measure simulated instruction and cache counts using Valgrind's Callgrind.
+    #[cfg(target_os = "linux")]
+    Callgrind,
+    /// Measure the peak resident set size reached during each phase.
+    #[cfg(target_os = "linux")]
+    PeakMemory,
+    /// Measure the bytes read and written, and the read and write system calls made, during each
+    /// phase.
     #[cfg(target_os = "linux")]
-    PerfCounters,
+    Io,
+    /// Measure using a plugin loaded from the given shared library, e.g.,
+    /// `plugin:/path/to/libmeasure.so`; see [plugin].
+    Plugin(PathBuf),
 }
 
 impl fmt::Display for MeasureType {
@@ -109,8 +198,27 @@ impl fmt::Display for MeasureType {
             MeasureType::Noop => write!(f, "noop"),
             MeasureType::Cycles => write!(f, "cycles"),
             MeasureType::VTune => write!(f, "vtune"),
+            MeasureType::WallTime(wall_time::TimeSource::Monotonic) => write!(f, "wall-time"),
+            MeasureType::WallTime(source) => write!(f, "wall-time:{}", source),
             #[cfg(target_os = "linux")]
-            MeasureType::PerfCounters => write!(f, "perf-counters"),
+            MeasureType::PerfCounters(events) => {
+                write!(f, "perf-counters")?;
+                for (i, event) in events.iter().enumerate() {
+                    write!(f, "{}{}", if i == 0 { ':' } else { ',' }, event)?;
+                }
+                Ok(())
+            }
+            #[cfg(target_os = "linux")]
+            MeasureType::Energy => write!(f, "energy"),
+            #[cfg(target_os = "windows")]
+            MeasureType::WindowsCounters => write!(f, "windows-counters"),
+            #[cfg(target_os = "linux")]
+            MeasureType::Callgrind => write!(f, "callgrind"),
+            #[cfg(target_os = "linux")]
+            MeasureType::PeakMemory => write!(f, "peak-memory"),
+            #[cfg(target_os = "linux")]
+            MeasureType::Io => write!(f, "io"),
+            MeasureType::Plugin(path) => write!(f, "plugin:{}", path.display()),
         }
     }
 }
@@ -122,8 +230,30 @@ impl FromStr for MeasureType {
             "noop" => Ok(Self::Noop),
             "cycles" => Ok(Self::Cycles),
             "vtune" => Ok(Self::VTune),
+            "wall-time" => Ok(Self::WallTime(wall_time::TimeSource::Monotonic)),
+            s if s.starts_with("wall-time:") => {
+                Ok(Self::WallTime(s["wall-time:".len()..].parse()?))
+            }
+            #[cfg(target_os = "linux")]
+            "perf-counters" => Ok(Self::PerfCounters(vec![])),
+            #[cfg(target_os = "linux")]
+            s if s.starts_with("perf-counters:") => Ok(Self::PerfCounters(
+                s["perf-counters:".len()..]
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()?,
+            )),
             #[cfg(target_os = "linux")]
-            "perf-counters" => Ok(Self::PerfCounters),
+            "energy" => Ok(Self::Energy),
+            #[cfg(target_os = "windows")]
+            "windows-counters" => Ok(Self::WindowsCounters),
+            #[cfg(target_os = "linux")]
+            "callgrind" => Ok(Self::Callgrind),
+            #[cfg(target_os = "linux")]
+            "peak-memory" => Ok(Self::PeakMemory),
+            #[cfg(target_os = "linux")]
+            "io" => Ok(Self::Io),
+            s if s.starts_with("plugin:") => Ok(Self::Plugin(s["plugin:".len()..].into())),
             _ => Err("unknown measure type"),
         }
     }
@@ -138,8 +268,24 @@ impl MeasureType {
             Self::Noop => Box::new(noop::NoopMeasure::new()),
             Self::Cycles => Box::new(cycles::CycleMeasure::new()),
             Self::VTune => Box::new(vtune::VTuneMeasure::new()),
+            Self::WallTime(source) => Box::new(wall_time::WallTimeMeasure::new(*source)),
+            #[cfg(target_os = "linux")]
+            Self::PerfCounters(events) if events.is_empty() => {
+                Box::new(counters::CounterMeasure::new())
+            }
+            #[cfg(target_os = "linux")]
+            Self::PerfCounters(events) => Box::new(counters::PmuMeasure::new(events)),
             #[cfg(target_os = "linux")]
-            Self::PerfCounters => Box::new(counters::CounterMeasure::new()),
+            Self::Energy => Box::new(energy::EnergyMeasure::new()),
+            #[cfg(target_os = "windows")]
+            Self::WindowsCounters => Box::new(windows::WindowsMeasure::new()),
+            #[cfg(target_os = "linux")]
+            Self::Callgrind => Box::new(callgrind::CallgrindMeasure::new()),
+            #[cfg(target_os = "linux")]
+            Self::PeakMemory => Box::new(memory::PeakMemoryMeasure::new()),
+            #[cfg(target_os = "linux")]
+            Self::Io => Box::new(io::IoMeasure::new()),
+            Self::Plugin(path) => Box::new(plugin::PluginMeasure::new(path)),
         }
     }
 }
@@ -153,3 +299,17 @@ impl Measure for Box<dyn Measure> {
         (**self).end(phase, measurements)
     }
 }
+
+/// Combine two [Measure]s; the first is started before, and ended after, the second so that, e.g.,
+/// a profiler wrapping a measure does not count towards the measurement.
+impl<A: Measure, B: Measure> Measure for (A, B) {
+    fn start(&mut self, phase: Phase) {
+        self.0.start(phase);
+        self.1.start(phase);
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        self.1.end(phase, measurements);
+        self.0.end(phase, measurements);
+    }
+}
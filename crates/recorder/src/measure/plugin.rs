@@ -0,0 +1,176 @@
+//! Measure using an implementation loaded from a shared library, e.g., with `--measure
+//! plugin:/path/to/libmeasure.so`. This allows measuring with counters that Sightglass knows nothing
+//! about (GPU counters, internal telemetry, etc.) without modifying Sightglass itself.
+//!
+//! Since Rust has no stable ABI, a plugin does not implement the [Measure] trait directly; instead
+//! it exports the following C functions, which mirror the trait:
+//!
+//! ```c
+//! // Create the plugin's measurement state, returning `NULL` on failure. This is called once,
+//! // before any measurements are taken.
+//! void *sightglass_measure_create(void);
+//!
+//! // Start measuring `phase` (0 = compilation, 1 = instantiation, 2 = execution, 3 =
+//! // deserialization).
+//! void sightglass_measure_start(void *state, uint32_t phase);
+//!
+//! // Finish measuring `phase`, calling `record` once for each event measured; the event name
+//! // (UTF-8, not NUL-terminated) only needs to live until `record` returns.
+//! void sightglass_measure_end(void *state, uint32_t phase,
+//!                             void (*record)(void *context, const uint8_t *event_ptr,
+//!                                            size_t event_len, uint64_t count),
+//!                             void *context);
+//!
+//! // Free the plugin's measurement state.
+//! void sightglass_measure_free(void *state);
+//! ```
+use super::{Measure, Measurements};
+use anyhow::{anyhow, Result};
+use sightglass_data::Phase;
+use std::{ffi::c_void, path::Path};
+
+type RecordFn = extern "C" fn(*mut c_void, *const u8, usize, u64);
+
+/// The functions exported by a measurement plugin.
+#[derive(Clone, Copy)]
+struct PluginApi {
+    create: unsafe extern "C" fn() -> *mut c_void,
+    start: unsafe extern "C" fn(*mut c_void, u32),
+    end: unsafe extern "C" fn(*mut c_void, u32, RecordFn, *mut c_void),
+    free: unsafe extern "C" fn(*mut c_void),
+}
+
+/// Measure using a dynamically loaded plugin.
+pub struct PluginMeasure {
+    api: PluginApi,
+    state: *mut c_void,
+    // Keep the library loaded for as long as its functions may be called.
+    _library: Option<libloading::Library>,
+}
+
+impl PluginMeasure {
+    pub fn new(path: &Path) -> Self {
+        Self::load(path).unwrap_or_else(|e| {
+            panic!(
+                "Unable to load the measurement plugin {}: {:#}",
+                path.display(),
+                e
+            )
+        })
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        // SAFETY: we have no way to check that the plugin exports functions with the correct
+        // signatures, so we must trust that it does.
+        unsafe {
+            let library = libloading::Library::new(path)?;
+            let api = PluginApi {
+                create: *library.get(b"sightglass_measure_create")?,
+                start: *library.get(b"sightglass_measure_start")?,
+                end: *library.get(b"sightglass_measure_end")?,
+                free: *library.get(b"sightglass_measure_free")?,
+            };
+            Self::with_api(api, Some(library))
+        }
+    }
+
+    unsafe fn with_api(api: PluginApi, library: Option<libloading::Library>) -> Result<Self> {
+        let state = (api.create)();
+        if state.is_null() {
+            return Err(anyhow!("`sightglass_measure_create` failed"));
+        }
+        Ok(Self {
+            api,
+            state,
+            _library: library,
+        })
+    }
+
+    /// Callback passed to `sightglass_measure_end` to record a single event.
+    extern "C" fn record(context: *mut c_void, event_ptr: *const u8, event_len: usize, count: u64) {
+        let (phase, measurements) = unsafe { &mut *(context as *mut (Phase, &mut Measurements)) };
+        let event = unsafe { std::slice::from_raw_parts(event_ptr, event_len) };
+        let event = String::from_utf8_lossy(event).into_owned();
+        measurements.add(*phase, event.into(), count);
+    }
+}
+
+fn phase_to_u32(phase: Phase) -> u32 {
+    match phase {
+        Phase::Compilation => 0,
+        Phase::Instantiation => 1,
+        Phase::Execution => 2,
+        Phase::Deserialization => 3,
+    }
+}
+
+impl Measure for PluginMeasure {
+    fn start(&mut self, phase: Phase) {
+        unsafe { (self.api.start)(self.state, phase_to_u32(phase)) }
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let mut context = (phase, measurements);
+        unsafe {
+            (self.api.end)(
+                self.state,
+                phase_to_u32(phase),
+                Self::record,
+                &mut context as *mut _ as *mut c_void,
+            )
+        }
+    }
+}
+
+impl Drop for PluginMeasure {
+    fn drop(&mut self) {
+        unsafe { (self.api.free)(self.state) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A minimal plugin counting the number of times each phase was started.
+    unsafe extern "C" fn create() -> *mut c_void {
+        Box::into_raw(Box::new([0u64; 3])) as *mut c_void
+    }
+    unsafe extern "C" fn start(state: *mut c_void, phase: u32) {
+        (*(state as *mut [u64; 3]))[phase as usize] += 1;
+    }
+    unsafe extern "C" fn end(state: *mut c_void, phase: u32, record: RecordFn, ctx: *mut c_void) {
+        let event = "starts";
+        let count = (*(state as *mut [u64; 3]))[phase as usize];
+        record(ctx, event.as_ptr(), event.len(), count);
+    }
+    unsafe extern "C" fn free(state: *mut c_void) {
+        drop(Box::from_raw(state as *mut [u64; 3]));
+    }
+
+    #[test]
+    fn record_plugin_events() {
+        let api = PluginApi {
+            create,
+            start,
+            end,
+            free,
+        };
+        let mut measure = unsafe { PluginMeasure::with_api(api, None).unwrap() };
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        for _ in 0..2 {
+            measure.start(Phase::Execution);
+            measure.end(Phase::Execution, &mut measurements);
+        }
+        let measurements = measurements.finish();
+        assert_eq!(measurements.len(), 2);
+        assert_eq!(measurements[1].phase, Phase::Execution);
+        assert_eq!(measurements[1].event, "starts");
+        assert_eq!(measurements[1].count, 2);
+    }
+
+    #[test]
+    fn missing_plugin() {
+        assert!(PluginMeasure::load(Path::new("/does/not/exist.so")).is_err());
+    }
+}
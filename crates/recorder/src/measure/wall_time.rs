@@ -0,0 +1,183 @@
+//! Measure the elapsed wall time of each phase using a selectable time source. The default,
+//! `monotonic`, uses the standard library's monotonic clock, which is subject to NTP slewing on
+//! Linux; `monotonic-raw` reads `CLOCK_MONOTONIC_RAW`, which is not, and `rdtscp` reads the
+//! time-stamp counter directly, fenced so that surrounding instructions are not reordered into or
+//! out of the measured region. The source is recorded in the event name so that measurements from
+//! different sources are never compared with each other.
+
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::{fmt, str::FromStr, time::Instant};
+
+/// The clock read by a [WallTimeMeasure].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeSource {
+    /// The standard library's monotonic clock (e.g., `CLOCK_MONOTONIC` on Linux).
+    Monotonic,
+    /// `clock_gettime(CLOCK_MONOTONIC_RAW)`, which is not adjusted by NTP.
+    #[cfg(target_os = "linux")]
+    MonotonicRaw,
+    /// The time-stamp counter, read with `rdtscp` and fenced with `lfence`.
+    #[cfg(target_arch = "x86_64")]
+    Rdtscp,
+}
+
+impl TimeSource {
+    /// The name of the event recorded for this source.
+    fn event(&self) -> &'static str {
+        match self {
+            TimeSource::Monotonic => "nanoseconds",
+            #[cfg(target_os = "linux")]
+            TimeSource::MonotonicRaw => "nanoseconds-monotonic-raw",
+            #[cfg(target_arch = "x86_64")]
+            TimeSource::Rdtscp => "rdtscp-ticks",
+        }
+    }
+}
+
+impl fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TimeSource::Monotonic => write!(f, "monotonic"),
+            #[cfg(target_os = "linux")]
+            TimeSource::MonotonicRaw => write!(f, "monotonic-raw"),
+            #[cfg(target_arch = "x86_64")]
+            TimeSource::Rdtscp => write!(f, "rdtscp"),
+        }
+    }
+}
+
+impl FromStr for TimeSource {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "monotonic" => Ok(Self::Monotonic),
+            #[cfg(target_os = "linux")]
+            "monotonic-raw" => Ok(Self::MonotonicRaw),
+            #[cfg(target_arch = "x86_64")]
+            "rdtscp" => Ok(Self::Rdtscp),
+            _ => Err("unknown time source"),
+        }
+    }
+}
+
+/// A point in time read from a [TimeSource].
+enum Timestamp {
+    Instant(Instant),
+    Count(u64),
+}
+
+/// Measure elapsed wall time.
+pub struct WallTimeMeasure {
+    source: TimeSource,
+    start: Option<Timestamp>,
+}
+
+impl WallTimeMeasure {
+    pub fn new(source: TimeSource) -> Self {
+        Self {
+            source,
+            start: None,
+        }
+    }
+}
+
+impl Measure for WallTimeMeasure {
+    fn start(&mut self, _phase: Phase) {
+        self.start = Some(match self.source {
+            TimeSource::Monotonic => Timestamp::Instant(Instant::now()),
+            #[cfg(target_os = "linux")]
+            TimeSource::MonotonicRaw => Timestamp::Count(monotonic_raw_nanos()),
+            #[cfg(target_arch = "x86_64")]
+            TimeSource::Rdtscp => Timestamp::Count(rdtsc_start()),
+        });
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let end = match self.source {
+            TimeSource::Monotonic => Timestamp::Instant(Instant::now()),
+            #[cfg(target_os = "linux")]
+            TimeSource::MonotonicRaw => Timestamp::Count(monotonic_raw_nanos()),
+            #[cfg(target_arch = "x86_64")]
+            TimeSource::Rdtscp => Timestamp::Count(rdtsc_end()),
+        };
+        let elapsed = match (self.start.take().expect("must call start before end"), end) {
+            (Timestamp::Instant(start), Timestamp::Instant(end)) => (end - start).as_nanos() as u64,
+            (Timestamp::Count(start), Timestamp::Count(end)) => end.wrapping_sub(start),
+            _ => unreachable!("the time source does not change"),
+        };
+        measurements.add(phase, self.source.event().into(), elapsed);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn monotonic_raw_nanos() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let result = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_RAW, &mut time) };
+    assert_eq!(result, 0, "Unable to read CLOCK_MONOTONIC_RAW");
+    time.tv_sec as u64 * 1_000_000_000 + time.tv_nsec as u64
+}
+
+/// Read the time-stamp counter at the start of a region: the `lfence` waits for all previous
+/// instructions to complete before `rdtsc` reads the counter.
+#[cfg(target_arch = "x86_64")]
+fn rdtsc_start() -> u64 {
+    use std::arch::x86_64::{_mm_lfence, _rdtsc};
+    unsafe {
+        _mm_lfence();
+        let ticks = _rdtsc();
+        _mm_lfence();
+        ticks
+    }
+}
+
+/// Read the time-stamp counter at the end of a region: `rdtscp` waits for all previous
+/// instructions to execute and the trailing `lfence` keeps later instructions from starting early.
+#[cfg(target_arch = "x86_64")]
+fn rdtsc_end() -> u64 {
+    use std::arch::x86_64::{__rdtscp, _mm_lfence};
+    unsafe {
+        let mut aux = 0;
+        let ticks = __rdtscp(&mut aux);
+        _mm_lfence();
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_sources() {
+        for source in ["monotonic", "monotonic-raw", "rdtscp"] {
+            if let Ok(parsed) = source.parse::<TimeSource>() {
+                assert_eq!(parsed.to_string(), source);
+            }
+        }
+        assert!("sundial".parse::<TimeSource>().is_err());
+    }
+
+    #[test]
+    fn sanity() {
+        let mut sources = vec![TimeSource::Monotonic];
+        #[cfg(target_os = "linux")]
+        sources.push(TimeSource::MonotonicRaw);
+        #[cfg(target_arch = "x86_64")]
+        sources.push(TimeSource::Rdtscp);
+
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        for source in sources {
+            let mut measure = WallTimeMeasure::new(source);
+            measure.start(Phase::Execution);
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            measure.end(Phase::Execution, &mut measurements);
+        }
+        for measurement in measurements.finish() {
+            assert!(measurement.count > 0, "{:?}", measurement);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Measure each phase using the Windows timing APIs: `QueryThreadCycleTime` counts the CPU cycles
+//! charged to the benchmarking thread (excluding time the thread was descheduled) and
+//! `QueryPerformanceCounter` provides a high-resolution wall-clock time. This is only available on
+//! Windows.
+use super::{Measure, Measurements};
+use sightglass_data::Phase;
+use winapi::um::{
+    processthreadsapi::GetCurrentThread, profileapi::QueryPerformanceCounter,
+    profileapi::QueryPerformanceFrequency, realtimeapiset::QueryThreadCycleTime,
+    winnt::LARGE_INTEGER,
+};
+
+/// Measure thread cycles and elapsed wall time using the Windows APIs.
+pub struct WindowsMeasure {
+    /// The number of performance counter ticks per second; this is fixed at system boot.
+    frequency: u64,
+    start: Option<(u64, u64)>,
+}
+
+impl WindowsMeasure {
+    pub fn new() -> Self {
+        let mut frequency: LARGE_INTEGER = unsafe { std::mem::zeroed() };
+        let result = unsafe { QueryPerformanceFrequency(&mut frequency) };
+        assert_ne!(
+            result, 0,
+            "Unable to query the performance counter frequency"
+        );
+        let frequency = unsafe { *frequency.QuadPart() } as u64;
+        Self {
+            frequency,
+            start: None,
+        }
+    }
+}
+
+impl Measure for WindowsMeasure {
+    fn start(&mut self, _phase: Phase) {
+        self.start = Some((thread_cycles(), performance_counter()));
+    }
+
+    fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+        let end_counter = performance_counter();
+        let end_cycles = thread_cycles();
+        let (start_cycles, start_counter) = self.start.take().expect("must call start before end");
+
+        measurements.reserve(2);
+        measurements.add(phase, "thread-cycles".into(), end_cycles - start_cycles);
+        measurements.add(
+            phase,
+            "nanoseconds".into(),
+            ticks_to_nanoseconds(end_counter - start_counter, self.frequency),
+        );
+    }
+}
+
+fn thread_cycles() -> u64 {
+    let mut cycles = 0;
+    let result = unsafe { QueryThreadCycleTime(GetCurrentThread(), &mut cycles) };
+    assert_ne!(result, 0, "Unable to query the thread cycle time");
+    cycles
+}
+
+fn performance_counter() -> u64 {
+    let mut counter: LARGE_INTEGER = unsafe { std::mem::zeroed() };
+    let result = unsafe { QueryPerformanceCounter(&mut counter) };
+    assert_ne!(result, 0, "Unable to query the performance counter");
+    (unsafe { *counter.QuadPart() }) as u64
+}
+
+/// Convert performance counter ticks to nanoseconds without overflowing for long phases.
+fn ticks_to_nanoseconds(ticks: u64, frequency: u64) -> u64 {
+    (ticks as u128 * 1_000_000_000 / frequency as u128) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn convert_ticks() {
+        assert_eq!(ticks_to_nanoseconds(10_000_000, 10_000_000), 1_000_000_000);
+        assert_eq!(ticks_to_nanoseconds(3, 3_000_000), 1_000);
+    }
+
+    #[test]
+    fn sanity() {
+        let mut measurements = Measurements::new("arch", "engine", "wasm");
+        let mut measure = WindowsMeasure::new();
+        measure.start(Phase::Execution);
+        let mut a = 0;
+        for i in 0..1_000_000 {
+            a = i
+        }
+        measure.end(Phase::Execution, &mut measurements);
+        let measurements = measurements.finish();
+        println!("Result: {}", a);
+        println!("Measurements: {:?}", measurements);
+        assert_eq!(measurements.len(), 2);
+    }
+}
@@ -0,0 +1,232 @@
+//! Run a natively compiled benchmark as a baseline for the Wasm engines. A benchmark may ship a
+//! shared library built from the same sources next to its Wasm file (e.g., `benchmark.so` next to
+//! `benchmark.wasm`); selecting the `native` engine runs that library in this process, measuring
+//! it through the same phases and hooks as the Wasm engines. See `include/sightglass.h` for how the
+//! library's `bench_start` and `bench_end` reach the recorder.
+//!
+//! There is no compilation or instantiation to speak of for native code, so the compilation phase
+//! measures loading the library and the instantiation phase measures resolving its entry point.
+//! During execution, the benchmark runs in the given working directory with its `stdout` and
+//! `stderr` (and, if given, `stdin`) redirected to the given files. The benchmark must return from
+//! `main` (or from the entry point being run, which takes no arguments) rather than exit.
+
+use crate::benchmark::BenchmarkOptions;
+use crate::measure::{Measure, Measurements};
+use anyhow::{Context, Result};
+use log::info;
+use sightglass_data::Phase;
+use std::env;
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+/// The name with which to select the native baseline in place of an engine library.
+pub const NATIVE_ENGINE: &str = "native";
+
+/// Find the natively compiled library shipped alongside a Wasm benchmark; e.g.,
+/// `benchmarks/noop/benchmark.so` for `benchmarks/noop/benchmark.wasm`.
+pub fn native_library_path(wasm_file: &Path) -> PathBuf {
+    wasm_file.with_extension(env::consts::DLL_EXTENSION)
+}
+
+type NativeInit = unsafe extern "C" fn(*mut c_void, NativeHook, NativeHook);
+type NativeHook = extern "C" fn(*mut c_void);
+type NativeMain = unsafe extern "C" fn(c_int, *const *const c_char) -> c_int;
+type NativeEntryPoint = unsafe extern "C" fn();
+
+/// Measure the phases of a natively compiled benchmark, like
+/// [benchmark](crate::benchmark::benchmark) does for a Wasm benchmark; if an
+/// `entry_point` is given, that function is called rather than `main`. Of the
+/// engine's `options`, only the precompiled module, executions, threads, and
+/// flags do not apply.
+pub fn benchmark<'a, 'c, M: Measure>(
+    library: &Path,
+    options: &BenchmarkOptions<'_>,
+    measure: &'a mut M,
+    measurements: &'a mut Measurements<'c>,
+) -> Result<()> {
+    let BenchmarkOptions {
+        working_dir,
+        stdout_path,
+        stderr_path,
+        stdin_path,
+        entry_point,
+        stop_after_phase,
+        ..
+    } = *options;
+    // Measure loading the library.
+    measure.start(Phase::Compilation);
+    let lib = unsafe { libloading::Library::new(library) };
+    measure.end(Phase::Compilation, measurements);
+    let lib = lib.with_context(|| format!("failed to load {}", library.display()))?;
+    info!("Loaded successfully");
+
+    if stop_after_phase == Some(Phase::Compilation) {
+        return Ok(());
+    }
+
+    // Measure resolving the entry point and passing the hooks to the benchmark.
+    let mut data = (measure, measurements);
+    let data_ptr = &mut data as *mut (&'a mut M, &'a mut Measurements<'c>) as *mut c_void;
+    data.0.start(Phase::Instantiation);
+    let entry_name = entry_point.unwrap_or("main");
+    let entry = unsafe {
+        lib.get::<NativeInit>(b"sightglass_native_init")
+            .and_then(|init| {
+                init(data_ptr, execution_start::<M>, execution_end::<M>);
+                lib.get::<*const c_void>(entry_name.as_bytes())
+            })
+            .map(|entry| *entry)
+    };
+    data.0.end(Phase::Instantiation, data.1);
+    let entry = entry.with_context(|| {
+        format!(
+            "{} does not export `{}` and `sightglass_native_init` (is it built with \
+             `sightglass.h`?)",
+            library.display(),
+            entry_name
+        )
+    })?;
+    info!("Instantiated successfully");
+
+    if stop_after_phase == Some(Phase::Instantiation) {
+        return Ok(());
+    }
+
+    // Execute the entry point; `bench_start` and `bench_end` measure the execution phase.
+    let stdio = Stdio::redirect(working_dir, stdout_path, stderr_path, stdin_path)?;
+    let status = if entry_point.is_some() {
+        let entry: NativeEntryPoint = unsafe { std::mem::transmute(entry) };
+        unsafe { entry() };
+        0
+    } else {
+        let main: NativeMain = unsafe { std::mem::transmute(entry) };
+        let argv = [c"benchmark".as_ptr(), ptr::null()];
+        unsafe { main(1, argv.as_ptr()) }
+    };
+    stdio.restore()?;
+    anyhow::ensure!(status == 0, "benchmark exited with code {}", status);
+    info!("Executed successfully");
+
+    Ok(())
+}
+
+/// The callback behind the native benchmark's `bench_start`.
+extern "C" fn execution_start<M: Measure>(data: *mut c_void) {
+    let data = data as *mut (&mut M, &mut Measurements);
+    let (measure, _) = unsafe { data.as_mut().unwrap() };
+    measure.start(Phase::Execution);
+}
+
+/// The callback behind the native benchmark's `bench_end`.
+extern "C" fn execution_end<M: Measure>(data: *mut c_void) {
+    let data = data as *mut (&mut M, &mut Measurements);
+    let (measure, measurements) = unsafe { data.as_mut().unwrap() };
+    measure.end(Phase::Execution, measurements);
+}
+
+/// The process' standard streams and working directory, saved while the benchmark runs with its
+/// own.
+struct Stdio {
+    saved: Vec<(c_int, c_int)>,
+    working_dir: PathBuf,
+}
+
+impl Stdio {
+    fn redirect(
+        working_dir: &Path,
+        stdout_path: &Path,
+        stderr_path: &Path,
+        stdin_path: Option<&Path>,
+    ) -> Result<Self> {
+        let create = |path: &Path| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+                .with_context(|| format!("failed to create {}", path.display()))
+        };
+        let mut files = vec![(1, create(stdout_path)?), (2, create(stderr_path)?)];
+        if let Some(stdin_path) = stdin_path {
+            let stdin = File::open(stdin_path)
+                .with_context(|| format!("failed to open {}", stdin_path.display()))?;
+            files.push((0, stdin));
+        }
+
+        io::stdout().flush()?;
+        io::stderr().flush()?;
+        let mut stdio = Stdio {
+            saved: vec![],
+            working_dir: env::current_dir()?,
+        };
+        for (fd, file) in files {
+            let saved = unsafe { libc::dup(fd) };
+            if saved >= 0 {
+                // Saved before redirecting, so that a failed redirection still restores (and
+                // closes) it.
+                stdio.saved.push((fd, saved));
+            }
+            if saved < 0 || unsafe { libc::dup2(file.as_raw_fd(), fd) } < 0 {
+                let error = io::Error::last_os_error();
+                stdio.restore()?;
+                return Err(error).context("failed to redirect the benchmark's stdio");
+            }
+        }
+        if let Err(e) = env::set_current_dir(working_dir) {
+            stdio.restore()?;
+            return Err(e).with_context(|| format!("failed to enter {}", working_dir.display()));
+        }
+        Ok(stdio)
+    }
+
+    fn restore(self) -> Result<()> {
+        // Write out anything the benchmark left in its C stdio buffers before switching back.
+        unsafe { libc::fflush(ptr::null_mut()) };
+        for (fd, saved) in self.saved {
+            let restored = unsafe { libc::dup2(saved, fd) };
+            unsafe { libc::close(saved) };
+            if restored < 0 {
+                return Err(io::Error::last_os_error()).context("failed to restore stdio");
+            }
+        }
+        env::set_current_dir(&self.working_dir)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measure::noop::NoopMeasure;
+
+    #[test]
+    fn find_native_library() {
+        assert_eq!(
+            native_library_path(Path::new("benchmarks/noop/benchmark.wasm")),
+            Path::new("benchmarks/noop/benchmark.so")
+        );
+    }
+
+    #[test]
+    fn missing_native_library() {
+        let mut measure = NoopMeasure::new();
+        let mut measurements = Measurements::new("x86_64", "native", "benchmark.wasm");
+        let options = BenchmarkOptions::new(
+            Path::new("."),
+            Path::new("stdout.log"),
+            Path::new("stderr.log"),
+        );
+        let result = benchmark(
+            Path::new("does-not-exist.so"),
+            &options,
+            &mut measure,
+            &mut measurements,
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,104 @@
+//! Profile each phase of a benchmark, in addition to measuring it.
+//!
+//! A profiler is attached to the benchmarking process using the same `start`/`end` hooks as a
+//! [Measure]; it records no measurements of its own but instead writes profile data to files whose
+//! names are derived from the engine and benchmark being run.
+use crate::measure::Measure;
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[cfg(target_os = "linux")]
+pub mod perf;
+
+/// [ProfileType] enumerates the available profilers and allows us to `build` one from its name:
+/// ```
+/// use sightglass_recorder::profile::ProfileType;
+/// let ty: Result<ProfileType, _> = "perf".parse();
+/// # #[cfg(target_os = "linux")]
+/// assert!(ty.is_ok());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub enum ProfileType {
+    /// Record each phase with `perf record`.
+    #[cfg(target_os = "linux")]
+    Perf,
+}
+
+impl fmt::Display for ProfileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(target_os = "linux")]
+            ProfileType::Perf => write!(f, "perf"),
+        }
+    }
+}
+
+impl FromStr for ProfileType {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            #[cfg(target_os = "linux")]
+            "perf" => Ok(Self::Perf),
+            _ => Err("unknown profile type"),
+        }
+    }
+}
+
+impl ProfileType {
+    /// Build a profiler which will write its profiles to files prefixed by `output_prefix`, e.g.,
+    /// `<output_prefix>.<phase>.perf.data`.
+    #[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+    pub fn build(&self, output_prefix: &Path) -> Box<dyn Measure> {
+        match *self {
+            #[cfg(target_os = "linux")]
+            Self::Perf => Box::new(perf::PerfProfile::new(output_prefix)),
+        }
+    }
+}
+
+/// Construct a file name prefix for the profiles of a given engine and benchmark, e.g.,
+/// `libengine-noop` for `engines/wasmtime/libengine.so` and `benchmarks/noop/benchmark.wasm`.
+pub fn output_prefix(directory: &Path, engine: &Path, wasm: &Path) -> PathBuf {
+    let engine = engine
+        .file_stem()
+        .map_or("engine".into(), |s| s.to_string_lossy());
+    let wasm_stem = wasm.file_stem().map(|s| s.to_string_lossy());
+    let wasm = match wasm_stem {
+        // Benchmarks are usually named by their parent directory, e.g., `noop/benchmark.wasm`.
+        Some(stem) if stem == "benchmark" => wasm
+            .parent()
+            .and_then(|p| p.file_name())
+            .map_or(stem, |s| s.to_string_lossy()),
+        Some(stem) => stem,
+        None => "benchmark".into(),
+    };
+    directory.join(format!("{}-{}", engine, wasm))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefix_from_engine_and_benchmark() {
+        assert_eq!(
+            output_prefix(
+                Path::new("out"),
+                Path::new("engines/wasmtime/libengine.so"),
+                Path::new("benchmarks/noop/benchmark.wasm")
+            ),
+            Path::new("out").join("libengine-noop")
+        );
+        assert_eq!(
+            output_prefix(
+                Path::new("."),
+                Path::new("/tmp/main.so"),
+                Path::new("spidermonkey.wasm")
+            ),
+            Path::new(".").join("main-spidermonkey")
+        );
+    }
+}
@@ -0,0 +1,164 @@
+//! Profile each phase using `perf record`. A separate `perf record` session is attached to the
+//! benchmarking process for each phase; each session starts with its events disabled and is only
+//! enabled, through `perf`'s control FIFOs, while its phase is running. This requires a version of
+//! `perf` supporting `--control` (Linux 5.10 or later) and, like the `perf-counters` measure, may
+//! require lowering `/proc/sys/kernel/perf_event_paranoid`.
+//!
+//! The resulting `<prefix>.<phase>.perf.data` files can be inspected with, e.g., `perf report -i
+//! <file>`.
+use crate::measure::{Measure, Measurements};
+use sightglass_data::Phase;
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+};
+
+/// Record a `perf` profile of each phase.
+pub struct PerfProfile {
+    output_prefix: PathBuf,
+    sessions: HashMap<Phase, PerfSession>,
+}
+
+impl PerfProfile {
+    pub fn new(output_prefix: &Path) -> Self {
+        Self {
+            output_prefix: output_prefix.to_path_buf(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// The file to which the profile of `phase` is written.
+    pub fn output_file(output_prefix: &Path, phase: Phase) -> PathBuf {
+        let mut file = output_prefix.as_os_str().to_owned();
+        file.push(format!(".{}.perf.data", phase));
+        file.into()
+    }
+}
+
+impl Measure for PerfProfile {
+    fn start(&mut self, phase: Phase) {
+        let output_prefix = &self.output_prefix;
+        let session = self.sessions.entry(phase).or_insert_with(|| {
+            PerfSession::spawn(&PerfProfile::output_file(output_prefix, phase), phase)
+        });
+        session.send("enable");
+    }
+
+    fn end(&mut self, phase: Phase, _measurements: &mut Measurements) {
+        let session = self
+            .sessions
+            .get_mut(&phase)
+            .expect("must call start before end");
+        session.send("disable");
+    }
+}
+
+/// A running `perf record` process attached to the current process.
+struct PerfSession {
+    child: Child,
+    control: File,
+    ack: BufReader<File>,
+    fifos: [PathBuf; 2],
+}
+
+impl PerfSession {
+    fn spawn(output_file: &Path, phase: Phase) -> Self {
+        let fifo_prefix =
+            std::env::temp_dir().join(format!("sightglass-perf-{}-{}", std::process::id(), phase));
+        let control_path = fifo_prefix.with_extension("ctl");
+        let ack_path = fifo_prefix.with_extension("ack");
+        mkfifo(&control_path);
+        mkfifo(&ack_path);
+
+        log::info!("Recording {} with perf to {}", phase, output_file.display());
+        let child = Command::new("perf")
+            .arg("record")
+            .arg("--call-graph=dwarf")
+            .arg("--pid")
+            .arg(std::process::id().to_string())
+            .arg("--delay=-1")
+            .arg(format!(
+                "--control=fifo:{},{}",
+                control_path.display(),
+                ack_path.display()
+            ))
+            .arg("--output")
+            .arg(output_file)
+            .arg("--quiet")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Unable to spawn `perf record`; is `perf` installed?");
+
+        // `perf` opens the control FIFO first and then blocks opening the acknowledgement FIFO for
+        // writing until we open it for reading; open them in the same order.
+        let control = OpenOptions::new()
+            .write(true)
+            .open(&control_path)
+            .expect("Unable to open the perf control FIFO");
+        let ack = BufReader::new(File::open(&ack_path).expect("Unable to open the perf ack FIFO"));
+
+        Self {
+            child,
+            control,
+            ack,
+            fifos: [control_path, ack_path],
+        }
+    }
+
+    /// Send a command to `perf` and wait for it to be acknowledged.
+    fn send(&mut self, command: &str) {
+        writeln!(self.control, "{}", command).expect("Unable to write to the perf control FIFO");
+        let mut ack = String::new();
+        self.ack
+            .read_line(&mut ack)
+            .expect("Unable to read from the perf ack FIFO");
+        assert_eq!(ack.trim_end_matches(['\n', '\0']), "ack");
+    }
+}
+
+impl Drop for PerfSession {
+    fn drop(&mut self) {
+        // `perf record` writes out its profile when interrupted.
+        unsafe {
+            libc::kill(self.child.id() as libc::pid_t, libc::SIGINT);
+        }
+        if let Err(e) = self.child.wait() {
+            log::warn!("Failed to wait for `perf record` to finish: {}", e);
+        }
+        for fifo in &self.fifos {
+            drop(fs::remove_file(fifo));
+        }
+    }
+}
+
+fn mkfifo(path: &Path) {
+    drop(fs::remove_file(path));
+    let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let result = unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) };
+    assert_eq!(
+        result,
+        0,
+        "Unable to create FIFO {}: {}",
+        path.display(),
+        std::io::Error::last_os_error()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_file_per_phase() {
+        assert_eq!(
+            PerfProfile::output_file(Path::new("out/libengine-noop"), Phase::Execution),
+            Path::new("out/libengine-noop.execution.perf.data")
+        );
+    }
+}
@@ -0,0 +1,225 @@
+//! Benchmark an engine linked directly into the benchmarking binary, rather than loaded from a
+//! shared library through the bench API. This suits environments in which building the engine as a
+//! `cdylib` is impractical (e.g., fully static musl binaries): the engine implements [BenchEngine]
+//! and a custom binary passes it to [benchmark], which measures the same phases, with the same
+//! [Measure]s and into the same [Measurements], as [crate::benchmark::benchmark] does for an engine
+//! library.
+//!
+//! ```ignore
+//! let mut measure = "wall-time".parse::<MeasureType>()?.build();
+//! let mut measurements = Measurements::new(this_arch, "my-engine", "benchmark.wasm");
+//! for _ in 0..iterations {
+//!     static_engine::benchmark::<MyEngine>(&config, &wasm, None, None, &mut measure, &mut measurements)?;
+//!     measurements.next_iteration();
+//! }
+//! sightglass_data::Format::Json.write(&measurements.finish(), std::io::stdout())?;
+//! ```
+
+use crate::measure::{Measure, Measurements};
+use anyhow::Result;
+use log::info;
+use sightglass_data::Phase;
+use std::{marker::PhantomData, path::PathBuf};
+
+/// An engine linked into the benchmarking binary; the Rust counterpart of the bench API's
+/// `wasm_bench_*` functions. A new engine is created for each iteration, and each of its methods
+/// is passed a [Timer] with which to mark the region of the phase to measure, so that the engine
+/// can leave out its own setup (e.g., creating WASI contexts).
+pub trait BenchEngine: Sized {
+    /// Create the engine, like `wasm_bench_create`.
+    fn new(config: &BenchConfig) -> Result<Self>;
+
+    /// Compile the Wasm, starting and ending `timer` around the compilation itself.
+    fn compile(&mut self, wasm: &[u8], timer: Timer<'_>) -> Result<()>;
+
+    /// Instantiate the compiled Wasm, starting and ending `timer` around the instantiation itself.
+    fn instantiate(&mut self, timer: Timer<'_>) -> Result<()>;
+
+    /// Execute the instance's `_start` or, if given, its exported `entry_point`. The benchmark's
+    /// `bench.start` and `bench.end` imports should start and end `timer`.
+    fn execute(&mut self, entry_point: Option<&str>, timer: Timer<'_>) -> Result<()>;
+}
+
+/// How to run a benchmark, as passed to [BenchEngine::new]; see `WasmBenchConfig` in the bench API.
+#[derive(Clone, Debug, Default)]
+pub struct BenchConfig {
+    /// The directory to preopen as the benchmark's working directory.
+    pub working_dir: PathBuf,
+    /// The file to which to write the benchmark's `stdout`.
+    pub stdout_path: PathBuf,
+    /// The file to which to write the benchmark's `stderr`.
+    pub stderr_path: PathBuf,
+    /// The file from which to read the benchmark's `stdin`, if any.
+    pub stdin_path: Option<PathBuf>,
+    /// Engine-specific flags, as given by `--engine-flags`.
+    pub execution_flags: Option<String>,
+}
+
+/// Marks the measured region of one phase on behalf of a [BenchEngine].
+///
+/// Like the timer callbacks of the bench API, this is a pointer to the measure and measurements
+/// under the hood; it borrows them for the [BenchEngine] call it is passed to, so it cannot outlive
+/// that call, and it is neither `Copy` nor `Send`, so it cannot be used from another thread. Host
+/// functions (e.g., the benchmark's `bench.start` and `bench.end` imports) can capture a reference
+/// to it for the duration of the call.
+pub struct Timer<'a> {
+    data: *mut (),
+    phase: Phase,
+    start: fn(*mut (), Phase),
+    end: fn(*mut (), Phase),
+    _borrow: PhantomData<&'a mut ()>,
+}
+
+impl<'a> Timer<'a> {
+    fn new<M: Measure>(data: &'a mut (&mut M, &mut Measurements), phase: Phase) -> Self {
+        Timer {
+            data: data as *mut (&mut M, &mut Measurements) as *mut (),
+            phase,
+            start: timer_start::<M>,
+            end: timer_end::<M>,
+            _borrow: PhantomData,
+        }
+    }
+
+    /// Start measuring the phase.
+    pub fn start(&self) {
+        (self.start)(self.data, self.phase)
+    }
+
+    /// Stop measuring the phase, recording the measurements.
+    pub fn end(&self) {
+        (self.end)(self.data, self.phase)
+    }
+}
+
+fn timer_start<M: Measure>(data: *mut (), phase: Phase) {
+    let data = data as *mut (&mut M, &mut Measurements);
+    let (measure, _) = unsafe { data.as_mut().unwrap() };
+    measure.start(phase);
+}
+
+fn timer_end<M: Measure>(data: *mut (), phase: Phase) {
+    let data = data as *mut (&mut M, &mut Measurements);
+    let (measure, measurements) = unsafe { data.as_mut().unwrap() };
+    measure.end(phase, measurements);
+}
+
+/// Measure the phases of a Wasm module's lifetime in the linked engine `E`, like
+/// [crate::benchmark::benchmark] does for an engine library; optionally stop after the given
+/// `stop_after_phase`, and execute the exported `entry_point` rather than `_start`.
+pub fn benchmark<E: BenchEngine>(
+    config: &BenchConfig,
+    wasm_bytes: &[u8],
+    entry_point: Option<&str>,
+    stop_after_phase: Option<Phase>,
+    measure: &mut impl Measure,
+    measurements: &mut Measurements,
+) -> Result<()> {
+    let mut engine = E::new(config)?;
+    let mut data = (measure, measurements);
+
+    engine.compile(wasm_bytes, Timer::new(&mut data, Phase::Compilation))?;
+    info!("Compiled successfully");
+    if stop_after_phase == Some(Phase::Compilation) {
+        return Ok(());
+    }
+
+    engine.instantiate(Timer::new(&mut data, Phase::Instantiation))?;
+    info!("Instantiated successfully");
+    if stop_after_phase == Some(Phase::Instantiation) {
+        return Ok(());
+    }
+
+    engine.execute(entry_point, Timer::new(&mut data, Phase::Execution))?;
+    info!("Executed successfully");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fake measure that records how many times it has been started.
+    struct CountingMeasure(u64);
+    impl Measure for CountingMeasure {
+        fn start(&mut self, _phase: Phase) {
+            self.0 += 1;
+        }
+        fn end(&mut self, phase: Phase, measurements: &mut Measurements) {
+            measurements.add(phase, "starts".into(), self.0);
+        }
+    }
+
+    /// A fake engine that "executes" by calling `bench.start` and `bench.end` twice.
+    struct TwiceEngine;
+    impl BenchEngine for TwiceEngine {
+        fn new(_config: &BenchConfig) -> Result<Self> {
+            Ok(TwiceEngine)
+        }
+        fn compile(&mut self, _wasm: &[u8], timer: Timer<'_>) -> Result<()> {
+            timer.start();
+            timer.end();
+            Ok(())
+        }
+        fn instantiate(&mut self, timer: Timer<'_>) -> Result<()> {
+            timer.start();
+            timer.end();
+            Ok(())
+        }
+        fn execute(&mut self, _entry_point: Option<&str>, timer: Timer<'_>) -> Result<()> {
+            let bench_start = || timer.start();
+            let bench_end = || timer.end();
+            for _ in 0..2 {
+                bench_start();
+                bench_end();
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn measure_linked_engine() {
+        let mut measure = CountingMeasure(0);
+        let mut measurements = Measurements::new("arch", "twice", "benchmark.wasm");
+        benchmark::<TwiceEngine>(
+            &BenchConfig::default(),
+            &[],
+            None,
+            None,
+            &mut measure,
+            &mut measurements,
+        )
+        .unwrap();
+        let phases: Vec<_> = measurements
+            .finish()
+            .iter()
+            .map(|m| (m.phase, m.count))
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                (Phase::Compilation, 1),
+                (Phase::Instantiation, 2),
+                (Phase::Execution, 3),
+                (Phase::Execution, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn stop_after_compilation() {
+        let mut measure = CountingMeasure(0);
+        let mut measurements = Measurements::new("arch", "twice", "benchmark.wasm");
+        benchmark::<TwiceEngine>(
+            &BenchConfig::default(),
+            &[],
+            None,
+            Some(Phase::Compilation),
+            &mut measure,
+            &mut measurements,
+        )
+        .unwrap();
+        assert_eq!(measurements.finish().len(), 1);
+    }
+}
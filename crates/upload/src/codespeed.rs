@@ -0,0 +1,108 @@
+//! Upload Sightglass results to a Codespeed server.
+use crate::groups::{group, Group};
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use serde_json::{json, Value};
+use sightglass_data::Measurement;
+
+/// Where a run's results belong in Codespeed.
+#[derive(Debug)]
+pub struct CodespeedRun<'a> {
+    /// The Codespeed project; e.g., `Wasmtime`.
+    pub project: &'a str,
+    /// The Codespeed executable, or, if unset, each result's engine.
+    pub executable: Option<&'a str>,
+    /// The Codespeed environment (i.e., the machine) the results were measured on, which must
+    /// already exist in Codespeed.
+    pub environment: &'a str,
+    /// The commit of the project the results are for.
+    pub commit: &'a str,
+    /// The branch of the commit.
+    pub branch: &'a str,
+}
+
+/// Upload the summary of each benchmark's `measurements` (its mean, standard deviation, minimum,
+/// and maximum) to the Codespeed server at `url`, through its `/result/add/json/` API. Each phase
+/// and event of a benchmark is a Codespeed benchmark; e.g., `benchmarks/bz2/benchmark.wasm
+/// execution cycles`. If `dry_run` is set, the results are printed rather than uploaded.
+pub fn upload_codespeed(
+    url: &str,
+    run: &CodespeedRun,
+    dry_run: bool,
+    measurements: &[Measurement],
+) -> Result<()> {
+    let results: Vec<Value> = group(measurements).iter().map(|g| result(run, g)).collect();
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    let url = format!("{}/result/add/json/", url.trim_end_matches('/'));
+    Client::new()
+        .post(&url)
+        .form(&[("json", serde_json::to_string(&results)?)])
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to upload results to {}", url))?;
+    log::info!("Uploaded {} results to {}", results.len(), url);
+    Ok(())
+}
+
+/// Describe a group of measurements as a Codespeed result.
+fn result(run: &CodespeedRun, group: &Group) -> Value {
+    json!({
+        "commitid": run.commit,
+        "branch": run.branch,
+        "project": run.project,
+        "executable": run.executable.unwrap_or(group.engine),
+        "benchmark": format!("{} {}", group.name(), group.event),
+        "environment": run.environment,
+        "result_value": group.mean(),
+        "std_dev": group.std_dev(),
+        "min": group.min(),
+        "max": group.max(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    #[test]
+    fn describe_result() {
+        let run = CodespeedRun {
+            project: "Wasmtime",
+            executable: None,
+            environment: "bench-1",
+            commit: "0123abcd",
+            branch: "main",
+        };
+        let group = Group {
+            arch: "x86_64",
+            engine: "libengine.so",
+            wasm: "benchmarks/bz2/benchmark.wasm",
+            phase: Phase::Execution,
+            event: "cycles",
+            threads: None,
+            input_size: None,
+            execution: None,
+            counts: vec![1, 3],
+        };
+        assert_eq!(
+            result(&run, &group),
+            json!({
+                "commitid": "0123abcd",
+                "branch": "main",
+                "project": "Wasmtime",
+                "executable": "libengine.so",
+                "benchmark": "benchmarks/bz2/benchmark.wasm execution cycles",
+                "environment": "bench-1",
+                "result_value": 2.0,
+                "std_dev": std::f64::consts::SQRT_2,
+                "min": 1,
+                "max": 3,
+            })
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Upload Sightglass results to a Conbench server.
+use crate::groups::{group, Group};
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use reqwest::header::{CONTENT_TYPE, COOKIE, SET_COOKIE};
+use serde_json::{json, Value};
+use sightglass_data::Measurement;
+use sightglass_fingerprint::Machine;
+
+/// Conbench only records durations, so only this event is uploaded.
+const EVENT: &str = "nanoseconds";
+
+/// Where a run's results belong in Conbench.
+#[derive(Debug)]
+pub struct ConbenchRun<'a> {
+    /// The Conbench run ID, which groups the results of the run.
+    pub run_id: &'a str,
+    /// The commit of the project the results are for.
+    pub commit: &'a str,
+    /// The project's repository; e.g., `https://github.com/bytecodealliance/wasmtime`.
+    pub repository: &'a str,
+    /// The branch of the commit, if known.
+    pub branch: Option<&'a str>,
+}
+
+/// Upload the wall time of each benchmark's `measurements` (in seconds) to the Conbench server at
+/// `url`, through its `/api/benchmark-results/` API, logging in with `credentials` (an email and
+/// password) if given. Each phase of a benchmark on an engine is a Conbench benchmark result, named
+/// (e.g., `benchmarks/bz2/benchmark.wasm execution`) and tagged with its engine, on a "cluster" of
+/// the current machine; Conbench computes the statistics from the raw samples. If `dry_run` is set,
+/// the results are printed rather than uploaded.
+pub fn upload_conbench(
+    url: &str,
+    credentials: Option<(&str, &str)>,
+    run: &ConbenchRun,
+    dry_run: bool,
+    measurements: &[Measurement],
+) -> Result<()> {
+    let machine = Machine::fingerprint()?;
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    let results: Vec<Value> = group(measurements)
+        .iter()
+        .filter(|g| g.event == EVENT)
+        .map(|g| result(run, &machine, &timestamp, g))
+        .collect();
+    if results.is_empty() {
+        log::warn!("No `{}` results to upload to Conbench", EVENT);
+    }
+    if dry_run {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+
+    let url = url.trim_end_matches('/');
+    let client = Client::new();
+    let cookies = match credentials {
+        Some((email, password)) => login(&client, url, email, password)?,
+        None => String::new(),
+    };
+    let url = format!("{}/api/benchmark-results/", url);
+    for result in &results {
+        let mut request = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(result)?);
+        if !cookies.is_empty() {
+            request = request.header(COOKIE, &cookies);
+        }
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to upload results to {}", url))?;
+    }
+    log::info!("Uploaded {} results to {}", results.len(), url);
+    Ok(())
+}
+
+/// Log in to Conbench, returning the session's cookies.
+fn login(client: &Client, url: &str, email: &str, password: &str) -> Result<String> {
+    let url = format!("{}/api/login/", url);
+    let response = client
+        .post(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(
+            &json!({ "email": email, "password": password }),
+        )?)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to log in to {}", url))?;
+    let cookies: Vec<_> = response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|cookie| cookie.to_str().ok()?.split(';').next())
+        .map(str::to_string)
+        .collect();
+    Ok(cookies.join("; "))
+}
+
+/// Describe a group of wall-time measurements as a Conbench benchmark result.
+fn result(run: &ConbenchRun, machine: &Machine, timestamp: &str, group: &Group) -> Value {
+    let mut tags = json!({
+        "name": group.name(),
+        "engine": group.engine,
+    });
+    if let Some(threads) = group.threads {
+        tags["threads"] = json!(threads);
+    }
+    if let Some(input_size) = group.input_size {
+        tags["input_size"] = json!(input_size);
+    }
+    if let Some(execution) = group.execution {
+        tags["execution"] = json!(execution);
+    }
+    let mut github = json!({ "commit": run.commit, "repository": run.repository });
+    if let Some(branch) = run.branch {
+        github["branch"] = json!(branch);
+    }
+    let seconds: Vec<f64> = group.counts.iter().map(|&c| c as f64 / 1e9).collect();
+    json!({
+        "run_id": run.run_id,
+        "batch_id": run.run_id,
+        "timestamp": timestamp,
+        "tags": tags,
+        "context": { "benchmark_language": "WebAssembly" },
+        "info": {},
+        "cluster_info": {
+            "name": machine.name,
+            "info": {
+                "arch": group.arch,
+                "os": machine.os,
+                "kernel": machine.kernel,
+                "cpu": machine.cpu,
+                "memory": machine.memory,
+            },
+            "optional_info": {},
+        },
+        "github": github,
+        "stats": {
+            "data": seconds,
+            "unit": "s",
+            "iterations": group.counts.len(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sightglass_data::Phase;
+
+    #[test]
+    fn describe_result() {
+        let run = ConbenchRun {
+            run_id: "nightly-1",
+            commit: "0123abcd",
+            repository: "https://github.com/bytecodealliance/wasmtime",
+            branch: Some("main"),
+        };
+        let machine = Machine {
+            id: "id".into(),
+            name: "bench-1".into(),
+            os: "Linux".into(),
+            kernel: "6.1".into(),
+            arch: "x86_64".into(),
+            cpu: "CPU".into(),
+            memory: "4 GiB".into(),
+        };
+        let group = Group {
+            arch: "x86_64",
+            engine: "libengine.so",
+            wasm: "benchmarks/bz2/benchmark.wasm",
+            phase: Phase::Execution,
+            event: EVENT,
+            threads: Some(4),
+            input_size: None,
+            execution: None,
+            counts: vec![1_000_000_000, 3_000_000_000],
+        };
+        let result = result(&run, &machine, "2024-01-01T00:00:00+00:00", &group);
+        assert_eq!(
+            result["tags"],
+            json!({
+                "name": "benchmarks/bz2/benchmark.wasm (4 threads) execution",
+                "engine": "libengine.so",
+                "threads": 4,
+            })
+        );
+        assert_eq!(
+            result["stats"],
+            json!({ "data": [1.0, 3.0], "unit": "s", "iterations": 2 })
+        );
+        assert_eq!(result["github"]["branch"], "main");
+        assert_eq!(result["cluster_info"]["name"], "bench-1");
+    }
+}
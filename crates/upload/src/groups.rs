@@ -0,0 +1,124 @@
+//! Group measurements into the series that benchmark dashboards (e.g., Codespeed and Conbench)
+//! track: one per benchmark, phase, event, and engine.
+use sightglass_analysis::summarize::run_label;
+use sightglass_data::{Measurement, Phase};
+use std::collections::BTreeMap;
+
+/// The counts of one event, in one phase of a benchmark, on one engine.
+#[derive(Debug, PartialEq)]
+pub(crate) struct Group<'a> {
+    pub arch: &'a str,
+    pub engine: &'a str,
+    pub wasm: &'a str,
+    pub phase: Phase,
+    pub event: &'a str,
+    pub threads: Option<u32>,
+    pub input_size: Option<&'a str>,
+    pub execution: Option<u32>,
+    pub counts: Vec<u64>,
+}
+
+impl Group<'_> {
+    /// Name the benchmark the group measures, for dashboards that know nothing of phases; e.g.,
+    /// `benchmarks/bz2/benchmark.wasm (4 threads) execution`.
+    pub fn name(&self) -> String {
+        format!(
+            "{}{} {}",
+            self.wasm,
+            run_label(self.threads, self.input_size, self.execution),
+            self.phase
+        )
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.counts.iter().sum::<u64>() as f64 / self.counts.len() as f64
+    }
+
+    /// The (sample) standard deviation of the counts.
+    pub fn std_dev(&self) -> f64 {
+        if self.counts.len() < 2 {
+            return 0.0;
+        }
+        let mean = self.mean();
+        let sum_of_squares: f64 = self.counts.iter().map(|&c| (c as f64 - mean).powi(2)).sum();
+        (sum_of_squares / (self.counts.len() - 1) as f64).sqrt()
+    }
+
+    pub fn min(&self) -> u64 {
+        self.counts.iter().copied().min().unwrap_or_default()
+    }
+
+    pub fn max(&self) -> u64 {
+        self.counts.iter().copied().max().unwrap_or_default()
+    }
+}
+
+/// Group the counts of `measurements`, in a stable order.
+pub(crate) fn group<'a>(measurements: &'a [Measurement<'_>]) -> Vec<Group<'a>> {
+    let mut groups = BTreeMap::new();
+    for m in measurements {
+        let key = (
+            m.wasm.as_ref(),
+            m.phase,
+            m.event.as_ref(),
+            m.engine.as_ref(),
+            m.arch.as_ref(),
+            m.threads,
+            m.input_size.as_deref(),
+            m.execution,
+        );
+        groups
+            .entry(key)
+            .or_insert_with(|| Group {
+                arch: key.4,
+                engine: key.3,
+                wasm: key.0,
+                phase: key.1,
+                event: key.2,
+                threads: key.5,
+                input_size: key.6,
+                execution: key.7,
+                counts: vec![],
+            })
+            .counts
+            .push(m.count);
+    }
+    groups.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_measurements() {
+        let measurement = |event: &'static str, count| Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: "benchmark.wasm".into(),
+            process: 1,
+            iteration: 0,
+            phase: Phase::Execution,
+            event: event.into(),
+            count,
+            threads: Some(2),
+            input_size: None,
+            execution: None,
+        };
+        let measurements = vec![
+            measurement("cycles", 2),
+            measurement("nanoseconds", 10),
+            measurement("cycles", 4),
+            measurement("cycles", 6),
+        ];
+        let groups = group(&measurements);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].event, "cycles");
+        assert_eq!(groups[0].counts, [2, 4, 6]);
+        assert_eq!(groups[0].name(), "benchmark.wasm (2 threads) execution");
+        assert_eq!(groups[0].mean(), 4.0);
+        assert_eq!(groups[0].std_dev(), 2.0);
+        assert_eq!((groups[0].min(), groups[0].max()), (2, 6));
+        assert_eq!(groups[1].std_dev(), 0.0);
+    }
+}
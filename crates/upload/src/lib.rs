@@ -1,9 +1,21 @@
-//! Upload Sightglass data into an ElasticSearch database.
+//! Upload Sightglass data into an ElasticSearch database or, with the `postgres` feature, a
+//! PostgreSQL database; post it to a Codespeed or Conbench dashboard; or export it over OTLP.
+mod codespeed;
+mod conbench;
 mod database;
+mod groups;
 mod measurement;
+mod otlp;
+#[cfg(feature = "postgres")]
+mod postgres;
 
+pub use crate::codespeed::{upload_codespeed, CodespeedRun};
+pub use crate::conbench::{upload_conbench, ConbenchRun};
 use crate::database::Database;
 use crate::measurement::UploadMeasurement;
+pub use crate::otlp::upload_otlp;
+#[cfg(feature = "postgres")]
+pub use crate::postgres::upload_postgres;
 use anyhow::{Context, Result};
 pub use measurement::MeasurementPackage;
 use sightglass_data::Measurement;
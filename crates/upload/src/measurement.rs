@@ -42,6 +42,19 @@ pub struct UploadMeasurement<'a> {
     /// instructions if the event is instructions retired.
     pub count: u64,
 
+    /// The number of threads the benchmark was run with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threads: Option<u32>,
+
+    /// The input size the benchmark was run with, if not its default input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_size: Option<Cow<'a, str>>,
+
+    /// The execution of its instance the measurement was taken during, if
+    /// each instance was executed several times.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub execution: Option<u32>,
+
     /// When the measurement was collected into a package (not necessarily when
     /// it was measured).
     pub datetime: Cow<'a, str>,
@@ -64,6 +77,9 @@ impl<'a> UploadMeasurement<'a> {
             phase: measurement.phase,
             event: Cow::Borrowed(measurement.event.as_ref()),
             count: measurement.count,
+            threads: measurement.threads,
+            input_size: measurement.input_size.as_deref().map(Cow::Borrowed),
+            execution: measurement.execution,
             datetime: Cow::Borrowed(datetime),
         }
     }
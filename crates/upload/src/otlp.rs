@@ -0,0 +1,341 @@
+//! Export Sightglass results to an OpenTelemetry collector, or any other receiver of OTLP/HTTP, in
+//! its JSON encoding.
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use serde_json::{json, Value};
+use sightglass_analysis::summarize::run_label;
+use sightglass_data::{Measurement, Phase};
+use sightglass_fingerprint::Machine;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The event whose counts time the spans of a trace.
+const EVENT: &str = "nanoseconds";
+
+/// Export each of the `measurements` to the OTLP/HTTP receiver at `endpoint` (e.g.,
+/// `http://localhost:4318`, to which `/v1/metrics` is appended) as a data point of a gauge named
+/// for its event (e.g., `sightglass.cycles`), with the engine, benchmark, phase, process, and
+/// iteration (and execution, if any) as attributes. If `traces` is set, each benchmark run is also
+/// exported (to `/v1/traces`) as a span with a child span per phase (and per execution of the
+/// execution phase), timed by the `nanoseconds` event; since
+/// Sightglass does not record when each run happened, the runs are laid end to end, ending now.
+/// The `headers` (e.g., an `Authorization` header) are sent with each request. If `dry_run` is
+/// set, the requests are printed rather than sent.
+pub fn upload_otlp(
+    endpoint: &str,
+    headers: &[(String, String)],
+    traces: bool,
+    dry_run: bool,
+    measurements: &[Measurement],
+) -> Result<()> {
+    let machine = Machine::fingerprint()?;
+    let resource = json!({
+        "attributes": [
+            string("service.name", "sightglass"),
+            string("host.name", &machine.name),
+            string("os.description", &format!("{} {}", machine.os, machine.kernel)),
+        ],
+    });
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("the system clock is before the Unix epoch")?
+        .as_nanos() as u64;
+    let mut requests = vec![("metrics", metrics_request(&resource, now, measurements))];
+    if traces {
+        let request = traces_request(&resource, now, measurements, &mut rand::random);
+        requests.push(("traces", request));
+    }
+    if dry_run {
+        for (_, request) in &requests {
+            println!("{}", serde_json::to_string_pretty(request)?);
+        }
+        return Ok(());
+    }
+
+    let client = Client::new();
+    for (signal, request) in &requests {
+        let url = format!("{}/v1/{}", endpoint.trim_end_matches('/'), signal);
+        let mut request = client
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(request)?);
+        for (name, value) in headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .with_context(|| format!("failed to export {} to {}", signal, url))?;
+        log::info!("Exported {} to {}", signal, url);
+    }
+    Ok(())
+}
+
+/// Describe each measurement as a gauge data point, in an `ExportMetricsServiceRequest`.
+fn metrics_request(resource: &Value, now: u64, measurements: &[Measurement]) -> Value {
+    let mut events: BTreeMap<&str, Vec<Value>> = BTreeMap::new();
+    for m in measurements {
+        let mut attributes = run_attributes(m);
+        attributes.push(string("sightglass.phase", &m.phase.to_string()));
+        if let Some(execution) = m.execution {
+            attributes.push(int("sightglass.execution", execution as u64));
+        }
+        events.entry(m.event.as_ref()).or_default().push(json!({
+            "attributes": attributes,
+            "timeUnixNano": now.to_string(),
+            "asInt": m.count.to_string(),
+        }));
+    }
+    let metrics: Vec<Value> = events
+        .into_iter()
+        .map(|(event, data_points)| {
+            json!({
+                "name": format!("sightglass.{}", event),
+                "unit": if event == EVENT { "ns" } else { "1" },
+                "gauge": { "dataPoints": data_points },
+            })
+        })
+        .collect();
+    json!({
+        "resourceMetrics": [{
+            "resource": resource,
+            "scopeMetrics": [{ "scope": scope(), "metrics": metrics }],
+        }],
+    })
+}
+
+/// A phase of a benchmark run, and the execution of its instance if there were several.
+type Step = (Phase, Option<u32>);
+
+/// The measurements of one benchmark run (i.e., one iteration of a process), by step.
+struct Run<'a> {
+    measurement: &'a Measurement<'a>,
+    phases: BTreeMap<Step, Vec<(&'a str, u64)>>,
+}
+
+impl Run<'_> {
+    fn duration(&self, phase: Step) -> u64 {
+        self.phases[&phase]
+            .iter()
+            .find(|(event, _)| *event == EVENT)
+            .map(|(_, count)| *count)
+            .unwrap_or_default()
+    }
+}
+
+/// Describe each benchmark run as a trace, in an `ExportTraceServiceRequest`: a span for the run
+/// with a child span for each of its phases, which has the phase's event counts as attributes.
+/// The trace and span IDs come from `random`.
+fn traces_request(
+    resource: &Value,
+    now: u64,
+    measurements: &[Measurement],
+    random: &mut dyn FnMut() -> u128,
+) -> Value {
+    // Collect the runs in the order they were measured.
+    let mut runs: Vec<Run> = vec![];
+    let mut indexes = HashMap::new();
+    for m in measurements {
+        let key = (
+            m.engine.as_ref(),
+            m.wasm.as_ref(),
+            m.arch.as_ref(),
+            m.threads,
+            m.input_size.as_deref(),
+            m.process,
+            m.iteration,
+        );
+        let index = *indexes.entry(key).or_insert_with(|| {
+            runs.push(Run {
+                measurement: m,
+                phases: BTreeMap::new(),
+            });
+            runs.len() - 1
+        });
+        runs[index]
+            .phases
+            .entry((m.phase, m.execution))
+            .or_default()
+            .push((m.event.as_ref(), m.count));
+    }
+    runs.retain(|run| run.phases.values().flatten().any(|(e, _)| *e == EVENT));
+    if runs.is_empty() {
+        log::warn!("No `{}` results to time the spans of traces", EVENT);
+    }
+
+    let total: u64 = runs
+        .iter()
+        .flat_map(|run| run.phases.keys().map(move |&phase| run.duration(phase)))
+        .sum();
+    let mut time = now.saturating_sub(total);
+    let mut spans = vec![];
+    for run in &runs {
+        let m = run.measurement;
+        let trace_id = format!("{:032x}", random());
+        let span_id = format!("{:016x}", random() as u64);
+        let start = time;
+        let mut children = vec![];
+        for (&(phase, execution), counts) in &run.phases {
+            let mut attributes = vec![string("sightglass.phase", &phase.to_string())];
+            if let Some(execution) = execution {
+                attributes.push(int("sightglass.execution", execution as u64));
+            }
+            for (event, count) in counts {
+                attributes.push(int(&format!("sightglass.{}", event), *count));
+            }
+            let end = time + run.duration((phase, execution));
+            children.push(json!({
+                "traceId": trace_id,
+                "spanId": format!("{:016x}", random() as u64),
+                "parentSpanId": span_id,
+                "name": phase.to_string(),
+                "kind": 1,
+                "startTimeUnixNano": time.to_string(),
+                "endTimeUnixNano": end.to_string(),
+                "attributes": attributes,
+            }));
+            time = end;
+        }
+        spans.push(json!({
+            "traceId": trace_id,
+            "spanId": span_id,
+            "name": format!("{}{}", m.wasm, run_label(m.threads, m.input_size.as_deref(), None)),
+            "kind": 1,
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": time.to_string(),
+            "attributes": run_attributes(m),
+        }));
+        spans.extend(children);
+    }
+    json!({
+        "resourceSpans": [{
+            "resource": resource,
+            "scopeSpans": [{ "scope": scope(), "spans": spans }],
+        }],
+    })
+}
+
+/// The attributes of the benchmark run a measurement belongs to.
+fn run_attributes(m: &Measurement) -> Vec<Value> {
+    let mut attributes = vec![
+        string("host.arch", &m.arch),
+        string("sightglass.engine", &m.engine),
+        string("sightglass.benchmark", &m.wasm),
+        int("sightglass.process", m.process as u64),
+        int("sightglass.iteration", m.iteration as u64),
+    ];
+    if let Some(threads) = m.threads {
+        attributes.push(int("sightglass.threads", threads as u64));
+    }
+    if let Some(input_size) = &m.input_size {
+        attributes.push(string("sightglass.input_size", input_size));
+    }
+    attributes
+}
+
+fn scope() -> Value {
+    json!({ "name": "sightglass", "version": env!("CARGO_PKG_VERSION") })
+}
+
+fn string(key: &str, value: &str) -> Value {
+    json!({ "key": key, "value": { "stringValue": value } })
+}
+
+fn int(key: &str, value: u64) -> Value {
+    json!({ "key": key, "value": { "intValue": value.to_string() } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measurement(
+        iteration: u32,
+        phase: Phase,
+        event: &'static str,
+        count: u64,
+    ) -> Measurement<'static> {
+        Measurement {
+            arch: "x86_64".into(),
+            engine: "libengine.so".into(),
+            wasm: "benchmarks/bz2/benchmark.wasm".into(),
+            process: 1,
+            iteration,
+            phase,
+            event: event.into(),
+            count,
+            threads: Some(4),
+            input_size: None,
+            execution: None,
+        }
+    }
+
+    #[test]
+    fn describe_metrics() {
+        let measurements = vec![
+            measurement(0, Phase::Execution, "cycles", 200),
+            measurement(0, Phase::Execution, "nanoseconds", 100),
+            measurement(1, Phase::Execution, "cycles", 300),
+        ];
+        let request = metrics_request(&json!({}), 1000, &measurements);
+        let metrics = &request["resourceMetrics"][0]["scopeMetrics"][0]["metrics"];
+        assert_eq!(metrics[0]["name"], "sightglass.cycles");
+        assert_eq!(metrics[1]["unit"], "ns");
+        let points = metrics[0]["gauge"]["dataPoints"].as_array().unwrap();
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1]["asInt"], "300");
+        assert_eq!(points[1]["timeUnixNano"], "1000");
+        assert!(points[1]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&int("sightglass.iteration", 1)));
+        assert!(points[1]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&string("sightglass.phase", "execution")));
+    }
+
+    #[test]
+    fn describe_traces() {
+        let measurements = vec![
+            measurement(0, Phase::Compilation, "nanoseconds", 30),
+            measurement(0, Phase::Execution, "nanoseconds", 50),
+            measurement(0, Phase::Execution, "cycles", 200),
+            measurement(1, Phase::Execution, "nanoseconds", 20),
+        ];
+        let mut id = 0;
+        let request = traces_request(&json!({}), 1000, &measurements, &mut || {
+            id += 1;
+            id
+        });
+        let spans = request["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        let times = |span: &Value| {
+            (
+                span["startTimeUnixNano"].as_str().unwrap().to_string(),
+                span["endTimeUnixNano"].as_str().unwrap().to_string(),
+            )
+        };
+        // The first run, then its compilation and execution; the second run, then its execution.
+        assert_eq!(spans.len(), 5);
+        assert_eq!(
+            spans[0]["name"],
+            "benchmarks/bz2/benchmark.wasm (4 threads)"
+        );
+        assert_eq!(spans[0]["traceId"], format!("{:032x}", 1));
+        assert_eq!(spans[0]["spanId"], format!("{:016x}", 2));
+        assert_eq!(times(&spans[0]), ("900".into(), "980".into()));
+        assert_eq!(spans[1]["name"], "compilation");
+        assert_eq!(spans[1]["parentSpanId"], spans[0]["spanId"]);
+        assert_eq!(times(&spans[1]), ("900".into(), "930".into()));
+        assert_eq!(times(&spans[2]), ("930".into(), "980".into()));
+        assert!(spans[2]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&int("sightglass.cycles", 200)));
+        assert_eq!(spans[3]["traceId"], format!("{:032x}", 5));
+        assert_eq!(times(&spans[3]), ("980".into(), "1000".into()));
+    }
+}
@@ -0,0 +1,407 @@
+//! Upload Sightglass data into a PostgreSQL database.
+use crate::measurement::MeasurementPackage;
+use ::postgres::{types::ToSql, Client, NoTls};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use sightglass_data::{EffectSize, Summary};
+use std::{borrow::Cow, collections::HashMap};
+
+/// The tables that uploads are inserted into, created if they do not already exist. Each upload is
+/// a run, on one machine, of measurements (with their summaries and effect sizes) of fingerprinted
+/// engines and benchmarks; the fingerprints are stored as JSON. The tables of earlier uploads gain
+/// the columns added since.
+const TABLES: &str = "
+CREATE TABLE IF NOT EXISTS machines (
+    id TEXT PRIMARY KEY,
+    fingerprint JSONB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS engines (
+    id TEXT PRIMARY KEY,
+    fingerprint JSONB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS benchmarks (
+    id TEXT PRIMARY KEY,
+    fingerprint JSONB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS runs (
+    id BIGSERIAL PRIMARY KEY,
+    machine TEXT NOT NULL REFERENCES machines (id),
+    datetime TIMESTAMPTZ NOT NULL
+);
+CREATE TABLE IF NOT EXISTS measurements (
+    run BIGINT NOT NULL REFERENCES runs (id),
+    engine TEXT NOT NULL REFERENCES engines (id),
+    benchmark TEXT NOT NULL REFERENCES benchmarks (id),
+    process BIGINT NOT NULL,
+    iteration BIGINT NOT NULL,
+    phase TEXT NOT NULL,
+    event TEXT NOT NULL,
+    count BIGINT NOT NULL,
+    threads BIGINT,
+    input_size TEXT,
+    execution BIGINT
+);
+CREATE TABLE IF NOT EXISTS summaries (
+    run BIGINT NOT NULL REFERENCES runs (id),
+    engine TEXT NOT NULL REFERENCES engines (id),
+    benchmark TEXT NOT NULL REFERENCES benchmarks (id),
+    phase TEXT NOT NULL,
+    event TEXT NOT NULL,
+    min BIGINT NOT NULL,
+    max BIGINT NOT NULL,
+    median BIGINT NOT NULL,
+    mean DOUBLE PRECISION NOT NULL,
+    mean_deviation DOUBLE PRECISION NOT NULL,
+    threads BIGINT,
+    input_size TEXT,
+    execution BIGINT
+);
+CREATE TABLE IF NOT EXISTS effect_sizes (
+    run BIGINT NOT NULL REFERENCES runs (id),
+    benchmark TEXT NOT NULL REFERENCES benchmarks (id),
+    phase TEXT NOT NULL,
+    event TEXT NOT NULL,
+    a_engine TEXT NOT NULL REFERENCES engines (id),
+    a_mean DOUBLE PRECISION NOT NULL,
+    b_engine TEXT NOT NULL REFERENCES engines (id),
+    b_mean DOUBLE PRECISION NOT NULL,
+    significance_level DOUBLE PRECISION NOT NULL,
+    half_width_confidence_interval DOUBLE PRECISION NOT NULL,
+    threads BIGINT,
+    input_size TEXT,
+    execution BIGINT
+);
+ALTER TABLE measurements ADD COLUMN IF NOT EXISTS execution BIGINT;
+ALTER TABLE summaries ADD COLUMN IF NOT EXISTS execution BIGINT;
+ALTER TABLE effect_sizes ADD COLUMN IF NOT EXISTS execution BIGINT;
+";
+
+/// PostgreSQL limits the number of parameters of a statement; batches of rows are kept under it.
+const MAX_PARAMETERS: usize = u16::MAX as usize;
+
+/// Upload the packaged measurements, with their `summaries` and `effect_sizes` (see
+/// `sightglass-analysis`), to the PostgreSQL database at `url` (e.g.,
+/// `postgresql://user@localhost/sightglass`), in the tables of `schema`.
+///
+/// The schema and its tables are created as needed, and the fingerprinted machine, engines, and
+/// benchmarks are only inserted if they are not already in the database. Everything else is
+/// inserted as a new run, in multi-row `INSERT`s of up to `batch_size` rows, within one
+/// transaction so that a failed upload leaves no partial run behind. If `dry_run` is set, nothing
+/// is sent to the database; the statements are only logged.
+pub fn upload_postgres(
+    url: &str,
+    schema: &str,
+    batch_size: usize,
+    dry_run: bool,
+    package: &MeasurementPackage,
+    summaries: &[Summary],
+    effect_sizes: &[EffectSize],
+) -> Result<()> {
+    anyhow::ensure!(
+        schema.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && schema
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        "invalid schema name: {}",
+        schema
+    );
+    let client = if dry_run {
+        None
+    } else {
+        Some(Client::connect(url, NoTls).context("failed to connect to the database")?)
+    };
+    let mut database = Database {
+        client,
+        batch_size: batch_size.max(1),
+    };
+
+    database.batch_execute(&format!(
+        "CREATE SCHEMA IF NOT EXISTS {0}; SET search_path TO {0};{1}",
+        schema, TABLES
+    ))?;
+    database.batch_execute("BEGIN")?;
+    match insert_run(&mut database, package, summaries, effect_sizes) {
+        Ok(run) => {
+            database.batch_execute("COMMIT")?;
+            log::info!(
+                "Uploaded run {} ({} measurements)",
+                run,
+                package.measurements.len()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            database.batch_execute("ROLLBACK")?;
+            Err(e)
+        }
+    }
+}
+
+/// Insert the package as a new run, returning the run's ID.
+fn insert_run(
+    database: &mut Database,
+    package: &MeasurementPackage,
+    summaries: &[Summary],
+    effect_sizes: &[EffectSize],
+) -> Result<i64> {
+    database.insert_fingerprint("machines", &package.machine.id, &package.machine)?;
+    let mut engines = HashMap::new();
+    for (path, fingerprint) in &package.engines {
+        database.insert_fingerprint("engines", &fingerprint.id, fingerprint)?;
+        engines.insert(path.as_ref(), fingerprint.id.as_str());
+    }
+    let mut benchmarks = HashMap::new();
+    for (path, fingerprint) in &package.benchmarks {
+        database.insert_fingerprint("benchmarks", &fingerprint.id, fingerprint)?;
+        benchmarks.insert(path.as_ref(), fingerprint.id.as_str());
+    }
+    let engine = |path: &Cow<str>| {
+        engines
+            .get(path.as_ref())
+            .map(|id| id.to_string())
+            .with_context(|| format!("the engine {} was not fingerprinted", path))
+    };
+    let benchmark = |path: &Cow<str>| {
+        benchmarks
+            .get(path.as_ref())
+            .map(|id| id.to_string())
+            .with_context(|| format!("the benchmark {} was not fingerprinted", path))
+    };
+
+    let run = database.insert_returning_id(
+        "INSERT INTO runs (machine, datetime) VALUES ($1, CAST($2 AS TEXT)::TIMESTAMPTZ) \
+         RETURNING id",
+        &[&package.machine.id, &package.datetime.as_ref()],
+    )?;
+
+    let mut rows: Vec<Row> = vec![];
+    for m in &package.measurements {
+        rows.push(vec![
+            Box::new(run),
+            Box::new(engine(&m.engine)?),
+            Box::new(benchmark(&m.wasm)?),
+            Box::new(i64::from(m.process)),
+            Box::new(i64::from(m.iteration)),
+            Box::new(m.phase.to_string()),
+            Box::new(m.event.to_string()),
+            Box::new(count(m.count)?),
+            Box::new(m.threads.map(i64::from)),
+            Box::new(m.input_size.as_deref().map(str::to_string)),
+            Box::new(m.execution.map(i64::from)),
+        ]);
+    }
+    database.insert_batched(
+        "measurements",
+        &[
+            "run",
+            "engine",
+            "benchmark",
+            "process",
+            "iteration",
+            "phase",
+            "event",
+            "count",
+            "threads",
+            "input_size",
+            "execution",
+        ],
+        &rows,
+    )?;
+
+    let mut rows: Vec<Row> = vec![];
+    for s in summaries {
+        rows.push(vec![
+            Box::new(run),
+            Box::new(engine(&s.engine)?),
+            Box::new(benchmark(&s.wasm)?),
+            Box::new(s.phase.to_string()),
+            Box::new(s.event.to_string()),
+            Box::new(count(s.min)?),
+            Box::new(count(s.max)?),
+            Box::new(count(s.median)?),
+            Box::new(s.mean),
+            Box::new(s.mean_deviation),
+            Box::new(s.threads.map(i64::from)),
+            Box::new(s.input_size.as_deref().map(str::to_string)),
+            Box::new(s.execution.map(i64::from)),
+        ]);
+    }
+    database.insert_batched(
+        "summaries",
+        &[
+            "run",
+            "engine",
+            "benchmark",
+            "phase",
+            "event",
+            "min",
+            "max",
+            "median",
+            "mean",
+            "mean_deviation",
+            "threads",
+            "input_size",
+            "execution",
+        ],
+        &rows,
+    )?;
+
+    let mut rows: Vec<Row> = vec![];
+    for e in effect_sizes {
+        rows.push(vec![
+            Box::new(run),
+            Box::new(benchmark(&e.wasm)?),
+            Box::new(e.phase.to_string()),
+            Box::new(e.event.to_string()),
+            Box::new(engine(&e.a_engine)?),
+            Box::new(e.a_mean),
+            Box::new(engine(&e.b_engine)?),
+            Box::new(e.b_mean),
+            Box::new(e.significance_level),
+            Box::new(e.half_width_confidence_interval),
+            Box::new(e.threads.map(i64::from)),
+            Box::new(e.input_size.as_deref().map(str::to_string)),
+            Box::new(e.execution.map(i64::from)),
+        ]);
+    }
+    database.insert_batched(
+        "effect_sizes",
+        &[
+            "run",
+            "benchmark",
+            "phase",
+            "event",
+            "a_engine",
+            "a_mean",
+            "b_engine",
+            "b_mean",
+            "significance_level",
+            "half_width_confidence_interval",
+            "threads",
+            "input_size",
+            "execution",
+        ],
+        &rows,
+    )?;
+
+    Ok(run)
+}
+
+/// PostgreSQL has no unsigned integers; counts are stored as `BIGINT`.
+fn count(count: u64) -> Result<i64> {
+    i64::try_from(count).with_context(|| format!("the count {} is too large to upload", count))
+}
+
+/// The values of one row to insert.
+type Row = Vec<Box<dyn ToSql + Sync>>;
+
+/// A connection to a PostgreSQL database, or none for a dry run, in which statements are only
+/// logged.
+struct Database {
+    client: Option<Client>,
+    batch_size: usize,
+}
+
+impl Database {
+    fn batch_execute(&mut self, statements: &str) -> Result<()> {
+        log::debug!("Executing: {}", statements.trim());
+        if let Some(client) = &mut self.client {
+            client.batch_execute(statements)?;
+        }
+        Ok(())
+    }
+
+    /// Insert a fingerprinted machine, engine, or benchmark into `table`, unless one with the same
+    /// ID is already there.
+    fn insert_fingerprint<T: Serialize>(
+        &mut self,
+        table: &str,
+        id: &str,
+        fingerprint: &T,
+    ) -> Result<()> {
+        let statement = format!(
+            "INSERT INTO {} (id, fingerprint) VALUES ($1, CAST($2 AS TEXT)::JSONB) \
+             ON CONFLICT (id) DO NOTHING",
+            table
+        );
+        let fingerprint = serde_json::to_string(fingerprint)?;
+        log::debug!("Executing: {}", statement);
+        log::trace!("Fingerprint {}: {}", id, fingerprint);
+        if let Some(client) = &mut self.client {
+            client
+                .execute(statement.as_str(), &[&id, &fingerprint])
+                .with_context(|| format!("failed to insert {} into {}", id, table))?;
+        }
+        Ok(())
+    }
+
+    fn insert_returning_id(
+        &mut self,
+        statement: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<i64> {
+        log::debug!("Executing: {}", statement);
+        match &mut self.client {
+            Some(client) => Ok(client.query_one(statement, params)?.try_get(0)?),
+            None => Ok(0),
+        }
+    }
+
+    /// Insert `rows` into `table` with as few multi-row `INSERT`s as the batch size allows.
+    fn insert_batched(&mut self, table: &str, columns: &[&str], rows: &[Row]) -> Result<()> {
+        let rows_per_batch = self.batch_size.min(MAX_PARAMETERS / columns.len());
+        for batch in rows.chunks(rows_per_batch) {
+            let statement = insert_statement(table, columns, batch.len());
+            log::debug!("Inserting {} rows into {}", batch.len(), table);
+            if let Some(client) = &mut self.client {
+                let params: Vec<&(dyn ToSql + Sync)> = batch
+                    .iter()
+                    .flat_map(|row| {
+                        debug_assert_eq!(row.len(), columns.len());
+                        row.iter().map(|value| value.as_ref())
+                    })
+                    .collect();
+                client
+                    .execute(statement.as_str(), &params)
+                    .with_context(|| format!("failed to insert into {}", table))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Build an `INSERT` of `rows` rows into `columns` of `table`, numbering the parameters row by
+/// row: `INSERT INTO t (a, b) VALUES ($1, $2), ($3, $4)`.
+fn insert_statement(table: &str, columns: &[&str], rows: usize) -> String {
+    let values: Vec<String> = (0..rows)
+        .map(|row| {
+            let params: Vec<String> = (1..=columns.len())
+                .map(|column| format!("${}", row * columns.len() + column))
+                .collect();
+            format!("({})", params.join(", "))
+        })
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES {}",
+        table,
+        columns.join(", "),
+        values.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_insert_statements() {
+        assert_eq!(
+            insert_statement("runs", &["a", "b"], 2),
+            "INSERT INTO runs (a, b) VALUES ($1, $2), ($3, $4)"
+        );
+        assert_eq!(
+            insert_statement("runs", &["a"], 1),
+            "INSERT INTO runs (a) VALUES ($1)"
+        );
+    }
+}
@@ -0,0 +1,352 @@
+//! An implementation of the Sightglass bench API that drives V8 through a
+//! Node.js process. This mirrors Wasmtime's `wasmtime-bench-api` crate so
+//! that the resulting shared library can be passed to `sightglass-cli
+//! benchmark --engine` like any other engine.
+//!
+//! Each benchmark state starts a `node` process running `shim.js` and sends it
+//! one command per phase; the bench API timers are started and stopped here,
+//! around each command, and around the benchmark's `bench.start` and
+//! `bench.end` calls, which the script forwards back. The engine flags
+//! (`--engine-flags`) are passed to `node`, so V8's tiers can be selected with
+//! e.g. `--liftoff --no-wasm-tier-up` or `--no-liftoff`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::env;
+use std::ffi::c_void;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::slice;
+
+/// The script run by `node`; see its header for the protocol.
+const SHIM: &str = include_str!("shim.js");
+
+/// The `node` binary to run, fixed at build time if `SIGHTGLASS_NODE` is set.
+const NODE: &str = match option_env!("SIGHTGLASS_NODE") {
+    Some(node) => node,
+    None => "node",
+};
+
+/// NB: Keep this in sync with the version defined in `sightglass-recorder`
+/// (and in `wasmtime-bench-api`)!
+#[repr(C)]
+pub struct WasmBenchConfig {
+    working_dir_ptr: *const u8,
+    working_dir_len: usize,
+
+    stdout_path_ptr: *const u8,
+    stdout_path_len: usize,
+
+    stderr_path_ptr: *const u8,
+    stderr_path_len: usize,
+
+    stdin_path_ptr: *const u8,
+    stdin_path_len: usize,
+
+    compilation_timer: *mut u8,
+    compilation_start: extern "C" fn(*mut u8),
+    compilation_end: extern "C" fn(*mut u8),
+
+    instantiation_timer: *mut u8,
+    instantiation_start: extern "C" fn(*mut u8),
+    instantiation_end: extern "C" fn(*mut u8),
+
+    execution_timer: *mut u8,
+    execution_start: extern "C" fn(*mut u8),
+    execution_end: extern "C" fn(*mut u8),
+
+    execution_flags_ptr: *const u8,
+    execution_flags_len: usize,
+}
+
+impl WasmBenchConfig {
+    unsafe fn string(ptr: *const u8, len: usize) -> Result<Option<String>> {
+        if ptr.is_null() {
+            return Ok(None);
+        }
+        let bytes = slice::from_raw_parts(ptr, len);
+        Ok(Some(String::from_utf8(bytes.to_vec())?))
+    }
+}
+
+/// A pair of timer callbacks, for one phase, as passed in the config.
+#[derive(Clone, Copy)]
+struct Timer {
+    data: *mut u8,
+    start: extern "C" fn(*mut u8),
+    end: extern "C" fn(*mut u8),
+}
+
+impl Timer {
+    fn start(&self) {
+        (self.start)(self.data)
+    }
+
+    fn end(&self) {
+        (self.end)(self.data)
+    }
+}
+
+/// The state of a single benchmark, returned to Sightglass as an opaque
+/// pointer.
+struct BenchState {
+    node: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    // The prepared arguments of the `prepare` command, sent before each
+    // instantiation.
+    prepare: String,
+    // Node reads the module from a file rather than over its stdin.
+    module_path: PathBuf,
+    compilation: Timer,
+    instantiation: Timer,
+    execution: Timer,
+}
+
+impl BenchState {
+    fn new(config: WasmBenchConfig) -> Result<Self> {
+        let (working_dir, stdout_path, stderr_path, stdin_path, flags) = unsafe {
+            (
+                WasmBenchConfig::string(config.working_dir_ptr, config.working_dir_len)?
+                    .context("a working directory is required")?,
+                WasmBenchConfig::string(config.stdout_path_ptr, config.stdout_path_len)?
+                    .context("a stdout path is required")?,
+                WasmBenchConfig::string(config.stderr_path_ptr, config.stderr_path_len)?
+                    .context("a stderr path is required")?,
+                WasmBenchConfig::string(config.stdin_path_ptr, config.stdin_path_len)?,
+                WasmBenchConfig::string(config.execution_flags_ptr, config.execution_flags_len)?,
+            )
+        };
+        let mut prepare = format!("prepare\t{}\t{}\t{}", working_dir, stdout_path, stderr_path);
+        if let Some(stdin_path) = stdin_path {
+            prepare.push('\t');
+            prepare.push_str(&stdin_path);
+        }
+
+        let mut node = Command::new(NODE)
+            .args(flags.as_deref().unwrap_or("").split_whitespace())
+            .arg("-e")
+            .arg(SHIM)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run `{}`", NODE))?;
+        let stdin = node.stdin.take().unwrap();
+        let stdout = BufReader::new(node.stdout.take().unwrap());
+        let module_path = env::temp_dir().join(format!("sightglass-v8-{}.wasm", node.id()));
+
+        Ok(Self {
+            node,
+            stdin,
+            stdout,
+            prepare,
+            module_path,
+            compilation: Timer {
+                data: config.compilation_timer,
+                start: config.compilation_start,
+                end: config.compilation_end,
+            },
+            instantiation: Timer {
+                data: config.instantiation_timer,
+                start: config.instantiation_start,
+                end: config.instantiation_end,
+            },
+            execution: Timer {
+                data: config.execution_timer,
+                start: config.execution_start,
+                end: config.execution_end,
+            },
+        })
+    }
+
+    fn compile(&mut self, bytes: &[u8]) -> Result<()> {
+        fs::write(&self.module_path, bytes)
+            .with_context(|| format!("failed to write {}", self.module_path.display()))?;
+        let load = format!("load\t{}", self.module_path.display());
+        self.request(&load)?;
+
+        self.compilation.start();
+        let result = self.request("compile");
+        self.compilation.end();
+        result
+    }
+
+    fn instantiate(&mut self) -> Result<()> {
+        let prepare = self.prepare.clone();
+        self.request(&prepare)?;
+
+        self.instantiation.start();
+        let result = self.request("instantiate");
+        self.instantiation.end();
+        result
+    }
+
+    fn execute(&mut self) -> Result<()> {
+        self.request("execute")
+    }
+
+    fn execute_entry(&mut self, name: &str) -> Result<()> {
+        self.request(&format!("execute\t{}", name))
+    }
+
+    fn set_executions(&mut self, count: u32) -> Result<()> {
+        self.request(&format!("executions\t{}", count))
+    }
+
+    /// Send a command to `node` and wait for its completion, forwarding any
+    /// `bench.start` and `bench.end` calls to the execution timer meanwhile.
+    fn request(&mut self, command: &str) -> Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()?;
+        loop {
+            let mut line = String::new();
+            if self.stdout.read_line(&mut line)? == 0 {
+                bail!("node exited unexpectedly during `{}`", command);
+            }
+            match line.trim_end_matches('\n') {
+                "ok" => return Ok(()),
+                "start" => {
+                    self.execution.start();
+                    self.ack()?;
+                }
+                "end" => {
+                    self.execution.end();
+                    self.ack()?;
+                }
+                reply => {
+                    return Err(match reply.strip_prefix("error ") {
+                        Some(message) => anyhow!("{}", message),
+                        None => anyhow!("unexpected reply from node: {}", reply),
+                    })
+                }
+            }
+        }
+    }
+
+    fn ack(&mut self) -> Result<()> {
+        writeln!(self.stdin, "ack")?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for BenchState {
+    fn drop(&mut self) {
+        let _ = self.node.kill();
+        let _ = self.node.wait();
+        let _ = fs::remove_file(&self.module_path);
+    }
+}
+
+const OK: i32 = 0;
+const ERR: i32 = -1;
+
+fn to_exit_code(result: Result<()>) -> i32 {
+    match result {
+        Ok(()) => OK,
+        Err(e) => {
+            eprintln!("v8-bench-api error: {:?}", e);
+            ERR
+        }
+    }
+}
+
+/// Create a new benchmark state, writing it to `out_bench_ptr`.
+#[no_mangle]
+pub extern "C" fn wasm_bench_create(
+    config: WasmBenchConfig,
+    out_bench_ptr: *mut *mut c_void,
+) -> i32 {
+    to_exit_code(BenchState::new(config).map(|state| {
+        assert!(!out_bench_ptr.is_null());
+        unsafe {
+            *out_bench_ptr = Box::into_raw(Box::new(state)) as *mut c_void;
+        }
+    }))
+}
+
+/// Free a benchmark state created by `wasm_bench_create`.
+#[no_mangle]
+pub extern "C" fn wasm_bench_free(state: *mut c_void) {
+    assert!(!state.is_null());
+    unsafe {
+        drop(Box::from_raw(state as *mut BenchState));
+    }
+}
+
+/// Compile the Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_compile(
+    state: *mut c_void,
+    wasm_bytes: *const u8,
+    wasm_bytes_length: usize,
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let bytes = unsafe { slice::from_raw_parts(wasm_bytes, wasm_bytes_length) };
+    to_exit_code(state.compile(bytes))
+}
+
+/// Instantiate the compiled Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_instantiate(state: *mut c_void) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    to_exit_code(state.instantiate())
+}
+
+/// Execute the instantiated Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_execute(state: *mut c_void) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    to_exit_code(state.execute())
+}
+
+/// Execute one of the entry points (e.g., `bench_parse`) of the instantiated
+/// Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_execute_entry(
+    state: *mut c_void,
+    name_ptr: *const u8,
+    name_len: usize,
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let name = unsafe { slice::from_raw_parts(name_ptr, name_len) };
+    to_exit_code(
+        std::str::from_utf8(name)
+            .context("the entry point name is not UTF-8")
+            .and_then(|name| state.execute_entry(name)),
+    )
+}
+
+/// Execute each instance `count` times rather than once.
+#[no_mangle]
+pub extern "C" fn wasm_bench_set_executions(state: *mut c_void, count: u32) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    to_exit_code(state.set_executions(count))
+}
+
+/// Report how the engine was built, one `(key, value)` item at a time: its
+/// kind, and the versions of V8 and Node.js in the `node` that the engine runs.
+#[no_mangle]
+pub extern "C" fn wasm_bench_build_info(
+    data: *mut c_void,
+    callback: extern "C" fn(*mut c_void, *const u8, usize, *const u8, usize),
+) {
+    let versions = Command::new(NODE)
+        .args(["-p", "process.versions.v8 + ' ' + process.versions.node"])
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    let mut versions = versions.split_whitespace();
+    let v8 = versions.next().unwrap_or("unknown");
+    let node = versions.next().unwrap_or("unknown");
+    for (key, value) in [
+        ("ENGINE", "node"),
+        ("V8_VERSION", v8),
+        ("NODE_VERSION", node),
+        ("NODE", NODE),
+    ] {
+        callback(data, key.as_ptr(), key.len(), value.as_ptr(), value.len());
+    }
+}
@@ -0,0 +1,149 @@
+// The Node.js side of the V8 bench API shim. The shim library starts this script in a `node`
+// process and drives it with one command per line on stdin; this script replies on stdout with
+// `ok` or `error <message>` once each command completes. While executing, the benchmark's
+// `bench.start` and `bench.end` calls are forwarded to the shim as `start` and `end` lines, and
+// this script blocks until the shim replies `ack` so that the shim's timers bracket exactly the
+// measured region.
+'use strict';
+
+const fs = require('fs');
+const { WASI } = require('wasi');
+
+let pending = Buffer.alloc(0);
+const chunk = Buffer.alloc(64 * 1024);
+
+// Read a line from stdin, synchronously, so that it can be used from within Wasm imports.
+function readLine() {
+  for (;;) {
+    const newline = pending.indexOf(10);
+    if (newline >= 0) {
+      const line = pending.subarray(0, newline).toString();
+      pending = pending.subarray(newline + 1);
+      return line;
+    }
+    let n;
+    try {
+      n = fs.readSync(0, chunk, 0, chunk.length, null);
+    } catch (e) {
+      if (e.code === 'EAGAIN') continue;
+      throw e;
+    }
+    if (n === 0) return null;
+    pending = Buffer.concat([pending, chunk.subarray(0, n)]);
+  }
+}
+
+function send(message) {
+  fs.writeSync(1, message + '\n');
+}
+
+// Forward a `bench.start` or `bench.end` call to the shim and wait for it to be measured.
+function notify(event) {
+  send(event);
+  const reply = readLine();
+  if (reply !== 'ack') throw new Error(`expected an ack from the shim, got: ${reply}`);
+}
+
+let bytes, module, wasi, instance;
+let stdio = [];
+let executions = 1;
+
+// Thrown by the benchmark's `proc_exit`, so that a command can be started again after it exits.
+class Exit {
+  constructor(code) {
+    this.code = code;
+  }
+}
+
+function closeStdio() {
+  stdio.forEach((fd) => fs.closeSync(fd));
+  stdio = [];
+}
+
+const commands = {
+  // Read the Wasm bytes outside of the measured compilation.
+  load(path) {
+    bytes = fs.readFileSync(path);
+  },
+  compile() {
+    module = new WebAssembly.Module(bytes);
+  },
+  // Set up WASI outside of the measured instantiation; stdin is the shim's channel so the
+  // benchmark reads from `/dev/null` unless given a file.
+  prepare(workingDir, stdoutPath, stderrPath, stdinPath) {
+    closeStdio();
+    stdio = [
+      fs.openSync(stdinPath || '/dev/null', 'r'),
+      fs.openSync(stdoutPath, 'w'),
+      fs.openSync(stderrPath, 'w'),
+    ];
+    wasi = new WASI({
+      version: 'preview1',
+      args: ['benchmark'],
+      env: {},
+      preopens: { '.': workingDir },
+      stdin: stdio[0],
+      stdout: stdio[1],
+      stderr: stdio[2],
+      returnOnExit: true,
+    });
+  },
+  instantiate() {
+    instance = new WebAssembly.Instance(module, {
+      wasi_snapshot_preview1: {
+        ...wasi.wasiImport,
+        proc_exit: (code) => {
+          throw new Exit(code);
+        },
+      },
+      bench: {
+        start: () => notify('start'),
+        end: () => notify('end'),
+      },
+    });
+  },
+  // Execute each instance this many times in a row.
+  executions(count) {
+    executions = Math.max(1, Number(count));
+  },
+  // Run the benchmark's `_start` or, if given, one of its `bench_*` entry points; a reactor module
+  // is initialized first. WASI only starts (or initializes) an instance once, so later executions
+  // call the export directly.
+  execute(entryPoint) {
+    try {
+      let run;
+      if (entryPoint) {
+        const entry = instance.exports[entryPoint];
+        if (typeof entry !== 'function') throw new Error(`no such entry point: ${entryPoint}`);
+        if (instance.exports._initialize) wasi.initialize(instance);
+        run = entry;
+      } else {
+        run = () => {
+          run = instance.exports._start;
+          wasi.start(instance);
+        };
+      }
+      for (let i = 0; i < executions; i++) {
+        try {
+          run();
+        } catch (e) {
+          if (!(e instanceof Exit)) throw e;
+          if (e.code) throw new Error(`benchmark exited with code ${e.code}`);
+        }
+      }
+    } finally {
+      closeStdio();
+    }
+  },
+};
+
+for (let line; (line = readLine()) !== null; ) {
+  const [command, ...args] = line.split('\t');
+  try {
+    if (!(command in commands)) throw new Error(`unknown command: ${command}`);
+    commands[command](...args);
+    send('ok');
+  } catch (e) {
+    send(`error ${String(e).replace(/\n/g, ' ')}`);
+  }
+}
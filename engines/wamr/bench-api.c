@@ -0,0 +1,439 @@
+// An implementation of the Sightglass bench API using WAMR (WebAssembly Micro Runtime). This
+// mirrors Wasmtime's `wasmtime-bench-api` crate so that the resulting shared library can be passed
+// to `sightglass-cli benchmark --engine` like any other engine.
+//
+// The engine flags (`--engine-flags`) select how WAMR runs the benchmark:
+// - `--interp` (the default): load the Wasm module into WAMR's interpreter; the compilation phase
+//   measures loading (and, for the fast interpreter, pre-compiling) the module
+// - `--aot`: compile the Wasm module ahead of time with `wamrc` (found in the `WAMRC` environment
+//   variable or on the `PATH`) and load the result; the compilation phase measures both
+//
+// With `--aot`, `sightglass-cli benchmark --precompiled` runs `wamrc` once and measures only loading
+// the AOT module, as the deserialization phase.
+//
+// With `sightglass-cli benchmark --wasm-threads`, the benchmark may use shared memories and spawn
+// threads through `wasi-threads`; it finds the number of threads to use in its `SIGHTGLASS_THREADS`
+// environment variable.
+
+#include <fcntl.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "wasm_export.h"
+
+#define OK 0
+#define ERR -1
+
+// The WAMR commit the adapter is built against, reported as build information; `build.rs` sets it.
+#ifndef SIGHTGLASS_WAMR_COMMIT
+#define SIGHTGLASS_WAMR_COMMIT "unknown"
+#endif
+
+#define STACK_SIZE (1024 * 1024)
+#define HEAP_SIZE (16 * 1024 * 1024)
+
+// NB: Keep this in sync with the version defined in `sightglass-recorder` (and in
+// `wasmtime-bench-api`)!
+typedef struct {
+    const uint8_t *working_dir_ptr;
+    size_t working_dir_len;
+
+    const uint8_t *stdout_path_ptr;
+    size_t stdout_path_len;
+
+    const uint8_t *stderr_path_ptr;
+    size_t stderr_path_len;
+
+    const uint8_t *stdin_path_ptr;
+    size_t stdin_path_len;
+
+    uint8_t *compilation_timer;
+    void (*compilation_start)(uint8_t *);
+    void (*compilation_end)(uint8_t *);
+
+    uint8_t *instantiation_timer;
+    void (*instantiation_start)(uint8_t *);
+    void (*instantiation_end)(uint8_t *);
+
+    uint8_t *execution_timer;
+    void (*execution_start)(uint8_t *);
+    void (*execution_end)(uint8_t *);
+
+    const uint8_t *execution_flags_ptr;
+    size_t execution_flags_len;
+} wasm_bench_config_t;
+
+typedef struct {
+    wasm_bench_config_t config;
+    char *working_dir;
+    char *stdout_path;
+    char *stderr_path;
+    char *stdin_path;
+    bool aot;
+    uint32_t executions;
+    // The number of threads the benchmark should use, or 0 if it is not threaded.
+    uint32_t threads;
+
+    // WAMR may modify the bytes it loads a module from, so it gets its own copy, which must live
+    // as long as the module.
+    uint8_t *module_bytes;
+    wasm_module_t module;
+    wasm_module_inst_t instance;
+    int stdio[3];
+    NativeSymbol *natives;
+    char error[128];
+} bench_state_t;
+
+static bool runtime_initialized = false;
+
+void wasm_bench_free(void *state_ptr);
+
+// The `bench.start` and `bench.end` host functions; their attachment is the benchmark state.
+static void bench_start(wasm_exec_env_t exec_env) {
+    bench_state_t *state = wasm_runtime_get_function_attachment(exec_env);
+    state->config.execution_start(state->config.execution_timer);
+}
+
+static void bench_end(wasm_exec_env_t exec_env) {
+    bench_state_t *state = wasm_runtime_get_function_attachment(exec_env);
+    state->config.execution_end(state->config.execution_timer);
+}
+
+static char *copy_string(const uint8_t *ptr, size_t len) {
+    if (ptr == NULL) {
+        return NULL;
+    }
+    char *s = malloc(len + 1);
+    memcpy(s, ptr, len);
+    s[len] = '\0';
+    return s;
+}
+
+static bool flags_equal(const char *flags, const char *expected) {
+    while (*flags == ' ') {
+        flags++;
+    }
+    size_t len = strlen(flags);
+    while (len > 0 && flags[len - 1] == ' ') {
+        len--;
+    }
+    return len == strlen(expected) && strncmp(flags, expected, len) == 0;
+}
+
+static uint8_t *read_file(const char *path, uint32_t *size) {
+    FILE *file = fopen(path, "rb");
+    if (file == NULL) {
+        return NULL;
+    }
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    uint8_t *bytes = malloc(len);
+    if (fread(bytes, 1, len, file) != (size_t)len) {
+        free(bytes);
+        bytes = NULL;
+    }
+    fclose(file);
+    *size = (uint32_t)len;
+    return bytes;
+}
+
+// Compile `wasm` ahead of time with `wamrc`, returning the bytes of the AOT module; a threaded
+// module must be compiled with `wamrc`'s multi-threading support.
+static uint8_t *compile_aot(const uint8_t *wasm, size_t wasm_len, bool threads,
+                            uint32_t *aot_len) {
+    char wasm_path[] = "/tmp/sightglass-wamr-XXXXXX";
+    int fd = mkstemp(wasm_path);
+    if (fd < 0) {
+        return NULL;
+    }
+    bool written = write(fd, wasm, wasm_len) == (ssize_t)wasm_len;
+    close(fd);
+
+    uint8_t *aot = NULL;
+    char aot_path[sizeof(wasm_path) + 4];
+    snprintf(aot_path, sizeof(aot_path), "%s.aot", wasm_path);
+    const char *wamrc = getenv("WAMRC") ? getenv("WAMRC") : "wamrc";
+    char command[4096];
+    snprintf(command, sizeof(command), "%s %s-o %s %s > /dev/null", wamrc,
+             threads ? "--enable-multi-thread " : "", aot_path, wasm_path);
+    if (written && system(command) == 0) {
+        aot = read_file(aot_path, aot_len);
+    } else {
+        fprintf(stderr, "wamr-bench-api: failed to run `%s`\n", command);
+    }
+    unlink(wasm_path);
+    unlink(aot_path);
+    return aot;
+}
+
+static void close_stdio(bench_state_t *state) {
+    for (int i = 0; i < 3; i++) {
+        if (state->stdio[i] >= 0) {
+            close(state->stdio[i]);
+            state->stdio[i] = -1;
+        }
+    }
+}
+
+static void free_instance(bench_state_t *state) {
+    if (state->instance != NULL) {
+        wasm_runtime_deinstantiate(state->instance);
+        state->instance = NULL;
+    }
+    close_stdio(state);
+}
+
+int wasm_bench_create(wasm_bench_config_t config, void **out_bench_ptr) {
+    if (!runtime_initialized) {
+        if (!wasm_runtime_init()) {
+            return ERR;
+        }
+        runtime_initialized = true;
+    }
+
+    bool aot;
+    char *flags = copy_string(config.execution_flags_ptr, config.execution_flags_len);
+    if (flags == NULL || flags_equal(flags, "") || flags_equal(flags, "--interp")) {
+        aot = false;
+    } else if (flags_equal(flags, "--aot")) {
+        aot = true;
+    } else {
+        fprintf(stderr, "wamr-bench-api: unsupported engine flags: %s\n", flags);
+        free(flags);
+        return ERR;
+    }
+    free(flags);
+
+    bench_state_t *state = calloc(1, sizeof(bench_state_t));
+    state->config = config;
+    state->aot = aot;
+    state->executions = 1;
+    state->working_dir = copy_string(config.working_dir_ptr, config.working_dir_len);
+    state->stdout_path = copy_string(config.stdout_path_ptr, config.stdout_path_len);
+    state->stderr_path = copy_string(config.stderr_path_ptr, config.stderr_path_len);
+    state->stdin_path = copy_string(config.stdin_path_ptr, config.stdin_path_len);
+    state->stdio[0] = state->stdio[1] = state->stdio[2] = -1;
+
+    // The host functions are registered once per state, each with the state as its attachment;
+    // WAMR resolves imports to the most recently registered functions when loading a module.
+    state->natives = calloc(2, sizeof(NativeSymbol));
+    state->natives[0] = (NativeSymbol){"start", bench_start, "()", state};
+    state->natives[1] = (NativeSymbol){"end", bench_end, "()", state};
+    if (!wasm_runtime_register_natives("bench", state->natives, 2)) {
+        wasm_bench_free(state);
+        return ERR;
+    }
+
+    *out_bench_ptr = state;
+    return OK;
+}
+
+void wasm_bench_free(void *state_ptr) {
+    bench_state_t *state = state_ptr;
+    free_instance(state);
+    if (state->module != NULL) {
+        wasm_runtime_unload(state->module);
+    }
+    free(state->module_bytes);
+    if (state->natives != NULL) {
+        wasm_runtime_unregister_natives("bench", state->natives);
+        free(state->natives);
+    }
+    free(state->working_dir);
+    free(state->stdout_path);
+    free(state->stderr_path);
+    free(state->stdin_path);
+    free(state);
+}
+
+int wasm_bench_compile(void *state_ptr, const uint8_t *wasm_bytes, size_t wasm_bytes_length) {
+    bench_state_t *state = state_ptr;
+    state->config.compilation_start(state->config.compilation_timer);
+    uint32_t len = (uint32_t)wasm_bytes_length;
+    if (state->aot) {
+        state->module_bytes = compile_aot(wasm_bytes, wasm_bytes_length, state->threads > 0, &len);
+    } else {
+        state->module_bytes = malloc(len);
+        memcpy(state->module_bytes, wasm_bytes, len);
+    }
+    if (state->module_bytes != NULL) {
+        state->module =
+            wasm_runtime_load(state->module_bytes, len, state->error, sizeof(state->error));
+    }
+    state->config.compilation_end(state->config.compilation_timer);
+
+    if (state->module == NULL) {
+        fprintf(stderr, "wamr-bench-api: failed to compile: %s\n", state->error);
+        return ERR;
+    }
+    return OK;
+}
+
+int wasm_bench_precompile(void *state_ptr, const uint8_t *wasm_bytes, size_t wasm_bytes_length,
+                          void *data, void (*callback)(void *, const uint8_t *, size_t)) {
+    bench_state_t *state = state_ptr;
+    if (!state->aot) {
+        fprintf(stderr, "wamr-bench-api: precompiling requires `--engine-flags=--aot`\n");
+        return ERR;
+    }
+    uint32_t len;
+    uint8_t *aot = compile_aot(wasm_bytes, wasm_bytes_length, state->threads > 0, &len);
+    if (aot == NULL) {
+        return ERR;
+    }
+    callback(data, aot, len);
+    free(aot);
+    return OK;
+}
+
+int wasm_bench_deserialize(void *state_ptr, const uint8_t *bytes, size_t bytes_length) {
+    bench_state_t *state = state_ptr;
+    uint32_t len = (uint32_t)bytes_length;
+    state->module_bytes = malloc(len);
+    memcpy(state->module_bytes, bytes, len);
+    state->module = wasm_runtime_load(state->module_bytes, len, state->error, sizeof(state->error));
+    if (state->module == NULL) {
+        fprintf(stderr, "wamr-bench-api: failed to load AOT module: %s\n", state->error);
+        return ERR;
+    }
+    return OK;
+}
+
+int wasm_bench_instantiate(void *state_ptr) {
+    bench_state_t *state = state_ptr;
+    free_instance(state);
+
+    // Each instance gets fresh WASI stdio, writing to the files Sightglass asked for.
+    state->stdio[0] = state->stdin_path ? open(state->stdin_path, O_RDONLY) : dup(0);
+    state->stdio[1] = open(state->stdout_path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    state->stdio[2] = open(state->stderr_path, O_WRONLY | O_CREAT | O_TRUNC, 0644);
+    if (state->stdio[0] < 0 || state->stdio[1] < 0 || state->stdio[2] < 0) {
+        close_stdio(state);
+        return ERR;
+    }
+    const char *map_dirs[] = {NULL};
+    char map_dir[4096];
+    snprintf(map_dir, sizeof(map_dir), ".::%s", state->working_dir);
+    map_dirs[0] = map_dir;
+    char *argv[] = {"benchmark"};
+    char threads_env[64];
+    const char *env[] = {threads_env};
+    snprintf(threads_env, sizeof(threads_env), "SIGHTGLASS_THREADS=%u", state->threads);
+    wasm_runtime_set_wasi_args_ex(state->module, NULL, 0, map_dirs, 1, env,
+                                  state->threads > 0 ? 1 : 0, argv, 1, state->stdio[0],
+                                  state->stdio[1], state->stdio[2]);
+
+    state->config.instantiation_start(state->config.instantiation_timer);
+    state->instance = wasm_runtime_instantiate(state->module, STACK_SIZE, HEAP_SIZE, state->error,
+                                               sizeof(state->error));
+    state->config.instantiation_end(state->config.instantiation_timer);
+
+    if (state->instance == NULL) {
+        fprintf(stderr, "wamr-bench-api: failed to instantiate: %s\n", state->error);
+        close_stdio(state);
+        return ERR;
+    }
+    return OK;
+}
+
+int wasm_bench_execute(void *state_ptr) {
+    bench_state_t *state = state_ptr;
+    if (state->instance == NULL) {
+        return ERR;
+    }
+    char *argv[] = {"benchmark"};
+    bool success = true;
+    for (uint32_t i = 0; success && i < state->executions; i++) {
+        success = wasm_application_execute_main(state->instance, 1, argv);
+        const char *exception = wasm_runtime_get_exception(state->instance);
+        // A WASI `proc_exit` is reported as an exception; only a zero exit code is a success.
+        if (!success && exception != NULL && strstr(exception, "wasi proc exit") != NULL) {
+            success = wasm_runtime_get_wasi_exit_code(state->instance) == 0;
+            wasm_runtime_clear_exception(state->instance);
+        }
+        if (!success) {
+            fprintf(stderr, "wamr-bench-api: failed to execute: %s\n",
+                    exception ? exception : "unknown error");
+        }
+    }
+    free_instance(state);
+    return success ? OK : ERR;
+}
+
+int wasm_bench_execute_entry(void *state_ptr, const uint8_t *name_ptr, size_t name_len) {
+    bench_state_t *state = state_ptr;
+    if (state->instance == NULL) {
+        return ERR;
+    }
+    // WAMR runs a reactor's `_initialize` when instantiating it, so the entry point can be called
+    // directly.
+    char name[256];
+    if (name_len >= sizeof(name)) {
+        free_instance(state);
+        return ERR;
+    }
+    memcpy(name, name_ptr, name_len);
+    name[name_len] = '\0';
+    bool success = true;
+    for (uint32_t i = 0; success && i < state->executions; i++) {
+        success = wasm_application_execute_func(state->instance, name, 0, NULL);
+    }
+    if (!success) {
+        const char *exception = wasm_runtime_get_exception(state->instance);
+        fprintf(stderr, "wamr-bench-api: failed to execute %s: %s\n", name,
+                exception ? exception : "unknown error");
+    }
+    free_instance(state);
+    return success ? OK : ERR;
+}
+
+int wasm_bench_set_executions(void *state_ptr, uint32_t count) {
+    bench_state_t *state = state_ptr;
+    state->executions = count > 0 ? count : 1;
+    return OK;
+}
+
+int wasm_bench_set_threads(void *state_ptr, uint32_t count) {
+    bench_state_t *state = state_ptr;
+    if (count == 0) {
+        return ERR;
+    }
+    // WAMR limits the number of threads an instance may spawn (to 4, by default).
+    state->threads = count;
+    wasm_runtime_set_max_thread_num(count);
+    return OK;
+}
+
+void wasm_bench_proposals(const void *state_ptr, void *data,
+                          void (*callback)(void *data, const uint8_t *name_ptr, size_t name_len)) {
+    // Both modes run the shared memories that `build.rs` enables, but only `wamrc` compiles SIMD (by
+    // default); the fast interpreter runs none.
+    const bench_state_t *state = state_ptr;
+    const char *interpreted[] = {"threads"};
+    const char *aot[] = {"threads", "simd"};
+    const char **proposals = state->aot ? aot : interpreted;
+    size_t count = state->aot ? sizeof(aot) / sizeof(aot[0])
+                              : sizeof(interpreted) / sizeof(interpreted[0]);
+    for (size_t i = 0; i < count; i++) {
+        callback(data, (const uint8_t *)proposals[i], strlen(proposals[i]));
+    }
+}
+
+void wasm_bench_build_info(void *data, void (*callback)(void *data, const uint8_t *key_ptr,
+                                                        size_t key_len, const uint8_t *value_ptr,
+                                                        size_t value_len)) {
+    const char *items[][2] = {
+        {"ENGINE", "wamr"},
+        {"WAMR_COMMIT", SIGHTGLASS_WAMR_COMMIT},
+        {"PROFILE", "release"},
+    };
+    for (size_t i = 0; i < sizeof(items) / sizeof(items[0]); i++) {
+        callback(data, (const uint8_t *)items[i][0], strlen(items[i][0]),
+                 (const uint8_t *)items[i][1], strlen(items[i][1]));
+    }
+}
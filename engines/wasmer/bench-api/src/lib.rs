@@ -0,0 +1,432 @@
+//! An implementation of the Sightglass bench API using Wasmer. This mirrors
+//! Wasmtime's `wasmtime-bench-api` crate so that the resulting shared library
+//! can be passed to `sightglass-cli benchmark --engine` like any other engine.
+//!
+//! The engine flags (`--engine-flags`) select Wasmer's compiler: `--cranelift`
+//! (the default) or `--singlepass`.
+
+use anyhow::{anyhow, bail, Context, Result};
+use std::ffi::c_void;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::slice;
+use wasmer::{
+    Cranelift, Exports, Function, Instance, Module, Singlepass, Store, Universal, WasmerEnv,
+};
+use wasmer_wasi::{WasiError, WasiState};
+
+/// NB: Keep this in sync with the version defined in `sightglass-recorder`
+/// (and in `wasmtime-bench-api`)!
+#[repr(C)]
+pub struct WasmBenchConfig {
+    working_dir_ptr: *const u8,
+    working_dir_len: usize,
+
+    stdout_path_ptr: *const u8,
+    stdout_path_len: usize,
+
+    stderr_path_ptr: *const u8,
+    stderr_path_len: usize,
+
+    stdin_path_ptr: *const u8,
+    stdin_path_len: usize,
+
+    compilation_timer: *mut u8,
+    compilation_start: extern "C" fn(*mut u8),
+    compilation_end: extern "C" fn(*mut u8),
+
+    instantiation_timer: *mut u8,
+    instantiation_start: extern "C" fn(*mut u8),
+    instantiation_end: extern "C" fn(*mut u8),
+
+    execution_timer: *mut u8,
+    execution_start: extern "C" fn(*mut u8),
+    execution_end: extern "C" fn(*mut u8),
+
+    execution_flags_ptr: *const u8,
+    execution_flags_len: usize,
+}
+
+impl WasmBenchConfig {
+    unsafe fn string(ptr: *const u8, len: usize) -> Result<Option<String>> {
+        if ptr.is_null() {
+            return Ok(None);
+        }
+        let bytes = slice::from_raw_parts(ptr, len);
+        Ok(Some(String::from_utf8(bytes.to_vec())?))
+    }
+
+    unsafe fn path(ptr: *const u8, len: usize) -> Result<Option<PathBuf>> {
+        Ok(Self::string(ptr, len)?.map(PathBuf::from))
+    }
+}
+
+/// A pair of timer callbacks, for one phase, as passed in the config.
+#[derive(Clone, Copy)]
+struct Timer {
+    // Stored as an address so that the timer can be shared with the `bench`
+    // host functions, which must be `Send` and `Sync`.
+    data: usize,
+    start: extern "C" fn(*mut u8),
+    end: extern "C" fn(*mut u8),
+}
+
+impl Timer {
+    fn start(&self) {
+        (self.start)(self.data as *mut u8)
+    }
+
+    fn end(&self) {
+        (self.end)(self.data as *mut u8)
+    }
+}
+
+/// The environment of the `bench.start` and `bench.end` host functions.
+#[derive(Clone, WasmerEnv)]
+struct BenchEnv {
+    execution: Timer,
+}
+
+fn bench_start(env: &BenchEnv) {
+    env.execution.start();
+}
+
+fn bench_end(env: &BenchEnv) {
+    env.execution.end();
+}
+
+/// The state of a single benchmark, returned to Sightglass as an opaque
+/// pointer.
+struct BenchState {
+    store: Store,
+    working_dir: PathBuf,
+    stdout_path: PathBuf,
+    stderr_path: PathBuf,
+    stdin_path: Option<PathBuf>,
+    compilation: Timer,
+    instantiation: Timer,
+    execution: Timer,
+    module: Option<Module>,
+    instance: Option<Instance>,
+    executions: u32,
+    proposals: &'static [&'static str],
+}
+
+impl BenchState {
+    fn new(config: WasmBenchConfig) -> Result<Self> {
+        let (working_dir, stdout_path, stderr_path, stdin_path, flags) = unsafe {
+            (
+                WasmBenchConfig::path(config.working_dir_ptr, config.working_dir_len)?
+                    .context("a working directory is required")?,
+                WasmBenchConfig::path(config.stdout_path_ptr, config.stdout_path_len)?
+                    .context("a stdout path is required")?,
+                WasmBenchConfig::path(config.stderr_path_ptr, config.stderr_path_len)?
+                    .context("a stderr path is required")?,
+                WasmBenchConfig::path(config.stdin_path_ptr, config.stdin_path_len)?,
+                WasmBenchConfig::string(config.execution_flags_ptr, config.execution_flags_len)?,
+            )
+        };
+
+        // Singlepass does not compile SIMD.
+        let (store, proposals): (_, &[&str]) = match flags.as_deref().map(str::trim) {
+            None | Some("") | Some("--cranelift") => (
+                Store::new(&Universal::new(Cranelift::default()).engine()),
+                &["simd"],
+            ),
+            Some("--singlepass") => (
+                Store::new(&Universal::new(Singlepass::default()).engine()),
+                &[],
+            ),
+            Some(flags) => bail!("unsupported Wasmer engine flags: {}", flags),
+        };
+
+        Ok(Self {
+            store,
+            working_dir,
+            stdout_path,
+            stderr_path,
+            stdin_path,
+            compilation: Timer {
+                data: config.compilation_timer as usize,
+                start: config.compilation_start,
+                end: config.compilation_end,
+            },
+            instantiation: Timer {
+                data: config.instantiation_timer as usize,
+                start: config.instantiation_start,
+                end: config.instantiation_end,
+            },
+            execution: Timer {
+                data: config.execution_timer as usize,
+                start: config.execution_start,
+                end: config.execution_end,
+            },
+            module: None,
+            instance: None,
+            executions: 1,
+            proposals,
+        })
+    }
+
+    fn compile(&mut self, bytes: &[u8]) -> Result<()> {
+        self.compilation.start();
+        let module = Module::new(&self.store, bytes);
+        self.compilation.end();
+        self.module = Some(module?);
+        Ok(())
+    }
+
+    fn precompile(&mut self, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(Module::new(&self.store, bytes)?.serialize()?)
+    }
+
+    fn deserialize(&mut self, bytes: &[u8]) -> Result<()> {
+        // Sightglass only passes bytes from `precompile` with the same engine
+        // flags, so they are compatible with this store.
+        let module = unsafe { Module::deserialize(&self.store, bytes) };
+        self.module = Some(module?);
+        Ok(())
+    }
+
+    fn instantiate(&mut self) -> Result<()> {
+        let module = self
+            .module
+            .as_ref()
+            .ok_or_else(|| anyhow!("must compile before instantiating"))?;
+
+        // Each instance gets a fresh WASI context, writing to the files
+        // Sightglass asked for.
+        let mut wasi = WasiState::new("benchmark");
+        wasi.map_dir(".", &self.working_dir)?
+            .stdout(Box::new(host_file(&self.stdout_path, false)?))
+            .stderr(Box::new(host_file(&self.stderr_path, false)?));
+        if let Some(stdin_path) = &self.stdin_path {
+            wasi.stdin(Box::new(host_file(stdin_path, true)?));
+        }
+        let mut wasi_env = wasi.finalize()?;
+        let mut import_object = wasi_env.import_object(module)?;
+
+        let env = BenchEnv {
+            execution: self.execution,
+        };
+        let mut bench = Exports::new();
+        bench.insert(
+            "start",
+            Function::new_native_with_env(&self.store, env.clone(), bench_start),
+        );
+        bench.insert(
+            "end",
+            Function::new_native_with_env(&self.store, env, bench_end),
+        );
+        import_object.register("bench", bench);
+
+        self.instantiation.start();
+        let instance = Instance::new(module, &import_object);
+        self.instantiation.end();
+        self.instance = Some(instance?);
+        Ok(())
+    }
+
+    fn execute(&mut self, entry_point: Option<&str>) -> Result<()> {
+        let instance = self
+            .instance
+            .take()
+            .ok_or_else(|| anyhow!("must instantiate before executing"))?;
+        let start = match entry_point {
+            // A reactor module is initialized before calling its entry point.
+            Some(name) => {
+                if let Ok(initialize) = instance.exports.get_function("_initialize") {
+                    initialize.call(&[])?;
+                }
+                instance.exports.get_function(name)?
+            }
+            None => instance.exports.get_function("_start")?,
+        };
+        // The benchmark's `bench.start` and `bench.end` calls time each execution.
+        for _ in 0..self.executions {
+            match start.call(&[]) {
+                Ok(_) => {}
+                Err(trap) => match trap.downcast::<WasiError>() {
+                    Ok(WasiError::Exit(0)) => {}
+                    Ok(WasiError::Exit(code)) => bail!("benchmark exited with code {}", code),
+                    Ok(e) => return Err(e.into()),
+                    Err(trap) => return Err(trap.into()),
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Open a host file for use as WASI stdio.
+fn host_file(path: &Path, read: bool) -> Result<wasmer_vfs::host_fs::File> {
+    let file: File = if read {
+        File::open(path)
+    } else {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    }
+    .with_context(|| format!("failed to open {}", path.display()))?;
+    Ok(wasmer_vfs::host_fs::File::new(
+        file,
+        path.to_path_buf(),
+        read,
+        !read,
+        false,
+    ))
+}
+
+const OK: i32 = 0;
+const ERR: i32 = -1;
+
+fn to_exit_code(result: Result<()>) -> i32 {
+    match result {
+        Ok(()) => OK,
+        Err(e) => {
+            eprintln!("wasmer-bench-api error: {:?}", e);
+            ERR
+        }
+    }
+}
+
+/// Create a new benchmark state, writing it to `out_bench_ptr`.
+#[no_mangle]
+pub extern "C" fn wasm_bench_create(
+    config: WasmBenchConfig,
+    out_bench_ptr: *mut *mut c_void,
+) -> i32 {
+    to_exit_code(BenchState::new(config).map(|state| {
+        assert!(!out_bench_ptr.is_null());
+        unsafe {
+            *out_bench_ptr = Box::into_raw(Box::new(state)) as *mut c_void;
+        }
+    }))
+}
+
+/// Free a benchmark state created by `wasm_bench_create`.
+#[no_mangle]
+pub extern "C" fn wasm_bench_free(state: *mut c_void) {
+    assert!(!state.is_null());
+    unsafe {
+        drop(Box::from_raw(state as *mut BenchState));
+    }
+}
+
+/// Compile the Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_compile(
+    state: *mut c_void,
+    wasm_bytes: *const u8,
+    wasm_bytes_length: usize,
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let bytes = unsafe { slice::from_raw_parts(wasm_bytes, wasm_bytes_length) };
+    to_exit_code(state.compile(bytes))
+}
+
+/// Compile the Wasm benchmark module ahead of time, passing the serialized
+/// module to `callback`.
+#[no_mangle]
+pub extern "C" fn wasm_bench_precompile(
+    state: *mut c_void,
+    wasm_bytes: *const u8,
+    wasm_bytes_length: usize,
+    data: *mut u8,
+    callback: extern "C" fn(*mut u8, *const u8, usize),
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let bytes = unsafe { slice::from_raw_parts(wasm_bytes, wasm_bytes_length) };
+    to_exit_code(
+        state
+            .precompile(bytes)
+            .map(|module| callback(data, module.as_ptr(), module.len())),
+    )
+}
+
+/// Load a module serialized by `wasm_bench_precompile`, in place of compiling
+/// the Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_deserialize(
+    state: *mut c_void,
+    bytes: *const u8,
+    bytes_length: usize,
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let bytes = unsafe { slice::from_raw_parts(bytes, bytes_length) };
+    to_exit_code(state.deserialize(bytes))
+}
+
+/// Instantiate the compiled Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_instantiate(state: *mut c_void) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    to_exit_code(state.instantiate())
+}
+
+/// Execute the instantiated Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_execute(state: *mut c_void) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    to_exit_code(state.execute(None))
+}
+
+/// Execute one of the entry points (e.g., `bench_parse`) of the instantiated
+/// Wasm benchmark module.
+#[no_mangle]
+pub extern "C" fn wasm_bench_execute_entry(
+    state: *mut c_void,
+    name_ptr: *const u8,
+    name_len: usize,
+) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    let name = unsafe { slice::from_raw_parts(name_ptr, name_len) };
+    to_exit_code(
+        std::str::from_utf8(name)
+            .context("the entry point name is not UTF-8")
+            .and_then(|name| state.execute(Some(name))),
+    )
+}
+
+/// Execute each instance `count` times rather than once.
+#[no_mangle]
+pub extern "C" fn wasm_bench_set_executions(state: *mut c_void, count: u32) -> i32 {
+    let state = unsafe { (state as *mut BenchState).as_mut().unwrap() };
+    state.executions = count.max(1);
+    OK
+}
+
+/// Report the Wasm proposals that the engine, with its flags, supports, one name at a time.
+#[no_mangle]
+pub extern "C" fn wasm_bench_proposals(
+    state: *const c_void,
+    data: *mut c_void,
+    callback: extern "C" fn(*mut c_void, *const u8, usize),
+) {
+    let state = unsafe { (state as *const BenchState).as_ref().unwrap() };
+    for proposal in state.proposals {
+        callback(data, proposal.as_ptr(), proposal.len());
+    }
+}
+
+/// Report how the engine was built, one `(key, value)` item at a time.
+#[no_mangle]
+pub extern "C" fn wasm_bench_build_info(
+    data: *mut c_void,
+    callback: extern "C" fn(*mut c_void, *const u8, usize, *const u8, usize),
+) {
+    let profile = if cfg!(debug_assertions) {
+        "debug"
+    } else {
+        "release"
+    };
+    for (key, value) in [
+        ("ENGINE", "wasmer"),
+        ("WASMER_VERSION", wasmer::VERSION),
+        ("PROFILE", profile),
+    ] {
+        callback(data, key.as_ptr(), key.len(), value.as_ptr(), value.len());
+    }
+}
@@ -0,0 +1,197 @@
+//! Build a Sightglass engine using Wasmer. Usage:
+//!
+//! ```
+//! rustc build.rs
+//! [VERSION=<wasmer version>] ./build [<destination dir>]
+//! ```
+//!
+//! Unlike Wasmtime, Wasmer does not provide an implementation of the Sightglass bench API; this
+//! script builds the one in the `bench-api` directory next to it.
+
+#![deny(missing_docs)]
+#![deny(clippy::all)]
+#![warn(clippy::pedantic)]
+#![allow(clippy::or_fun_call)]
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+fn main() {
+    // The sole CLI argument is the path at which to place the built engine library and metadata.
+    let args: Vec<_> = env::args_os().collect();
+    let destination_dir = match args.get(1) {
+        Some(p) => Path::new(p)
+            .canonicalize()
+            .expect("the first parameter is not a valid directory"),
+        None => env::current_dir().unwrap(),
+    };
+
+    // Collect configuration for building the engine library from environment variables.
+    // - `VERSION` is the Wasmer version to build against, e.g. `2.3.0`; if not present, the latest
+    //   version compatible with `bench-api/Cargo.toml` is used
+    // - `BUILD_DIR` is used internally to control where the library is built; if not present, a
+    //   temporary directory is created and later removed
+    let version = var("VERSION");
+    let (build_dir, remove_build_dir) = if let Some(p) = env::var_os("BUILD_DIR") {
+        let p = PathBuf::from(p)
+            .canonicalize()
+            .expect("BUILD_DIR must be a valid directory");
+        (p, false)
+    } else {
+        (create_temp_directory(), true)
+    };
+
+    // Copy the bench API implementation into the build directory so that its lock file and build
+    // artifacts do not end up in the Sightglass tree.
+    section("Retrieving the bench API");
+    let source_dir = env::current_exe()
+        .unwrap()
+        .parent()
+        .expect("the build script should be in a directory")
+        .join("bench-api");
+    copy(source_dir.join("Cargo.toml"), build_dir.join("Cargo.toml"));
+    fs::create_dir_all(build_dir.join("src")).expect("unable to create source directory");
+    copy(source_dir.join("src/lib.rs"), build_dir.join("src/lib.rs"));
+    exec(&["cargo", "generate-lockfile"], &build_dir);
+    if let Some(version) = &version {
+        for package in ["wasmer", "wasmer-vfs", "wasmer-wasi"] {
+            exec(
+                &["cargo", "update", "-p", package, "--precise", version],
+                &build_dir,
+            );
+        }
+    }
+
+    // Build the engine library.
+    section("Building the engine");
+    exec(&["cargo", "build", "--release"], &build_dir);
+
+    // Construct a `.build-info` file that will capture the important details a user would want to
+    // know if attempting to replicate benchmark results. (The current set is not exhaustive!).
+    section("Collecting metadata");
+    let build_info = write_buildinfo(&build_dir);
+    let build_info_contents =
+        fs::read_to_string(&build_info).expect("unable to read .build-info file");
+    eprintln!("{}", build_info_contents);
+
+    // Finally, the generated files are copied to their destination and we clean up the build
+    // directory.
+    section("Copying files to destination");
+    let from_engine_library = build_dir
+        .join("target/release")
+        .join(as_library_filename("wasmer_bench_api"));
+    let to_engine_library = destination_dir.join(as_library_filename("engine"));
+    copy(from_engine_library, to_engine_library);
+    let to_build_info = destination_dir.join(".build-info");
+    copy(build_info, to_build_info);
+    if remove_build_dir {
+        eprintln!(
+            "Removing temporary build directory: {}",
+            build_dir.display()
+        );
+        fs::remove_dir_all(&build_dir).expect("unable to clean up temporary build directory");
+    }
+}
+
+/// Print a section header for logging.
+fn section(title: &str) {
+    eprintln!();
+    eprintln!("===== {} =====", title);
+}
+
+/// Helpful wrapper to access an environment variable as a string. `env::var` returns an error when
+/// the `OsString` cannot be converted, which is not exactly what we want. This function panics if the
+/// string cannot be converted but still returns an `Option` indicating if the variable was present.
+fn var(key: &str) -> Option<String> {
+    env::var_os(key).map(|s| {
+        s.into_string()
+            .expect("the given value could not be converted to UTF-8")
+    })
+}
+
+/// Helpful wrapper to create a temporary directory; e.g., `/tmp/sightglass-wasmer-build-<current
+/// unix seconds>`)
+fn create_temp_directory() -> PathBuf {
+    let mut p = env::temp_dir();
+    let time = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    p.push(format!(
+        "sightglass-wasmer-build-{}.{}",
+        time.as_secs(),
+        time.subsec_nanos()
+    ));
+    fs::create_dir(&p).expect("unable to create temporary build directory");
+    eprintln!("Creating temporary build directory: {}", p.display());
+    p
+}
+
+/// Execute a `command` in the `working_directory`, panicking on failure.
+fn exec<P: AsRef<Path>>(command: &[&str], working_directory: P) {
+    eprintln!("> {}", command.join(" "));
+    let mut cmd = Command::new(command[0]);
+    cmd.args(&command[1..]);
+    cmd.current_dir(working_directory);
+    let status = cmd.status().expect("unable to execute command");
+    assert!(status.success());
+}
+
+/// Same as `exec` but captures the command output.
+fn exec_with_stdout<P: AsRef<Path>>(command: &[&str], working_directory: P) -> String {
+    eprintln!("> {}", command.join(" "));
+    let mut cmd = Command::new(command[0]);
+    cmd.args(&command[1..]);
+    cmd.current_dir(working_directory);
+    let out = cmd.output().expect("unable to execute command");
+    assert!(out.status.success());
+    std::str::from_utf8(&out.stdout).unwrap().trim().to_string()
+}
+
+/// Collect system metadata used for building the Wasmer engine and emit a `.build-info` file
+/// containing key-value pairs.
+fn write_buildinfo<P>(build_dir: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    let build_dir = build_dir.as_ref();
+    // E.g., `registry+https://github.com/rust-lang/crates.io-index#wasmer@2.3.0`.
+    let pkgid = exec_with_stdout(&["cargo", "pkgid", "wasmer"], &build_dir);
+    let version = pkgid.rsplit(['@', ':', '#']).next().unwrap().to_string();
+    let cargo = exec_with_stdout(&["cargo", "--version"], &build_dir);
+    let rustc = exec_with_stdout(&["rustc", "--version"], &build_dir);
+    let build_info = build_dir.join(".build-info");
+    eprintln!("Writing metadata to {}:", build_info.display());
+    {
+        let mut file = File::create(&build_info).expect("failed to create .build-info file");
+        writeln!(file, "NAME=wasmer").unwrap();
+        writeln!(file, "VERSION={}", version).unwrap();
+        writeln!(file, "_CARGO={}", cargo).unwrap();
+        writeln!(file, "_RUSTC={}", rustc).unwrap();
+    }
+    build_info
+}
+
+/// Helpful wrapper to copy a file.
+fn copy<P: AsRef<Path>>(from: P, to: P) {
+    let from = from.as_ref();
+    let to = to.as_ref();
+    eprintln!("Copying: {} -> {}", from.display(), to.display());
+    fs::copy(from, to).expect("unable to copy file");
+}
+
+/// Calculate the library name for a sightglass library on the target operating system: e.g.,
+/// `engine.dll`, `libengine.so`.
+#[must_use]
+pub fn as_library_filename(name: &str) -> String {
+    format!(
+        "{}{}{}",
+        env::consts::DLL_PREFIX,
+        name,
+        env::consts::DLL_SUFFIX
+    )
+}
@@ -1,6 +1,8 @@
 #ifndef sightglass_h
 #define sightglass_h 1
 
+#ifdef __wasm__
+
 /**
  * Call this function to indicate that recording should start. This call should be placed
  * immediately prior to the code to measure with sightglass-recorder. The attributes allow compilers
@@ -19,6 +21,49 @@ __attribute__((import_module("bench")))
 __attribute__((import_name("end")))
 void bench_end();
 
+#else
+
+/**
+ * When compiled natively (e.g., `cc -shared -fPIC benchmark.c -o benchmark.so`), a benchmark is a
+ * shared library that sightglass-recorder loads and runs in its own process, as the `native`
+ * engine. Before calling `main`, the recorder passes its measurement callbacks to
+ * `sightglass_native_init`; `bench_start` and `bench_end` then call them. These definitions are
+ * weak so that every file of a benchmark can include this header.
+ */
+__attribute__((weak)) void *sightglass_native_data;
+__attribute__((weak)) void (*sightglass_native_start)(void *);
+__attribute__((weak)) void (*sightglass_native_end)(void *);
+
+__attribute__((weak)) void sightglass_native_init(void *data, void (*start)(void *),
+                                                  void (*end)(void *))
+{
+    sightglass_native_data = data;
+    sightglass_native_start = start;
+    sightglass_native_end = end;
+}
+
+/**
+ * Call this function to indicate that recording should start. This call should be placed
+ * immediately prior to the code to measure with sightglass-recorder.
+ */
+__attribute__((weak)) void bench_start()
+{
+    if (sightglass_native_start)
+        sightglass_native_start(sightglass_native_data);
+}
+
+/**
+ * Call this function to indicate that recording should end. This call should be placed immediately
+ * after the code to measure with sightglass-recorder.
+ */
+__attribute__((weak)) void bench_end()
+{
+    if (sightglass_native_end)
+        sightglass_native_end(sightglass_native_data);
+}
+
+#endif
+
 /**
  * Call this function to prevent certain compiler-related optimizations related to knowing the value
  * of the passed variable.